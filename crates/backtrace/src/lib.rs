@@ -0,0 +1,105 @@
+//! Walks the frame pointer chain of a stack,
+//! the code must be built with `-C force-frame-pointers=yes`.
+
+#![no_std]
+
+#[cfg(test)]
+extern crate std;
+
+use core::{mem, ops::Range};
+
+// Deeper chains are cut off, they are most likely corrupt.
+const MAX_DEPTH: usize = 64;
+
+/// The return addresses of the frames on a stack, innermost first.
+/// The walk stops at the first frame pointer that is misaligned,
+/// outside of `stack` or not above the previous one.
+pub struct Frames {
+    fp: usize,
+    stack: Range<usize>,
+    depth: usize,
+}
+
+impl Frames {
+    pub fn new(fp: usize, stack: Range<usize>) -> Self {
+        Self {
+            fp,
+            stack,
+            depth: 0,
+        }
+    }
+
+    // The frame record of `fp`, the two words below it, must lie in the stack.
+    fn valid(&self, fp: usize) -> bool {
+        fp % mem::size_of::<usize>() == 0
+            && fp >= self.stack.start + 2 * mem::size_of::<usize>()
+            && fp <= self.stack.end
+    }
+}
+
+impl Iterator for Frames {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.depth == MAX_DEPTH || !self.valid(self.fp) {
+            return None;
+        }
+        let record = self.fp as *const usize;
+        let (ra, prev_fp) = unsafe { (*record.sub(1), *record.sub(2)) };
+        self.depth += 1;
+        // The stack grows down, so the frames of the callers are above.
+        self.fp = if prev_fp > self.fp { prev_fp } else { 0 };
+        Some(ra)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::mem;
+    use std::{vec, vec::Vec};
+
+    use super::{Frames, MAX_DEPTH};
+
+    const WORD: usize = mem::size_of::<usize>();
+
+    // Lays out a frame record below each of `fps`, word indices into `stack` innermost first,
+    // the frame at `fps[i]` returns to `0x1000 + i`. The outermost frame links to `last`.
+    fn chain(stack: &mut [usize], fps: &[usize], last: usize) -> usize {
+        let base = stack.as_ptr() as usize;
+        for (i, &fp) in fps.iter().enumerate() {
+            stack[fp - 1] = 0x1000 + i;
+            stack[fp - 2] = fps.get(i + 1).map_or(last, |caller| base + caller * WORD);
+        }
+        base + fps[0] * WORD
+    }
+
+    fn walk(stack: &[usize], fp: usize) -> Vec<usize> {
+        let base = stack.as_ptr() as usize;
+        Frames::new(fp, base..base + stack.len() * WORD).collect()
+    }
+
+    #[test]
+    fn walks_the_chain_until_a_bad_frame_pointer() {
+        let mut stack = vec![0; 32];
+        // The outermost frame links outside of the stack.
+        let fp = chain(&mut stack, &[8, 16, 24], 0xdead_beef);
+        assert_eq!(walk(&stack, fp), [0x1000, 0x1001, 0x1002]);
+        // A misaligned frame pointer.
+        assert!(walk(&stack, fp + 1).is_empty());
+
+        // A caller frame below its callee ends the walk.
+        let below = stack.as_ptr() as usize + 4 * WORD;
+        let fp = chain(&mut stack, &[8, 16, 24], below);
+        assert_eq!(walk(&stack, fp), [0x1000, 0x1001, 0x1002]);
+        let fp = chain(&mut stack, &[16, 8], 0);
+        assert_eq!(walk(&stack, fp), [0x1000]);
+    }
+
+    #[test]
+    fn walk_is_bounded() {
+        let mut stack = vec![0; 2 * MAX_DEPTH + 8];
+        let fps: Vec<usize> = (1..MAX_DEPTH + 4).map(|i| 2 * i).collect();
+        let fp = chain(&mut stack, &fps, 0);
+        assert_eq!(walk(&stack, fp).len(), MAX_DEPTH);
+    }
+}
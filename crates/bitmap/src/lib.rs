@@ -43,6 +43,44 @@ impl Bitmap {
         self.0.len() as u32 * u64::BITS
     }
 
+    /// Grows the bitmap to hold at least `nbits` bits, the set bits are kept and the new bits are 0.
+    /// A bitmap that already holds `nbits` bits is left alone.
+    pub fn grow(&mut self, nbits: u32) {
+        let size = div_round_up!(nbits, u64::BITS) as usize;
+        if size <= self.0.len() {
+            return;
+        }
+        let mut data = Vec::with_capacity(size);
+        data.extend_from_slice(&self.0);
+        data.resize(size, 0);
+        self.0 = data.into();
+    }
+
+    /// Shrinks the bitmap to hold `nbits` bits, rounded up to a whole u64.
+    /// Fails with the position of the first set bit that would be lost, leaving the bitmap unchanged.
+    pub fn shrink(&mut self, nbits: u32) -> Result<(), u32> {
+        let size = div_round_up!(nbits, u64::BITS) as usize;
+        if size >= self.0.len() {
+            return Ok(());
+        }
+        if let Some(idx) = self.0[size..].iter().position(|row| *row != 0) {
+            let row = size + idx;
+            return Err(row as u32 * u64::BITS + self.0[row].leading_zeros());
+        }
+        self.0 = self.0[..size].into();
+        Ok(())
+    }
+
+    /// Grows or shrinks the bitmap to `nbits` bits, see `grow` and `shrink`.
+    pub fn resize(&mut self, nbits: u32) -> Result<(), u32> {
+        if nbits > self.capacity() {
+            self.grow(nbits);
+            Ok(())
+        } else {
+            self.shrink(nbits)
+        }
+    }
+
     /// Returns the bit of the `offset` position.
     /// true - 1
     /// false - 0
@@ -101,6 +139,11 @@ impl Bitmap {
         })
     }
 
+    /// Returns the number of bits set to 1.
+    pub fn count_ones(&self) -> u32 {
+        self.0.iter().map(|row| row.count_ones()).sum()
+    }
+
     #[inline(always)]
     fn bit_mask(offset: u32) -> u64 {
         (1 << (u64::BITS - 1)) >> (offset & (u64::BITS - 1))
@@ -181,6 +224,20 @@ mod test {
         assert!(!bitmap.test_and_set(100, true));
     }
 
+    #[test]
+    fn bitmap_count_ones() {
+        let mut bitmap = Bitmap::new(200);
+        assert_eq!(bitmap.count_ones(), 0);
+
+        for offset in [0, 1, 63, 64, 199] {
+            bitmap.test_and_set(offset, true);
+        }
+        assert_eq!(bitmap.count_ones(), 5);
+
+        bitmap.test_and_set(64, false);
+        assert_eq!(bitmap.count_ones(), 4);
+    }
+
     #[test]
     fn bitmap_find_next_zero() {
         let mut bitmap = Bitmap::new(32767);
@@ -219,6 +276,49 @@ mod test {
         assert_eq!(bitmap.find_next_zero(0, None), None);
     }
 
+    #[test]
+    fn bitmap_grow_keeps_set_bits() {
+        let mut bitmap = Bitmap::new(100);
+        for offset in [0, 63, 64, 127] {
+            bitmap.test_and_set(offset, true);
+        }
+
+        bitmap.grow(1000);
+        assert_eq!(bitmap.capacity(), 1024);
+        assert_eq!(bitmap.count_ones(), 4);
+        for offset in [0, 63, 64, 127] {
+            assert!(bitmap.test(offset));
+        }
+        assert!((128..1024).all(|offset| !bitmap.test(offset)));
+        assert_eq!(bitmap.find_next_zero(127, None), Some(128));
+
+        // Growing to a smaller size does nothing.
+        bitmap.grow(10);
+        assert_eq!(bitmap.capacity(), 1024);
+    }
+
+    #[test]
+    fn bitmap_shrink() {
+        let mut bitmap = Bitmap::new(1000);
+        bitmap.test_and_set(1, true);
+        bitmap.test_and_set(500, true);
+
+        assert_eq!(bitmap.shrink(100), Err(500));
+        assert_eq!(bitmap.capacity(), 1024);
+        assert!(bitmap.test(500));
+
+        bitmap.test_and_set(500, false);
+        assert_eq!(bitmap.shrink(100), Ok(()));
+        assert_eq!(bitmap.capacity(), 128);
+        assert!(bitmap.test(1));
+
+        assert_eq!(bitmap.resize(300), Ok(()));
+        assert_eq!(bitmap.capacity(), 320);
+        assert!(bitmap.test(1));
+        assert_eq!(bitmap.resize(1), Ok(()));
+        assert_eq!(bitmap.capacity(), 64);
+    }
+
     #[test]
     fn bitmap_find_next_zero_with_end() {
         let mut bitmap = Bitmap::new(10);
@@ -0,0 +1,1241 @@
+use alloc::{boxed::Box, collections::VecDeque, slice, sync::Arc, vec::Vec};
+use core::{
+    future::Future,
+    marker::PhantomPinned,
+    mem,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{ready, Context, Poll, Waker},
+};
+use futures_util::{
+    future::{poll_fn, BoxFuture, MaybeDone},
+    task::{waker_ref, ArcWake},
+    FutureExt, Stream,
+};
+use pin_project::pin_project;
+
+use spinlock::{Irq, MutexIrq};
+
+use crate::{BlkDevice, BlkSize, Result};
+
+/// A byte addressed view of a block device.
+/// Partial blocks are read-modify-written a whole physical block at a time,
+/// so a write never leaves a physical block half updated by us and
+/// the device never has to read-modify-write it again internally.
+pub struct Disk<I> {
+    phy_blk_device: Arc<dyn BlkDevice>,
+    /// The I/O unit, the device's physical block size.
+    blk_size: BlkSize,
+    /// Number of physical blocks, updated by `refresh_capacity`.
+    blk_count: AtomicUsize,
+    /// log2(physical block size / logical block size)
+    lblks_per_blk_log2: u8,
+    capacity: AtomicUsize,
+    read_ahead: MutexIrq<I, ReadAhead<I>>,
+}
+
+impl<I: Irq + 'static> Disk<I> {
+    /// Reads ahead `read_ahead_blks` blocks of sequential reads, see `set_read_ahead`.
+    pub fn new(phy_blk_device: Arc<dyn BlkDevice>, read_ahead_blks: usize) -> Self {
+        let logical_blk_size = phy_blk_device.logical_blk_size();
+        let blk_size = BlkSize::with_blk_size_log2(
+            phy_blk_device
+                .physical_blk_size()
+                .blk_size_log2
+                .max(logical_blk_size.blk_size_log2),
+        );
+        let lblks_per_blk_log2 = blk_size.blk_size_log2 - logical_blk_size.blk_size_log2;
+        // A trailing run of logical blocks shorter than a physical block is not used.
+        let blk_count = phy_blk_device.blk_count() >> lblks_per_blk_log2;
+        Self {
+            capacity: AtomicUsize::new(blk_size.mul(blk_count)),
+            phy_blk_device,
+            blk_size,
+            blk_count: AtomicUsize::new(blk_count),
+            lblks_per_blk_log2,
+            read_ahead: MutexIrq::new(ReadAhead::new(read_ahead_blks)),
+        }
+    }
+
+    /// Sets how many blocks are read ahead of sequential reads, 0 disables readahead.
+    pub fn set_read_ahead(&self, blk_cnt: usize) {
+        let mut read_ahead = self.read_ahead.lock();
+        read_ahead.window = blk_cnt;
+        read_ahead.invalidate();
+    }
+
+    /// Read some bytes from this disk into the specified buffer, returning how many bytes were read.
+    pub fn read_at<'a>(&'a self, offset: u64, buf: &'a mut [u8]) -> ReadAtFut<'a, I> {
+        assert!(!buf.is_empty(), "buf must not be empty");
+        let read_space = PhySpace::calc(offset, buf.len() as u64, self.blk_size, self.blk_count());
+        if let Some(read_space) = &read_space {
+            self.read_ahead_after(read_space.start_blk_id, read_space.end_blk_id);
+        }
+        // Block-aligned reads go straight to the device, without a scratch block.
+        let state = match &read_space {
+            Some(read_space) if !read_space.has_partial_head_blk() => ReadAtState::FullBlks {
+                blk_id: read_space.start_blk_id,
+                fut: None,
+            },
+            _ => ReadAtState::HeadPartialBlk(None),
+        };
+        ReadAtFut {
+            disk: self,
+            read_space,
+            buf: BufRef(buf),
+            read_size: 0,
+            state,
+        }
+    }
+
+    /// Write a buffer into this disk, returning how many bytes were written.
+    pub fn write_at<'a>(&'a self, offset: u64, src: &'a [u8]) -> WriteAtFut<'a, I> {
+        assert!(!src.is_empty(), "src must not be empty");
+
+        let write_space = PhySpace::calc(offset, src.len() as u64, self.blk_size, self.blk_count());
+        // Block-aligned writes need no read-modify-write of the first block.
+        let state = match &write_space {
+            Some(write_space) if !write_space.has_partial_head_blk() => WriteAtState::FullBlks {
+                blk_id: write_space.start_blk_id,
+                fut: None,
+            },
+            _ => WriteAtState::HeadPartialBlk(None),
+        };
+        WriteAtFut {
+            disk: self,
+            write_space,
+            src,
+            written_size: 0,
+            state,
+        }
+    }
+
+    /// Discard the physical blocks lying entirely within `offset..offset + len`,
+    /// the partial blocks at either end are left alone.
+    pub fn discard(&self, offset: u64, len: u64) -> BoxFuture<'_, Result<()>> {
+        let start_blk_id = self.blk_size.div_round_up_by(offset) as usize;
+        let end_blk_id = (self.blk_size.div_by(offset + len) as usize).min(self.blk_count());
+        if start_blk_id >= end_blk_id {
+            return Box::pin(core::future::ready(Ok(())));
+        }
+        self.read_ahead.lock().invalidate();
+        self.phy_blk_device.discard(
+            start_blk_id << self.lblks_per_blk_log2,
+            (end_blk_id - start_blk_id) << self.lblks_per_blk_log2,
+        )
+    }
+
+    /// Sync disk, ensuring that all intermediately buffered contents reach their destination.
+    pub async fn sync(&self) -> Result<()> {
+        self.phy_blk_device.flush().await
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity.load(Ordering::Acquire)
+    }
+
+    /// Re-reads the block count of the device, which may have been resized since,
+    /// and returns the new capacity.
+    pub fn refresh_capacity(&self) -> usize {
+        let blk_count = self.phy_blk_device.blk_count() >> self.lblks_per_blk_log2;
+        let capacity = self.blk_size.mul(blk_count);
+        self.blk_count.store(blk_count, Ordering::Release);
+        self.capacity.store(capacity, Ordering::Release);
+        capacity
+    }
+
+    fn blk_count(&self) -> usize {
+        self.blk_count.load(Ordering::Acquire)
+    }
+
+    /// Returns a stream of the successive blocks of this disk, from offset 0 to `capacity()`.
+    /// The last block is short if the capacity is not a multiple of the block size.
+    pub fn blocks(&self) -> Blocks<'_, I> {
+        Blocks {
+            disk: self,
+            offset: 0,
+            reads: VecDeque::with_capacity(BLOCKS_READ_AHEAD + 1),
+        }
+    }
+
+    /// Read `len` bytes at `offset` into a buffer owned by the returned future.
+    fn read_owned(&self, offset: u64, len: usize) -> BoxFuture<'_, Result<Vec<u8>>> {
+        Box::pin(async move {
+            let mut buf = vec![0; len];
+            let read_size = self.read_at(offset, &mut buf).await?;
+            buf.truncate(read_size);
+            Ok(buf)
+        })
+    }
+
+    /// Starts reading ahead the blocks after `end_blk_id` if the read of
+    /// `start_blk_id..=end_blk_id` continues the previous one.
+    fn read_ahead_after(&self, start_blk_id: usize, end_blk_id: usize) {
+        let mut read_ahead = self.read_ahead.lock();
+        // A read that ended in the middle of a block is continued from that block.
+        let sequential = matches!(
+            read_ahead.last_blk_id,
+            Some(last) if start_blk_id == last || start_blk_id == last + 1
+        );
+        read_ahead.last_blk_id = Some(end_blk_id);
+        // The device may still be filling the buffer of a request in flight,
+        // so it is never dropped before it completes.
+        if read_ahead.poll_in_flight(None).is_pending() {
+            return;
+        }
+        let start_blk_id = end_blk_id + 1;
+        let blk_cnt = read_ahead.window.min(self.blk_count() - start_blk_id);
+        // Blocks still served from the current window are not replaced either.
+        if !sequential
+            || blk_cnt == 0
+            || read_ahead.covers(start_blk_id, 1)
+            || read_ahead.covers(end_blk_id, 1)
+        {
+            return;
+        }
+
+        let phy_blk_device = self.phy_blk_device.clone();
+        let lblk_id = start_blk_id << self.lblks_per_blk_log2;
+        let len = self.blk_size.mul(blk_cnt);
+        let fut = Box::pin(async move {
+            let mut data = vec![0; len];
+            phy_blk_device.read_blks(lblk_id, &mut data).await?;
+            Ok(data)
+        });
+        read_ahead.blks = Some(ReadAheadBlks {
+            start_blk_id,
+            blk_cnt,
+            generation: read_ahead.generation,
+            data: ReadAheadData::InFlight(fut),
+        });
+        // Poll once so the request is sent to the device now.
+        let _ = read_ahead.poll_in_flight(None);
+    }
+
+    /// Read the physical blocks starting at `blk_id`, `buf` holds a whole number of them.
+    /// Blocks read ahead are served without going to the device.
+    fn read_blks<'a>(&'a self, blk_id: usize, buf: &'a mut [u8]) -> BoxFuture<'a, Result<()>> {
+        let blk_cnt = self.blk_size.div_by(buf.len() as u64) as usize;
+        let read_ahead = self.read_ahead.lock();
+        if read_ahead.copy_to(blk_id, self.blk_size, buf) {
+            return Box::pin(core::future::ready(Ok(())));
+        }
+        let in_flight = read_ahead.covers(blk_id, blk_cnt);
+        drop(read_ahead);
+
+        Box::pin(async move {
+            if in_flight {
+                let copied = poll_fn(|cx| {
+                    let mut read_ahead = self.read_ahead.lock();
+                    ready!(read_ahead.poll_in_flight(Some(cx)));
+                    Poll::Ready(read_ahead.copy_to(blk_id, self.blk_size, buf))
+                })
+                .await;
+                if copied {
+                    return Ok(());
+                }
+            }
+            // The read ahead failed, or a write went to the device while the blocks were read.
+            // The device reports the error again if it persists.
+            self.phy_blk_device
+                .read_blks(blk_id << self.lblks_per_blk_log2, buf)
+                .await
+        })
+    }
+
+    /// Write the physical blocks starting at `blk_id`, `src` holds a whole number of them.
+    fn write_blks<'a>(&'a self, blk_id: usize, src: &'a [u8]) -> BoxFuture<'a, Result<()>> {
+        self.read_ahead.lock().invalidate();
+        self.phy_blk_device
+            .write_blks(blk_id << self.lblks_per_blk_log2, src)
+    }
+}
+
+/// The blocks read ahead of a sequential reader.
+struct ReadAhead<I> {
+    /// Number of blocks read ahead, 0 disables readahead.
+    window: usize,
+    /// The last block of the previous read.
+    last_blk_id: Option<usize>,
+    /// Bumped by every write, blocks read in an older generation may be stale.
+    generation: usize,
+    blks: Option<ReadAheadBlks>,
+    /// The readers waiting for the request in flight.
+    wakers: Arc<ReadAheadWakers<I>>,
+}
+
+struct ReadAheadBlks {
+    start_blk_id: usize,
+    blk_cnt: usize,
+    generation: usize,
+    data: ReadAheadData,
+}
+
+enum ReadAheadData {
+    InFlight(BoxFuture<'static, Result<Vec<u8>>>),
+    Ready(Vec<u8>),
+}
+
+impl<I: Irq + 'static> ReadAhead<I> {
+    fn new(window: usize) -> Self {
+        Self {
+            window,
+            last_blk_id: None,
+            generation: 0,
+            blks: None,
+            wakers: Arc::new(ReadAheadWakers(MutexIrq::new(Vec::new()))),
+        }
+    }
+
+    /// Whether the blocks `blk_id..blk_id + blk_cnt` are read ahead.
+    fn covers(&self, blk_id: usize, blk_cnt: usize) -> bool {
+        matches!(
+            &self.blks,
+            Some(blks) if blks.generation == self.generation
+                && blk_id >= blks.start_blk_id
+                && blk_id + blk_cnt <= blks.start_blk_id + blks.blk_cnt
+        )
+    }
+
+    /// Copies the blocks starting at `blk_id` into `buf` if they have been read ahead.
+    fn copy_to(&self, blk_id: usize, blk_size: BlkSize, buf: &mut [u8]) -> bool {
+        if !self.covers(blk_id, blk_size.div_by(buf.len() as u64) as usize) {
+            return false;
+        }
+        match &self.blks {
+            Some(ReadAheadBlks {
+                start_blk_id,
+                data: ReadAheadData::Ready(data),
+                ..
+            }) => {
+                let start = blk_size.mul(blk_id - start_blk_id);
+                buf.copy_from_slice(&data[start..start + buf.len()]);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Polls the request in flight, if any, `cx` is woken once it completes.
+    /// Blocks that failed to be read or went stale meanwhile are dropped.
+    fn poll_in_flight(&mut self, cx: Option<&Context<'_>>) -> Poll<()> {
+        let blks = match &mut self.blks {
+            Some(blks) => blks,
+            None => return Poll::Ready(()),
+        };
+        let fut = match &mut blks.data {
+            ReadAheadData::InFlight(fut) => fut,
+            ReadAheadData::Ready(_) => return Poll::Ready(()),
+        };
+        if let Some(cx) = cx {
+            self.wakers.register(cx.waker());
+        }
+        // The request keeps only the waker it was last polled with,
+        // this one wakes every reader waiting for it.
+        let waker = waker_ref(&self.wakers);
+        match fut.poll_unpin(&mut Context::from_waker(&waker)) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(Ok(data)) if blks.generation == self.generation => {
+                blks.data = ReadAheadData::Ready(data)
+            }
+            Poll::Ready(_) => self.blks = None,
+        }
+        Poll::Ready(())
+    }
+
+    fn invalidate(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+        // A request in flight is kept until it completes, `covers` no longer matches it.
+        if let Some(ReadAheadBlks {
+            data: ReadAheadData::Ready(_),
+            ..
+        }) = &self.blks
+        {
+            self.blks = None;
+        }
+    }
+}
+
+struct ReadAheadWakers<I>(MutexIrq<I, Vec<Waker>>);
+
+impl<I: Irq> ReadAheadWakers<I> {
+    fn register(&self, waker: &Waker) {
+        let mut wakers = self.0.lock();
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+}
+
+impl<I: Irq> ArcWake for ReadAheadWakers<I> {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        let wakers = mem::take(&mut *arc_self.0.lock());
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+/// Number of blocks [`Blocks`] reads beyond the one it yields next.
+const BLOCKS_READ_AHEAD: usize = 1;
+
+/// Stream for the [`blocks`](Disk::blocks) method.
+pub struct Blocks<'a, I> {
+    disk: &'a Disk<I>,
+    /// Offset of the next block to start reading.
+    offset: u64,
+    /// The block to yield next, followed by the blocks read ahead.
+    reads: VecDeque<MaybeDone<BoxFuture<'a, Result<Vec<u8>>>>>,
+}
+
+impl<I: Irq + 'static> Stream for Blocks<'_, I> {
+    type Item = Result<Vec<u8>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let Self {
+            disk,
+            offset,
+            reads,
+        } = &mut *self;
+
+        let capacity = disk.capacity() as u64;
+        let blk_size = disk.blk_size.size() as u64;
+        while reads.len() <= BLOCKS_READ_AHEAD && *offset < capacity {
+            let len = blk_size.min(capacity - *offset);
+            reads.push_back(MaybeDone::Future(disk.read_owned(*offset, len as usize)));
+            *offset += len;
+        }
+
+        // Poll the blocks read ahead too, so their requests are in flight
+        // while the caller handles the block yielded now.
+        for read in reads.iter_mut() {
+            let _ = Pin::new(read).poll(cx);
+        }
+
+        let read = match reads.front_mut() {
+            Some(read) => read,
+            None => return Poll::Ready(None),
+        };
+        match Pin::new(read).take_output() {
+            Some(res) => {
+                reads.pop_front();
+                Poll::Ready(Some(res))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// Future for the [`read_at`](Disk::read_at)
+#[pin_project]
+pub struct ReadAtFut<'a, I> {
+    disk: &'a Disk<I>,
+    read_space: Option<PhySpace>,
+    buf: BufRef<'a>,
+    read_size: usize,
+    #[pin]
+    state: ReadAtState<'a>,
+}
+
+#[pin_project(project = ReadAtStateProj)]
+enum ReadAtState<'a> {
+    /// If the offset starts in the middle of a block in the underlying block device,
+    /// Need to read out the entire block and copy the required part to the buf.
+    HeadPartialBlk(#[pin] Option<ReadPartialBlkFutAndData<'a>>),
+    /// If the last part of the data to be read is less than one block,
+    /// Need to read out the entire block and copy the required part to the buf.
+    TailPartialBlk(#[pin] Option<ReadPartialBlkFutAndData<'a>>),
+    FullBlks {
+        blk_id: usize,
+        #[pin]
+        fut: Option<BoxFuture<'a, Result<()>>>,
+    },
+}
+
+#[pin_project]
+struct ReadPartialBlkFutAndData<'a> {
+    #[pin]
+    fut: BoxFuture<'a, Result<()>>,
+    #[pin]
+    blk_data: BlkData,
+    #[pin]
+    _pin: PhantomPinned,
+}
+
+impl<'a> ReadPartialBlkFutAndData<'a> {
+    fn new(fut: BoxFuture<'a, Result<()>>, blk_data: BlkData) -> Self {
+        Self {
+            fut,
+            blk_data,
+            _pin: PhantomPinned,
+        }
+    }
+}
+
+impl<I: Irq + 'static> Future for ReadAtFut<'_, I> {
+    type Output = Result<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+
+        let read_space = match this.read_space {
+            Some(read_space) => read_space,
+            None => return Poll::Ready(Ok(0)),
+        };
+
+        let blk_size = this.disk.blk_size.size() as usize;
+        loop {
+            let new_state = match this.state.as_mut().project() {
+                ReadAtStateProj::HeadPartialBlk(fut_and_data) => match fut_and_data.as_pin_mut() {
+                    None => {
+                        let mut blk_data = BlkData(vec![0; blk_size]);
+
+                        ReadAtState::HeadPartialBlk(Some(ReadPartialBlkFutAndData::new(
+                            this.disk.read_blks(read_space.start_blk_id, unsafe {
+                                blk_data.as_mut_slice()
+                            }),
+                            blk_data,
+                        )))
+                    }
+
+                    Some(fut_and_data) => {
+                        let fut_and_data_proj = fut_and_data.project();
+                        ready!(fut_and_data_proj.fut.poll(cx)?);
+
+                        if read_space.start_blk_id == read_space.end_blk_id {
+                            let src = &fut_and_data_proj.blk_data[read_space
+                                .pos_of_head_partial_blk
+                                .unwrap()
+                                ..read_space.pos_of_tail_partial_blk.unwrap_or(blk_size)];
+                            (&mut this.buf[..src.len()]).copy_from_slice(src);
+                            return Poll::Ready(Ok(src.len()));
+                        }
+
+                        let src = &fut_and_data_proj.blk_data
+                            [read_space.pos_of_head_partial_blk.unwrap()..];
+                        (&mut this.buf[..src.len()]).copy_from_slice(src);
+                        *this.read_size += src.len();
+                        ReadAtState::FullBlks {
+                            blk_id: read_space.start_blk_id + 1,
+                            fut: None,
+                        }
+                    }
+                },
+
+                ReadAtStateProj::TailPartialBlk(fut_and_data) => match fut_and_data.as_pin_mut() {
+                    None => {
+                        if !read_space.has_partial_tail_blk() {
+                            return Poll::Ready(Ok(*this.read_size));
+                        }
+                        let mut blk_data = BlkData(vec![0; blk_size]);
+                        ReadAtState::TailPartialBlk(Some(ReadPartialBlkFutAndData::new(
+                            this.disk.read_blks(read_space.end_blk_id, unsafe {
+                                blk_data.as_mut_slice()
+                            }),
+                            blk_data,
+                        )))
+                    }
+
+                    Some(fut_and_data) => {
+                        let fut_and_data_proj = fut_and_data.project();
+                        ready!(fut_and_data_proj.fut.poll(cx)?);
+                        let src = &fut_and_data_proj.blk_data
+                            [..read_space.pos_of_tail_partial_blk.unwrap()];
+                        (&mut this.buf[*this.read_size..]).copy_from_slice(src);
+                        *this.read_size += src.len();
+                        return Poll::Ready(Ok(*this.read_size));
+                    }
+                },
+
+                ReadAtStateProj::FullBlks { blk_id, fut } => match fut.as_pin_mut() {
+                    None => {
+                        if *blk_id as isize > read_space.last_full_blk() {
+                            // FullBlocks finished reading, try to read the last part of the data if necessary.
+                            ReadAtState::TailPartialBlk(None)
+                        } else {
+                            // Read all the full blocks in one batched request.
+                            let blk_cnt = full_blk_cnt(*blk_id, read_space);
+                            let buf = unsafe { this.buf.extend_lifetime() };
+                            ReadAtState::FullBlks {
+                                blk_id: *blk_id,
+                                fut: Some(this.disk.read_blks(
+                                    *blk_id,
+                                    &mut buf[*this.read_size..*this.read_size + blk_cnt * blk_size],
+                                )),
+                            }
+                        }
+                    }
+                    Some(fut) => {
+                        ready!(fut.poll(cx)?);
+                        let blk_cnt = full_blk_cnt(*blk_id, read_space);
+                        *this.read_size += blk_cnt * blk_size;
+                        ReadAtState::FullBlks {
+                            blk_id: *blk_id + blk_cnt,
+                            fut: None,
+                        }
+                    }
+                },
+            };
+
+            this.state.set(new_state);
+        }
+    }
+}
+
+/// Future for the [write_at](Disk::write_at) method.
+#[pin_project]
+pub struct WriteAtFut<'a, I> {
+    disk: &'a Disk<I>,
+    write_space: Option<PhySpace>,
+    src: &'a [u8],
+    written_size: usize,
+    #[pin]
+    state: WriteAtState<'a, I>,
+}
+
+#[pin_project(project = WriteAtStateProj)]
+enum WriteAtState<'a, I> {
+    HeadPartialBlk(#[pin] Option<WritePartialBlkFut<'a, I>>),
+    TailPartialBlk(#[pin] Option<WritePartialBlkFut<'a, I>>),
+    FullBlks {
+        blk_id: usize,
+        #[pin]
+        fut: Option<BoxFuture<'a, Result<()>>>,
+    },
+}
+
+impl<I: Irq + 'static> Future for WriteAtFut<'_, I> {
+    type Output = Result<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+
+        let write_space = match this.write_space {
+            Some(write_space) => write_space,
+            None => return Poll::Ready(Ok(0)),
+        };
+
+        let blk_size = this.disk.blk_size.size() as usize;
+        loop {
+            let new_state = match this.state.as_mut().project() {
+                WriteAtStateProj::HeadPartialBlk(write_partial_blk_fut) => {
+                    match write_partial_blk_fut.as_pin_mut() {
+                        None => {
+                            let (src, target_range) = if write_space.start_blk_id
+                                == write_space.end_blk_id
+                            {
+                                let pos_of_head_partial_blk =
+                                    write_space.pos_of_head_partial_blk.unwrap();
+                                let pos_of_tail_partial_blk =
+                                    write_space.pos_of_tail_partial_blk.unwrap_or(blk_size);
+
+                                (
+                                    &this.src[..pos_of_tail_partial_blk - pos_of_head_partial_blk],
+                                    pos_of_head_partial_blk..pos_of_tail_partial_blk,
+                                )
+                            } else {
+                                (
+                                    &this.src
+                                        [..blk_size - write_space.pos_of_head_partial_blk.unwrap()],
+                                    write_space.pos_of_head_partial_blk.unwrap()..blk_size,
+                                )
+                            };
+
+                            WriteAtState::HeadPartialBlk(Some(write_partial_blk(
+                                *this.disk,
+                                write_space.start_blk_id,
+                                src,
+                                target_range,
+                            )))
+                        }
+                        Some(write_partial_blk_fut) => {
+                            let written_size = ready!(write_partial_blk_fut.poll(cx)?);
+                            if write_space.start_blk_id == write_space.end_blk_id {
+                                return Poll::Ready(Ok(written_size));
+                            }
+                            *this.written_size += written_size;
+                            WriteAtState::FullBlks {
+                                blk_id: write_space.start_blk_id + 1,
+                                fut: None,
+                            }
+                        }
+                    }
+                }
+                WriteAtStateProj::TailPartialBlk(write_partial_blk_fut) => {
+                    match write_partial_blk_fut.as_pin_mut() {
+                        None => {
+                            if !write_space.has_partial_tail_blk() {
+                                return Poll::Ready(Ok(*this.written_size));
+                            }
+                            WriteAtState::TailPartialBlk(Some(write_partial_blk(
+                                *this.disk,
+                                write_space.end_blk_id,
+                                &this.src[*this.written_size..],
+                                0..write_space.pos_of_tail_partial_blk.unwrap(),
+                            )))
+                        }
+                        Some(write_partial_blk_fut) => {
+                            *this.written_size += ready!(write_partial_blk_fut.poll(cx)?);
+                            return Poll::Ready(Ok(*this.written_size));
+                        }
+                    }
+                }
+                WriteAtStateProj::FullBlks { blk_id, fut } => match fut.as_pin_mut() {
+                    None => {
+                        if *blk_id as isize > write_space.last_full_blk() {
+                            // FullBlocks finished writing, try to write the last part of the data if necessary.
+                            WriteAtState::TailPartialBlk(None)
+                        } else {
+                            // Write all the full blocks in one batched request.
+                            let blk_cnt = full_blk_cnt(*blk_id, write_space);
+                            WriteAtState::FullBlks {
+                                blk_id: *blk_id,
+                                fut: Some(this.disk.write_blks(
+                                    *blk_id,
+                                    &this.src[*this.written_size
+                                        ..*this.written_size + blk_cnt * blk_size],
+                                )),
+                            }
+                        }
+                    }
+                    Some(fut) => {
+                        ready!(fut.poll(cx)?);
+
+                        let blk_cnt = full_blk_cnt(*blk_id, write_space);
+                        *this.written_size += blk_cnt * blk_size;
+                        WriteAtState::FullBlks {
+                            blk_id: *blk_id + blk_cnt,
+                            fut: None,
+                        }
+                    }
+                },
+            };
+            this.state.set(new_state);
+        }
+    }
+}
+
+/// Future for [write_partial_blk](write_partial_blk) function.
+/// To write PartialBlock data, need to read the entire physical block data,
+/// Then modify the data, finally write it back
+#[pin_project]
+struct WritePartialBlkFut<'a, I> {
+    disk: &'a Disk<I>,
+    blk_id: usize,
+    blk_data: BlkData,
+    src: &'a [u8],
+    target_range: core::ops::Range<usize>,
+    #[pin]
+    state: WritePartialBlkState<'a>,
+}
+
+fn write_partial_blk<'a, I: Irq + 'static>(
+    disk: &'a Disk<I>,
+    blk_id: usize,
+    src: &'a [u8],
+    target_range: core::ops::Range<usize>,
+) -> WritePartialBlkFut<'a, I> {
+    WritePartialBlkFut {
+        state: WritePartialBlkState::Init,
+        disk,
+        blk_id,
+        blk_data: BlkData(vec![0u8; disk.blk_size.size() as usize]),
+        target_range,
+        src,
+    }
+}
+
+#[pin_project(project = WritePartialBlkStateProj)]
+enum WritePartialBlkState<'a> {
+    Init,
+    ReadBlk(#[pin] BoxFuture<'a, Result<()>>),
+    WriteBlk(#[pin] BoxFuture<'a, Result<()>>),
+}
+
+impl<'a, I: Irq + 'static> Future for WritePartialBlkFut<'a, I> {
+    type Output = Result<usize>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.as_mut().project();
+
+        loop {
+            let new_state = match this.state.as_mut().project() {
+                WritePartialBlkStateProj::Init => WritePartialBlkState::ReadBlk(
+                    this.disk
+                        .read_blks(*this.blk_id, unsafe { this.blk_data.as_mut_slice() }),
+                ),
+                WritePartialBlkStateProj::ReadBlk(read_fut) => {
+                    ready!(read_fut.poll(cx)?);
+                    (&mut this.blk_data[this.target_range.clone()]).copy_from_slice(this.src);
+                    WritePartialBlkState::WriteBlk(
+                        this.disk
+                            .write_blks(*this.blk_id, unsafe { this.blk_data.as_mut_slice() }),
+                    )
+                }
+                WritePartialBlkStateProj::WriteBlk(write_fut) => {
+                    ready!(write_fut.poll(cx)?);
+                    return Poll::Ready(Ok(this.src.len()));
+                }
+            };
+            this.state.set(new_state);
+        }
+    }
+}
+
+/// Number of full blocks in `phy_space` from `blk_id` on.
+fn full_blk_cnt(blk_id: usize, phy_space: &PhySpace) -> usize {
+    (phy_space.last_full_blk() - blk_id as isize + 1) as usize
+}
+
+/// PhySpace represents space range of blk device, in physical blocks.
+#[derive(Debug)]
+struct PhySpace {
+    start_blk_id: usize,
+    end_blk_id: usize,
+    /// The starting position of this PhySpace in the first block.
+    /// if None, the first block is a full-block.
+    pos_of_head_partial_blk: Option<usize>,
+    /// The end position of this PhySpace in the last block.
+    /// if None, the last block is a full-block.
+    pos_of_tail_partial_blk: Option<usize>,
+}
+
+impl PhySpace {
+    fn calc(abs_offset: u64, len: u64, blk_size: BlkSize, blk_count: usize) -> Option<Self> {
+        let start_blk_id = blk_size.div_by(abs_offset) as usize;
+        if start_blk_id >= blk_count {
+            return None;
+        }
+
+        let pos_of_head_partial_blk = blk_size.mod_by(abs_offset) as usize;
+        let len_of_head_partial_blk = blk_size.size() as usize - pos_of_head_partial_blk;
+
+        let (end_blk_id, pos_of_tail_partial_blk) = if len < len_of_head_partial_blk as u64 {
+            (start_blk_id, pos_of_head_partial_blk + len as usize)
+        } else {
+            let remainder_len = len - len_of_head_partial_blk as u64;
+            let end_blk_id = start_blk_id + blk_size.div_round_up_by(remainder_len) as usize;
+
+            if end_blk_id >= blk_count {
+                (blk_count - 1, 0)
+            } else {
+                (end_blk_id, blk_size.mod_by(remainder_len) as usize)
+            }
+        };
+
+        Some(Self {
+            start_blk_id,
+            end_blk_id,
+            pos_of_head_partial_blk: if pos_of_head_partial_blk > 0 {
+                Some(pos_of_head_partial_blk)
+            } else {
+                None
+            },
+            pos_of_tail_partial_blk: if pos_of_tail_partial_blk > 0 {
+                Some(pos_of_tail_partial_blk)
+            } else {
+                None
+            },
+        })
+    }
+
+    fn has_partial_head_blk(&self) -> bool {
+        self.pos_of_head_partial_blk.is_some()
+    }
+
+    fn has_partial_tail_blk(&self) -> bool {
+        self.pos_of_tail_partial_blk.is_some()
+    }
+
+    fn last_full_blk(&self) -> isize {
+        if self.has_partial_tail_blk() {
+            self.end_blk_id as isize - 1
+        } else {
+            self.end_blk_id as isize
+        }
+    }
+}
+
+struct BufRef<'a>(&'a mut [u8]);
+
+impl BufRef<'_> {
+    unsafe fn extend_lifetime<'a>(&mut self) -> &'a mut [u8] {
+        slice::from_raw_parts_mut(self.0.as_mut_ptr(), self.0.len())
+    }
+}
+
+impl Deref for BufRef<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl DerefMut for BufRef<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0
+    }
+}
+
+struct BlkData(Vec<u8>);
+
+impl BlkData {
+    unsafe fn as_mut_slice<'a>(&mut self) -> &'a mut [u8] {
+        slice::from_raw_parts_mut(self.0.as_mut_ptr(), self.0.len())
+    }
+}
+
+impl Deref for BlkData {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        self.0.deref()
+    }
+}
+
+impl DerefMut for BlkData {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0.deref_mut()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::{boxed::Box, sync::Arc, vec::Vec};
+    use core::{
+        future::Future,
+        mem,
+        pin::Pin,
+        sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+        task::{Context, Poll, Waker},
+    };
+    use futures_util::{future::BoxFuture, StreamExt};
+    use spin::Mutex;
+    use spinlock::Irq;
+    use tokio_test::{assert_pending, assert_ready, block_on, task};
+
+    use crate::{BlkDevice, BlkSize, Result};
+
+    struct TestIrq;
+
+    impl Irq for TestIrq {
+        fn push_off() {}
+        fn pop_off() {}
+    }
+
+    type Disk = super::Disk<TestIrq>;
+
+    const LOGICAL: usize = 512;
+    const PHYSICAL: usize = 4 * LOGICAL;
+    const READ_AHEAD: usize = 8;
+
+    /// A RAM device with 4KB of physical blocks of 4 logical blocks,
+    /// recording the requests it gets as (start logical block, length in bytes).
+    /// Reads sent while `hold` is set stay in flight until the next `release`.
+    struct MockDevice {
+        data: Mutex<Vec<u8>>,
+        reads: Mutex<Vec<(usize, usize)>>,
+        writes: Mutex<Vec<(usize, usize)>>,
+        hold: AtomicBool,
+        releases: AtomicUsize,
+        held_wakers: Mutex<Vec<Waker>>,
+        /// Reads dropped while still in flight.
+        dropped_in_flight: AtomicUsize,
+        flushes: AtomicUsize,
+    }
+
+    impl MockDevice {
+        fn new(physical_blks: usize) -> Arc<Self> {
+            Arc::new(Self {
+                data: Mutex::new((0..physical_blks * PHYSICAL).map(|i| i as u8).collect()),
+                reads: Mutex::new(Vec::new()),
+                writes: Mutex::new(Vec::new()),
+                hold: AtomicBool::new(false),
+                releases: AtomicUsize::new(0),
+                held_wakers: Mutex::new(Vec::new()),
+                dropped_in_flight: AtomicUsize::new(0),
+                flushes: AtomicUsize::new(0),
+            })
+        }
+
+        fn release(&self) {
+            self.releases.fetch_add(1, Ordering::SeqCst);
+            for waker in mem::take(&mut *self.held_wakers.lock()) {
+                waker.wake();
+            }
+        }
+    }
+
+    struct MockRead<'a> {
+        device: &'a MockDevice,
+        start: usize,
+        buf: &'a mut [u8],
+        /// `releases` when the read was sent, if it is held.
+        held: Option<usize>,
+        done: bool,
+    }
+
+    impl Future for MockRead<'_> {
+        type Output = Result<()>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            if self.held == Some(self.device.releases.load(Ordering::SeqCst)) {
+                self.device.held_wakers.lock().push(cx.waker().clone());
+                return Poll::Pending;
+            }
+            let Self {
+                device, start, buf, ..
+            } = &mut *self;
+            buf.copy_from_slice(&device.data.lock()[*start..*start + buf.len()]);
+            self.done = true;
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl Drop for MockRead<'_> {
+        fn drop(&mut self) {
+            if !self.done {
+                self.device.dropped_in_flight.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    impl BlkDevice for MockDevice {
+        fn read_blk<'a>(&'a self, blk_id: usize, buf: &'a mut [u8]) -> BoxFuture<'a, Result<()>> {
+            self.read_blks(blk_id, buf)
+        }
+
+        fn write_blk<'a>(&'a self, blk_id: usize, src: &'a [u8]) -> BoxFuture<'a, Result<()>> {
+            self.write_blks(blk_id, src)
+        }
+
+        fn read_blks<'a>(
+            &'a self,
+            start_blk_id: usize,
+            buf: &'a mut [u8],
+        ) -> BoxFuture<'a, Result<()>> {
+            self.reads.lock().push((start_blk_id, buf.len()));
+            Box::pin(MockRead {
+                device: self,
+                start: start_blk_id * LOGICAL,
+                buf,
+                held: self
+                    .hold
+                    .load(Ordering::SeqCst)
+                    .then(|| self.releases.load(Ordering::SeqCst)),
+                done: false,
+            })
+        }
+
+        fn write_blks<'a>(
+            &'a self,
+            start_blk_id: usize,
+            src: &'a [u8],
+        ) -> BoxFuture<'a, Result<()>> {
+            self.writes.lock().push((start_blk_id, src.len()));
+            let start = start_blk_id * LOGICAL;
+            self.data.lock()[start..start + src.len()].copy_from_slice(src);
+            Box::pin(core::future::ready(Ok(())))
+        }
+
+        fn flush(&self) -> BoxFuture<'_, Result<()>> {
+            self.flushes.fetch_add(1, Ordering::SeqCst);
+            Box::pin(core::future::ready(Ok(())))
+        }
+
+        fn logical_blk_size(&self) -> BlkSize {
+            BlkSize::new(LOGICAL as u32)
+        }
+
+        fn physical_blk_size(&self) -> BlkSize {
+            BlkSize::new(PHYSICAL as u32)
+        }
+
+        fn blk_count(&self) -> usize {
+            self.data.lock().len() / LOGICAL
+        }
+    }
+
+    // Every request covers whole physical blocks.
+    fn assert_physical(requests: &[(usize, usize)]) {
+        for &(start_blk_id, len) in requests {
+            assert_eq!(start_blk_id * LOGICAL % PHYSICAL, 0, "{:?}", requests);
+            assert_eq!(len % PHYSICAL, 0, "{:?}", requests);
+        }
+    }
+
+    #[test]
+    fn partial_writes_are_padded_to_physical_blocks() {
+        let device = MockDevice::new(4);
+        let disk = Disk::new(device.clone(), READ_AHEAD);
+        let mut expected = device.data.lock().clone();
+
+        // Within one logical block, spanning logical blocks, spanning physical blocks.
+        for (offset, len) in [(600, 100), (1000, 1500), (PHYSICAL - 10, 2 * PHYSICAL + 20)] {
+            let src = vec![0xaa; len];
+            assert_eq!(block_on(disk.write_at(offset as u64, &src)).unwrap(), len);
+            expected[offset..offset + len].copy_from_slice(&src);
+        }
+
+        assert_physical(&device.reads.lock());
+        assert_physical(&device.writes.lock());
+        assert!(*device.data.lock() == expected);
+    }
+
+    #[test]
+    fn partial_reads_read_whole_physical_blocks() {
+        let device = MockDevice::new(4);
+        let disk = Disk::new(device.clone(), READ_AHEAD);
+        let expected = device.data.lock().clone();
+
+        let mut buf = vec![0; PHYSICAL + 100];
+        let offset = PHYSICAL / 2 + 3;
+        assert_eq!(
+            block_on(disk.read_at(offset as u64, &mut buf)).unwrap(),
+            buf.len()
+        );
+        assert_eq!(buf[..], expected[offset..offset + buf.len()]);
+        assert_physical(&device.reads.lock());
+        assert!(device.writes.lock().is_empty());
+    }
+
+    #[test]
+    fn aligned_accesses_need_no_scratch_block() {
+        let device = MockDevice::new(4);
+        let disk = Disk::new(device.clone(), READ_AHEAD);
+
+        let src = vec![0xaa; 2 * PHYSICAL];
+        assert_eq!(
+            block_on(disk.write_at(PHYSICAL as u64, &src)).unwrap(),
+            src.len()
+        );
+        assert!(device.reads.lock().is_empty());
+        assert_eq!(*device.writes.lock(), [(PHYSICAL / LOGICAL, 2 * PHYSICAL)]);
+
+        let mut buf = vec![0; 2 * PHYSICAL];
+        assert_eq!(
+            block_on(disk.read_at(PHYSICAL as u64, &mut buf)).unwrap(),
+            buf.len()
+        );
+        assert_eq!(buf, src);
+        // Read straight into `buf`, with one request.
+        assert_eq!(*device.reads.lock(), [(PHYSICAL / LOGICAL, 2 * PHYSICAL)]);
+    }
+
+    #[test]
+    fn sync_flushes_the_device_once() {
+        let device = MockDevice::new(4);
+        let disk = Disk::new(device.clone(), READ_AHEAD);
+
+        let src = vec![0xaa; 100];
+        block_on(disk.write_at(10, &src)).unwrap();
+        assert_eq!(device.flushes.load(Ordering::SeqCst), 0);
+        block_on(disk.sync()).unwrap();
+        assert_eq!(device.flushes.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn blocks_concatenate_to_the_whole_disk() {
+        let device = MockDevice::new(6);
+        let disk = Disk::new(device.clone(), READ_AHEAD);
+        let mut expected = vec![0; disk.capacity()];
+        assert_eq!(
+            block_on(disk.read_at(0, &mut expected)).unwrap(),
+            expected.len()
+        );
+
+        let blocks: Vec<_> = block_on(disk.blocks().collect());
+        assert_eq!(blocks.len(), 6);
+        let mut data = Vec::new();
+        for blk in blocks {
+            let blk = blk.unwrap();
+            assert_eq!(blk.len(), PHYSICAL);
+            data.extend_from_slice(&blk);
+        }
+        assert!(data == expected);
+    }
+
+    fn read_blk(disk: &Disk, blk_id: usize) -> Vec<u8> {
+        let mut buf = vec![0; PHYSICAL];
+        let offset = (blk_id * PHYSICAL) as u64;
+        assert_eq!(block_on(disk.read_at(offset, &mut buf)).unwrap(), PHYSICAL);
+        buf
+    }
+
+    #[test]
+    fn sequential_reads_are_read_ahead() {
+        let device = MockDevice::new(32);
+        let disk = Disk::new(device.clone(), READ_AHEAD);
+        disk.set_read_ahead(8);
+        let expected = device.data.lock().clone();
+
+        for blk_id in 0..10 {
+            assert_eq!(
+                read_blk(&disk, blk_id)[..],
+                expected[blk_id * PHYSICAL..(blk_id + 1) * PHYSICAL]
+            );
+        }
+        // The second read makes the reader sequential, the next 8 blocks are
+        // requested at once before the block it reads.
+        assert_eq!(
+            *device.reads.lock(),
+            [(0, PHYSICAL), (8, 8 * PHYSICAL), (4, PHYSICAL)]
+        );
+    }
+
+    #[test]
+    fn readers_are_woken_by_the_read_ahead() {
+        let device = MockDevice::new(32);
+        let disk = Disk::new(device.clone(), READ_AHEAD);
+        disk.set_read_ahead(8);
+        let expected = device.data.lock().clone();
+
+        read_blk(&disk, 0);
+        // Blocks 2..10 are read ahead and held in flight.
+        let mut buf = vec![0; PHYSICAL];
+        device.hold.store(true, Ordering::SeqCst);
+        let read = disk.read_at(PHYSICAL as u64, &mut buf);
+        device.hold.store(false, Ordering::SeqCst);
+        block_on(read).unwrap();
+
+        let mut read = task::spawn(disk.read_at(2 * PHYSICAL as u64, &mut buf));
+        assert_pending!(read.poll());
+        device.release();
+        assert!(read.is_woken());
+        assert_eq!(assert_ready!(read.poll()).unwrap(), PHYSICAL);
+        drop(read);
+        assert_eq!(buf[..], expected[2 * PHYSICAL..3 * PHYSICAL]);
+        // Served by the read ahead.
+        assert_eq!(device.reads.lock().len(), 3);
+    }
+
+    #[test]
+    fn writes_keep_the_read_ahead_in_flight() {
+        let device = MockDevice::new(32);
+        let disk = Disk::new(device.clone(), READ_AHEAD);
+        disk.set_read_ahead(8);
+
+        read_blk(&disk, 0);
+        // Blocks 2..10 are read ahead and held in flight.
+        let mut buf = vec![0; PHYSICAL];
+        device.hold.store(true, Ordering::SeqCst);
+        let read = disk.read_at(PHYSICAL as u64, &mut buf);
+        device.hold.store(false, Ordering::SeqCst);
+        block_on(read).unwrap();
+
+        let src = vec![0xaa; PHYSICAL];
+        assert_eq!(
+            block_on(disk.write_at(3 * PHYSICAL as u64, &src)).unwrap(),
+            PHYSICAL
+        );
+        assert_eq!(device.dropped_in_flight.load(Ordering::SeqCst), 0);
+
+        // The blocks read ahead are stale, they are read again.
+        read_blk(&disk, 2);
+        device.release();
+        assert_eq!(read_blk(&disk, 3), src);
+        assert_eq!(device.dropped_in_flight.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,170 @@
+//! Block devices, and the byte addressed `Disk` over them.
+
+#![feature(generic_associated_types)]
+#![feature(ready_macro)]
+#![no_std]
+
+#[macro_use]
+extern crate alloc;
+
+pub mod disk;
+#[cfg(feature = "naive_fs")]
+mod naive_fs_disk;
+pub mod ram_blk;
+
+use core::{marker::PhantomData, ops};
+
+use alloc::boxed::Box;
+use futures_util::future::BoxFuture;
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The device is not ready.
+    NotReady,
+    /// Failed to alloc DMA memory.
+    DmaErr,
+    /// I/O Error
+    IoErr,
+    /// Invalid parameter.
+    InvalidParam,
+}
+
+/// BlkDevice represents a block device.
+pub trait BlkDevice: Send + Sync {
+    /// Read the data of the specified block into the `buf` slice.
+    /// Block ids and buf lengths are in units of the logical block size.
+    /// Buf slice length must equal blk_size.
+    fn read_blk<'a>(&'a self, blk_id: usize, buf: &'a mut [u8]) -> BoxFuture<'a, Result<()>>;
+
+    /// Writes `src` slice data to the specified block.
+    /// Buf slice length must equal blk_size.
+    fn write_blk<'a>(&'a self, blk_id: usize, src: &'a [u8]) -> BoxFuture<'a, Result<()>>;
+
+    /// Read `buf.len() / blk_size` contiguous blocks starting at `start_blk_id`.
+    /// Buf slice length must be a multiple of blk_size.
+    /// Devices that can transfer several blocks in one request should override this;
+    /// the default issues one `read_blk` per block.
+    fn read_blks<'a>(
+        &'a self,
+        start_blk_id: usize,
+        buf: &'a mut [u8],
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let blk_size = self.logical_blk_size().size() as usize;
+            if buf.len() % blk_size != 0 {
+                return Err(Error::InvalidParam);
+            }
+            for (i, blk) in buf.chunks_mut(blk_size).enumerate() {
+                self.read_blk(start_blk_id + i, blk).await?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Write `src.len() / blk_size` contiguous blocks starting at `start_blk_id`.
+    /// Src slice length must be a multiple of blk_size.
+    fn write_blks<'a>(&'a self, start_blk_id: usize, src: &'a [u8]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let blk_size = self.logical_blk_size().size() as usize;
+            if src.len() % blk_size != 0 {
+                return Err(Error::InvalidParam);
+            }
+            for (i, blk) in src.chunks(blk_size).enumerate() {
+                self.write_blk(start_blk_id + i, blk).await?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Tell the device that `blk_cnt` blocks starting at `start_blk_id` no longer hold data,
+    /// so it may reclaim them. Reading them afterwards returns unspecified data.
+    /// Devices without TRIM/discard need not override this.
+    fn discard(&self, _start_blk_id: usize, _blk_cnt: usize) -> BoxFuture<'_, Result<()>> {
+        Box::pin(core::future::ready(Ok(())))
+    }
+
+    /// Flush the device's volatile write cache, so every completed write is durable.
+    /// Devices without a write cache need not override this.
+    fn flush(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(core::future::ready(Ok(())))
+    }
+
+    /// Get the BlkDevice's logical block size, the unit it is addressed in.
+    fn logical_blk_size(&self) -> BlkSize;
+
+    /// Get the BlkDevice's physical block size, the unit it writes atomically.
+    /// Writing less than a physical block makes the device read-modify-write it internally.
+    /// Never smaller than the logical block size, defaults to it.
+    fn physical_blk_size(&self) -> BlkSize {
+        self.logical_blk_size()
+    }
+
+    /// Get the BlkDevice's block count, in logical blocks.
+    fn blk_count(&self) -> usize;
+}
+
+/// The block size type.
+#[derive(Debug, Clone, Copy)]
+pub struct BlkSize<BS = u32> {
+    /// log2(blk_size)
+    pub blk_size_log2: u8,
+    _maker: PhantomData<BS>,
+}
+
+impl<BS> BlkSize<BS>
+where
+    BS: From<u8> + ops::Shl<u8, Output = BS>,
+{
+    /// Create BlkSize
+    pub fn new(blk_size: u32) -> Self {
+        assert!(blk_size.is_power_of_two(), "block_size must be power of 2.");
+
+        Self {
+            blk_size_log2: (blk_size - 1).count_ones() as u8,
+            _maker: PhantomData,
+        }
+    }
+
+    /// Create BlkSize with log2(blk_size) value.
+    pub fn with_blk_size_log2(blk_size_log2: u8) -> Self {
+        Self {
+            blk_size_log2,
+            _maker: PhantomData,
+        }
+    }
+
+    /// Returns block size.
+    pub fn size(&self) -> BS {
+        BS::from(1) << self.blk_size_log2
+    }
+
+    /// Performs `dividend` / `blk_size`.
+    pub fn div_by<D: ops::Shr<u8, Output = D>>(&self, dividend: D) -> D {
+        dividend >> self.blk_size_log2
+    }
+
+    ///  Performs `dividend` / `blk_size` and round up to the nearest integer if not evenly divisable.
+    pub fn div_round_up_by<D>(&self, dividend: D) -> D
+    where
+        BS: ops::Sub<Output = BS>,
+        D: From<BS> + ops::Add<Output = D> + ops::Shr<u8, Output = D>,
+    {
+        (dividend + D::from(self.size() - BS::from(1))) >> self.blk_size_log2
+    }
+
+    /// Performs `m` * `blk_size`.
+    pub fn mul<M: ops::Shl<u8, Output = M>>(&self, m: M) -> M {
+        m << self.blk_size_log2
+    }
+
+    /// Performs `dividend` % `blk_size`.
+    pub fn mod_by<D>(&self, dividend: D) -> D
+    where
+        BS: ops::Sub<Output = BS>,
+        D: From<BS> + ops::BitAnd<Output = D>,
+    {
+        dividend & D::from(self.size() - BS::from(1))
+    }
+}
@@ -0,0 +1,55 @@
+//! `Disk` as the disk of a naive filesystem.
+
+use alloc::boxed::Box;
+use futures_util::{future::Map, FutureExt, TryFutureExt};
+use naive_fs::BoxFuture;
+use spinlock::Irq;
+
+use crate::{
+    disk::{Disk, ReadAtFut, WriteAtFut},
+    Error, Result,
+};
+
+impl<I: Irq + 'static> naive_fs::Disk for Disk<I> {
+    type ReadAtFut<'a> = Map<ReadAtFut<'a, I>, fn(Result<usize>) -> naive_fs::DiskResult<u32>>;
+
+    type WriteAtFut<'a> = Map<WriteAtFut<'a, I>, fn(Result<usize>) -> naive_fs::DiskResult<u32>>;
+
+    type SyncFut<'a> = BoxFuture<'a, naive_fs::DiskResult<()>>;
+
+    fn read_at<'a>(&'a self, offset: u32, buf: &'a mut [u8]) -> Self::ReadAtFut<'a> {
+        Disk::read_at(self, offset as u64, buf).map(|res| match res {
+            Ok(len) => Ok(len as u32),
+            Err(e) => Err(e.into()),
+        })
+    }
+
+    fn write_at<'a>(&'a self, offset: u32, src: &'a [u8]) -> Self::WriteAtFut<'a> {
+        Disk::write_at(self, offset as u64, src).map(|res| match res {
+            Ok(len) => Ok(len as u32),
+            Err(e) => Err(e.into()),
+        })
+    }
+
+    fn sync(&self) -> Self::SyncFut<'_> {
+        Box::pin(Disk::sync(self).map_err(Into::into))
+    }
+
+    fn discard(&self, offset: u32, len: u32) -> BoxFuture<'_, naive_fs::DiskResult<()>> {
+        Box::pin(Disk::discard(self, offset as u64, len as u64).map_err(Into::into))
+    }
+
+    fn capacity(&self) -> u32 {
+        Disk::capacity(self) as u32
+    }
+
+    fn refresh_capacity(&self) -> u32 {
+        Disk::refresh_capacity(self) as u32
+    }
+}
+
+impl From<Error> for naive_fs::DiskError {
+    fn from(disk_err: Error) -> Self {
+        Box::new(disk_err)
+    }
+}
@@ -1,4 +1,10 @@
-// #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
 pub mod riscv;
-// #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
 pub use riscv::*;
+
+// Hosted builds, like the tests of the crates using this one, print to stderr.
+#[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+mod host;
+#[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+pub use host::*;
@@ -0,0 +1,276 @@
+//! The memory the flattened device tree handed over by the firmware describes:
+//! the regions of physical memory and the ones the firmware keeps for itself.
+
+#![no_std]
+
+extern crate alloc;
+#[cfg(test)]
+extern crate std;
+
+use core::{convert::TryInto, ops::Range};
+
+use alloc::vec::Vec;
+use device_tree::{DeviceTree, Node};
+use mm::PhysicalAddress;
+
+pub const MAGIC: u32 = 0xd00dfeed;
+
+/// The length of the header `size` needs.
+pub const HEADER_LEN: usize = 8;
+
+/// Returns the size of the device tree blob starting with `header`, `None` if it is not one.
+pub fn size(header: &[u8]) -> Option<usize> {
+    if header.len() < HEADER_LEN || read_cells(&header[..4]) as u32 != MAGIC {
+        return None;
+    }
+    Some(read_cells(&header[4..HEADER_LEN]))
+}
+
+/// Parses the device tree blob `dtb`.
+pub fn load(dtb: &[u8]) -> Option<DeviceTree> {
+    size(dtb)?;
+    DeviceTree::load(dtb).ok()
+}
+
+/// Returns the physical memory regions listed by the `reg` properties
+/// of the `/memory` nodes of `dt`.
+pub fn memory_regions(dt: &DeviceTree) -> Vec<Range<PhysicalAddress>> {
+    let cells = Cells::of(&dt.root, Cells::default());
+    dt.root
+        .children
+        .iter()
+        .filter(|node| {
+            node.prop_raw("device_type").map(|ty| ty.as_slice()) == Some(&b"memory\0"[..])
+        })
+        .flat_map(|node| cells.reg(node))
+        .collect()
+}
+
+/// Returns the memory regions the firmware keeps for itself,
+/// the `reg`s of the children of the `/reserved-memory` node of `dt`.
+pub fn reserved_memory_regions(dt: &DeviceTree) -> Vec<Range<PhysicalAddress>> {
+    let root_cells = Cells::of(&dt.root, Cells::default());
+    dt.root
+        .children
+        .iter()
+        .filter(|node| node.name == "reserved-memory")
+        .flat_map(|reserved| {
+            let cells = Cells::of(reserved, root_cells);
+            reserved
+                .children
+                .iter()
+                .flat_map(move |node| cells.reg(node))
+        })
+        .collect()
+}
+
+/// How many 32-bit cells make up the addresses and sizes in the `reg` of the children of a node.
+#[derive(Clone, Copy)]
+struct Cells {
+    address: usize,
+    size: usize,
+}
+
+impl Default for Cells {
+    // Defaults of the devicetree specification.
+    fn default() -> Self {
+        Self {
+            address: 2,
+            size: 1,
+        }
+    }
+}
+
+impl Cells {
+    // The cells of the children of `node`, taken from `parent` unless `node` sets them.
+    fn of(node: &Node, parent: Self) -> Self {
+        Self {
+            address: node
+                .prop_u32("#address-cells")
+                .map_or(parent.address, |n| n as usize),
+            size: node
+                .prop_u32("#size-cells")
+                .map_or(parent.size, |n| n as usize),
+        }
+    }
+
+    fn reg(self, node: &Node) -> Vec<Range<PhysicalAddress>> {
+        let entry_len = (self.address + self.size) * 4;
+        match node.prop_raw("reg") {
+            Some(reg) if entry_len > 0 => reg
+                .chunks_exact(entry_len)
+                .map(|entry| {
+                    let (start, size) = entry.split_at(self.address * 4);
+                    let start = read_cells(start);
+                    PhysicalAddress(start)..PhysicalAddress(start.saturating_add(read_cells(size)))
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+// Reads a big endian number made of 32-bit cells.
+fn read_cells(cells: &[u8]) -> usize {
+    cells.chunks_exact(4).fold(0, |n, cell| {
+        (n << 32) | u32::from_be_bytes(cell.try_into().unwrap()) as usize
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+
+    use mm::PhysicalAddress;
+
+    use super::{load, memory_regions, reserved_memory_regions, size};
+
+    // Writes a flattened device tree blob, version 17, with an empty memory reservation block.
+    #[derive(Default)]
+    struct Blob {
+        structs: Vec<u8>,
+        strings: Vec<u8>,
+    }
+
+    impl Blob {
+        fn token(&mut self, token: u32) -> &mut Self {
+            self.structs.extend_from_slice(&token.to_be_bytes());
+            self
+        }
+
+        fn pad(&mut self) {
+            let len = (self.structs.len() + 3) & !3;
+            self.structs.resize(len, 0);
+        }
+
+        fn begin(&mut self, name: &str) -> &mut Self {
+            self.token(1);
+            self.structs.extend_from_slice(name.as_bytes());
+            self.structs.push(0);
+            self.pad();
+            self
+        }
+
+        fn end(&mut self) -> &mut Self {
+            self.token(2)
+        }
+
+        fn prop(&mut self, name: &str, value: &[u8]) -> &mut Self {
+            let name_off = self.strings.len() as u32;
+            self.strings.extend_from_slice(name.as_bytes());
+            self.strings.push(0);
+            self.token(3).token(value.len() as u32).token(name_off);
+            self.structs.extend_from_slice(value);
+            self.pad();
+            self
+        }
+
+        fn cells(&mut self, name: &str, cells: &[u32]) -> &mut Self {
+            let value: Vec<u8> = cells.iter().flat_map(|c| c.to_be_bytes()).collect();
+            self.prop(name, &value)
+        }
+
+        fn finish(&mut self) -> Vec<u8> {
+            self.token(9);
+            let (header_len, rsvmap_len) = (40, 16);
+            let off_struct = header_len + rsvmap_len;
+            let off_strings = off_struct + self.structs.len();
+            let total = off_strings + self.strings.len();
+            let mut blob = Vec::new();
+            for field in [
+                super::MAGIC,
+                total as u32,
+                off_struct as u32,
+                off_strings as u32,
+                header_len as u32,
+                17,
+                16,
+                0,
+                self.strings.len() as u32,
+                self.structs.len() as u32,
+            ] {
+                blob.extend_from_slice(&field.to_be_bytes());
+            }
+            blob.resize(off_struct, 0);
+            blob.extend_from_slice(&self.structs);
+            blob.extend_from_slice(&self.strings);
+            blob
+        }
+    }
+
+    fn region(start: usize, len: usize) -> core::ops::Range<PhysicalAddress> {
+        PhysicalAddress(start)..PhysicalAddress(start + len)
+    }
+
+    #[test]
+    fn memory_nodes_are_read_with_the_cells_of_the_root() {
+        let blob = Blob::default()
+            .begin("")
+            .cells("#address-cells", &[2])
+            .cells("#size-cells", &[2])
+            .begin("memory@80000000")
+            .prop("device_type", b"memory\0")
+            .cells("reg", &[0, 0x8000_0000, 0, 0x400_0000, 1, 0, 0, 0x1000])
+            .end()
+            .begin("uart@10000000")
+            .cells("reg", &[0, 0x1000_0000, 0, 0x100])
+            .end()
+            .begin("memory@c0000000")
+            .prop("device_type", b"memory\0")
+            .cells("reg", &[0, 0xc000_0000, 0, 0x200_0000])
+            .end()
+            .end()
+            .finish();
+        assert_eq!(size(&blob), Some(blob.len()));
+
+        let dt = load(&blob).unwrap();
+        assert_eq!(
+            memory_regions(&dt),
+            [
+                region(0x8000_0000, 0x400_0000),
+                region(0x1_0000_0000, 0x1000),
+                region(0xc000_0000, 0x200_0000),
+            ]
+        );
+        assert!(reserved_memory_regions(&dt).is_empty());
+    }
+
+    #[test]
+    fn reserved_memory_uses_its_own_cells() {
+        // The root sets no cells, so its children take the defaults: 2 for addresses, 1 for sizes.
+        let blob = Blob::default()
+            .begin("")
+            .begin("memory@80000000")
+            .prop("device_type", b"memory\0")
+            .cells("reg", &[0, 0x8000_0000, 0x800_0000])
+            .end()
+            .begin("reserved-memory")
+            .cells("#address-cells", &[1])
+            .cells("#size-cells", &[1])
+            .begin("mmode_resv0@80000000")
+            .cells("reg", &[0x8000_0000, 0x2_0000])
+            .end()
+            .begin("mmode_resv1@80020000")
+            .cells("reg", &[0x8002_0000, 0x1_0000])
+            .end()
+            .end()
+            .end()
+            .finish();
+
+        let dt = load(&blob).unwrap();
+        assert_eq!(memory_regions(&dt), [region(0x8000_0000, 0x800_0000)]);
+        assert_eq!(
+            reserved_memory_regions(&dt),
+            [region(0x8000_0000, 0x2_0000), region(0x8002_0000, 0x1_0000)]
+        );
+    }
+
+    #[test]
+    fn a_blob_without_the_magic_is_not_loaded() {
+        let mut blob = Blob::default().begin("").end().finish();
+        assert_eq!(size(&blob[..4]), None);
+        blob[0] = 0;
+        assert_eq!(size(&blob), None);
+        assert!(load(&blob).is_none());
+    }
+}
@@ -1,84 +1,280 @@
-use alloc::{collections::BTreeMap, sync::Arc, task::Wake};
-use core::{pin::Pin, task::Context, task::Waker};
+use alloc::{collections::BTreeMap, sync::Arc, task::Wake, vec::Vec};
+use core::{
+    cell::UnsafeCell,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll, Waker},
+};
 use crossbeam_queue::ArrayQueue;
+use lock_api::{Mutex, RawMutex};
 
-use crate::ThreadFuture;
+#[cfg(feature = "watchdog")]
+use core::{sync::atomic::AtomicUsize, time::Duration};
 
-const TASK_QUEUE_FULL: &str = "task_queue full";
+use crate::{TaskResult, ThreadFuture, WaitForInterrupt};
 
-type Tasks<TF> = BTreeMap<<TF as ThreadFuture>::ID, (TF, Option<Waker>)>;
+type Tasks<TF> = BTreeMap<<TF as ThreadFuture>::ID, Arc<Task<TF>>>;
 
-pub struct FIFOExecutor<TF: ThreadFuture> {
-    tasks: Tasks<TF>,
+struct Task<TF: ThreadFuture> {
+    thread: TF::Thread,
+    future: UnsafeCell<TF>,
+    wake: Arc<TaskWaker<TF>>,
+    waker: Waker,
+    // Set while a hart polls `future`.
+    polling: AtomicBool,
+    // The `progress` of `wake` the watchdog last saw, the tick it saw it at,
+    // and whether the wait since was logged. Only accessed with `tasks` locked.
+    #[cfg(feature = "watchdog")]
+    seen_progress: AtomicUsize,
+    #[cfg(feature = "watchdog")]
+    seen_at: AtomicUsize,
+    #[cfg(feature = "watchdog")]
+    reported: AtomicBool,
+}
+
+// `future` is only accessed by the hart that set `polling`.
+unsafe impl<TF: ThreadFuture> Sync for Task<TF> {}
+unsafe impl<TF: ThreadFuture> Send for Task<TF> {}
+
+/// An executor shared by all harts.
+/// Every hart runs `run_ready_tasks` on the same FIFO queue of woken tasks,
+/// a task is polled by at most one hart at a time.
+pub struct FIFOExecutor<TF: ThreadFuture, M: RawMutex> {
+    tasks: Mutex<M, Tasks<TF>>,
     task_queue: Arc<ArrayQueue<TF::ID>>,
+    #[cfg(feature = "watchdog")]
+    watchdog: Watchdog,
+}
+
+/// Counts timer ticks to notice tasks that wait to be polled, or are being polled,
+/// for too long. A task parked until some event wakes it is not waiting on the executor.
+#[cfg(feature = "watchdog")]
+#[derive(Default)]
+struct Watchdog {
+    ticks: AtomicUsize,
+    // Ticks a task may go without progress before it is logged, 0 disables the watchdog.
+    stall_ticks: AtomicUsize,
 }
 
-impl<TF> FIFOExecutor<TF>
+impl<TF, M> FIFOExecutor<TF, M>
 where
     TF: ThreadFuture,
+    TF::Output: TaskResult,
+    M: RawMutex,
 {
     pub fn new(queue_size: usize) -> Self {
         Self {
-            tasks: BTreeMap::new(),
+            tasks: Mutex::new(BTreeMap::new()),
             task_queue: Arc::new(ArrayQueue::new(queue_size)),
+            #[cfg(feature = "watchdog")]
+            watchdog: Watchdog::default(),
+        }
+    }
+
+    /// Sets how many `watchdog_tick`s a task may wait to be polled, or be polled,
+    /// before it is logged. 0, the default, disables the watchdog.
+    #[cfg(feature = "watchdog")]
+    pub fn set_watchdog_ticks(&self, ticks: usize) {
+        self.watchdog.stall_ticks.store(ticks, Ordering::Relaxed);
+    }
+
+    /// Advances the watchdog by one tick, meant to be called on every timer interrupt.
+    /// Logs, and returns, the ids of the tasks that have been queued or polled for the
+    /// configured number of ticks without being woken, polled or completing since.
+    /// A stall of a task is logged once, the next one only after it made progress.
+    #[cfg(feature = "watchdog")]
+    pub fn watchdog_tick(&self) -> Vec<TF::ID> {
+        let now = self.watchdog.ticks.fetch_add(1, Ordering::Relaxed) + 1;
+        let stall_ticks = self.watchdog.stall_ticks.load(Ordering::Relaxed);
+        let mut stalled = Vec::new();
+        if stall_ticks == 0 {
+            return stalled;
+        }
+        // May interrupt a hart holding the lock, the next tick tries again.
+        let tasks = match self.tasks.try_lock() {
+            Some(tasks) => tasks,
+            None => return stalled,
+        };
+        for (task_id, task) in tasks.iter() {
+            let progress = task.wake.progress.load(Ordering::Acquire);
+            if task.seen_progress.swap(progress, Ordering::Relaxed) != progress {
+                task.seen_at.store(now, Ordering::Relaxed);
+                task.reported.store(false, Ordering::Relaxed);
+                continue;
+            }
+            let polling = task.polling.load(Ordering::Acquire);
+            if !polling && !task.wake.queued.load(Ordering::Acquire) {
+                // Parked until some event wakes it, which may take arbitrarily long.
+                continue;
+            }
+            // Ticks of other harts may have stamped a later tick than `now`.
+            let waited = now.saturating_sub(task.seen_at.load(Ordering::Relaxed));
+            if waited >= stall_ticks && !task.reported.swap(true, Ordering::Relaxed) {
+                let state = if polling {
+                    "polled"
+                } else {
+                    "waiting to be polled"
+                };
+                debug::println!(
+                    "executor stalled: task {:?} {} for {} ticks",
+                    task_id,
+                    state,
+                    waited
+                );
+                stalled.push(task_id.clone());
+            }
+        }
+        stalled
+    }
+
+    /// Returns whether some task waits to be polled or is being polled, so the watchdog
+    /// needs its ticks even while a hart idles.
+    #[cfg(feature = "watchdog")]
+    pub fn watchdog_armed(&self) -> bool {
+        self.watchdog.stall_ticks.load(Ordering::Relaxed) != 0
+            && self.tasks.lock().values().any(|task| {
+                task.polling.load(Ordering::Acquire) || task.wake.queued.load(Ordering::Acquire)
+            })
+    }
+
+    /// Returns the timer deadline of an idle hart whose soonest timer expires at `deadline`:
+    /// no later than `tick`, the next watchdog tick, while the watchdog is armed.
+    #[cfg(feature = "watchdog")]
+    pub fn watchdog_deadline(
+        &self,
+        deadline: Option<Duration>,
+        tick: Duration,
+    ) -> Option<Duration> {
+        if !self.watchdog_armed() {
+            return deadline;
         }
+        Some(deadline.map_or(tick, |deadline| deadline.min(tick)))
     }
 
     /// Returns the thread corresponding to the tid.
     pub fn thread(&self, tid: &TF::ID) -> Option<TF::Thread> {
-        self.tasks.get(tid).map(|(x, _)| x.thread().clone())
+        self.tasks.lock().get(tid).map(|task| task.thread.clone())
     }
 
-    pub fn spawn(&mut self, thread_fut: TF) -> Option<()> {
-        let task_id = thread_fut.id().clone();
-        let tasks = &mut self.tasks;
-        self.task_queue
-            .push(task_id.clone())
-            .map_or(None, |_| Some(()))?;
+    /// Returns the threads of all spawned tasks.
+    pub fn threads(&self) -> impl Iterator<Item = TF::Thread> {
+        self.tasks
+            .lock()
+            .values()
+            .map(|task| task.thread.clone())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    /// Returns whether some woken task waits to be polled.
+    pub fn has_ready_tasks(&self) -> bool {
+        !self.task_queue.is_empty()
+    }
+
+    /// Waits for an interrupt with `W::wfi`, unless a task was woken since the last
+    /// `run_ready_tasks`. Instead of ticking, the timer is programmed for `W::next_deadline`.
+    /// Must be called with interrupts disabled, so a task woken by an interrupt after the check
+    /// leaves the interrupt pending, which ends `wfi`. Returns whether the hart waited.
+    pub fn idle<W: WaitForInterrupt>(&self) -> bool {
+        if self.has_ready_tasks() {
+            return false;
+        }
+        W::set_timer(W::next_deadline());
+        W::wfi();
+        true
+    }
 
-        if tasks.insert(task_id, (thread_fut, None)).is_some() {
+    /// Spawns a task and queues it to be polled.
+    /// Returns `None` if there are as many tasks as the queue has room for,
+    /// every task fits into the queue at once so waking one never fails.
+    /// There is no unwinding to catch a panic with, so a task reports a failure it can
+    /// recover from through its output, which `run_ready_tasks` checks with `TaskResult`.
+    pub fn spawn(&self, thread_fut: TF) -> Option<()> {
+        let task_id = thread_fut.id().clone();
+        let wake = Arc::new(TaskWaker::new(task_id.clone(), self.task_queue.clone()));
+        let task = Arc::new(Task {
+            thread: thread_fut.thread().clone(),
+            future: UnsafeCell::new(thread_fut),
+            waker: Waker::from(wake.clone()),
+            wake,
+            polling: AtomicBool::new(false),
+            #[cfg(feature = "watchdog")]
+            seen_progress: AtomicUsize::new(0),
+            #[cfg(feature = "watchdog")]
+            seen_at: AtomicUsize::new(self.watchdog.ticks.load(Ordering::Relaxed)),
+            #[cfg(feature = "watchdog")]
+            reported: AtomicBool::new(false),
+        });
+        let mut tasks = self.tasks.lock();
+        if tasks.len() >= self.task_queue.capacity() {
+            return None;
+        }
+        if tasks.insert(task_id.clone(), task.clone()).is_some() {
             panic!("task with same ID already in tasks");
         }
+        task.waker.wake_by_ref();
         Some(())
     }
 
-    pub fn run_ready_tasks(&mut self) {
-        let Self { tasks, task_queue } = self;
-        while let Some(task_id) = task_queue.pop() {
-            let (thread, waker_opt) = match tasks.get_mut(&task_id) {
-                Some(tup) => tup,
+    /// Polls woken tasks until the queue is empty.
+    /// A task that completes with an error has it logged, it does not stop the others.
+    /// May be called by several harts at once.
+    pub fn run_ready_tasks(&self) {
+        while let Some(task_id) = self.task_queue.pop() {
+            let task = match self.tasks.lock().get(&task_id) {
+                Some(task) => task.clone(),
                 None => continue,
             };
+            // Wakes from now on queue the task again.
+            task.wake.queued.store(false, Ordering::Release);
 
-            let waker = match waker_opt {
-                Some(ref waker) => waker,
-                None => {
-                    *waker_opt =
-                        Some(TaskWaker::<TF>::new(task_id.clone(), task_queue.clone()).waker());
-                    waker_opt.as_ref().unwrap()
-                }
-            };
+            if task
+                .polling
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                // Woken while another hart polls it, poll it again once that hart is done.
+                task.waker.wake_by_ref();
+                continue;
+            }
 
-            let mut context = Context::from_waker(waker);
+            #[cfg(feature = "watchdog")]
+            task.wake.progress.fetch_add(1, Ordering::AcqRel);
 
-            if unsafe { Pin::new_unchecked(thread) }
-                .poll(&mut context)
-                .is_ready()
+            let mut context = Context::from_waker(&task.waker);
+            if let Poll::Ready(output) =
+                unsafe { Pin::new_unchecked(&mut *task.future.get()) }.poll(&mut context)
             {
-                // Remove from tasks and waker_cache when task is complete
-                tasks.remove(&task_id);
+                if let Some(err) = output.err() {
+                    debug::println!("task {:?} failed: {:?}", task_id, err);
+                }
+                // Remove from tasks when task is complete
+                self.tasks.lock().remove(&task_id);
             }
+            #[cfg(feature = "watchdog")]
+            task.wake.progress.fetch_add(1, Ordering::AcqRel);
+            task.polling.store(false, Ordering::Release);
         }
     }
 
+    /// Returns the waker of the task `task_id`,
+    /// a waker doing nothing if the task has completed or was never spawned.
     pub fn waker(&self, task_id: &TF::ID) -> Waker {
-        TaskWaker::<TF>::new(task_id.clone(), self.task_queue.clone()).waker()
+        match self.tasks.lock().get(task_id) {
+            Some(task) => task.waker.clone(),
+            None => Waker::from(Arc::new(NoopWaker)),
+        }
     }
 }
 
 struct TaskWaker<TRD: ThreadFuture> {
     task_id: TRD::ID,
     task_queue: Arc<ArrayQueue<TRD::ID>>,
+    // Set while the task is in `task_queue`, so that it is queued at most once.
+    queued: AtomicBool,
+    // Bumped whenever the task is queued, starts or ends a poll, for the watchdog.
+    #[cfg(feature = "watchdog")]
+    progress: AtomicUsize,
 }
 
 impl<TRD: ThreadFuture> TaskWaker<TRD> {
@@ -86,19 +282,29 @@ impl<TRD: ThreadFuture> TaskWaker<TRD> {
         Self {
             task_id,
             task_queue,
+            queued: AtomicBool::new(false),
+            #[cfg(feature = "watchdog")]
+            progress: AtomicUsize::new(0),
         }
     }
-    fn waker(self) -> Waker {
-        Waker::from(Arc::new(self))
-    }
 
     fn wake_task(&self) {
-        if self.task_queue.push(self.task_id.clone()).is_err() {
-            panic!("{}", TASK_QUEUE_FULL);
+        if !self.queued.swap(true, Ordering::AcqRel) {
+            #[cfg(feature = "watchdog")]
+            self.progress.fetch_add(1, Ordering::AcqRel);
+            // `spawn` keeps the number of tasks within the capacity of the queue.
+            let pushed = self.task_queue.push(self.task_id.clone());
+            debug_assert!(pushed.is_ok(), "task queue full");
         }
     }
 }
 
+struct NoopWaker;
+
+impl Wake for NoopWaker {
+    fn wake(self: Arc<Self>) {}
+}
+
 impl<TRD: ThreadFuture> Wake for TaskWaker<TRD> {
     fn wake(self: Arc<Self>) {
         self.wake_task();
@@ -108,3 +314,373 @@ impl<TRD: ThreadFuture> Wake for TaskWaker<TRD> {
         self.wake_task();
     }
 }
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use alloc::sync::Arc;
+    use core::{
+        fmt::{self, Debug},
+        future::Future,
+        pin::Pin,
+        sync::atomic::{AtomicUsize, Ordering},
+        task::{Context, Poll},
+        time::Duration,
+    };
+    use std::{cell::Cell, thread, thread_local, vec, vec::Vec};
+
+    use super::FIFOExecutor;
+    use crate::{ThreadFuture, WaitForInterrupt};
+
+    type Executor = FIFOExecutor<Yield, spin::Mutex<()>>;
+
+    // Wakes itself `wakes` times on each of its first `polls` polls, then completes.
+    struct Yield {
+        id: usize,
+        polls: usize,
+        wakes: usize,
+        done: Arc<AtomicUsize>,
+    }
+
+    impl Yield {
+        fn new(id: usize, polls: usize, wakes: usize, done: &Arc<AtomicUsize>) -> Self {
+            Self {
+                id,
+                polls,
+                wakes,
+                done: done.clone(),
+            }
+        }
+    }
+
+    impl Future for Yield {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.polls == 0 {
+                self.done.fetch_add(1, Ordering::SeqCst);
+                return Poll::Ready(());
+            }
+            self.polls -= 1;
+            for _ in 0..self.wakes {
+                cx.waker().wake_by_ref();
+            }
+            Poll::Pending
+        }
+    }
+
+    impl ThreadFuture for Yield {
+        type ID = usize;
+
+        type Thread = usize;
+
+        fn id(&self) -> &usize {
+            &self.id
+        }
+
+        fn thread(&self) -> &usize {
+            &self.id
+        }
+    }
+
+    // Counts how many times it was formatted, that is logged.
+    struct Failure(Arc<AtomicUsize>);
+
+    impl Debug for Failure {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            f.write_str("Failure")
+        }
+    }
+
+    // Completes on its first poll, with an error if `logged` is set.
+    struct Fallible {
+        id: usize,
+        logged: Option<Arc<AtomicUsize>>,
+    }
+
+    impl Future for Fallible {
+        type Output = Result<(), Failure>;
+
+        fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+            Poll::Ready(match self.logged.take() {
+                Some(logged) => Err(Failure(logged)),
+                None => Ok(()),
+            })
+        }
+    }
+
+    impl ThreadFuture for Fallible {
+        type ID = usize;
+
+        type Thread = usize;
+
+        fn id(&self) -> &usize {
+            &self.id
+        }
+
+        fn thread(&self) -> &usize {
+            &self.id
+        }
+    }
+
+    thread_local! {
+        // The soonest timer deadline, the deadlines programmed and the interrupts waited for.
+        static DEADLINE: Cell<Option<Duration>> = const { Cell::new(None) };
+        static TIMER: Cell<Vec<Option<Duration>>> = const { Cell::new(Vec::new()) };
+        static WFIS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    struct TestWfi;
+
+    impl WaitForInterrupt for TestWfi {
+        fn wfi() {
+            WFIS.with(|wfis| wfis.set(wfis.get() + 1));
+        }
+
+        fn next_deadline() -> Option<Duration> {
+            DEADLINE.with(Cell::get)
+        }
+
+        fn set_timer(deadline: Option<Duration>) {
+            TIMER.with(|timer| {
+                let mut programmed = timer.take();
+                programmed.push(deadline);
+                timer.set(programmed);
+            });
+        }
+    }
+
+    // Returns the deadlines `TestWfi` programmed and how many times it waited.
+    fn idled() -> (Vec<Option<Duration>>, usize) {
+        (TIMER.with(Cell::take), WFIS.with(|wfis| wfis.replace(0)))
+    }
+
+    #[test]
+    fn idle_waits_for_the_soonest_deadline() {
+        let done = Arc::new(AtomicUsize::new(0));
+        let executor = Executor::new(2);
+        executor.spawn(Yield::new(1, 1, 0, &done)).unwrap();
+        executor.run_ready_tasks();
+
+        // No timer is pending, the hart sleeps until some other interrupt.
+        assert!(executor.idle::<TestWfi>());
+        assert_eq!(idled(), (vec![None], 1));
+
+        DEADLINE.with(|deadline| deadline.set(Some(Duration::from_millis(30))));
+        assert!(executor.idle::<TestWfi>());
+        assert_eq!(idled(), (vec![Some(Duration::from_millis(30))], 1));
+    }
+
+    #[test]
+    fn a_wake_before_idle_cancels_it() {
+        let done = Arc::new(AtomicUsize::new(0));
+        let executor = Executor::new(2);
+        executor.spawn(Yield::new(1, 1, 0, &done)).unwrap();
+        assert!(!executor.idle::<TestWfi>());
+        executor.run_ready_tasks();
+
+        // Woken by an interrupt handler between `run_ready_tasks` and `idle`.
+        executor.waker(&1).wake();
+        assert!(!executor.idle::<TestWfi>());
+        assert_eq!(idled(), (vec![], 0));
+        executor.run_ready_tasks();
+        assert_eq!(done.load(Ordering::SeqCst), 1);
+        assert!(executor.idle::<TestWfi>());
+        assert_eq!(idled().1, 1);
+    }
+
+    #[cfg(feature = "watchdog")]
+    #[test]
+    fn the_watchdog_deadline_is_no_later_than_its_tick_while_armed() {
+        let (soon, tick, late) = (
+            Some(Duration::from_millis(10)),
+            Duration::from_millis(100),
+            Some(Duration::from_secs(5)),
+        );
+        let done = Arc::new(AtomicUsize::new(0));
+        let executor = Executor::new(2);
+        executor.set_watchdog_ticks(3);
+        executor.spawn(Yield::new(1, 1, 0, &done)).unwrap();
+        executor.spawn(Yield::new(2, 1, 0, &done)).unwrap();
+        executor.run_ready_tasks();
+        assert_eq!(executor.watchdog_deadline(None, tick), None);
+        assert_eq!(executor.watchdog_deadline(late, tick), late);
+
+        // A task waits to be polled.
+        executor.waker(&2).wake();
+        assert_eq!(executor.watchdog_deadline(None, tick), Some(tick));
+        assert_eq!(executor.watchdog_deadline(late, tick), Some(tick));
+        assert_eq!(executor.watchdog_deadline(soon, tick), soon);
+    }
+
+    #[test]
+    fn failed_tasks_are_logged_and_dropped() {
+        let logged = Arc::new(AtomicUsize::new(0));
+        let executor = FIFOExecutor::<Fallible, spin::Mutex<()>>::new(2);
+        executor
+            .spawn(Fallible {
+                id: 1,
+                logged: Some(logged.clone()),
+            })
+            .unwrap();
+        executor
+            .spawn(Fallible {
+                id: 2,
+                logged: None,
+            })
+            .unwrap();
+        executor.run_ready_tasks();
+        assert_eq!(logged.load(Ordering::SeqCst), 1);
+        assert_eq!(executor.threads().count(), 0);
+    }
+
+    #[cfg(feature = "watchdog")]
+    #[test]
+    fn watchdog_ignores_parked_tasks() {
+        let done = Arc::new(AtomicUsize::new(0));
+        let executor = Executor::new(2);
+        executor.set_watchdog_ticks(3);
+        // Never woken again after its first poll.
+        executor.spawn(Yield::new(1, 1, 0, &done)).unwrap();
+        executor.run_ready_tasks();
+        assert!(!executor.watchdog_armed());
+        for _ in 0..10 {
+            assert!(executor.watchdog_tick().is_empty());
+        }
+    }
+
+    #[cfg(feature = "watchdog")]
+    #[test]
+    fn watchdog_names_a_task_left_in_the_queue() {
+        let done = Arc::new(AtomicUsize::new(0));
+        let executor = Executor::new(2);
+        executor.set_watchdog_ticks(3);
+        executor.spawn(Yield::new(1, 1, 0, &done)).unwrap();
+        executor.spawn(Yield::new(2, 1, 0, &done)).unwrap();
+        executor.run_ready_tasks();
+        // Woken, but the run loop does not come back to it.
+        executor.waker(&2).wake();
+        assert!(executor.watchdog_armed());
+        let reports: Vec<_> = (0..10).map(|_| executor.watchdog_tick()).collect();
+        assert_eq!(reports.concat(), [2]);
+        assert_eq!(reports.iter().position(|ids| !ids.is_empty()), Some(3));
+
+        executor.run_ready_tasks();
+        assert_eq!(done.load(Ordering::SeqCst), 1);
+        assert!(!executor.watchdog_armed());
+    }
+
+    #[cfg(feature = "watchdog")]
+    #[test]
+    fn watchdog_names_a_task_stuck_in_poll() {
+        // Spins in its first poll until `release` is set.
+        struct Spin {
+            id: usize,
+            polling: Arc<AtomicUsize>,
+            release: Arc<AtomicUsize>,
+        }
+
+        impl Future for Spin {
+            type Output = ();
+
+            fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+                self.polling.store(1, Ordering::SeqCst);
+                while self.release.load(Ordering::SeqCst) == 0 {
+                    thread::yield_now();
+                }
+                Poll::Ready(())
+            }
+        }
+
+        impl ThreadFuture for Spin {
+            type ID = usize;
+
+            type Thread = usize;
+
+            fn id(&self) -> &usize {
+                &self.id
+            }
+
+            fn thread(&self) -> &usize {
+                &self.id
+            }
+        }
+
+        let (polling, release) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let executor = Arc::new(FIFOExecutor::<Spin, spin::Mutex<()>>::new(1));
+        executor.set_watchdog_ticks(2);
+        executor
+            .spawn(Spin {
+                id: 7,
+                polling: polling.clone(),
+                release: release.clone(),
+            })
+            .unwrap();
+        let worker = {
+            let executor = executor.clone();
+            thread::spawn(move || executor.run_ready_tasks())
+        };
+        while polling.load(Ordering::SeqCst) == 0 {
+            thread::yield_now();
+        }
+        let reports: Vec<_> = (0..5).map(|_| executor.watchdog_tick()).collect();
+        assert_eq!(reports.concat(), [7]);
+        release.store(1, Ordering::SeqCst);
+        worker.join().unwrap();
+        assert!(executor.watchdog_tick().is_empty());
+    }
+
+    #[test]
+    fn repeated_wakes_queue_a_task_once() {
+        let done = Arc::new(AtomicUsize::new(0));
+        let executor = Executor::new(2);
+        executor.spawn(Yield::new(1, 10, 5, &done)).unwrap();
+        executor.spawn(Yield::new(2, 10, 5, &done)).unwrap();
+        executor.run_ready_tasks();
+        assert_eq!(done.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn spawn_fails_once_the_queue_could_overflow() {
+        let done = Arc::new(AtomicUsize::new(0));
+        let executor = Executor::new(2);
+        executor.spawn(Yield::new(1, 1, 1, &done)).unwrap();
+        executor.spawn(Yield::new(2, 1, 1, &done)).unwrap();
+        assert!(executor.spawn(Yield::new(3, 1, 1, &done)).is_none());
+        executor.run_ready_tasks();
+        assert_eq!(done.load(Ordering::SeqCst), 2);
+        // Completed tasks make room for new ones.
+        executor.spawn(Yield::new(3, 1, 1, &done)).unwrap();
+    }
+
+    #[test]
+    fn tasks_spawned_on_any_worker_all_run() {
+        const WORKERS: usize = 4;
+        const TASKS_PER_WORKER: usize = 50;
+        let done = Arc::new(AtomicUsize::new(0));
+        let executor = Arc::new(Executor::new(WORKERS * TASKS_PER_WORKER));
+        let workers: Vec<_> = (0..WORKERS)
+            .map(|worker| {
+                let (executor, done) = (executor.clone(), done.clone());
+                thread::spawn(move || {
+                    for i in 0..TASKS_PER_WORKER {
+                        let id = worker * TASKS_PER_WORKER + i;
+                        executor.spawn(Yield::new(id, 3, 2, &done)).unwrap();
+                        executor.run_ready_tasks();
+                    }
+                    while done.load(Ordering::SeqCst) < WORKERS * TASKS_PER_WORKER {
+                        executor.run_ready_tasks();
+                        thread::yield_now();
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(done.load(Ordering::SeqCst), WORKERS * TASKS_PER_WORKER);
+        assert_eq!(executor.threads().count(), 0);
+    }
+}
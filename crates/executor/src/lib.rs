@@ -1,6 +1,6 @@
 #![no_std]
 
-use core::{future::Future, fmt::Debug};
+use core::{fmt::Debug, future::Future, time::Duration};
 extern crate alloc;
 
 // executor implementation
@@ -19,6 +19,33 @@ pub trait ThreadFuture: Future + 'static {
     fn thread(&self) -> &Self::Thread;
 }
 
+/// The output of a task, checked by the executor once the task completes.
+pub trait TaskResult {
+    /// Returns the error the task failed with, `None` if it succeeded.
+    fn err(&self) -> Option<&dyn Debug>;
+}
+
+impl TaskResult for () {
+    fn err(&self) -> Option<&dyn Debug> {
+        None
+    }
+}
+
+impl<E: Debug> TaskResult for Result<(), E> {
+    fn err(&self) -> Option<&dyn Debug> {
+        self.as_ref().err().map(|err| err as &dyn Debug)
+    }
+}
+
 pub trait WaitForInterrupt {
     fn wfi();
+
+    /// Returns when the soonest pending timer expires, `None` if there is none.
+    /// An idle hart needs no timer interrupt before then.
+    fn next_deadline() -> Option<Duration> {
+        None
+    }
+
+    /// Programs a single timer interrupt at `deadline`, `None` turns the timer interrupt off.
+    fn set_timer(_deadline: Option<Duration>) {}
 }
@@ -0,0 +1,268 @@
+//! Fast user-space locking: the tasks waiting on a futex word, queued by the key of the word.
+
+#![no_std]
+
+extern crate alloc;
+#[cfg(test)]
+extern crate std;
+
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll, Waker},
+};
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+};
+use spinlock::{Irq, MutexIrq};
+
+struct Waiter<I> {
+    woken: AtomicBool,
+    waker: MutexIrq<I, Option<Waker>>,
+}
+
+/// The waiters of each futex, in the order they started to wait.
+pub struct Futexes<I, K> {
+    #[allow(clippy::type_complexity)]
+    queues: MutexIrq<I, BTreeMap<K, VecDeque<Arc<Waiter<I>>>>>,
+}
+
+impl<I: Irq, K: Ord + Copy> Futexes<I, K> {
+    #[allow(clippy::new_without_default)]
+    pub const fn new() -> Self {
+        Self {
+            queues: MutexIrq::new(BTreeMap::new()),
+        }
+    }
+
+    /// Queues a waiter on the futex `key` if its word, read by `load`, still holds `val`.
+    /// Returns `None` if it does not. The returned future completes when the waiter is
+    /// woken by `wake`, dropping it first leaves the queue.
+    /// `load` runs under the lock `wake` takes, so a wake after the word changed is not missed.
+    pub fn wait(
+        &self,
+        key: K,
+        val: u32,
+        load: impl FnOnce() -> u32,
+    ) -> Option<FutexWait<'_, I, K>> {
+        let mut queues = self.queues.lock();
+        if load() != val {
+            return None;
+        }
+        let waiter = Arc::new(Waiter {
+            woken: AtomicBool::new(false),
+            waker: MutexIrq::new(None),
+        });
+        queues.entry(key).or_default().push_back(waiter.clone());
+        Some(FutexWait {
+            futexes: self,
+            key,
+            waiter,
+        })
+    }
+
+    /// Wakes up to `n` waiters of the futex `key`, returns the number of woken waiters.
+    pub fn wake(&self, key: K, n: usize) -> usize {
+        let mut queues = self.queues.lock();
+        let queue = match queues.get_mut(&key) {
+            Some(queue) => queue,
+            None => return 0,
+        };
+
+        let mut woken = 0;
+        while woken < n {
+            let waiter = match queue.pop_front() {
+                Some(waiter) => waiter,
+                None => break,
+            };
+            waiter.woken.store(true, Ordering::Release);
+            if let Some(waker) = waiter.waker.lock().take() {
+                waker.wake();
+            }
+            woken += 1;
+        }
+        if queue.is_empty() {
+            queues.remove(&key);
+        }
+        woken
+    }
+
+    /// Clears the thread id in the word of the futex `key` with `clear`, then wakes a waiter.
+    /// An exiting thread does so for the address it set with `set_tid_address`,
+    /// to let a thread joining it know that it is gone.
+    pub fn clear_child_tid(&self, key: K, clear: impl FnOnce()) -> usize {
+        clear();
+        self.wake(key, 1)
+    }
+}
+
+pub struct FutexWait<'a, I: Irq, K: Ord + Copy> {
+    futexes: &'a Futexes<I, K>,
+    key: K,
+    waiter: Arc<Waiter<I>>,
+}
+
+impl<I: Irq, K: Ord + Copy> Future for FutexWait<'_, I, K> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.waiter.woken.load(Ordering::Acquire) {
+            return Poll::Ready(());
+        }
+        *self.waiter.waker.lock() = Some(cx.waker().clone());
+        // `wake` may have run before the waker was registered.
+        if self.waiter.woken.load(Ordering::Acquire) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<I: Irq, K: Ord + Copy> Drop for FutexWait<'_, I, K> {
+    fn drop(&mut self) {
+        if self.waiter.woken.load(Ordering::Acquire) {
+            return;
+        }
+        // The wait was cancelled (e.g. timed out), leave the queue.
+        let mut queues = self.futexes.queues.lock();
+        if let Some(queue) = queues.get_mut(&self.key) {
+            queue.retain(|waiter| !Arc::ptr_eq(waiter, &self.waiter));
+            if queue.is_empty() {
+                queues.remove(&self.key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::{
+        future::Future,
+        pin::Pin,
+        sync::atomic::{AtomicU32, AtomicUsize, Ordering},
+        task::{Context, Poll, Waker},
+    };
+    use std::{sync::Arc, task::Wake, vec::Vec};
+
+    use spinlock::Irq;
+
+    use super::Futexes;
+
+    struct TestIrq;
+
+    impl Irq for TestIrq {
+        fn push_off() {}
+        fn pop_off() {}
+    }
+
+    // Keyed by (address space, virtual address), as the kernel does.
+    type TestFutexes = Futexes<TestIrq, (usize, usize)>;
+
+    #[derive(Default)]
+    struct CountWaker(AtomicUsize);
+
+    impl Wake for CountWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn poll<F: Future + Unpin>(fut: &mut F, waker: &Arc<CountWaker>) -> Poll<F::Output> {
+        let waker = Waker::from(waker.clone());
+        Pin::new(fut).poll(&mut Context::from_waker(&waker))
+    }
+
+    #[test]
+    fn wake_wakes_the_oldest_waiters_first() {
+        let futexes = TestFutexes::new();
+        let wakers: [Arc<CountWaker>; 3] = Default::default();
+        let mut waits: Vec<_> = wakers
+            .iter()
+            .map(|waker| {
+                let mut wait = futexes.wait((1, 0x1000), 7, || 7).unwrap();
+                assert_eq!(poll(&mut wait, waker), Poll::Pending);
+                wait
+            })
+            .collect();
+
+        assert_eq!(futexes.wake((1, 0x1000), 2), 2);
+        let woken: Vec<_> = wakers
+            .iter()
+            .map(|waker| waker.0.load(Ordering::SeqCst))
+            .collect();
+        assert_eq!(woken, [1, 1, 0]);
+        assert_eq!(poll(&mut waits[0], &wakers[0]), Poll::Ready(()));
+        assert_eq!(poll(&mut waits[1], &wakers[1]), Poll::Ready(()));
+        assert_eq!(poll(&mut waits[2], &wakers[2]), Poll::Pending);
+
+        assert_eq!(futexes.wake((1, 0x1000), usize::MAX), 1);
+        assert_eq!(poll(&mut waits[2], &wakers[2]), Poll::Ready(()));
+        assert_eq!(futexes.wake((1, 0x1000), 1), 0);
+    }
+
+    #[test]
+    fn a_changed_word_is_not_waited_on() {
+        let futexes = TestFutexes::new();
+        assert!(futexes.wait((1, 0x1000), 7, || 8).is_none());
+        assert_eq!(futexes.wake((1, 0x1000), 1), 0);
+    }
+
+    #[test]
+    fn a_wake_before_the_first_poll_is_not_lost() {
+        let futexes = TestFutexes::new();
+        let waker = Arc::new(CountWaker::default());
+        let mut wait = futexes.wait((1, 0x1000), 0, || 0).unwrap();
+        assert_eq!(futexes.wake((1, 0x1000), 1), 1);
+        assert_eq!(poll(&mut wait, &waker), Poll::Ready(()));
+    }
+
+    #[test]
+    fn address_spaces_do_not_share_futexes() {
+        let futexes = TestFutexes::new();
+        let waker = Arc::new(CountWaker::default());
+        let mut wait = futexes.wait((1, 0x1000), 0, || 0).unwrap();
+        assert_eq!(poll(&mut wait, &waker), Poll::Pending);
+        assert_eq!(futexes.wake((2, 0x1000), 1), 0);
+        assert_eq!(futexes.wake((1, 0x1004), 1), 0);
+        assert_eq!(poll(&mut wait, &waker), Poll::Pending);
+        assert_eq!(futexes.wake((1, 0x1000), 1), 1);
+    }
+
+    #[test]
+    fn an_exiting_thread_clears_its_id_and_wakes_the_joiner() {
+        let futexes = TestFutexes::new();
+        let waker = Arc::new(CountWaker::default());
+        let tid = AtomicU32::new(7);
+        let load = || tid.load(Ordering::SeqCst);
+        let mut join = futexes.wait((1, 0x1000), 7, load).unwrap();
+        assert_eq!(poll(&mut join, &waker), Poll::Pending);
+
+        let clear = || tid.store(0, Ordering::SeqCst);
+        assert_eq!(futexes.clear_child_tid((1, 0x1000), clear), 1);
+        assert_eq!(tid.load(Ordering::SeqCst), 0);
+        assert_eq!(waker.0.load(Ordering::SeqCst), 1);
+        assert_eq!(poll(&mut join, &waker), Poll::Ready(()));
+        // A thread joining it later does not wait.
+        assert!(futexes.wait((1, 0x1000), 7, load).is_none());
+    }
+
+    #[test]
+    fn a_cancelled_wait_leaves_the_queue() {
+        let futexes = TestFutexes::new();
+        let waker = Arc::new(CountWaker::default());
+        let cancelled = futexes.wait((1, 0x1000), 0, || 0).unwrap();
+        let mut wait = futexes.wait((1, 0x1000), 0, || 0).unwrap();
+        assert_eq!(poll(&mut wait, &waker), Poll::Pending);
+        drop(cancelled);
+
+        // The wake goes to the waiter still waiting.
+        assert_eq!(futexes.wake((1, 0x1000), 1), 1);
+        assert_eq!(poll(&mut wait, &waker), Poll::Ready(()));
+        drop(wait);
+        assert!(futexes.queues.lock().is_empty());
+    }
+}
@@ -7,7 +7,7 @@ use riscv::{
 // Linear mapping
 #[cfg(target_arch = "riscv32")]
 const LINEAR_MAPPING_PHYS_OFFSET: usize = 0x0000_0000;
-#[cfg(target_arch = "riscv64")]
+#[cfg(not(target_arch = "riscv32"))]
 const LINEAR_MAPPING_PHYS_OFFSET: usize = 0xFFFF_FFFF_0000_0000;
 
 pub type PageParam = PageParamSv39;
@@ -26,6 +26,12 @@ impl crate::page::PageParam for PageParamSv39 {
 
     const FLAG_PTE_VALID: Flag = 1 << 0;
 
+    // First of the two RSW bits reserved for the supervisor.
+    const FLAG_PTE_SWAPPED: Flag = 1 << 8;
+
+    // Second RSW bit.
+    const FLAG_PTE_NOACCESS: Flag = 1 << 9;
+
     const PAGE_SIZE_SHIFT: usize = 12;
 
     const PTE_COUNT: usize = 512;
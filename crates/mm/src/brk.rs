@@ -0,0 +1,144 @@
+use crate::{
+    frame::Allocator,
+    memory::{MapType, Memory, Segment},
+    page::{Flag, PageParam},
+    VirtualAddress,
+};
+
+/// The heap of a process, grown and shrunk by `brk`.
+#[derive(Clone, Copy, Debug)]
+pub struct ProgramBreak {
+    /// Start of the heap, just past the highest ELF load segment
+    pub start: VirtualAddress,
+    /// Current program break
+    pub current: VirtualAddress,
+}
+
+impl ProgramBreak {
+    /// Move the program break to `addr`, growing or shrinking the heap segment of `memory`,
+    /// whose pages get `flags`. Returns the program break afterwards, which does not move
+    /// if `addr` is below the start of the heap or the heap can not grow up to it,
+    /// so `brk(0)` queries the current program break.
+    pub fn set<MutexType, A, Param>(
+        &mut self,
+        memory: &mut Memory<'_, MutexType, A, Param>,
+        addr: VirtualAddress,
+        flags: Flag,
+    ) -> VirtualAddress
+    where
+        MutexType: lock_api::RawMutex,
+        A: Allocator,
+        Param: PageParam,
+        [(); Param::PAGE_LEVELS]:,
+        [(); Param::PAGE_SIZE]:,
+    {
+        if addr < self.start {
+            return self.current;
+        }
+        let page_round_up = |addr: VirtualAddress| {
+            Some(addr.0.checked_add(Param::PAGE_SIZE - 1)? / Param::PAGE_SIZE * Param::PAGE_SIZE)
+        };
+        let (old_end, new_end) = match (page_round_up(self.current), page_round_up(addr)) {
+            (Some(old_end), Some(new_end)) => (old_end, new_end),
+            _ => return self.current,
+        };
+        if old_end != new_end {
+            let res = if old_end == self.start.0 {
+                memory.add_user_segment(
+                    Segment {
+                        addr_range: self.start..VirtualAddress(new_end),
+                        flags,
+                        map_type: MapType::Framed,
+                    },
+                    &[],
+                )
+            } else {
+                memory.resize_user_segment(self.start, VirtualAddress(new_end))
+            };
+            if res.is_err() {
+                return self.current;
+            }
+        }
+        self.current = addr;
+        addr
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::sync::atomic::Ordering;
+
+    use super::ProgramBreak;
+    use crate::{
+        memory::{MapType, Memory, Segment},
+        page::{mapper::PageMapper, PageParam},
+        test_mem::{frame_bytes, test_allocator, TestParam},
+        VirtualAddress,
+    };
+
+    const PAGE: usize = TestParam::PAGE_SIZE;
+
+    #[test]
+    fn heap_grows_and_shrinks_with_the_break() {
+        let (allocator, free) = test_allocator(32);
+        let mut memory = Memory::new(PageMapper::<_, _, TestParam>::create(&allocator).unwrap());
+        let flags =
+            TestParam::flag_set_user(TestParam::FLAG_PTE_READABLE | TestParam::FLAG_PTE_WRITEABLE);
+        let start = VirtualAddress(0x1000_0000);
+        let mut brk = ProgramBreak {
+            start,
+            current: start,
+        };
+
+        // brk(0) and addresses below the heap query the break.
+        assert_eq!(brk.set(&mut memory, VirtualAddress(0), flags), start);
+        assert_eq!(
+            brk.set(&mut memory, VirtualAddress(start.0 - 1), flags),
+            start
+        );
+        assert!(memory.translate(start).is_none());
+
+        let end = VirtualAddress(start.0 + PAGE + 16);
+        assert_eq!(brk.set(&mut memory, end, flags), end);
+        assert_eq!(brk.current, end);
+        for addr in [start, VirtualAddress(end.0 - 1)] {
+            let (frame, pte_flags) = memory.translate(addr).unwrap();
+            assert!(TestParam::pte_writeable(pte_flags));
+            frame_bytes(frame)[addr.0 % PAGE] = 0x5a;
+            assert_eq!(
+                frame_bytes(memory.translate(addr).unwrap().0)[addr.0 % PAGE],
+                0x5a
+            );
+        }
+        assert!(memory
+            .translate(VirtualAddress(start.0 + 2 * PAGE))
+            .is_none());
+
+        // The heap can not grow into another segment.
+        let other = VirtualAddress(start.0 + 4 * PAGE);
+        memory
+            .add_user_segment(
+                Segment {
+                    addr_range: other..VirtualAddress(other.0 + PAGE),
+                    flags,
+                    map_type: MapType::Framed,
+                },
+                &[],
+            )
+            .unwrap()
+            .ignore();
+        assert_eq!(
+            brk.set(&mut memory, VirtualAddress(other.0 + 1), flags),
+            end
+        );
+
+        let free_grown = free.load(Ordering::SeqCst);
+        assert_eq!(brk.set(&mut memory, start, flags), start);
+        assert_eq!(free.load(Ordering::SeqCst), free_grown + 2);
+        assert!(memory.translate(start).is_none());
+        // The heap segment is gone, growing again adds a new one.
+        let end = VirtualAddress(start.0 + 8);
+        assert_eq!(brk.set(&mut memory, end, flags), end);
+        assert!(memory.translate(start).is_some());
+    }
+}
@@ -0,0 +1,39 @@
+//! Helpers for loading executables into lazy segments.
+
+/// The most bytes read for the ELF header and the program headers of an executable.
+pub const ELF_HEADERS_MAX: u64 = 64 * 1024;
+
+/// Returns the end of the program header table, None if it overflows, or ends past
+/// the end of the file of `file_size` bytes or past `ELF_HEADERS_MAX`.
+pub fn ph_table_end(
+    ph_offset: u64,
+    ph_entry_size: u16,
+    ph_count: u16,
+    file_size: u64,
+) -> Option<usize> {
+    let end = (ph_entry_size as u64 * ph_count as u64).checked_add(ph_offset)?;
+    if end > file_size.min(ELF_HEADERS_MAX) {
+        return None;
+    }
+    Some(end as usize)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ph_table_end, ELF_HEADERS_MAX};
+
+    #[test]
+    fn program_headers_inside_the_file() {
+        assert_eq!(ph_table_end(64, 56, 4, 4096), Some(64 + 56 * 4));
+        assert_eq!(ph_table_end(64, 56, 0, 64), Some(64));
+        assert_eq!(ph_table_end(64, 56, 4, 64 + 56 * 4), Some(64 + 56 * 4));
+    }
+
+    #[test]
+    fn program_headers_past_the_file_are_rejected() {
+        assert_eq!(ph_table_end(64, 56, 4, 64 + 56 * 4 - 1), None);
+        assert_eq!(ph_table_end(u64::MAX - 8, 56, 4, u64::MAX), None);
+        assert_eq!(ph_table_end(64, u16::MAX, u16::MAX, u64::MAX), None);
+        assert_eq!(ph_table_end(ELF_HEADERS_MAX, 56, 1, u64::MAX), None);
+    }
+}
@@ -1,14 +1,17 @@
-use core::usize;
+use core::{ops::Range, usize};
 
 use alloc::vec::Vec;
 
 use super::{farme_round_up, Allocator, Frame, PhysicalAddress};
+use crate::ranges_overlap;
 
 pub struct BumpAllocator<const FRAME_SIZE: usize> {
     next: PhysicalAddress,
     start: PhysicalAddress,
     end: PhysicalAddress,
     allocated: usize,
+    // Frames overlapping these are never handed out.
+    reserved: Vec<Range<PhysicalAddress>>,
 }
 
 impl<const FRAME_SIZE: usize> BumpAllocator<FRAME_SIZE> {
@@ -22,18 +25,36 @@ impl<const FRAME_SIZE: usize> BumpAllocator<FRAME_SIZE> {
             start,
             end,
             allocated: 0,
+            reserved: Vec::new(),
         }
     }
+
+    // Returns the reserved region overlapping the frame starting at `addr`.
+    fn reserved_at(&self, addr: PhysicalAddress) -> Option<&Range<PhysicalAddress>> {
+        let frame = addr..PhysicalAddress(addr.0 + FRAME_SIZE);
+        self.reserved
+            .iter()
+            .find(|region| ranges_overlap(region, &frame))
+    }
 }
 
 impl<const FRAME_SIZE: usize> Allocator for BumpAllocator<FRAME_SIZE> {
-    fn init(&mut self, start: PhysicalAddress, end: PhysicalAddress) {
+    fn init(
+        &mut self,
+        start: PhysicalAddress,
+        end: PhysicalAddress,
+        reserved: &[Range<PhysicalAddress>],
+    ) {
         self.start = farme_round_up(start, FRAME_SIZE);
         self.next = self.start;
         self.end = end;
+        self.reserved = reserved.to_vec();
     }
 
     fn alloc(&mut self) -> Option<Frame> {
+        while let Some(region) = self.reserved_at(self.next) {
+            self.next = farme_round_up(region.end, FRAME_SIZE);
+        }
         if self.next.0 < self.end.0 - FRAME_SIZE {
             let frame = Frame::of_addr(self.next);
             self.next.0 += FRAME_SIZE;
@@ -45,23 +66,40 @@ impl<const FRAME_SIZE: usize> Allocator for BumpAllocator<FRAME_SIZE> {
     }
 
     fn alloc_consecutive(&mut self, n: usize) -> Vec<Frame> {
-        let mut frames = Vec::with_capacity(n);
-        for _ in 0..n {
-            match self.alloc() {
-                Some(f) => frames.push(f),
-                None => {
-                    for f in frames {
-                        self.dealloc(&f);
-                    }
-                    return Vec::new();
-                }
-            }
+        if n == 0 {
+            return Vec::new();
         }
+        let mut start = self.next;
+        // Move the run past every reserved region it overlaps.
+        let end = loop {
+            let end = match n
+                .checked_mul(FRAME_SIZE)
+                .and_then(|len| start.0.checked_add(len))
+            {
+                Some(end) if end <= self.end.0 => PhysicalAddress(end),
+                _ => return Vec::new(),
+            };
+            match self
+                .reserved
+                .iter()
+                .find(|region| ranges_overlap(region, &(start..end)))
+            {
+                Some(region) => start = farme_round_up(region.end, FRAME_SIZE),
+                None => break end,
+            }
+        };
 
-        frames
+        self.next = end;
+        self.allocated += n;
+        (0..n)
+            .map(|i| Frame::of_addr(PhysicalAddress(start.0 + i * FRAME_SIZE)))
+            .collect()
     }
 
-    fn dealloc(&mut self, _frame: &Frame) -> bool {
+    fn dealloc(&mut self, frame: &Frame) -> bool {
+        if self.reserved_at(frame.start()).is_some() {
+            return false;
+        }
         self.allocated -= 1;
         if self.allocated == 0 {
             self.next = self.start;
@@ -69,3 +107,55 @@ impl<const FRAME_SIZE: usize> Allocator for BumpAllocator<FRAME_SIZE> {
         true
     }
 }
+
+#[cfg(test)]
+mod test {
+    use alloc::vec::Vec;
+
+    use super::BumpAllocator;
+    use crate::{frame::Allocator, Frame, PhysicalAddress};
+
+    const FRAME_SIZE: usize = 0x1000;
+
+    fn addrs(frames: &[Frame]) -> Vec<usize> {
+        frames.iter().map(|frame| frame.start().0).collect()
+    }
+
+    #[test]
+    fn test_alloc_consecutive_skips_reserved_hole() {
+        let mut allocator = BumpAllocator::<FRAME_SIZE>::uninit();
+        // Frames 0x10000..0x20000, with frame 0x12000 reserved.
+        allocator.init(
+            PhysicalAddress(0x10000),
+            PhysicalAddress(0x20000),
+            &[PhysicalAddress(0x12000)..PhysicalAddress(0x12800)],
+        );
+
+        assert_eq!(addrs(&allocator.alloc_consecutive(1)), [0x10000]);
+        // The run starting at 0x11000 would cross the hole.
+        assert_eq!(
+            addrs(&allocator.alloc_consecutive(3)),
+            [0x13000, 0x14000, 0x15000]
+        );
+        assert_eq!(allocator.alloc().unwrap().start().0, 0x16000);
+    }
+
+    #[test]
+    fn test_alloc_consecutive_fails_without_a_long_enough_run() {
+        let mut allocator = BumpAllocator::<FRAME_SIZE>::uninit();
+        allocator.init(
+            PhysicalAddress(0x10000),
+            PhysicalAddress(0x18000),
+            &[PhysicalAddress(0x13000)..PhysicalAddress(0x14000)],
+        );
+
+        // 3 free frames before the hole and 4 after it.
+        assert!(allocator.alloc_consecutive(5).is_empty());
+        assert!(allocator.alloc_consecutive(0).is_empty());
+        assert_eq!(
+            addrs(&allocator.alloc_consecutive(4)),
+            [0x14000, 0x15000, 0x16000, 0x17000]
+        );
+        assert!(allocator.alloc_consecutive(1).is_empty());
+    }
+}
@@ -1,4 +1,6 @@
-use alloc::vec::Vec;
+use core::ops::Range;
+
+use alloc::{collections::BTreeMap, vec::Vec};
 
 use super::Frame;
 use super::PhysicalAddress;
@@ -6,12 +8,23 @@ use super::PhysicalAddress;
 pub mod allocator;
 
 pub trait Allocator {
-    fn init(&mut self, _start: PhysicalAddress, _end: PhysicalAddress) {}
+    /// Hands out frames between `start` and `end`,
+    /// except those overlapping a region of `reserved`.
+    fn init(
+        &mut self,
+        _start: PhysicalAddress,
+        _end: PhysicalAddress,
+        _reserved: &[Range<PhysicalAddress>],
+    ) {
+    }
 
     fn alloc(&mut self) -> Option<Frame>;
 
+    /// Allocate `n` physically contiguous frames,
+    /// returns no frames if there is no such run.
     fn alloc_consecutive(&mut self, n: usize) -> Vec<Frame>;
 
+    /// Returns false if `frame` was rejected, e.g. because it is reserved.
     fn dealloc(&mut self, frame: &Frame) -> bool;
 }
 
@@ -21,7 +34,14 @@ const fn farme_round_up(pa: PhysicalAddress, frame_size: usize) -> PhysicalAddre
 }
 
 pub struct LockedAllocator<MutexType, A> {
-    inner: lock_api::Mutex<MutexType, A>,
+    inner: lock_api::Mutex<MutexType, Frames<A>>,
+}
+
+struct Frames<A> {
+    allocator: A,
+    // Number of references beyond the first to the frames mapped by more than one
+    // address space, a frame is only returned to `allocator` when its last reference is dropped.
+    shared: BTreeMap<PhysicalAddress, usize>,
 }
 
 impl<MutexType, A> LockedAllocator<MutexType, A>
@@ -31,23 +51,55 @@ where
 {
     pub const fn new(allocator: A) -> Self {
         Self {
-            inner: lock_api::Mutex::new(allocator),
+            inner: lock_api::Mutex::new(Frames {
+                allocator,
+                shared: BTreeMap::new(),
+            }),
         }
     }
 
-    pub fn init(&self, start: PhysicalAddress, end: PhysicalAddress) {
-        self.inner.lock().init(start, end);
+    pub fn init(
+        &self,
+        start: PhysicalAddress,
+        end: PhysicalAddress,
+        reserved: &[Range<PhysicalAddress>],
+    ) {
+        self.inner.lock().allocator.init(start, end, reserved);
     }
 
     pub fn alloc(&self) -> Option<Frame> {
-        self.inner.lock().alloc()
+        self.inner.lock().allocator.alloc()
     }
 
     pub fn alloc_consecutive(&self, n: usize) -> Vec<Frame> {
-        self.inner.lock().alloc_consecutive(n)
+        self.inner.lock().allocator.alloc_consecutive(n)
     }
 
+    /// Drop a reference to `frame`, the frame is freed with its last reference.
+    /// Returns false if `frame` was rejected, e.g. because it is reserved.
     pub fn dealloc(&self, frame: &Frame) -> bool {
-        self.inner.lock().dealloc(frame)
+        let mut frames = self.inner.lock();
+        if let Some(refs) = frames.shared.get_mut(&frame.start()) {
+            *refs -= 1;
+            if *refs == 0 {
+                frames.shared.remove(&frame.start());
+            }
+            return true;
+        }
+        frames.allocator.dealloc(frame)
+    }
+
+    /// Add a reference to `frame`, for one more address space mapping it.
+    pub fn share(&self, frame: &Frame) {
+        *self.inner.lock().shared.entry(frame.start()).or_insert(0) += 1;
+    }
+
+    /// Returns the number of references to the allocated `frame`.
+    pub fn ref_count(&self, frame: &Frame) -> usize {
+        self.inner
+            .lock()
+            .shared
+            .get(&frame.start())
+            .map_or(1, |refs| refs + 1)
     }
 }
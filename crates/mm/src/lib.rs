@@ -1,13 +1,21 @@
 #![allow(incomplete_features)]
 #![feature(generic_const_exprs)]
+#![feature(const_btree_new)]
 #![no_std]
 
 extern crate alloc;
+#[cfg(test)]
+extern crate std;
 
 pub mod arch;
+pub mod brk;
+pub mod elf;
 pub mod frame;
 pub mod memory;
 pub mod page;
+pub mod swap;
+#[cfg(test)]
+mod test_mem;
 
 use core::{fmt, iter::Iterator, ops::Range};
 
@@ -18,6 +26,11 @@ pub enum Error {
     NoSpace,
     InvalidVirtualAddress(VirtualAddress),
     InvalidPageTable(usize),
+    InvalidFlags(page::Flag),
+    /// The swap device failed to read or write a page.
+    SwapIo,
+    /// The source of a lazy segment failed to read a page.
+    SourceIo,
 }
 
 pub trait Addr: Sized {
@@ -133,6 +146,11 @@ impl<T: Addr + Clone> Space<T> {
     pub fn start(&self) -> T {
         self.0.clone()
     }
+
+    /// Returns the address range of `size` bytes starting at this space.
+    pub fn range(&self, size: usize) -> Range<T> {
+        self.start()..self.start().add(size)
+    }
 }
 
 impl<T: Addr> From<T> for Space<T> {
@@ -141,6 +159,31 @@ impl<T: Addr> From<T> for Space<T> {
     }
 }
 
+/// Returns true if `a` and `b` share at least one address.
+/// Ranges that merely touch (`a.end == b.start`) do not overlap.
+pub fn ranges_overlap<T: PartialOrd>(a: &Range<T>, b: &Range<T>) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+pub trait AddrRange<T> {
+    /// Returns true if `addr` lies inside this range.
+    fn contains_addr(&self, addr: T) -> bool;
+
+    /// Returns true if every address of `other` lies inside this range.
+    fn contains_range(&self, other: &Range<T>) -> bool;
+}
+
+impl<T: Addr + PartialOrd> AddrRange<T> for Range<T> {
+    fn contains_addr(&self, addr: T) -> bool {
+        self.start <= addr && addr < self.end
+    }
+
+    fn contains_range(&self, other: &Range<T>) -> bool {
+        self.start <= other.start && other.end <= self.end
+    }
+}
+
+#[derive(Clone)]
 pub struct SpaceIter<'a, T: Addr, const SIZE: usize> {
     end: &'a T,
     next: T,
@@ -162,7 +205,7 @@ where
     type Item = Space<T>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if &self.next > self.end {
+        if &self.next >= self.end {
             None
         } else {
             let next = self.next.clone();
@@ -171,3 +214,53 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{ranges_overlap, AddrRange, Frame, Page, PhysicalAddress, VirtualAddress};
+
+    fn va(start: usize, end: usize) -> core::ops::Range<VirtualAddress> {
+        VirtualAddress(start)..VirtualAddress(end)
+    }
+
+    fn pa(start: usize, end: usize) -> core::ops::Range<PhysicalAddress> {
+        PhysicalAddress(start)..PhysicalAddress(end)
+    }
+
+    #[test]
+    fn touching_ranges_do_not_overlap() {
+        assert!(!ranges_overlap(&va(0x1000, 0x2000), &va(0x2000, 0x3000)));
+        assert!(!ranges_overlap(&va(0x2000, 0x3000), &va(0x1000, 0x2000)));
+        assert!(ranges_overlap(&va(0x1000, 0x2001), &va(0x2000, 0x3000)));
+        assert!(!ranges_overlap(&pa(0x1000, 0x2000), &pa(0x2000, 0x3000)));
+    }
+
+    #[test]
+    fn contained_and_identical_ranges() {
+        let outer = va(0x1000, 0x4000);
+        assert!(ranges_overlap(&outer, &va(0x2000, 0x3000)));
+        assert!(ranges_overlap(&va(0x2000, 0x3000), &outer));
+        assert!(outer.contains_range(&va(0x2000, 0x3000)));
+        assert!(!va(0x2000, 0x3000).contains_range(&outer));
+
+        assert!(ranges_overlap(&outer, &outer));
+        assert!(outer.contains_range(&outer));
+        assert!(pa(0x1000, 0x4000).contains_range(&pa(0x1000, 0x4000)));
+        assert!(!outer.contains_range(&va(0x3000, 0x4001)));
+    }
+
+    #[test]
+    fn range_bounds_are_half_open() {
+        let range = va(0x1000, 0x2000);
+        assert!(range.contains_addr(VirtualAddress(0x1000)));
+        assert!(range.contains_addr(VirtualAddress(0x1fff)));
+        assert!(!range.contains_addr(VirtualAddress(0x2000)));
+        assert!(!pa(0x1000, 0x2000).contains_addr(PhysicalAddress(0xfff)));
+
+        assert_eq!(Page::of_addr(VirtualAddress(0x1000)).range(0x1000), range);
+        assert_eq!(
+            Frame::of_addr(PhysicalAddress(0x3000)).range(0x1000),
+            pa(0x3000, 0x4000)
+        );
+    }
+}
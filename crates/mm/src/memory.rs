@@ -1,16 +1,28 @@
-use crate::page::flush::FlushGuard;
-
 use super::{
     frame::Allocator,
-    page::{flush::FlushAllGuard, mapper::PageMapper, Flag, PageParam},
-    Error, Frame, PageIter, Result, VirtualAddress,
+    page::{
+        flush::{FlushAllGuard, FlushBatch},
+        mapper::PageMapper,
+        Flag, PageParam,
+    },
+    ranges_overlap, Addr, AddrRange, Error, Frame, Page, PageIter, PhysicalAddress, Result,
+    VirtualAddress,
 };
-use alloc::vec::Vec;
-use core::ops::Range;
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{future::Future, ops::Range, pin::Pin};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
 
 pub struct Memory<'a, MutexType, A, Param> {
     kernel_segments: Vec<Segment>,
     user_segments: Vec<Segment>,
+    // Start address and lowest allowed start of the user segments that grow downwards on faults.
+    // The start follows the segment as it grows, and the lower part when it is split.
+    grow_down_segments: Vec<(VirtualAddress, VirtualAddress)>,
+    // Where the pages of the lazy user segments are read from.
+    lazy_backings: Vec<LazyBacking>,
+    // Where `reclaim` continues its sweep over the user pages.
+    clock_hand: VirtualAddress,
     // todo for debug `pub`
     pub page_mapper: PageMapper<'a, MutexType, A, Param>,
 }
@@ -42,64 +54,611 @@ where
         Self {
             kernel_segments: Vec::new(),
             user_segments: Vec::new(),
+            grow_down_segments: Vec::new(),
+            lazy_backings: Vec::new(),
+            clock_hand: VirtualAddress(0),
             page_mapper,
         }
     }
 
-    pub fn borrow_memory(&self, asid: usize) -> Result<Self> {
+    /// Create a copy-on-write copy of this address space, see `mark_all_cow`.
+    /// Pages in swap stay there, the copy references the same swap slots.
+    pub fn borrow_memory(&mut self, asid: usize) -> Result<Self> {
+        // The guard flushes the stale writable entries when dropped.
+        self.mark_all_cow();
         let new_page_mapper = self.page_mapper.borrow_memory(asid)?;
 
         Ok(Self {
             kernel_segments: self.kernel_segments.clone(),
             user_segments: self.user_segments.clone(),
+            grow_down_segments: self.grow_down_segments.clone(),
+            lazy_backings: self.lazy_backings.clone(),
+            clock_hand: VirtualAddress(0),
             page_mapper: new_page_mapper,
         })
     }
 
-    pub fn handle_page_fault(&mut self, vaddr: VirtualAddress) -> Result<FlushGuard<Param>> {
-        self.page_mapper.handle_page_fault(vaddr)
+    /// Make every user page of this address space read-only and count one more reference
+    /// to its frame, for the copy sharing the frames. Writes then fault and copy the page,
+    /// a frame is freed once neither address space references it.
+    pub fn mark_all_cow(&mut self) -> FlushAllGuard<Param> {
+        self.page_mapper.mark_all_cow()
+    }
+
+    /// Handle a user page fault at `vaddr`.
+    /// A fault in the growth window of a grow-down segment extends the segment,
+    /// a fault on a page in swap maps it again if its frame is still around,
+    /// otherwise the caller reads it, see `Fault::SwapIn`,
+    /// a fault on a page of a lazy segment that is not loaded yet has the caller read it,
+    /// see `Fault::Load`, or maps a zero-filled page if it has no backing,
+    /// a fault on a page dropped by `discard` maps a zero-filled page,
+    /// a fault on a copy-on-write page copies the page.
+    /// Any other fault, such as one in a segment that may not be accessed at all,
+    /// can not be recovered from and is returned as an error.
+    /// `Error::NoSpace` is returned when frames run out, the caller may `reclaim` pages
+    /// of any address space and retry.
+    pub fn handle_page_fault(&mut self, vaddr: VirtualAddress) -> Result<Fault<Param>> {
+        if let Some(flush) = self.grow_down(vaddr)? {
+            return Ok(Fault::Handled(flush));
+        }
+
+        let mut flush = FlushBatch::new(self.page_mapper.asid());
+        let segment = self
+            .user_segments
+            .iter()
+            .find(|segment| segment.addr_range.contains_addr(vaddr))
+            .ok_or(Error::InvalidVirtualAddress(vaddr))?;
+        let (segment_flags, segment_map_type) = (segment.flags, segment.map_type);
+        if !Param::flags_grant_access(segment_flags) {
+            return Err(Error::InvalidVirtualAddress(vaddr));
+        }
+        match self.page_mapper.swap_in(vaddr)? {
+            Some(Ok(guard)) => {
+                flush.push(guard);
+                return Ok(Fault::Handled(flush));
+            }
+            Some(Err(slot)) => return Ok(Fault::SwapIn(slot)),
+            None => {}
+        }
+        let translated = self.page_mapper.translate(vaddr);
+        if translated.is_none() {
+            if segment_map_type == MapType::Lazy {
+                if let Some(load) = self.lazy_load(vaddr) {
+                    return Ok(Fault::Load(load));
+                }
+            }
+            match segment_map_type {
+                // A page of a lazy segment without a backing is zero-filled.
+                MapType::Framed | MapType::Lazy => {
+                    let zero = [0; { Param::PAGE_SIZE }];
+                    let page = Page::of_addr(vaddr.align_down_to(Param::PAGE_SIZE));
+                    flush.push(unsafe {
+                        self.page_mapper
+                            .alloc_and_map(&page, segment_flags, &zero)?
+                    });
+                    return Ok(Fault::Handled(flush));
+                }
+                MapType::Linear => {}
+            }
+        }
+        let cow = match translated {
+            Some((_, pte_flags)) => {
+                Param::pte_writeable(segment_flags) && !Param::pte_writeable(pte_flags)
+            }
+            None => false,
+        };
+        if !cow {
+            return Err(Error::InvalidVirtualAddress(vaddr));
+        }
+        flush.push(self.page_mapper.handle_page_fault(vaddr)?);
+        Ok(Fault::Handled(flush))
+    }
+
+    /// Map the page containing `vaddr` with `data`, read from swap `slot`
+    /// after `handle_page_fault` returned `Fault::SwapIn`.
+    /// Nothing is mapped if the page left the slot meanwhile, e.g. it was unmapped.
+    /// On `Error::NoSpace` the call may be retried once frames are freed, or given up
+    /// with `cancel_swap_in`.
+    pub fn finish_swap_in(
+        &mut self,
+        vaddr: VirtualAddress,
+        slot: usize,
+        data: &[u8],
+    ) -> Result<FlushBatch<Param>> {
+        let mut flush = FlushBatch::new(self.page_mapper.asid());
+        if let Some(guard) = self.page_mapper.finish_swap_in(vaddr, slot, data)? {
+            flush.push(guard);
+        }
+        Ok(flush)
+    }
+
+    /// Drop the reference to swap `slot` taken by `Fault::SwapIn`, when the page could not be read.
+    pub fn cancel_swap_in(&self, slot: usize) {
+        self.page_mapper.cancel_swap_in(slot)
+    }
+
+    /// Map the page containing `vaddr` with `page`, read by `load`
+    /// after `handle_page_fault` returned `Fault::Load`.
+    /// Nothing is mapped if the page was mapped meanwhile, or no longer has the backing
+    /// `load` was read from, e.g. its segment was replaced; the access then faults again.
+    /// On `Error::NoSpace` the call may be retried once frames are freed.
+    pub fn finish_lazy_load(
+        &mut self,
+        vaddr: VirtualAddress,
+        load: &LazyLoad,
+        page: &[u8],
+    ) -> Result<FlushBatch<Param>> {
+        let mut flush = FlushBatch::new(self.page_mapper.asid());
+        let flags = match self.user_segments.iter().find(|segment| {
+            segment.map_type == MapType::Lazy && segment.addr_range.contains_addr(vaddr)
+        }) {
+            Some(segment) => segment.flags,
+            None => return Ok(flush),
+        };
+        let current = self.lazy_load(vaddr);
+        if self.page_mapper.translate(vaddr).is_some()
+            || !current.map_or(false, |current| current.same_as(load))
+        {
+            return Ok(flush);
+        }
+        let page_start = vaddr.align_down_to(Param::PAGE_SIZE);
+        flush.push(unsafe {
+            self.page_mapper
+                .alloc_and_map(&Page::of_addr(page_start), flags, page)?
+        });
+        Ok(flush)
     }
 
-    pub fn add_kernel_segment(&mut self, segment: Segment) -> Result<FlushAllGuard<Param>> {
+    // What to read into the page containing `vaddr` from the backing of its lazy segment,
+    // None if no byte of the page has a backing.
+    fn lazy_load(&self, vaddr: VirtualAddress) -> Option<LazyLoad> {
+        let page_start = vaddr.align_down_to(Param::PAGE_SIZE);
+        let backing = self
+            .lazy_backings
+            .iter()
+            .rev()
+            .find(|backing| backing.range.contains_addr(vaddr))?;
+        let data_start = page_start.max(backing.range.start);
+        let data_end = page_start
+            .add(Param::PAGE_SIZE)
+            .min(backing.range.start.add(backing.len))
+            .min(backing.range.end);
+        if data_start >= data_end {
+            return None;
+        }
+        Some(LazyLoad {
+            source: backing.source.clone(),
+            offset: backing.offset + (data_start.0 - backing.range.start.0),
+            page_offset: data_start.0 - page_start.0,
+            len: data_end.0 - data_start.0,
+        })
+    }
+
+    /// Evict one user page of this address space to swap, chosen with the clock algorithm:
+    /// pages are swept in address order, a page accessed since the last sweep is spared once.
+    /// Only writable pages are evicted, read-only pages may share their frame with a forked
+    /// address space. Returns `None` if there is no swap or no page could be evicted.
+    /// The caller writes the page to its slot once the lock of this address space
+    /// is released, see `SwapOut`.
+    pub fn reclaim(&mut self) -> Result<Option<SwapOut<Param>>> {
+        if !self.page_mapper.has_swap() {
+            return Ok(None);
+        }
+        let mut ranges: Vec<Range<VirtualAddress>> = self
+            .user_segments
+            .iter()
+            .filter(|segment| segment.map_type == MapType::Framed)
+            .map(|segment| segment.addr_range.clone())
+            .collect();
+        ranges.sort_by_key(|range| range.start);
+        let pages = ranges
+            .iter()
+            .flat_map(PageIter::<{ Param::PAGE_SIZE }>::new);
+        let hand = self.clock_hand;
+
+        let mut flush = FlushBatch::new(self.page_mapper.asid());
+        // The first round may only clear accessed bits, the second then finds a victim.
+        for _ in 0..2 {
+            let sweep = pages
+                .clone()
+                .filter(|page| page.start() >= hand)
+                .chain(pages.clone().filter(|page| page.start() < hand));
+            for page in sweep {
+                match self.page_mapper.translate(page.start()) {
+                    Some((_, pte_flags)) if Param::pte_writeable(pte_flags) => {}
+                    _ => continue,
+                }
+                match self.page_mapper.take_accessed(&page) {
+                    Some((false, guard)) => guard.ignore(),
+                    Some((true, guard)) => {
+                        flush.push(guard);
+                        continue;
+                    }
+                    None => continue,
+                }
+                if let Some((guard, slot, frame)) = unsafe { self.page_mapper.swap_out(&page)? } {
+                    flush.push(guard);
+                    self.clock_hand = page.start().add(Param::PAGE_SIZE);
+                    return Ok(Some(SwapOut { flush, slot, frame }));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Translates `addr` to the physical address it is mapped to.
+    pub fn translate(&self, addr: VirtualAddress) -> Option<(PhysicalAddress, Flag)> {
+        self.page_mapper.translate(addr)
+    }
+
+    pub fn add_kernel_segment(&mut self, segment: Segment) -> Result<FlushBatch<Param>> {
         self.check_overlap(&segment.addr_range)?;
-        let flush_all_guard = segment.map(&mut self.page_mapper, &[])?;
+        let flush = segment.map(&mut self.page_mapper, &[])?;
         self.kernel_segments.push(segment);
-        Ok(flush_all_guard)
+        Ok(flush)
+    }
+
+    /// Add a user segment whose pages are loaded when they are first accessed.
+    /// The first `len` bytes of the segment are read from `source` starting at `offset`,
+    /// the bytes after them are zero. Nothing is mapped until the pages are accessed.
+    pub fn add_lazy_user_segment(
+        &mut self,
+        mut segment: Segment,
+        source: Arc<dyn PageSource>,
+        offset: usize,
+        len: usize,
+    ) -> Result<()> {
+        self.check_overlap(&segment.addr_range)?;
+        segment.map_type = MapType::Lazy;
+        // Backings of segments that were removed since are stale.
+        self.lazy_backings
+            .retain(|backing| !ranges_overlap(&backing.range, &segment.addr_range));
+        self.lazy_backings.push(LazyBacking {
+            range: segment.addr_range.clone(),
+            source,
+            offset,
+            len,
+        });
+        self.user_segments.push(segment);
+        Ok(())
     }
 
     pub fn add_user_segment(
         &mut self,
         segment: Segment,
         init_data: &[u8],
-    ) -> Result<FlushAllGuard<Param>> {
+    ) -> Result<FlushBatch<Param>> {
         self.check_overlap(&segment.addr_range)?;
-        let flush_all_guard = segment.map(&mut self.page_mapper, init_data)?;
+        let flush = segment.map(&mut self.page_mapper, init_data)?;
         self.user_segments.push(segment);
-        Ok(flush_all_guard)
+        Ok(flush)
+    }
+
+    /// Add a user segment that grows downwards, page by page, when the pages below it are accessed.
+    /// The segment never grows below `limit`.
+    pub fn add_grow_down_segment(
+        &mut self,
+        segment: Segment,
+        limit: VirtualAddress,
+    ) -> Result<FlushBatch<Param>> {
+        let start = segment.addr_range.start;
+        let flush = self.add_user_segment(segment, &[])?;
+        self.grow_down_segments.push((start, limit));
+        Ok(flush)
+    }
+
+    /// Change the lowest address the grow-down segment containing `addr` may grow down to.
+    /// Pages it already has below the new limit stay mapped.
+    /// Returns false if there is no such segment.
+    pub fn set_grow_down_limit(&mut self, addr: VirtualAddress, limit: VirtualAddress) -> bool {
+        let start = match self
+            .user_segments
+            .iter()
+            .find(|segment| segment.addr_range.contains_addr(addr))
+        {
+            Some(segment) => segment.addr_range.start,
+            None => return false,
+        };
+        match self
+            .grow_down_segments
+            .iter_mut()
+            .find(|(segment_start, _)| *segment_start == start)
+        {
+            Some((_, segment_limit)) => {
+                *segment_limit = limit;
+                true
+            }
+            None => false,
+        }
     }
 
     pub fn remove_user_segments(&mut self) -> Result<Option<FlushAllGuard<Param>>> {
         if self.user_segments.is_empty() {
             return Ok(None);
         }
-        let flush_all_guard = self
-            .user_segments
-            .first()
-            .unwrap()
-            .unmap(&mut self.page_mapper)?;
-        for segment in self.user_segments.iter().skip(1) {
-            segment.unmap(&mut self.page_mapper)?;
+        for segment in self.user_segments.iter() {
+            // The whole address space goes away, a single full flush below suffices.
+            segment.unmap(&mut self.page_mapper)?.ignore();
         }
         self.user_segments.truncate(0);
-        Ok(Some(flush_all_guard))
+        self.grow_down_segments.truncate(0);
+        self.lazy_backings.truncate(0);
+        Ok(Some(FlushAllGuard::new(self.page_mapper.asid())))
+    }
+
+    /// Grow or shrink the user segment starting at `start` so that it ends at `new_end`.
+    /// The segment is removed when `new_end` equals `start`.
+    pub fn resize_user_segment(
+        &mut self,
+        start: VirtualAddress,
+        new_end: VirtualAddress,
+    ) -> Result<FlushBatch<Param>> {
+        let idx = self
+            .user_segments
+            .iter()
+            .position(|segment| segment.addr_range.start == start)
+            .ok_or(Error::InvalidVirtualAddress(start))?;
+        let segment = &self.user_segments[idx];
+        let old_end = segment.addr_range.end;
+
+        let mut flush = FlushBatch::new(self.page_mapper.asid());
+        if new_end > old_end {
+            let grown = Segment {
+                addr_range: old_end..new_end,
+                flags: segment.flags,
+                map_type: segment.map_type,
+            };
+            self.check_overlap(&grown.addr_range)?;
+            flush.append(grown.map(&mut self.page_mapper, &[])?);
+        } else if new_end < old_end {
+            if new_end < start {
+                return Err(Error::InvalidVirtualAddress(new_end));
+            }
+            flush.append(
+                Segment {
+                    addr_range: new_end..old_end,
+                    flags: segment.flags,
+                    map_type: segment.map_type,
+                }
+                .unmap(&mut self.page_mapper)?,
+            );
+        }
+
+        if new_end == start {
+            self.user_segments.remove(idx);
+            self.forget_grow_down(start);
+        } else {
+            self.user_segments[idx].addr_range.end = new_end;
+        }
+        Ok(flush)
+    }
+
+    /// Unmap every user page inside `addr_range`.
+    /// Segments partially covered by `addr_range` are shrunk or split in two.
+    /// Returns `None` if nothing was mapped there.
+    pub fn unmap_range(
+        &mut self,
+        addr_range: &Range<VirtualAddress>,
+    ) -> Result<Option<FlushBatch<Param>>> {
+        self.split_user_segment_at(addr_range.start);
+        self.split_user_segment_at(addr_range.end);
+
+        let mut flush: Option<FlushBatch<Param>> = None;
+        let mut idx = 0;
+        while idx < self.user_segments.len() {
+            if !ranges_overlap(&self.user_segments[idx].addr_range, addr_range) {
+                idx += 1;
+                continue;
+            }
+            let segment = self.user_segments.remove(idx);
+            self.forget_grow_down(segment.addr_range.start);
+            let segment_flush = segment.unmap(&mut self.page_mapper)?;
+            match &mut flush {
+                Some(flush) => flush.append(segment_flush),
+                None => flush = Some(segment_flush),
+            }
+        }
+        Ok(flush)
+    }
+
+    /// Add a `Framed` user segment in place of the user pages inside its range, like `MAP_FIXED`.
+    /// Its pages are not mapped up front but zero-filled when they are first accessed,
+    /// so nothing can fail once the old pages are gone.
+    /// Fails without unmapping anything if the range overlaps a kernel segment.
+    pub fn replace_user_segment(&mut self, segment: Segment) -> Result<Option<FlushBatch<Param>>> {
+        for kernel_segment in self.kernel_segments.iter() {
+            if ranges_overlap(&kernel_segment.addr_range, &segment.addr_range) {
+                return Err(Error::AddressOverlap(
+                    kernel_segment.addr_range.clone(),
+                    segment.addr_range.clone(),
+                ));
+            }
+        }
+        let flush = self.unmap_range(&segment.addr_range)?;
+        self.user_segments.push(segment);
+        Ok(flush)
+    }
+
+    /// Free the frames of the user pages inside `addr_range`, keeping their segments.
+    /// The next access of such a page maps a zero-filled page,
+    /// or loads it again from its backing if it belongs to a lazy segment.
+    /// `addr_range` must be completely covered by user segments.
+    pub fn discard(&mut self, addr_range: &Range<VirtualAddress>) -> Result<FlushBatch<Param>> {
+        self.check_covered(addr_range)?;
+
+        let mut flush = FlushBatch::new(self.page_mapper.asid());
+        for segment in self.user_segments.iter() {
+            if segment.map_type == MapType::Linear
+                || !ranges_overlap(&segment.addr_range, addr_range)
+            {
+                continue;
+            }
+            flush.append(
+                Segment {
+                    addr_range: segment.addr_range.start.max(addr_range.start)
+                        ..segment.addr_range.end.min(addr_range.end),
+                    flags: segment.flags,
+                    map_type: segment.map_type,
+                }
+                .unmap(&mut self.page_mapper)?,
+            );
+        }
+        Ok(flush)
+    }
+
+    /// Change the flags of every user page inside `addr_range` to `flags`,
+    /// splitting segments at the boundaries of `addr_range` where needed.
+    /// Flags granting no access keep the pages and their contents for a later change.
+    /// `addr_range` must be completely covered by user segments.
+    pub fn protect(
+        &mut self,
+        addr_range: &Range<VirtualAddress>,
+        flags: Flag,
+    ) -> Result<FlushBatch<Param>> {
+        self.check_covered(addr_range)?;
+        self.split_user_segment_at(addr_range.start);
+        self.split_user_segment_at(addr_range.end);
+
+        let mut flush = FlushBatch::new(self.page_mapper.asid());
+        for segment in self.user_segments.iter_mut() {
+            if !ranges_overlap(&segment.addr_range, addr_range) {
+                continue;
+            }
+            for page in segment.page_iter::<{ Param::PAGE_SIZE }>() {
+                match unsafe { self.page_mapper.protect(&page, flags) } {
+                    Ok(Some(guard)) => flush.push(guard),
+                    // Not accessed since it was dropped by `discard`, or never loaded.
+                    Ok(None) | Err(Error::InvalidVirtualAddress(_)) => {}
+                    Err(err) => return Err(err),
+                }
+            }
+            segment.flags = flags;
+        }
+        Ok(flush)
+    }
+
+    /// Find a free range of `len` bytes aligned to `align` inside `within`,
+    /// which is not covered by any user segment. The space below the lowest and
+    /// above the highest segment count as gaps too.
+    /// Gaps are scanned from the highest address downwards,
+    /// which keeps new regions away from a heap growing upwards.
+    pub fn find_free_region(
+        &self,
+        len: usize,
+        align: usize,
+        within: Range<VirtualAddress>,
+    ) -> Option<Range<VirtualAddress>> {
+        // The growth windows of grow-down segments are kept free as well.
+        let mut ranges: Vec<Range<VirtualAddress>> = self
+            .user_segments
+            .iter()
+            .map(|segment| {
+                self.grow_down_limit(segment)
+                    .unwrap_or(segment.addr_range.start)..segment.addr_range.end
+            })
+            .collect();
+        // An empty range at the end bounds the gap after the last segment.
+        ranges.push(within.end..within.end);
+        ranges.sort_by_key(|range| range.start);
+
+        // Each gap ends where a range starts and begins at the highest end of the ranges below it.
+        let mut lower_end = within.start;
+        let gaps: Vec<Range<VirtualAddress>> = ranges
+            .iter()
+            .map(|range| {
+                let gap = lower_end..range.start;
+                lower_end = lower_end.max(range.end);
+                gap
+            })
+            .collect();
+        gaps.into_iter().rev().find_map(|gap| {
+            let end = gap.end.min(within.end).align_down_to(align);
+            let start = VirtualAddress(end.0.checked_sub(len)?).align_down_to(align);
+            if start >= gap.start {
+                Some(start..start.add(len))
+            } else {
+                None
+            }
+        })
+    }
+
+    // Returns the lowest address `segment` may grow down to, None if it does not grow.
+    fn grow_down_limit(&self, segment: &Segment) -> Option<VirtualAddress> {
+        self.grow_down_segments
+            .iter()
+            .find(|(start, _)| *start == segment.addr_range.start)
+            .map(|(_, limit)| *limit)
+    }
+
+    // The segment starting at `start` is gone, a segment mapped there later does not grow.
+    fn forget_grow_down(&mut self, start: VirtualAddress) {
+        self.grow_down_segments.retain(|(s, _)| *s != start);
+    }
+
+    // Extend the grow-down segment whose growth window contains `vaddr` down to its page.
+    // Returns None if `vaddr` is not in any growth window.
+    fn grow_down(&mut self, vaddr: VirtualAddress) -> Result<Option<FlushBatch<Param>>> {
+        let page_start = vaddr.align_down_to(Param::PAGE_SIZE);
+        let idx = match self.user_segments.iter().position(|segment| {
+            self.grow_down_limit(segment).map_or(false, |limit| {
+                limit <= page_start && page_start < segment.addr_range.start
+            })
+        }) {
+            Some(idx) => idx,
+            None => return Ok(None),
+        };
+        let old_start = self.user_segments[idx].addr_range.start;
+        self.check_overlap(&(page_start..old_start))?;
+        let flush = self.user_segments[idx].grow_down(page_start, &mut self.page_mapper)?;
+        if let Some((start, _)) = self
+            .grow_down_segments
+            .iter_mut()
+            .find(|(start, _)| *start == old_start)
+        {
+            *start = page_start;
+        }
+        Ok(Some(flush))
+    }
+
+    // Split the user segment that strictly contains `addr` into two segments meeting at `addr`.
+    fn split_user_segment_at(&mut self, addr: VirtualAddress) {
+        if let Some(idx) = self.user_segments.iter().position(|segment| {
+            segment.addr_range.start < addr && segment.addr_range.contains_addr(addr)
+        }) {
+            let mut upper = self.user_segments[idx].clone();
+            upper.addr_range.start = addr;
+            self.user_segments[idx].addr_range.end = addr;
+            self.user_segments.insert(idx + 1, upper);
+        }
+    }
+
+    // Check that `addr_range` is covered by user segments without holes
+    fn check_covered(&self, addr_range: &Range<VirtualAddress>) -> Result<()> {
+        let mut ranges: Vec<&Range<VirtualAddress>> = self
+            .user_segments
+            .iter()
+            .map(|segment| &segment.addr_range)
+            .filter(|range| ranges_overlap(range, addr_range))
+            .collect();
+        ranges.sort_by_key(|range| range.start);
+
+        let mut covered_end = addr_range.start;
+        for range in ranges {
+            if range.start > covered_end {
+                return Err(Error::InvalidVirtualAddress(covered_end));
+            }
+            covered_end = covered_end.max(range.end);
+        }
+        if covered_end < addr_range.end {
+            return Err(Error::InvalidVirtualAddress(covered_end));
+        }
+        Ok(())
     }
 
     // Check if `addr_range` and existing segments overlap
     fn check_overlap(&self, addr_range: &Range<VirtualAddress>) -> Result<()> {
         for segment in self.kernel_segments.iter().chain(self.user_segments.iter()) {
-            if segment.addr_range.contains(&addr_range.start)
-                || addr_range.contains(&segment.addr_range.start)
-            {
+            if ranges_overlap(&segment.addr_range, addr_range) {
                 return Err(Error::AddressOverlap(
                     segment.addr_range.clone(),
                     addr_range.clone(),
@@ -114,6 +673,62 @@ where
 pub enum MapType {
     Linear,
     Framed,
+    /// Like `Framed`, but the frames are allocated and filled on the first access of their page.
+    Lazy,
+}
+
+/// Where the pages of a lazy segment are read from, such as the file the segment is loaded from.
+pub trait PageSource: Send + Sync {
+    /// Read up to `buf.len()` bytes at `offset` into `buf`.
+    /// Returns the number of bytes read, 0 at the end of the source.
+    fn read_at<'a>(&'a self, offset: usize, buf: &'a mut [u8]) -> BoxFuture<'a, Result<usize>>;
+}
+
+/// The bytes of a page of a lazy segment, to read without holding the lock of the
+/// address space, see `Fault::Load`: the `len` bytes at `offset` of `source` go
+/// `page_offset` bytes into the page, its other bytes are zero.
+pub struct LazyLoad {
+    pub source: Arc<dyn PageSource>,
+    pub offset: usize,
+    pub page_offset: usize,
+    pub len: usize,
+}
+
+impl LazyLoad {
+    /// Fill `page`, a zeroed page, from the source.
+    pub async fn read(&self, page: &mut [u8]) -> Result<()> {
+        let buf = &mut page[self.page_offset..self.page_offset + self.len];
+        let mut read = 0;
+        while read < buf.len() {
+            match self
+                .source
+                .read_at(self.offset + read, &mut buf[read..])
+                .await?
+            {
+                // A source shorter than recorded, the rest of the page stays zero.
+                0 => break,
+                n => read += n,
+            }
+        }
+        Ok(())
+    }
+
+    fn same_as(&self, other: &LazyLoad) -> bool {
+        // Compare the data pointers only, the vtables of a type may be duplicated.
+        let source = |load: &LazyLoad| Arc::as_ptr(&load.source) as *const u8;
+        source(self) == source(other)
+            && (self.offset, self.page_offset, self.len)
+                == (other.offset, other.page_offset, other.len)
+    }
+}
+
+// The bytes of `range` are read from `source`, starting at `offset`, those after `len` are zero.
+#[derive(Clone)]
+struct LazyBacking {
+    range: Range<VirtualAddress>,
+    source: Arc<dyn PageSource>,
+    offset: usize,
+    len: usize,
 }
 
 #[derive(Clone, Debug)]
@@ -139,7 +754,7 @@ impl Segment {
         &self,
         page_mapper: &mut PageMapper<'a, MutexType, A, Param>,
         init_data: &[u8],
-    ) -> Result<FlushAllGuard<Param>>
+    ) -> Result<FlushBatch<Param>>
     where
         MutexType: lock_api::RawMutex,
         A: Allocator,
@@ -147,12 +762,13 @@ impl Segment {
         [(); Param::PAGE_LEVELS]:,
         [(); Param::PAGE_SIZE]:,
     {
+        let mut flush = FlushBatch::new(page_mapper.asid());
         unsafe {
             match self.map_type {
                 MapType::Linear => {
                     for page in self.page_iter::<{ Param::PAGE_SIZE }>() {
                         let frame = Frame::of_addr(Param::linear_kvirt_to_phys(page.start()));
-                        page_mapper.map(&page, &frame, self.flags)?.ignore()
+                        flush.push(page_mapper.map(&page, &frame, self.flags)?)
                     }
                 }
                 MapType::Framed => {
@@ -182,22 +798,47 @@ impl Segment {
                                 .copy_from_slice(buf);
                         };
 
-                        page_mapper
-                            .alloc_and_map(&page, self.flags, &page_init_data)?
-                            .ignore()
+                        flush.push(page_mapper.alloc_and_map(&page, self.flags, &page_init_data)?)
                     }
                 }
+                // Pages are mapped by the page fault handler when accessed.
+                MapType::Lazy => {}
             }
         }
 
-        // todo
-        Ok(FlushAllGuard::new(page_mapper.asid()))
+        Ok(flush)
+    }
+
+    /// Extend this segment downwards so that it starts at `new_start`, mapping the new pages.
+    pub fn grow_down<'a, MutexType, A, Param>(
+        &mut self,
+        new_start: VirtualAddress,
+        page_mapper: &mut PageMapper<'a, MutexType, A, Param>,
+    ) -> Result<FlushBatch<Param>>
+    where
+        MutexType: lock_api::RawMutex,
+        A: Allocator,
+        Param: PageParam,
+        [(); Param::PAGE_LEVELS]:,
+        [(); Param::PAGE_SIZE]:,
+    {
+        if new_start >= self.addr_range.start {
+            return Ok(FlushBatch::new(page_mapper.asid()));
+        }
+        let flush = Segment {
+            addr_range: new_start..self.addr_range.start,
+            flags: self.flags,
+            map_type: self.map_type,
+        }
+        .map(page_mapper, &[])?;
+        self.addr_range.start = new_start;
+        Ok(flush)
     }
 
     pub fn unmap<'a, MutexType, A, Param>(
         &self,
         page_mapper: &mut PageMapper<'a, MutexType, A, Param>,
-    ) -> Result<FlushAllGuard<Param>>
+    ) -> Result<FlushBatch<Param>>
     where
         MutexType: lock_api::RawMutex,
         A: Allocator,
@@ -205,27 +846,803 @@ impl Segment {
         [(); Param::PAGE_LEVELS]:,
         [(); Param::PAGE_SIZE]:,
     {
+        let mut flush = FlushBatch::new(page_mapper.asid());
         match self.map_type {
             MapType::Linear => {
                 for page in self.page_iter::<{ Param::PAGE_SIZE }>() {
                     unsafe {
                         if let Some((guard, _)) = page_mapper.unmap(&page)? {
-                            guard.ignore()
+                            flush.push(guard)
                         }
                     }
                 }
             }
-            MapType::Framed => {
+            MapType::Framed | MapType::Lazy => {
                 for page in self.page_iter::<{ Param::PAGE_SIZE }>() {
-                    unsafe {
-                        if let Some(guard) = page_mapper.unmap_and_dealloc(&page)? {
-                            guard.ignore()
-                        }
+                    match unsafe { page_mapper.unmap_and_dealloc(&page) } {
+                        Ok(Some(guard)) => flush.push(guard),
+                        // Dropped by `Memory::discard` and not accessed since,
+                        // or a page of a lazy segment that was never accessed.
+                        Ok(None) | Err(Error::InvalidVirtualAddress(_)) => {}
+                        Err(err) => return Err(err),
                     }
                 }
             }
         }
-        // todo
-        Ok(FlushAllGuard::new(page_mapper.asid()))
+        Ok(flush)
+    }
+}
+
+/// How `Memory::handle_page_fault` resolved a fault.
+pub enum Fault<Param: PageParam> {
+    /// The page is mapped, the access can be retried once the entries are flushed.
+    Handled(FlushBatch<Param>),
+    /// The page has to be read from this swap slot, which is done without holding
+    /// the lock of the address space. The page is then passed to `Memory::finish_swap_in`,
+    /// or the slot to `Memory::cancel_swap_in` if the read failed.
+    SwapIn(usize),
+    /// The page of a lazy segment has to be read, which is done without holding the lock
+    /// of the address space. The page is then passed to `Memory::finish_lazy_load`.
+    Load(LazyLoad),
+}
+
+/// A page evicted by `Memory::reclaim`. Its entry is flushed once `flush` is dropped,
+/// then `frame` is written to `slot` and the write ended with `SwapSpace::finish_write`,
+/// which returns the frame to free.
+pub struct SwapOut<Param: PageParam> {
+    pub flush: FlushBatch<Param>,
+    pub slot: usize,
+    pub frame: Frame,
+}
+
+#[cfg(test)]
+mod test {
+    use core::{
+        future::Future,
+        sync::atomic::Ordering,
+        task::{Context, Poll, Waker},
+    };
+    use std::{collections::HashMap, task::Wake};
+
+    use alloc::{boxed::Box, sync::Arc, vec, vec::Vec};
+
+    use super::{BoxFuture, Fault, LazyLoad, MapType, Memory, PageSource, Segment, SwapOut};
+    use crate::{
+        page::{mapper::PageMapper, PageParam},
+        swap::{LockedSwap, SwapSpace},
+        test_mem::{frame_bytes, test_allocator, FrameList, TestAllocator, TestParam},
+        Result, VirtualAddress,
+    };
+
+    const RW: usize = TestParam::FLAG_PTE_READABLE | TestParam::FLAG_PTE_WRITEABLE;
+    const PAGE: usize = TestParam::PAGE_SIZE;
+
+    fn handled(fault: Result<Fault<TestParam>>) {
+        match fault.unwrap() {
+            Fault::Handled(flush) => flush.ignore(),
+            Fault::SwapIn(slot) => panic!("unexpected swap in from slot {}", slot),
+            Fault::Load(_) => panic!("unexpected load"),
+        }
+    }
+
+    /// A swap device keeping the written pages by slot.
+    #[derive(Default)]
+    struct MockDevice {
+        pages: HashMap<usize, Vec<u8>>,
+        writes: usize,
+        reads: usize,
+    }
+
+    impl MockDevice {
+        // Write an evicted page like the kernel does once the address space is unlocked.
+        fn write(
+            &mut self,
+            swap: &LockedSwap<spin::Mutex<()>>,
+            allocator: &TestAllocator,
+            out: SwapOut<TestParam>,
+        ) {
+            let SwapOut { flush, slot, frame } = out;
+            flush.ignore();
+            self.pages.insert(slot, frame_bytes(frame.start()).to_vec());
+            self.writes += 1;
+            assert_eq!(swap.finish_write(slot, true), Some(frame.clone()));
+            allocator.dealloc(&frame);
+        }
+
+        fn read(&mut self, slot: usize) -> Vec<u8> {
+            self.reads += 1;
+            self.pages[&slot].clone()
+        }
+    }
+
+    // Handle a fault on `vaddr`, reading the page from `device` when asked to.
+    fn fault_in(
+        memory: &mut Memory<spin::Mutex<()>, FrameList, TestParam>,
+        device: &mut MockDevice,
+        vaddr: VirtualAddress,
+    ) {
+        match memory.handle_page_fault(vaddr).unwrap() {
+            Fault::Handled(flush) => flush.ignore(),
+            Fault::SwapIn(slot) => {
+                let data = device.read(slot);
+                memory.finish_swap_in(vaddr, slot, &data).unwrap().ignore();
+            }
+            Fault::Load(_) => panic!("unexpected load"),
+        }
+    }
+
+    #[test]
+    fn protect_none_keeps_the_page() {
+        let (allocator, free) = test_allocator(32);
+        let mut memory = Memory::new(PageMapper::<_, _, TestParam>::create(&allocator).unwrap());
+        let start = VirtualAddress(0x1000_0000);
+        let range = start..VirtualAddress(start.0 + TestParam::PAGE_SIZE);
+        memory
+            .add_user_segment(
+                Segment {
+                    addr_range: range.clone(),
+                    flags: TestParam::flag_set_user(RW),
+                    map_type: MapType::Framed,
+                },
+                &[5; 8],
+            )
+            .unwrap()
+            .ignore();
+        let (frame, _) = memory.translate(start).unwrap();
+
+        memory
+            .protect(&range, TestParam::flag_set_user(0))
+            .unwrap()
+            .ignore();
+        assert!(memory.translate(start).is_none());
+        assert!(memory.handle_page_fault(start).is_err());
+
+        // A fork shares the frame of the page that may not be accessed.
+        let mut child = memory.borrow_memory(1).unwrap();
+        child
+            .protect(&range, TestParam::flag_set_user(RW))
+            .unwrap()
+            .ignore();
+        let (child_frame, flags) = child.translate(start).unwrap();
+        assert_eq!(child_frame, frame);
+        assert!(!TestParam::pte_writeable(flags));
+        handled(child.handle_page_fault(start));
+        let (child_frame, _) = child.translate(start).unwrap();
+        assert_ne!(child_frame, frame);
+        assert_eq!(&frame_bytes(child_frame)[..8], &[5; 8]);
+
+        memory
+            .protect(&range, TestParam::flag_set_user(RW))
+            .unwrap()
+            .ignore();
+        let (restored, flags) = memory.translate(start).unwrap();
+        assert_eq!(restored, frame);
+        assert!(TestParam::pte_writeable(flags));
+        assert_eq!(&frame_bytes(restored)[..8], &[5; 8]);
+
+        for memory in [&mut memory, &mut child] {
+            memory.remove_user_segments().unwrap().unwrap().ignore();
+            memory.page_mapper.free_page_table().ignore();
+        }
+        assert_eq!(free.load(Ordering::SeqCst), 32);
+    }
+
+    #[test]
+    fn a_fork_does_not_see_the_writes_of_its_parent_a_thread_does() {
+        let (allocator, free) = test_allocator(32);
+        let memory = Memory::new(PageMapper::<_, _, TestParam>::create(&allocator).unwrap());
+        let memory = Arc::new(spin::Mutex::new(memory));
+        let start = VirtualAddress(0x1000_0000);
+        memory
+            .lock()
+            .add_user_segment(
+                Segment {
+                    addr_range: start..VirtualAddress(start.0 + PAGE),
+                    flags: TestParam::flag_set_user(RW),
+                    map_type: MapType::Framed,
+                },
+                &[5; 8],
+            )
+            .unwrap()
+            .ignore();
+
+        // A thread, `CLONE_VM | CLONE_THREAD`, uses the address space of the process,
+        // a fork a copy-on-write copy of it.
+        let thread = memory.clone();
+        let mut fork = memory.lock().borrow_memory(1).unwrap();
+
+        // The parent writes, which copies the page it shares with the fork.
+        let (_, flags) = memory.lock().translate(start).unwrap();
+        assert!(!TestParam::pte_writeable(flags));
+        handled(memory.lock().handle_page_fault(start));
+        let (frame, flags) = memory.lock().translate(start).unwrap();
+        assert!(TestParam::pte_writeable(flags));
+        frame_bytes(frame)[0] = 7;
+
+        let (thread_frame, _) = thread.lock().translate(start).unwrap();
+        assert_eq!(frame_bytes(thread_frame)[0], 7);
+        let (fork_frame, _) = fork.translate(start).unwrap();
+        assert_ne!(fork_frame, frame);
+        assert_eq!(&frame_bytes(fork_frame)[..8], &[5; 8]);
+
+        drop(thread);
+        let mut memory = Arc::try_unwrap(memory).ok().unwrap().into_inner();
+        for memory in [&mut memory, &mut fork] {
+            memory.remove_user_segments().unwrap().unwrap().ignore();
+            memory.page_mapper.free_page_table().ignore();
+        }
+        assert_eq!(free.load(Ordering::SeqCst), 32);
+    }
+
+    #[test]
+    fn fixed_mapping_replaces_the_pages_it_covers() {
+        let (allocator, free) = test_allocator(32);
+        let mut memory = Memory::new(PageMapper::<_, _, TestParam>::create(&allocator).unwrap());
+        let start = VirtualAddress(0x1000_0000);
+        memory
+            .add_user_segment(
+                Segment {
+                    addr_range: start..VirtualAddress(start.0 + 3 * PAGE),
+                    flags: TestParam::flag_set_user(RW),
+                    map_type: MapType::Framed,
+                },
+                &[7; 3 * PAGE],
+            )
+            .unwrap()
+            .ignore();
+
+        // Map a page over the middle one and write to it.
+        let middle = VirtualAddress(start.0 + PAGE);
+        let range = middle..VirtualAddress(middle.0 + PAGE);
+        let flush = memory
+            .replace_user_segment(Segment {
+                addr_range: range.clone(),
+                flags: TestParam::flag_set_user(RW),
+                map_type: MapType::Framed,
+            })
+            .unwrap();
+        assert!(flush.is_some());
+        assert!(memory.translate(middle).is_none());
+        handled(memory.handle_page_fault(middle));
+        let (frame, _) = memory.translate(middle).unwrap();
+        assert!(frame_bytes(frame).iter().all(|byte| *byte == 0));
+        frame_bytes(frame)[0] = 1;
+        assert_eq!(frame_bytes(memory.translate(middle).unwrap().0)[0], 1);
+        for survivor in [start, VirtualAddress(start.0 + 2 * PAGE)] {
+            let (frame, _) = memory.translate(survivor).unwrap();
+            assert!(frame_bytes(frame).iter().all(|byte| *byte == 7));
+        }
+
+        // A range overlapping a kernel segment is refused before anything is unmapped.
+        let kernel_start = VirtualAddress(start.0 + 3 * PAGE);
+        memory
+            .add_kernel_segment(Segment {
+                addr_range: kernel_start..VirtualAddress(kernel_start.0 + PAGE),
+                flags: RW,
+                map_type: MapType::Framed,
+            })
+            .unwrap()
+            .ignore();
+        assert!(matches!(
+            memory.replace_user_segment(Segment {
+                addr_range: start..VirtualAddress(kernel_start.0 + PAGE),
+                flags: TestParam::flag_set_user(RW),
+                map_type: MapType::Framed,
+            }),
+            Err(crate::Error::AddressOverlap(..))
+        ));
+        assert!(memory.translate(start).is_some());
+        assert!(memory.translate(middle).is_some());
+
+        memory.unmap_range(&range).unwrap().unwrap().ignore();
+        assert!(memory.translate(middle).is_none());
+        assert!(memory.handle_page_fault(middle).is_err());
+        let before = free.load(Ordering::SeqCst);
+        memory.remove_user_segments().unwrap().unwrap().ignore();
+        assert_eq!(free.load(Ordering::SeqCst), before + 2);
+    }
+
+    fn user_segment(start: usize, end: usize) -> Segment {
+        Segment {
+            addr_range: VirtualAddress(start)..VirtualAddress(end),
+            flags: TestParam::flag_set_user(RW),
+            map_type: MapType::Lazy,
+        }
+    }
+
+    #[test]
+    fn overlapping_segments_are_refused() {
+        let (allocator, _) = test_allocator(32);
+        let mut memory = Memory::new(PageMapper::<_, _, TestParam>::create(&allocator).unwrap());
+        memory
+            .add_user_segment(user_segment(0x10_000, 0x14_000), &[])
+            .unwrap()
+            .ignore();
+        for (start, end) in [
+            (0x10_000, 0x14_000),
+            (0x0f_000, 0x11_000),
+            (0x13_000, 0x15_000),
+            (0x11_000, 0x12_000),
+            (0x0f_000, 0x15_000),
+        ] {
+            assert!(matches!(
+                memory.add_user_segment(user_segment(start, end), &[]),
+                Err(crate::Error::AddressOverlap(..))
+            ));
+        }
+        // Touching segments do not overlap.
+        memory
+            .add_user_segment(user_segment(0x0f_000, 0x10_000), &[])
+            .unwrap()
+            .ignore();
+        memory
+            .add_user_segment(user_segment(0x14_000, 0x15_000), &[])
+            .unwrap()
+            .ignore();
+    }
+
+    #[test]
+    fn free_regions_are_found_in_gaps_from_the_top() {
+        let (allocator, _) = test_allocator(32);
+        let mut memory = Memory::new(PageMapper::<_, _, TestParam>::create(&allocator).unwrap());
+        let within = VirtualAddress(0x10_000)..VirtualAddress(0x40_000);
+        let find = |memory: &Memory<_, _, _>, len| {
+            memory
+                .find_free_region(len, PAGE, within.clone())
+                .map(|range| (range.start.0, range.end.0))
+        };
+        assert_eq!(find(&memory, 2 * PAGE), Some((0x3e_000, 0x40_000)));
+
+        // A one page gap at 0x20_000 and a three pages gap at 0x30_000.
+        for (start, end) in [
+            (0x10_000, 0x20_000),
+            (0x21_000, 0x30_000),
+            (0x33_000, 0x40_000),
+        ] {
+            memory
+                .add_user_segment(user_segment(start, end), &[])
+                .unwrap()
+                .ignore();
+        }
+        assert_eq!(find(&memory, PAGE), Some((0x32_000, 0x33_000)));
+        assert_eq!(find(&memory, 3 * PAGE), Some((0x30_000, 0x33_000)));
+        assert_eq!(find(&memory, 4 * PAGE), None);
+        // Unaligned lengths are placed at an aligned start.
+        assert_eq!(find(&memory, PAGE / 2), Some((0x32_000, 0x32_800)));
+
+        // The growth window of a grow-down segment is not handed out.
+        memory
+            .unmap_range(&(VirtualAddress(0x33_000)..VirtualAddress(0x40_000)))
+            .unwrap();
+        memory
+            .add_grow_down_segment(user_segment(0x3f_000, 0x40_000), VirtualAddress(0x38_000))
+            .unwrap()
+            .ignore();
+        assert_eq!(find(&memory, 4 * PAGE), Some((0x34_000, 0x38_000)));
+        assert_eq!(find(&memory, 9 * PAGE), None);
+    }
+
+    #[test]
+    fn unmapping_the_middle_splits_the_segment() {
+        let (allocator, free) = test_allocator(32);
+        let mut memory = Memory::new(PageMapper::<_, _, TestParam>::create(&allocator).unwrap());
+        let start = VirtualAddress(0x1000_0000);
+        let page = |i: usize| VirtualAddress(start.0 + i * PAGE);
+        let data: Vec<u8> = (0..4).flat_map(|i| [i as u8 + 1; PAGE]).collect();
+        memory
+            .add_user_segment(
+                Segment {
+                    addr_range: start..page(4),
+                    flags: TestParam::flag_set_user(RW),
+                    map_type: MapType::Framed,
+                },
+                &data,
+            )
+            .unwrap()
+            .ignore();
+        let free_mapped = free.load(Ordering::SeqCst);
+
+        memory
+            .unmap_range(&(page(1)..page(3)))
+            .unwrap()
+            .unwrap()
+            .ignore();
+        assert_eq!(free.load(Ordering::SeqCst), free_mapped + 2);
+        for i in [1, 2] {
+            assert!(memory.translate(page(i)).is_none());
+            assert!(memory.handle_page_fault(page(i)).is_err());
+        }
+        for i in [0, 3] {
+            let (frame, _) = memory.translate(page(i)).unwrap();
+            assert!(frame_bytes(frame).iter().all(|byte| *byte == i as u8 + 1));
+        }
+
+        // The survivors are separate segments now, each can be changed on its own.
+        memory
+            .protect(
+                &(page(3)..page(4)),
+                TestParam::flag_set_user(TestParam::FLAG_PTE_READABLE),
+            )
+            .unwrap()
+            .ignore();
+        assert!(TestParam::pte_writeable(
+            memory.translate(page(0)).unwrap().1
+        ));
+        assert!(!TestParam::pte_writeable(
+            memory.translate(page(3)).unwrap().1
+        ));
+        assert!(memory.protect(&(page(0)..page(4)), 0).is_err());
+        memory
+            .unmap_range(&(page(0)..page(1)))
+            .unwrap()
+            .unwrap()
+            .ignore();
+        let (frame, _) = memory.translate(page(3)).unwrap();
+        assert!(frame_bytes(frame).iter().all(|byte| *byte == 4));
+
+        memory.remove_user_segments().unwrap().unwrap().ignore();
+        memory.page_mapper.free_page_table().ignore();
+        assert_eq!(free.load(Ordering::SeqCst), 32);
+    }
+
+    #[test]
+    fn discarded_pages_read_back_as_zero() {
+        let (allocator, free) = test_allocator(32);
+        let mut memory = Memory::new(PageMapper::<_, _, TestParam>::create(&allocator).unwrap());
+        let start = VirtualAddress(0x1000_0000);
+        let end = VirtualAddress(start.0 + 3 * PAGE);
+        memory
+            .add_user_segment(
+                Segment {
+                    addr_range: start..end,
+                    flags: TestParam::flag_set_user(RW),
+                    map_type: MapType::Framed,
+                },
+                &[9; 3 * PAGE],
+            )
+            .unwrap()
+            .ignore();
+        let free_mapped = free.load(Ordering::SeqCst);
+
+        let discarded = VirtualAddress(start.0 + PAGE)..end;
+        memory.discard(&discarded).unwrap().ignore();
+        assert_eq!(free.load(Ordering::SeqCst), free_mapped + 2);
+        assert!(memory.translate(discarded.start).is_none());
+        let (kept, _) = memory.translate(start).unwrap();
+        assert!(frame_bytes(kept).iter().all(|byte| *byte == 9));
+
+        handled(memory.handle_page_fault(discarded.start));
+        let (frame, flags) = memory.translate(discarded.start).unwrap();
+        assert!(TestParam::pte_writeable(flags));
+        assert!(frame_bytes(frame).iter().all(|byte| *byte == 0));
+        assert_eq!(free.load(Ordering::SeqCst), free_mapped + 1);
+
+        // Only ranges covered by segments can be discarded.
+        assert!(memory
+            .discard(&(start..VirtualAddress(end.0 + PAGE)))
+            .is_err());
+    }
+
+    #[test]
+    fn page_table_teardown_frees_pages_that_may_not_be_accessed() {
+        let (allocator, free) = test_allocator(32);
+        let mut memory = Memory::new(PageMapper::<_, _, TestParam>::create(&allocator).unwrap());
+        let start = VirtualAddress(0x1000_0000);
+        memory
+            .add_user_segment(
+                Segment {
+                    addr_range: start..VirtualAddress(start.0 + 2 * TestParam::PAGE_SIZE),
+                    flags: TestParam::flag_set_user(0),
+                    map_type: MapType::Framed,
+                },
+                &[],
+            )
+            .unwrap()
+            .ignore();
+        assert!(memory.translate(start).is_none());
+        memory.page_mapper.free_page_table().ignore();
+        assert_eq!(free.load(Ordering::SeqCst), 32);
+    }
+
+    #[test]
+    fn grow_down_segment_grows_after_its_top_is_unmapped() {
+        const PAGE: usize = TestParam::PAGE_SIZE;
+        let (allocator, free) = test_allocator(32);
+        let mut memory = Memory::new(PageMapper::<_, _, TestParam>::create(&allocator).unwrap());
+        let end = VirtualAddress(0x1000_0000);
+        let start = VirtualAddress(end.0 - 2 * PAGE);
+        memory
+            .add_grow_down_segment(
+                Segment {
+                    addr_range: start..end,
+                    flags: TestParam::flag_set_user(RW),
+                    map_type: MapType::Framed,
+                },
+                VirtualAddress(end.0 - 4 * PAGE),
+            )
+            .unwrap()
+            .ignore();
+        memory
+            .unmap_range(&(VirtualAddress(end.0 - PAGE)..end))
+            .unwrap()
+            .unwrap()
+            .ignore();
+
+        let below = VirtualAddress(start.0 - PAGE);
+        handled(memory.handle_page_fault(below));
+        assert!(memory.translate(below).is_some());
+        // The limit is found through any address of the segment, which now starts lower.
+        assert!(memory.set_grow_down_limit(start, below));
+        assert!(memory
+            .handle_page_fault(VirtualAddress(below.0 - PAGE))
+            .is_err());
+
+        memory.remove_user_segments().unwrap().unwrap().ignore();
+        memory.page_mapper.free_page_table().ignore();
+        assert_eq!(free.load(Ordering::SeqCst), 32);
+    }
+
+    #[test]
+    fn swapped_out_pages_are_read_back_and_slots_freed() {
+        let (allocator, free) = test_allocator(64);
+        let swap = LockedSwap::<spin::Mutex<()>>::new(32);
+        let mut device = MockDevice::default();
+        let mut page_mapper = PageMapper::<_, _, TestParam>::create(&allocator).unwrap();
+        page_mapper.set_swap(&swap);
+        let mut memory = Memory::new(page_mapper);
+        let start = VirtualAddress(0x1000_0000);
+        let pages = 24;
+        memory
+            .add_user_segment(
+                Segment {
+                    addr_range: start..VirtualAddress(start.0 + pages * PAGE),
+                    flags: TestParam::flag_set_user(RW),
+                    map_type: MapType::Framed,
+                },
+                &[],
+            )
+            .unwrap()
+            .ignore();
+        let range = start..VirtualAddress(start.0 + pages * PAGE);
+        memory.discard(&range).unwrap().ignore();
+        // Leave fewer frames than pages.
+        let held: Vec<_> = core::iter::from_fn(|| allocator.alloc())
+            .take(free.load(Ordering::SeqCst) - 12)
+            .collect();
+
+        // Touch more pages than there are frames, evicting pages when frames run out.
+        for i in 0..pages {
+            let vaddr = VirtualAddress(start.0 + i * PAGE);
+            loop {
+                match memory.handle_page_fault(vaddr) {
+                    Err(crate::Error::NoSpace) => {
+                        let out = memory.reclaim().unwrap().expect("no page to evict");
+                        device.write(&swap, &allocator, out);
+                    }
+                    fault => break handled(fault),
+                }
+            }
+            let (frame, _) = memory.translate(vaddr).unwrap();
+            frame_bytes(frame)[0] = i as u8;
+        }
+        assert!(device.writes > 0);
+        assert_eq!(swap.used_count(), device.writes);
+        // Leave frames for the page tables of the fork.
+        for _ in 0..4 {
+            let out = memory.reclaim().unwrap().unwrap();
+            device.write(&swap, &allocator, out);
+        }
+
+        // A fork references the same slots, reading a page back in one keeps it for the other.
+        let mut child = memory.borrow_memory(1).unwrap();
+        let swapped = (0..pages)
+            .map(|i| VirtualAddress(start.0 + i * PAGE))
+            .find(|vaddr| memory.translate(*vaddr).is_none())
+            .unwrap();
+        let used = swap.used_count();
+        child.page_mapper.set_asid(1);
+        fault_in(&mut child, &mut device, swapped);
+        let (frame, _) = child.translate(swapped).unwrap();
+        assert_eq!(frame_bytes(frame)[0] as usize, (swapped.0 - start.0) / PAGE);
+        assert_eq!(swap.used_count(), used);
+        assert_eq!(device.reads, 1);
+
+        for memory in [&mut child, &mut memory] {
+            memory.remove_user_segments().unwrap().unwrap().ignore();
+            memory.page_mapper.free_page_table().ignore();
+        }
+        assert_eq!(swap.used_count(), 0);
+        held.iter().for_each(|frame| {
+            allocator.dealloc(frame);
+        });
+        assert_eq!(free.load(Ordering::SeqCst), 64);
+    }
+
+    #[test]
+    fn page_table_teardown_frees_swap_slots() {
+        let (allocator, free) = test_allocator(16);
+        let swap = LockedSwap::<spin::Mutex<()>>::new(8);
+        let mut device = MockDevice::default();
+        let mut page_mapper = PageMapper::<_, _, TestParam>::create(&allocator).unwrap();
+        page_mapper.set_swap(&swap);
+        let mut memory = Memory::new(page_mapper);
+        let start = VirtualAddress(0x1000_0000);
+        memory
+            .add_user_segment(
+                Segment {
+                    addr_range: start..VirtualAddress(start.0 + 4 * PAGE),
+                    flags: TestParam::flag_set_user(RW),
+                    map_type: MapType::Framed,
+                },
+                &[7; 4 * PAGE],
+            )
+            .unwrap()
+            .ignore();
+        while let Some(out) = memory.reclaim().unwrap() {
+            device.write(&swap, &allocator, out);
+        }
+        assert_eq!(swap.used_count(), 4);
+
+        // A page still being written is freed once the write ends.
+        let mut other = Memory::new({
+            let mut page_mapper = PageMapper::<_, _, TestParam>::create(&allocator).unwrap();
+            page_mapper.set_swap(&swap);
+            page_mapper
+        });
+        other
+            .add_user_segment(
+                Segment {
+                    addr_range: start..VirtualAddress(start.0 + PAGE),
+                    flags: TestParam::flag_set_user(RW),
+                    map_type: MapType::Framed,
+                },
+                &[1],
+            )
+            .unwrap()
+            .ignore();
+        let SwapOut { flush, slot, frame } = other.reclaim().unwrap().unwrap();
+        flush.ignore();
+        other.page_mapper.free_page_table().ignore();
+        assert_eq!(swap.finish_write(slot, true), Some(frame.clone()));
+        allocator.dealloc(&frame);
+
+        // Segments are not removed, the page table teardown alone frees the slots.
+        memory.page_mapper.free_page_table().ignore();
+        assert_eq!(swap.used_count(), 0);
+        assert_eq!(free.load(Ordering::SeqCst), 16);
+    }
+
+    /// A file of `len` bytes, byte `i` being `i`, read at most `chunk` bytes at a time.
+    struct MockSource {
+        len: usize,
+        chunk: usize,
+    }
+
+    impl PageSource for MockSource {
+        fn read_at<'a>(&'a self, offset: usize, buf: &'a mut [u8]) -> BoxFuture<'a, Result<usize>> {
+            let left = self.len.saturating_sub(offset);
+            let n = buf.len().min(self.chunk).min(left);
+            for (i, byte) in buf[..n].iter_mut().enumerate() {
+                *byte = (offset + i) as u8;
+            }
+            Box::pin(core::future::ready(Ok(n)))
+        }
+    }
+
+    // Poll a future that completes without waiting, as the reads of `MockSource`.
+    fn poll_once<F: Future>(fut: F) -> F::Output {
+        struct NoopWaker;
+        impl Wake for NoopWaker {
+            fn wake(self: Arc<Self>) {}
+        }
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut cx = Context::from_waker(&waker);
+        match Box::pin(fut).as_mut().poll(&mut cx) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("the future is pending"),
+        }
+    }
+
+    fn expect_load(
+        memory: &mut Memory<spin::Mutex<()>, FrameList, TestParam>,
+        vaddr: VirtualAddress,
+    ) -> LazyLoad {
+        match memory.handle_page_fault(vaddr).unwrap() {
+            Fault::Load(load) => load,
+            _ => panic!("expected a load"),
+        }
+    }
+
+    fn lazy_segment(
+        memory: &mut Memory<spin::Mutex<()>, FrameList, TestParam>,
+        start: VirtualAddress,
+        source: &Arc<dyn PageSource>,
+    ) {
+        // 2.5 pages of the file from offset 16, in a 4 pages segment.
+        let segment = Segment {
+            addr_range: start..VirtualAddress(start.0 + 4 * PAGE),
+            flags: TestParam::flag_set_user(RW),
+            map_type: MapType::Lazy,
+        };
+        memory
+            .add_lazy_user_segment(segment, source.clone(), 16, 2 * PAGE + PAGE / 2)
+            .unwrap();
+    }
+
+    #[test]
+    fn lazy_pages_are_read_without_the_address_space() {
+        let (allocator, _) = test_allocator(32);
+        let mut memory = Memory::new(PageMapper::<_, _, TestParam>::create(&allocator).unwrap());
+        let source: Arc<dyn PageSource> = Arc::new(MockSource {
+            len: 8 * PAGE,
+            chunk: 100,
+        });
+        let start = VirtualAddress(0x1000_0000);
+        lazy_segment(&mut memory, start, &source);
+
+        // The fault maps nothing, the page is read by the caller.
+        let page_start = VirtualAddress(start.0 + 2 * PAGE);
+        let vaddr = VirtualAddress(page_start.0 + 8);
+        let load = expect_load(&mut memory, vaddr);
+        assert!(memory.translate(vaddr).is_none());
+        assert_eq!(load.offset, 16 + 2 * PAGE);
+        assert_eq!((load.page_offset, load.len), (0, PAGE / 2));
+
+        let mut page = vec![0; PAGE];
+        poll_once(load.read(&mut page)).unwrap();
+        memory
+            .finish_lazy_load(vaddr, &load, &page)
+            .unwrap()
+            .ignore();
+        let (frame, _) = memory.translate(page_start).unwrap();
+        let bytes = frame_bytes(frame);
+        assert!(bytes[..PAGE / 2]
+            .iter()
+            .enumerate()
+            .all(|(i, byte)| *byte == (16 + 2 * PAGE + i) as u8));
+        assert!(bytes[PAGE / 2..].iter().all(|byte| *byte == 0));
+
+        // A page past the backed bytes is zero-filled by the fault itself.
+        handled(memory.handle_page_fault(VirtualAddress(start.0 + 3 * PAGE)));
+    }
+
+    #[test]
+    fn stale_lazy_loads_map_nothing() {
+        let (allocator, _) = test_allocator(32);
+        let mut memory = Memory::new(PageMapper::<_, _, TestParam>::create(&allocator).unwrap());
+        let source: Arc<dyn PageSource> = Arc::new(MockSource {
+            len: 8 * PAGE,
+            chunk: PAGE,
+        });
+        let start = VirtualAddress(0x1000_0000);
+        lazy_segment(&mut memory, start, &source);
+        let load = expect_load(&mut memory, start);
+        let page = vec![0xff; PAGE];
+
+        // The segment is replaced by one from another file while the page is read.
+        let range = start..VirtualAddress(start.0 + 4 * PAGE);
+        memory.unmap_range(&range).unwrap().unwrap().ignore();
+        let other: Arc<dyn PageSource> = Arc::new(MockSource {
+            len: 8 * PAGE,
+            chunk: PAGE,
+        });
+        lazy_segment(&mut memory, start, &other);
+        memory
+            .finish_lazy_load(start, &load, &page)
+            .unwrap()
+            .ignore();
+        assert!(memory.translate(start).is_none());
+
+        // Another fault loaded the page meanwhile.
+        let load = expect_load(&mut memory, start);
+        let flush = memory.finish_lazy_load(start, &load, &page).unwrap();
+        flush.ignore();
+        let (frame, _) = memory.translate(start).unwrap();
+        memory
+            .finish_lazy_load(start, &load, &[0; PAGE])
+            .unwrap()
+            .ignore();
+        assert_eq!(memory.translate(start).unwrap().0, frame);
+        assert!(frame_bytes(frame).iter().all(|byte| *byte == 0xff));
     }
 }
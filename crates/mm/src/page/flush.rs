@@ -1,6 +1,8 @@
-use core::{marker::PhantomData, mem};
+use core::{marker::PhantomData, mem, ops::Range};
 
-use crate::Page;
+use alloc::vec::Vec;
+
+use crate::{Addr, Page, VirtualAddress};
 
 use super::PageParam;
 
@@ -65,3 +67,171 @@ impl<Param: PageParam> Drop for FlushAllGuard<Param> {
         self.flush();
     }
 }
+
+/// A batch touching more pages than this is flushed with a single full flush.
+pub const FLUSH_BATCH_MAX_PAGES: usize = 32;
+
+/// Collects the pages touched by a batch of `map`/`unmap` operations
+/// and flushes them all at once when dropped.
+pub struct FlushBatch<Param: PageParam> {
+    asid: Option<usize>,
+    // Disjoint page-aligned ranges, contiguous pushes are merged.
+    ranges: Vec<Range<VirtualAddress>>,
+    pages: usize,
+    flush_all: bool,
+    _maker: PhantomData<Param>,
+}
+
+impl<Param: PageParam> FlushBatch<Param> {
+    pub fn new(asid: Option<usize>) -> Self {
+        Self {
+            asid,
+            ranges: Vec::new(),
+            pages: 0,
+            flush_all: false,
+            _maker: PhantomData,
+        }
+    }
+
+    /// Takes over the flush of a single page from `guard`.
+    pub fn push(&mut self, guard: FlushGuard<Param>) {
+        let start = guard.page.start();
+        guard.ignore();
+        self.push_range(start..start.add(Param::PAGE_SIZE));
+    }
+
+    /// Records that every page overlapping `range` needs to be flushed.
+    pub fn push_range(&mut self, range: Range<VirtualAddress>) {
+        if range.start >= range.end || self.flush_all {
+            return;
+        }
+        let start = range.start.align_down_to(Param::PAGE_SIZE);
+        let end = range
+            .end
+            .add(Param::PAGE_SIZE - 1)
+            .align_down_to(Param::PAGE_SIZE);
+
+        self.pages += (end.0 - start.0) / Param::PAGE_SIZE;
+        if self.pages > FLUSH_BATCH_MAX_PAGES {
+            self.flush_all = true;
+            self.ranges.clear();
+            return;
+        }
+        match self.ranges.last_mut() {
+            Some(last) if last.end == start => last.end = end,
+            _ => self.ranges.push(start..end),
+        }
+    }
+
+    /// Moves the pending flushes of `other` into this batch.
+    pub fn append(&mut self, mut other: Self) {
+        if other.flush_all {
+            self.flush_all = true;
+            self.ranges.clear();
+        } else {
+            for range in other.ranges.drain(..) {
+                self.push_range(range);
+            }
+        }
+        other.ignore();
+    }
+
+    pub fn flush(&mut self) {
+        unsafe {
+            if self.flush_all {
+                Param::flush_tlb(self.asid, None);
+            } else {
+                for range in &self.ranges {
+                    for addr in (range.start.0..range.end.0).step_by(Param::PAGE_SIZE) {
+                        Param::flush_tlb(self.asid, Some(VirtualAddress(addr)));
+                    }
+                }
+            }
+        }
+        self.ignore_pending();
+    }
+
+    pub fn ignore(mut self) {
+        self.ignore_pending();
+    }
+
+    fn ignore_pending(&mut self) {
+        self.ranges.clear();
+        self.pages = 0;
+        self.flush_all = false;
+    }
+}
+
+impl<Param: PageParam> Drop for FlushBatch<Param> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FlushBatch, FlushGuard, FLUSH_BATCH_MAX_PAGES};
+    use crate::{
+        page::PageParam,
+        test_mem::{take_flushes, TestParam},
+        Page, VirtualAddress,
+    };
+
+    const PAGE: usize = TestParam::PAGE_SIZE;
+
+    fn pages(start: usize, count: usize) -> core::ops::Range<VirtualAddress> {
+        VirtualAddress(start)..VirtualAddress(start + count * PAGE)
+    }
+
+    #[test]
+    fn batch_flushes_each_page_once_when_dropped() {
+        take_flushes();
+        let mut batch = FlushBatch::<TestParam>::new(None);
+        batch.push(FlushGuard::new(
+            None,
+            Page::of_addr(VirtualAddress(0x1000_0000)),
+        ));
+        batch.push_range(pages(0x1000_0000 + PAGE, 2));
+        // An unaligned range flushes every page it touches.
+        batch.push_range(VirtualAddress(0x2000_0800)..VirtualAddress(0x2000_1800));
+        assert_eq!(take_flushes(), (0, 0));
+        drop(batch);
+        assert_eq!(take_flushes(), (0, 5));
+
+        let mut batch = FlushBatch::<TestParam>::new(None);
+        batch.push_range(pages(0x1000_0000, 2));
+        batch.flush();
+        drop(batch);
+        assert_eq!(take_flushes(), (0, 2));
+
+        let mut batch = FlushBatch::<TestParam>::new(None);
+        batch.push_range(pages(0x1000_0000, 2));
+        batch.ignore();
+        assert_eq!(take_flushes(), (0, 0));
+    }
+
+    #[test]
+    fn large_batches_are_flushed_at_once() {
+        take_flushes();
+        let mut batch = FlushBatch::<TestParam>::new(None);
+        batch.push_range(pages(0x1000_0000, FLUSH_BATCH_MAX_PAGES));
+        drop(batch);
+        assert_eq!(take_flushes(), (0, FLUSH_BATCH_MAX_PAGES));
+
+        let mut batch = FlushBatch::<TestParam>::new(None);
+        batch.push_range(pages(0x1000_0000, FLUSH_BATCH_MAX_PAGES));
+        batch.push_range(pages(0x2000_0000, 1));
+        drop(batch);
+        assert_eq!(take_flushes(), (1, 0));
+
+        // A batch taking over a full flush does a full flush as well.
+        let mut small = FlushBatch::<TestParam>::new(None);
+        small.push_range(pages(0x1000_0000, 1));
+        let mut large = FlushBatch::<TestParam>::new(None);
+        large.push_range(pages(0x2000_0000, FLUSH_BATCH_MAX_PAGES + 1));
+        small.append(large);
+        assert_eq!(take_flushes(), (0, 0));
+        drop(small);
+        assert_eq!(take_flushes(), (1, 0));
+    }
+}
@@ -1,6 +1,9 @@
-use core::{marker::PhantomData, ptr};
+use core::{marker::PhantomData, ptr, slice};
 
-use crate::{Addr, Error, Result, VirtualAddress};
+use crate::{
+    swap::{SwapIn, SwapSpace},
+    Addr, Error, PhysicalAddress, Result, VirtualAddress,
+};
 
 use super::{
     flush::{FlushAllGuard, FlushGuard},
@@ -9,11 +12,21 @@ use super::{
     Flag, Frame, Page, PageParam,
 };
 
+// The bytes of `frame`, through the linear mapping.
+unsafe fn page_bytes<'f, Param: PageParam>(frame: &Frame) -> &'f mut [u8] {
+    slice::from_raw_parts_mut(
+        Param::linear_phys_to_kvirt(frame.start()).as_mut_ptr(),
+        Param::PAGE_SIZE,
+    )
+}
+
 pub struct PageMapper<'a, MutexType, A, Param> {
     // Address space identifier, representing the specified process in tlb
     asid: Option<usize>,
     root_table: PageTable<Param>,
     allocator: &'a LockedAllocator<MutexType, A>,
+    // Where pages are evicted to when frames run out, `None` if there is no swap.
+    swap: Option<&'a (dyn SwapSpace + Sync)>,
     _maker: PhantomData<Param>,
 }
 
@@ -29,6 +42,10 @@ where
         self.asid
     }
 
+    pub fn has_swap(&self) -> bool {
+        self.swap.is_some()
+    }
+
     /// # Safety
     pub unsafe fn activate(&self) {
         // todo asid
@@ -59,10 +76,15 @@ where
             asid: None,
             root_table,
             allocator,
+            swap: None,
             _maker: PhantomData,
         }
     }
 
+    pub fn set_swap(&mut self, swap: &'a (dyn SwapSpace + Sync)) {
+        self.swap = Some(swap)
+    }
+
     /// # Safety
     pub unsafe fn alloc_and_map(
         &mut self,
@@ -112,6 +134,79 @@ where
         Ok(FlushGuard::new(self.asid, page.clone()))
     }
 
+    /// Maps `page` to `frame` with a single leaf entry in the page table at `level`,
+    /// e.g. a 2 MiB megapage or a 1 GiB gigapage on Sv39.
+    /// Both addresses must be aligned to `Param::level_size(level)`,
+    /// and nothing may be mapped in that span yet.
+    ///
+    /// # Safety
+    pub unsafe fn map_huge(
+        &mut self,
+        page: &Page,
+        frame: &Frame,
+        level: usize,
+        flags: Flag,
+    ) -> Result<FlushGuard<Param>> {
+        if level >= Param::PAGE_LEVELS {
+            return Err(Error::InvalidVirtualAddress(page.start()));
+        }
+        if !Param::flags_grant_access(flags) {
+            return Err(Error::InvalidFlags(flags));
+        }
+        let size = Param::level_size(level);
+        if page.start().0 % size != 0 || frame.start().0 % size != 0 {
+            return Err(Error::InvalidVirtualAddress(page.start()));
+        }
+
+        let mut tab = self.root_table();
+        let pte_idxs = Param::pte_idxs(page.start());
+        for &pte_idx in &pte_idxs[0..level] {
+            let mut pte = tab
+                .get_entry(pte_idx)
+                .ok_or_else(|| Error::InvalidVirtualAddress(page.start()))?;
+            match pte.next_page_table() {
+                Ok(next) => tab = next,
+                Err(NextPageError::Invalid) => {
+                    let next = PageTable::new(self.allocator.alloc().ok_or(Error::NoSpace)?);
+                    pte.set_nonleaf(next.frame.start());
+                    tab = next;
+                }
+                Err(NextPageError::NoNext) => {
+                    // Already covered by a larger leaf
+                    return Err(Error::InvalidVirtualAddress(page.start()));
+                }
+            }
+        }
+
+        let mut pte = tab
+            .get_entry(pte_idxs[level])
+            .ok_or_else(|| Error::InvalidVirtualAddress(page.start()))?;
+        if pte.is_valid() {
+            return Err(Error::InvalidVirtualAddress(page.start()));
+        }
+        pte.set(frame.start(), flags);
+
+        Ok(FlushGuard::new(self.asid, page.clone()))
+    }
+
+    /// Translates `addr` to its physical address and the flags of the leaf entry mapping it.
+    /// Leaves at any level are understood, so huge pages translate as well.
+    pub fn translate(&self, addr: VirtualAddress) -> Option<(PhysicalAddress, Flag)> {
+        let mut tab = self.root_table();
+        for (level, &pte_idx) in Param::pte_idxs(addr).iter().enumerate() {
+            let pte = unsafe { tab.get_entry(pte_idx)? };
+            match pte.next_page_table() {
+                Ok(next) => tab = next,
+                Err(NextPageError::Invalid) => return None,
+                Err(NextPageError::NoNext) => {
+                    let offset = addr.0 % Param::level_size(level);
+                    return Some((pte.frame().start().add(offset), pte.flags()));
+                }
+            }
+        }
+        None
+    }
+
     /// # Safety
     pub unsafe fn unmap_and_dealloc(&mut self, page: &Page) -> Result<Option<FlushGuard<Param>>> {
         Ok(if let Some((flush_guard, pte)) = self.unmap(page)? {
@@ -122,6 +217,10 @@ where
         })
     }
 
+    /// Unmap `page` and return its entry, the frame stays allocated
+    /// and is the caller's to free, see `unmap_and_dealloc`.
+    /// Returns `None` if the page was in swap, its slot is freed.
+    ///
     /// # Safety
     pub unsafe fn unmap(
         &mut self,
@@ -133,6 +232,16 @@ where
                 .get_entry(pte_idx)
                 .ok_or_else(|| Error::InvalidVirtualAddress(page.start()))?;
 
+            if let Some(slot) = Param::pte_swap_slot(pte.data()) {
+                // The page lives in swap, there is no frame to return.
+                self.free_slot(slot);
+                pte.clear();
+                return Ok(None);
+            }
+            if pte.is_noaccess() {
+                pte.set_invalid();
+                return Ok(Some((FlushGuard::new(self.asid, page.clone()), pte)));
+            }
             match pte.next_page_table() {
                 Ok(next) => tab = next,
                 Err(NextPageError::Invalid) => {
@@ -140,57 +249,349 @@ where
                 }
                 Err(NextPageError::NoNext) => {
                     // This is already a leaf node
-                    return Ok(if pte.free(self.allocator) {
-                        Some((FlushGuard::new(self.asid, page.clone()), pte))
+                    pte.set_invalid();
+                    return Ok(Some((FlushGuard::new(self.asid, page.clone()), pte)));
+                }
+            }
+        }
+        Err(Error::InvalidVirtualAddress(page.start()))
+    }
+
+    /// Rewrite the flags of the leaf entry mapping `page`, keeping its frame.
+    /// Flags granting no access keep the frame in an invalid entry, see `PageTableEntry::set`.
+    /// A frame shared with another address space stays read-only,
+    /// the page fault handler makes it writable once it has been copied.
+    /// A page in swap records `flags` for when it is read back.
+    /// Returns `None` if `page` is not mapped or in swap.
+    ///
+    /// # Safety
+    pub unsafe fn protect(
+        &mut self,
+        page: &Page,
+        flags: Flag,
+    ) -> Result<Option<FlushGuard<Param>>> {
+        let mut tab = self.root_table();
+        for &pte_idx in Param::pte_idxs(page.start()).iter() {
+            let mut pte = tab
+                .get_entry(pte_idx)
+                .ok_or_else(|| Error::InvalidVirtualAddress(page.start()))?;
+
+            if let Some(slot) = Param::pte_swap_slot(pte.data()) {
+                pte.set_swapped(slot, flags);
+                return Ok(None);
+            }
+            match pte.next_page_table() {
+                Ok(next) => tab = next,
+                Err(NextPageError::Invalid) if !pte.is_noaccess() => return Ok(None),
+                Err(_) => {
+                    let frame = pte.frame();
+                    let flags = if self.allocator.ref_count(&frame) > 1 {
+                        Param::pte_set_unwritable(flags)
                     } else {
-                        None
-                    });
+                        flags
+                    };
+                    pte.set(frame.start(), flags);
+                    return Ok(Some(FlushGuard::new(self.asid, page.clone())));
                 }
             }
         }
         Err(Error::InvalidVirtualAddress(page.start()))
     }
 
+    /// Evict the base page `page` to a swap slot, its entry keeps the flags and records the slot.
+    /// Returns the slot and the frame, which the caller writes to the slot
+    /// and then ends the write with `SwapSpace::finish_write`.
+    /// Returns `None` if `page` is not mapped by a base page.
+    ///
+    /// # Safety
+    /// The frame must not be mapped anywhere else.
+    pub unsafe fn swap_out(
+        &mut self,
+        page: &Page,
+    ) -> Result<Option<(FlushGuard<Param>, usize, Frame)>> {
+        let swap = self.swap.ok_or(Error::NoSpace)?;
+        let mut pte = match self.leaf_entry(page.start()) {
+            Some(pte) if matches!(pte.next_page_table(), Err(NextPageError::NoNext)) => pte,
+            _ => return Ok(None),
+        };
+        let frame = pte.frame();
+        let slot = swap.start_write(frame.clone())?;
+        pte.set_swapped(slot, pte.flags());
+        Ok(Some((FlushGuard::new(self.asid, page.clone()), slot, frame)))
+    }
+
+    /// Map the page containing `addr` again if it is in swap and its frame is still around.
+    /// Returns the slot as the error if the page has to be read from it first,
+    /// see `finish_swap_in`, and `None` if the page is not in swap.
+    pub fn swap_in(
+        &mut self,
+        addr: VirtualAddress,
+    ) -> Result<Option<core::result::Result<FlushGuard<Param>, usize>>> {
+        let mut pte = match unsafe { self.leaf_entry(addr) } {
+            Some(pte) => pte,
+            None => return Ok(None),
+        };
+        let (slot, swap) = match (Param::pte_swap_slot(pte.data()), self.swap) {
+            (Some(slot), Some(swap)) => (slot, swap),
+            _ => return Ok(None),
+        };
+        let frame = match swap.take(slot) {
+            SwapIn::Frame(frame) => frame,
+            SwapIn::Copy(src) => {
+                let frame = self.allocator.alloc().ok_or(Error::NoSpace)?;
+                unsafe { page_bytes::<Param>(&frame).copy_from_slice(page_bytes::<Param>(&src)) };
+                self.free_slot(slot);
+                frame
+            }
+            SwapIn::Read => return Ok(Some(Err(slot))),
+        };
+        pte.set(frame.start(), pte.flags() & !Param::FLAG_PTE_SWAPPED);
+        Ok(Some(Ok(FlushGuard::new(
+            self.asid,
+            Page::of_addr(addr.align_down_to_shift(Param::PAGE_SIZE_SHIFT)),
+        ))))
+    }
+
+    /// Map the page containing `addr` with `data` read from `slot`, after `swap_in` returned
+    /// the slot. The reference to the slot added for the read is dropped, except on
+    /// `Error::NoSpace`, so that the call can be retried once frames are freed.
+    /// Returns `None` if the entry no longer references the slot, e.g. it was unmapped meanwhile.
+    pub fn finish_swap_in(
+        &mut self,
+        addr: VirtualAddress,
+        slot: usize,
+        data: &[u8],
+    ) -> Result<Option<FlushGuard<Param>>> {
+        let res = self.map_swapped_in(addr, slot, data);
+        if !matches!(res, Err(Error::NoSpace)) {
+            self.free_slot(slot);
+        }
+        res
+    }
+
+    fn map_swapped_in(
+        &mut self,
+        addr: VirtualAddress,
+        slot: usize,
+        data: &[u8],
+    ) -> Result<Option<FlushGuard<Param>>> {
+        let mut pte = match unsafe { self.leaf_entry(addr) } {
+            Some(pte) if Param::pte_swap_slot(pte.data()) == Some(slot) => pte,
+            _ => return Ok(None),
+        };
+        let frame = self.allocator.alloc().ok_or(Error::NoSpace)?;
+        unsafe { page_bytes::<Param>(&frame).copy_from_slice(data) };
+        self.free_slot(slot);
+        pte.set(frame.start(), pte.flags() & !Param::FLAG_PTE_SWAPPED);
+        Ok(Some(FlushGuard::new(
+            self.asid,
+            Page::of_addr(addr.align_down_to_shift(Param::PAGE_SIZE_SHIFT)),
+        )))
+    }
+
+    /// Drop the reference to `slot` added for a read that failed, see `swap_in`.
+    pub fn cancel_swap_in(&self, slot: usize) {
+        self.free_slot(slot)
+    }
+
+    // Drop a reference to a swap slot, freeing a frame that still held its page.
+    fn free_slot(&self, slot: usize) {
+        if let Some(frame) = self.swap.and_then(|swap| swap.free(slot)) {
+            self.allocator.dealloc(&frame);
+        }
+    }
+
+    /// Clear the accessed bit of the base page `page`, returns whether it was set.
+    /// Returns `None` if `page` is not mapped by a base page.
+    pub fn take_accessed(&mut self, page: &Page) -> Option<(bool, FlushGuard<Param>)> {
+        let mut pte = unsafe { self.leaf_entry(page.start())? };
+        if !matches!(pte.next_page_table(), Err(NextPageError::NoNext)) {
+            return None;
+        }
+        let accessed = Param::pte_accessed(pte.data());
+        pte.set(pte.frame().start(), Param::pte_set_unaccessed(pte.flags()));
+        Some((accessed, FlushGuard::new(self.asid, page.clone())))
+    }
+
+    // Returns the last level entry for `addr`, valid or not.
+    // Returns `None` if a table on the way is missing or `addr` is covered by a huge page.
+    unsafe fn leaf_entry(&self, addr: VirtualAddress) -> Option<PageTableEntry<Param>> {
+        let mut tab = self.root_table();
+        let pte_idxs = Param::pte_idxs(addr);
+        for &pte_idx in &pte_idxs[0..pte_idxs.len() - 1] {
+            tab = tab.get_entry(pte_idx)?.next_page_table().ok()?;
+        }
+        tab.get_entry(pte_idxs[pte_idxs.len() - 1])
+    }
+
+    /// Free the page tables, the frames of the pages and their swap slots.
     pub fn free_page_table(&mut self) -> FlushAllGuard<Param> {
-        self.root_table.free(self.allocator);
+        self.root_table.free(self.allocator, self.swap);
+        FlushAllGuard::new(self.asid)
+    }
+
+    /// Make every user page read-only and add a reference to its frame,
+    /// for the address space about to share the frames through `borrow_memory`.
+    /// A write to a page then faults and is handled by `handle_page_fault`.
+    pub fn mark_all_cow(&mut self) -> FlushAllGuard<Param> {
+        self.root_table.mark_cow(self.allocator);
         FlushAllGuard::new(self.asid)
     }
 
     pub fn borrow_memory(&self, asid: usize) -> Result<Self> {
         let mut new_mapper = Self::new(
-            self.root_table.borrow_memory(self.allocator)?,
+            self.root_table.borrow_memory(self.allocator, self.swap)?,
             self.allocator,
         );
 
         new_mapper.set_asid(asid);
+        new_mapper.swap = self.swap;
 
         Ok(new_mapper)
     }
 
+    /// Give the copy-on-write page containing `addr` a frame of its own and make it writable,
+    /// dropping this address space's reference to the shared frame.
+    /// The last address space referencing a frame takes it over without a copy.
     pub fn handle_page_fault(&mut self, addr: VirtualAddress) -> Result<FlushGuard<Param>> {
         let src_page = Page::of_addr(addr.align_down_to_shift(Param::PAGE_SIZE_SHIFT));
+        let mut pte = match unsafe { self.leaf_entry(src_page.start()) } {
+            Some(pte) if matches!(pte.next_page_table(), Err(NextPageError::NoNext)) => pte,
+            _ => return Err(Error::InvalidVirtualAddress(addr)),
+        };
+        let src_frame = pte.frame();
+        let flags = Param::pte_flags(Param::pte_set_writable(pte.data()));
+        if self.allocator.ref_count(&src_frame) == 1 {
+            pte.set(src_frame.start(), flags);
+            return Ok(FlushGuard::new(self.asid, src_page));
+        }
+
         let target_frame = self.allocator.alloc().ok_or(Error::NoSpace)?;
         unsafe {
-            let src_page_data: &[u8] =
-                core::slice::from_raw_parts(src_page.start().as_mut_ptr(), Param::PAGE_SIZE);
-
+            let src_page_data: &[u8] = core::slice::from_raw_parts(
+                Param::linear_phys_to_kvirt(src_frame.start()).as_mut_ptr(),
+                Param::PAGE_SIZE,
+            );
             let target_page_data: &mut [u8] = core::slice::from_raw_parts_mut(
                 Param::linear_phys_to_kvirt(target_frame.start()).as_mut_ptr(),
                 Param::PAGE_SIZE,
             );
             target_page_data.copy_from_slice(src_page_data);
-
-            let (flush, pte) = self.unmap(&src_page)?.unwrap();
-            flush.ignore();
-            self.map(
-                &src_page,
-                &target_frame,
-                Param::pte_flags(Param::pte_set_writable(pte.data())),
-            )
         }
+        pte.set(target_frame.start(), flags);
+        self.allocator.dealloc(&src_frame);
+        Ok(FlushGuard::new(self.asid, src_page))
     }
 
     pub fn root_table(&self) -> PageTable<Param> {
         self.root_table.clone()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use core::sync::atomic::Ordering;
+
+    use super::PageMapper;
+    use crate::{
+        page::PageParam,
+        test_mem::{frame_bytes, test_allocator, TestParam},
+        Addr, Frame, Page, PhysicalAddress, VirtualAddress,
+    };
+
+    const RW: usize = TestParam::FLAG_PTE_READABLE | TestParam::FLAG_PTE_WRITEABLE;
+
+    #[test]
+    fn cow_write_drops_shared_reference() {
+        let (allocator, free) = test_allocator(32);
+        let mut parent = PageMapper::<_, _, TestParam>::create(&allocator).unwrap();
+        let page = Page::of_addr(VirtualAddress(0x1000_0000));
+        unsafe {
+            parent
+                .alloc_and_map(&page, TestParam::flag_set_user(RW), &[7; 16])
+                .unwrap()
+                .ignore();
+        }
+        parent.mark_all_cow().ignore();
+        let mut child = parent.borrow_memory(1).unwrap();
+        let (shared, _) = parent.translate(page.start()).unwrap();
+        assert_eq!(allocator.ref_count(&Frame::of_addr(shared)), 2);
+
+        let free_before_write = free.load(Ordering::SeqCst);
+        child.handle_page_fault(page.start()).unwrap().ignore();
+        let (copy, flags) = child.translate(page.start()).unwrap();
+        assert_ne!(copy, shared);
+        assert!(TestParam::pte_writeable(flags));
+        assert_eq!(&frame_bytes(copy)[..16], &[7; 16]);
+        assert_eq!(allocator.ref_count(&Frame::of_addr(shared)), 1);
+        assert_eq!(free.load(Ordering::SeqCst), free_before_write - 1);
+
+        // The last reference takes the frame over without a copy.
+        parent.handle_page_fault(page.start()).unwrap().ignore();
+        let (taken_over, flags) = parent.translate(page.start()).unwrap();
+        assert_eq!(taken_over, shared);
+        assert!(TestParam::pte_writeable(flags));
+        assert_eq!(free.load(Ordering::SeqCst), free_before_write - 1);
+
+        parent.free_page_table().ignore();
+        child.free_page_table().ignore();
+        assert_eq!(free.load(Ordering::SeqCst), 32);
+    }
+
+    #[test]
+    fn unmap_and_dealloc_frees_the_frame_once() {
+        let (allocator, free) = test_allocator(8);
+        let mut mapper = PageMapper::<_, _, TestParam>::create(&allocator).unwrap();
+        let page = Page::of_addr(VirtualAddress(0x2000_0000));
+        unsafe {
+            mapper
+                .alloc_and_map(&page, TestParam::flag_set_user(RW), &[])
+                .unwrap()
+                .ignore();
+            let free_mapped = free.load(Ordering::SeqCst);
+            mapper.unmap_and_dealloc(&page).unwrap().unwrap().ignore();
+            assert_eq!(free.load(Ordering::SeqCst), free_mapped + 1);
+        }
+        assert!(mapper.translate(page.start()).is_none());
+        mapper.free_page_table().ignore();
+        assert_eq!(free.load(Ordering::SeqCst), 8);
+    }
+
+    #[test]
+    fn huge_pages_translate_every_address_they_span() {
+        const MEGA: usize = 2 << 20;
+        assert_eq!(TestParam::level_size(2), TestParam::PAGE_SIZE);
+        assert_eq!(TestParam::level_size(1), MEGA);
+        assert_eq!(TestParam::level_size(0), 1 << 30);
+
+        let (allocator, _) = test_allocator(8);
+        let mut mapper = PageMapper::<_, _, TestParam>::create(&allocator).unwrap();
+        // Only the page table entries are written, the frame itself is never accessed.
+        let page = Page::of_addr(VirtualAddress(0x4000_0000));
+        let frame = Frame::of_addr(PhysicalAddress(0x8020_0000));
+        let flags = TestParam::flag_set_kernel(RW);
+        let inside = Page::of_addr(VirtualAddress(0x4000_1000));
+        let misaligned = Frame::of_addr(PhysicalAddress(0x8020_1000));
+        unsafe {
+            assert!(mapper.map_huge(&inside, &frame, 1, flags).is_err());
+            assert!(mapper.map_huge(&page, &misaligned, 1, flags).is_err());
+            assert!(mapper
+                .map_huge(&page, &frame, TestParam::PAGE_LEVELS, flags)
+                .is_err());
+            mapper.map_huge(&page, &frame, 1, flags).unwrap().ignore();
+        }
+
+        for offset in [0, 0x1234, MEGA - 1] {
+            let (addr, pte_flags) = mapper.translate(page.start().add(offset)).unwrap();
+            assert_eq!(addr, PhysicalAddress(0x8020_0000 + offset));
+            assert!(TestParam::pte_writeable(pte_flags));
+        }
+        assert!(mapper.translate(page.start().add(MEGA)).is_none());
+
+        // The span is taken, neither a base page nor another huge page can go there.
+        unsafe {
+            assert!(mapper.map(&inside, &frame, flags).is_err());
+            assert!(mapper.map_huge(&page, &frame, 1, flags).is_err());
+        }
+    }
+}
@@ -19,6 +19,12 @@ pub trait PageParam {
     const FLAG_PTE_DIRTY: Flag;
     // Page valid flag bit
     const FLAG_PTE_VALID: Flag;
+    // Marks an invalid entry whose page was evicted to swap,
+    // must be a bit the MMU ignores in invalid entries
+    const FLAG_PTE_SWAPPED: Flag;
+    // Marks an invalid entry of a page that may not be accessed at all but keeps its frame,
+    // must be a bit the MMU ignores in invalid entries
+    const FLAG_PTE_NOACCESS: Flag;
 
     // Number of page table levels
     const PAGE_LEVELS: usize;
@@ -75,6 +81,13 @@ pub trait PageParam {
         (pte & Self::FLAG_PTE_ACCESSED) == Self::FLAG_PTE_ACCESSED
     }
 
+    /// Returns true if `flags` allow reading, writing or executing the page.
+    #[inline(always)]
+    fn flags_grant_access(flags: Flag) -> bool {
+        flags & (Self::FLAG_PTE_READABLE | Self::FLAG_PTE_WRITEABLE | Self::FLAG_PTE_EXECUTABLE)
+            != 0
+    }
+
     #[inline(always)]
     fn pte_is_valid(pte: usize) -> bool {
         (pte & Self::FLAG_PTE_VALID) == Self::FLAG_PTE_VALID
@@ -92,6 +105,40 @@ pub trait PageParam {
         pte & (!Self::FLAG_PTE_VALID)
     }
 
+    #[inline(always)]
+    fn pte_set_unaccessed(pte: usize) -> usize {
+        pte & (!Self::FLAG_PTE_ACCESSED)
+    }
+
+    /// Create an invalid entry for a page evicted to swap `slot`, keeping `flags` for when it is
+    /// read back. The slot number takes the place of the frame number.
+    fn create_swap_pte(slot: usize, flags: Flag) -> usize {
+        Self::create_pte(
+            PhysicalAddress(slot << Self::PAGE_SIZE_SHIFT),
+            Self::pte_set_invalid(flags) | Self::FLAG_PTE_SWAPPED,
+        )
+    }
+
+    /// Returns the swap slot of an entry created by `create_swap_pte`.
+    fn pte_swap_slot(pte: usize) -> Option<usize> {
+        if !Self::pte_is_valid(pte) && pte & Self::FLAG_PTE_SWAPPED == Self::FLAG_PTE_SWAPPED {
+            Some(Self::pte_address(pte).0 >> Self::PAGE_SIZE_SHIFT)
+        } else {
+            None
+        }
+    }
+
+    /// Create an invalid entry for a page that may not be accessed, keeping its frame at `addr`.
+    /// A leaf entry without any of read, write or execute would point to the next level table.
+    fn create_noaccess_pte(addr: PhysicalAddress, flags: Flag) -> usize {
+        Self::create_pte(addr, Self::pte_set_invalid(flags) | Self::FLAG_PTE_NOACCESS)
+    }
+
+    /// Returns true if `pte` was created by `create_noaccess_pte`.
+    fn pte_is_noaccess(pte: usize) -> bool {
+        !Self::pte_is_valid(pte) && pte & Self::FLAG_PTE_NOACCESS == Self::FLAG_PTE_NOACCESS
+    }
+
     fn pte_address(pte: usize) -> PhysicalAddress;
 
     // `pte` existence of next level page table
@@ -113,6 +160,12 @@ pub trait PageParam {
     // Return pte flags
     fn pte_flags(pte: usize) -> Flag;
 
+    /// Number of bytes mapped by a leaf entry in a page table at `level`,
+    /// where level 0 is the root table and `PAGE_LEVELS - 1` maps base pages.
+    fn level_size(level: usize) -> usize {
+        Self::PAGE_SIZE * Self::PTE_COUNT.pow((Self::PAGE_LEVELS - 1 - level) as u32)
+    }
+
     // Linear mapping of physical addresses to kernel virtual addresses
     fn linear_phys_to_kvirt(pa: PhysicalAddress) -> VirtualAddress {
         VirtualAddress(pa.0 + Self::LINEAR_MAPPING_PHYS_OFFSET)
@@ -1,6 +1,6 @@
 use core::{marker::PhantomData, option::Option};
 
-use crate::{frame::LockedAllocator, Addr, Error, Result};
+use crate::{frame::LockedAllocator, swap::SwapSpace, Addr, Error, Result};
 
 use super::{frame::Allocator, Flag, Frame, PageParam, PhysicalAddress, VirtualAddress};
 
@@ -39,19 +39,26 @@ impl<Param: PageParam> PageTable<Param> {
         PageTableEntry::new(pte_kvirt_addr.0 as *mut usize)
     }
 
-    pub fn free<MutexType, A>(&mut self, allocator: &LockedAllocator<MutexType, A>)
-    where
+    pub fn free<MutexType, A>(
+        &mut self,
+        allocator: &LockedAllocator<MutexType, A>,
+        swap: Option<&(dyn SwapSpace + Sync)>,
+    ) where
         MutexType: lock_api::RawMutex,
         A: Allocator,
     {
         unsafe { self.entry_iter() }.for_each(|(_, mut pte)| {
-            pte.free(allocator);
+            pte.free(allocator, swap);
         });
         allocator.dealloc(&self.frame);
     }
+
+    /// Copy this table and the tables below it, for an address space sharing the pages.
+    /// Pages in swap get another reference to their slot.
     pub fn borrow_memory<MutexType, A>(
         &self,
         allocator: &LockedAllocator<MutexType, A>,
+        swap: Option<&(dyn SwapSpace + Sync)>,
     ) -> Result<Self>
     where
         MutexType: lock_api::RawMutex,
@@ -62,16 +69,42 @@ impl<Param: PageParam> PageTable<Param> {
         for (idx, pte) in unsafe { self.entry_iter() } {
             let target_pte_addr =
                 Param::linear_phys_to_kvirt(target_frame.start().add(idx * Param::PAGE_ENTRY_SIZE));
-            pte.borrow_memory(PageTableEntry::new(target_pte_addr.as_mut_ptr()), allocator)?;
+            pte.borrow_memory(
+                PageTableEntry::new(target_pte_addr.as_mut_ptr()),
+                allocator,
+                swap,
+            )?;
         }
 
         Ok(Self::new(target_frame))
     }
 
+    /// Make the user pages mapped by this table and the tables below it read-only,
+    /// adding a reference to their frames for the copy of the table that is about to share them.
+    pub fn mark_cow<MutexType, A>(&self, allocator: &LockedAllocator<MutexType, A>)
+    where
+        MutexType: lock_api::RawMutex,
+        A: Allocator,
+    {
+        for (_, pte) in unsafe { self.entry_iter() } {
+            match pte.next_page_table() {
+                Ok(tab) => tab.mark_cow(allocator),
+                Err(NextPageError::NoNext) if Param::pte_is_user(pte.data()) => {
+                    allocator.share(&pte.frame());
+                    pte.set_data(Param::pte_set_unwritable(pte.data()));
+                }
+                Err(NextPageError::Invalid) if pte.is_noaccess() => allocator.share(&pte.frame()),
+                Err(_) => {}
+            }
+        }
+    }
+
+    // Iterate over the valid entries, the entries of pages that may not be accessed,
+    // which hold a frame as well, and the entries of pages in swap.
     unsafe fn entry_iter(&self) -> impl Iterator<Item = (usize, PageTableEntry<Param>)> + '_ {
         (0..Param::PTE_COUNT)
             .map(move |idx| (idx, self.get_entry_unchecked(idx)))
-            .filter(|(_, pte)| pte.is_valid())
+            .filter(|(_, pte)| pte.is_valid() || pte.is_noaccess() || pte.swap_slot().is_some())
     }
 
     // Get the kernel virtual address of the specified page table entry
@@ -104,8 +137,14 @@ impl<Param> PageTableEntry<Param> {
 }
 
 impl<Param: PageParam> PageTableEntry<Param> {
+    /// Point this leaf entry at `addr`. If `flags` grant no access at all,
+    /// the entry is made invalid and only keeps the frame, see `create_noaccess_pte`.
     pub fn set(&mut self, addr: PhysicalAddress, flags: usize) {
-        self.set_data(Param::create_pte(addr, flags | Param::FLAG_PTE_VALID))
+        if Param::flags_grant_access(flags) {
+            self.set_data(Param::create_pte(addr, flags | Param::FLAG_PTE_VALID))
+        } else {
+            self.set_data(Param::create_noaccess_pte(addr, flags))
+        }
     }
 
     pub fn set_nonleaf(&mut self, addr: PhysicalAddress) {
@@ -127,13 +166,29 @@ impl<Param: PageParam> PageTableEntry<Param> {
         Ok(PageTable::new(self.frame()))
     }
 
-    pub fn free<MutexType, A>(&mut self, allocator: &LockedAllocator<MutexType, A>) -> bool
+    pub fn free<MutexType, A>(
+        &mut self,
+        allocator: &LockedAllocator<MutexType, A>,
+        swap: Option<&(dyn SwapSpace + Sync)>,
+    ) -> bool
     where
         MutexType: lock_api::RawMutex,
         A: Allocator,
     {
+        if let Some(slot) = self.swap_slot() {
+            if let Some(frame) = swap.and_then(|swap| swap.free(slot)) {
+                allocator.dealloc(&frame);
+            }
+            self.clear();
+            return true;
+        }
+        if self.is_noaccess() {
+            allocator.dealloc(&self.frame());
+            self.set_invalid();
+            return true;
+        }
         match self.next_page_table() {
-            Ok(mut tab) => tab.free(allocator),
+            Ok(mut tab) => tab.free(allocator, swap),
             Err(NextPageError::NoNext) => {
                 allocator.dealloc(&self.frame());
             }
@@ -147,14 +202,26 @@ impl<Param: PageParam> PageTableEntry<Param> {
         &self,
         mut target: PageTableEntry<Param>,
         allocator: &LockedAllocator<MutexType, A>,
+        swap: Option<&(dyn SwapSpace + Sync)>,
     ) -> Result<()>
     where
         MutexType: lock_api::RawMutex,
         A: Allocator,
     {
+        if let Some(slot) = self.swap_slot() {
+            if let Some(swap) = swap {
+                swap.share(slot);
+            }
+            target.set_data(self.data());
+            return Ok(());
+        }
+        if self.is_noaccess() {
+            target.set_data(self.data());
+            return Ok(());
+        }
         match self.next_page_table() {
             Ok(tab) => {
-                let new_tab = tab.borrow_memory(allocator)?;
+                let new_tab = tab.borrow_memory(allocator, swap)?;
                 target.set_nonleaf(new_tab.frame.start());
                 Ok(())
             }
@@ -174,12 +241,22 @@ impl<Param: PageParam> PageTableEntry<Param> {
         Param::pte_is_valid(self.data())
     }
 
+    pub fn is_noaccess(&self) -> bool {
+        Param::pte_is_noaccess(self.data())
+    }
+
+    /// Returns the swap slot of an entry whose page was evicted to swap.
+    pub fn swap_slot(&self) -> Option<usize> {
+        Param::pte_swap_slot(self.data())
+    }
+
     pub fn flags(&self) -> Flag {
         Param::pte_flags(self.data())
     }
 
+    /// Make this entry invalid, it keeps its frame number but no longer holds the frame.
     pub fn set_invalid(&mut self) {
-        self.set_data(Param::pte_set_invalid(self.data()))
+        self.set_data(Param::pte_set_invalid(self.data()) & !Param::FLAG_PTE_NOACCESS)
     }
 
     pub fn data(&self) -> usize {
@@ -190,11 +267,16 @@ impl<Param: PageParam> PageTableEntry<Param> {
         unsafe { *self.data = new_data }
     }
 
-    #[allow(dead_code)]
-    fn clear(&mut self) {
+    pub fn clear(&mut self) {
         self.set_data(0)
     }
 
+    /// Replace the entry by one recording that its page was evicted to swap `slot`,
+    /// and is mapped with `flags` when read back.
+    pub fn set_swapped(&mut self, slot: usize, flags: Flag) {
+        self.set_data(Param::create_swap_pte(slot, flags))
+    }
+
     fn has_next_table(&self) -> bool {
         Param::pte_has_next_table(self.data())
     }
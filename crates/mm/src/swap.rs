@@ -0,0 +1,160 @@
+use alloc::vec::Vec;
+
+use crate::{Error, Frame, Result};
+
+/// Swap space as seen by the page mappers, it only keeps track of the slots.
+/// Pages are written to and read from the swap device by its owner, without holding
+/// the lock of an address space, see `Memory::reclaim` and `Fault::SwapIn`.
+pub trait SwapSpace {
+    /// Reserve a free slot for the page held by `frame`, which is about to be written to it.
+    /// The entry that mapped `frame` now references the slot, the frame stays allocated
+    /// until the write ends with `finish_write`.
+    fn start_write(&self, frame: Frame) -> Result<usize>;
+
+    /// End the write to `slot`, which succeeded if `written`.
+    /// Returns the frame to free, `None` if it still holds the only copy of the page.
+    fn finish_write(&self, slot: usize, written: bool) -> Option<Frame>;
+
+    /// Add a reference to `slot`, for an entry copied into another address space.
+    fn share(&self, slot: usize);
+
+    /// Get the page in `slot` back for an entry referencing it.
+    fn take(&self, slot: usize) -> SwapIn;
+
+    /// Drop a reference to `slot`, the slot is free once no entry references it.
+    /// Returns a frame still holding the page, to be freed.
+    fn free(&self, slot: usize) -> Option<Frame>;
+}
+
+/// How an entry referencing a slot gets its page back, see `SwapSpace::take`.
+pub enum SwapIn {
+    /// The page is handed over in this frame, the slot is free.
+    Frame(Frame),
+    /// The page is in this frame, which other entries referencing the slot or the write
+    /// to the slot still need. The caller copies it and drops its reference to the slot.
+    Copy(Frame),
+    /// The page has to be read from the device. A reference to the slot is added,
+    /// so that it is not reused during the read, the caller drops it once done.
+    Read,
+}
+
+#[derive(Clone)]
+enum Slot {
+    Free,
+    /// Being written from `frame`.
+    Writing { frame: Frame, refs: usize },
+    /// Holds a page.
+    Stored { refs: usize },
+    /// The write failed, `frame` holds the only copy of the page.
+    Failed { frame: Frame, refs: usize },
+}
+
+struct Slots {
+    slots: Vec<Slot>,
+    // Slot to start the search for a free slot from.
+    next: usize,
+}
+
+/// Swap space of `slot_count` slots.
+pub struct LockedSwap<MutexType> {
+    inner: lock_api::Mutex<MutexType, Slots>,
+}
+
+impl<MutexType> LockedSwap<MutexType>
+where
+    MutexType: lock_api::RawMutex,
+{
+    pub fn new(slot_count: usize) -> Self {
+        Self {
+            inner: lock_api::Mutex::new(Slots {
+                slots: alloc::vec![Slot::Free; slot_count],
+                next: 0,
+            }),
+        }
+    }
+
+    /// Returns the number of slots in use.
+    pub fn used_count(&self) -> usize {
+        self.inner
+            .lock()
+            .slots
+            .iter()
+            .filter(|slot| !matches!(slot, Slot::Free))
+            .count()
+    }
+}
+
+impl<MutexType> SwapSpace for LockedSwap<MutexType>
+where
+    MutexType: lock_api::RawMutex,
+{
+    fn start_write(&self, frame: Frame) -> Result<usize> {
+        let mut inner = self.inner.lock();
+        let count = inner.slots.len();
+        let slot = (0..count)
+            .map(|i| (inner.next + i) % count)
+            .find(|slot| matches!(inner.slots[*slot], Slot::Free))
+            .ok_or(Error::NoSpace)?;
+        inner.slots[slot] = Slot::Writing { frame, refs: 1 };
+        inner.next = (slot + 1) % count;
+        Ok(slot)
+    }
+
+    fn finish_write(&self, slot: usize, written: bool) -> Option<Frame> {
+        let mut inner = self.inner.lock();
+        let (frame, refs) = match &inner.slots[slot] {
+            Slot::Writing { frame, refs } => (frame.clone(), *refs),
+            _ => return None,
+        };
+        let (state, freed) = match (refs, written) {
+            (0, _) => (Slot::Free, Some(frame)),
+            (refs, true) => (Slot::Stored { refs }, Some(frame)),
+            (refs, false) => (Slot::Failed { frame, refs }, None),
+        };
+        inner.slots[slot] = state;
+        freed
+    }
+
+    fn share(&self, slot: usize) {
+        match &mut self.inner.lock().slots[slot] {
+            Slot::Writing { refs, .. } | Slot::Stored { refs } | Slot::Failed { refs, .. } => {
+                *refs += 1
+            }
+            Slot::Free => {}
+        }
+    }
+
+    fn take(&self, slot: usize) -> SwapIn {
+        let mut inner = self.inner.lock();
+        match &mut inner.slots[slot] {
+            Slot::Failed { frame, refs: 1 } => {
+                let frame = frame.clone();
+                inner.slots[slot] = Slot::Free;
+                SwapIn::Frame(frame)
+            }
+            Slot::Failed { frame, .. } | Slot::Writing { frame, .. } => SwapIn::Copy(frame.clone()),
+            Slot::Stored { refs } => {
+                *refs += 1;
+                SwapIn::Read
+            }
+            Slot::Free => unreachable!("swap slot {} is referenced but free", slot),
+        }
+    }
+
+    fn free(&self, slot: usize) -> Option<Frame> {
+        let mut inner = self.inner.lock();
+        let entry = &mut inner.slots[slot];
+        match entry {
+            Slot::Writing { refs, .. } => *refs = refs.saturating_sub(1),
+            Slot::Stored { refs } | Slot::Failed { refs, .. } if *refs > 1 => *refs -= 1,
+            Slot::Stored { .. } => *entry = Slot::Free,
+            Slot::Failed { frame, .. } => {
+                let frame = frame.clone();
+                *entry = Slot::Free;
+                return Some(frame);
+            }
+            Slot::Free => {}
+        }
+        None
+    }
+}
@@ -0,0 +1,160 @@
+//! Frames and page tables in host memory, for the tests of this crate.
+//! Physical addresses are host addresses, so frames are accessed through
+//! the linear mapping like in the kernel.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::alloc::{alloc_zeroed, Layout};
+
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::{
+    arch::page::PageParamSv39,
+    frame::{Allocator, LockedAllocator},
+    page::{Flag, PageParam},
+    Frame, PhysicalAddress, VirtualAddress,
+};
+
+pub type TestAllocator = LockedAllocator<spin::Mutex<()>, FrameList>;
+
+/// Sv39 with an identity linear mapping and no TLB to flush,
+/// the flushes are only counted, see `take_flushes`.
+pub struct TestParam;
+
+std::thread_local! {
+    // Full and single page TLB flushes of the current test.
+    static FLUSHES: core::cell::Cell<(usize, usize)> = core::cell::Cell::new((0, 0));
+}
+
+/// Returns the number of full and single page flushes since the last call on this thread.
+pub fn take_flushes() -> (usize, usize) {
+    FLUSHES.with(|flushes| flushes.replace((0, 0)))
+}
+
+impl PageParam for TestParam {
+    const FLAG_PTE_READABLE: Flag = PageParamSv39::FLAG_PTE_READABLE;
+    const FLAG_PTE_WRITEABLE: Flag = PageParamSv39::FLAG_PTE_WRITEABLE;
+    const FLAG_PTE_EXECUTABLE: Flag = PageParamSv39::FLAG_PTE_EXECUTABLE;
+    const FLAG_PTE_ACCESSED: Flag = PageParamSv39::FLAG_PTE_ACCESSED;
+    const FLAG_PTE_DIRTY: Flag = PageParamSv39::FLAG_PTE_DIRTY;
+    const FLAG_PTE_VALID: Flag = PageParamSv39::FLAG_PTE_VALID;
+    const FLAG_PTE_SWAPPED: Flag = PageParamSv39::FLAG_PTE_SWAPPED;
+    const FLAG_PTE_NOACCESS: Flag = PageParamSv39::FLAG_PTE_NOACCESS;
+    const PAGE_LEVELS: usize = PageParamSv39::PAGE_LEVELS;
+    const PAGE_SIZE_SHIFT: usize = PageParamSv39::PAGE_SIZE_SHIFT;
+    const PTE_COUNT: usize = PageParamSv39::PTE_COUNT;
+    const LINEAR_MAPPING_PHYS_OFFSET: usize = 0;
+
+    unsafe fn flush_tlb(_asid: Option<usize>, addr: Option<VirtualAddress>) {
+        FLUSHES.with(|flushes| {
+            let (all, pages) = flushes.get();
+            flushes.set(match addr {
+                Some(_) => (all, pages + 1),
+                None => (all + 1, pages),
+            });
+        });
+    }
+
+    unsafe fn activate_root_table(_root_table_addr: PhysicalAddress, _asid: Option<usize>) {}
+
+    fn create_pte(addr: PhysicalAddress, flags: Flag) -> usize {
+        PageParamSv39::create_pte(addr, flags)
+    }
+
+    fn create_nonleaf_pte(addr: PhysicalAddress) -> usize {
+        PageParamSv39::create_nonleaf_pte(addr)
+    }
+
+    fn flag_set_user(flags: Flag) -> Flag {
+        PageParamSv39::flag_set_user(flags)
+    }
+
+    fn flag_set_kernel(flags: Flag) -> Flag {
+        PageParamSv39::flag_set_kernel(flags)
+    }
+
+    fn pte_is_kernel(pte: usize) -> bool {
+        PageParamSv39::pte_is_kernel(pte)
+    }
+
+    fn pte_address(pte: usize) -> PhysicalAddress {
+        PageParamSv39::pte_address(pte)
+    }
+
+    fn pte_has_next_table(pte: usize) -> bool {
+        PageParamSv39::pte_has_next_table(pte)
+    }
+
+    fn pte_idxs(va: VirtualAddress) -> [usize; Self::PAGE_LEVELS] {
+        PageParamSv39::pte_idxs(va)
+    }
+
+    fn pte_flags(pte: usize) -> Flag {
+        PageParamSv39::pte_flags(pte)
+    }
+}
+
+/// Hands out the frames of a zeroed host buffer, in any order.
+pub struct FrameList {
+    free: Vec<Frame>,
+    free_count: Arc<AtomicUsize>,
+}
+
+impl FrameList {
+    pub fn new(frames: usize) -> Self {
+        let size = TestParam::PAGE_SIZE;
+        let layout = Layout::from_size_align(frames * size, size).unwrap();
+        let base = unsafe { alloc_zeroed(layout) } as usize;
+        assert_ne!(base, 0);
+        Self {
+            free: (0..frames)
+                .rev()
+                .map(|i| Frame::of_addr(PhysicalAddress(base + i * size)))
+                .collect(),
+            free_count: Arc::new(AtomicUsize::new(frames)),
+        }
+    }
+
+    /// A counter of the frames not handed out, which stays readable
+    /// after the list moved into a `LockedAllocator`.
+    pub fn free_count(&self) -> Arc<AtomicUsize> {
+        self.free_count.clone()
+    }
+}
+
+impl Allocator for FrameList {
+    fn alloc(&mut self) -> Option<Frame> {
+        let frame = self.free.pop()?;
+        unsafe {
+            core::ptr::write_bytes(frame.start().0 as *mut u8, 0, TestParam::PAGE_SIZE);
+        }
+        self.free_count.fetch_sub(1, Ordering::SeqCst);
+        Some(frame)
+    }
+
+    fn alloc_consecutive(&mut self, n: usize) -> Vec<Frame> {
+        (0..n).map_while(|_| self.alloc()).collect()
+    }
+
+    fn dealloc(&mut self, frame: &Frame) -> bool {
+        assert!(
+            !self.free.contains(frame),
+            "double free of {}",
+            frame.start()
+        );
+        self.free.push(frame.clone());
+        self.free_count.fetch_add(1, Ordering::SeqCst);
+        true
+    }
+}
+
+/// Returns a locked allocator over `frames` frames and the counter of its free frames.
+pub fn test_allocator(frames: usize) -> (TestAllocator, Arc<AtomicUsize>) {
+    let list = FrameList::new(frames);
+    let free_count = list.free_count();
+    (LockedAllocator::new(list), free_count)
+}
+
+/// Returns the `TestParam::PAGE_SIZE` bytes of the frame at `addr`.
+pub fn frame_bytes(addr: PhysicalAddress) -> &'static mut [u8] {
+    unsafe { core::slice::from_raw_parts_mut(addr.0 as *mut u8, TestParam::PAGE_SIZE) }
+}
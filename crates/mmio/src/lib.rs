@@ -0,0 +1,187 @@
+//! Accessors for memory mapped device registers.
+//!
+//! Every access is volatile, so the compiler neither elides nor merges it, and two
+//! accesses through these accessors keep their program order. A compiler fence is
+//! placed on each side of the access, so plain memory accesses, such as the filling
+//! of a buffer the device is about to read, are not moved across it either.
+//!
+//! A [`Mmio`] window knows its length, an access to a register that does not lie
+//! within it, or is misaligned, panics instead of touching unrelated memory.
+
+#![no_std]
+
+use core::{
+    marker::PhantomData,
+    mem, ptr,
+    sync::atomic::{compiler_fence, Ordering},
+};
+
+use mm::{Addr, VirtualAddress};
+
+/// A register of type `T` at `offset` bytes from the base of its device.
+pub struct Reg<T> {
+    offset: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Reg<T> {
+    pub const fn new(offset: usize) -> Self {
+        Self {
+            offset,
+            _marker: PhantomData,
+        }
+    }
+
+    pub const fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl<T> Clone for Reg<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.offset)
+    }
+}
+
+impl<T> Copy for Reg<T> {}
+
+/// The register window of a memory mapped device.
+#[derive(Clone, Copy)]
+pub struct Mmio {
+    base: VirtualAddress,
+    len: usize,
+}
+
+impl Mmio {
+    /// # Safety
+    /// The `len` bytes at `base` must be the mapped registers of a device.
+    pub const unsafe fn new(base: VirtualAddress, len: usize) -> Self {
+        Self { base, len }
+    }
+
+    pub fn base(&self) -> VirtualAddress {
+        self.base
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the window that starts `offset` bytes into this one and ends with it,
+    /// such as the registers of one context of the device.
+    /// Panics if `offset` is past the end of this window.
+    pub fn at(&self, offset: usize) -> Self {
+        assert!(
+            offset <= self.len,
+            "mmio: offset {:#x} out of a {:#x} bytes window",
+            offset,
+            self.len
+        );
+        Self {
+            base: self.base.add(offset),
+            len: self.len - offset,
+        }
+    }
+
+    pub fn read<T: Copy>(&self, reg: Reg<T>) -> T {
+        compiler_fence(Ordering::SeqCst);
+        let val = unsafe { ptr::read_volatile(self.ptr(reg)) };
+        compiler_fence(Ordering::SeqCst);
+        val
+    }
+
+    pub fn write<T: Copy>(&self, reg: Reg<T>, val: T) {
+        compiler_fence(Ordering::SeqCst);
+        unsafe { ptr::write_volatile(self.ptr(reg), val) };
+        compiler_fence(Ordering::SeqCst);
+    }
+
+    /// Reads the register, and writes back the value returned by `f`.
+    /// The read and the write are not atomic with respect to the device.
+    pub fn modify<T: Copy>(&self, reg: Reg<T>, f: impl FnOnce(T) -> T) {
+        let val = self.read(reg);
+        self.write(reg, f(val));
+    }
+
+    /// Sets the bits of `mask` in the register.
+    pub fn set_bits(&self, reg: Reg<u32>, mask: u32) {
+        self.modify(reg, |val| val | mask);
+    }
+
+    /// Clears the bits of `mask` in the register.
+    pub fn clear_bits(&self, reg: Reg<u32>, mask: u32) {
+        self.modify(reg, |val| val & !mask);
+    }
+
+    fn ptr<T>(&self, reg: Reg<T>) -> *mut T {
+        let in_window = matches!(
+            reg.offset.checked_add(mem::size_of::<T>()),
+            Some(end) if end <= self.len
+        );
+        assert!(
+            in_window,
+            "mmio: register at {:#x} out of a {:#x} bytes window",
+            reg.offset, self.len
+        );
+        let addr = self.base.add(reg.offset);
+        assert!(
+            addr.0 % mem::align_of::<T>() == 0,
+            "mmio: misaligned register at {:#x}",
+            reg.offset
+        );
+        addr.as_mut_ptr()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mm::VirtualAddress;
+
+    use super::{Mmio, Reg};
+
+    const REG0: Reg<u32> = Reg::new(0);
+    const REG1: Reg<u32> = Reg::new(4);
+    const BYTE5: Reg<u8> = Reg::new(5);
+
+    fn window(regs: &mut [u32]) -> Mmio {
+        unsafe { Mmio::new(VirtualAddress(regs.as_mut_ptr() as usize), regs.len() * 4) }
+    }
+
+    #[test]
+    fn accesses_the_registers_of_the_window() {
+        let mut regs = [0u32; 4];
+        let mmio = window(&mut regs);
+        mmio.write(REG1, 0x1234_5678);
+        mmio.set_bits(REG0, 0b101);
+        mmio.clear_bits(REG0, 0b001);
+        assert_eq!(mmio.read(REG1), 0x1234_5678);
+        assert_eq!(mmio.read(BYTE5), 0x56);
+        assert_eq!(mmio.at(4).read(REG0), 0x1234_5678);
+        assert_eq!(regs, [0b100, 0x1234_5678, 0, 0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of a 0x10 bytes window")]
+    fn register_past_the_end_panics() {
+        let mut regs = [0u32; 4];
+        window(&mut regs).read(Reg::<u32>::new(16));
+    }
+
+    #[test]
+    #[should_panic(expected = "out of a 0x8 bytes window")]
+    fn register_straddling_the_end_of_a_sub_window_panics() {
+        let mut regs = [0u32; 4];
+        window(&mut regs).at(8).write(Reg::<u64>::new(4), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "misaligned")]
+    fn misaligned_register_panics() {
+        let mut regs = [0u32; 4];
+        window(&mut regs).read(Reg::<u32>::new(2));
+    }
+}
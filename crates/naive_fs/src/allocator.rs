@@ -6,7 +6,7 @@ use crate::{
     BlkId, Result,
 };
 
-use core::future;
+use core::{future, mem};
 
 pub(crate) struct Allocator {
     bitmap: MaybeDirty<Bitmap>,
@@ -74,10 +74,38 @@ impl Allocator {
         old
     }
 
+    /// Recomputes the number of free ids from the bitmap.
+    /// Returns the previously recorded count if it disagreed with the bitmap.
+    pub fn repair_free(&mut self) -> Option<u16> {
+        let used = self.bitmap.count_ones().min(self.capacity as u32) as u16;
+        let free = self.capacity - used;
+        if free == self.free {
+            None
+        } else {
+            Some(mem::replace(&mut self.free, free))
+        }
+    }
+
+    /// Extends the allocator to `capacity` ids, the ids in use stay allocated.
+    pub fn grow(&mut self, capacity: u16) {
+        debug_assert!(capacity >= self.capacity);
+        self.bitmap.grow(capacity as u32);
+        self.free += capacity - self.capacity;
+        self.capacity = capacity;
+    }
+
+    pub fn capacity(&self) -> u16 {
+        self.capacity
+    }
+
     pub fn free(&self) -> u16 {
         self.free
     }
 
+    pub fn bitmap(&self) -> &MaybeDirty<Bitmap> {
+        &self.bitmap
+    }
+
     pub fn bitmap_blk_id(&self) -> BlkId {
         self.bitmap.addr.blk_id
     }
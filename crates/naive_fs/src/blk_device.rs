@@ -1,13 +1,14 @@
-use crate::{div_round_up, Addr, BlkSize, Error, Result};
+use crate::{div_round_up, Addr, BlkId, BlkSize, Error, Result};
 use alloc::{boxed::Box, vec::Vec};
 use core::{
     any::Any,
     future::{ready, Future, Ready},
     mem, slice,
+    sync::atomic::{AtomicBool, Ordering},
 };
 use future_ext::{WithArg1, WithArg1Ext};
 use futures_util::{
-    future::{Either, Map, MapErr, MapOk},
+    future::{BoxFuture, Either, Map, MapErr, MapOk},
     FutureExt, TryFutureExt,
 };
 
@@ -46,7 +47,19 @@ pub trait Disk: 'static {
 
     fn sync(&self) -> Self::SyncFut<'_>;
 
+    /// Tells the disk that `len` bytes at `offset` are no longer used, so it can reclaim them.
+    /// Disks that can not reclaim space need not override this.
+    fn discard(&self, _offset: u32, _len: u32) -> BoxFuture<'_, DiskResult<()>> {
+        Box::pin(ready(Ok(())))
+    }
+
     fn capacity(&self) -> u32;
+
+    /// Re-reads the capacity of a disk that may have been resized, and returns it.
+    /// Disks that do not cache their capacity need not override this.
+    fn refresh_capacity(&self) -> u32 {
+        self.capacity()
+    }
 }
 
 pub(crate) async fn read_val_at<DK: Disk, T: FromBytes>(disk: &DK, offset: u32) -> DiskResult<T> {
@@ -55,6 +68,19 @@ pub(crate) async fn read_val_at<DK: Disk, T: FromBytes>(disk: &DK, offset: u32)
     Ok(T::from_bytes(&bytes).unwrap())
 }
 
+/// Returns the bytes of the items of `slice` laid out one after another.
+pub(crate) fn slice_to_bytes<T: ToBytes>(slice: &[T]) -> Vec<u8> {
+    let item_byte_len = match slice.first() {
+        Some(item) => item.bytes_len(),
+        None => return Vec::new(),
+    };
+    let mut bytes_buf = vec![0; slice.len() * item_byte_len];
+    for (item, out) in slice.iter().zip(bytes_buf.chunks_exact_mut(item_byte_len)) {
+        item.to_bytes(out);
+    }
+    bytes_buf
+}
+
 pub type ReadAtFut<'a, DK> = MapErr<<DK as Disk>::ReadAtFut<'a>, fn(DiskError) -> Error>;
 
 pub type ReadValAtFut<'a, T, DK> =
@@ -73,7 +99,7 @@ pub type ReadBytesFut<'a, DK> =
 pub struct BlkDevice<DK> {
     disk: DK,
     pub blk_size: BlkSize,
-    read_only: bool,
+    read_only: AtomicBool,
 }
 
 impl<DK: Disk> BlkDevice<DK> {
@@ -81,7 +107,7 @@ impl<DK: Disk> BlkDevice<DK> {
         Self {
             disk,
             blk_size,
-            read_only,
+            read_only: AtomicBool::new(read_only),
         }
     }
 
@@ -151,7 +177,7 @@ impl<DK: Disk> BlkDevice<DK> {
             blk_size,
             read_only,
         } = self;
-        if *read_only {
+        if read_only.load(Ordering::Acquire) {
             return Either::Left(ready(Err(Error::ReadOnly)));
         }
         Either::Right(
@@ -183,18 +209,7 @@ impl<DK: Disk> BlkDevice<DK> {
         WithArg1<Either<Ready<Result<u32>>, WriteAtFut<'a, DK>>, Vec<u8>>,
         fn((Result<u32>, Vec<u8>)) -> Result<u32>,
     > {
-        let mut bytes_buf = if slice.is_empty() {
-            Vec::new()
-        } else {
-            let item_byte_len = slice[0].bytes_len();
-            let mut bytes_buf = vec![0; slice.len() * item_byte_len];
-            let mut offset = 0;
-            for item in slice {
-                item.to_bytes(&mut bytes_buf[offset..offset + item_byte_len]);
-                offset += item_byte_len;
-            }
-            bytes_buf
-        };
+        let mut bytes_buf = slice_to_bytes(slice);
 
         self.write_at(addr, unsafe {
             slice::from_raw_parts_mut(bytes_buf.as_mut_ptr(), bytes_buf.len())
@@ -203,10 +218,52 @@ impl<DK: Disk> BlkDevice<DK> {
         .map(|(res, _)| res)
     }
 
+    pub fn read_only(&self) -> bool {
+        self.read_only.load(Ordering::Acquire)
+    }
+
+    /// Switches the device to (or from) read-only while it is in use.
+    pub(crate) fn set_read_only(&self, read_only: bool) {
+        self.read_only.store(read_only, Ordering::Release);
+    }
+
     pub fn disk(&self) -> &DK {
         &self.disk
     }
 
+    /// Returns the number of blocks the disk holds now, it may have grown since it was opened.
+    pub fn refresh_capacity(&self) -> u32 {
+        self.blk_size.div_by(self.disk.refresh_capacity())
+    }
+
+    /// Discards the blocks `blk_ids`, one request per run of contiguous ids.
+    /// A read-only device is left alone.
+    pub async fn discard_blks(&self, mut blk_ids: Vec<BlkId>) -> Result<()> {
+        if self.read_only() {
+            return Ok(());
+        }
+        blk_ids.sort_unstable();
+        blk_ids.dedup();
+        let mut blk_ids = blk_ids.into_iter().filter(|&blk_id| blk_id != 0).peekable();
+        while let Some(start) = blk_ids.next() {
+            let mut cnt = 1;
+            while blk_ids
+                .next_if(|&blk_id| blk_id as u32 == start as u32 + cnt)
+                .is_some()
+            {
+                cnt += 1;
+            }
+            self.disk
+                .discard(
+                    Addr::new(start, 0).abs_offset(self.blk_size),
+                    self.blk_size.mul(cnt),
+                )
+                .await
+                .map_err(Error::DiskError)?;
+        }
+        Ok(())
+    }
+
     pub fn sync(&self) -> MapErr<DK::SyncFut<'_>, fn(DiskError) -> Error> {
         let Self { disk, .. } = self;
         disk.sync().map_err(Error::DiskError)
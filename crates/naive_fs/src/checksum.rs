@@ -0,0 +1,26 @@
+use byte_struct::ByteStruct;
+
+/// Length of the checksum field, the last field of every checksummed structure.
+pub(crate) const CHECKSUM_LEN: usize = 4;
+
+/// CRC32C (Castagnoli) of `bytes`.
+pub(crate) fn crc32c(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| {
+            (crc >> 1) ^ (0x82F6_3B78 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+/// Returns the checksum of the serialized `val`, not counting its own checksum field.
+pub(crate) fn of<T: ByteStruct>(val: &T) -> u32 {
+    let mut bytes = vec![0; T::BYTE_LEN];
+    val.write_bytes(&mut bytes);
+    crc32c(&bytes[..T::BYTE_LEN - CHECKSUM_LEN])
+}
+
+/// Overwrites the trailing checksum field of the serialized structure in `bytes`.
+pub(crate) fn seal(bytes: &mut [u8]) {
+    let (data, checksum) = bytes.split_at_mut(bytes.len() - CHECKSUM_LEN);
+    checksum.copy_from_slice(&crc32c(data).to_le_bytes());
+}
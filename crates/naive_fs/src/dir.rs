@@ -5,9 +5,9 @@ use crate::{
 };
 
 use super::{blk_device::Disk, Error, Result};
-use alloc::{boxed::Box, str, string::String, vec::Vec};
+use alloc::{boxed::Box, string::String, vec::Vec};
 use byte_struct::*;
-use core::{fmt, pin::Pin};
+use core::{convert::TryFrom, fmt, pin::Pin};
 use futures_util::{pin_mut, stream, Stream, StreamExt};
 
 /// RawDirEntry
@@ -88,12 +88,18 @@ where
     /// Append ".", ".." to this directory.
     pub async fn append_dot(&self, parent_inode_id: InodeId) -> Result<()> {
         self.check_dir().await?;
-        let dot_raw_dir_entry =
-            RawDirEntry::new(self.inode_id, ".".as_bytes().into(), FileType::Dir);
+        let dot_raw_dir_entry = RawDirEntry::new(
+            self.inode_id,
+            DirEntryName::try_from(&b"."[..])?,
+            FileType::Dir,
+        );
         self.write(0, &dot_raw_dir_entry).await?;
 
-        let dotdot_raw_dir_entry =
-            RawDirEntry::new(parent_inode_id, "..".as_bytes().into(), FileType::Dir);
+        let dotdot_raw_dir_entry = RawDirEntry::new(
+            parent_inode_id,
+            DirEntryName::try_from(&b".."[..])?,
+            FileType::Dir,
+        );
         self.write(dot_raw_dir_entry.rec_len as u32, &dotdot_raw_dir_entry)
             .await?;
         Ok(())
@@ -127,38 +133,42 @@ where
         let mut dir_entry_stream = self.dir_entry_stream();
         let mut dir_entry_stream_pinned = unsafe { Pin::new_unchecked(&mut dir_entry_stream) };
 
-        // Skip '.' and '..'
-        dir_entry_stream_pinned
+        // Skip '.'. Space freed by `remove` is merged into the preceding entry's
+        // `rec_len`, so '..' itself may have room for a new entry.
+        let (dot_dir_entry, dot_offset) = dir_entry_stream_pinned
             .next()
             .await
             .expect("Expect `.` dir entry.")?;
-        let (_, dotdot_offset) = dir_entry_stream_pinned
-            .next()
-            .await
-            .expect("Expect `..` dir entry.")?;
         let raw_dir_entry_size = RawDirEntry::BYTE_LEN as u16;
-        let mut insert_offset = dotdot_offset + raw_dir_entry_size as u32;
+        let mut insert_offset = dot_offset + dot_dir_entry.rec_len as u32;
 
-        let new_rec_len = loop {
+        let (new_rec_len, shrunk) = loop {
             match dir_entry_stream_pinned.next().await {
                 Some(Ok((mut dir_entry, offset))) => {
                     if dir_entry.rec_len >= raw_dir_entry_size * 2 {
                         // There is enough space in the current dir_entry to store a new dir_entry
                         let origin_rev_len = dir_entry.rec_len;
                         dir_entry.rec_len = raw_dir_entry_size;
-                        self.write(offset, &dir_entry).await?;
                         insert_offset = offset + dir_entry.rec_len as u32;
-                        break origin_rev_len - raw_dir_entry_size;
+                        break (
+                            origin_rev_len - raw_dir_entry_size,
+                            Some((dir_entry, offset)),
+                        );
                     }
                     insert_offset = offset + dir_entry.rec_len as u32;
                 }
                 Some(Err(e)) => return Err(e),
-                None => break raw_dir_entry_size,
+                None => break (raw_dir_entry_size, None),
             }
         };
 
         let raw_dir_entry = RawDirEntry::with_rec_len(inode_id, name, file_type, new_rec_len);
         self.write(insert_offset, &raw_dir_entry).await?;
+        // The new entry is only reachable once the one before it is shrunk, so a crash
+        // in between leaves the directory as it was.
+        if let Some((dir_entry, offset)) = shrunk {
+            self.write(offset, &dir_entry).await?;
+        }
 
         Ok(())
     }
@@ -169,21 +179,22 @@ where
         let dir_entry_stream = self.dir_entry_stream();
         pin_mut!(dir_entry_stream);
 
-        let mut last_dir_entry: Option<RawDirEntry> = None;
+        let mut last_dir_entry: Option<(RawDirEntry, u32)> = None;
 
         loop {
             match dir_entry_stream.next().await {
                 Some(Ok((dir_entry, offset))) => {
                     if dir_entry.name() == name {
-                        // Delete by merging into the previous dir_entry
-                        if let Some(mut last_raw_dir_entry) = last_dir_entry {
+                        // Delete by merging into the previous dir_entry, so that
+                        // a later `append` can reuse the freed slot.
+                        if let Some((mut last_raw_dir_entry, last_offset)) = last_dir_entry {
                             last_raw_dir_entry.rec_len += dir_entry.rec_len;
-                            self.write(offset, &last_raw_dir_entry).await?;
+                            self.write(last_offset, &last_raw_dir_entry).await?;
                             return Ok(Some(dir_entry));
                         }
                     }
 
-                    last_dir_entry = Some(dir_entry);
+                    last_dir_entry = Some((dir_entry, offset));
                 }
                 Some(Err(e)) => return Err(e),
                 None => return Ok(None),
@@ -231,21 +242,28 @@ where
     }
 }
 
+/// Names of the entries added or removed by users, "." and ".." are managed by the filesystem.
+/// The VFS rejects the names no filesystem accepts.
 fn check_dir_entry_name(name: &[u8]) -> Result<()> {
     if name == ".".as_bytes() || name == "..".as_bytes() {
-        Err(Error::InvalidDirEntryName(Box::new(name.into())))
+        Err(Error::InvalidDirEntryName(Box::new(
+            DirEntryName::try_from(name)?,
+        )))
     } else {
         Ok(())
     }
 }
 
+/// Maximum length of a directory entry name, in bytes.
+pub const DIR_ENTRY_NAME_CAP: usize = 255;
+
 pub struct DirEntryName {
-    bytes: [u8; 255],
+    bytes: [u8; DIR_ENTRY_NAME_CAP],
     len: u8,
 }
 
 impl DirEntryName {
-    pub fn new(bytes: [u8; 255], len: u8) -> Self {
+    pub fn new(bytes: [u8; DIR_ENTRY_NAME_CAP], len: u8) -> Self {
         Self { bytes, len }
     }
 
@@ -262,11 +280,17 @@ impl DirEntryName {
     }
 }
 
-impl From<&[u8]> for DirEntryName {
-    fn from(s: &[u8]) -> Self {
-        let mut bytes = [0; 255];
+/// Fails with `NameTooLong` if `s` is longer than `DIR_ENTRY_NAME_CAP`.
+impl TryFrom<&[u8]> for DirEntryName {
+    type Error = Error;
+
+    fn try_from(s: &[u8]) -> Result<Self> {
+        if s.len() > DIR_ENTRY_NAME_CAP {
+            return Err(Error::NameTooLong);
+        }
+        let mut bytes = [0; DIR_ENTRY_NAME_CAP];
         (&mut bytes[..s.len()]).copy_from_slice(s);
-        Self::new(bytes, s.len() as u8)
+        Ok(Self::new(bytes, s.len() as u8))
     }
 }
 
@@ -278,7 +302,7 @@ impl From<DirEntryName> for String {
 
 impl fmt::Debug for DirEntryName {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", str::from_utf8(self.as_slice()).unwrap())
+        write!(f, "{}", String::from_utf8_lossy(self.as_slice()))
     }
 }
 
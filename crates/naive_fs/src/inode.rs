@@ -1,23 +1,30 @@
-use core::{convert::TryInto, iter::once, ops::Range};
+use core::{
+    convert::TryInto,
+    future::{ready, Ready},
+    iter::once,
+    mem,
+    ops::{Deref, DerefMut, Range},
+};
 
 use crate::{
     blk_device::{self, Disk, FromBytes, ToBytes},
-    consts,
+    checksum, consts,
     maybe_dirty::{MaybeDirty, Syncable},
     scoped,
-    super_blk::SuperBlk,
+    super_blk::{Features, SuperBlk},
+    xattr::Xattrs,
     Addr, BlkDevice, BlkId, BlkSize, Error, InodeId, NaiveFs, Result,
 };
 use alloc::{boxed::Box, sync::Arc, vec::Vec};
 use byte_struct::*;
 use futures_util::{
-    future::{BoxFuture, Map},
+    future::{BoxFuture, Either, Map},
     FutureExt,
 };
 
-use future_ext::{WithArg2, WithArg2Ext};
+use future_ext::{WithArg3, WithArg3Ext};
 
-use sleeplock::RwLock;
+use sleeplock::{Mutex, RwLock};
 
 /// RawInode
 #[derive(ByteStruct, Debug)]
@@ -49,14 +56,12 @@ pub struct RawInode {
     /// Direct block that points to the data Block id of this inode.
     pub direct_blks: [BlkId; consts::INODE_DIRECT_BLK_COUNT],
     pub indirect_blk: BlkId,
-}
-
-impl<DK: Disk + Sync> Syncable<DK> for RawInode {
-    type SyncFut<'a> = impl core::future::Future<Output = Result<()>> + 'a;
-
-    fn sync<'a>(&'a self, _blk_device: &'a BlkDevice<DK>) -> Self::SyncFut<'a> {
-        async { Ok(()) }
-    }
+    /// Block holding the extended attributes, 0 if there are none.
+    /// This and the checksum are only stored with `Features::EXT_INODE`.
+    pub xattr_blk: BlkId,
+    /// CRC32C of the fields above, written on every store.
+    /// Only verified if the super block has `Features::CHECKSUM`.
+    pub checksum: u32,
 }
 
 impl FromBytes for RawInode {
@@ -73,6 +78,7 @@ impl FromBytes for RawInode {
 impl ToBytes for RawInode {
     fn to_bytes(&self, out: &mut [u8]) {
         self.write_bytes(out);
+        checksum::seal(&mut out[..Self::BYTE_LEN]);
     }
 
     fn bytes_len(&self) -> usize {
@@ -87,6 +93,10 @@ impl Default for RawInode {
 }
 
 impl RawInode {
+    /// Length of the records of volumes without `Features::EXT_INODE`,
+    /// which end with `indirect_blk`.
+    pub const BASE_LEN: usize = Self::BYTE_LEN - BlkId::BYTES_LEN - checksum::CHECKSUM_LEN;
+
     pub fn new(
         mode: Mode,
         uid: u16,
@@ -106,12 +116,72 @@ impl RawInode {
             links_count: 1,
             direct_blks,
             indirect_blk: 0,
+            xattr_blk: 0,
+            checksum: 0,
         }
     }
 
     pub fn valid(&self) -> bool {
         self.links_count != 0
     }
+
+    pub fn checksum_ok(&self) -> bool {
+        self.checksum == checksum::of(self)
+    }
+}
+
+/// A `RawInode` as stored in the inode table, in records of `len` bytes,
+/// see `SuperBlk::inode_len`.
+pub struct InodeRecord {
+    raw: RawInode,
+    len: u32,
+}
+
+impl InodeRecord {
+    pub fn new(raw: RawInode, len: u32) -> Self {
+        Self { raw, len }
+    }
+
+    /// Reads a record of `bytes.len()` bytes, the fields it does not store are zeroed.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut full = [0; RawInode::BYTE_LEN];
+        full[..bytes.len()].copy_from_slice(bytes);
+        Self::new(RawInode::read_bytes(&full), bytes.len() as u32)
+    }
+}
+
+impl Deref for InodeRecord {
+    type Target = RawInode;
+
+    fn deref(&self) -> &Self::Target {
+        &self.raw
+    }
+}
+
+impl DerefMut for InodeRecord {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.raw
+    }
+}
+
+impl ToBytes for InodeRecord {
+    fn to_bytes(&self, out: &mut [u8]) {
+        let mut full = [0; RawInode::BYTE_LEN];
+        self.raw.to_bytes(&mut full);
+        out[..self.len as usize].copy_from_slice(&full[..self.len as usize]);
+    }
+
+    fn bytes_len(&self) -> usize {
+        self.len as usize
+    }
+}
+
+impl<DK: Disk + Sync> Syncable<DK> for InodeRecord {
+    type SyncFut<'a> = impl core::future::Future<Output = Result<()>> + 'a;
+
+    fn sync<'a>(&'a self, _blk_device: &'a BlkDevice<DK>) -> Self::SyncFut<'a> {
+        async { Ok(()) }
+    }
 }
 
 bitflags! {
@@ -199,17 +269,47 @@ impl Mode {
     }
 }
 
-pub type InodeLoadFut<'a, MutexType, DK> = Map<
-    WithArg2<blk_device::ReadValAtFut<'a, RawInode, DK>, InodeId, &'a Arc<NaiveFs<MutexType, DK>>>,
-    fn(
-        (Result<RawInode>, InodeId, &'a Arc<NaiveFs<MutexType, DK>>),
-    ) -> Result<Option<Inode<MutexType, DK>>>,
+#[allow(clippy::type_complexity)]
+pub type InodeLoadFut<'a, MutexType, DK> = Either<
+    Ready<Result<Option<Inode<MutexType, DK>>>>,
+    Map<
+        WithArg3<blk_device::ReadBytesFut<'a, DK>, InodeId, Addr, &'a Arc<NaiveFs<MutexType, DK>>>,
+        fn(
+            (
+                Result<Vec<u8>>,
+                InodeId,
+                Addr,
+                &'a Arc<NaiveFs<MutexType, DK>>,
+            ),
+        ) -> Result<Option<Inode<MutexType, DK>>>,
+    >,
 >;
 
+/// Most blocks `Inode::read_vectored` and `Inode::write_vectored` stage at once.
+const VECTORED_CHUNK_BLKS: u32 = 16;
+
+/// Returns how many of the buffers of `lens` go in the next IO:
+/// a single one, or as many as fit together in `chunk_len` bytes.
+fn vectored_group_len(lens: impl Iterator<Item = usize>, chunk_len: usize) -> usize {
+    let mut total = 0;
+    let mut count = 0;
+    for len in lens {
+        total += len;
+        if count > 0 && total > chunk_len {
+            break;
+        }
+        count += 1;
+    }
+    count
+}
+
 pub struct Inode<MutexType, DK> {
     pub inode_id: InodeId,
-    pub raw: RwLock<MutexType, MaybeDirty<RawInode>>,
+    pub raw: RwLock<MutexType, MaybeDirty<InodeRecord>>,
     naive_fs: Arc<NaiveFs<MutexType, DK>>,
+    /// Held across a whole write, so that two writers can not both allocate
+    /// the same missing block, readers do not take it.
+    io_lock: Mutex<MutexType, ()>,
 
     direct_blk_len: u32,
 }
@@ -221,7 +321,7 @@ where
 {
     pub(crate) fn new(
         inode_id: InodeId,
-        raw_inode: MaybeDirty<RawInode>,
+        raw_inode: MaybeDirty<InodeRecord>,
         naive_fs: Arc<NaiveFs<MutexType, DK>>,
     ) -> Self {
         Self {
@@ -232,6 +332,7 @@ where
                 .mul(consts::INODE_DIRECT_BLK_COUNT as u32),
             raw: RwLock::new(raw_inode),
             naive_fs,
+            io_lock: Mutex::new(()),
         }
     }
 
@@ -239,23 +340,34 @@ where
         inode_id: InodeId,
         naive_fs: &Arc<NaiveFs<MutexType, DK>>,
     ) -> InodeLoadFut<'_, MutexType, DK> {
-        naive_fs
-            .blk_device
-            .read_val_at::<RawInode>(naive_fs.super_blk.raw_inode_addr(inode_id))
-            .with_arg2(inode_id, naive_fs)
-            .map(|(res, inode_id, naive_fs)| {
-                res.map(|raw| {
-                    if raw.valid() {
-                        Some(Self::new(
-                            inode_id,
-                            MaybeDirty::new(naive_fs.super_blk.raw_inode_addr(inode_id), raw),
-                            naive_fs.clone(),
-                        ))
-                    } else {
-                        None
+        let addr = match naive_fs.super_blk.raw_inode_addr(inode_id) {
+            Ok(addr) => addr,
+            Err(e) => return Either::Left(ready(Err(naive_fs.handle_error(e)))),
+        };
+        Either::Right(
+            naive_fs
+                .blk_device
+                .read_bytes(addr, naive_fs.super_blk.inode_len())
+                .with_arg3(inode_id, addr, naive_fs)
+                .map(|(res, inode_id, addr, naive_fs)| {
+                    let raw = InodeRecord::from_bytes(&res?);
+                    if !raw.valid() {
+                        return Ok(None);
                     }
-                })
-            })
+                    let checksummed = naive_fs
+                        .super_blk
+                        .features
+                        .contains(Features::CHECKSUM | Features::EXT_INODE);
+                    if checksummed && !raw.checksum_ok() {
+                        return Err(naive_fs.handle_error(Error::ChecksumMismatch));
+                    }
+                    Ok(Some(Self::new(
+                        inode_id,
+                        MaybeDirty::new(addr, raw),
+                        naive_fs.clone(),
+                    )))
+                }),
+        )
     }
 
     pub fn naive_fs(&self) -> &Arc<NaiveFs<MutexType, DK>> {
@@ -274,45 +386,66 @@ where
         self.raw.read().await.mode
     }
 
-    #[allow(clippy::type_complexity)]
-    pub fn link(
-        &self,
-    ) -> Map<
-        sleeplock::RwLockWriteFuture<MutexType, MaybeDirty<RawInode>>,
-        fn(sleeplock::RwLockWriteGuard<MutexType, MaybeDirty<RawInode>>) -> (),
-    > {
-        self.raw.write().map(|mut raw| {
-            if raw.valid() {
-                raw.links_count += 1;
-            }
-        })
+    pub async fn link(&self) -> Result<()> {
+        self.naive_fs.check_writable()?;
+        let mut raw = self.raw.write().await;
+        if raw.valid() {
+            raw.links_count += 1;
+        }
+        Ok(())
     }
 
     pub async fn unlink(&self) -> Result<()> {
+        self.naive_fs.check_writable()?;
+        self.unlink_inner()
+            .await
+            .map_err(|e| self.naive_fs.handle_error(e))
+    }
+
+    async fn unlink_inner(&self) -> Result<()> {
+        // A write in progress could otherwise allocate blocks that are never freed.
+        let _io = self.io_lock.lock().await;
         let mut raw_inode = self.raw.write().await;
         raw_inode.links_count -= 1;
 
-        raw_inode.sync(self.blk_device()).await?;
+        self.super_blk()
+            .sync_with_inode(self.blk_device(), Some(&*raw_inode))
+            .await?;
         if raw_inode.links_count != 0 {
             return Ok(());
         }
 
-        let io_blks = self.io_blks::<false>(0, raw_inode.size).await?;
-
+        let (size, indirect_blk, xattr_blk) =
+            (raw_inode.size, raw_inode.indirect_blk, raw_inode.xattr_blk);
+        // `io_blks` reads the inode, the io lock keeps the blocks from changing meanwhile.
+        drop(raw_inode);
+
+        let io_blks = self.io_blks::<false>(0, size).await?;
+        let blk_ids: Vec<BlkId> = io_blks
+            .iter()
+            .map(|blk| blk.addr.blk_id)
+            .chain(once(indirect_blk))
+            .chain(once(xattr_blk))
+            .collect();
+
+        // Discarded before they are freed, a block reallocated meanwhile would lose its new data.
+        // Reclaiming the space is best effort, the blocks are freed either way.
+        let _ = self.blk_device().discard_blks(blk_ids.clone()).await;
         self.super_blk()
-            .try_dealloc_n_blks(
-                io_blks
-                    .iter()
-                    .map(|blk| blk.addr.blk_id)
-                    .chain(once(raw_inode.indirect_blk)),
-            )
+            .try_dealloc_n_blks(blk_ids.into_iter())
             .await;
 
         self.super_blk().dealloc_inode(self.inode_id).await;
         Ok(())
     }
 
-    pub async fn read_at(&self, offset: u32, mut buf: &mut [u8]) -> Result<u32> {
+    pub async fn read_at(&self, offset: u32, buf: &mut [u8]) -> Result<u32> {
+        self.read_at_inner(offset, buf)
+            .await
+            .map_err(|e| self.naive_fs.handle_error(e))
+    }
+
+    async fn read_at_inner(&self, offset: u32, mut buf: &mut [u8]) -> Result<u32> {
         let inode_size = self.raw.read().await.size;
         if offset >= inode_size {
             return Ok(0);
@@ -329,14 +462,16 @@ where
 
         let mut read_offset = 0;
         let mut read_len = 0;
-        for blk in io_blks.iter() {
-            let next_offset = read_offset + blk.len(blk_device.blk_size);
-            read_len += blk_device
-                .read_at(
-                    blk.addr,
-                    &mut buf[read_offset as usize..next_offset as usize],
-                )
-                .await?;
+        for (addr, len) in io_blks.runs(blk_device.blk_size) {
+            let next_offset = read_offset + len;
+            let run_buf = &mut buf[read_offset as usize..next_offset as usize];
+            read_len += if addr.blk_id == 0 {
+                // A hole, never written, reads as zeros.
+                run_buf.fill(0);
+                len
+            } else {
+                blk_device.read_at(addr, run_buf).await?
+            };
             read_offset = next_offset;
         }
 
@@ -354,16 +489,32 @@ where
     }
 
     pub async fn write_at(&self, offset: u32, buf: &[u8]) -> Result<u32> {
+        self.naive_fs.check_writable()?;
+        self.write_at_inner(offset, buf)
+            .await
+            .map_err(|e| self.naive_fs.handle_error(e))
+    }
+
+    async fn write_at_inner(&self, offset: u32, buf: &[u8]) -> Result<u32> {
+        let _io = self.io_lock.lock().await;
         let blk_device = scoped!(self.blk_device());
 
+        // The entries of a directory are metadata, with a journal they are written
+        // in one transaction with the inode rather than straight to their blocks.
+        let journal_blks = self.super_blk().journal.is_some() && self.mode().await.is_dir();
         let io_blks = self.io_blks::<true>(offset, buf.len() as u32).await?;
+        let mut blk_writes = Vec::new();
         let mut write_offset = 0;
         let mut write_len = 0;
-        for blk in io_blks.iter() {
-            let next_offset = write_offset + blk.len(blk_device.blk_size);
-            write_len += blk_device
-                .write_at(blk.addr, &buf[write_offset as usize..next_offset as usize])
-                .await?;
+        for (addr, len) in io_blks.runs(blk_device.blk_size) {
+            let next_offset = write_offset + len;
+            let run_buf = &buf[write_offset as usize..next_offset as usize];
+            write_len += if journal_blks {
+                blk_writes.push((addr, run_buf.to_vec()));
+                len
+            } else {
+                blk_device.write_at(addr, run_buf).await?
+            };
             write_offset = next_offset;
         }
 
@@ -371,9 +522,75 @@ where
         if offset + write_len > raw.size {
             raw.size = offset + write_len;
         }
+        if !blk_writes.is_empty() {
+            self.super_blk()
+                .sync_blks_with_inode(blk_device, &raw, blk_writes)
+                .await?;
+        }
         Ok(write_len)
     }
 
+    /// Fill `bufs` one after another from `offset`. Small buffers are read together
+    /// through a staging buffer of at most `VECTORED_CHUNK_BLKS` blocks,
+    /// so blocks spanning several of them are read once.
+    pub async fn read_vectored(&self, offset: u32, mut bufs: &mut [&mut [u8]]) -> Result<u32> {
+        let chunk_len = self.vectored_chunk_len();
+        let mut read_size = 0;
+        while !bufs.is_empty() {
+            let count = vectored_group_len(bufs.iter().map(|buf| buf.len()), chunk_len);
+            let (group, rest) = mem::take(&mut bufs).split_at_mut(count);
+            bufs = rest;
+
+            let group_len = group.iter().map(|buf| buf.len()).sum::<usize>();
+            let len = if let [buf] = group {
+                self.read_at(offset + read_size, buf).await?
+            } else {
+                let mut chunk = vec![0; group_len];
+                let len = self.read_at(offset + read_size, &mut chunk).await?;
+                let mut src = &chunk[..len as usize];
+                for dst in group.iter_mut() {
+                    let n = dst.len().min(src.len());
+                    dst[..n].copy_from_slice(&src[..n]);
+                    src = &src[n..];
+                }
+                len
+            };
+            read_size += len;
+            if (len as usize) < group_len {
+                break;
+            }
+        }
+        Ok(read_size)
+    }
+
+    /// Write `srcs` one after another from `offset`. Small buffers are written together
+    /// through a staging buffer of at most `VECTORED_CHUNK_BLKS` blocks,
+    /// so blocks spanning several of them are written once.
+    pub async fn write_vectored(&self, offset: u32, mut srcs: &[&[u8]]) -> Result<u32> {
+        let chunk_len = self.vectored_chunk_len();
+        let mut written = 0;
+        while !srcs.is_empty() {
+            let count = vectored_group_len(srcs.iter().map(|src| src.len()), chunk_len);
+            let (group, rest) = srcs.split_at(count);
+            srcs = rest;
+
+            let group_len = group.iter().map(|src| src.len()).sum::<usize>();
+            let len = match group {
+                [src] => self.write_at(offset + written, src).await?,
+                _ => self.write_at(offset + written, &group.concat()).await?,
+            };
+            written += len;
+            if (len as usize) < group_len {
+                break;
+            }
+        }
+        Ok(written)
+    }
+
+    fn vectored_chunk_len(&self) -> usize {
+        self.blk_device().blk_size.mul(VECTORED_CHUNK_BLKS) as usize
+    }
+
     pub async fn write<T: ToBytes>(&self, offset: u32, val: &T) -> Result<()> {
         let mut buf = vec![0; val.bytes_len()];
         val.to_bytes(&mut buf);
@@ -381,13 +598,130 @@ where
         Ok(())
     }
 
+    /// Returns the value of the extended attribute `name`.
+    pub async fn getxattr(&self, name: &[u8]) -> Result<Option<Vec<u8>>> {
+        let xattr_blk = self.raw.read().await.xattr_blk;
+        let xattrs = self
+            .read_xattrs(xattr_blk)
+            .await
+            .map_err(|e| self.naive_fs.handle_error(e))?;
+        Ok(xattrs.get(name).cloned())
+    }
+
+    /// Returns the names of all extended attributes.
+    pub async fn listxattr(&self) -> Result<Vec<Vec<u8>>> {
+        let xattr_blk = self.raw.read().await.xattr_blk;
+        let xattrs = self
+            .read_xattrs(xattr_blk)
+            .await
+            .map_err(|e| self.naive_fs.handle_error(e))?;
+        Ok(xattrs.names())
+    }
+
+    /// Create or replace the extended attribute `name`.
+    /// All extended attributes of an inode must fit in one block,
+    /// and the volume must have `Features::EXT_INODE` to store them.
+    pub async fn setxattr(&self, name: &[u8], value: &[u8]) -> Result<()> {
+        self.naive_fs.check_writable()?;
+        if !self.super_blk().features.contains(Features::EXT_INODE) {
+            return Err(Error::Unsupported);
+        }
+        if name.len() > u8::MAX as usize || value.len() > u16::MAX as usize {
+            return Err(Error::NoSpace);
+        }
+        self.update_xattrs(|xattrs| {
+            xattrs.insert(name.to_vec(), value.to_vec());
+            true
+        })
+        .await
+        .map_err(|e| self.naive_fs.handle_error(e))
+    }
+
+    /// Remove the extended attribute `name`, returns its value.
+    pub async fn removexattr(&self, name: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.naive_fs.check_writable()?;
+        let mut removed = None;
+        self.update_xattrs(|xattrs| {
+            removed = xattrs.remove(name);
+            removed.is_some()
+        })
+        .await
+        .map_err(|e| self.naive_fs.handle_error(e))?;
+        Ok(removed)
+    }
+
+    async fn read_xattrs(&self, xattr_blk: BlkId) -> Result<Xattrs> {
+        if xattr_blk == 0 {
+            return Ok(Xattrs::default());
+        }
+        let bytes = self
+            .blk_device()
+            .read_bytes(Addr::new(xattr_blk, 0), self.naive_fs.blk_size())
+            .await?;
+        Ok(Xattrs::from_bytes(&bytes))
+    }
+
+    /// Apply `f` to the extended attributes and write them back if it returns true.
+    /// The xattr block is allocated on the first attribute and freed with the last one.
+    async fn update_xattrs(&self, f: impl FnOnce(&mut Xattrs) -> bool) -> Result<()> {
+        let mut raw = self.raw.write().await;
+        let mut xattrs = self.read_xattrs(raw.xattr_blk).await?;
+        if !f(&mut xattrs) {
+            return Ok(());
+        }
+
+        if xattrs.is_empty() {
+            let _ = self.blk_device().discard_blks(vec![raw.xattr_blk]).await;
+            self.super_blk().dealloc_blk(raw.xattr_blk).await;
+            raw.xattr_blk = 0;
+            return Ok(());
+        }
+        if xattrs.bytes_len() > self.naive_fs.blk_size() as usize {
+            return Err(Error::NoSpace);
+        }
+        if raw.xattr_blk == 0 {
+            raw.xattr_blk = self.super_blk().alloc_blk().await.ok_or(Error::NoSpace)?;
+        }
+        self.blk_device()
+            .write_at(Addr::new(raw.xattr_blk, 0), &xattrs.to_bytes())
+            .await?;
+        Ok(())
+    }
+
+    /// Allocate the missing (zero) entries of `blk_ids` in one batch,
+    /// so a large write gets adjacent blocks where free space allows.
+    /// Returns whether any block was allocated, allocates none if not all of them fit.
+    async fn alloc_missing_blks(&self, blk_ids: &mut [BlkId]) -> Result<bool> {
+        let missing = blk_ids.iter().filter(|blk_id| **blk_id == 0).count();
+        if missing == 0 {
+            return Ok(false);
+        }
+        let alloced = self.super_blk().try_alloc_n_blks(missing as u16).await;
+        if alloced.len() < missing {
+            self.super_blk()
+                .try_dealloc_n_blks(alloced.into_iter())
+                .await;
+            return Err(Error::NoSpace);
+        }
+        blk_ids
+            .iter_mut()
+            .filter(|blk_id| **blk_id == 0)
+            .zip(alloced)
+            .for_each(|(blk_id, alloced)| *blk_id = alloced);
+        Ok(true)
+    }
+
     async fn io_blks<const OR_ALLOC: bool>(&self, offset: u32, len: u32) -> Result<IoBlks> {
+        // The offsets of the indirect blocks start after the direct blocks.
         if offset >= self.direct_blk_len {
             Ok(IoBlks {
                 direct_blks: None,
-                indirect_blks: Some(self.find_in_indirect_blks::<OR_ALLOC>(offset, len).await?),
+                indirect_blks: Some(
+                    self.find_in_indirect_blks::<OR_ALLOC>(offset - self.direct_blk_len, len)
+                        .await?,
+                ),
             })
-        } else if offset + len < self.direct_blk_len {
+        } else if offset + len <= self.direct_blk_len {
             Ok(IoBlks {
                 direct_blks: Some(self.find_in_direct_blks::<OR_ALLOC>(offset, len).await?),
                 indirect_blks: None,
@@ -426,29 +760,15 @@ where
                 blks: direct_blks,
                 blks_slice_range: (nth_blk as usize..(nth_blk + n_blks) as usize),
                 first_blk_offset,
-                last_blk_len: if n_blks == 1 {
-                    LenOfBlk::Len(len)
-                } else {
-                    LenOfBlk::Len(blk_size.mod_by(offset + len))
-                },
+                last_blk_len: LenOfBlk::of_last_blk(blk_size, offset, len, n_blks),
             }
         };
 
         if OR_ALLOC {
-            let mut alloced = false;
-
-            for blk_id in &mut direct_blks.blks[direct_blks.blks_slice_range.clone()] {
-                if *blk_id == 0 {
-                    *blk_id = self
-                        .naive_fs()
-                        .super_blk
-                        .alloc_blk()
-                        .await
-                        .ok_or(Error::NoSpace)?;
-                    alloced = true;
-                }
-            }
-
+            let range = direct_blks.blks_slice_range.clone();
+            let alloced = self
+                .alloc_missing_blks(&mut direct_blks.blks[range])
+                .await?;
             if alloced {
                 self.raw.write().await.direct_blks = direct_blks.blks;
             }
@@ -462,6 +782,16 @@ where
         offset: u32,
         len: u32,
     ) -> Result<IndirectBlks> {
+        let blk_device = scoped!(self.blk_device());
+        let blk_size = blk_device.blk_size;
+
+        let nth_blk = blk_size.div_by(offset);
+        let first_blk_offset = blk_size.mod_by(offset);
+        let n_blks = blk_size
+            .div_round_up_by(first_blk_offset + len)
+            .min(self.super_blk().blk_ids_count_pre_blk - nth_blk);
+        let last_blk_len = LenOfBlk::of_last_blk(blk_size, offset, len, n_blks);
+
         let mut indirect_blk = self.raw.read().await.indirect_blk;
         if indirect_blk == 0 {
             if OR_ALLOC {
@@ -473,19 +803,15 @@ where
                     .ok_or(Error::NoSpace)?;
                 self.raw.write().await.indirect_blk = indirect_blk;
             } else {
-                return Ok(IndirectBlks::empty());
+                // None of the blocks was written, they are all holes.
+                return Ok(IndirectBlks {
+                    blks: vec![0; n_blks as usize],
+                    first_blk_offset,
+                    last_blk_len,
+                });
             }
         }
 
-        let blk_device = scoped!(self.blk_device());
-        let blk_size = blk_device.blk_size;
-
-        let nth_blk = blk_size.div_by(offset);
-        let first_blk_offset = blk_size.mod_by(offset);
-        let n_blks = blk_size
-            .div_round_up_by(first_blk_offset + len)
-            .min(self.super_blk().blk_ids_count_pre_blk - nth_blk);
-
         let mut indirect_blks: Vec<BlkId> = blk_device
             .read_vec(
                 Addr::new(indirect_blk, nth_blk * BlkId::BYTES_LEN as u32),
@@ -495,37 +821,26 @@ where
 
         indirect_blks.resize(n_blks as usize, 0);
 
-        if OR_ALLOC {
-            let mut alloced = false;
-            for blk_id in indirect_blks.iter_mut() {
-                if *blk_id == 0 {
-                    *blk_id = self
-                        .naive_fs()
-                        .super_blk
-                        .alloc_blk()
-                        .await
-                        .ok_or(Error::NoSpace)?;
-                    alloced = true;
-                }
-            }
-            if alloced {
-                blk_device
-                    .write_slice(
-                        Addr::new(indirect_blk, nth_blk * BlkId::BYTES_LEN as u32),
-                        &indirect_blks,
-                    )
+        // The indirect block is written once for all the blocks allocated.
+        if OR_ALLOC && self.alloc_missing_blks(&mut indirect_blks).await? {
+            let addr = Addr::new(indirect_blk, nth_blk * BlkId::BYTES_LEN as u32);
+            if self.super_blk().journal.is_some() {
+                // A crash must not leave allocated blocks that the indirect block
+                // does not point to, or an indirect block the inode does not point to.
+                let raw = self.raw.read().await;
+                let writes = vec![(addr, blk_device::slice_to_bytes(&indirect_blks))];
+                self.super_blk()
+                    .sync_blks_with_inode(blk_device, &raw, writes)
                     .await?;
+            } else {
+                blk_device.write_slice(addr, &indirect_blks).await?;
             }
         }
 
         Ok(IndirectBlks {
             blks: indirect_blks,
             first_blk_offset,
-            last_blk_len: if n_blks == 1 {
-                LenOfBlk::Len(len)
-            } else {
-                LenOfBlk::Len(blk_size.mod_by(offset + len))
-            },
+            last_blk_len,
         })
     }
 }
@@ -536,10 +851,10 @@ where
     DK: Disk + Sync + Send,
 {
     pub fn sync(&self) -> BoxFuture<Result<()>> {
-        Box::pin(<Self as Syncable<_>>::sync(
-            self,
-            scoped!(self.blk_device()),
-        ))
+        Box::pin(
+            <Self as Syncable<_>>::sync(self, scoped!(self.blk_device()))
+                .map(|res| res.map_err(|e| self.naive_fs.handle_error(e))),
+        )
     }
 }
 
@@ -555,8 +870,10 @@ where
         let Self { raw, naive_fs, .. } = self;
 
         async move {
-            raw.read().await.sync(blk_device).await?;
-            scoped!(&naive_fs.super_blk).sync(blk_device).await?;
+            let raw = raw.read().await;
+            scoped!(&naive_fs.super_blk)
+                .sync_with_inode(blk_device, Some(&*raw))
+                .await?;
             blk_device.sync().await
         }
     }
@@ -568,6 +885,28 @@ struct IoBlks {
 }
 
 impl IoBlks {
+    /// Returns the blocks merged into runs that are contiguous on the device,
+    /// as (start, len) pairs, so that each run takes a single device request.
+    /// A hole is a run of its own, starting at block 0.
+    fn runs(&self, blk_size: BlkSize) -> Vec<(Addr, u32)> {
+        let mut runs: Vec<(Addr, u32)> = Vec::new();
+        for blk in self.iter() {
+            let len = blk.len(blk_size);
+            if let Some((start, run_len)) = runs.last_mut() {
+                if blk.addr.offset_of_blk == 0
+                    && start.blk_id != 0
+                    && blk.addr.blk_id != 0
+                    && start.abs_offset(blk_size) + *run_len == blk.addr.abs_offset(blk_size)
+                {
+                    *run_len += len;
+                    continue;
+                }
+            }
+            runs.push((blk.addr, len));
+        }
+        runs
+    }
+
     pub fn iter(&self) -> IoBlksIter<'_, '_> {
         IoBlksIter {
             direct_blks_iter: match self.direct_blks {
@@ -642,13 +981,6 @@ struct IndirectBlks {
 }
 
 impl IndirectBlks {
-    pub fn empty() -> Self {
-        Self {
-            blks: Vec::new(),
-            first_blk_offset: 0,
-            last_blk_len: LenOfBlk::End,
-        }
-    }
     pub fn iter(&self) -> BlksRange {
         BlksRange::new(&self.blks, self.first_blk_offset, self.last_blk_len)
     }
@@ -675,6 +1007,21 @@ enum LenOfBlk {
     Len(u32),
 }
 
+impl LenOfBlk {
+    /// Returns the length in the last of the `n_blks` blocks covering `len` bytes at `offset`.
+    fn of_last_blk(blk_size: BlkSize, offset: u32, len: u32, n_blks: u32) -> Self {
+        if n_blks == 1 {
+            return LenOfBlk::Len(len);
+        }
+        match blk_size.mod_by(offset + len) {
+            // The range ends on a block boundary.
+            0 => LenOfBlk::End,
+            last_len => LenOfBlk::Len(last_len),
+        }
+    }
+}
+
+/// The blocks of a range of an inode, a block id of 0 is a hole that was never written.
 struct BlksRange<'a> {
     blks: &'a [BlkId],
 
@@ -708,14 +1055,11 @@ impl Iterator for BlksRange<'_> {
         }
         let last = self.blks.len() - 1;
 
-        self.blks.get(self.idx).and_then(|&blk_id| {
-            if blk_id == 0 {
-                return None;
-            }
+        self.blks.get(self.idx).map(|&blk_id| {
             let idx = self.idx;
             self.idx += 1;
 
-            Some(if idx == 0 {
+            if idx == 0 {
                 Blk {
                     addr: Addr::new(blk_id, self.first_blk_offset),
                     len: if self.blks.len() == 1 {
@@ -734,7 +1078,7 @@ impl Iterator for BlksRange<'_> {
                     addr: Addr::new(blk_id, 0),
                     len: LenOfBlk::End,
                 }
-            })
+            }
         })
     }
 }
@@ -762,16 +1106,26 @@ impl ToBytes for BlkId {
 
 #[cfg(test)]
 mod test {
-    use alloc::{sync::Arc, vec::Vec};
+    use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
+    use bitmap::Bitmap;
+    use core::{
+        convert::TryFrom,
+        future::{ready, Future, Ready},
+        sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+        task::Poll,
+    };
+    use futures_util::future::{join, poll_fn};
     use tokio_test::block_on;
 
     use crate::{
-        blk_device::{self, BlkDevice},
+        blk_device::{self, BlkDevice, Disk, DiskError, DiskResult, FromBytes, ToBytes},
         consts,
-        inode::{Blk, Inode, LenOfBlk, RawInode},
+        dir::{DirEntryName, FileType},
+        inode::{Blk, Inode, InodeRecord, LenOfBlk, Mode, RawInode},
+        journal::RawJournalHeader,
         ram_disk::RamDisk,
-        super_blk::{RawSuperBlk, SuperBlk},
-        Addr, BlkId, BlkSize, MaybeDirty, NaiveFs,
+        super_blk::{Features, OnError, RawSuperBlk, SuperBlk},
+        Addr, BlkId, BlkSize, BoxFuture, Error, MaybeDirty, NaiveFs,
     };
 
     #[test]
@@ -838,15 +1192,22 @@ mod test {
                 BlkSize::<u32>::new(32),
                 65,
                 33,
-                vec![Blk {
-                    addr: Addr::new(3, 1),
-                    len: LenOfBlk::End,
-                }],
+                // The 2 bytes past the last block fall in a hole.
+                vec![
+                    Blk {
+                        addr: Addr::new(3, 1),
+                        len: LenOfBlk::End,
+                    },
+                    Blk {
+                        addr: Addr::new(0, 0),
+                        len: LenOfBlk::Len(2),
+                    },
+                ],
             ),
         ];
 
         for (direct_blks, blk_size, offset, len, expected) in cases {
-            let mut raw_inode = MaybeDirty::new(Addr::new(0, 0), RawInode::default());
+            let mut raw_inode = MaybeDirty::new(Addr::new(0, 0), raw_inode_record());
 
             let mut direct_blks_arr = [0; consts::INODE_DIRECT_BLK_COUNT];
             (&mut direct_blks_arr[..direct_blks.len()]).copy_from_slice(&direct_blks);
@@ -928,15 +1289,22 @@ mod test {
                 BlkSize::<u32>::new(32),
                 65,
                 33,
-                vec![Blk {
-                    addr: Addr::new(3, 1),
-                    len: LenOfBlk::End,
-                }],
+                // The 2 bytes past the last block fall in a hole.
+                vec![
+                    Blk {
+                        addr: Addr::new(3, 1),
+                        len: LenOfBlk::End,
+                    },
+                    Blk {
+                        addr: Addr::new(0, 0),
+                        len: LenOfBlk::Len(2),
+                    },
+                ],
             ),
         ];
 
         for (indirect_blks, blk_size, offset, len, expected) in cases {
-            let mut raw_inode = MaybeDirty::new(Addr::new(0, 0), RawInode::default());
+            let mut raw_inode = MaybeDirty::new(Addr::new(0, 0), raw_inode_record());
             let indirect_blk_id = 99;
             raw_inode.indirect_blk = indirect_blk_id;
             let inode = Inode::new(1, raw_inode, Arc::new(create_naive_fs(blk_size)));
@@ -992,7 +1360,7 @@ mod test {
         ];
 
         for (direct_blks, indirect_blks, blk_size, offset, len, expected) in cases {
-            let mut raw_inode = MaybeDirty::new(Addr::new(0, 0), RawInode::default());
+            let mut raw_inode = MaybeDirty::new(Addr::new(0, 0), raw_inode_record());
             let indirect_blk_id = 99;
             raw_inode.indirect_blk = indirect_blk_id;
 
@@ -1018,6 +1386,858 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_dir_reuses_removed_slots() {
+        let naive_fs = Arc::new(NaiveFs::<spin::Mutex<()>, _>::create_blank(
+            RamDisk::<spin::RwLock<()>>::new(64 * 1024),
+            BlkSize::new(1024),
+            [0; 16],
+            [0; 16],
+        ));
+        let root = block_on(naive_fs.create_root(0)).unwrap();
+        let name = |i: u16| format!("file{}", i);
+        let dir_size = || block_on(root.raw.read()).size;
+
+        for i in 1..=16 {
+            block_on(root.append(i + 1, entry_name(name(i).as_bytes()), FileType::RegFile))
+                .unwrap();
+        }
+        let full_size = dir_size();
+
+        for i in (1..=16).step_by(2) {
+            assert!(block_on(root.remove(name(i).as_bytes())).unwrap().is_some());
+        }
+        assert_eq!(dir_size(), full_size);
+
+        for i in 17..=24 {
+            block_on(root.append(i + 1, entry_name(name(i).as_bytes()), FileType::RegFile))
+                .unwrap();
+        }
+        assert_eq!(dir_size(), full_size);
+
+        let mut names: Vec<_> = block_on(root.ls())
+            .unwrap()
+            .iter()
+            .map(|dir_entry| String::from_utf8(dir_entry.name().to_vec()).unwrap())
+            .collect();
+        names.sort();
+        let mut expected: Vec<_> = (2..=16)
+            .step_by(2)
+            .chain(17..=24)
+            .map(name)
+            .chain([".".into(), "..".into()])
+            .collect();
+        expected.sort();
+        assert_eq!(names, expected);
+
+        for i in 1..=16 {
+            let live = i % 2 == 0;
+            assert_eq!(
+                block_on(root.lookup(name(i).as_bytes())).unwrap().is_some(),
+                live
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_dir_entry_names_are_rejected() {
+        let naive_fs = Arc::new(NaiveFs::<spin::Mutex<()>, _>::create_blank(
+            RamDisk::<spin::RwLock<()>>::new(64 * 1024),
+            BlkSize::new(1024),
+            [0; 16],
+            [0; 16],
+        ));
+        let root = block_on(naive_fs.create_root(0)).unwrap();
+
+        assert!(matches!(
+            DirEntryName::try_from(&[b'a'; 300][..]),
+            Err(Error::NameTooLong)
+        ));
+        assert!(DirEntryName::try_from(&[b'a'; 255][..]).is_ok());
+        for name in [&b"."[..], b".."] {
+            assert!(matches!(
+                block_on(root.append(2, entry_name(name), FileType::RegFile)),
+                Err(Error::InvalidDirEntryName(_))
+            ));
+        }
+        assert_eq!(block_on(root.ls()).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_read_only_mount_rejects_writes() {
+        let disk = SharedDisk::default();
+        let naive_fs = Arc::new(create_blank_naive_fs(disk.clone()));
+        let root = block_on(naive_fs.create_root(0)).unwrap();
+        block_on(root.sync()).unwrap();
+
+        let naive_fs = Arc::new(block_on(NaiveFs::<spin::Mutex<()>, _>::open(disk, true)).unwrap());
+        let root = block_on(naive_fs.load_inode(crate::root_inode_id()))
+            .unwrap()
+            .unwrap();
+
+        assert!(naive_fs.read_only());
+        assert!(matches!(
+            block_on(naive_fs.create_inode(Mode::TY_REG, 0, 0, 0)),
+            Err(Error::ReadOnly)
+        ));
+        assert!(matches!(
+            block_on(root.write_at(0, &[1, 2, 3])),
+            Err(Error::ReadOnly)
+        ));
+        assert!(matches!(block_on(root.link()), Err(Error::ReadOnly)));
+        assert!(matches!(block_on(root.unlink()), Err(Error::ReadOnly)));
+        assert!(matches!(
+            block_on(root.append(3, entry_name(b"file"), FileType::RegFile)),
+            Err(Error::ReadOnly)
+        ));
+        assert!(block_on(root.lookup(".".as_bytes())).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_disk_error_mounts_as_ro() {
+        let disk = SharedDisk::default();
+        let naive_fs = Arc::new(create_blank_naive_fs(disk.clone()));
+        let root = block_on(naive_fs.create_root(0)).unwrap();
+        let file = block_on(naive_fs.create_inode(Mode::TY_REG, 0, 0, 0)).unwrap();
+        block_on(root.sync()).unwrap();
+        block_on(file.sync()).unwrap();
+
+        disk.broken.store(true, Ordering::Release);
+        assert!(matches!(
+            block_on(file.write_at(0, &[1, 2, 3])),
+            Err(Error::DiskError(_))
+        ));
+        assert!(naive_fs.read_only());
+
+        disk.broken.store(false, Ordering::Release);
+        assert!(matches!(
+            block_on(file.write_at(0, &[1, 2, 3])),
+            Err(Error::ReadOnly)
+        ));
+        assert_eq!(block_on(file.read_at(0, &mut [0; 3])).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_xattr_survives_remount() {
+        let disk = SharedDisk::default();
+        let naive_fs = Arc::new(create_blank_naive_fs(disk.clone()));
+        let file = block_on(naive_fs.create_inode(Mode::TY_REG, 0, 0, 0)).unwrap();
+        block_on(file.setxattr(b"user.label", b"secret")).unwrap();
+        block_on(file.sync()).unwrap();
+
+        let naive_fs =
+            Arc::new(block_on(NaiveFs::<spin::Mutex<()>, _>::open(disk, false)).unwrap());
+        let file = block_on(naive_fs.load_inode(file.inode_id))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            block_on(file.getxattr(b"user.label")).unwrap(),
+            Some(b"secret".to_vec())
+        );
+        assert_eq!(block_on(file.getxattr(b"user.missing")).unwrap(), None);
+    }
+
+    #[test]
+    fn test_listxattr() {
+        let naive_fs = Arc::new(create_blank_naive_fs(SharedDisk::default()));
+        let file = block_on(naive_fs.create_inode(Mode::TY_REG, 0, 0, 0)).unwrap();
+        assert!(block_on(file.listxattr()).unwrap().is_empty());
+
+        block_on(file.setxattr(b"user.b", b"2")).unwrap();
+        block_on(file.setxattr(b"user.a", b"1")).unwrap();
+        block_on(file.setxattr(b"user.b", b"3")).unwrap();
+        assert_eq!(
+            block_on(file.listxattr()).unwrap(),
+            vec![b"user.a".to_vec(), b"user.b".to_vec()]
+        );
+        assert_eq!(
+            block_on(file.getxattr(b"user.b")).unwrap(),
+            Some(b"3".to_vec())
+        );
+
+        let too_big = vec![0; naive_fs.blk_size() as usize];
+        assert!(matches!(
+            block_on(file.setxattr(b"user.c", &too_big)),
+            Err(Error::NoSpace)
+        ));
+        block_on(file.sync()).unwrap();
+    }
+
+    #[test]
+    fn test_removexattr() {
+        let naive_fs = Arc::new(create_blank_naive_fs(SharedDisk::default()));
+        let file = block_on(naive_fs.create_inode(Mode::TY_REG, 0, 0, 0)).unwrap();
+        block_on(file.setxattr(b"user.a", b"1")).unwrap();
+        block_on(file.setxattr(b"user.b", b"2")).unwrap();
+
+        assert_eq!(
+            block_on(file.removexattr(b"user.a")).unwrap(),
+            Some(b"1".to_vec())
+        );
+        assert_eq!(block_on(file.removexattr(b"user.a")).unwrap(), None);
+        assert_eq!(
+            block_on(file.listxattr()).unwrap(),
+            vec![b"user.b".to_vec()]
+        );
+
+        block_on(file.removexattr(b"user.b")).unwrap();
+        assert_eq!(block_on(file.raw.read()).xattr_blk, 0);
+        block_on(file.sync()).unwrap();
+    }
+
+    #[test]
+    fn test_sync_flushes_every_fs() {
+        let disks = [SharedDisk::default(), SharedDisk::default()];
+        let mut allocated = Vec::new();
+        for disk in &disks {
+            let naive_fs = Arc::new(create_blank_naive_fs(disk.clone()));
+            let root = block_on(naive_fs.create_root(0)).unwrap();
+            block_on(root.sync()).unwrap();
+            let syncs = disk.syncs.load(Ordering::Acquire);
+
+            let blk_id = block_on(naive_fs.super_blk().alloc_blk()).unwrap();
+            block_on(naive_fs.sync()).unwrap();
+            assert!(disk.syncs.load(Ordering::Acquire) > syncs);
+            allocated.push(blk_id);
+        }
+
+        for (disk, blk_id) in disks.iter().zip(allocated) {
+            let naive_fs =
+                block_on(NaiveFs::<spin::Mutex<()>, _>::open(disk.clone(), false)).unwrap();
+            assert!(block_on(naive_fs.super_blk().blk_id_allocator.lock()).contains(blk_id));
+        }
+    }
+
+    #[test]
+    fn test_inode_checksum_round_trips() {
+        let disk = SharedDisk::default();
+        let naive_fs = Arc::new(create_blank_naive_fs(disk.clone()));
+        let file = block_on(naive_fs.create_inode(Mode::TY_REG, 0, 0, 0)).unwrap();
+        block_on(file.sync()).unwrap();
+
+        let naive_fs =
+            Arc::new(block_on(NaiveFs::<spin::Mutex<()>, _>::open(disk, false)).unwrap());
+        let file = block_on(naive_fs.load_inode(file.inode_id))
+            .unwrap()
+            .unwrap();
+        assert!(block_on(file.raw.read()).checksum_ok());
+    }
+
+    #[test]
+    fn test_load_detects_flipped_inode() {
+        let disk = SharedDisk::default();
+        let naive_fs = Arc::new(create_blank_naive_fs(disk.clone()));
+        let file = block_on(naive_fs.create_inode(Mode::TY_REG, 0, 0, 0)).unwrap();
+        block_on(file.sync()).unwrap();
+
+        // A bit of the uid.
+        let offset = naive_fs
+            .super_blk()
+            .raw_inode_addr(file.inode_id)
+            .unwrap()
+            .abs_offset(naive_fs.blk_device.blk_size)
+            + 2;
+        let mut byte = [0];
+        block_on(disk.ram_disk.read_at(offset, &mut byte)).unwrap();
+        byte[0] ^= 1;
+        block_on(disk.ram_disk.write_at(offset, &byte)).unwrap();
+
+        let naive_fs =
+            Arc::new(block_on(NaiveFs::<spin::Mutex<()>, _>::open(disk, false)).unwrap());
+        assert!(matches!(
+            block_on(naive_fs.load_inode(file.inode_id)),
+            Err(Error::ChecksumMismatch)
+        ));
+        assert!(naive_fs.read_only());
+    }
+
+    #[test]
+    fn test_unlink_discards_freed_blks() {
+        let disk = SharedDisk::default();
+        let naive_fs = Arc::new(create_blank_naive_fs(disk.clone()));
+        let file = block_on(naive_fs.create_inode(Mode::TY_REG, 0, 0, 0)).unwrap();
+        // 12 direct blocks and 8 blocks through the indirect block.
+        let src = vec![1; 20 * 1024];
+        assert_eq!(block_on(file.write_at(0, &src)).unwrap(), src.len() as u32);
+
+        let io_blks = block_on(file.io_blks::<false>(0, src.len() as u32)).unwrap();
+        let mut blk_ids: Vec<u32> = io_blks
+            .iter()
+            .map(|blk| blk.addr.blk_id as u32)
+            .chain([block_on(file.raw.read()).indirect_blk as u32])
+            .collect();
+        blk_ids.sort_unstable();
+        let mut expected: Vec<(u32, u32)> = Vec::new();
+        for blk_id in blk_ids {
+            match expected.last_mut() {
+                Some((start, len)) if *start + *len == blk_id * 1024 => *len += 1024,
+                _ => expected.push((blk_id * 1024, 1024)),
+            }
+        }
+
+        block_on(file.unlink()).unwrap();
+        assert_eq!(*disk.discards.lock(), expected);
+        assert_eq!(expected.iter().map(|(_, len)| len).sum::<u32>(), 21 * 1024);
+        block_on(naive_fs.sync()).unwrap();
+    }
+
+    #[test]
+    fn test_vectored_io() {
+        let naive_fs = Arc::new(create_blank_naive_fs(SharedDisk::default()));
+        let file = block_on(naive_fs.create_inode(Mode::TY_REG, 0, 0, 0)).unwrap();
+        let srcs: [&[u8]; 3] = [b"hello", &[7; 2000], b"world"];
+
+        assert_eq!(block_on(file.write_vectored(10, &srcs)).unwrap(), 2010);
+        let mut buf = vec![0; 2010];
+        assert_eq!(block_on(file.read_at(10, &mut buf)).unwrap(), 2010);
+        assert_eq!(buf, srcs.concat());
+
+        let (mut head, mut tail) = ([0; 1000], vec![0; 1500]);
+        assert_eq!(
+            block_on(file.read_vectored(10, &mut [&mut head[..], &mut tail[..]])).unwrap(),
+            2010
+        );
+        assert_eq!(head[..], buf[..1000]);
+        assert_eq!(tail[..1010], buf[1000..]);
+        block_on(file.sync()).unwrap();
+    }
+
+    #[test]
+    fn test_vectored_io_is_staged_in_bounded_chunks() {
+        let disk = SharedDisk::default();
+        let naive_fs = Arc::new(create_unjournaled_naive_fs(disk.clone()));
+        let file = block_on(naive_fs.create_inode(Mode::TY_REG, 0, 0, 0)).unwrap();
+        // 20 blocks worth of small buffers, more than one staging chunk.
+        let srcs: Vec<Vec<u8>> = (0..40u8).map(|i| vec![i; 512]).collect();
+        let src_refs: Vec<&[u8]> = srcs.iter().map(|src| &src[..]).collect();
+
+        let writes = disk.writes.load(Ordering::Acquire);
+        assert_eq!(
+            block_on(file.write_vectored(0, &src_refs)).unwrap(),
+            40 * 512
+        );
+        // A few writes per chunk of 16 blocks, not one per buffer.
+        assert!(disk.writes.load(Ordering::Acquire) - writes < 10);
+
+        // Reads stop at the end of the file.
+        let mut bufs: Vec<Vec<u8>> = (0..50).map(|_| vec![0xff; 512]).collect();
+        let mut buf_refs: Vec<&mut [u8]> = bufs.iter_mut().map(|buf| &mut buf[..]).collect();
+        assert_eq!(
+            block_on(file.read_vectored(0, &mut buf_refs)).unwrap(),
+            40 * 512
+        );
+        assert_eq!(bufs[..40], srcs[..]);
+        assert!(bufs[40..].iter().all(|buf| buf.iter().all(|b| *b == 0xff)));
+        block_on(file.sync()).unwrap();
+    }
+
+    #[test]
+    fn test_sequential_write_is_coalesced() {
+        let disk = SharedDisk::default();
+        let naive_fs = Arc::new(create_unjournaled_naive_fs(disk.clone()));
+        let file = block_on(naive_fs.create_inode(Mode::TY_REG, 0, 0, 0)).unwrap();
+
+        // 12 direct blocks and 8 blocks through the indirect block.
+        let src: Vec<u8> = (0..20 * 1024).map(|i| i as u8).collect();
+        let writes = disk.writes.load(Ordering::Acquire);
+        assert_eq!(block_on(file.write_at(0, &src)).unwrap(), src.len() as u32);
+        // One run of direct blocks, the indirect block and one run of indirect blocks.
+        assert!(disk.writes.load(Ordering::Acquire) - writes <= 3);
+
+        let mut buf = vec![0; src.len()];
+        assert_eq!(
+            block_on(file.read_at(0, &mut buf)).unwrap(),
+            src.len() as u32
+        );
+        assert_eq!(buf, src);
+        block_on(file.sync()).unwrap();
+    }
+
+    #[test]
+    fn test_open_baseline_layout_image() {
+        // A volume of the first on-disk layout, written field by field: a 41-byte super block,
+        // the descriptor right after it, 54-byte inodes and nothing after the descriptor.
+        let disk = SharedDisk::default();
+        let write = |offset: u32, bytes: &[u8]| {
+            block_on(disk.ram_disk.write_at(offset, bytes)).unwrap();
+        };
+        let le16 =
+            |vals: &[u16]| -> Vec<u8> { vals.iter().flat_map(|v| v.to_le_bytes()).collect() };
+
+        // inodes_count, blks_count, blk_size_log2, on_error, uuid, volume_name,
+        // prealloc_blocks and prealloc_dir_blocks.
+        let mut super_blk = le16(&[64, 64]);
+        super_blk.push(9);
+        super_blk.extend(le16(&[OnError::MountAsRo as u16]));
+        super_blk.extend([0; 32]);
+        super_blk.extend([1, 1]);
+        assert_eq!(super_blk.len(), 41);
+        write(0, &super_blk);
+        // The inode table takes 64 * 54 bytes, blocks 3 to 9.
+        write(41, &le16(&[1, 2, 3, 64 - 10, 64 - 3]));
+        let blk_bitmap = bitmap_with_ones(64, 10);
+        let inode_bitmap = bitmap_with_ones(64, 3);
+        write(512, &bytes_of(&blk_bitmap));
+        write(1024, &bytes_of(&inode_bitmap));
+
+        let root_mode = Mode::TY_DIR | Mode::PERM_RWX_USR;
+        // mode, uid, gid, size, atime, ctime, mtime, dtime, links_count,
+        // direct_blks and indirect_blk.
+        let mut root = le16(&[root_mode.bits(), 0, 0]);
+        root.extend([0; 20]);
+        root.extend(le16(&[1]));
+        root.extend([0; 26]);
+        assert_eq!(root.len(), 54);
+        write(3 * 512 + 2 * 54, &root);
+
+        let naive_fs =
+            Arc::new(block_on(NaiveFs::<spin::Mutex<()>, _>::open(disk.clone(), false)).unwrap());
+        assert!(!naive_fs.read_only());
+        assert_eq!(naive_fs.blk_count(), 64);
+        let root = block_on(naive_fs.load_inode(2)).unwrap().unwrap();
+        assert_eq!(block_on(root.mode()), root_mode);
+
+        // The root written above is empty, its entries go to a newly allocated block.
+        block_on(root.append_dot(2)).unwrap();
+        let file = block_on(naive_fs.create_inode(Mode::TY_REG, 0, 0, 0)).unwrap();
+        block_on(root.append(file.inode_id, entry_name(b"file"), FileType::RegFile)).unwrap();
+        assert!(matches!(
+            block_on(file.setxattr(b"user.a", b"1")),
+            Err(Error::Unsupported)
+        ));
+        block_on(file.sync()).unwrap();
+        block_on(root.sync()).unwrap();
+
+        // The new inode went to its 54-byte record, leaving the root inode intact.
+        let mut mode = [0; 2];
+        block_on(
+            disk.ram_disk
+                .read_at(3 * 512 + file.inode_id as u32 * 54, &mut mode),
+        )
+        .unwrap();
+        assert_eq!(u16::from_le_bytes(mode), Mode::TY_REG.bits());
+
+        let naive_fs =
+            Arc::new(block_on(NaiveFs::<spin::Mutex<()>, _>::open(disk, false)).unwrap());
+        let root = block_on(naive_fs.load_inode(2)).unwrap().unwrap();
+        assert_eq!(block_on(root.mode()), root_mode);
+        let entry = block_on(root.lookup(b"file")).unwrap().unwrap();
+        assert_eq!(entry.inode_id, file.inode_id);
+        assert!(block_on(naive_fs.load_inode(file.inode_id))
+            .unwrap()
+            .is_some());
+    }
+
+    fn bitmap_with_ones(nbits: u32, ones: u32) -> Bitmap {
+        let mut bitmap = Bitmap::new(nbits);
+        for offset in 0..ones {
+            bitmap.test_and_set(offset, true);
+        }
+        bitmap
+    }
+
+    fn bytes_of<T: ToBytes>(val: &T) -> Vec<u8> {
+        let mut bytes = vec![0; val.bytes_len()];
+        val.to_bytes(&mut bytes);
+        bytes
+    }
+
+    #[test]
+    fn test_journal_replays_committed_transaction() {
+        let disk = SharedDisk::default();
+        let naive_fs = Arc::new(create_blank_naive_fs(disk.clone()));
+        let root = block_on(naive_fs.create_root(0)).unwrap();
+        block_on(root.sync()).unwrap();
+
+        let file = block_on(naive_fs.create_inode(Mode::TY_REG, 0, 0, 0)).unwrap();
+        let blk_id = block_on(file.raw.read()).direct_blks[0];
+        // Crash once the journal is flushed, before any of its writes are applied.
+        disk.crash_after_syncs(1);
+        block_on(file.sync()).unwrap();
+        disk.recover();
+
+        let inode_offset = naive_fs
+            .super_blk()
+            .raw_inode_addr(file.inode_id)
+            .unwrap()
+            .abs_offset(naive_fs.blk_device.blk_size);
+        let raw_inode = block_on(blk_device::read_val_at::<_, RawInode>(&disk, inode_offset));
+        assert!(!raw_inode.unwrap().valid());
+
+        let naive_fs =
+            Arc::new(block_on(NaiveFs::<spin::Mutex<()>, _>::open(disk.clone(), false)).unwrap());
+        // The bitmaps, the descriptor and the inode were replayed together.
+        assert!(!naive_fs.read_only());
+        assert!(block_on(naive_fs.load_inode(file.inode_id))
+            .unwrap()
+            .is_some());
+        assert!(block_on(naive_fs.super_blk().blk_id_allocator.lock()).contains(blk_id));
+        assert!(block_on(naive_fs.super_blk().inode_id_allocator.lock()).contains(file.inode_id));
+    }
+
+    #[test]
+    fn test_journal_ignores_torn_transaction() {
+        let disk = SharedDisk::default();
+        let naive_fs = Arc::new(create_blank_naive_fs(disk.clone()));
+        let root = block_on(naive_fs.create_root(0)).unwrap();
+        block_on(root.sync()).unwrap();
+
+        let file = block_on(naive_fs.create_inode(Mode::TY_REG, 0, 0, 0)).unwrap();
+        let blk_id = block_on(file.raw.read()).direct_blks[0];
+        disk.crash_after_syncs(1);
+        block_on(file.sync()).unwrap();
+        disk.recover();
+
+        // A bit of the journaled payload that did not reach the disk intact.
+        let journal = naive_fs.super_blk().journal.unwrap();
+        let offset = Addr::new(journal.start, 0).abs_offset(naive_fs.blk_device.blk_size)
+            + RawJournalHeader::BYTES_LEN as u32;
+        let mut byte = [0];
+        block_on(disk.ram_disk.read_at(offset, &mut byte)).unwrap();
+        byte[0] ^= 1;
+        block_on(disk.ram_disk.write_at(offset, &byte)).unwrap();
+
+        let naive_fs =
+            Arc::new(block_on(NaiveFs::<spin::Mutex<()>, _>::open(disk, false)).unwrap());
+        assert!(!naive_fs.read_only());
+        assert!(block_on(naive_fs.load_inode(file.inode_id))
+            .unwrap()
+            .is_none());
+        assert!(!block_on(naive_fs.super_blk().blk_id_allocator.lock()).contains(blk_id));
+        assert!(!block_on(naive_fs.super_blk().inode_id_allocator.lock()).contains(file.inode_id));
+    }
+
+    #[test]
+    fn test_journal_pending_refuses_read_only_open() {
+        let disk = SharedDisk::default();
+        let naive_fs = Arc::new(create_blank_naive_fs(disk.clone()));
+        let root = block_on(naive_fs.create_root(0)).unwrap();
+        block_on(root.sync()).unwrap();
+
+        let file = block_on(naive_fs.create_inode(Mode::TY_REG, 0, 0, 0)).unwrap();
+        disk.crash_after_syncs(1);
+        block_on(file.sync()).unwrap();
+        disk.recover();
+
+        let writes = disk.writes.load(Ordering::Acquire);
+        assert!(matches!(
+            block_on(NaiveFs::<spin::Mutex<()>, _>::open(disk.clone(), true)),
+            Err(Error::ReadOnly)
+        ));
+        assert_eq!(disk.writes.load(Ordering::Acquire), writes);
+
+        // A writable open replays the transaction, read-only opens work again after it.
+        block_on(NaiveFs::<spin::Mutex<()>, _>::open(disk.clone(), false)).unwrap();
+        let naive_fs = Arc::new(block_on(NaiveFs::<spin::Mutex<()>, _>::open(disk, true)).unwrap());
+        assert!(block_on(naive_fs.load_inode(file.inode_id))
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_journal_replays_directory_entry() {
+        let disk = SharedDisk::default();
+        let naive_fs = Arc::new(create_blank_naive_fs(disk.clone()));
+        let root = block_on(naive_fs.create_root(0)).unwrap();
+        block_on(root.sync()).unwrap();
+
+        disk.crash_after_syncs(1);
+        block_on(root.append(3, entry_name(b"file"), FileType::RegFile)).unwrap();
+        disk.recover();
+
+        let naive_fs =
+            Arc::new(block_on(NaiveFs::<spin::Mutex<()>, _>::open(disk, false)).unwrap());
+        let root = block_on(naive_fs.load_inode(2)).unwrap().unwrap();
+        let entry = block_on(root.lookup(b"file")).unwrap().unwrap();
+        assert_eq!(entry.inode_id, 3);
+    }
+
+    #[test]
+    fn test_journal_replays_indirect_blk() {
+        let disk = SharedDisk::default();
+        let naive_fs = Arc::new(create_blank_naive_fs(disk.clone()));
+        let root = block_on(naive_fs.create_root(0)).unwrap();
+        block_on(root.sync()).unwrap();
+        let file = block_on(naive_fs.create_inode(Mode::TY_REG, 0, 0, 0)).unwrap();
+        block_on(file.sync()).unwrap();
+
+        // Crash once the indirect block is journaled, the data is lost with it.
+        disk.crash_after_syncs(1);
+        block_on(file.write_at(file.direct_blk_len, &[1; 16])).unwrap();
+        disk.recover();
+        // What the crash lost is not written back when the old inode is dropped.
+        block_on(file.raw.write()).set_dirty(false);
+
+        let naive_fs =
+            Arc::new(block_on(NaiveFs::<spin::Mutex<()>, _>::open(disk, false)).unwrap());
+        let file = block_on(naive_fs.load_inode(file.inode_id))
+            .unwrap()
+            .unwrap();
+        let indirect_blk = block_on(file.raw.read()).indirect_blk;
+        assert_ne!(indirect_blk, 0);
+        let blk_ids: Vec<BlkId> =
+            block_on(naive_fs.blk_device.read_vec(Addr::new(indirect_blk, 0), 1)).unwrap();
+        let blk_id_allocator = block_on(naive_fs.super_blk().blk_id_allocator.lock());
+        assert!(blk_id_allocator.contains(indirect_blk));
+        assert!(blk_id_allocator.contains(blk_ids[0]));
+    }
+
+    #[test]
+    fn test_resize_makes_new_blks_allocatable() {
+        let disk = SharedDisk::default();
+        disk.set_capacity(16 * 1024);
+        let naive_fs = NaiveFs::<spin::Mutex<()>, _>::create_blank(
+            disk.clone(),
+            BlkSize::new(512),
+            [0; 16],
+            [0; 16],
+        );
+        assert_eq!(naive_fs.blk_count(), 32);
+        let used = block_on(naive_fs.super_blk().try_alloc_n_blks(u16::MAX));
+        assert!(block_on(naive_fs.super_blk().alloc_blk()).is_none());
+
+        // The disk has not grown yet.
+        assert!(matches!(
+            block_on(naive_fs.resize(128)),
+            Err(Error::NoSpace)
+        ));
+        disk.set_capacity(64 * 1024);
+        assert!(matches!(
+            block_on(naive_fs.resize(16)),
+            Err(Error::Unsupported)
+        ));
+        block_on(naive_fs.resize(128)).unwrap();
+        assert_eq!(naive_fs.blk_count(), 128);
+
+        let blk_id = block_on(naive_fs.super_blk().alloc_blk()).unwrap();
+        assert!(blk_id > 32);
+        {
+            let allocator = block_on(naive_fs.super_blk().blk_id_allocator.lock());
+            assert_eq!(allocator.capacity(), 128);
+            assert!(used.iter().all(|&blk_id| allocator.contains(blk_id)));
+        }
+        // The inode table of 32 records of 60 bytes has room for 2 more in its last block.
+        assert_eq!(naive_fs.super_blk().inode_table_capacity(32), 34);
+        assert_eq!(
+            block_on(naive_fs.super_blk().inode_id_allocator.lock()).capacity(),
+            34
+        );
+        block_on(naive_fs.sync()).unwrap();
+
+        let naive_fs = block_on(NaiveFs::<spin::Mutex<()>, _>::open(disk.clone(), false)).unwrap();
+        assert_eq!(naive_fs.blk_count(), 128);
+        assert!(!naive_fs.read_only());
+        let allocator = block_on(naive_fs.super_blk().blk_id_allocator.lock());
+        // The 32 blocks of the small volume and `blk_id` are in use.
+        assert_eq!(allocator.free(), 128 - 32 - 1);
+        assert!(allocator.contains(blk_id));
+        assert!(used.iter().all(|&blk_id| allocator.contains(blk_id)));
+        assert_eq!(naive_fs.super_blk().raw_super_blk.lock().inodes_count, 34);
+        assert_eq!(
+            block_on(naive_fs.super_blk().inode_id_allocator.lock()).capacity(),
+            34
+        );
+    }
+
+    #[test]
+    fn test_concurrent_writes_do_not_lose_blks() {
+        let disk = SharedDisk::default();
+        let naive_fs = Arc::new(create_blank_naive_fs(disk.clone()));
+        let file = block_on(naive_fs.create_inode(Mode::TY_REG, 0, 0, 0)).unwrap();
+        let free = block_on(naive_fs.super_blk().blk_id_allocator.lock()).free();
+
+        // Two overlapping appends past the 12 direct blocks, whose reads of the indirect block interleave.
+        disk.yield_on_read.store(true, Ordering::Release);
+        let (first, second) = (vec![1; 3000], vec![2; 3000]);
+        let (first_len, second_len) = block_on(join(
+            file.write_at(12 * 1024, &first),
+            file.write_at(13 * 1024, &second),
+        ));
+        assert_eq!(first_len.unwrap(), 3000);
+        assert_eq!(second_len.unwrap(), 3000);
+        assert_eq!(block_on(file.raw.read()).size, 13 * 1024 + 3000);
+
+        // The indirect block and the 4 blocks holding 12K..16K, none allocated twice.
+        let allocated = free - block_on(naive_fs.super_blk().blk_id_allocator.lock()).free();
+        assert_eq!(allocated, 5);
+        let mut buf = vec![0; 4096];
+        assert_eq!(
+            block_on(file.read_at(12 * 1024, &mut buf)).unwrap(),
+            1024 + 3000
+        );
+        assert!(buf[..1024].iter().all(|&byte| byte == 1));
+        assert!(buf[1024..4024].iter().all(|&byte| byte == 2));
+        block_on(file.sync()).unwrap();
+    }
+
+    #[test]
+    fn test_holes_read_as_zeros() {
+        let disk = SharedDisk::default();
+        let naive_fs = Arc::new(create_blank_naive_fs(disk.clone()));
+        let file = block_on(naive_fs.create_inode(Mode::TY_REG, 0, 0, 0)).unwrap();
+        // Block 1 is skipped and stays a hole.
+        block_on(file.write_at(0, &[1; 1024])).unwrap();
+        block_on(file.write_at(2 * 1024, &[2; 1024])).unwrap();
+        assert_eq!(block_on(file.raw.read()).direct_blks[1], 0);
+
+        let mut buf = vec![0xff; 3 * 1024];
+        let reads = disk.reads.load(Ordering::Acquire);
+        assert_eq!(block_on(file.read_at(0, &mut buf)).unwrap(), 3 * 1024);
+        // One read for each written block, none for the hole.
+        assert_eq!(disk.reads.load(Ordering::Acquire) - reads, 2);
+        assert!(buf[..1024].iter().all(|&byte| byte == 1));
+        assert!(buf[1024..2 * 1024].iter().all(|&byte| byte == 0));
+        assert!(buf[2 * 1024..].iter().all(|&byte| byte == 2));
+        block_on(file.sync()).unwrap();
+    }
+
+    /// Returns Pending once, after asking to be polled again.
+    fn yield_once() -> impl Future<Output = ()> {
+        let mut yielded = false;
+        poll_fn(move |cx| {
+            if yielded {
+                Poll::Ready(())
+            } else {
+                yielded = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        })
+    }
+
+    fn entry_name(name: &[u8]) -> DirEntryName {
+        DirEntryName::try_from(name).unwrap()
+    }
+
+    fn raw_inode_record() -> InodeRecord {
+        InodeRecord::new(RawInode::default(), RawInode::BYTES_LEN as u32)
+    }
+
+    fn create_blank_naive_fs<DK: Disk + Sync>(disk: DK) -> NaiveFs<spin::Mutex<()>, DK> {
+        NaiveFs::create_blank(disk, BlkSize::new(1024), [0; 16], [0; 16])
+    }
+
+    /// A volume like `create_blank_naive_fs` without the journal,
+    /// whose writes all go straight to their blocks.
+    fn create_unjournaled_naive_fs<DK: Disk + Sync>(disk: DK) -> NaiveFs<spin::Mutex<()>, DK> {
+        let blk_size = BlkSize::new(1024);
+        let blks_count = blk_size.div_by(disk.capacity()) as u16;
+        let raw_super_blk = RawSuperBlk {
+            inodes_count: blks_count,
+            blks_count,
+            blk_size_log2: blk_size.blk_size_log2,
+            prealloc_blocks: 1,
+            prealloc_dir_blocks: 1,
+            ..Default::default()
+        };
+        NaiveFs {
+            super_blk: SuperBlk::create_blank(
+                raw_super_blk,
+                Features::CHECKSUM | Features::EXT_INODE,
+            ),
+            blk_device: BlkDevice::new(disk, blk_size, false),
+        }
+    }
+
+    /// A `RamDisk` that can be shared between mounts and made to fail on demand.
+    #[derive(Clone)]
+    struct SharedDisk {
+        ram_disk: Arc<RamDisk<spin::RwLock<()>>>,
+        broken: Arc<AtomicBool>,
+        syncs: Arc<AtomicUsize>,
+        reads: Arc<AtomicUsize>,
+        writes: Arc<AtomicUsize>,
+        // (offset, len) of every discard, in order.
+        discards: Arc<spin::Mutex<Vec<(u32, u32)>>>,
+        // Writes are silently dropped once `syncs` reaches this.
+        crash_at_syncs: Arc<AtomicUsize>,
+        // The capacity reported, at most that of `ram_disk`.
+        capacity: Arc<AtomicU32>,
+        // Reads return Pending once, letting the futures joined with them run in between.
+        yield_on_read: Arc<AtomicBool>,
+    }
+
+    impl SharedDisk {
+        fn set_capacity(&self, capacity: u32) {
+            self.capacity.store(capacity, Ordering::Release);
+        }
+
+        /// Drops every write after `syncs` more syncs, as if the power was lost.
+        fn crash_after_syncs(&self, syncs: usize) {
+            self.crash_at_syncs.store(
+                self.syncs.load(Ordering::Acquire) + syncs,
+                Ordering::Release,
+            );
+        }
+
+        fn recover(&self) {
+            self.crash_at_syncs.store(usize::MAX, Ordering::Release);
+        }
+    }
+
+    impl Default for SharedDisk {
+        fn default() -> Self {
+            Self {
+                ram_disk: Arc::new(RamDisk::new(64 * 1024)),
+                broken: Arc::new(AtomicBool::new(false)),
+                syncs: Arc::new(AtomicUsize::new(0)),
+                reads: Arc::new(AtomicUsize::new(0)),
+                writes: Arc::new(AtomicUsize::new(0)),
+                discards: Default::default(),
+                crash_at_syncs: Arc::new(AtomicUsize::new(usize::MAX)),
+                capacity: Arc::new(AtomicU32::new(64 * 1024)),
+                yield_on_read: Arc::new(AtomicBool::new(false)),
+            }
+        }
+    }
+
+    impl Disk for SharedDisk {
+        type ReadAtFut<'a> = BoxFuture<'a, DiskResult<u32>>;
+        type WriteAtFut<'a> = Ready<DiskResult<u32>>;
+        type SyncFut<'a> = Ready<DiskResult<()>>;
+
+        fn read_at<'a>(&'a self, offset: u32, buf: &'a mut [u8]) -> Self::ReadAtFut<'a> {
+            if self.broken.load(Ordering::Acquire) {
+                return Box::pin(ready(Err(Box::new(()) as DiskError)));
+            }
+            self.reads.fetch_add(1, Ordering::AcqRel);
+            let res = self.ram_disk.read_at(offset, buf);
+            if self.yield_on_read.load(Ordering::Acquire) {
+                Box::pin(async move {
+                    yield_once().await;
+                    res.await
+                })
+            } else {
+                Box::pin(res)
+            }
+        }
+
+        fn write_at<'a>(&'a self, offset: u32, buf: &'a [u8]) -> Self::WriteAtFut<'a> {
+            if self.broken.load(Ordering::Acquire) {
+                return ready(Err(Box::new(()) as DiskError));
+            }
+            if self.syncs.load(Ordering::Acquire) >= self.crash_at_syncs.load(Ordering::Acquire) {
+                return ready(Ok(buf.len() as u32));
+            }
+            self.writes.fetch_add(1, Ordering::AcqRel);
+            self.ram_disk.write_at(offset, buf)
+        }
+
+        fn sync(&self) -> Self::SyncFut<'_> {
+            self.syncs.fetch_add(1, Ordering::AcqRel);
+            ready(Ok(()))
+        }
+
+        fn discard(&self, offset: u32, len: u32) -> BoxFuture<'_, DiskResult<()>> {
+            self.discards.lock().push((offset, len));
+            Box::pin(ready(Ok(())))
+        }
+
+        fn capacity(&self) -> u32 {
+            self.capacity.load(Ordering::Acquire)
+        }
+    }
+
     fn create_naive_fs(blk_size: BlkSize) -> NaiveFs<spin::Mutex<()>, RamDisk<spin::RwLock<()>>> {
         create_naive_fs_with_blk_device(BlkDevice::new(RamDisk::new(4096), blk_size, false))
     }
@@ -1031,7 +2251,15 @@ mod test {
         };
 
         NaiveFs {
-            super_blk: SuperBlk::new(rsb, false, 0, Default::default(), Default::default()),
+            super_blk: SuperBlk::new(
+                rsb,
+                Features::empty(),
+                false,
+                0,
+                None,
+                Default::default(),
+                Default::default(),
+            ),
             blk_device,
         }
     }
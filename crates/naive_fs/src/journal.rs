@@ -0,0 +1,215 @@
+use core::{
+    convert::TryInto,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use alloc::vec::Vec;
+use byte_struct::*;
+
+use crate::{
+    blk_device::{self, BlkDevice, Disk, FromBytes, ToBytes},
+    checksum,
+    maybe_dirty::MaybeDirty,
+    Addr, BlkId, BlkSize, Error, Result,
+};
+
+/// Marks a committed transaction that may not have been applied yet.
+const JOURNAL_MAGIC: u32 = 0x4a4e_4653;
+
+/// Length of the `offset` and `len` preceding the bytes of every record.
+const RECORD_HEADER_LEN: usize = 8;
+
+/// The header at the start of the journal area, followed by the payload:
+/// | offset: u32 | len: u32 | bytes | ...
+/// The transaction is committed once the header and the payload are on disk
+/// and the checksum matches, a torn write leaves a mismatching checksum.
+#[derive(ByteStruct, Debug, Default)]
+#[byte_struct_le]
+pub struct RawJournalHeader {
+    pub magic: u32,
+    pub records_count: u16,
+    pub payload_len: u32,
+    /// CRC32C of the payload.
+    pub checksum: u32,
+}
+
+impl FromBytes for RawJournalHeader {
+    const BYTES_LEN: usize = Self::BYTE_LEN;
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        Some(Self::read_bytes(bytes))
+    }
+}
+
+/// The metadata writes of one sync, they reach their place through the journal
+/// so that a crash leaves either all or none of them applied.
+#[derive(Default)]
+pub(crate) struct Transaction<'a> {
+    writes: Vec<(Addr, Vec<u8>)>,
+    // The dirty flags of the staged values, cleared once the writes are applied.
+    staged: Vec<&'a AtomicBool>,
+}
+
+impl<'a> Transaction<'a> {
+    /// Stages `md` if it is dirty.
+    pub fn add<T: ToBytes>(&mut self, md: &'a MaybeDirty<T>) {
+        if !md.is_dirty() {
+            return;
+        }
+        let mut bytes = vec![0; md.bytes_len()];
+        md.to_bytes(&mut bytes);
+        self.writes.push((md.addr, bytes));
+        self.staged.push(&md.is_dirty);
+    }
+
+    /// Stages a write of `bytes` at `addr` that has no dirty flag to clear,
+    /// e.g. a part of an indirect block or of a directory.
+    pub fn add_bytes(&mut self, addr: Addr, bytes: Vec<u8>) {
+        self.writes.push((addr, bytes));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+}
+
+/// The journal area, `blks_count` blocks starting at `start`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Journal {
+    pub start: BlkId,
+    pub blks_count: u16,
+}
+
+impl Journal {
+    /// Returns the number of blocks a journal needs to hold the biggest transaction,
+    /// the two bitmaps, a block for the inode, the super block and the descriptor,
+    /// and a block for the written part of an indirect block or of a directory.
+    pub fn blks_count_for(blks_count: u16, inodes_count: u16, blk_size: BlkSize) -> u16 {
+        let bitmap_bytes = |nbits: u16| crate::div_round_up!(nbits as u32, u64::BITS) * 8;
+        blk_size.div_round_up_by(bitmap_bytes(blks_count) + bitmap_bytes(inodes_count)) as u16 + 2
+    }
+
+    fn addr(&self) -> Addr {
+        Addr::new(self.start, 0)
+    }
+
+    fn capacity(&self, blk_size: BlkSize) -> u32 {
+        blk_size.mul(self.blks_count as u32)
+    }
+
+    /// Writes `txn` to the journal and applies it once the journal is on disk.
+    /// Returns `Error::NoSpace` if the transaction does not fit in the journal.
+    pub async fn commit<DK: Disk>(
+        &self,
+        blk_device: &BlkDevice<DK>,
+        txn: Transaction<'_>,
+    ) -> Result<()> {
+        if txn.is_empty() {
+            return Ok(());
+        }
+
+        let mut payload = Vec::new();
+        for (addr, bytes) in &txn.writes {
+            payload.extend_from_slice(&addr.abs_offset(blk_device.blk_size).to_le_bytes());
+            payload.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+            payload.extend_from_slice(bytes);
+        }
+        let header = RawJournalHeader {
+            magic: JOURNAL_MAGIC,
+            records_count: txn.writes.len() as u16,
+            payload_len: payload.len() as u32,
+            checksum: checksum::crc32c(&payload),
+        };
+        let mut journal = vec![0; RawJournalHeader::BYTE_LEN + payload.len()];
+        if journal.len() as u32 > self.capacity(blk_device.blk_size) {
+            return Err(Error::NoSpace);
+        }
+        header.write_bytes(&mut journal[..RawJournalHeader::BYTE_LEN]);
+        journal[RawJournalHeader::BYTE_LEN..].copy_from_slice(&payload);
+
+        blk_device.write_at(self.addr(), &journal).await?;
+        blk_device.sync().await?;
+
+        for (addr, bytes) in &txn.writes {
+            blk_device.write_at(*addr, bytes).await?;
+        }
+        // The journal may only be reused once the writes it protects are on disk.
+        blk_device.sync().await?;
+        for is_dirty in txn.staged {
+            is_dirty.store(false, Ordering::Release);
+        }
+
+        // Replaying an applied transaction is harmless, so clearing it need not be synced.
+        blk_device
+            .write_at(self.addr(), &[0; RawJournalHeader::BYTE_LEN])
+            .await?;
+        Ok(())
+    }
+
+    /// Returns whether a crash left a committed transaction in the journal,
+    /// which `replay` has to apply before the volume is consistent.
+    pub async fn is_pending<DK: Disk>(&self, disk: &DK, blk_size: BlkSize) -> Result<bool> {
+        Ok(self.committed(disk, blk_size).await?.is_some())
+    }
+
+    /// Reads the committed transaction in the journal, as its records count and payload.
+    async fn committed<DK: Disk>(
+        &self,
+        disk: &DK,
+        blk_size: BlkSize,
+    ) -> Result<Option<(u16, Vec<u8>)>> {
+        let offset = self.addr().abs_offset(blk_size);
+        let header = blk_device::read_val_at::<DK, RawJournalHeader>(disk, offset)
+            .await
+            .map_err(Error::DiskError)?;
+        let payload_end = RawJournalHeader::BYTE_LEN as u64 + header.payload_len as u64;
+        if header.magic != JOURNAL_MAGIC || payload_end > self.capacity(blk_size) as u64 {
+            return Ok(None);
+        }
+
+        let mut payload = vec![0; header.payload_len as usize];
+        disk.read_at(offset + RawJournalHeader::BYTE_LEN as u32, &mut payload)
+            .await
+            .map_err(Error::DiskError)?;
+        if checksum::crc32c(&payload) != header.checksum {
+            // The crash interrupted the commit, none of the writes were applied.
+            return Ok(None);
+        }
+        Ok(Some((header.records_count, payload)))
+    }
+
+    /// Applies the transaction left in the journal by a crash, if it was committed.
+    /// Returns true if a transaction was replayed.
+    pub async fn replay<DK: Disk>(&self, disk: &DK, blk_size: BlkSize) -> Result<bool> {
+        let (records_count, payload) = match self.committed(disk, blk_size).await? {
+            Some(committed) => committed,
+            None => return Ok(false),
+        };
+
+        let mut rest = &payload[..];
+        for _ in 0..records_count {
+            let record_header = rest.get(..RECORD_HEADER_LEN).ok_or(Error::Corrupt)?;
+            let offset = u32::from_le_bytes(record_header[..4].try_into().unwrap());
+            let len = u32::from_le_bytes(record_header[4..].try_into().unwrap()) as usize;
+            let bytes = rest
+                .get(RECORD_HEADER_LEN..RECORD_HEADER_LEN + len)
+                .ok_or(Error::Corrupt)?;
+            disk.write_at(offset, bytes)
+                .await
+                .map_err(Error::DiskError)?;
+            rest = &rest[RECORD_HEADER_LEN + len..];
+        }
+        disk.sync().await.map_err(Error::DiskError)?;
+        disk.write_at(
+            self.addr().abs_offset(blk_size),
+            &[0; RawJournalHeader::BYTE_LEN],
+        )
+        .await
+        .map_err(Error::DiskError)?;
+        disk.sync().await.map_err(Error::DiskError)?;
+        Ok(true)
+    }
+}
@@ -4,7 +4,7 @@
 #![feature(type_alias_impl_trait)]
 #![no_std]
 
-use core::{marker::PhantomData, ops};
+use core::{convert::TryFrom, marker::PhantomData, ops};
 #[allow(unused_imports)]
 #[macro_use]
 extern crate alloc;
@@ -13,17 +13,23 @@ extern crate bitflags;
 
 mod allocator;
 mod blk_device;
+mod checksum;
 mod consts;
 pub mod dir;
 pub mod inode;
+mod journal;
 mod maybe_dirty;
 #[cfg(test)]
 mod ram_disk;
 mod super_blk;
+mod xattr;
 
 use alloc::{boxed::Box, sync::Arc};
-use inode::{Inode, InodeLoadFut, RawInode};
-use super_blk::{RawSuperBlk, SuperBlk};
+use futures_util::FutureExt;
+use inode::{Inode, InodeLoadFut, InodeRecord, RawInode};
+use journal::Journal;
+use maybe_dirty::Syncable;
+use super_blk::{Features, OnError, RawSuperBlk, SuperBlk};
 
 pub type Result<T> = core::result::Result<T, Error>;
 
@@ -39,8 +45,16 @@ pub enum Error {
     NoSpace,
     NotDir,
     InvalidDirEntryName(Box<dir::DirEntryName>),
+    /// A name is longer than `dir::DIR_ENTRY_NAME_CAP`.
+    NameTooLong,
     ReadOnly,
+    /// On-disk metadata points outside the volume.
+    Corrupt,
     DiskError(blk_device::DiskError),
+    /// An on-disk structure does not match its checksum.
+    ChecksumMismatch,
+    /// The operation is not supported, such as shrinking a volume.
+    Unsupported,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -66,15 +80,18 @@ impl Addr {
         blk_size.mul(self.blk_id as u32) + self.offset_of_blk
     }
 
-    pub fn add_offset(mut self, offset: u32, blk_size: BlkSize) -> Self {
-        let offset = self.offset_of_blk + offset;
-        self.blk_id += blk_size.div_by(offset) as BlkId;
+    /// Returns None if the resulting block id does not fit in a `BlkId`.
+    pub fn add_offset(mut self, offset: u32, blk_size: BlkSize) -> Option<Self> {
+        let offset = self.offset_of_blk.checked_add(offset)?;
+        let blks = BlkId::try_from(blk_size.div_by(offset)).ok()?;
+        self.blk_id = self.blk_id.checked_add(blks)?;
         self.offset_of_blk = blk_size.mod_by(offset);
-        self
+        Some(self)
     }
 
-    pub fn add(mut self, other: Addr, blk_size: BlkSize) -> Self {
-        self.blk_id += other.blk_id;
+    /// Returns None if the resulting block id does not fit in a `BlkId`.
+    pub fn add(mut self, other: Addr, blk_size: BlkSize) -> Option<Self> {
+        self.blk_id = self.blk_id.checked_add(other.blk_id)?;
         self.add_offset(other.offset_of_blk, blk_size)
     }
 }
@@ -199,9 +216,10 @@ where
             prealloc_blocks: 1,
             prealloc_dir_blocks: 1,
         };
+        let features = Features::CHECKSUM | Features::JOURNAL | Features::EXT_INODE;
 
         Self {
-            super_blk: SuperBlk::create_blank(raw_super_blk),
+            super_blk: SuperBlk::create_blank(raw_super_blk, features),
             blk_device: BlkDevice::new(disk, fs_blk_size, false),
         }
     }
@@ -213,6 +231,7 @@ where
         gid: u16,
         create_unix_timestamp: u32,
     ) -> Result<Inode<MutexType, DK>> {
+        self.check_writable()?;
         let inode_id = self.super_blk.alloc_inode().await.ok_or(Error::NoSpace)?;
         self.create_inode_inner(inode_id, mode, uid, gid, create_unix_timestamp)
             .await
@@ -230,6 +249,7 @@ where
         self: &Arc<Self>,
         create_unix_timestamp: u32,
     ) -> Result<Inode<MutexType, DK>> {
+        self.check_writable()?;
         let inode = self
             .create_inode_inner(
                 root_inode_id(),
@@ -254,14 +274,15 @@ where
         gid: u16,
         create_unix_timestamp: u32,
     ) -> Result<Inode<MutexType, DK>> {
+        let addr = self.super_blk.raw_inode_addr(inode_id)?;
         let mut prealloc_blks = if mode.contains(inode::Mode::TY_REG) {
-            self.super_blk.raw_super_blk.prealloc_blocks
+            self.super_blk.raw_super_blk.lock().prealloc_blocks
         } else if mode.contains(inode::Mode::TY_DIR) {
-            self.super_blk.raw_super_blk.prealloc_dir_blocks
+            self.super_blk.raw_super_blk.lock().prealloc_dir_blocks
         } else {
             0
         };
-        prealloc_blks = prealloc_blks.max(consts::INODE_DIRECT_BLK_COUNT as u8);
+        prealloc_blks = prealloc_blks.min(consts::INODE_DIRECT_BLK_COUNT as u8);
         let mut direct_blks = [0; consts::INODE_DIRECT_BLK_COUNT];
         if prealloc_blks > 0 {
             self.super_blk
@@ -273,13 +294,61 @@ where
         }
 
         let raw_inode = MaybeDirty::new(
-            self.super_blk.raw_inode_addr(inode_id),
-            RawInode::new(mode, uid, gid, direct_blks, create_unix_timestamp),
+            addr,
+            InodeRecord::new(
+                RawInode::new(mode, uid, gid, direct_blks, create_unix_timestamp),
+                self.super_blk.inode_len(),
+            ),
         );
         raw_inode.set_dirty(true);
         Ok(Inode::new(inode_id, raw_inode, self.clone()))
     }
 
+    /// Grows the volume to `new_blks_count` blocks, once its disk has grown,
+    /// see `BlkDevice::refresh_capacity`. The new blocks are free, the ones in use stay allocated.
+    /// The inode table lies before the data blocks and keeps its blocks, the inodes count
+    /// grows with the blocks count as far as they hold more inodes.
+    pub async fn resize(&self, new_blks_count: u16) -> Result<()> {
+        self.check_writable()?;
+        // Held until the counts are updated, a sync or another resize waits for them.
+        let mut blk_id_allocator = self.super_blk.blk_id_allocator.lock().await;
+        let mut inode_id_allocator = self.super_blk.inode_id_allocator.lock().await;
+        let (blks_count, inodes_count) = {
+            let raw_super_blk = self.super_blk.raw_super_blk.lock();
+            (raw_super_blk.blks_count, raw_super_blk.inodes_count)
+        };
+        if new_blks_count < blks_count {
+            return Err(Error::Unsupported);
+        }
+        if new_blks_count == blks_count {
+            return Ok(());
+        }
+        let new_inodes_count = new_blks_count
+            .min(self.super_blk.inode_table_capacity(inodes_count))
+            .max(inodes_count);
+
+        let blk_size = self.blk_device.blk_size;
+        // Each bitmap has a single block, and the journal was sized for the old bitmaps.
+        let bitmap_len = |nbits: u16| crate::div_round_up!(nbits as u32, u64::BITS) * 8;
+        let journal_too_small = self.super_blk.journal.map_or(false, |journal| {
+            Journal::blks_count_for(new_blks_count, new_inodes_count, blk_size) > journal.blks_count
+        });
+        if new_blks_count as u32 > self.blk_device.refresh_capacity()
+            || bitmap_len(new_blks_count) > blk_size.size()
+            || bitmap_len(new_inodes_count) > blk_size.size()
+            || journal_too_small
+        {
+            return Err(Error::NoSpace);
+        }
+
+        blk_id_allocator.grow(new_blks_count);
+        inode_id_allocator.grow(new_inodes_count);
+        let mut raw_super_blk = self.super_blk.raw_super_blk.lock();
+        raw_super_blk.blks_count = new_blks_count;
+        raw_super_blk.inodes_count = new_inodes_count;
+        Ok(())
+    }
+
     pub fn super_blk(&self) -> &SuperBlk<MutexType> {
         &self.super_blk
     }
@@ -291,7 +360,56 @@ where
 
     /// Get the BlkDevice's block count.
     pub fn blk_count(&self) -> usize {
-        self.super_blk().raw_super_blk.blks_count as usize
+        self.super_blk().raw_super_blk.lock().blks_count as usize
+    }
+
+    /// Returns true if the filesystem is mounted (or has been remounted) read-only.
+    pub fn read_only(&self) -> bool {
+        self.blk_device.read_only()
+    }
+
+    /// Returns `Error::ReadOnly` if the filesystem must not be modified.
+    pub fn check_writable(&self) -> Result<()> {
+        if self.read_only() {
+            Err(Error::ReadOnly)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Applies the `on_error` policy of the super block when `err` is a disk error
+    /// or corrupted metadata, and hands `err` back to the caller.
+    pub(crate) fn handle_error(&self, err: Error) -> Error {
+        if let Error::DiskError(_) | Error::Corrupt | Error::ChecksumMismatch = err {
+            match OnError::from(self.super_blk.raw_super_blk.lock().on_error) {
+                OnError::Continue => {}
+                OnError::MountAsRo => self.blk_device.set_read_only(true),
+                OnError::Panic => panic!("naive_fs: {:?}", err),
+            }
+        }
+        err
+    }
+}
+
+impl<MutexType, DK> NaiveFs<MutexType, DK>
+where
+    MutexType: lock_api::RawMutex<GuardMarker = lock_api::GuardSend> + Sync + Send,
+    DK: Disk + Sync + Send,
+{
+    /// Write the super block and the allocators back and flush the block device.
+    /// Inodes are written back by their own `sync`.
+    pub fn sync(&self) -> BoxFuture<Result<()>> {
+        let Self {
+            super_blk,
+            blk_device,
+        } = self;
+        Box::pin(
+            async move {
+                super_blk.sync(blk_device).await?;
+                blk_device.sync().await
+            }
+            .map(|res| res.map_err(|e| self.handle_error(e))),
+        )
     }
 }
 
@@ -301,3 +419,30 @@ macro_rules! div_round_up {
         ($n + ($d - 1)) / $d
     };
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{Addr, BlkId, BlkSize};
+
+    #[test]
+    fn test_add_offset() {
+        let addr = Addr::new(1, 100)
+            .add_offset(1000, BlkSize::new(512))
+            .unwrap();
+        assert_eq!(addr.blk_id, 3);
+        assert_eq!(addr.offset_of_blk, 76);
+    }
+
+    #[test]
+    fn test_add_offset_rejects_overflow() {
+        let blk_size = BlkSize::new(512);
+        assert!(Addr::new(BlkId::MAX, 0).add_offset(512, blk_size).is_none());
+        assert!(Addr::new(0, 1).add_offset(u32::MAX, blk_size).is_none());
+        assert!(Addr::zerod()
+            .add_offset(blk_size.mul(BlkId::MAX as u32 + 1), blk_size)
+            .is_none());
+        assert!(Addr::new(BlkId::MAX, 0)
+            .add(Addr::new(1, 0), blk_size)
+            .is_none());
+    }
+}
@@ -38,7 +38,7 @@ where
 
 impl<RwLockType> Disk for RamDisk<RwLockType>
 where
-    RwLockType: lock_api::RawRwLock,
+    RwLockType: lock_api::RawRwLock + 'static,
 {
     type ReadAtFut<'a> = future::Ready<DiskResult<u32>>;
 
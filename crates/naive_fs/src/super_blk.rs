@@ -8,8 +8,9 @@ use sleeplock::{Mutex, MutexGuard};
 use crate::{
     allocator::Allocator,
     blk_device::{self, BlkDevice, Disk, FromBytes, ReadBytesFut, ToBytes},
-    consts,
-    inode::RawInode,
+    checksum, consts,
+    inode::{InodeRecord, RawInode},
+    journal::{Journal, Transaction},
     maybe_dirty::{MaybeDirty, Syncable},
     root_inode_id, scoped, Addr, BlkId, BlkSize, Error, InodeId, Result,
 };
@@ -17,7 +18,7 @@ use byte_struct::*;
 use future_ext::{WithArg1, WithArg1Ext, WithArg3, WithArg3Ext};
 
 /// RawSuperBlock
-#[derive(ByteStruct)]
+#[derive(ByteStruct, Clone)]
 #[byte_struct_le]
 pub struct RawSuperBlk {
     pub inodes_count: u16,
@@ -39,6 +40,21 @@ pub struct RawSuperBlk {
     pub prealloc_dir_blocks: u8,
 }
 
+bitflags! {
+    pub struct Features: u16 {
+        /// The super block and the inodes carry a checksum that is verified on load.
+        /// Volumes created without it are loaded unverified.
+        const CHECKSUM = 0x1;
+        /// Metadata is written through the journal described by `RawSuperBlkExt`,
+        /// and a transaction left there by a crash is replayed on load.
+        const JOURNAL = 0x2;
+        /// The records of the inode table are `RawInode::BYTE_LEN` bytes long,
+        /// with the xattr block and the checksum. Without it they are `RawInode::BASE_LEN`
+        /// bytes long, and the inodes have neither extended attributes nor checksums.
+        const EXT_INODE = 0x4;
+    }
+}
+
 impl FromBytes for RawSuperBlk {
     const BYTES_LEN: usize = Self::BYTE_LEN;
 
@@ -93,18 +109,64 @@ impl ToBytes for RawDescriptor {
     }
 }
 
+/// The fields added to the super block since the first on-disk layout.
+/// They follow the descriptor, in the space that volumes of the first layout
+/// leave zeroed, so that those load with no features.
+#[derive(ByteStruct, Default)]
+#[byte_struct_le]
+pub struct RawSuperBlkExt {
+    /// Optional features the volume was created with, see `Features`.
+    pub features: u16,
+    /// First block of the journal, only used with `Features::JOURNAL`.
+    pub journal_blk: BlkId,
+    /// Number of blocks of the journal, 0 if there is none.
+    pub journal_blks_count: u16,
+    /// CRC32C of the super block and of the fields above, written on every store.
+    pub checksum: u32,
+}
+
+impl FromBytes for RawSuperBlkExt {
+    const BYTES_LEN: usize = Self::BYTE_LEN;
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        Some(Self::read_bytes(bytes))
+    }
+}
+
+impl ToBytes for RawSuperBlkExt {
+    fn to_bytes(&self, out: &mut [u8]) {
+        self.write_bytes(out);
+    }
+
+    fn bytes_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+}
+
 #[repr(u16)]
 pub enum OnError {
     /// Pretend nothing has happened
-    #[allow(dead_code)]
     Continue = 1,
     /// Remount as read-only
     MountAsRo = 2,
     /// Causing Kernel Panic
-    #[allow(dead_code)]
     Panic = 3,
 }
 
+impl From<u16> for OnError {
+    fn from(raw: u16) -> Self {
+        match raw {
+            1 => Self::Continue,
+            3 => Self::Panic,
+            // Unknown policies fall back to the safest one.
+            _ => Self::MountAsRo,
+        }
+    }
+}
+
 impl Default for RawSuperBlk {
     fn default() -> Self {
         Self {
@@ -126,6 +188,28 @@ impl RawSuperBlk {
     }
 }
 
+impl RawSuperBlkExt {
+    /// Returns the features of the volume, `Error::Unsupported` if it has unknown ones.
+    pub fn features(&self) -> Result<Features> {
+        Features::from_bits(self.features).ok_or(Error::Unsupported)
+    }
+
+    /// Returns the checksum of `raw_super_blk` and of `self`, not counting its checksum field.
+    pub fn checksum_of(&self, raw_super_blk: &RawSuperBlk) -> u32 {
+        let mut bytes = vec![0; RawSuperBlk::BYTE_LEN + Self::BYTE_LEN];
+        let (super_blk_bytes, ext_bytes) = bytes.split_at_mut(RawSuperBlk::BYTE_LEN);
+        raw_super_blk.write_bytes(super_blk_bytes);
+        self.write_bytes(ext_bytes);
+        checksum::crc32c(&bytes[..bytes.len() - checksum::CHECKSUM_LEN])
+    }
+
+    /// Returns false if the volume is checksummed and the stored checksum is wrong.
+    pub fn checksum_ok(&self, raw_super_blk: &RawSuperBlk) -> bool {
+        self.features & Features::CHECKSUM.bits() == 0
+            || self.checksum == self.checksum_of(raw_super_blk)
+    }
+}
+
 impl RawDescriptor {
     #[allow(dead_code)]
     pub fn block_full(&self) -> bool {
@@ -154,13 +238,26 @@ impl<DK: Disk + Sync> Syncable<DK> for RawDescriptor {
     }
 }
 
+impl<DK: Disk + Sync> Syncable<DK> for RawSuperBlkExt {
+    type SyncFut<'a> = impl future::Future<Output = Result<()>> + 'a;
+
+    fn sync<'a>(&'a self, _blk_device: &'a BlkDevice<DK>) -> Self::SyncFut<'a> {
+        async { Ok(()) }
+    }
+}
+
 pub struct SuperBlk<MutexType> {
-    pub raw_super_blk: MaybeDirty<RawSuperBlk>,
+    /// Locked so that `NaiveFs::resize` can grow the volume while it is shared.
+    /// It is copied out to be written, the lock is never held across an await.
+    pub raw_super_blk: lock_api::Mutex<MutexType, MaybeDirty<RawSuperBlk>>,
+    pub features: Features,
     pub inode_table: BlkId,
 
     pub blk_ids_count_pre_blk: u32,
     pub bytes_per_indirect_blk: BlkSize,
 
+    pub(crate) journal: Option<Journal>,
+
     pub(crate) blk_id_allocator: Mutex<MutexType, Allocator>,
     pub(crate) inode_id_allocator: Mutex<MutexType, Allocator>,
 }
@@ -168,8 +265,10 @@ pub struct SuperBlk<MutexType> {
 impl<MutexType: lock_api::RawMutex> SuperBlk<MutexType> {
     pub(crate) fn new(
         raw_super_blk: RawSuperBlk,
+        features: Features,
         is_dirty: bool,
         inode_table: BlkId,
+        journal: Option<Journal>,
         blk_id_allocator: Allocator,
         inode_id_allocator: Allocator,
     ) -> Self {
@@ -184,12 +283,15 @@ impl<MutexType: lock_api::RawMutex> SuperBlk<MutexType> {
             BlkSize::new(raw_super_blk.blk_size().mul(blk_ids_count_pre_blk));
 
         Self {
-            raw_super_blk,
+            raw_super_blk: lock_api::Mutex::new(raw_super_blk),
+            features,
 
             inode_table,
             blk_ids_count_pre_blk,
             bytes_per_indirect_blk,
 
+            journal,
+
             blk_id_allocator: Mutex::new(blk_id_allocator),
             inode_id_allocator: Mutex::new(inode_id_allocator),
         }
@@ -199,19 +301,32 @@ impl<MutexType: lock_api::RawMutex> SuperBlk<MutexType> {
         disk: DK,
         read_only: bool,
     ) -> Result<(SuperBlk<MutexType>, BlkDevice<DK>)> {
-        let raw_super_blk =
-            blk_device::read_val_at::<DK, RawSuperBlk>(&disk, consts::SUPER_BLK_OFFSET)
-                .await
-                .map_err(Error::DiskError)?;
-
-        let raw_descriptor =
-            blk_device::read_val_at::<DK, RawDescriptor>(&disk, raw_descriptor_offset())
-                .await
-                .map_err(Error::DiskError)?;
+        let (mut raw_super_blk, mut raw_descriptor, raw_ext) = load_raw(&disk).await?;
+        let features = raw_ext.features()?;
+        let journal = if features.contains(Features::JOURNAL) && raw_ext.journal_blks_count > 0 {
+            Some(Journal {
+                start: raw_ext.journal_blk,
+                blks_count: raw_ext.journal_blks_count,
+            })
+        } else {
+            None
+        };
+        // The replayed transaction may rewrite the super block and the descriptor.
+        // A read-only mount must not write, and the volume is inconsistent without
+        // the replay, so it is refused until the volume is mounted writable once.
+        if let Some(journal) = journal {
+            if read_only {
+                if journal.is_pending(&disk, raw_super_blk.blk_size()).await? {
+                    return Err(Error::ReadOnly);
+                }
+            } else if journal.replay(&disk, raw_super_blk.blk_size()).await? {
+                (raw_super_blk, raw_descriptor, _) = load_raw(&disk).await?;
+            }
+        }
 
         let blk_device = BlkDevice::new(disk, raw_super_blk.blk_size(), read_only);
 
-        let blk_id_allocator = load_allocator(
+        let mut blk_id_allocator = load_allocator(
             raw_descriptor.blk_bitmap,
             raw_super_blk.blks_count,
             raw_descriptor.free_blks_count,
@@ -219,7 +334,7 @@ impl<MutexType: lock_api::RawMutex> SuperBlk<MutexType> {
         )
         .await?;
 
-        let inode_id_allocator = load_allocator(
+        let mut inode_id_allocator = load_allocator(
             raw_descriptor.inode_bitmap,
             raw_super_blk.inodes_count,
             raw_descriptor.free_inodes_count,
@@ -227,11 +342,29 @@ impl<MutexType: lock_api::RawMutex> SuperBlk<MutexType> {
         )
         .await?;
 
+        // The free counts in the descriptor can drift from the bitmaps,
+        // e.g. after a crash between syncing the bitmaps and the descriptor.
+        // The allocators always continue with the bitmap-derived counts.
+        let inconsistent =
+            blk_id_allocator.repair_free().is_some() | inode_id_allocator.repair_free().is_some();
+        let mut is_dirty = false;
+        if inconsistent {
+            match OnError::from(raw_super_blk.on_error) {
+                OnError::Continue => is_dirty = !read_only,
+                OnError::MountAsRo => blk_device.set_read_only(true),
+                OnError::Panic => {
+                    panic!("naive_fs: free counts in the descriptor disagree with the bitmaps")
+                }
+            }
+        }
+
         Ok((
             Self::new(
                 raw_super_blk,
-                false,
+                features,
+                is_dirty,
                 raw_descriptor.inode_table,
+                journal,
                 blk_id_allocator,
                 inode_id_allocator,
             ),
@@ -239,7 +372,7 @@ impl<MutexType: lock_api::RawMutex> SuperBlk<MutexType> {
         ))
     }
 
-    pub fn create_blank(raw_super_blk: RawSuperBlk) -> Self {
+    pub fn create_blank(raw_super_blk: RawSuperBlk, features: Features) -> Self {
         let mut blk_id_allocator = Allocator::new(
             MaybeDirty::new(
                 Addr::new(consts::BLK_BITMAP_BLK_ID, 0),
@@ -251,9 +384,19 @@ impl<MutexType: lock_api::RawMutex> SuperBlk<MutexType> {
 
         let inode_table_blk_count = raw_super_blk
             .blk_size()
-            .div_round_up_by(raw_super_blk.inodes_count as u32 * RawInode::BYTE_LEN as u32)
+            .div_round_up_by(raw_super_blk.inodes_count as u32 * inode_len(features))
             as u16;
-        let reserved_blk_ids = consts::INODE_TABLE_BLK_ID + inode_table_blk_count;
+        let journal = features.contains(Features::JOURNAL).then(|| Journal {
+            start: consts::INODE_TABLE_BLK_ID + inode_table_blk_count,
+            blks_count: Journal::blks_count_for(
+                raw_super_blk.blks_count,
+                raw_super_blk.inodes_count,
+                raw_super_blk.blk_size(),
+            ),
+        });
+        let reserved_blk_ids = consts::INODE_TABLE_BLK_ID
+            + inode_table_blk_count
+            + journal.map_or(0, |journal| journal.blks_count);
         //  Pre allocate the reserved blk ids
         for _ in 1..=reserved_blk_ids {
             blk_id_allocator.alloc();
@@ -275,8 +418,10 @@ impl<MutexType: lock_api::RawMutex> SuperBlk<MutexType> {
 
         Self::new(
             raw_super_blk,
+            features,
             true,
             consts::INODE_TABLE_BLK_ID,
+            journal,
             blk_id_allocator,
             inode_id_allocator,
         )
@@ -284,8 +429,8 @@ impl<MutexType: lock_api::RawMutex> SuperBlk<MutexType> {
 
     fn raw_descriptor(
         &self,
-        blk_id_allocator: MutexGuard<MutexType, Allocator>,
-        inode_id_allocator: MutexGuard<MutexType, Allocator>,
+        blk_id_allocator: &Allocator,
+        inode_id_allocator: &Allocator,
     ) -> MaybeDirty<RawDescriptor> {
         let raw_descriptor = {
             RawDescriptor {
@@ -300,6 +445,52 @@ impl<MutexType: lock_api::RawMutex> SuperBlk<MutexType> {
         MaybeDirty::new(Addr::new(0, raw_descriptor_offset()), raw_descriptor)
     }
 
+    /// Builds the extension of `raw_super_blk`, which holds its checksum,
+    /// so it is written whenever the super block is.
+    fn raw_ext(&self, raw_super_blk: &RawSuperBlk) -> MaybeDirty<RawSuperBlkExt> {
+        let mut raw_ext = RawSuperBlkExt {
+            features: self.features.bits(),
+            journal_blk: self.journal.map_or(0, |journal| journal.start),
+            journal_blks_count: self.journal.map_or(0, |journal| journal.blks_count),
+            checksum: 0,
+        };
+        raw_ext.checksum = raw_ext.checksum_of(raw_super_blk);
+
+        MaybeDirty::new(Addr::new(0, raw_ext_offset()), raw_ext)
+    }
+
+    /// Returns the length of the records of the inode table.
+    pub fn inode_len(&self) -> u32 {
+        inode_len(self.features)
+    }
+
+    /// Returns the number of inodes the blocks of an inode table of `inodes_count`
+    /// records can hold, the unused tail of its last block included.
+    pub(crate) fn inode_table_capacity(&self, inodes_count: u16) -> u16 {
+        let blk_size = self.blk_size();
+        let table_len =
+            blk_size.mul(blk_size.div_round_up_by(inodes_count as u32 * self.inode_len()));
+        (table_len / self.inode_len()).min(u16::MAX as u32) as u16
+    }
+
+    pub fn blk_size(&self) -> BlkSize {
+        self.raw_super_blk.lock().blk_size()
+    }
+
+    /// Copies the super block out of its lock, together with its dirty flag.
+    /// Once the copy is written, `settle_raw_super_blk` carries the flag back.
+    fn raw_super_blk_copy(&self) -> MaybeDirty<RawSuperBlk> {
+        let raw_super_blk = self.raw_super_blk.lock();
+        let copy = MaybeDirty::new(raw_super_blk.addr, RawSuperBlk::clone(&raw_super_blk));
+        copy.set_dirty(raw_super_blk.is_dirty());
+        copy
+    }
+
+    fn settle_raw_super_blk(&self, copy: MaybeDirty<RawSuperBlk>) {
+        self.raw_super_blk.lock().set_dirty(copy.is_dirty());
+        copy.set_dirty(false);
+    }
+
     #[allow(clippy::type_complexity)]
     pub(crate) fn alloc_blk(
         &self,
@@ -331,7 +522,6 @@ impl<MutexType: lock_api::RawMutex> SuperBlk<MutexType> {
             })
     }
 
-    #[allow(dead_code)]
     #[allow(clippy::type_complexity)]
     pub(crate) fn dealloc_blk(
         &self,
@@ -391,23 +581,57 @@ impl<MutexType: lock_api::RawMutex> SuperBlk<MutexType> {
     }
 }
 
+/// Reads the super block, the descriptor and the extension of the super block
+/// straight from the disk.
+async fn load_raw<DK: Disk>(disk: &DK) -> Result<(RawSuperBlk, RawDescriptor, RawSuperBlkExt)> {
+    let raw_super_blk = blk_device::read_val_at::<DK, RawSuperBlk>(disk, consts::SUPER_BLK_OFFSET)
+        .await
+        .map_err(Error::DiskError)?;
+    let raw_descriptor =
+        blk_device::read_val_at::<DK, RawDescriptor>(disk, raw_descriptor_offset())
+            .await
+            .map_err(Error::DiskError)?;
+    let raw_ext = blk_device::read_val_at::<DK, RawSuperBlkExt>(disk, raw_ext_offset())
+        .await
+        .map_err(Error::DiskError)?;
+    // The `on_error` policy is part of the corrupted super block, so it is not trusted.
+    if !raw_ext.checksum_ok(&raw_super_blk) {
+        return Err(Error::ChecksumMismatch);
+    }
+    Ok((raw_super_blk, raw_descriptor, raw_ext))
+}
+
 const fn raw_descriptor_offset() -> u32 {
     consts::SUPER_BLK_OFFSET + RawSuperBlk::BYTES_LEN as u32
 }
 
-impl<MutexType> SuperBlk<MutexType> {
-    /// Calculates the Addr for a given `offset`
-    pub fn position(&self, offset: u32) -> Addr {
-        let blk_n = self.raw_super_blk.blk_size().div_by(offset) as BlkId;
-        let offset_of_block = self.raw_super_blk.blk_size().mod_by(offset) as u32;
-        Addr::new(blk_n, offset_of_block)
+const fn raw_ext_offset() -> u32 {
+    raw_descriptor_offset() + RawDescriptor::BYTES_LEN as u32
+}
+
+fn inode_len(features: Features) -> u32 {
+    if features.contains(Features::EXT_INODE) {
+        RawInode::BYTE_LEN as u32
+    } else {
+        RawInode::BASE_LEN as u32
     }
+}
 
-    pub fn raw_inode_addr(&self, inode_id: InodeId) -> Addr {
-        Addr::new(self.inode_table, 0).add_offset(
-            inode_id as u32 * RawInode::BYTE_LEN as u32,
-            self.raw_super_blk.blk_size(),
-        )
+impl<MutexType: lock_api::RawMutex> SuperBlk<MutexType> {
+    /// Calculates the Addr for a given `offset`,
+    /// None if `offset` is beyond the last addressable block.
+    pub fn position(&self, offset: u32) -> Option<Addr> {
+        Addr::zerod().add_offset(offset, self.blk_size())
+    }
+
+    /// Returns `Error::Corrupt` if the inode table entry of `inode_id` is not addressable.
+    pub fn raw_inode_addr(&self, inode_id: InodeId) -> Result<Addr> {
+        let offset = (inode_id as u32)
+            .checked_mul(self.inode_len())
+            .ok_or(Error::Corrupt)?;
+        Addr::new(self.inode_table, 0)
+            .add_offset(offset, self.blk_size())
+            .ok_or(Error::Corrupt)
     }
 }
 
@@ -418,23 +642,118 @@ where
     type SyncFut<'a> = impl future::Future<Output = Result<()>> + 'a where MutexType: 'a;
 
     fn sync<'a>(&'a self, blk_device: &'a BlkDevice<DK>) -> Self::SyncFut<'a> {
-        async move {
-            let blk_id_allocator = scoped!(&self.blk_id_allocator).lock().await;
-            let inode_id_allocator = scoped!(&self.inode_id_allocator).lock().await;
-            let super_blk_is_dirty = self.raw_super_blk.is_dirty();
-            scoped!(&self.raw_super_blk).sync(blk_device).await?;
-
-            blk_id_allocator.sync(blk_device).await?;
-            inode_id_allocator.sync(blk_device).await?;
-
-            let raw_descriptor = self.raw_descriptor(blk_id_allocator, inode_id_allocator);
-            if super_blk_is_dirty {
-                raw_descriptor.set_dirty(true);
-                raw_descriptor.sync(blk_device).await?;
+        self.sync_with_inode(blk_device, None)
+    }
+}
+
+impl<MutexType: lock_api::RawMutex> SuperBlk<MutexType> {
+    /// Writes `raw_inode`, if any, and the dirty super block and bitmaps back.
+    /// With a journal they are written as one transaction, together with the descriptor
+    /// whenever a bitmap changed, so that its free counts always match the bitmaps.
+    pub(crate) async fn sync_with_inode<DK: Disk + Sync>(
+        &self,
+        blk_device: &BlkDevice<DK>,
+        raw_inode: Option<&MaybeDirty<InodeRecord>>,
+    ) -> Result<()> {
+        match self.journal {
+            Some(journal) => {
+                self.commit_with_inode(journal, blk_device, raw_inode, Vec::new())
+                    .await
+            }
+            None => {
+                if let Some(raw_inode) = raw_inode {
+                    raw_inode.sync(blk_device).await?;
+                }
+                self.sync_unjournaled(blk_device).await
             }
-            Ok(())
         }
     }
+
+    /// Writes `writes`, the blocks of `raw_inode` that describe the file system
+    /// such as its indirect block or its directory entries, in the same transaction
+    /// as the inode and the dirty super block and bitmaps.
+    /// Without a journal they are written one after another.
+    pub(crate) async fn sync_blks_with_inode<DK: Disk + Sync>(
+        &self,
+        blk_device: &BlkDevice<DK>,
+        raw_inode: &MaybeDirty<InodeRecord>,
+        writes: Vec<(Addr, Vec<u8>)>,
+    ) -> Result<()> {
+        match self.journal {
+            Some(journal) => {
+                self.commit_with_inode(journal, blk_device, Some(raw_inode), writes)
+                    .await
+            }
+            None => {
+                for (addr, bytes) in writes {
+                    blk_device.write_at(addr, &bytes).await?;
+                }
+                self.sync_with_inode(blk_device, Some(raw_inode)).await
+            }
+        }
+    }
+
+    async fn commit_with_inode<DK: Disk + Sync>(
+        &self,
+        journal: Journal,
+        blk_device: &BlkDevice<DK>,
+        raw_inode: Option<&MaybeDirty<InodeRecord>>,
+        writes: Vec<(Addr, Vec<u8>)>,
+    ) -> Result<()> {
+        let blk_id_allocator = scoped!(&self.blk_id_allocator).lock().await;
+        let inode_id_allocator = scoped!(&self.inode_id_allocator).lock().await;
+        let raw_super_blk = self.raw_super_blk_copy();
+        let raw_descriptor = self.raw_descriptor(&blk_id_allocator, &inode_id_allocator);
+        raw_descriptor.set_dirty(
+            raw_super_blk.is_dirty()
+                || blk_id_allocator.bitmap().is_dirty()
+                || inode_id_allocator.bitmap().is_dirty(),
+        );
+        let raw_ext = self.raw_ext(&raw_super_blk);
+        raw_ext.set_dirty(raw_super_blk.is_dirty());
+
+        let mut txn = Transaction::default();
+        for (addr, bytes) in writes {
+            txn.add_bytes(addr, bytes);
+        }
+        if let Some(raw_inode) = raw_inode {
+            txn.add(raw_inode);
+        }
+        txn.add(&raw_super_blk);
+        txn.add(blk_id_allocator.bitmap());
+        txn.add(inode_id_allocator.bitmap());
+        txn.add(&raw_descriptor);
+        txn.add(&raw_ext);
+        let res = journal.commit(blk_device, txn).await;
+        self.settle_raw_super_blk(raw_super_blk);
+        // The descriptor and the extension are rebuilt on every sync.
+        raw_descriptor.set_dirty(false);
+        raw_ext.set_dirty(false);
+        res
+    }
+
+    async fn sync_unjournaled<DK: Disk + Sync>(&self, blk_device: &BlkDevice<DK>) -> Result<()> {
+        let blk_id_allocator = scoped!(&self.blk_id_allocator).lock().await;
+        let inode_id_allocator = scoped!(&self.inode_id_allocator).lock().await;
+        let raw_super_blk = self.raw_super_blk_copy();
+        let super_blk_is_dirty = raw_super_blk.is_dirty();
+        let res = raw_super_blk.sync(blk_device).await;
+        self.settle_raw_super_blk(raw_super_blk);
+        res?;
+
+        blk_id_allocator.sync(blk_device).await?;
+        inode_id_allocator.sync(blk_device).await?;
+
+        let raw_descriptor = self.raw_descriptor(&blk_id_allocator, &inode_id_allocator);
+        if super_blk_is_dirty {
+            raw_descriptor.set_dirty(true);
+            raw_descriptor.sync(blk_device).await?;
+            let raw_ext = self.raw_ext(&self.raw_super_blk.lock());
+            raw_ext.set_dirty(true);
+            raw_ext.sync(blk_device).await?;
+        }
+        Ok(())
+    }
 }
 
 type LoadAllocatorFut<'a, DK> = Map<
@@ -450,7 +769,8 @@ fn load_allocator<DK: Disk>(
 ) -> LoadAllocatorFut<'_, DK> {
     let addr = Addr::new(bitmap_blk_id, 0);
     blk_device
-        .read_bytes(addr, crate::div_round_up!(capacity as u32, u8::BITS))
+        // The bitmap is stored as whole u64 words.
+        .read_bytes(addr, crate::div_round_up!(capacity as u32, u64::BITS) * 8)
         .with_arg3(addr, capacity, free)
         .map(|(bitmap_bytes_res, addr, capacity, free)| {
             bitmap_bytes_res.map(|bitmap_bytes| {
@@ -462,3 +782,200 @@ fn load_allocator<DK: Disk>(
             })
         })
 }
+
+#[cfg(test)]
+mod test {
+    use bitmap::Bitmap;
+    use tokio_test::block_on;
+
+    use super::{
+        raw_descriptor_offset, raw_ext_offset, Features, OnError, RawDescriptor, RawSuperBlk,
+        RawSuperBlkExt, SuperBlk,
+    };
+    use crate::{
+        blk_device::{self, Disk, ToBytes},
+        consts,
+        maybe_dirty::Syncable,
+        ram_disk::RamDisk,
+        BlkId, BlkSize, Error,
+    };
+
+    type TestDisk = RamDisk<spin::RwLock<()>>;
+
+    #[test]
+    fn test_load_repairs_free_counts() {
+        let disk = image_with_wrong_free_counts(OnError::Continue);
+        let (super_blk, blk_device) =
+            block_on(SuperBlk::<spin::Mutex<()>>::load(disk, false)).unwrap();
+
+        assert!(!blk_device.read_only());
+        assert_eq!(block_on(super_blk.blk_id_allocator.lock()).free(), 61);
+        assert_eq!(block_on(super_blk.inode_id_allocator.lock()).free(), 62);
+
+        // The repaired counts are written back on the next sync.
+        block_on(super_blk.sync(&blk_device)).unwrap();
+        let raw_descriptor = block_on(blk_device::read_val_at::<_, RawDescriptor>(
+            blk_device.disk(),
+            raw_descriptor_offset(),
+        ))
+        .unwrap();
+        assert_eq!(raw_descriptor.free_blks_count, 61);
+        assert_eq!(raw_descriptor.free_inodes_count, 62);
+    }
+
+    #[test]
+    fn test_load_mounts_as_ro_on_wrong_free_counts() {
+        let disk = image_with_wrong_free_counts(OnError::MountAsRo);
+        let (super_blk, blk_device) =
+            block_on(SuperBlk::<spin::Mutex<()>>::load(disk, false)).unwrap();
+
+        assert!(blk_device.read_only());
+        assert!(!super_blk.raw_super_blk.lock().is_dirty());
+        assert_eq!(block_on(super_blk.blk_id_allocator.lock()).free(), 61);
+        assert_eq!(block_on(super_blk.inode_id_allocator.lock()).free(), 62);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_load_panics_on_wrong_free_counts() {
+        let disk = image_with_wrong_free_counts(OnError::Panic);
+        let _ = block_on(SuperBlk::<spin::Mutex<()>>::load(disk, false));
+    }
+
+    #[test]
+    fn test_raw_inode_addr_rejects_overflow() {
+        let super_blk = SuperBlk::<spin::Mutex<()>>::new(
+            RawSuperBlk::default(),
+            Features::empty(),
+            false,
+            BlkId::MAX,
+            None,
+            Default::default(),
+            Default::default(),
+        );
+        assert!(super_blk.raw_inode_addr(0).is_ok());
+        assert!(matches!(
+            super_blk.raw_inode_addr(1000),
+            Err(Error::Corrupt)
+        ));
+    }
+
+    #[test]
+    fn test_super_blk_checksum_round_trips() {
+        let disk = image_with_wrong_free_counts(OnError::Continue);
+        let raw_super_blk = block_on(blk_device::read_val_at::<_, RawSuperBlk>(
+            &disk,
+            consts::SUPER_BLK_OFFSET,
+        ))
+        .unwrap();
+        let raw_ext = block_on(blk_device::read_val_at::<_, RawSuperBlkExt>(
+            &disk,
+            raw_ext_offset(),
+        ))
+        .unwrap();
+
+        assert!(raw_ext.features().unwrap().contains(Features::CHECKSUM));
+        assert_ne!(raw_ext.checksum, 0);
+        assert!(raw_ext.checksum_ok(&raw_super_blk));
+    }
+
+    #[test]
+    fn test_load_rejects_unknown_features() {
+        let disk = image_with_wrong_free_counts(OnError::Continue);
+        let raw_super_blk = block_on(blk_device::read_val_at::<_, RawSuperBlk>(
+            &disk,
+            consts::SUPER_BLK_OFFSET,
+        ))
+        .unwrap();
+        // A feature this driver does not know, as written by a newer one.
+        let mut raw_ext = RawSuperBlkExt {
+            features: Features::CHECKSUM.bits() | 0x100,
+            ..Default::default()
+        };
+        raw_ext.checksum = raw_ext.checksum_of(&raw_super_blk);
+        write(&disk, raw_ext_offset(), &raw_ext);
+
+        assert!(matches!(
+            block_on(SuperBlk::<spin::Mutex<()>>::load(disk, false)),
+            Err(Error::Unsupported)
+        ));
+    }
+
+    #[test]
+    fn test_load_detects_flipped_super_blk() {
+        let disk = image_with_wrong_free_counts(OnError::Continue);
+        // A bit of the volume name.
+        flip_bit(&disk, consts::SUPER_BLK_OFFSET + 23);
+
+        assert!(matches!(
+            block_on(SuperBlk::<spin::Mutex<()>>::load(disk, false)),
+            Err(Error::ChecksumMismatch)
+        ));
+    }
+
+    /// Writes an image of 64 blocks and 64 inodes with 3 blocks and 2 inodes in use,
+    /// whose descriptor claims that everything is free.
+    fn image_with_wrong_free_counts(on_error: OnError) -> TestDisk {
+        let blk_size = BlkSize::<u32>::new(512);
+        let disk = TestDisk::new(blk_size.mul(64));
+
+        let raw_super_blk = RawSuperBlk {
+            inodes_count: 64,
+            blks_count: 64,
+            blk_size_log2: blk_size.blk_size_log2,
+            on_error: on_error as u16,
+            ..Default::default()
+        };
+        write(&disk, consts::SUPER_BLK_OFFSET, &raw_super_blk);
+
+        let raw_descriptor = RawDescriptor {
+            blk_bitmap: consts::BLK_BITMAP_BLK_ID,
+            inode_bitmap: consts::INODE_BITMAP_BLK_ID,
+            inode_table: consts::INODE_TABLE_BLK_ID,
+            free_blks_count: 64,
+            free_inodes_count: 64,
+            ..Default::default()
+        };
+        write(&disk, raw_descriptor_offset(), &raw_descriptor);
+
+        let mut raw_ext = RawSuperBlkExt {
+            features: Features::CHECKSUM.bits(),
+            ..Default::default()
+        };
+        raw_ext.checksum = raw_ext.checksum_of(&raw_super_blk);
+        write(&disk, raw_ext_offset(), &raw_ext);
+
+        write(
+            &disk,
+            blk_size.mul(consts::BLK_BITMAP_BLK_ID as u32),
+            &bitmap_with_ones(64, 3),
+        );
+        write(
+            &disk,
+            blk_size.mul(consts::INODE_BITMAP_BLK_ID as u32),
+            &bitmap_with_ones(64, 2),
+        );
+        disk
+    }
+
+    fn bitmap_with_ones(nbits: u32, ones: u32) -> Bitmap {
+        let mut bitmap = Bitmap::new(nbits);
+        for offset in 0..ones {
+            bitmap.test_and_set(offset, true);
+        }
+        bitmap
+    }
+
+    fn write<T: ToBytes>(disk: &TestDisk, offset: u32, val: &T) {
+        let mut bytes = vec![0; val.bytes_len()];
+        val.to_bytes(&mut bytes);
+        block_on(disk.write_at(offset, &bytes)).unwrap();
+    }
+
+    fn flip_bit(disk: &TestDisk, offset: u32) {
+        let mut byte = [0];
+        block_on(disk.read_at(offset, &mut byte)).unwrap();
+        byte[0] ^= 1;
+        block_on(disk.write_at(offset, &byte)).unwrap();
+    }
+}
@@ -0,0 +1,80 @@
+use core::convert::TryInto;
+
+use alloc::{collections::BTreeMap, vec::Vec};
+
+/// The extended attributes of an inode, stored in a single block:
+/// | count: u16 | name_len: u8 | value_len: u16 | name | value | ...
+#[derive(Debug, Default)]
+pub(crate) struct Xattrs(BTreeMap<Vec<u8>, Vec<u8>>);
+
+impl Xattrs {
+    /// Parse the xattr block, a truncated entry ends the list.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut xattrs = BTreeMap::new();
+        let count = match bytes.get(..2) {
+            Some(count) => u16::from_le_bytes(count.try_into().unwrap()),
+            None => return Self(xattrs),
+        };
+
+        let mut offset = 2;
+        for _ in 0..count {
+            let header = match bytes.get(offset..offset + 3) {
+                Some(header) => header,
+                None => break,
+            };
+            let name_len = header[0] as usize;
+            let value_len = u16::from_le_bytes([header[1], header[2]]) as usize;
+            let name_start = offset + 3;
+            let value_start = name_start + name_len;
+            let end = value_start + value_len;
+            if end > bytes.len() {
+                break;
+            }
+            xattrs.insert(
+                bytes[name_start..value_start].to_vec(),
+                bytes[value_start..end].to_vec(),
+            );
+            offset = end;
+        }
+        Self(xattrs)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.bytes_len());
+        bytes.extend_from_slice(&(self.0.len() as u16).to_le_bytes());
+        for (name, value) in &self.0 {
+            bytes.push(name.len() as u8);
+            bytes.extend_from_slice(&(value.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(name);
+            bytes.extend_from_slice(value);
+        }
+        bytes
+    }
+
+    /// Returns the number of bytes `to_bytes` produces.
+    pub fn bytes_len(&self) -> usize {
+        self.0
+            .iter()
+            .fold(2, |len, (name, value)| len + 3 + name.len() + value.len())
+    }
+
+    pub fn get(&self, name: &[u8]) -> Option<&Vec<u8>> {
+        self.0.get(name)
+    }
+
+    pub fn insert(&mut self, name: Vec<u8>, value: Vec<u8>) -> Option<Vec<u8>> {
+        self.0.insert(name, value)
+    }
+
+    pub fn remove(&mut self, name: &[u8]) -> Option<Vec<u8>> {
+        self.0.remove(name)
+    }
+
+    pub fn names(&self) -> Vec<Vec<u8>> {
+        self.0.keys().cloned().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
@@ -0,0 +1,84 @@
+use crate::Error;
+
+bitflags! {
+    pub struct CloneFlags: usize {
+        /// Share the address space.
+        const VM = 0x0000_0100;
+        /// Share the file descriptor table.
+        const FILES = 0x0000_0400;
+        /// Share the signal handlers, requires `VM`.
+        const SIGHAND = 0x0000_0800;
+        /// Create a thread of the calling process, requires `SIGHAND`.
+        const THREAD = 0x0001_0000;
+        /// Set the thread pointer of the child to `tls`.
+        const SETTLS = 0x0008_0000;
+        /// Store the id of the child at `ptid`.
+        const PARENT_SETTID = 0x0010_0000;
+        /// Clear the id of the child at `ctid` when it exits, as `set_tid_address` does.
+        const CHILD_CLEARTID = 0x0020_0000;
+    }
+}
+
+bitflags! {
+    /// What a forked process shares with its parent instead of getting a copy.
+    pub struct ShareFlags: u8 {
+        /// The address space
+        const MEMORY = 0x1;
+        /// The file descriptor table
+        const FILES = 0x2;
+    }
+}
+
+/// What `clone` creates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CloneKind {
+    /// A thread of the calling process, which shares everything with the other threads.
+    Thread,
+    /// A process sharing these with its parent and getting a copy of the rest.
+    Process(ShareFlags),
+}
+
+impl CloneFlags {
+    /// What `clone` creates with the flags.
+    /// Without `THREAD`, the new process shares what `VM` and `FILES` ask for.
+    pub fn kind(&self) -> Result<CloneKind, Error> {
+        if self.contains(Self::THREAD) && !self.contains(Self::SIGHAND)
+            || self.contains(Self::SIGHAND) && !self.contains(Self::VM)
+        {
+            return Err(Error::InvalidArgument);
+        }
+        if self.contains(Self::THREAD) {
+            return Ok(CloneKind::Thread);
+        }
+        let mut share = ShareFlags::empty();
+        share.set(ShareFlags::MEMORY, self.contains(Self::VM));
+        share.set(ShareFlags::FILES, self.contains(Self::FILES));
+        Ok(CloneKind::Process(share))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CloneFlags, CloneKind, ShareFlags};
+    use crate::Error;
+
+    #[test]
+    fn a_thread_shares_the_address_space_and_a_fork_copies_it() {
+        let thread = CloneFlags::VM | CloneFlags::SIGHAND | CloneFlags::THREAD;
+        assert_eq!(thread.kind(), Ok(CloneKind::Thread));
+        assert_eq!(
+            CloneFlags::empty().kind(),
+            Ok(CloneKind::Process(ShareFlags::empty()))
+        );
+        assert_eq!(
+            (CloneFlags::VM | CloneFlags::FILES).kind(),
+            Ok(CloneKind::Process(ShareFlags::MEMORY | ShareFlags::FILES))
+        );
+
+        assert_eq!(
+            (CloneFlags::VM | CloneFlags::THREAD).kind(),
+            Err(Error::InvalidArgument)
+        );
+        assert_eq!((CloneFlags::SIGHAND).kind(), Err(Error::InvalidArgument));
+    }
+}
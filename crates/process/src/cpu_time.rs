@@ -0,0 +1,137 @@
+use core::time::Duration;
+
+/// CPU time used by a process, split between user mode and the kernel working for it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CpuTimes {
+    pub user: Duration,
+    pub system: Duration,
+}
+
+impl CpuTimes {
+    pub fn add(&mut self, other: &CpuTimes) {
+        self.user += other.user;
+        self.system += other.system;
+    }
+}
+
+/// The calling process, `who` of `getrusage`.
+pub const RUSAGE_SELF: isize = 0;
+/// The terminated children of the calling process that were reaped.
+pub const RUSAGE_CHILDREN: isize = -1;
+
+/// The CPU time used by a process and by the children it reaped.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CpuUsage {
+    own: CpuTimes,
+    children: CpuTimes,
+}
+
+impl CpuUsage {
+    /// Charges `times` to the process.
+    pub fn charge(&mut self, times: &CpuTimes) {
+        self.own.add(times);
+    }
+
+    /// Counts the time of the reaped child `child` and of the children it reaped
+    /// to the children of the process.
+    pub fn reap(&mut self, child: &CpuUsage) {
+        self.children.add(&child.own);
+        self.children.add(&child.children);
+    }
+
+    /// The CPU time `getrusage` reports for `who`, None if `who` is not one of
+    /// `RUSAGE_SELF` and `RUSAGE_CHILDREN`.
+    pub fn get(&self, who: isize) -> Option<CpuTimes> {
+        match who {
+            RUSAGE_SELF => Some(self.own),
+            RUSAGE_CHILDREN => Some(self.children),
+            _ => None,
+        }
+    }
+}
+
+/// Measures the time a thread is polled for on the clock `now`,
+/// the time spent in user mode as user time and the rest as system time.
+pub struct CpuTimer<C> {
+    now: C,
+    start: Duration,
+    user: Duration,
+}
+
+impl<C: Fn() -> Duration> CpuTimer<C> {
+    pub fn start(now: C) -> Self {
+        let start = now();
+        Self {
+            now,
+            start,
+            user: Duration::ZERO,
+        }
+    }
+
+    /// Runs `user`, which returns once the thread leaves user mode.
+    pub fn run_user<T>(&mut self, user: impl FnOnce() -> T) -> T {
+        let start = (self.now)();
+        let ret = user();
+        self.user += (self.now)().saturating_sub(start);
+        ret
+    }
+
+    /// The time measured since the timer started.
+    pub fn elapsed(&self) -> CpuTimes {
+        let polled = (self.now)().saturating_sub(self.start);
+        CpuTimes {
+            user: self.user,
+            system: polled.saturating_sub(self.user),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::{cell::Cell, time::Duration};
+
+    use super::{CpuTimer, CpuTimes, CpuUsage, RUSAGE_CHILDREN, RUSAGE_SELF};
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn times(user: u64, system: u64) -> CpuTimes {
+        CpuTimes {
+            user: ms(user),
+            system: ms(system),
+        }
+    }
+
+    #[test]
+    fn time_in_user_mode_is_user_time_and_the_rest_system_time() {
+        let clock = Cell::new(ms(100));
+        let mut timer = CpuTimer::start(|| clock.get());
+        clock.set(ms(102));
+        let trap = timer.run_user(|| {
+            clock.set(ms(110));
+            7
+        });
+        assert_eq!(trap, 7);
+        clock.set(ms(113));
+        assert_eq!(timer.elapsed(), times(8, 5));
+    }
+
+    #[test]
+    fn rusage_children_counts_only_reaped_children() {
+        let mut parent = CpuUsage::default();
+        let mut child = CpuUsage::default();
+        let mut grandchild = CpuUsage::default();
+        parent.charge(&times(1, 2));
+        child.charge(&times(10, 20));
+        grandchild.charge(&times(100, 200));
+        assert_eq!(parent.get(RUSAGE_CHILDREN), Some(CpuTimes::default()));
+
+        child.reap(&grandchild);
+        parent.reap(&child);
+        assert_eq!(parent.get(RUSAGE_SELF), Some(times(1, 2)));
+        assert_eq!(parent.get(RUSAGE_CHILDREN), Some(times(110, 220)));
+        // RUSAGE_THREAD
+        assert_eq!(parent.get(1), None);
+    }
+}
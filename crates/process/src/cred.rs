@@ -0,0 +1,115 @@
+use crate::Error;
+
+/// The user and group ids a process runs as.
+/// Permission checks use the effective ids, the real ids tell who started the process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+    pub euid: u32,
+    pub egid: u32,
+}
+
+impl Credentials {
+    pub const fn root() -> Self {
+        Self {
+            uid: 0,
+            gid: 0,
+            euid: 0,
+            egid: 0,
+        }
+    }
+
+    /// Whether the process may act as any user.
+    pub fn privileged(&self) -> bool {
+        self.euid == 0
+    }
+
+    /// Whether the process may send signals to a process running as `target`.
+    pub fn may_signal(&self, target: &Credentials) -> bool {
+        self.privileged() || self.uid == target.uid || self.euid == target.uid
+    }
+
+    /// The owner and group of the files the process creates, the ids its permission
+    /// checks use.
+    pub fn fs_ids(&self) -> (u32, u32) {
+        (self.euid, self.egid)
+    }
+
+    /// Sets the user ids, as `setuid` does.
+    /// A privileged process sets both the real and effective uid to any id and
+    /// gives up its privilege doing so, others may only switch the effective uid back to the real uid.
+    pub fn set_uid(&mut self, uid: u32) -> Result<(), Error> {
+        if self.privileged() {
+            self.uid = uid;
+        } else if uid != self.uid {
+            return Err(Error::NotPermitted);
+        }
+        self.euid = uid;
+        Ok(())
+    }
+
+    /// Sets the group ids, as `setgid` does, see `set_uid`.
+    pub fn set_gid(&mut self, gid: u32) -> Result<(), Error> {
+        if self.privileged() {
+            self.gid = gid;
+        } else if gid != self.gid {
+            return Err(Error::NotPermitted);
+        }
+        self.egid = gid;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Credentials;
+    use crate::Error;
+
+    #[test]
+    fn root_gives_up_its_privilege_with_setuid() {
+        let mut cred = Credentials::root();
+        assert_eq!(cred.set_gid(100), Ok(()));
+        assert_eq!(cred.set_uid(1000), Ok(()));
+        assert!(!cred.privileged());
+        assert_eq!(
+            (cred.uid, cred.euid, cred.gid, cred.egid),
+            (1000, 1000, 100, 100)
+        );
+        // Created files belong to the new ids.
+        assert_eq!(cred.fs_ids(), (1000, 100));
+
+        assert_eq!(cred.set_uid(0), Err(Error::NotPermitted));
+        assert_eq!(cred.set_gid(0), Err(Error::NotPermitted));
+        assert_eq!(cred.euid, 1000);
+    }
+
+    #[test]
+    fn an_unprivileged_process_may_only_switch_back_to_its_real_ids() {
+        let mut cred = Credentials {
+            uid: 1000,
+            gid: 100,
+            euid: 1001,
+            egid: 101,
+        };
+        assert_eq!(cred.fs_ids(), (1001, 101));
+        assert_eq!(cred.set_uid(1002), Err(Error::NotPermitted));
+        assert_eq!(cred.set_uid(1000), Ok(()));
+        assert_eq!(cred.set_gid(100), Ok(()));
+        assert_eq!(cred.fs_ids(), (1000, 100));
+    }
+
+    #[test]
+    fn signals_go_to_processes_of_the_same_user() {
+        let user = |uid, euid| Credentials {
+            uid,
+            gid: 100,
+            euid,
+            egid: 100,
+        };
+        assert!(user(1000, 1000).may_signal(&user(1000, 0)));
+        assert!(user(1001, 1000).may_signal(&user(1000, 1000)));
+        assert!(!user(1001, 1001).may_signal(&user(1000, 1000)));
+        assert!(Credentials::root().may_signal(&user(1000, 1000)));
+    }
+}
@@ -0,0 +1,87 @@
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+
+use spinlock::{Irq, RwLockIrq};
+
+use crate::{Pid, Process};
+
+/// The parent and the children of a process.
+pub struct Family<I, P> {
+    parent: RwLockIrq<I, Option<Arc<P>>>,
+    children: RwLockIrq<I, BTreeMap<Pid, Arc<P>>>,
+}
+
+impl<I, P> Family<I, P> {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            parent: RwLockIrq::new(None),
+            children: RwLockIrq::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl<I: Irq, P: Process<Irq = I>> Family<I, P> {
+    /// Makes `child` a child of `parent`, e.g. once `parent` forked it.
+    pub fn adopt(parent: &Arc<P>, child: Arc<P>) {
+        *child.family().parent.write() = Some(parent.clone());
+        parent.family().children.write().insert(child.pid(), child);
+    }
+
+    pub fn parent(&self) -> Option<Arc<P>> {
+        self.parent.read().clone()
+    }
+
+    /// The id of the parent, 0 if the process has none as `getppid` reports it.
+    pub fn ppid(&self) -> Pid {
+        self.parent.read().as_ref().map_or(0, |parent| parent.pid())
+    }
+
+    pub fn child(&self, pid: &Pid) -> Option<Arc<P>> {
+        self.children.read().get(pid).cloned()
+    }
+
+    pub fn children(&self) -> Vec<Arc<P>> {
+        self.children.read().values().cloned().collect()
+    }
+
+    /// Removes the child `pid`, once it is reaped.
+    pub fn remove_child(&self, pid: &Pid) -> Option<Arc<P>> {
+        self.children.write().remove(pid)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+
+    use crate::{
+        mock::{fork, TestProc},
+        Process,
+    };
+
+    #[test]
+    fn a_forked_child_reports_its_parent() {
+        let init = TestProc::new(1);
+        assert_eq!(init.family().ppid(), 0);
+        assert!(init.family().parent().is_none());
+
+        let child = fork(&init, 2);
+        let grandchild = fork(&child, 3);
+        assert_eq!(child.family().ppid(), init.pid());
+        assert_eq!(grandchild.family().ppid(), child.pid());
+        assert!(init.family().child(&3).is_none());
+        assert_eq!(child.family().children().len(), 1);
+    }
+
+    #[test]
+    fn a_removed_child_is_no_longer_listed() {
+        let init = TestProc::new(1);
+        fork(&init, 2);
+        fork(&init, 3);
+        let reaped = init.family().remove_child(&2).unwrap();
+        assert_eq!(reaped.pid(), 2);
+        assert!(init.family().remove_child(&2).is_none());
+        let pids: Vec<_> = init.family().children().iter().map(|c| c.pid()).collect();
+        assert_eq!(pids, [3]);
+    }
+}
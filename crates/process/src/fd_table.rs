@@ -0,0 +1,208 @@
+use alloc::vec::Vec;
+
+/// The files a process has open, by file descriptor number.
+#[derive(Clone)]
+pub struct FdTable<F> {
+    max_fd: usize,
+    next_fd: usize,
+    /// File descriptor numbers are below `limit`, the `RLIMIT_NOFILE` of the process.
+    limit: usize,
+    files: Vec<Option<F>>,
+}
+
+impl<F> FdTable<F> {
+    pub const fn new(limit: usize) -> Self {
+        Self {
+            max_fd: 0,
+            next_fd: 0,
+            limit,
+            files: Vec::new(),
+        }
+    }
+
+    /// Limit the file descriptor numbers of new files to below `limit`.
+    /// Files that are already open are kept.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+    }
+
+    pub fn get(&self, fd_num: usize) -> Option<&F> {
+        self.files.get(fd_num)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, fd_num: usize) -> Option<&mut F> {
+        self.files.get_mut(fd_num)?.as_mut()
+    }
+
+    /// Returns the open files with their file descriptor numbers.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &F)> {
+        self.files
+            .iter()
+            .enumerate()
+            .filter_map(|(fd_num, file)| Some((fd_num, file.as_ref()?)))
+    }
+
+    /// Add a file to the lowest available slot.
+    /// Return the file descriptor number or None if no slot was found
+    pub fn add(&mut self, file: F) -> Option<usize> {
+        self.insert(self.next_fd, file)
+    }
+
+    /// Add a file to the lowest available slot greater than or equal to min.
+    /// Return the file descriptor number or None if no slot was found
+    pub fn add_min(&mut self, file: F, min: usize) -> Option<usize> {
+        let fd_num = if min <= self.next_fd {
+            self.next_fd
+        } else {
+            self.files
+                .iter()
+                .skip(min)
+                .position(Option::is_none)
+                .map(|pos| min + pos)
+                .unwrap_or_else(|| self.files.len().max(min))
+        };
+
+        self.insert(fd_num, file)
+    }
+
+    /// Insert a file with a specific fd number.
+    /// Return the file descriptor number or None if the slot was not empty, or fd_num was invalid
+    pub fn insert(&mut self, fd_num: usize, file: F) -> Option<usize> {
+        if fd_num < self.limit {
+            if fd_num >= self.files.len() {
+                self.files.resize_with(fd_num + 1, || None);
+            }
+
+            let slot = unsafe { self.files.get_unchecked_mut(fd_num) };
+
+            if slot.is_none() {
+                slot.replace(file);
+
+                if fd_num == self.next_fd {
+                    self.next_fd = self
+                        .files
+                        .iter()
+                        .skip(self.next_fd + 1)
+                        .position(Option::is_none)
+                        .map(|pos| self.next_fd + 1 + pos)
+                        .unwrap_or(self.files.len())
+                }
+
+                if fd_num > self.max_fd {
+                    self.max_fd = fd_num;
+                }
+
+                return Some(fd_num);
+            }
+        }
+        None
+    }
+
+    /// Place a file at a specific fd number, removing the file that was there.
+    /// Return the removed file or None if fd_num was invalid
+    pub fn replace(&mut self, fd_num: usize, file: F) -> Option<Option<F>> {
+        if fd_num >= self.limit {
+            return None;
+        }
+        let removed_file = self.remove(fd_num);
+        self.insert(fd_num, file).map(|_| removed_file)
+    }
+
+    pub fn remove(&mut self, fd_num: usize) -> Option<F> {
+        let removed_file = self.files.get_mut(fd_num).and_then(|f| f.take());
+        if removed_file.is_some() {
+            if fd_num == self.max_fd {
+                let max_fd = self.files.iter().rposition(Option::is_some).unwrap_or(0);
+                self.files.truncate(max_fd + 1);
+                self.files.shrink_to_fit();
+                self.max_fd = max_fd;
+            }
+
+            if fd_num < self.next_fd {
+                self.next_fd = fd_num
+            }
+        }
+
+        removed_file
+    }
+
+    /// Remove the files `pred` returns true for.
+    pub fn remove_if(&mut self, mut pred: impl FnMut(&F) -> bool) -> Vec<F> {
+        let fds: Vec<usize> = self
+            .iter()
+            .filter(|(_, file)| pred(file))
+            .map(|(fd_num, _)| fd_num)
+            .collect();
+        fds.into_iter()
+            .filter_map(|fd_num| self.remove(fd_num))
+            .collect()
+    }
+
+    /// Remove all files.
+    pub fn remove_all(&mut self) -> Vec<F> {
+        let files = self.files.drain(..).flatten().collect();
+        self.max_fd = 0;
+        self.next_fd = 0;
+        files
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::FdTable;
+
+    #[test]
+    fn lowering_the_limit_refuses_new_files_but_keeps_open_ones() {
+        let mut table = FdTable::new(8);
+        for fd_num in 0..4 {
+            assert_eq!(table.add(fd_num), Some(fd_num));
+        }
+
+        table.set_limit(3);
+        // EMFILE
+        assert_eq!(table.add(4), None);
+        assert_eq!(table.add_min(4, 1), None);
+        assert_eq!(table.get(3), Some(&3));
+
+        // A slot freed below the limit is reused.
+        assert_eq!(table.remove(1), Some(1));
+        assert_eq!(table.add(5), Some(1));
+        assert_eq!(table.replace(3, 6), None);
+        assert_eq!(table.get(3), Some(&3));
+        assert_eq!(table.replace(2, 6), Some(Some(2)));
+    }
+
+    #[test]
+    fn files_take_the_lowest_free_number() {
+        let mut table = FdTable::new(8);
+        for fd_num in 0..3 {
+            assert_eq!(table.add(fd_num), Some(fd_num));
+        }
+        assert_eq!(table.remove(0), Some(0));
+        assert_eq!(table.add_min(10, 1), Some(3));
+        assert_eq!(table.add_min(11, 6), Some(6));
+        assert_eq!(table.add(12), Some(0));
+        assert_eq!(table.insert(6, 13), None);
+
+        assert_eq!(table.remove_if(|file| file % 2 == 0), [12, 2, 10]);
+        let open: std::vec::Vec<_> = table.iter().collect();
+        assert_eq!(open, [(1, &1), (6, &11)]);
+        assert_eq!(table.remove_all(), [1, 11]);
+        assert_eq!(table.add(14), Some(0));
+    }
+
+    #[test]
+    fn dupfd_takes_the_lowest_free_number_at_or_above_its_argument() {
+        let mut table = FdTable::new(8);
+        for fd_num in 0..3 {
+            assert_eq!(table.add(fd_num), Some(fd_num));
+        }
+        assert_eq!(table.add_min(10, 5), Some(5));
+        assert_eq!(table.add_min(11, 5), Some(6));
+        assert_eq!(table.add_min(12, 0), Some(3));
+        assert_eq!(table.add_min(13, 7), Some(7));
+        // EMFILE, no free number below the limit.
+        assert_eq!(table.add_min(14, 5), None);
+        assert_eq!(table.add_min(15, 8), None);
+    }
+}
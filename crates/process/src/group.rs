@@ -0,0 +1,253 @@
+use alloc::{sync::Arc, vec::Vec};
+
+use spinlock::{Irq, RwLockIrq};
+
+use crate::{Error, Pid, Process};
+
+/// The process group and session a process belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProcGroup {
+    /// Process group id, the id of the group leader
+    pub pgid: Pid,
+    /// Session id, the id of the session leader
+    pub sid: Pid,
+}
+
+/// The process group of a process, and the leader of the group.
+pub struct Group<I, P> {
+    ids: RwLockIrq<I, ProcGroup>,
+    /// The leader of the process group, `None` if the process leads its own group.
+    leader: RwLockIrq<I, Option<Arc<P>>>,
+}
+
+impl<I, P> Group<I, P> {
+    /// The group of the process `pid`, which leads the group and its session.
+    pub fn new(pid: Pid) -> Self {
+        Self::with_ids(ProcGroup {
+            pgid: pid,
+            sid: pid,
+        })
+    }
+
+    fn with_ids(ids: ProcGroup) -> Self {
+        Self {
+            ids: RwLockIrq::new(ids),
+            leader: RwLockIrq::new(None),
+        }
+    }
+}
+
+impl<I: Irq, P: Process<Irq = I>> Group<I, P> {
+    /// The group of a child forked by the process, the child is in the same session and
+    /// joins the group with `join` once it has an id.
+    pub fn fork(&self) -> Self {
+        Self::with_ids(self.ids())
+    }
+
+    pub fn ids(&self) -> ProcGroup {
+        *self.ids.read()
+    }
+
+    pub fn pgid(&self) -> Pid {
+        self.ids.read().pgid
+    }
+
+    pub fn sid(&self) -> Pid {
+        self.ids.read().sid
+    }
+}
+
+/// Returns the leader of the process group of `proc`.
+pub fn leader<P: Process>(proc: &Arc<P>) -> Arc<P> {
+    let leader = proc.group().leader.read().clone();
+    leader.unwrap_or_else(|| proc.clone())
+}
+
+/// Moves `proc` into the group led by `leader` within its session,
+/// `None` makes the process the leader of a new group.
+pub fn join<P: Process>(proc: &P, leader: Option<Arc<P>>) {
+    let group = proc.group();
+    group.ids.write().pgid = leader.as_ref().map_or(proc.pid(), |leader| leader.pid());
+    *group.leader.write() = leader;
+}
+
+pub fn is_session_leader<P: Process>(proc: &P) -> bool {
+    proc.group().sid() == proc.pid()
+}
+
+/// Returns the processes of `procs` in the process group `pgid`.
+pub fn members<P: Process>(procs: impl IntoIterator<Item = Arc<P>>, pgid: Pid) -> Vec<Arc<P>> {
+    procs
+        .into_iter()
+        .filter(|proc| proc.group().pgid() == pgid)
+        .collect()
+}
+
+/// Moves the process `pid` (the caller if 0) into the process group `pgid`
+/// (a new group led by `pid` if 0), as `setpgid` does. `members` lists the processes
+/// of a group. The process must be the caller or one of its children, and the group
+/// must belong to the same session.
+pub fn set_pgid<P: Process>(
+    caller: &Arc<P>,
+    pid: Pid,
+    pgid: Pid,
+    members: impl FnOnce(Pid) -> Vec<Arc<P>>,
+) -> Result<(), Error> {
+    let proc = if pid == 0 || pid == caller.pid() {
+        caller.clone()
+    } else {
+        caller.family().child(&pid).ok_or(Error::NoSuchProcess)?
+    };
+    let sid = proc.group().sid();
+    if is_session_leader(&*proc) || sid != caller.group().sid() {
+        return Err(Error::NotPermitted);
+    }
+
+    let pgid = if pgid == 0 { proc.pid() } else { pgid };
+    let leader = if pgid == proc.pid() {
+        None
+    } else {
+        let member = members(pgid)
+            .into_iter()
+            .find(|member| member.group().sid() == sid)
+            .ok_or(Error::NotPermitted)?;
+        Some(leader(&member))
+    };
+    join(&*proc, leader);
+    Ok(())
+}
+
+/// Starts a new session led by `proc`, as `setsid` does, `members` lists the processes
+/// of a group. Fails if the id of `proc` is already used as a process group id.
+/// Returns the id of the session.
+pub fn set_sid<P: Process>(
+    proc: &P,
+    members: impl FnOnce(Pid) -> Vec<Arc<P>>,
+) -> Result<Pid, Error> {
+    if !members(proc.pid()).is_empty() {
+        return Err(Error::NotPermitted);
+    }
+    let group = proc.group();
+    *group.ids.write() = ProcGroup {
+        pgid: proc.pid(),
+        sid: proc.pid(),
+    };
+    *group.leader.write() = None;
+    Ok(proc.pid())
+}
+
+/// What a terminal does with a read or a write by a process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TtyAccess {
+    /// The access goes ahead.
+    Allowed,
+    /// The group of the process is sent SIGTTIN for a read, SIGTTOU for a write, to stop it.
+    Stop,
+    /// The read fails.
+    Denied,
+}
+
+/// A background process group of the session controlled by a terminal may not use it.
+/// `session` is the session the terminal controls and `foreground` its foreground group,
+/// `ignored` tells whether the process in `caller` ignores or blocks the signal that
+/// would stop it: reads fail then and writes go ahead.
+pub fn tty_access(
+    caller: ProcGroup,
+    session: Option<Pid>,
+    foreground: Option<Pid>,
+    read: bool,
+    ignored: bool,
+) -> TtyAccess {
+    if session != Some(caller.sid) || foreground.unwrap_or(caller.pgid) == caller.pgid {
+        TtyAccess::Allowed
+    } else if !ignored {
+        TtyAccess::Stop
+    } else if read {
+        TtyAccess::Denied
+    } else {
+        TtyAccess::Allowed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, vec::Vec};
+
+    use super::{
+        is_session_leader, leader, members, set_pgid, set_sid, tty_access, ProcGroup, TtyAccess,
+    };
+    use crate::{
+        mock::{fork, TestProc},
+        Error, Pid, Process,
+    };
+
+    fn pids(procs: &[Arc<TestProc>]) -> Vec<Pid> {
+        procs.iter().map(|proc| proc.pid()).collect()
+    }
+
+    #[test]
+    fn a_group_signal_reaches_every_member() {
+        let init = TestProc::new(1);
+        let (a, b, other) = (fork(&init, 2), fork(&init, 3), fork(&init, 4));
+        let procs = [init.clone(), a.clone(), b.clone(), other.clone()];
+        let group_of = |pgid| members(procs.iter().cloned(), pgid);
+
+        // `a` starts a group, `b` joins it.
+        assert_eq!(set_pgid(&init, 2, 0, group_of), Ok(()));
+        assert_eq!(set_pgid(&init, 3, 2, group_of), Ok(()));
+        assert_eq!(b.group().pgid(), 2);
+        assert!(Arc::ptr_eq(&leader(&b), &a));
+
+        // `kill(-2, sig)` signals the members of the group 2.
+        assert_eq!(pids(&group_of(2)), [2, 3]);
+        assert_eq!(pids(&group_of(1)), [1, 4]);
+    }
+
+    #[test]
+    fn setpgid_only_moves_the_caller_and_its_children_within_the_session() {
+        let init = TestProc::new(1);
+        let child = fork(&init, 2);
+        let grandchild = fork(&child, 3);
+        let procs = [init.clone(), child.clone(), grandchild.clone()];
+        let group_of = |pgid| members(procs.iter().cloned(), pgid);
+
+        assert_eq!(set_pgid(&init, 3, 0, group_of), Err(Error::NoSuchProcess));
+        // A session leader stays in its group.
+        assert_eq!(set_pgid(&init, 0, 2, group_of), Err(Error::NotPermitted));
+        // There is no group 7 to join.
+        assert_eq!(set_pgid(&init, 2, 7, group_of), Err(Error::NotPermitted));
+
+        assert_eq!(set_pgid(&init, 2, 0, group_of), Ok(()));
+        // The group 2 is led by `child`, it may not start a session.
+        assert_eq!(set_sid(&*child, group_of), Err(Error::NotPermitted));
+        assert_eq!(set_pgid(&child, 3, 0, group_of), Ok(()));
+        assert_eq!(set_pgid(&child, 0, 1, group_of), Ok(()));
+        assert_eq!(set_sid(&*child, group_of), Ok(2));
+        assert!(is_session_leader(&*child));
+
+        // `grandchild` is in another session than the one of `child` now.
+        assert_eq!(set_pgid(&child, 3, 2, group_of), Err(Error::NotPermitted));
+        assert_eq!(grandchild.group().ids().sid, 1);
+    }
+
+    #[test]
+    fn a_background_read_stops_the_group_with_sigttin() {
+        let background = ProcGroup { pgid: 2, sid: 1 };
+        let foreground = ProcGroup { pgid: 1, sid: 1 };
+        let access = |caller, read, ignored| tty_access(caller, Some(1), Some(1), read, ignored);
+
+        assert_eq!(access(background, true, false), TtyAccess::Stop);
+        assert_eq!(access(foreground, true, false), TtyAccess::Allowed);
+        // A read that can not stop the group fails, a write goes ahead.
+        assert_eq!(access(background, true, true), TtyAccess::Denied);
+        assert_eq!(access(background, false, true), TtyAccess::Allowed);
+
+        // The terminal does not control the session of the caller.
+        let other = ProcGroup { pgid: 5, sid: 5 };
+        assert_eq!(access(other, true, false), TtyAccess::Allowed);
+        assert_eq!(
+            tty_access(background, None, None, true, false),
+            TtyAccess::Allowed
+        );
+    }
+}
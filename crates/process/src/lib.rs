@@ -0,0 +1,58 @@
+//! The bookkeeping of processes and threads: their ids, the processes they are related to,
+//! the process groups and sessions they belong to, the users they run as,
+//! the files they have open, the limits on the resources they use and the CPU time they use.
+
+#![no_std]
+
+#[cfg(test)]
+extern crate std;
+
+extern crate alloc;
+#[macro_use]
+extern crate bitflags;
+
+mod clone;
+mod cpu_time;
+mod cred;
+mod family;
+mod fd_table;
+pub mod group;
+#[cfg(test)]
+mod mock;
+mod rlimit;
+mod tid;
+
+pub use clone::{CloneFlags, CloneKind, ShareFlags};
+pub use cpu_time::{CpuTimer, CpuTimes, CpuUsage, RUSAGE_CHILDREN, RUSAGE_SELF};
+pub use cred::Credentials;
+pub use family::Family;
+pub use fd_table::FdTable;
+pub use group::{Group, ProcGroup};
+pub use rlimit::{resource, RLimit, RLimits};
+pub use tid::{RawThreadId, TidAllocator};
+
+use spinlock::Irq;
+
+/// The id of a process, the id of its main thread.
+pub type Pid = RawThreadId;
+
+/// A process, as the bookkeeping of this crate sees it.
+pub trait Process: Sized {
+    type Irq: Irq;
+
+    fn pid(&self) -> Pid;
+
+    fn family(&self) -> &Family<Self::Irq, Self>;
+
+    fn group(&self) -> &Group<Self::Irq, Self>;
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    /// There is no process with the id.
+    NoSuchProcess,
+    /// The caller may not act on the process.
+    NotPermitted,
+    /// The request is malformed.
+    InvalidArgument,
+}
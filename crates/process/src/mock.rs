@@ -0,0 +1,59 @@
+//! Processes for the tests.
+
+use alloc::sync::Arc;
+
+use spinlock::Irq;
+
+use crate::{group, Family, Group, Pid, Process};
+
+pub struct TestIrq;
+
+impl Irq for TestIrq {
+    fn push_off() {}
+    fn pop_off() {}
+}
+
+pub struct TestProc {
+    pid: Pid,
+    family: Family<TestIrq, TestProc>,
+    group: Group<TestIrq, TestProc>,
+}
+
+impl TestProc {
+    /// Creates the process `pid`, which leads its own group and session.
+    pub fn new(pid: Pid) -> Arc<Self> {
+        Arc::new(Self {
+            pid,
+            family: Family::new(),
+            group: Group::new(pid),
+        })
+    }
+}
+
+impl Process for TestProc {
+    type Irq = TestIrq;
+
+    fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    fn family(&self) -> &Family<TestIrq, Self> {
+        &self.family
+    }
+
+    fn group(&self) -> &Group<TestIrq, Self> {
+        &self.group
+    }
+}
+
+/// Creates the process `pid` as a child of `parent`, in the group of `parent`.
+pub fn fork(parent: &Arc<TestProc>, pid: Pid) -> Arc<TestProc> {
+    let child = Arc::new(TestProc {
+        pid,
+        family: Family::new(),
+        group: parent.group.fork(),
+    });
+    group::join(&*child, Some(group::leader(parent)));
+    Family::adopt(parent, child.clone());
+    child
+}
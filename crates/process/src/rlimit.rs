@@ -0,0 +1,118 @@
+use core::{convert::TryFrom, mem};
+
+use crate::Error;
+
+/// Limit on the consumption of a resource, `struct rlimit`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RLimit {
+    /// Soft limit, the limit that is enforced
+    pub cur: u64,
+    /// Hard limit, the ceiling of the soft limit
+    pub max: u64,
+}
+
+impl RLimit {
+    pub const INFINITY: u64 = !0;
+
+    pub const fn new(cur: u64, max: u64) -> Self {
+        Self { cur, max }
+    }
+
+    pub const fn unlimited() -> Self {
+        Self::new(Self::INFINITY, Self::INFINITY)
+    }
+
+    /// The soft limit as a count or a size, `INFINITY` becomes `usize::MAX`.
+    pub fn cur_usize(&self) -> usize {
+        usize::try_from(self.cur).unwrap_or(usize::MAX)
+    }
+
+    /// Checks that the limit may be replaced by `new`, as `setrlimit` does.
+    /// The soft limit may not exceed the hard limit, and only a `privileged` process
+    /// may raise the hard limit, never above `ceiling`.
+    pub fn check_replace(&self, new: &RLimit, privileged: bool, ceiling: u64) -> Result<(), Error> {
+        if new.cur > new.max {
+            return Err(Error::InvalidArgument);
+        }
+        if (new.max > self.max && !privileged) || new.max > ceiling {
+            return Err(Error::NotPermitted);
+        }
+        Ok(())
+    }
+}
+
+/// Resources whose consumption is limited, values of `RLIMIT_*`.
+pub mod resource {
+    /// Size of the main thread stack
+    pub const STACK: usize = 3;
+    /// One more than the highest file descriptor number
+    pub const NOFILE: usize = 7;
+    /// Number of signals queued on each pending queue
+    pub const SIGPENDING: usize = 11;
+    /// Number of resources
+    pub const NLIMITS: usize = 16;
+}
+
+/// The resource limits of a process, inherited across `fork`.
+#[derive(Clone, Copy, Debug)]
+pub struct RLimits([RLimit; resource::NLIMITS]);
+
+impl RLimits {
+    /// Limits where no resource is limited.
+    pub const fn unlimited() -> Self {
+        Self([RLimit::unlimited(); resource::NLIMITS])
+    }
+
+    /// Returns the limit of `resource`, None if `resource` is not a `RLIMIT_*` value.
+    pub fn get(&self, resource: usize) -> Option<RLimit> {
+        self.0.get(resource).copied()
+    }
+
+    /// Replaces the limit of `resource`, returns the previous limit.
+    pub fn replace(&mut self, resource: usize, limit: RLimit) -> Option<RLimit> {
+        Some(mem::replace(self.0.get_mut(resource)?, limit))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{resource, RLimit, RLimits};
+    use crate::Error;
+
+    #[test]
+    fn getrlimit_reads_the_limit_setrlimit_replaced() {
+        let mut limits = RLimits::unlimited();
+        let nofile = RLimit::new(4, 8);
+        assert_eq!(
+            limits.replace(resource::NOFILE, nofile),
+            Some(RLimit::unlimited())
+        );
+        assert_eq!(limits.get(resource::NOFILE), Some(nofile));
+        assert_eq!(limits.get(resource::STACK), Some(RLimit::unlimited()));
+
+        assert_eq!(limits.get(resource::NLIMITS), None);
+        assert_eq!(limits.replace(resource::NLIMITS, nofile), None);
+    }
+
+    #[test]
+    fn only_a_privileged_process_raises_a_hard_limit() {
+        let old = RLimit::new(4, 8);
+        assert_eq!(old.check_replace(&RLimit::new(8, 8), false, 64), Ok(()));
+        assert_eq!(old.check_replace(&RLimit::new(2, 2), false, 64), Ok(()));
+        assert_eq!(
+            old.check_replace(&RLimit::new(4, 2), false, 64),
+            Err(Error::InvalidArgument)
+        );
+        assert_eq!(
+            old.check_replace(&RLimit::new(4, 16), false, 64),
+            Err(Error::NotPermitted)
+        );
+        assert_eq!(old.check_replace(&RLimit::new(4, 16), true, 64), Ok(()));
+        assert_eq!(
+            old.check_replace(&RLimit::new(4, 128), true, 64),
+            Err(Error::NotPermitted)
+        );
+        assert_eq!(RLimit::unlimited().cur_usize(), usize::MAX);
+    }
+}
@@ -0,0 +1,103 @@
+use bitmap::Bitmap;
+use spinlock::{Irq, MutexIrq};
+
+pub type RawThreadId = u32;
+
+/// A thread id allocator, the ids are below `max` and never 0.
+/// The deallocated thread id can be reallocated.
+pub struct TidAllocator<I>(MutexIrq<I, Inner>);
+
+struct Inner {
+    last_id: RawThreadId,
+    free: u32, // Number of unallocated ids.
+    max: RawThreadId,
+    // Once the ids reach `max`, allocation starts over above `reserved`.
+    reserved: RawThreadId,
+    tidmap: Bitmap,
+}
+
+impl<I: Irq> TidAllocator<I> {
+    pub fn new(max: RawThreadId, reserved: RawThreadId) -> Self {
+        Self(MutexIrq::new(Inner {
+            last_id: 0,
+            free: max - 1,
+            max,
+            reserved,
+            tidmap: Bitmap::new(max),
+        }))
+    }
+
+    /// Allocate thread id, return None means no thread id is free.
+    pub fn alloc(&self) -> Option<RawThreadId> {
+        let mut inner = self.0.lock();
+        if inner.free == 0 {
+            return None;
+        }
+        let mut id = if inner.last_id + 1 >= inner.max {
+            inner.reserved + 1
+        } else {
+            inner.last_id + 1
+        };
+
+        if inner.tidmap.test_and_set(id, true) {
+            // `id` has been allocated.
+            let max = Some(inner.max);
+            id = if let Some(newid) = inner.tidmap.find_next_zero(id, max) {
+                newid
+            } else {
+                inner.tidmap.find_next_zero(inner.reserved + 1, max)?
+            };
+            inner.tidmap.test_and_set(id, true);
+        }
+        inner.last_id = id;
+        inner.free -= 1;
+        Some(id)
+    }
+
+    /// Deallocate `thread_id`,
+    /// returns false indicating that the `thread_id` has been allocated or has never been allocated.
+    pub fn dealloc(&self, thread_id: RawThreadId) -> bool {
+        let mut inner = self.0.lock();
+        let old = inner.tidmap.test_and_set(thread_id, false);
+        inner.free += 1;
+        if inner.last_id == thread_id {
+            inner.last_id -= 1;
+        }
+        old
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+
+    use super::TidAllocator;
+    use crate::mock::TestIrq;
+
+    #[test]
+    fn threads_get_distinct_ids() {
+        let tids = TidAllocator::<TestIrq>::new(64, 8);
+        let ids: Vec<_> = (0..3).map(|_| tids.alloc().unwrap()).collect();
+        assert_eq!(ids, [1, 2, 3]);
+
+        // The ids after the last one are handed out before a freed one.
+        assert!(tids.dealloc(2));
+        assert_eq!(tids.alloc(), Some(4));
+    }
+
+    #[test]
+    fn ids_start_over_above_the_reserved_ones() {
+        let tids = TidAllocator::<TestIrq>::new(8, 2);
+        let ids: Vec<_> = (0..7).map(|_| tids.alloc().unwrap()).collect();
+        assert_eq!(ids, [1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(tids.alloc(), None);
+
+        for id in [2, 4, 5] {
+            assert!(tids.dealloc(id));
+        }
+        // Past the highest id, allocation starts over above the reserved ids, 2 stays free.
+        assert_eq!(tids.alloc(), Some(4));
+        assert_eq!(tids.alloc(), Some(5));
+        assert_eq!(tids.alloc(), None);
+    }
+}
@@ -0,0 +1,154 @@
+//! The software pseudo random pool behind `/dev/random`, `/dev/urandom` and `getrandom`.
+
+#![no_std]
+
+#[cfg(test)]
+extern crate std;
+
+use spinlock::{Irq, MutexIrq};
+
+/// The bytes generated under the pool lock at a time, a multiple of the 8 bytes of a word.
+pub const FILL_CHUNK: usize = 256;
+
+/// Fills `buf` with bytes from `pool`, seeding it with `seed` on first use.
+/// The bytes are generated into a buffer on the stack, a chunk at a time, and copied to
+/// `buf` without the lock held: `buf` may be a large user buffer, whose copy must not
+/// keep interrupts off.
+pub fn fill_from<I: Irq>(pool: &MutexIrq<I, Pool>, seed: impl Fn() -> u64, buf: &mut [u8]) {
+    let mut chunk = [0; FILL_CHUNK];
+    for dst in buf.chunks_mut(FILL_CHUNK) {
+        let chunk = &mut chunk[..dst.len()];
+        {
+            let mut pool = pool.lock();
+            if !pool.seeded {
+                pool.reseed(seed());
+            }
+            pool.fill(chunk);
+        }
+        dst.copy_from_slice(chunk);
+    }
+}
+
+/// A software pseudo random generator (xoshiro256**).
+/// It is not cryptographically secure, there is no hardware entropy source yet.
+pub struct Pool {
+    state: [u64; 4],
+    seeded: bool,
+}
+
+impl Pool {
+    /// Returns a pool that is seeded by the first `fill_from`.
+    pub const fn unseeded() -> Self {
+        Self {
+            state: [0; 4],
+            seeded: false,
+        }
+    }
+
+    /// Returns a pool producing the same bytes for the same `seed`.
+    pub fn with_seed(seed: u64) -> Self {
+        let mut pool = Self::unseeded();
+        pool.reseed(seed);
+        pool
+    }
+
+    fn reseed(&mut self, mut seed: u64) {
+        // Spread the seed over the whole state with splitmix64, it must not be all zeros.
+        for word in self.state.iter_mut() {
+            seed = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = seed;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            *word = z ^ (z >> 31);
+        }
+        self.seeded = true;
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::cell::Cell;
+    use std::vec;
+
+    use spinlock::Irq;
+
+    use super::{fill_from, Pool, FILL_CHUNK};
+
+    struct TestIrq;
+
+    impl Irq for TestIrq {
+        fn push_off() {}
+        fn pop_off() {}
+    }
+
+    type MutexIrq<T> = spinlock::MutexIrq<TestIrq, T>;
+
+    #[test]
+    fn same_seed_same_bytes() {
+        let (mut a, mut b) = ([0; 64], [0; 64]);
+        Pool::with_seed(42).fill(&mut a);
+        Pool::with_seed(42).fill(&mut b);
+        assert_eq!(a, b);
+        Pool::with_seed(43).fill(&mut b);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn chunked_fill_is_the_pool_stream() {
+        let len = 3 * FILL_CHUNK + 5;
+        let mut expected = vec![0; len];
+        Pool::with_seed(7).fill(&mut expected);
+
+        let pool = MutexIrq::new(Pool::with_seed(7));
+        let mut buf = vec![0; len];
+        fill_from(&pool, || unreachable!(), &mut buf);
+        assert_eq!(buf, expected);
+    }
+
+    #[test]
+    fn successive_fills_differ() {
+        let pool = MutexIrq::new(Pool::with_seed(7));
+        let (mut a, mut b) = ([0; 32], [0; 32]);
+        fill_from(&pool, || unreachable!(), &mut a);
+        fill_from(&pool, || unreachable!(), &mut b);
+        assert_ne!(a, b);
+        fill_from(&pool, || unreachable!(), &mut []);
+    }
+
+    #[test]
+    fn unseeded_pool_is_seeded_once() {
+        let pool = MutexIrq::new(Pool::unseeded());
+        let seeds = Cell::new(0);
+        let seed = || {
+            seeds.set(seeds.get() + 1);
+            7
+        };
+        let mut buf = vec![0; 2 * FILL_CHUNK];
+        fill_from(&pool, seed, &mut buf);
+        assert_eq!(seeds.get(), 1);
+
+        let mut expected = vec![0; 2 * FILL_CHUNK];
+        Pool::with_seed(7).fill(&mut expected);
+        assert_eq!(buf, expected);
+    }
+}
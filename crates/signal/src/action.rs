@@ -0,0 +1,102 @@
+use crate::{Info, SignalSet, Signo};
+
+const SIG_HANDLER_DFL: usize = 0;
+const SIG_HANDLER_IGN: usize = 1;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union SigHandler {
+    pub handler: extern "C" fn(usize),
+    pub info_handler: extern "C" fn(usize, *const Info),
+    // `SIG_DFL` and `SIG_IGN` are not functions.
+    addr: usize,
+}
+
+impl SigHandler {
+    pub fn default_handler() -> Self {
+        Self {
+            addr: SIG_HANDLER_DFL,
+        }
+    }
+
+    /// The `SIG_IGN` handler.
+    pub fn ignore_handler() -> Self {
+        Self {
+            addr: SIG_HANDLER_IGN,
+        }
+    }
+
+    pub fn is_ignored(&self, sig: &Signo) -> bool {
+        let handler = self.as_usize();
+        handler == SIG_HANDLER_IGN || (handler == SIG_HANDLER_DFL && sig.kernel_ignore())
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.as_usize() == SIG_HANDLER_DFL
+    }
+
+    /// Returns true if the handler is `SIG_IGN`, unlike `is_ignored`
+    /// it does not count the default handlers that ignore the signal.
+    pub fn is_sig_ign(&self) -> bool {
+        self.as_usize() == SIG_HANDLER_IGN
+    }
+
+    pub fn as_usize(&self) -> usize {
+        unsafe { self.addr }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone)]
+pub struct SigAction {
+    handler: Option<SigHandler>,
+    pub flags: SigActionFlags,
+    pub mask: SignalSet,
+}
+
+impl Default for SigAction {
+    fn default() -> Self {
+        Self {
+            handler: None,
+            flags: SigActionFlags::empty(),
+            mask: SignalSet::empty(),
+        }
+    }
+}
+
+impl SigAction {
+    pub fn handler(&self) -> SigHandler {
+        self.handler.unwrap_or_else(SigHandler::default_handler)
+    }
+
+    pub fn set_handler(&mut self, h: SigHandler) {
+        self.handler = Some(h)
+    }
+}
+
+bitflags! {
+    pub struct SigActionFlags: usize {
+        /// NOCLDSTOP flag to turn off SIGCHLD when children stop.
+        const NOCLDSTOP = 0x00000001;
+        /// NOCLDWAIT flag on SIGCHLD to inhibit zombies.
+        const NOCLDWAIT = 0x00000002;
+        /// SIGINFO delivers the signal with `Info` structs.
+        const SIGINFO = 0x00000004;
+        /// ONSTACK indicates that a registered `AltStack` will be used.
+        const ONSTACK = 0x08000000;
+        /// RESTART flag to get restarting signals (which were the default long ago)
+        const RESTART = 0x10000000;
+        /// NODEFER prevents the current signal from being masked in the handler.
+        const NODEFER = 0x40000000;
+        /// RESETHAND clears the handler when the signal is delivered.
+        const RESETHAND = 0x80000000;
+    }
+}
+
+/// Whether `sig` terminates the process when `action` is taken.
+pub fn sig_fatal(sig: &Signo, action: &SigAction) -> bool {
+    !Signo::MASK_SIG_KERNEL_IGNORE
+        .union(&Signo::MASK_SIG_KERNEL_STOP)
+        .contains(sig)
+        && action.handler().is_default()
+}
@@ -0,0 +1,84 @@
+/// The user context a signal handler interrupted in a syscall.
+pub trait SyscallContext {
+    /// The number of the interrupted syscall.
+    fn syscall_num(&self) -> usize;
+
+    /// Makes the interrupted syscall fail with EINTR.
+    fn interrupt(&mut self);
+}
+
+/// What a signal handler returns to, the syscall `S` it interrupted in the context `C`.
+pub struct SignalContext<C, S> {
+    pub arch_ctx: C,
+    /// The syscall interrupted by the handler, resumed by `rt_sigreturn`.
+    pub syscall: Option<S>,
+    /// The handler was registered with `SigActionFlags::RESTART`.
+    pub restart: bool,
+}
+
+impl<C: SyscallContext, S> SignalContext<C, S> {
+    pub fn new(arch_ctx: C, restart: bool) -> Self {
+        Self {
+            arch_ctx,
+            syscall: None,
+            restart,
+        }
+    }
+
+    /// Keeps `syscall`, interrupted by the handler, to resume it once the handler returns,
+    /// if the handler asked for it and `restartable` tells the syscall can be restarted.
+    /// Otherwise the syscall is dropped and the interrupted context sees it fail with EINTR.
+    pub fn interrupt_syscall(&mut self, syscall: S, restartable: impl FnOnce(usize) -> bool) {
+        if self.restart && restartable(self.arch_ctx.syscall_num()) {
+            self.syscall = Some(syscall);
+        } else {
+            self.arch_ctx.interrupt();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SignalContext, SyscallContext};
+
+    const SYS_READ: usize = 63;
+    const SYS_NANOSLEEP: usize = 101;
+    const EINTR: isize = 4;
+
+    struct TestContext {
+        num: usize,
+        ret: usize,
+    }
+
+    impl SyscallContext for TestContext {
+        fn syscall_num(&self) -> usize {
+            self.num
+        }
+
+        fn interrupt(&mut self) {
+            self.ret = -EINTR as usize;
+        }
+    }
+
+    // Interrupts the syscall `num`, which the handler of the signal wants restarted or not.
+    fn interrupt(num: usize, restart: bool) -> SignalContext<TestContext, &'static str> {
+        let mut sig_ctx = SignalContext::new(TestContext { num, ret: 0 }, restart);
+        sig_ctx.interrupt_syscall("syscall", |num| num == SYS_READ);
+        sig_ctx
+    }
+
+    #[test]
+    fn restartable_syscalls_are_resumed_after_a_restart_handler() {
+        let sig_ctx = interrupt(SYS_READ, true);
+        assert_eq!(sig_ctx.syscall, Some("syscall"));
+        assert_eq!(sig_ctx.arch_ctx.ret, 0);
+    }
+
+    #[test]
+    fn other_syscalls_fail_with_eintr() {
+        for sig_ctx in [interrupt(SYS_READ, false), interrupt(SYS_NANOSLEEP, true)] {
+            assert_eq!(sig_ctx.syscall, None);
+            assert_eq!(sig_ctx.arch_ctx.ret as isize, -EINTR);
+        }
+    }
+}
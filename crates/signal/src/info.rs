@@ -0,0 +1,176 @@
+use core::mem::ManuallyDrop;
+
+use mm::VirtualAddress;
+
+use crate::Signo;
+
+#[repr(C)]
+#[derive(Clone)]
+pub struct Info {
+    pub sig: Signo,
+    pub errno: usize,
+    pub code: isize,
+    pub(crate) fields: InfoFields,
+}
+
+impl Info {
+    /// Creates the info of a signal sent by `kill` from the process `pid`.
+    pub fn kill(sig: Signo, pid: u32) -> Self {
+        Self {
+            sig,
+            errno: 0,
+            code: SI_USER,
+            fields: InfoFields {
+                kill: ManuallyDrop::new(InfoFieldsKill { pid, uid: 0 }),
+            },
+        }
+    }
+
+    /// Creates the info of a signal sent by `tkill` or `tgkill` from the process `pid`.
+    pub fn tkill(sig: Signo, pid: u32) -> Self {
+        Self {
+            code: SI_TKILL,
+            ..Self::kill(sig, pid)
+        }
+    }
+
+    /// Creates the info of a signal generated by the kernel.
+    pub fn kernel(sig: Signo) -> Self {
+        Self {
+            code: SI_KERNEL,
+            ..Self::kill(sig, 0)
+        }
+    }
+
+    /// Creates the info of the `SIGCHLD` sent when the child `pid` of user `uid` changes state,
+    /// `code` is one of the `CLD_*` values and `status` the exit code or the signal.
+    pub fn child(pid: u32, uid: u32, code: isize, status: i32) -> Self {
+        Self {
+            sig: Signo::SIGCHLD,
+            errno: 0,
+            code,
+            fields: InfoFields {
+                child: ManuallyDrop::new(InfoFieldsChild {
+                    pid,
+                    uid,
+                    status,
+                    utime: 0,
+                    stime: 0,
+                }),
+            },
+        }
+    }
+
+    /// Creates the info of a signal raised by a memory access fault at `addr`.
+    pub fn fault(sig: Signo, addr: VirtualAddress) -> Self {
+        Self {
+            sig,
+            errno: 0,
+            code: SI_KERNEL,
+            fields: InfoFields {
+                fault: ManuallyDrop::new(InfoFieldsFault { addr }),
+            },
+        }
+    }
+}
+
+// si_code values
+// Digital reserves positive values for kernel-generated signals.
+
+/// sent by kill, sigsend, raise
+pub const SI_USER: isize = 0;
+/// sent by the kernel
+pub const SI_KERNEL: isize = 0x80;
+/// sent by sigqueue
+pub const SI_QUEUE: isize = -1;
+/// sent by timer expiration
+pub const SI_TIMER: isize = -2;
+/// sent by real time mesq state change
+pub const SI_MESGQ: isize = -3;
+/// sent by AIO completion
+pub const SI_ASYNCIO: isize = -4;
+/// sent by queued SIGIO
+pub const SI_SIGIO: isize = -5;
+/// sent by tkill system call
+pub const SI_TKILL: isize = -6;
+/// sent by execve() killing subsidiary threads
+pub const SI_DETHREAD: isize = -7;
+/// sent by glibc async name lookup completion
+pub const SI_ASYNCNL: isize = -60;
+
+// SIGCHLD si_codes
+
+/// child has exited
+pub const CLD_EXITED: isize = 1;
+/// child was killed
+pub const CLD_KILLED: isize = 2;
+/// child terminated abnormally
+pub const CLD_DUMPED: isize = 3;
+/// traced child has trapped
+pub const CLD_TRAPPED: isize = 4;
+/// child has stopped
+pub const CLD_STOPPED: isize = 5;
+/// stopped child has continued
+pub const CLD_CONTINUED: isize = 6;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union InfoFields {
+    /// Kill
+    pub(crate) kill: ManuallyDrop<InfoFieldsKill>,
+    /// POSIX.1b signals
+    rt: ManuallyDrop<InfoFieldsRt>,
+    /// SIGILL, SIGFPE, SIGSEGV, SIGBUS
+    pub(crate) fault: ManuallyDrop<InfoFieldsFault>,
+    /// SIGCHLD
+    child: ManuallyDrop<InfoFieldsChild>,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct InfoFieldsKill {
+    /// Sender's pid
+    pub(crate) pid: u32,
+    /// Sender's uid
+    uid: u32,
+}
+
+/// SIGILL, SIGFPE, SIGSEGV, SIGBUS
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct InfoFieldsFault {
+    /// Faulting memory address
+    pub(crate) addr: VirtualAddress,
+}
+
+/// SIGCHLD
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct InfoFieldsChild {
+    /// Which child
+    pid: u32,
+    /// Real uid of the child
+    uid: u32,
+    /// Exit code or signal
+    status: i32,
+    utime: isize,
+    stime: isize,
+}
+
+/// POSIX.1b signals
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct InfoFieldsRt {
+    /// Sender's pid
+    pid: u32,
+    /// Sender's uid
+    uid: u32,
+    val: InfoValue,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union InfoValue {
+    int: isize,
+    ptr: VirtualAddress,
+}
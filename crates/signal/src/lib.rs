@@ -0,0 +1,323 @@
+//! POSIX signals: the signal sets and actions, the infos of the signals and the queues
+//! they are pending in, for a process and for its threads.
+
+#![no_std]
+#![feature(linked_list_cursors)]
+
+#[cfg(test)]
+extern crate std;
+
+extern crate alloc;
+#[macro_use]
+extern crate bitflags;
+
+mod action;
+mod context;
+mod info;
+mod pending;
+mod proc_signal;
+mod stack;
+
+pub use action::{sig_fatal, SigAction, SigActionFlags, SigHandler};
+pub use context::{SignalContext, SyscallContext};
+pub use info::*;
+pub use pending::{dequeue_signal, has_pendding_sigs, suspend_mask, wait_for_signal, Pending};
+pub use proc_signal::{ProcSignal, SigBlocked, SignalFlags};
+pub use stack::{AltStack, SigStack, StackError, MINSIGSTKSZ, SS_DISABLE, SS_ONSTACK};
+
+use core::{
+    iter,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignalSet(u64);
+
+impl SignalSet {
+    pub fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Returns the set of all signals.
+    pub fn fill() -> Self {
+        Self(!0)
+    }
+
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    #[inline(always)]
+    pub const fn difference(&self, other: &Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    #[inline(always)]
+    pub const fn sigmask(sig: &Signo) -> Self {
+        Self(1 << (sig.to_primitive() as u64 - 1))
+    }
+
+    #[inline(always)]
+    pub const fn contains(&self, sig: &Signo) -> bool {
+        !Self::sigmask(sig).intersection(self).is_emptry()
+    }
+
+    #[inline(always)]
+    pub const fn union(&self, other: &Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    #[inline(always)]
+    pub const fn inv(&self) -> Self {
+        Self(!self.0)
+    }
+
+    #[inline(always)]
+    pub const fn intersection(&self, other: &Self) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    pub const fn bits(&self) -> u64 {
+        self.0
+    }
+
+    pub const fn is_emptry(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn delset(&mut self, sig: &Signo) {
+        self.0 &= !Self::sigmask(sig).0
+    }
+
+    /// Returns the lowest numbered signal in the set.
+    pub fn min_sig(&self) -> Option<Signo> {
+        Signo::from_primitive(self.0.trailing_zeros() as u8 + 1)
+    }
+
+    /// Returns the signals in the set in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = Signo> + '_ {
+        (1..=NSIG)
+            .filter_map(Signo::from_primitive)
+            .filter(move |sig| self.contains(sig))
+    }
+}
+
+impl iter::FromIterator<Signo> for SignalSet {
+    fn from_iter<I: IntoIterator<Item = Signo>>(iter: I) -> Self {
+        iter.into_iter()
+            .fold(Self::empty(), |set, sig| set.union(&Self::sigmask(&sig)))
+    }
+}
+
+num_enum::num_enum! (
+    pub Signo:u8 {
+        SIGHUP = 1,
+        SIGINT = 2,
+        SIGQUIT = 3,
+        SIGILL = 4,
+        SIGTRAP = 5,
+        SIGABRT = 6,
+        SIGBUS = 7,
+        SIGFPE = 8,
+        SIGKILL = 9,
+        SIGUSR1 = 10,
+        SIGSEGV = 11,
+        SIGUSR2 = 12,
+        SIGPIPE = 13,
+        SIGALRM = 14,
+        SIGTERM = 15,
+        SIGSTKFLT = 16,
+        SIGCHLD = 17,
+        SIGCONT = 18,
+        SIGSTOP = 19,
+        SIGTSTP = 20,
+        SIGTTIN = 21,
+        SIGTTOU = 22,
+        SIGURG = 23,
+        SIGXCPU = 24,
+        SIGXFSZ = 25,
+        SIGVTALRM = 26,
+        SIGPROF = 27,
+        SIGWINCH = 28,
+        SIGIO = 29,
+        SIGPWR = 30,
+        SIGSYS = 31,
+        SIGRTMIN = 32,
+        SIGRT33 = 33,
+        SIGRT34 = 34,
+        SIGRT35 = 35,
+        SIGRT36 = 36,
+        SIGRT37 = 37,
+        SIGRT38 = 38,
+        SIGRT39 = 39,
+        SIGRT40 = 40,
+        SIGRT41 = 41,
+        SIGRT42 = 42,
+        SIGRT43 = 43,
+        SIGRT44 = 44,
+        SIGRT45 = 45,
+        SIGRT46 = 46,
+        SIGRT47 = 47,
+        SIGRT48 = 48,
+        SIGRT49 = 49,
+        SIGRT50 = 50,
+        SIGRT51 = 51,
+        SIGRT52 = 52,
+        SIGRT53 = 53,
+        SIGRT54 = 54,
+        SIGRT55 = 55,
+        SIGRT56 = 56,
+        SIGRT57 = 57,
+        SIGRT58 = 58,
+        SIGRT59 = 59,
+        SIGRT60 = 60,
+        SIGRT61 = 61,
+        SIGRT62 = 62,
+        SIGRT63 = 63,
+        SIGRTMAX = 64,
+    }
+);
+
+impl Signo {
+    pub const MASK_SIG_KERNEL_ONLY: SignalSet =
+        SignalSet::sigmask(&Signo::SIGKILL).union(&SignalSet::sigmask(&Signo::SIGSTOP));
+
+    #[inline(always)]
+    pub const fn kernel_only(&self) -> bool {
+        Self::MASK_SIG_KERNEL_ONLY.contains(self)
+    }
+
+    pub const MASK_SIG_KERNEL_IGNORE: SignalSet = SignalSet::sigmask(&Signo::SIGCONT)
+        .union(&SignalSet::sigmask(&Signo::SIGCHLD))
+        .union(&SignalSet::sigmask(&Signo::SIGWINCH))
+        .union(&SignalSet::sigmask(&Signo::SIGURG));
+
+    #[inline(always)]
+    pub const fn kernel_ignore(&self) -> bool {
+        Self::MASK_SIG_KERNEL_IGNORE.contains(self)
+    }
+
+    pub const MASK_SIG_KERNEL_STOP: SignalSet = SignalSet::sigmask(&Signo::SIGSTOP)
+        .union(&SignalSet::sigmask(&Signo::SIGTSTP))
+        .union(&SignalSet::sigmask(&Signo::SIGTTIN))
+        .union(&SignalSet::sigmask(&Signo::SIGTTOU));
+
+    #[inline(always)]
+    pub const fn kernel_stop(&self) -> bool {
+        Self::MASK_SIG_KERNEL_STOP.contains(self)
+    }
+
+    pub const MASK_SIG_SYNCHRONOUS: SignalSet = SignalSet::sigmask(&Signo::SIGSEGV)
+        .union(&SignalSet::sigmask(&Signo::SIGBUS))
+        .union(&SignalSet::sigmask(&Signo::SIGILL))
+        .union(&SignalSet::sigmask(&Signo::SIGTRAP))
+        .union(&SignalSet::sigmask(&Signo::SIGFPE))
+        .union(&SignalSet::sigmask(&Signo::SIGSYS));
+
+    #[inline(always)]
+    pub const fn synchronous(&self) -> bool {
+        Self::MASK_SIG_SYNCHRONOUS.contains(self)
+    }
+
+    /// Whether this is a standard signal, from `SIGRTMIN` on signals are realtime.
+    pub fn legacy(&self) -> bool {
+        self < &Self::SIGRTMIN
+    }
+}
+
+/// Signal count
+pub const NSIG: u8 = Signo::SIGRTMAX as u8;
+
+const FLAGS_SIG_STOPPING: u8 = 0b1;
+const FLAGS_HAS_PENDDING_SIGS: u8 = 0b10;
+
+/// The signal state of a thread that is read without the lock of the signals of its process.
+#[derive(Debug, Default)]
+pub struct ThreadFlags(AtomicU8);
+
+impl ThreadFlags {
+    pub const fn new() -> Self {
+        Self(AtomicU8::new(0))
+    }
+
+    /// The thread is stopped by a stop signal, until `SIGCONT` is sent.
+    pub fn stopping(&self) -> bool {
+        self.0.load(Ordering::Acquire) & FLAGS_SIG_STOPPING != 0
+    }
+
+    /// Stops the thread, the other flags are kept. Returns false if it was already stopped.
+    pub fn stop(&self) -> bool {
+        self.0.fetch_or(FLAGS_SIG_STOPPING, Ordering::AcqRel) & FLAGS_SIG_STOPPING == 0
+    }
+
+    /// Continues the thread, the other flags are kept. Returns true if it was stopped.
+    pub fn cont(&self) -> bool {
+        self.0.fetch_and(!FLAGS_SIG_STOPPING, Ordering::AcqRel) & FLAGS_SIG_STOPPING != 0
+    }
+
+    /// A signal that is not blocked may be pending for the thread.
+    pub fn has_pending_sigs(&self) -> bool {
+        self.0.load(Ordering::Acquire) & FLAGS_HAS_PENDDING_SIGS != 0
+    }
+
+    pub fn set_pending_sigs(&self) {
+        self.0.fetch_or(FLAGS_HAS_PENDDING_SIGS, Ordering::AcqRel);
+    }
+
+    pub fn clear_pending_sigs(&self) {
+        self.0.fetch_and(!FLAGS_HAS_PENDDING_SIGS, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+
+    use super::{SignalSet, Signo, ThreadFlags, NSIG};
+
+    #[test]
+    fn iter_lists_the_signals_in_ascending_order() {
+        let set = SignalSet::sigmask(&Signo::SIGRTMAX)
+            .union(&SignalSet::sigmask(&Signo::SIGUSR1))
+            .union(&SignalSet::sigmask(&Signo::SIGHUP));
+        let sigs: Vec<_> = set.iter().collect();
+        assert_eq!(sigs, [Signo::SIGHUP, Signo::SIGUSR1, Signo::SIGRTMAX]);
+        assert_eq!(SignalSet::empty().iter().count(), 0);
+    }
+
+    #[test]
+    fn fill_holds_every_signal() {
+        let all = SignalSet::fill();
+        assert_eq!(all.iter().count(), NSIG as usize);
+        assert_eq!(all.iter().next(), Some(Signo::SIGHUP));
+        assert_eq!(all.iter().last(), Some(Signo::SIGRTMAX));
+    }
+
+    #[test]
+    fn a_set_collects_the_signals_it_iterates() {
+        let sigs = [Signo::SIGINT, Signo::SIGCHLD, Signo::SIGRTMIN];
+        let set: SignalSet = sigs.iter().copied().collect();
+        assert_eq!(set.bits(), 1 << 1 | 1 << 16 | 1 << 31);
+        assert_eq!(set.iter().collect::<Vec<_>>(), sigs);
+        assert_eq!(set.iter().collect::<SignalSet>(), set);
+        assert_eq!(set.min_sig(), Some(Signo::SIGINT));
+    }
+
+    #[test]
+    fn the_pending_flag_survives_a_stop_and_sigcont() {
+        let flags = ThreadFlags::new();
+        flags.set_pending_sigs();
+        assert!(flags.stop());
+        assert!(!flags.stop());
+        assert!(flags.stopping() && flags.has_pending_sigs());
+
+        assert!(flags.cont());
+        assert!(!flags.cont());
+        assert!(!flags.stopping());
+        assert!(flags.has_pending_sigs());
+
+        flags.clear_pending_sigs();
+        assert!(flags.stop());
+        assert!(!flags.has_pending_sigs());
+    }
+}
@@ -0,0 +1,343 @@
+use core::{
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use alloc::collections::LinkedList;
+
+use crate::{Info, SignalSet, Signo, SI_USER};
+
+pub struct Pending {
+    signal: SignalSet,
+    queue: LinkedList<Info>,
+}
+
+impl Pending {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            signal: SignalSet::empty(),
+            queue: LinkedList::new(),
+        }
+    }
+
+    /// The pending signals.
+    pub fn signal(&self) -> &SignalSet {
+        &self.signal
+    }
+
+    /// Remove signals in mask from the pending set and queue.
+    pub fn flush_by_mask(&mut self, mask: &SignalSet) {
+        let m = self.signal.intersection(mask);
+        if m.is_emptry() {
+            return;
+        }
+
+        self.signal = self.signal.difference(mask);
+
+        let mut queue_cursor = self.queue.cursor_front_mut();
+
+        while let Some(sig_info) = queue_cursor.current() {
+            if mask.contains(&sig_info.sig) {
+                queue_cursor.remove_current();
+            }
+            queue_cursor.move_next();
+        }
+    }
+
+    pub fn contains(&self, sig: &Signo) -> bool {
+        self.signal.contains(sig)
+    }
+
+    /// Queue `info`, at most `cap` signals are queued.
+    /// Returns the info back if the queue is full and the signal is a realtime signal,
+    /// a legacy signal is only recorded in `signal` then, as it is never queued twice anyway.
+    pub fn push(&mut self, info: Info, cap: usize) -> core::result::Result<(), Info> {
+        let full = self.queue.len() >= cap;
+        if full && !info.sig.legacy() {
+            return Err(info);
+        }
+
+        self.signal = self.signal.union(&SignalSet::sigmask(&info.sig));
+        if !full {
+            self.queue.push_back(info);
+        }
+
+        Ok(())
+    }
+
+    /// Sends `info` to the queue of at most `cap` signals, returns false if its signal is
+    /// a legacy signal that is already pending, which is dropped as a legacy signal is
+    /// pending once. Fails as `push` does.
+    pub fn queue(&mut self, info: Info, cap: usize) -> core::result::Result<bool, Info> {
+        if info.sig.legacy() && self.contains(&info.sig) {
+            return Ok(false);
+        }
+        self.push(info, cap)?;
+        Ok(true)
+    }
+}
+
+/// Takes the next signal of `pending` that is not in `mask`,
+/// and whether it was the only one of its number.
+pub fn dequeue_signal(pending: &mut Pending, mask: &SignalSet) -> (Option<Info>, bool) {
+    let mut s = pending.signal.difference(mask);
+    let target_sig = if !s.is_emptry() {
+        // Synchronous signals should be dequeued first.
+        let sync = s.intersection(&Signo::MASK_SIG_SYNCHRONOUS);
+        if !sync.is_emptry() {
+            s = sync;
+        }
+        s.min_sig().unwrap()
+    } else {
+        return (None, false);
+    };
+
+    let queued = pending
+        .queue
+        .iter()
+        .filter(|info| info.sig == target_sig)
+        .count();
+    // Realtime signals are dequeued in the order they were queued,
+    // for the others the ones generated by the kernel come first.
+    let first = pending.queue.iter().position(|info| info.sig == target_sig);
+    let target_idx = if target_sig.legacy() {
+        pending
+            .queue
+            .iter()
+            .position(|info| info.sig == target_sig && info.code > SI_USER)
+            .or(first)
+    } else {
+        first
+    };
+
+    let only_one_target = queued <= 1;
+    if only_one_target {
+        pending.signal.delset(&target_sig);
+    }
+
+    let target_info = match target_idx {
+        Some(idx) => {
+            let mut cursor = pending.queue.cursor_front_mut();
+            for _ in 0..idx {
+                cursor.move_next();
+            }
+            cursor.remove_current()
+        }
+        // A legacy signal that found the queue full, its info was not kept.
+        None => Some(Info::kill(target_sig, 0)),
+    };
+    (target_info, only_one_target)
+}
+
+pub fn has_pendding_sigs(
+    thread_pending_signal: &SignalSet,
+    shared_pending_signal: &SignalSet,
+    blocked: &SignalSet,
+) -> bool {
+    !thread_pending_signal.difference(blocked).is_emptry()
+        || !shared_pending_signal.difference(blocked).is_emptry()
+}
+
+/// The mask `rt_sigsuspend` installs for `mask`, SIGKILL and SIGSTOP can not be blocked.
+pub fn suspend_mask(mask: SignalSet) -> SignalSet {
+    let unblockable =
+        SignalSet::sigmask(&Signo::SIGKILL).union(&SignalSet::sigmask(&Signo::SIGSTOP));
+    mask.difference(&unblockable)
+}
+
+/// Sleeps until `pending` returns true, that is a signal that is not blocked is pending,
+/// then wakes the task to deliver it. It never completes: delivering the signal drops it.
+pub fn wait_for_signal<T, F: Fn() -> bool>(pending: F) -> WaitForSignal<T, F> {
+    WaitForSignal {
+        pending,
+        _output: PhantomData,
+    }
+}
+
+pub struct WaitForSignal<T, F> {
+    pending: F,
+    _output: PhantomData<fn() -> T>,
+}
+
+impl<T, F: Fn() -> bool> Future for WaitForSignal<T, F> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if (self.pending)() {
+            // Let the task deliver it.
+            cx.waker().wake_by_ref();
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::{
+        cell::RefCell,
+        future::Future,
+        pin::Pin,
+        sync::atomic::{AtomicUsize, Ordering},
+        task::{Context, Poll, Waker},
+    };
+    use std::{iter, sync::Arc, task::Wake, vec::Vec};
+
+    use super::{dequeue_signal, has_pendding_sigs, suspend_mask, wait_for_signal, Pending};
+    use crate::{Info, SignalSet, Signo};
+
+    fn push(pending: &mut Pending, sig: Signo) {
+        assert!(pending.push(Info::kill(sig, 1), 8).is_ok());
+    }
+
+    #[derive(Default)]
+    struct CountWaker(AtomicUsize);
+
+    impl Wake for CountWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn pause_waits_for_a_pending_signal() {
+        let none = SignalSet::empty();
+        let mut pending = Pending::new();
+        assert!(!has_pendding_sigs(&pending.signal, &none, &none));
+
+        push(&mut pending, Signo::SIGUSR1);
+        assert!(has_pendding_sigs(&pending.signal, &none, &none));
+    }
+
+    #[test]
+    fn sigsuspend_unblocks_the_signals_outside_its_mask() {
+        let mask = suspend_mask(SignalSet::sigmask(&Signo::SIGUSR1));
+        let none = SignalSet::empty();
+        let mut pending = Pending::new();
+
+        push(&mut pending, Signo::SIGUSR1);
+        assert!(!has_pendding_sigs(&pending.signal, &none, &mask));
+        assert!(dequeue_signal(&mut pending, &mask).0.is_none());
+
+        push(&mut pending, Signo::SIGUSR2);
+        assert!(has_pendding_sigs(&pending.signal, &none, &mask));
+        let (info, _) = dequeue_signal(&mut pending, &mask);
+        assert!(info.unwrap().sig == Signo::SIGUSR2);
+        // SIGUSR1 stays pending until the mask is lifted.
+        assert!(!has_pendding_sigs(&pending.signal, &none, &mask));
+        assert!(has_pendding_sigs(&pending.signal, &none, &none));
+    }
+
+    #[test]
+    fn sigsuspend_can_not_block_sigkill_and_sigstop() {
+        let mask = suspend_mask(SignalSet::fill());
+        assert!(!mask.contains(&Signo::SIGKILL));
+        assert!(!mask.contains(&Signo::SIGSTOP));
+        assert!(mask.contains(&Signo::SIGUSR1));
+    }
+
+    #[test]
+    fn the_wait_wakes_only_on_a_signal_outside_the_mask() {
+        let mask = suspend_mask(SignalSet::sigmask(&Signo::SIGUSR1));
+        let none = SignalSet::empty();
+        let pending = RefCell::new(Pending::new());
+        let mut wait =
+            wait_for_signal::<(), _>(|| has_pendding_sigs(&pending.borrow().signal, &none, &mask));
+        let count = Arc::new(CountWaker::default());
+        let waker = Waker::from(count.clone());
+        let mut poll = || Pin::new(&mut wait).poll(&mut Context::from_waker(&waker));
+
+        assert_eq!(poll(), Poll::Pending);
+        push(&mut pending.borrow_mut(), Signo::SIGUSR1);
+        assert_eq!(poll(), Poll::Pending);
+        assert_eq!(count.0.load(Ordering::SeqCst), 0);
+
+        push(&mut pending.borrow_mut(), Signo::SIGUSR2);
+        // The wait does not complete, the woken task delivers the signal and drops it.
+        assert_eq!(poll(), Poll::Pending);
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn push_marks_the_signal_pending_until_its_last_info_is_taken() {
+        let none = SignalSet::empty();
+        let mut pending = Pending::new();
+        assert!(!pending.contains(&Signo::SIGRT33));
+
+        push(&mut pending, Signo::SIGRT33);
+        push(&mut pending, Signo::SIGRT33);
+        assert!(pending.contains(&Signo::SIGRT33));
+        assert_eq!(*pending.signal(), SignalSet::sigmask(&Signo::SIGRT33));
+
+        let (info, only_one) = dequeue_signal(&mut pending, &none);
+        assert!(info.is_some() && !only_one);
+        assert!(pending.contains(&Signo::SIGRT33));
+        let (info, only_one) = dequeue_signal(&mut pending, &none);
+        assert!(info.is_some() && only_one);
+        assert!(!pending.contains(&Signo::SIGRT33));
+    }
+
+    #[test]
+    fn a_full_queue_refuses_realtime_signals_but_records_legacy_ones() {
+        let none = SignalSet::empty();
+        let mut pending = Pending::new();
+        assert!(matches!(
+            pending.queue(Info::kill(Signo::SIGRT33, 1), 2),
+            Ok(true)
+        ));
+        assert!(matches!(
+            pending.queue(Info::kill(Signo::SIGUSR1, 1), 2),
+            Ok(true)
+        ));
+        // A legacy signal is pending once.
+        assert!(matches!(
+            pending.queue(Info::kill(Signo::SIGUSR1, 1), 2),
+            Ok(false)
+        ));
+
+        let refused = pending.queue(Info::kill(Signo::SIGRT34, 7), 2);
+        assert!(matches!(refused, Err(info) if info.sig == Signo::SIGRT34));
+        assert!(!pending.contains(&Signo::SIGRT34));
+        assert!(matches!(
+            pending.queue(Info::kill(Signo::SIGUSR2, 1), 2),
+            Ok(true)
+        ));
+        assert!(pending.contains(&Signo::SIGUSR2));
+
+        let sigs: Vec<_> = iter::from_fn(|| dequeue_signal(&mut pending, &none).0)
+            .map(|info| info.sig)
+            .collect();
+        // SIGUSR2 is delivered without the info the full queue could not keep.
+        assert_eq!(sigs, [Signo::SIGUSR1, Signo::SIGUSR2, Signo::SIGRT33]);
+        assert!(pending.signal().is_emptry());
+    }
+
+    #[test]
+    fn realtime_signals_are_taken_by_number_then_in_queue_order() {
+        let none = SignalSet::empty();
+        let mut pending = Pending::new();
+        for (sig, pid) in [
+            (Signo::SIGRT34, 1),
+            (Signo::SIGRT33, 2),
+            (Signo::SIGRT34, 3),
+            (Signo::SIGRT33, 4),
+        ] {
+            assert!(pending.push(Info::kill(sig, pid), 8).is_ok());
+        }
+
+        let taken: Vec<_> = iter::from_fn(|| dequeue_signal(&mut pending, &none).0)
+            .map(|info| (info.sig, unsafe { info.fields.kill.pid }))
+            .collect();
+        assert_eq!(
+            taken,
+            [
+                (Signo::SIGRT33, 2),
+                (Signo::SIGRT33, 4),
+                (Signo::SIGRT34, 1),
+                (Signo::SIGRT34, 3),
+            ]
+        );
+    }
+}
@@ -0,0 +1,313 @@
+use core::mem;
+
+use crate::{
+    dequeue_signal, Info, Pending, SigAction, SigActionFlags, SigHandler, SignalSet, Signo,
+    CLD_CONTINUED, CLD_STOPPED, NSIG,
+};
+
+bitflags! {
+    pub struct SignalFlags: usize {
+        const UNKILLABLE = 0x00000040;
+    }
+}
+
+pub struct SigBlocked {
+    pub blocked: SignalSet,
+    pub real_blocked: SignalSet,
+}
+
+/// The signal state shared by the threads of a process.
+pub struct ProcSignal {
+    actions: [SigAction; NSIG as usize],
+    /// `shared_pending` holds the signals sent to the process group
+    pub shared_pending: Pending,
+    /// Blocked signals set
+    pub blocked: SigBlocked,
+    /// Current thread group signal load-balancing target
+    /// A signal sent to a process group requires a thread in the process to handle it.
+    /// For load balancing purposes,
+    /// `current_target` stores the current thread that is handling the signal,
+    /// ensure that the threads processing the signal are as different as possible each time.
+    pub current_target: Option<u32>,
+    pub flags: SignalFlags,
+    /// Maximum number of queued signals per pending queue of the process and of its threads.
+    pub pending_limit: usize,
+}
+
+impl ProcSignal {
+    pub fn new(pending_limit: usize) -> Self {
+        Self {
+            actions: array_init::array_init(|_| Default::default()),
+            shared_pending: Pending::new(),
+            blocked: SigBlocked {
+                blocked: SignalSet::empty(),
+                real_blocked: SignalSet::empty(),
+            },
+            current_target: None,
+            flags: SignalFlags::empty(),
+            pending_limit,
+        }
+    }
+
+    pub fn fork(&self) -> Self {
+        Self {
+            actions: self.actions.clone(),
+            ..Self::new(self.pending_limit)
+        }
+    }
+
+    pub fn action(&self, sig: &Signo) -> &SigAction {
+        &self.actions[sig.to_primitive() as usize - 1]
+    }
+
+    pub fn action_mut(&mut self, sig: &Signo) -> &mut SigAction {
+        &mut self.actions[sig.to_primitive() as usize - 1]
+    }
+
+    pub fn replace_action(&mut self, sig: &Signo, sa: SigAction) -> SigAction {
+        mem::replace(self.action_mut(sig), sa)
+    }
+
+    /// Queues `info` to the thread whose own signals are `thread_pending`,
+    /// or to the process if `None`. Fails as `Pending::queue` does.
+    pub fn queue(
+        &mut self,
+        thread_pending: Option<&mut Pending>,
+        info: Info,
+    ) -> core::result::Result<bool, Info> {
+        let pending = match thread_pending {
+            Some(pending) => pending,
+            None => &mut self.shared_pending,
+        };
+        pending.queue(info, self.pending_limit)
+    }
+
+    /// Takes the next signal that is not `blocked` for the thread whose own signals are
+    /// `thread_pending`, its own signals come before the ones sent to the process.
+    /// Also returns whether it was the only one of its number, as `dequeue_signal` does.
+    pub fn dequeue(
+        &mut self,
+        thread_pending: &mut Pending,
+        blocked: &SignalSet,
+    ) -> (Option<Info>, bool) {
+        match dequeue_signal(thread_pending, blocked) {
+            (None, _) => dequeue_signal(&mut self.shared_pending, blocked),
+            taken => taken,
+        }
+    }
+
+    /// What a child changing state with `code`, one of the `CLD_*` values, does to its
+    /// parent of these signals: whether SIGCHLD is sent, and whether the child exited and
+    /// is reaped without the parent waiting for it.
+    pub fn child_notification(&self, code: isize) -> (bool, bool) {
+        let exited = !matches!(code, CLD_STOPPED | CLD_CONTINUED);
+        let act = self.action(&Signo::SIGCHLD);
+        let send = exited || !act.flags.contains(SigActionFlags::NOCLDSTOP);
+        let reap =
+            exited && (act.flags.contains(SigActionFlags::NOCLDWAIT) || act.handler().is_sig_ign());
+        (send, reap)
+    }
+
+    /// Whether `sig` is dropped when it is sent, `is_init` if the process is the global init.
+    pub fn ignored(&self, sig: &Signo, is_init: bool) -> bool {
+        // Blocked signals are never ignored,
+        // since the signal handler may change by the time it is unblocked.
+        if self.blocked.blocked.contains(sig) || self.blocked.real_blocked.contains(sig) {
+            return false;
+        }
+
+        let handler = self.action(sig).handler();
+
+        // SIGKILL and SIGSTOP may not be sent to the global init
+        if is_init && sig.kernel_only() {
+            return true;
+        }
+
+        if self.flags.contains(SignalFlags::UNKILLABLE)
+            && handler.is_default()
+            && !sig.kernel_only()
+        {
+            return true;
+        }
+
+        handler.is_ignored(sig)
+    }
+
+    /// Prepares a synchronous signal, like SIGSEGV, raised by a thread itself.
+    /// Such a signal can not be blocked or ignored, if it is, its action is reset to the default.
+    /// Returns true if the default action must be taken, which terminates the process.
+    pub fn force(&mut self, sig: &Signo) -> bool {
+        let blocked = self.blocked.blocked.contains(sig);
+        if blocked {
+            self.blocked.blocked.delset(sig);
+        }
+        let act = self.action_mut(sig);
+        if blocked || act.handler().is_ignored(sig) {
+            act.set_handler(SigHandler::default_handler());
+        }
+        act.handler().is_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{iter, vec::Vec};
+
+    use mm::VirtualAddress;
+
+    use super::ProcSignal;
+    use crate::{
+        dequeue_signal, Info, Pending, SigAction, SigActionFlags, SigHandler, SignalSet, Signo,
+        CLD_CONTINUED, CLD_EXITED, CLD_STOPPED, SI_TKILL,
+    };
+
+    extern "C" fn handler(_: usize) {}
+
+    // A `ProcSignal` with a handler for `sigs`.
+    fn handled(sigs: &[Signo]) -> ProcSignal {
+        let mut signal = ProcSignal::new(8);
+        for sig in sigs {
+            signal.action_mut(sig).set_handler(SigHandler { handler });
+        }
+        signal
+    }
+
+    #[test]
+    fn actions_are_indexed_by_the_signal_number() {
+        let mut signal = ProcSignal::new(8);
+        for sig in [Signo::SIGHUP, Signo::SIGRTMAX] {
+            let mut act = SigAction::default();
+            act.flags = SigActionFlags::RESTART;
+            let old = signal.replace_action(&sig, act);
+            assert!(old.flags.is_empty());
+            assert_eq!(signal.action(&sig).flags, SigActionFlags::RESTART);
+        }
+        assert!(signal.action(&Signo::SIGINT).flags.is_empty());
+        assert!(signal.action(&Signo::SIGRT63).flags.is_empty());
+    }
+
+    #[test]
+    fn faults_are_delivered_to_their_handlers_first() {
+        let mut signal = handled(&[Signo::SIGSEGV, Signo::SIGBUS]);
+        let mut pending = Pending::new();
+        assert!(pending.push(Info::kill(Signo::SIGUSR1, 1), 8).is_ok());
+        for (sig, addr) in [(Signo::SIGBUS, 0x2000), (Signo::SIGSEGV, 0x1000)] {
+            assert!(!signal.force(&sig));
+            let info = Info::fault(sig, VirtualAddress(addr));
+            assert!(pending.push(info, 8).is_ok());
+        }
+
+        let none = SignalSet::empty();
+        let taken: Vec<_> = iter::from_fn(|| dequeue_signal(&mut pending, &none).0)
+            .map(|info| (info.sig, unsafe { info.fields.fault.addr }))
+            .take(2)
+            .collect();
+        assert_eq!(
+            taken,
+            [
+                (Signo::SIGBUS, VirtualAddress(0x2000)),
+                (Signo::SIGSEGV, VirtualAddress(0x1000)),
+            ]
+        );
+    }
+
+    #[test]
+    fn blocked_or_ignored_faults_terminate_the_process() {
+        let mut signal = handled(&[Signo::SIGSEGV]);
+        signal.blocked.blocked = SignalSet::sigmask(&Signo::SIGSEGV);
+        assert!(signal.force(&Signo::SIGSEGV));
+        // The fault unblocks it and resets its action.
+        assert!(!signal.blocked.blocked.contains(&Signo::SIGSEGV));
+        assert!(signal.action(&Signo::SIGSEGV).handler().is_default());
+
+        signal
+            .action_mut(&Signo::SIGBUS)
+            .set_handler(SigHandler::ignore_handler());
+        assert!(signal.force(&Signo::SIGBUS));
+        assert!(signal.action(&Signo::SIGBUS).handler().is_default());
+    }
+
+    #[test]
+    fn tkill_reaches_only_the_target_thread() {
+        let mut signal = ProcSignal::new(8);
+        let (mut target, mut other) = (Pending::new(), Pending::new());
+        let none = SignalSet::empty();
+        let tkill = Info::tkill(Signo::SIGUSR1, 1);
+        assert!(matches!(signal.queue(Some(&mut target), tkill), Ok(true)));
+        assert!(target.contains(&Signo::SIGUSR1));
+        assert!(signal.shared_pending.signal().is_emptry());
+
+        assert!(signal.dequeue(&mut other, &none).0.is_none());
+        let (info, only_one) = signal.dequeue(&mut target, &none);
+        assert!(matches!(info, Some(info) if info.sig == Signo::SIGUSR1 && info.code == SI_TKILL));
+        assert!(only_one);
+    }
+
+    #[test]
+    fn signals_to_the_process_are_taken_by_any_thread_after_its_own() {
+        let mut signal = ProcSignal::new(8);
+        let mut thread = Pending::new();
+        let none = SignalSet::empty();
+        assert!(matches!(
+            signal.queue(None, Info::kill(Signo::SIGUSR1, 1)),
+            Ok(true)
+        ));
+        assert!(matches!(
+            signal.queue(Some(&mut thread), Info::tkill(Signo::SIGUSR2, 1)),
+            Ok(true)
+        ));
+
+        let taken: Vec<_> = iter::from_fn(|| signal.dequeue(&mut thread, &none).0)
+            .map(|info| info.sig)
+            .collect();
+        assert_eq!(taken, [Signo::SIGUSR2, Signo::SIGUSR1]);
+    }
+
+    // Notifies the parent whose signals are `signal` of its child 2 changing state with `code`,
+    // returns whether SIGCHLD was queued and whether the child is reaped.
+    fn notify(signal: &mut ProcSignal, code: isize) -> (bool, bool) {
+        let (send, reap) = signal.child_notification(code);
+        let queued = send
+            && !signal.ignored(&Signo::SIGCHLD, false)
+            && matches!(signal.queue(None, Info::child(2, 0, code, 0)), Ok(true));
+        (queued, reap)
+    }
+
+    #[test]
+    fn an_exited_child_leaves_sigchld_pending_for_a_handler() {
+        let mut signal = handled(&[Signo::SIGCHLD]);
+        assert_eq!(notify(&mut signal, CLD_EXITED), (true, false));
+        let (info, _) = signal.dequeue(&mut Pending::new(), &SignalSet::empty());
+        let info = info.unwrap();
+        assert!(info.sig == Signo::SIGCHLD && info.code == CLD_EXITED);
+
+        // SIGCHLD is ignored by default.
+        let mut signal = ProcSignal::new(8);
+        assert_eq!(notify(&mut signal, CLD_EXITED), (false, false));
+        assert!(signal.shared_pending.signal().is_emptry());
+    }
+
+    #[test]
+    fn nocldwait_and_sig_ign_reap_exited_children() {
+        let mut signal = handled(&[Signo::SIGCHLD]);
+        signal.action_mut(&Signo::SIGCHLD).flags = SigActionFlags::NOCLDWAIT;
+        assert_eq!(notify(&mut signal, CLD_EXITED), (true, true));
+        // A stopped child is not reaped.
+        assert_eq!(signal.child_notification(CLD_STOPPED), (true, false));
+
+        let mut signal = ProcSignal::new(8);
+        signal
+            .action_mut(&Signo::SIGCHLD)
+            .set_handler(SigHandler::ignore_handler());
+        assert_eq!(notify(&mut signal, CLD_EXITED), (false, true));
+    }
+
+    #[test]
+    fn nocldstop_keeps_stops_quiet() {
+        let mut signal = handled(&[Signo::SIGCHLD]);
+        signal.action_mut(&Signo::SIGCHLD).flags = SigActionFlags::NOCLDSTOP;
+        assert_eq!(signal.child_notification(CLD_STOPPED), (false, false));
+        assert_eq!(signal.child_notification(CLD_CONTINUED), (false, false));
+        assert_eq!(signal.child_notification(CLD_EXITED), (true, false));
+    }
+}
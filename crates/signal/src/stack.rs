@@ -0,0 +1,172 @@
+/// `stack_t`, describes an alternate signal stack.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SigStack {
+    /// Base address of stack
+    pub sp: usize,
+    pub flags: i32,
+    /// Number of bytes in stack
+    pub size: usize,
+}
+
+/// The thread is currently executing on the alternate signal stack.
+pub const SS_ONSTACK: i32 = 1;
+/// The alternate signal stack is disabled.
+pub const SS_DISABLE: i32 = 2;
+/// Minimum size of an alternate signal stack.
+pub const MINSIGSTKSZ: usize = 2048;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StackError {
+    /// The thread is executing on the stack.
+    OnStack,
+    TooSmall,
+    Invalid,
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct AltStack {
+    /// Top address of stack
+    pub sp: usize,
+    /// Number of bytes in stack, 0 if no stack is registered
+    pub size: usize,
+}
+
+impl AltStack {
+    pub fn enabled(&self) -> bool {
+        self.size != 0
+    }
+
+    pub fn on_stack(&self, sp: usize) -> bool {
+        sp <= self.sp && sp > self.sp - self.size
+    }
+
+    /// The stack pointer a handler starts with when the thread is interrupted at `sp`,
+    /// `onstack` if the handler was registered with `SigActionFlags::ONSTACK`.
+    /// The handler runs on this stack, unless the thread is already running on it.
+    pub fn handler_sp(&self, onstack: bool, sp: usize) -> usize {
+        if onstack && self.enabled() && !self.on_stack(sp) {
+            self.sp
+        } else {
+            sp
+        }
+    }
+
+    /// Describes the stack to a thread executing at `sp`.
+    pub fn query(&self, sp: usize) -> SigStack {
+        let on_stack = self.enabled() && self.on_stack(sp);
+        SigStack {
+            sp: self.sp - self.size,
+            flags: if on_stack {
+                SS_ONSTACK
+            } else if !self.enabled() {
+                SS_DISABLE
+            } else {
+                0
+            },
+            size: self.size,
+        }
+    }
+
+    /// Replaces the stack with `ss` for a thread executing at `sp`,
+    /// which must not be executing on it.
+    pub fn set(&mut self, ss: &SigStack, sp: usize) -> Result<(), StackError> {
+        if self.enabled() && self.on_stack(sp) {
+            return Err(StackError::OnStack);
+        }
+        *self = match ss.flags {
+            SS_DISABLE => Self::default(),
+            0 | SS_ONSTACK => {
+                if ss.size < MINSIGSTKSZ {
+                    return Err(StackError::TooSmall);
+                }
+                Self {
+                    sp: ss.sp.checked_add(ss.size).ok_or(StackError::Invalid)?,
+                    size: ss.size,
+                }
+            }
+            _ => return Err(StackError::Invalid),
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AltStack, SigStack, StackError, MINSIGSTKSZ, SS_DISABLE, SS_ONSTACK};
+
+    const BASE: usize = 0x1000_0000;
+    const SIZE: usize = 2 * MINSIGSTKSZ;
+
+    fn stack() -> AltStack {
+        let mut stack = AltStack::default();
+        let ss = SigStack {
+            sp: BASE,
+            flags: 0,
+            size: SIZE,
+        };
+        stack.set(&ss, 0x7fff_f000).unwrap();
+        stack
+    }
+
+    #[test]
+    fn handlers_registered_onstack_start_at_the_top_of_the_stack() {
+        let stack = stack();
+        let user_sp = 0x7fff_f000;
+        let sp = stack.handler_sp(true, user_sp);
+        assert_eq!(sp, BASE + SIZE);
+        // A nested handler stays below the interrupted one.
+        let nested_sp = sp - 0x100;
+        assert!(stack.on_stack(nested_sp));
+        assert_eq!(stack.handler_sp(true, nested_sp), nested_sp);
+
+        assert_eq!(stack.handler_sp(false, user_sp), user_sp);
+        assert_eq!(AltStack::default().handler_sp(true, user_sp), user_sp);
+    }
+
+    #[test]
+    fn query_returns_the_stack_that_was_set() {
+        let mut stack = stack();
+        let ss = SigStack {
+            sp: BASE,
+            flags: 0,
+            size: SIZE,
+        };
+        assert_eq!(stack.query(0x7fff_f000), ss);
+        assert_eq!(
+            stack.query(BASE + 0x10),
+            SigStack {
+                flags: SS_ONSTACK,
+                ..ss
+            }
+        );
+
+        // It can not be changed from a handler running on it.
+        assert_eq!(stack.set(&ss, BASE + 0x10), Err(StackError::OnStack));
+        let disable = SigStack {
+            flags: SS_DISABLE,
+            ..ss
+        };
+        stack.set(&disable, 0x7fff_f000).unwrap();
+        assert_eq!(stack.query(0x7fff_f000).flags, SS_DISABLE);
+    }
+
+    #[test]
+    fn small_stacks_and_unknown_flags_are_refused() {
+        let mut stack = AltStack::default();
+        let small = SigStack {
+            sp: BASE,
+            flags: 0,
+            size: MINSIGSTKSZ - 1,
+        };
+        assert_eq!(stack.set(&small, 0), Err(StackError::TooSmall));
+        let bad_flags = SigStack {
+            flags: 4,
+            size: MINSIGSTKSZ,
+            ..small
+        };
+        assert_eq!(stack.set(&bad_flags, 0), Err(StackError::Invalid));
+        assert!(!stack.enabled());
+    }
+}
@@ -0,0 +1,514 @@
+#![no_std]
+
+#[cfg(test)]
+extern crate std;
+
+use core::marker::PhantomData;
+
+/// Disables and restores the interrupts of the current CPU.
+/// `push_off` and `pop_off` are matched, to undo 2 `push_off`s 2 `pop_off`s are required,
+/// the last `pop_off` restores the interrupt state the first `push_off` found.
+pub trait Irq {
+    fn push_off();
+
+    fn pop_off();
+}
+
+/// The nesting of the `push_off`s of a CPU, for implementing `Irq`.
+#[derive(Debug, Default)]
+pub struct NestedOff {
+    // depth of nesting of push_off()
+    depth: usize,
+    // Whether interrupts were enabled before the outermost push_off()
+    enabled: bool,
+}
+
+impl NestedOff {
+    pub const fn new() -> Self {
+        Self {
+            depth: 0,
+            enabled: false,
+        }
+    }
+
+    /// Records a `push_off` that disabled interrupts, `was_enabled` tells whether they were
+    /// enabled before.
+    pub fn push(&mut self, was_enabled: bool) {
+        if self.depth == 0 {
+            self.enabled = was_enabled;
+        }
+        self.depth += 1;
+    }
+
+    /// Records a `pop_off`, returns true if interrupts have to be enabled again.
+    pub fn pop(&mut self) -> bool {
+        assert!(self.depth > 0, "pop_off without a matching push_off");
+        self.depth -= 1;
+        self.depth == 0 && self.enabled
+    }
+
+    /// Returns how many `push_off`s are not matched by a `pop_off` yet.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+}
+
+/// Keeps interrupts disabled on the current CPU for as long as it lives, like a `push_off`
+/// matched by a `pop_off` on drop.
+/// Guards nest, only dropping the outermost one restores the interrupt state
+/// it was created in.
+/// A guard must be dropped on the CPU that created it.
+#[must_use = "interrupts are restored as soon as the guard is dropped"]
+pub struct InterruptGuard<I: Irq>(PhantomData<I>);
+
+impl<I: Irq> InterruptGuard<I> {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        I::push_off();
+        Self(PhantomData)
+    }
+}
+
+impl<I: Irq> Drop for InterruptGuard<I> {
+    fn drop(&mut self) {
+        I::pop_off();
+    }
+}
+
+/// A spin-based lock providing mutually exclusive access to data.
+/// And the `MutexIrq` will turn off interrupt when enters the critical section
+/// and resumes interrupt on exit from the critical section.
+pub struct MutexIrq<I, T: ?Sized>(PhantomData<I>, spin::Mutex<T>);
+
+unsafe impl<I, T: ?Sized + Send> Sync for MutexIrq<I, T> {}
+unsafe impl<I, T: ?Sized + Send> Send for MutexIrq<I, T> {}
+
+impl<I, T> MutexIrq<I, T> {
+    pub const fn new(value: T) -> Self {
+        Self(PhantomData, spin::Mutex::new(value))
+    }
+}
+
+impl<I: Irq, T> MutexIrq<I, T> {
+    /// Acquires a mutex
+    pub fn lock(&self) -> MutexIrqGuard<'_, I, T> {
+        // Turn off interrupt before locking
+        let irq = InterruptGuard::<I>::new();
+        MutexIrqGuard {
+            inner: self.1.lock(),
+            _irq: irq,
+        }
+    }
+
+    /// Attempts to acquire the mutex without spinning,
+    /// returns `None` and leaves the interrupt state untouched if it is locked.
+    pub fn try_lock(&self) -> Option<MutexIrqGuard<'_, I, T>> {
+        let irq = InterruptGuard::<I>::new();
+        // If the lock is not acquired, dropping `irq` resumes the interrupt state
+        self.1
+            .try_lock()
+            .map(|inner| MutexIrqGuard { inner, _irq: irq })
+    }
+}
+
+unsafe impl<I: Irq> lock_api::RawMutex for MutexIrq<I, ()> {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = Self::new(());
+    type GuardMarker = lock_api::GuardSend;
+
+    #[inline(always)]
+    fn lock(&self) {
+        // Call `I::push_off()` to turn off interrupt when locking
+        I::push_off();
+        <spin::Mutex<()> as lock_api::RawMutex>::lock(&self.1);
+    }
+
+    #[inline(always)]
+    fn try_lock(&self) -> bool {
+        // Call `I::push_off()` to turn off interrupt when locking
+        I::push_off();
+        if !<spin::Mutex<()> as lock_api::RawMutex>::try_lock(&self.1) {
+            // Lock not acquired, resume interrupt state
+            I::pop_off();
+            false
+        } else {
+            true
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn unlock(&self) {
+        <spin::Mutex<()> as lock_api::RawMutex>::unlock(&self.1);
+        // Call `I::pop_off()` to resume interrupt
+        I::pop_off();
+    }
+
+    #[inline(always)]
+    fn is_locked(&self) -> bool {
+        <spin::Mutex<()> as lock_api::RawMutex>::is_locked(&self.1)
+    }
+}
+
+/// A lock that provides data access to either one writer or many readers.
+/// And the `RwLockIrq` will turn off interrupt when enters the critical section
+/// and resumes interrupt on exit from the critical section.
+pub struct RwLockIrq<I, T: ?Sized>(PhantomData<I>, spin::RwLock<T>);
+
+unsafe impl<I, T: ?Sized + Send> Send for RwLockIrq<I, T> {}
+unsafe impl<I, T: ?Sized + Send + Sync> Sync for RwLockIrq<I, T> {}
+
+impl<I, T> RwLockIrq<I, T> {
+    pub const fn new(value: T) -> Self {
+        Self(PhantomData, spin::RwLock::new(value))
+    }
+}
+
+impl<I: Irq, T> RwLockIrq<I, T> {
+    pub fn read(&self) -> RwLockReadIrqGuard<'_, I, T> {
+        let irq = InterruptGuard::<I>::new();
+        RwLockReadIrqGuard {
+            inner: self.1.read(),
+            _irq: irq,
+        }
+    }
+
+    /// Like `read`, but returns `None` instead of spinning while a writer holds the lock.
+    pub fn try_read(&self) -> Option<RwLockReadIrqGuard<'_, I, T>> {
+        let irq = InterruptGuard::<I>::new();
+        self.1
+            .try_read()
+            .map(|inner| RwLockReadIrqGuard { inner, _irq: irq })
+    }
+
+    pub fn write(&self) -> RwLockWriteIrqGuard<'_, I, T> {
+        let irq = InterruptGuard::<I>::new();
+        RwLockWriteIrqGuard {
+            inner: self.1.write(),
+            _irq: irq,
+        }
+    }
+
+    /// Like `write`, but returns `None` instead of spinning while the lock is held.
+    pub fn try_write(&self) -> Option<RwLockWriteIrqGuard<'_, I, T>> {
+        let irq = InterruptGuard::<I>::new();
+        self.1
+            .try_write()
+            .map(|inner| RwLockWriteIrqGuard { inner, _irq: irq })
+    }
+
+    pub fn upgradeable_read(&self) -> RwLockUpgradableIrqGuard<'_, I, T> {
+        let irq = InterruptGuard::<I>::new();
+        RwLockUpgradableIrqGuard {
+            inner: self.1.upgradeable_read(),
+            _irq: irq,
+        }
+    }
+
+    pub fn try_upgradeable_read(&self) -> Option<RwLockUpgradableIrqGuard<'_, I, T>> {
+        let irq = InterruptGuard::<I>::new();
+        self.1
+            .try_upgradeable_read()
+            .map(|inner| RwLockUpgradableIrqGuard { inner, _irq: irq })
+    }
+}
+
+unsafe impl<I: Irq> lock_api::RawRwLock for RwLockIrq<I, ()> {
+    type GuardMarker = lock_api::GuardSend;
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = Self::new(());
+
+    #[inline(always)]
+    fn lock_shared(&self) {
+        I::push_off();
+        <spin::RwLock<()> as lock_api::RawRwLock>::lock_shared(&self.1)
+    }
+
+    #[inline(always)]
+    fn try_lock_shared(&self) -> bool {
+        I::push_off();
+        if !<spin::RwLock<()> as lock_api::RawRwLock>::try_lock_shared(&self.1) {
+            // Lock not acquired, resume interrupt state
+            I::pop_off();
+            false
+        } else {
+            true
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn unlock_shared(&self) {
+        <spin::RwLock<()> as lock_api::RawRwLock>::unlock_shared(&self.1);
+        // resume interrupt state
+        I::pop_off();
+    }
+
+    #[inline(always)]
+    fn lock_exclusive(&self) {
+        I::push_off();
+        <spin::RwLock<()> as lock_api::RawRwLock>::lock_exclusive(&self.1);
+    }
+
+    #[inline(always)]
+    fn try_lock_exclusive(&self) -> bool {
+        I::push_off();
+        if !<spin::RwLock<()> as lock_api::RawRwLock>::try_lock_exclusive(&self.1) {
+            // resume interrupt state
+            I::pop_off();
+            false
+        } else {
+            true
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn unlock_exclusive(&self) {
+        <spin::RwLock<()> as lock_api::RawRwLock>::unlock_exclusive(&self.1);
+        // resume interrupt state
+        I::pop_off();
+    }
+
+    #[inline(always)]
+    fn is_locked(&self) -> bool {
+        <spin::RwLock<()> as lock_api::RawRwLock>::is_locked(&self.1)
+    }
+}
+
+// The lock guard is declared first and so dropped first,
+// the lock is released before the interrupt state is resumed.
+pub struct MutexIrqGuard<'a, I: Irq, T> {
+    inner: spin::MutexGuard<'a, T>,
+    _irq: InterruptGuard<I>,
+}
+pub struct RwLockWriteIrqGuard<'a, I: Irq, T> {
+    inner: spin::RwLockWriteGuard<'a, T>,
+    _irq: InterruptGuard<I>,
+}
+pub struct RwLockReadIrqGuard<'a, I: Irq, T> {
+    inner: spin::RwLockReadGuard<'a, T>,
+    _irq: InterruptGuard<I>,
+}
+pub struct RwLockUpgradableIrqGuard<'a, I: Irq, T> {
+    inner: spin::RwLockUpgradableGuard<'a, T>,
+    _irq: InterruptGuard<I>,
+}
+
+impl<'a, I: Irq, T> RwLockWriteIrqGuard<'a, I, T> {
+    pub fn downgrade(self) -> RwLockReadIrqGuard<'a, I, T> {
+        RwLockReadIrqGuard {
+            inner: self.inner.downgrade(),
+            _irq: self._irq,
+        }
+    }
+
+    pub fn downgrade_to_upgradeable(self) -> RwLockUpgradableIrqGuard<'a, I, T> {
+        RwLockUpgradableIrqGuard {
+            inner: self.inner.downgrade_to_upgradeable(),
+            _irq: self._irq,
+        }
+    }
+}
+
+impl<'a, I: Irq, T> RwLockUpgradableIrqGuard<'a, I, T> {
+    pub fn upgrade(self) -> RwLockWriteIrqGuard<'a, I, T> {
+        RwLockWriteIrqGuard {
+            inner: self.inner.upgrade(),
+            _irq: self._irq,
+        }
+    }
+
+    pub fn try_upgrade(self) -> core::result::Result<RwLockWriteIrqGuard<'a, I, T>, Self> {
+        let irq = self._irq;
+        match self.inner.try_upgrade() {
+            Ok(inner) => Ok(RwLockWriteIrqGuard { inner, _irq: irq }),
+            Err(inner) => Err(RwLockUpgradableIrqGuard { inner, _irq: irq }),
+        }
+    }
+}
+
+macro_rules! impl_deref_for_guard {
+    ($name:ident) => {
+        impl<'a, I: Irq, T> core::ops::Deref for $name<'a, I, T> {
+            type Target = T;
+            fn deref(&self) -> &Self::Target {
+                self.inner.deref()
+            }
+        }
+    };
+}
+
+macro_rules! impl_deref_mut_for_guard {
+    ($name:ident) => {
+        impl<'a, I: Irq, T> core::ops::DerefMut for $name<'a, I, T> {
+            fn deref_mut(&mut self) -> &mut Self::Target {
+                self.inner.deref_mut()
+            }
+        }
+    };
+}
+
+impl_deref_for_guard!(MutexIrqGuard);
+impl_deref_for_guard!(RwLockReadIrqGuard);
+impl_deref_for_guard!(RwLockWriteIrqGuard);
+impl_deref_for_guard!(RwLockUpgradableIrqGuard);
+
+impl_deref_mut_for_guard!(MutexIrqGuard);
+impl_deref_mut_for_guard!(RwLockWriteIrqGuard);
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, collections::BTreeMap, sync::Arc, thread, vec::Vec};
+
+    use super::{InterruptGuard, Irq, MutexIrq, NestedOff, RwLockIrq};
+
+    std::thread_local! {
+        // Each thread plays a CPU, interrupts start enabled.
+        static CPU: RefCell<(NestedOff, bool)> = RefCell::new((NestedOff::new(), true));
+    }
+
+    struct TestIrq;
+
+    impl Irq for TestIrq {
+        fn push_off() {
+            CPU.with(|cpu| {
+                let (nested, enabled) = &mut *cpu.borrow_mut();
+                nested.push(core::mem::replace(enabled, false));
+            })
+        }
+
+        fn pop_off() {
+            CPU.with(|cpu| {
+                let (nested, enabled) = &mut *cpu.borrow_mut();
+                if nested.pop() {
+                    *enabled = true;
+                }
+            })
+        }
+    }
+
+    fn enabled() -> bool {
+        CPU.with(|cpu| cpu.borrow().1)
+    }
+
+    fn depth() -> usize {
+        CPU.with(|cpu| cpu.borrow().0.depth())
+    }
+
+    fn set_enabled(enabled: bool) {
+        CPU.with(|cpu| cpu.borrow_mut().1 = enabled)
+    }
+
+    #[test]
+    fn nested_guards_restore_the_original_state() {
+        let outer = InterruptGuard::<TestIrq>::new();
+        let inner = InterruptGuard::<TestIrq>::new();
+        assert!(!enabled());
+        drop(inner);
+        assert!(!enabled());
+        assert_eq!(depth(), 1);
+        drop(outer);
+        assert!(enabled());
+        assert_eq!(depth(), 0);
+
+        // Interrupts disabled before the outermost guard stay disabled.
+        set_enabled(false);
+        let outer = InterruptGuard::<TestIrq>::new();
+        drop(InterruptGuard::<TestIrq>::new());
+        drop(outer);
+        assert!(!enabled());
+        set_enabled(true);
+
+        // The guard of a lock nests inside other guards like any other.
+        let lock = MutexIrq::<TestIrq, _>::new(0);
+        let outer = InterruptGuard::<TestIrq>::new();
+        *lock.lock() += 1;
+        assert!(!enabled());
+        drop(outer);
+        assert!(enabled());
+    }
+
+    #[test]
+    #[should_panic(expected = "pop_off without a matching push_off")]
+    fn unmatched_pop_off_panics() {
+        TestIrq::pop_off();
+    }
+
+    #[test]
+    fn try_read_and_try_write_fail_without_spinning() {
+        let lock = RwLockIrq::<TestIrq, _>::new(1);
+        {
+            let read = lock.try_read().unwrap();
+            assert_eq!(*read, 1);
+            assert!(lock.try_read().is_some());
+            assert!(lock.try_write().is_none());
+            assert_eq!(depth(), 1);
+        }
+        {
+            let mut write = lock.try_write().unwrap();
+            *write = 2;
+            assert!(lock.try_read().is_none());
+            assert!(lock.try_write().is_none());
+            // The failed attempts leave the interrupt state as they found it.
+            assert_eq!(depth(), 1);
+            assert!(!enabled());
+        }
+        assert_eq!(depth(), 0);
+        assert!(enabled());
+        assert_eq!(*lock.try_read().unwrap(), 2);
+
+        let mutex = MutexIrq::<TestIrq, _>::new(());
+        let guard = mutex.try_lock().unwrap();
+        assert!(mutex.try_lock().is_none());
+        assert_eq!(depth(), 1);
+        drop(guard);
+        assert!(enabled());
+    }
+
+    #[test]
+    fn raw_locks_restore_interrupts_on_failure() {
+        use lock_api::{RawMutex, RawRwLock};
+
+        let raw = RwLockIrq::<TestIrq, ()>::INIT;
+        raw.lock_exclusive();
+        assert!(!raw.try_lock_shared());
+        assert!(!raw.try_lock_exclusive());
+        assert_eq!(depth(), 1);
+        unsafe { raw.unlock_exclusive() };
+        assert!(enabled());
+
+        let raw = MutexIrq::<TestIrq, ()>::INIT;
+        assert!(RawMutex::try_lock(&raw));
+        assert!(!RawMutex::try_lock(&raw));
+        unsafe { RawMutex::unlock(&raw) };
+        assert_eq!(depth(), 0);
+    }
+
+    #[test]
+    fn concurrent_registration_into_a_static() {
+        static REGISTRY: RwLockIrq<TestIrq, BTreeMap<u32, Arc<dyn Fn() -> u32 + Send + Sync>>> =
+            RwLockIrq::new(BTreeMap::new());
+
+        let threads: Vec<_> = (0..8u32)
+            .map(|cpu| {
+                thread::spawn(move || {
+                    for i in 0..64 {
+                        let irq = cpu * 64 + i;
+                        REGISTRY.write().insert(irq, Arc::new(move || irq));
+                        // Look up an entry and call it without holding the lock.
+                        let ack = REGISTRY.read().get(&irq).cloned().unwrap();
+                        assert_eq!(ack(), irq);
+                    }
+                    assert_eq!(depth(), 0);
+                    assert!(enabled());
+                })
+            })
+            .collect();
+        threads
+            .into_iter()
+            .for_each(|thread| thread.join().unwrap());
+
+        let registry = REGISTRY.read();
+        assert_eq!(registry.len(), 8 * 64);
+        assert!(registry.iter().all(|(irq, ack)| ack() == *irq));
+    }
+}
@@ -0,0 +1,200 @@
+#![no_std]
+
+use core::{
+    ops::{Add, Sub},
+    time::Duration,
+};
+
+pub const NSEC_PER_SEC: i64 = 1_000_000_000;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Timespec {
+    pub sec: i64,  // Seconds - >= 0
+    pub nsec: i32, // Nanoseconds - [0, 999999999]
+}
+
+impl Timespec {
+    pub fn unix_timestamp(&self) -> u32 {
+        self.sec as u32
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.sec == 0 && self.nsec == 0
+    }
+
+    /// Converts a time given by user space, returns None if it is negative
+    /// or `nsec` is not in [0, 999999999].
+    pub fn to_duration(&self) -> Option<Duration> {
+        if self.sec < 0 || !(0..NSEC_PER_SEC).contains(&(self.nsec as i64)) {
+            return None;
+        }
+        Some(Duration::new(self.sec as u64, self.nsec as u32))
+    }
+
+    /// Creates a normalized `Timespec`, carrying whole seconds out of `nsec`.
+    /// A negative time has a negative `sec` and a non-negative `nsec`.
+    pub fn new(sec: i64, nsec: i64) -> Self {
+        Self {
+            sec: sec + nsec.div_euclid(NSEC_PER_SEC),
+            nsec: nsec.rem_euclid(NSEC_PER_SEC) as i32,
+        }
+    }
+
+    pub fn from_nanos(nanos: i128) -> Self {
+        let nsec_per_sec = NSEC_PER_SEC as i128;
+        Self {
+            sec: nanos.div_euclid(nsec_per_sec) as i64,
+            nsec: nanos.rem_euclid(nsec_per_sec) as i32,
+        }
+    }
+
+    pub fn as_nanos(&self) -> i128 {
+        self.sec as i128 * NSEC_PER_SEC as i128 + self.nsec as i128
+    }
+
+    pub fn from_millis(millis: i64) -> Self {
+        Self::new(millis.div_euclid(1000), millis.rem_euclid(1000) * 1_000_000)
+    }
+}
+
+impl Add for Timespec {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self::new(self.sec + rhs.sec, self.nsec as i64 + rhs.nsec as i64)
+    }
+}
+
+impl Sub for Timespec {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self::new(self.sec - rhs.sec, self.nsec as i64 - rhs.nsec as i64)
+    }
+}
+
+impl From<Duration> for Timespec {
+    fn from(duration: Duration) -> Self {
+        Self {
+            sec: duration.as_secs() as i64,
+            nsec: duration.subsec_nanos() as i32,
+        }
+    }
+}
+
+/// `struct timeval`, a time with microsecond precision.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Timeval {
+    pub sec: i64,
+    pub usec: i64,
+}
+
+impl From<Duration> for Timeval {
+    fn from(duration: Duration) -> Self {
+        Self {
+            sec: duration.as_secs() as i64,
+            usec: duration.subsec_micros() as i64,
+        }
+    }
+}
+
+impl From<u32> for Timespec {
+    fn from(unix_timestamp: u32) -> Self {
+        Self {
+            sec: unix_timestamp as i64,
+            nsec: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::time::Duration;
+
+    use super::{Timespec, Timeval, NSEC_PER_SEC};
+
+    #[test]
+    fn nsec_overflow_carries_into_sec() {
+        let sum = Timespec::new(1, 600_000_000) + Timespec::new(2, 700_000_000);
+        assert_eq!(sum, Timespec::new(4, 300_000_000));
+        assert_eq!(
+            Timespec::new(0, 3 * NSEC_PER_SEC + 5),
+            Timespec { sec: 3, nsec: 5 }
+        );
+        let max_nsec = Timespec::new(0, NSEC_PER_SEC - 1);
+        assert_eq!(max_nsec + Timespec::new(0, 1), Timespec { sec: 1, nsec: 0 });
+    }
+
+    #[test]
+    fn negative_results_keep_nsec_positive() {
+        let diff = Timespec::new(1, 200_000_000) - Timespec::new(2, 700_000_000);
+        assert_eq!(
+            diff,
+            Timespec {
+                sec: -2,
+                nsec: 500_000_000
+            }
+        );
+        assert_eq!(diff.as_nanos(), -1_500_000_000);
+        assert_eq!(
+            Timespec::new(0, -1),
+            Timespec {
+                sec: -1,
+                nsec: 999_999_999
+            }
+        );
+        assert_eq!(
+            Timespec::from_millis(-1),
+            Timespec {
+                sec: -1,
+                nsec: 999_000_000
+            }
+        );
+        assert!(diff < Timespec::default());
+        assert_eq!(diff.to_duration(), None);
+    }
+
+    #[test]
+    fn conversions_round_trip() {
+        for nanos in [
+            0,
+            1,
+            999_999_999,
+            1_000_000_000,
+            -1,
+            -1_000_000_001,
+            i64::MAX as i128,
+        ] {
+            assert_eq!(Timespec::from_nanos(nanos).as_nanos(), nanos);
+        }
+        let duration = Duration::new(5, 123_456_789);
+        let timespec = Timespec::from(duration);
+        assert_eq!(timespec.to_duration(), Some(duration));
+        assert_eq!(Timespec::from_nanos(timespec.as_nanos()), timespec);
+        assert_eq!(Timespec::from_millis(1500), Timespec::new(1, 500_000_000));
+        assert_eq!(
+            Timeval::from(duration),
+            Timeval {
+                sec: 5,
+                usec: 123_456
+            }
+        );
+        assert_eq!(Timespec::from(7u32).unix_timestamp(), 7);
+    }
+
+    #[test]
+    fn out_of_range_user_times_are_refused() {
+        assert_eq!(Timespec { sec: 1, nsec: -1 }.to_duration(), None);
+        assert_eq!(
+            Timespec {
+                sec: 1,
+                nsec: NSEC_PER_SEC as i32
+            }
+            .to_duration(),
+            None
+        );
+        assert!(Timespec::default().is_zero());
+    }
+}
@@ -1,27 +1,33 @@
 use alloc::{boxed::Box, sync::Arc, vec::Vec};
 
-use super::{mount_fs::NotDynInode, vfs};
-use crate::{spinlock::MutexIrq, time::Timespec};
 use futures_util::future::BoxFuture;
+use spinlock::{Irq, MutexIrq};
+use time::Timespec;
 
-pub type Filesystem<InnerFs> = Arc<CacheFs<InnerFs>>;
+use crate as vfs;
+use crate::{mount_fs::NotDynInode, DirEntryName, FsStr};
 
-pub struct CacheFs<InnerFs: vfs::Filesystem> {
+pub type Filesystem<InnerFs, I> = Arc<CacheFs<InnerFs, I>>;
+
+pub struct CacheFs<InnerFs: vfs::Filesystem, I> {
     inner: InnerFs,
-    inodes_cache: MutexIrq<lru::LruCache<usize, Arc<CInode<InnerFs>>>>,
+    inodes_cache: MutexIrq<I, lru::LruCache<usize, Arc<CInode<InnerFs, I>>>>,
 }
 
-impl<InnerFs: vfs::Filesystem + 'static> vfs::Filesystem for Filesystem<InnerFs> {
-    type Inode = Arc<CInode<InnerFs>>;
+impl<InnerFs: vfs::Filesystem + 'static, I: Irq + 'static> vfs::Filesystem
+    for Filesystem<InnerFs, I>
+{
+    type Inode = Arc<CInode<InnerFs, I>>;
 
     type CreateInodeFut<'a> = BoxFuture<'a, vfs::Result<Self::Inode>>;
     type LoadInodeFut<'a> = BoxFuture<'a, vfs::Result<Option<Self::Inode>>>;
+    type SyncFut<'a> = InnerFs::SyncFut<'a>;
 
     fn root_dir_entry_raw(&self) -> vfs::RawDirEntry {
         self.inner.root_dir_entry_raw()
     }
 
-    fn root_dir_entry(&self) -> vfs::DirEntry<Filesystem<InnerFs>> {
+    fn root_dir_entry(&self) -> vfs::DirEntry<Filesystem<InnerFs, I>> {
         vfs::DirEntry {
             raw: self.root_dir_entry_raw(),
             fs: self.clone(),
@@ -64,6 +70,10 @@ impl<InnerFs: vfs::Filesystem + 'static> vfs::Filesystem for Filesystem<InnerFs>
         })
     }
 
+    fn sync(&self) -> Self::SyncFut<'_> {
+        self.inner.sync()
+    }
+
     /// Get the BlkDevice's block_size.
     fn blk_size(&self) -> u32 {
         self.inner.blk_size()
@@ -73,21 +83,26 @@ impl<InnerFs: vfs::Filesystem + 'static> vfs::Filesystem for Filesystem<InnerFs>
     fn blk_count(&self) -> usize {
         self.inner.blk_count()
     }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
 }
 
-pub struct CInode<InnerFs: vfs::Filesystem> {
-    cache_fs: Arc<CacheFs<InnerFs>>,
+pub struct CInode<InnerFs: vfs::Filesystem, I> {
+    cache_fs: Arc<CacheFs<InnerFs, I>>,
     inner: InnerFs::Inode,
 }
 
-impl<InnerFs: vfs::Filesystem + 'static> NotDynInode for Arc<CInode<InnerFs>> {}
+impl<InnerFs: vfs::Filesystem + 'static, I> NotDynInode for Arc<CInode<InnerFs, I>> {}
 
-impl<InnerFs: vfs::Filesystem + 'static> vfs::Inode for Arc<CInode<InnerFs>> {
-    type FS = Filesystem<InnerFs>;
+impl<InnerFs: vfs::Filesystem + 'static, I: Irq + 'static> vfs::Inode for Arc<CInode<InnerFs, I>> {
+    type FS = Filesystem<InnerFs, I>;
 
     type MetadataFut<'a> = <InnerFs::Inode as vfs::Inode>::MetadataFut<'a>;
     type ChownFut<'a> = <InnerFs::Inode as vfs::Inode>::ChownFut<'a>;
     type ChmodFut<'a> = <InnerFs::Inode as vfs::Inode>::ChmodFut<'a>;
+    type SetTimesFut<'a> = <InnerFs::Inode as vfs::Inode>::SetTimesFut<'a>;
     type LinkFut<'a> = <InnerFs::Inode as vfs::Inode>::LinkFut<'a>;
     type UnlinkFut<'a> = <InnerFs::Inode as vfs::Inode>::UnlinkFut<'a>;
     type ReadAtFut<'a> = <InnerFs::Inode as vfs::Inode>::ReadAtFut<'a>;
@@ -101,6 +116,11 @@ impl<InnerFs: vfs::Filesystem + 'static> vfs::Inode for Arc<CInode<InnerFs>> {
     type LsRawFut<'a> = <InnerFs::Inode as vfs::Inode>::LsRawFut<'a>;
     type LsFut<'a> = BoxFuture<'a, vfs::Result<Vec<vfs::DirEntry<Self::FS>>>>;
     type IOCtlFut<'a> = <InnerFs::Inode as vfs::Inode>::IOCtlFut<'a>;
+    type PollFut<'a> = <InnerFs::Inode as vfs::Inode>::PollFut<'a>;
+    type GetXattrFut<'a> = <InnerFs::Inode as vfs::Inode>::GetXattrFut<'a>;
+    type SetXattrFut<'a> = <InnerFs::Inode as vfs::Inode>::SetXattrFut<'a>;
+    type ListXattrFut<'a> = <InnerFs::Inode as vfs::Inode>::ListXattrFut<'a>;
+    type RemoveXattrFut<'a> = <InnerFs::Inode as vfs::Inode>::RemoveXattrFut<'a>;
 
     fn id(&self) -> usize {
         self.inner.id()
@@ -118,6 +138,15 @@ impl<InnerFs: vfs::Filesystem + 'static> vfs::Inode for Arc<CInode<InnerFs>> {
         self.inner.chmod(mode)
     }
 
+    fn set_times(
+        &self,
+        atime: Option<Timespec>,
+        mtime: Option<Timespec>,
+        ctime: Option<Timespec>,
+    ) -> Self::SetTimesFut<'_> {
+        self.inner.set_times(atime, mtime, ctime)
+    }
+
     fn link(&self) -> Self::LinkFut<'_> {
         self.inner.link()
     }
@@ -134,6 +163,22 @@ impl<InnerFs: vfs::Filesystem + 'static> vfs::Inode for Arc<CInode<InnerFs>> {
         self.inner.write_at(offset, src)
     }
 
+    fn read_vectored<'a, 'b>(
+        &'a self,
+        offset: u64,
+        bufs: &'a mut [&'b mut [u8]],
+    ) -> BoxFuture<'a, vfs::Result<usize>> {
+        self.inner.read_vectored(offset, bufs)
+    }
+
+    fn write_vectored<'a>(
+        &'a self,
+        offset: u64,
+        srcs: &'a [&'a [u8]],
+    ) -> BoxFuture<'a, vfs::Result<usize>> {
+        self.inner.write_vectored(offset, srcs)
+    }
+
     fn sync(&self) -> Self::SyncFut<'_> {
         self.inner.sync()
     }
@@ -142,11 +187,11 @@ impl<InnerFs: vfs::Filesystem + 'static> vfs::Inode for Arc<CInode<InnerFs>> {
         self.inner.append_dot(parent_inode_id)
     }
 
-    fn lookup_raw<'a>(&'a self, name: &'a super::FsStr) -> Self::LookupRawFut<'a> {
+    fn lookup_raw<'a>(&'a self, name: &'a FsStr) -> Self::LookupRawFut<'a> {
         self.inner.lookup_raw(name)
     }
 
-    fn lookup<'a>(&'a self, name: &'a super::FsStr) -> Self::LookupFut<'a> {
+    fn lookup<'a>(&'a self, name: &'a FsStr) -> Self::LookupFut<'a> {
         Box::pin(async move {
             Ok(self
                 .inner
@@ -161,14 +206,14 @@ impl<InnerFs: vfs::Filesystem + 'static> vfs::Inode for Arc<CInode<InnerFs>> {
 
     fn append(
         &self,
-        dir_entry_name: super::DirEntryName,
+        dir_entry_name: DirEntryName,
         inode_id: usize,
         file_type: Option<vfs::FileType>,
     ) -> Self::AppendFut<'_> {
         self.inner.append(dir_entry_name, inode_id, file_type)
     }
 
-    fn remove<'a>(&'a self, dir_entry_name: &'a super::FsStr) -> Self::RemoveFut<'a> {
+    fn remove<'a>(&'a self, dir_entry_name: &'a FsStr) -> Self::RemoveFut<'a> {
         self.inner.remove(dir_entry_name)
     }
 
@@ -194,4 +239,24 @@ impl<InnerFs: vfs::Filesystem + 'static> vfs::Inode for Arc<CInode<InnerFs>> {
     fn ioctl(&self, cmd: u32, arg: usize) -> Self::IOCtlFut<'_> {
         self.inner.ioctl(cmd, arg)
     }
+
+    fn poll(&self, events: vfs::PollEvents) -> Self::PollFut<'_> {
+        self.inner.poll(events)
+    }
+
+    fn getxattr<'a>(&'a self, name: &'a [u8]) -> Self::GetXattrFut<'a> {
+        self.inner.getxattr(name)
+    }
+
+    fn setxattr<'a>(&'a self, name: &'a [u8], value: &'a [u8]) -> Self::SetXattrFut<'a> {
+        self.inner.setxattr(name, value)
+    }
+
+    fn listxattr(&self) -> Self::ListXattrFut<'_> {
+        self.inner.listxattr()
+    }
+
+    fn removexattr<'a>(&'a self, name: &'a [u8]) -> Self::RemoveXattrFut<'a> {
+        self.inner.removexattr(name)
+    }
 }
@@ -0,0 +1,608 @@
+//! Open files and the descriptors referring to them.
+
+use alloc::sync::Arc;
+use core::{
+    future::Future,
+    task::{Context, Poll},
+};
+use futures_util::{pin_mut, task::noop_waker_ref};
+use sleeplock::Mutex;
+use spinlock::{Irq, MutexIrq, RwLockIrq};
+
+use crate::{ioctl, mount_fs::DynInode, util, Error, Mode, PollEvents, Result};
+
+type Inode = Arc<dyn DynInode>;
+
+/// Enumeration of possible methods to seek within an [File](File).
+///
+/// It is used by the [`seek`](File::seek) method.
+#[derive(Copy, PartialEq, Eq, Clone, Debug)]
+pub enum SeekFrom {
+    /// Sets the offset to the provided number of bytes.
+    Start(u64),
+
+    /// Sets the offset to the size of this file plus the specified number of
+    /// bytes.
+    ///
+    /// It is possible to seek beyond the end of a file, but it's an error to
+    /// seek before byte 0.
+    End(i64),
+
+    /// Sets the offset to the current position plus the specified number of
+    /// bytes.
+    ///
+    /// It is possible to seek beyond the end of a file, but it's an error to
+    /// seek before byte 0.
+    Current(i64),
+}
+
+bitflags! {
+    pub struct OpenOptions: u8 {
+        const READ = 0x20;
+        const WRITE = 0x1;
+        const CREATE = 0x2;
+        const APPEND = 0x10;
+        const TRUNC = 0x4;
+        const NONBLOCK = 0x8;
+        /// Only names the file, for `*at` lookups and `fstat`, it cannot be read or written.
+        const PATH = 0x40;
+        /// Fails to open anything but a directory.
+        const DIRECTORY = 0x80;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Description {
+    opts: OpenOptions,
+}
+
+/// The file offset, shared by every descriptor referring to the same open file.
+#[derive(Debug, Default)]
+struct OffsetState {
+    offset: u64,
+}
+
+/// An open file, shared by all descriptors duplicated from the same `open`,
+/// including the copies a forked process inherits.
+/// Separate `open` calls create separate open files with independent offsets.
+struct OpenFile<I: Irq> {
+    inode: Inode,
+    description: RwLockIrq<I, Description>,
+    // Not held across the inode access, a read or write takes the offset to start at
+    // and stores the offset after the transferred bytes once it is done.
+    offset: Mutex<MutexIrq<I, ()>, OffsetState>,
+}
+
+impl<I: Irq> OpenFile<I> {
+    // Called when the last descriptor referencing this file is closed.
+    async fn finalize(&self) -> Result<()> {
+        let opts = self.description.read().opts;
+        if opts.contains(OpenOptions::WRITE) {
+            self.inode.sync().await?;
+        }
+        Ok(())
+    }
+}
+
+impl<I: Irq> Drop for OpenFile<I> {
+    fn drop(&mut self) {
+        // A file dropped without `close` is not synced, its writes stay cached
+        // until the filesystem is synced. An exiting process closes its files.
+        self.inode.release();
+    }
+}
+
+/// A file descriptor, cloning it yields a duplicate referencing the same open file.
+pub struct Descriptor<I: Irq> {
+    file: Arc<OpenFile<I>>,
+    cloexec: bool,
+}
+
+impl<I: Irq> Clone for Descriptor<I> {
+    fn clone(&self) -> Self {
+        self.dup(self.cloexec)
+    }
+}
+
+impl<I: Irq> Descriptor<I> {
+    pub fn new(inode: Inode, opts: OpenOptions, cloexec: bool) -> Self {
+        inode.open();
+        Self {
+            file: Arc::new(OpenFile {
+                inode,
+                description: RwLockIrq::new(Description { opts }),
+                offset: Mutex::new(OffsetState::default()),
+            }),
+            cloexec,
+        }
+    }
+
+    /// Opens `inode` with `opts`, fails with `NotDir` if `DIRECTORY` is set and it is not one.
+    /// Besides `PATH`, only `DIRECTORY` counts for a descriptor that only names the file.
+    pub async fn open(inode: Inode, opts: OpenOptions, cloexec: bool) -> Result<Self> {
+        let opts = if opts.contains(OpenOptions::PATH) {
+            opts & (OpenOptions::PATH | OpenOptions::DIRECTORY)
+        } else {
+            opts
+        };
+        if opts.contains(OpenOptions::DIRECTORY) && !inode.metadata().await?.mode.is_dir() {
+            return Err(Error::NotDir);
+        }
+        Ok(Self::new(inode, opts, cloexec))
+    }
+
+    /// Returns a new descriptor referring to the same open file,
+    /// the two share the offset but not the close-on-exec flag.
+    pub fn dup(&self, cloexec: bool) -> Self {
+        Self {
+            file: self.file.clone(),
+            cloexec,
+        }
+    }
+
+    /// Returns the inode of the open file.
+    pub fn inode(&self) -> &Inode {
+        &self.file.inode
+    }
+
+    /// Seek to an offset, in bytes.
+    pub async fn seek(&self, pos: SeekFrom) -> Result<u64> {
+        let size = match pos {
+            SeekFrom::End(_) => self.file.inode.metadata().await?.size,
+            _ => 0,
+        };
+        let mut state = self.file.offset.lock().await;
+        let offset = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(delta) => size as i64 + delta,
+            SeekFrom::Current(delta) => state.offset as i64 + delta,
+        };
+        if offset < 0 {
+            return Err(Error::InvalidSeekOffset);
+        }
+        state.offset = offset as u64;
+        Ok(state.offset)
+    }
+
+    /// Read some bytes from this file into the specified buffer, returning how many bytes were read.
+    /// A non-blocking file fails with `WouldBlock` instead of waiting for data.
+    pub async fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        self.check_ready(PollEvents::IN)?;
+        let offset = self.file.offset.lock().await.offset;
+        let read_size = self.file.inode.read_at(offset, buf).await?;
+        self.file.offset.lock().await.offset = offset + read_size as u64;
+        Ok(read_size)
+    }
+
+    /// Write a buffer into this file, returning how many bytes were written.
+    /// A non-blocking file fails with `WouldBlock` instead of waiting for room.
+    pub async fn write(&self, src: &[u8]) -> Result<usize> {
+        if !self.writable() {
+            return Err(Error::ReadOnly);
+        }
+        self.check_ready(PollEvents::OUT)?;
+        let offset = self.write_offset().await?;
+        let write_size = self.file.inode.write_at(offset, src).await?;
+        self.file.offset.lock().await.offset = offset + write_size as u64;
+        Ok(write_size)
+    }
+
+    /// Like `read`, but fills `bufs` one after another.
+    pub async fn read_vectored(&self, bufs: &mut [&mut [u8]]) -> Result<usize> {
+        self.check_ready(PollEvents::IN)?;
+        let offset = self.file.offset.lock().await.offset;
+        let read_size = self.file.inode.read_vectored(offset, bufs).await?;
+        self.file.offset.lock().await.offset = offset + read_size as u64;
+        Ok(read_size)
+    }
+
+    /// Like `write`, but writes `srcs` one after another.
+    pub async fn write_vectored(&self, srcs: &[&[u8]]) -> Result<usize> {
+        if !self.writable() {
+            return Err(Error::ReadOnly);
+        }
+        self.check_ready(PollEvents::OUT)?;
+        let offset = self.write_offset().await?;
+        let write_size = self.file.inode.write_vectored(offset, srcs).await?;
+        self.file.offset.lock().await.offset = offset + write_size as u64;
+        Ok(write_size)
+    }
+
+    // The offset a write starts at, the end of the file if it is opened for appending.
+    async fn write_offset(&self) -> Result<u64> {
+        if self.options().contains(OpenOptions::APPEND) {
+            Ok(self.file.inode.metadata().await?.size)
+        } else {
+            Ok(self.file.offset.lock().await.offset)
+        }
+    }
+
+    /// Returns the current offset, in bytes.
+    pub async fn offset(&self) -> u64 {
+        self.file.offset.lock().await.offset
+    }
+
+    /// Move the offset forward by `len` bytes, e.g. after transferring data without `read`/`write`.
+    pub async fn advance(&self, len: u64) {
+        self.file.offset.lock().await.offset += len;
+    }
+
+    // Polls the inode once if the file is non-blocking, regular files are always ready.
+    fn check_ready(&self, events: PollEvents) -> Result<()> {
+        if !self.nonblocking() {
+            return Ok(());
+        }
+        let poll = self.file.inode.poll(events);
+        pin_mut!(poll);
+        match poll.poll(&mut Context::from_waker(noop_waker_ref())) {
+            Poll::Ready(ready) if ready.intersects(events) => Ok(()),
+            _ => Err(Error::WouldBlock),
+        }
+    }
+
+    /// Returns the flags the file was opened with, as changed by `set_status_flags`.
+    pub fn options(&self) -> OpenOptions {
+        self.file.description.read().opts
+    }
+
+    /// Replace the flags that may change after open, `APPEND` and `NONBLOCK`, others are ignored.
+    /// The change is seen through all duplicates of this descriptor.
+    pub fn set_status_flags(&self, opts: OpenOptions) {
+        let settable = OpenOptions::APPEND | OpenOptions::NONBLOCK;
+        let mut desc = self.file.description.write();
+        desc.opts = (desc.opts - settable) | (opts & settable);
+    }
+
+    /// Returns whether this descriptor is closed by `execve`.
+    pub fn cloexec(&self) -> bool {
+        self.cloexec
+    }
+
+    pub fn set_cloexec(&mut self, cloexec: bool) {
+        self.cloexec = cloexec;
+    }
+
+    pub fn nonblocking(&self) -> bool {
+        self.file
+            .description
+            .read()
+            .opts
+            .contains(OpenOptions::NONBLOCK)
+    }
+
+    /// Whether the descriptor was opened with `O_PATH`.
+    pub fn is_path(&self) -> bool {
+        self.file
+            .description
+            .read()
+            .opts
+            .contains(OpenOptions::PATH)
+    }
+
+    pub fn readable(&self) -> bool {
+        self.file
+            .description
+            .read()
+            .opts
+            .contains(OpenOptions::READ)
+    }
+
+    pub fn writable(&self) -> bool {
+        self.file
+            .description
+            .read()
+            .opts
+            .contains(OpenOptions::WRITE)
+    }
+
+    /// Handles the generic `ioctl` commands of the open file, `FIONBIO`, and `FIONREAD`
+    /// on a regular file, the others are passed to the inode. `arg` must be a valid pointer.
+    pub async fn ioctl(&self, cmd: u32, arg: usize) -> Result<()> {
+        match cmd {
+            ioctl::CMD_FIONBIO => {
+                let mut opts = self.options();
+                opts.set(OpenOptions::NONBLOCK, unsafe { *(arg as *const i32) } != 0);
+                self.set_status_flags(opts);
+                return Ok(());
+            }
+            ioctl::CMD_FIONREAD => {
+                // Regular files have everything after the offset available.
+                let metadata = self.file.inode.metadata().await?;
+                if metadata.mode.file_type() == Mode::TY_REG {
+                    let available = metadata.size.saturating_sub(self.offset().await);
+                    unsafe { *(arg as *mut i32) = available.min(i32::MAX as u64) as i32 };
+                    return Ok(());
+                }
+            }
+            _ => {}
+        }
+        self.file.inode.ioctl(cmd, arg).await
+    }
+
+    /// Flush this file, ensuring that all intermediately buffered contents reach their underlying device.
+    pub async fn flush(&self) -> Result<()> {
+        if self.writable() {
+            self.file.inode.sync().await?;
+        }
+        Ok(())
+    }
+
+    /// Close this descriptor.
+    /// The open file is finalized only if this was its last descriptor,
+    /// duplicates keep it open.
+    pub async fn close(self) -> Result<()> {
+        match Arc::try_unwrap(self.file) {
+            Ok(file) => file.finalize().await,
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+/// Copies up to `count` bytes from `src` to `dst` within the kernel, as `sendfile` does.
+/// If `offset` is given, reading starts at `*offset` which is then advanced
+/// instead of the offset of `src`. Returns the number of bytes copied.
+pub async fn sendfile<I: Irq>(
+    dst: &Descriptor<I>,
+    src: &Descriptor<I>,
+    offset: Option<&mut u64>,
+    count: u64,
+) -> Result<u64> {
+    let src_offset = match &offset {
+        Some(offset) => **offset,
+        None => src.offset().await,
+    };
+    let copied = util::copy_at(
+        src.inode(),
+        src_offset,
+        dst.inode(),
+        dst.offset().await,
+        Some(count),
+    )
+    .await?;
+    match offset {
+        Some(offset) => *offset += copied,
+        None => src.advance(copied).await,
+    }
+    dst.advance(copied).await;
+    Ok(copied)
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::sync::Arc;
+    use tokio_test::block_on;
+
+    use super::{sendfile, Descriptor, OpenOptions, SeekFrom};
+    use crate::{
+        dev_fs::DevInode,
+        ioctl,
+        mock::{create, ram_vfs, TestDev, TestIrq},
+        mount_fs::DynInode,
+        socket::SocketEnd,
+        Error, Mode, Result,
+    };
+
+    // Two files of a RAM filesystem opened for reading and writing, the first holds `data`.
+    fn open_two(data: &[u8]) -> (Descriptor<TestIrq>, Descriptor<TestIrq>) {
+        let (vfs, root) = ram_vfs();
+        let opts = OpenOptions::READ | OpenOptions::WRITE;
+        let open = |name| {
+            let inode = block_on(create(&vfs, &root, name, Mode::TY_REG));
+            Descriptor::new(Arc::new(inode) as Arc<dyn DynInode>, opts, false)
+        };
+        let (src, dst) = (open("src"), open("dst"));
+        assert_eq!(block_on(src.write(data)).unwrap(), data.len());
+        (src, dst)
+    }
+
+    #[test]
+    fn only_the_last_close_of_an_open_file_syncs_it() {
+        let (dev, inode) = TestDev::inode();
+        let file = Descriptor::<TestIrq>::new(inode, OpenOptions::WRITE, false);
+        let dup = file.dup(true);
+        assert!(dup.cloexec() && !file.cloexec());
+
+        block_on(dup.close()).unwrap();
+        assert_eq!(dev.syncs(), 0);
+        // The duplicate closed, the file still works.
+        assert_eq!(block_on(file.write(b"abc")).unwrap(), 3);
+        block_on(file.close()).unwrap();
+        assert_eq!(dev.syncs(), 1);
+    }
+
+    #[test]
+    fn a_file_not_opened_for_writing_is_not_synced() {
+        let (dev, inode) = TestDev::inode();
+        let file = Descriptor::<TestIrq>::new(inode, OpenOptions::READ, false);
+        block_on(file.clone().close()).unwrap();
+        block_on(file.close()).unwrap();
+        assert_eq!(dev.syncs(), 0);
+    }
+
+    #[test]
+    fn sendfile_reads_from_the_offset_of_the_file_and_advances_it() {
+        let (src, dst) = open_two(b"hello world");
+        block_on(src.seek(SeekFrom::Start(6))).unwrap();
+        assert_eq!(block_on(sendfile(&dst, &src, None, 100)).unwrap(), 5);
+        assert_eq!(block_on(src.offset()), 11);
+        assert_eq!(block_on(dst.offset()), 5);
+
+        let mut buf = [0; 8];
+        block_on(dst.seek(SeekFrom::Start(0))).unwrap();
+        assert_eq!(block_on(dst.read(&mut buf)).unwrap(), 5);
+        assert_eq!(&buf[..5], b"world");
+    }
+
+    #[test]
+    fn sendfile_from_an_explicit_offset_leaves_the_offset_of_the_file() {
+        let (src, dst) = open_two(b"hello world");
+        let mut offset = 2;
+        assert_eq!(
+            block_on(sendfile(&dst, &src, Some(&mut offset), 3)).unwrap(),
+            3
+        );
+        assert_eq!(offset, 5);
+        // Still at the end of the write of `data`.
+        assert_eq!(block_on(src.offset()), 11);
+
+        let mut buf = [0; 8];
+        block_on(dst.seek(SeekFrom::Start(0))).unwrap();
+        assert_eq!(block_on(dst.read(&mut buf)).unwrap(), 3);
+        assert_eq!(&buf[..3], b"llo");
+    }
+
+    #[test]
+    fn a_duplicate_shares_the_offset_a_separate_open_does_not() {
+        let (file, _) = open_two(b"");
+        let dup = file.dup(false);
+        let other = Descriptor::<TestIrq>::new(file.inode().clone(), OpenOptions::READ, false);
+
+        assert_eq!(block_on(dup.write(b"abcd")).unwrap(), 4);
+        assert_eq!(block_on(file.offset()), 4);
+        assert_eq!(block_on(file.seek(SeekFrom::Start(1))).unwrap(), 1);
+        let mut buf = [0; 2];
+        assert_eq!(block_on(dup.read(&mut buf)).unwrap(), 2);
+        assert_eq!(&buf, b"bc");
+        assert_eq!(block_on(file.offset()), 3);
+
+        assert_eq!(block_on(other.offset()), 0);
+        assert_eq!(block_on(other.read(&mut buf)).unwrap(), 2);
+        assert_eq!(&buf, b"ab");
+        assert_eq!(block_on(dup.offset()), 3);
+    }
+
+    // A regular file and a directory of a RAM filesystem.
+    fn file_and_dir() -> (Arc<dyn DynInode>, Arc<dyn DynInode>) {
+        let (vfs, root) = ram_vfs();
+        let file = block_on(create(&vfs, &root, "file", Mode::TY_REG));
+        let dir = block_on(create(&vfs, &root, "dir", Mode::TY_DIR));
+        (Arc::new(file), Arc::new(dir))
+    }
+
+    fn open(inode: &Arc<dyn DynInode>, opts: OpenOptions) -> Result<Descriptor<TestIrq>> {
+        block_on(Descriptor::open(inode.clone(), opts, false))
+    }
+
+    #[test]
+    fn directory_opens_nothing_but_a_directory() {
+        let (file, dir) = file_and_dir();
+        assert!(matches!(
+            open(&file, OpenOptions::READ | OpenOptions::DIRECTORY),
+            Err(Error::NotDir)
+        ));
+        assert!(open(&file, OpenOptions::READ).is_ok());
+        let opened = open(&dir, OpenOptions::READ | OpenOptions::DIRECTORY).unwrap();
+        assert!(opened.readable() && !opened.is_path());
+    }
+
+    #[test]
+    fn a_path_descriptor_only_names_the_file() {
+        let (file, dir) = file_and_dir();
+        let all = OpenOptions::READ
+            | OpenOptions::WRITE
+            | OpenOptions::CREATE
+            | OpenOptions::TRUNC
+            | OpenOptions::APPEND
+            | OpenOptions::NONBLOCK;
+        let path = open(&file, OpenOptions::PATH | all).unwrap();
+        assert!(path.is_path());
+        assert_eq!(path.options(), OpenOptions::PATH);
+        assert!(!path.readable() && !path.writable() && !path.nonblocking());
+        assert!(matches!(block_on(path.write(b"a")), Err(Error::ReadOnly)));
+        // It can still be stat'ed.
+        let metadata = block_on(path.inode().metadata()).unwrap();
+        assert_eq!(metadata.mode.file_type(), Mode::TY_REG);
+
+        let path_dir = OpenOptions::PATH | OpenOptions::DIRECTORY;
+        assert!(matches!(open(&file, path_dir), Err(Error::NotDir)));
+        assert_eq!(open(&dir, path_dir | all).unwrap().options(), path_dir);
+    }
+
+    // The two ends of a socket pair opened with `opts`.
+    fn socket_pair(opts: OpenOptions) -> (Descriptor<TestIrq>, Descriptor<TestIrq>) {
+        let (end0, end1) = SocketEnd::<TestIrq>::pair();
+        let open = |end: SocketEnd<TestIrq>| {
+            let inode: Arc<dyn DynInode> = Arc::new(Arc::new(end) as Arc<dyn DevInode>);
+            Descriptor::new(inode, opts, false)
+        };
+        (open(end0), open(end1))
+    }
+
+    #[test]
+    fn a_nonblocking_read_fails_with_would_block_until_data_arrives() {
+        let (end0, end1) = socket_pair(OpenOptions::READ | OpenOptions::WRITE);
+        end0.set_status_flags(OpenOptions::NONBLOCK);
+        let mut buf = [0; 4];
+        assert!(matches!(
+            block_on(end0.read(&mut buf)),
+            Err(Error::WouldBlock)
+        ));
+
+        assert_eq!(block_on(end1.write(b"hi")).unwrap(), 2);
+        assert_eq!(block_on(end0.read(&mut buf)).unwrap(), 2);
+        assert_eq!(&buf[..2], b"hi");
+        assert!(matches!(
+            block_on(end0.read(&mut buf)),
+            Err(Error::WouldBlock)
+        ));
+    }
+
+    #[test]
+    fn status_flags_are_shared_by_duplicates_close_on_exec_is_not() {
+        let (file, _) = open_two(b"");
+        let mut dup = file.dup(false);
+        // Only APPEND and NONBLOCK change, the access mode is kept.
+        dup.set_status_flags(OpenOptions::NONBLOCK | OpenOptions::APPEND);
+        assert_eq!(
+            file.options(),
+            OpenOptions::READ | OpenOptions::WRITE | OpenOptions::NONBLOCK | OpenOptions::APPEND
+        );
+        file.set_status_flags(OpenOptions::READ);
+        assert!(!dup.nonblocking() && dup.writable());
+        assert!(!dup.options().contains(OpenOptions::APPEND));
+
+        dup.set_cloexec(true);
+        assert!(dup.cloexec() && !file.cloexec());
+        assert!(dup.clone().cloexec());
+    }
+
+    #[test]
+    fn fionread_counts_the_bytes_left_to_read() {
+        let (file, _) = open_two(b"hello");
+        let mut nread = -1;
+        let argp = &mut nread as *mut i32 as usize;
+        block_on(file.ioctl(ioctl::CMD_FIONREAD, argp)).unwrap();
+        assert_eq!(nread, 0);
+        block_on(file.seek(SeekFrom::Start(1))).unwrap();
+        block_on(file.ioctl(ioctl::CMD_FIONREAD, argp)).unwrap();
+        assert_eq!(nread, 4);
+
+        // A socket counts what its peer wrote.
+        let (end0, end1) = socket_pair(OpenOptions::READ | OpenOptions::WRITE);
+        assert_eq!(block_on(end1.write(b"abc")).unwrap(), 3);
+        block_on(end0.ioctl(ioctl::CMD_FIONREAD, argp)).unwrap();
+        assert_eq!(nread, 3);
+    }
+
+    #[test]
+    fn fionbio_switches_the_file_to_nonblocking_and_back() {
+        let (end0, _end1) = socket_pair(OpenOptions::READ | OpenOptions::WRITE);
+        let on = 1i32;
+        block_on(end0.ioctl(ioctl::CMD_FIONBIO, &on as *const i32 as usize)).unwrap();
+        assert!(end0.nonblocking());
+        let mut buf = [0; 1];
+        assert!(matches!(
+            block_on(end0.read(&mut buf)),
+            Err(Error::WouldBlock)
+        ));
+
+        let off = 0i32;
+        block_on(end0.ioctl(ioctl::CMD_FIONBIO, &off as *const i32 as usize)).unwrap();
+        assert!(!end0.nonblocking());
+        // Unknown commands reach the inode.
+        assert!(matches!(
+            block_on(end0.ioctl(ioctl::CMD_TCGETS, 0)),
+            Err(Error::Unsupport)
+        ));
+    }
+}
@@ -0,0 +1,46 @@
+//! The ioctl commands, see https://man7.org/linux/man-pages/man4/tty_ioctl.4.html
+
+/// Equivalent to tcgetattr(fd, argp).
+/// Get the current serial port settings.
+pub const CMD_TCGETS: u32 = 0x5401;
+
+/// Equivalent to tcsetattr(fd, TCSANOW, argp).
+/// Set the current serial port settings.
+pub const CMD_TCSETS: u32 = 0x5402;
+
+/// Equivalent to tcsetattr(fd, TCSADRAIN, argp).
+/// Allow the output buffer to drain, and set the current
+/// serial port settings.
+pub const CMD_TCSETSW: u32 = 0x5403;
+
+/// Equivalent to tcsetattr(fd, TCSAFLUSH, argp).
+/// Allow the output buffer to drain, discard pending input,
+/// and set the current serial port settings.
+pub const CMD_TCSETSF: u32 = 0x5404;
+
+/// When successful, equivalent to *argp = tcgetpgrp(fd).
+/// Get the process group ID of the foreground process group
+/// on this terminal.
+pub const CMD_TIOCGPGRP: u32 = 0x540F;
+
+/// Equivalent to tcsetpgrp(fd, *argp).
+/// Set the foreground process group ID of this terminal.
+pub const CMD_TIOCSPGRP: u32 = 0x5410;
+
+/// Get window size.
+pub const CMD_TIOCGWINSZ: u32 = 0x5413;
+
+// Generic commands, valid on any file descriptor and handled by `Descriptor::ioctl`
+// and `sys_ioctl` before the inode sees them, except `FIONREAD` on files that are not regular.
+
+/// Get the number of bytes available to read.
+pub const CMD_FIONREAD: u32 = 0x541B;
+
+/// Set (`*argp != 0`) or clear the non-blocking flag of the open file.
+pub const CMD_FIONBIO: u32 = 0x5421;
+
+/// Clear the close-on-exec flag of the descriptor.
+pub const CMD_FIONCLEX: u32 = 0x5450;
+
+/// Set the close-on-exec flag of the descriptor.
+pub const CMD_FIOCLEX: u32 = 0x5451;
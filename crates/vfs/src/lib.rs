@@ -0,0 +1,1296 @@
+//! The virtual filesystem: the traits filesystems implement, the path walk on top of them,
+//! open files, the in-memory, mount and process filesystems, and the sockets and terminals
+//! devices are built on.
+
+#![no_std]
+#![feature(generic_associated_types)]
+#![feature(map_try_insert)]
+
+#[cfg(test)]
+extern crate std;
+
+#[macro_use]
+extern crate alloc;
+#[macro_use]
+extern crate bitflags;
+
+#[allow(clippy::type_complexity)]
+pub mod cache_fs;
+pub mod dev_fs;
+pub mod file;
+pub mod fs_str;
+pub mod ioctl;
+#[cfg(test)]
+mod mock;
+#[allow(clippy::type_complexity)]
+pub mod mount_fs;
+pub mod mount_table;
+#[allow(clippy::type_complexity)]
+#[cfg(feature = "naive_fs")]
+pub mod naive_fs_vfs;
+pub mod path;
+pub mod proc_fs;
+pub mod ram_fs;
+pub mod socket;
+pub mod stat;
+pub mod tty;
+pub mod util;
+pub mod wait_queue;
+
+use core::{fmt, future::Future};
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+use futures_util::future::BoxFuture;
+use time::Timespec;
+
+use fs_str::DIR_ENTRY_NAME_CAP;
+pub use fs_str::{DirEntryName, FsStr, FsString};
+pub use path::{Path, PATH_CAP};
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+pub type InodeId = usize;
+
+/// Maximum number of symlinks followed while resolving a single path.
+pub const MAX_SYMLINK_FOLLOWS: usize = 40;
+
+#[derive(Debug)]
+pub enum Error {
+    NotDir,
+    NoRootDir,
+    NoSuchFileOrDirectory,
+    EntryExist,
+    NoSpace,
+    BlkErr(blk::Error),
+    Eof,
+    InvalidDirEntryName(Box<DirEntryName>),
+    WrongFS,
+    ReadOnly,
+    UnsupportedFs(String /* filesystem name */),
+    InvalidSeekOffset,
+    Unsupport,
+    NoSuchProcess(u32 /* pid */),
+    NotSymlink,
+    TooManySymlinks,
+    /// The filesystem is still in use.
+    Busy,
+    NotMountPoint,
+    /// The operation would block on a non-blocking file.
+    WouldBlock,
+    /// The operation was interrupted by a signal.
+    Interrupted,
+    /// The device does not exist, e.g. `/dev/tty` without a controlling terminal.
+    NoDevice,
+    /// The device refused the operation.
+    Io,
+    /// The caller is not allowed to perform the operation.
+    NotPermitted,
+    /// The other end of a pipe or socket is closed.
+    BrokenPipe,
+    /// A path or a name is longer than the filesystem supports.
+    NameTooLong,
+    /// The permissions of the file do not allow the access.
+    PermissionDenied,
+    /// A time is out of range.
+    InvalidTime,
+}
+
+/// Checks a name given for a new directory entry: it must be 1 to `DIR_ENTRY_NAME_CAP` bytes long,
+/// contain no '/' or NUL and not be "." or "..".
+/// This is the only check, filesystems rely on it for the names they get.
+pub fn check_dir_entry_name(name: &FsStr) -> Result<()> {
+    let bytes = name.as_bytes();
+    if bytes.len() > DIR_ENTRY_NAME_CAP {
+        return Err(Error::NameTooLong);
+    }
+    if bytes.is_empty()
+        || bytes.iter().any(|&c| c == b'/' || c == 0)
+        || bytes == b"."
+        || bytes == b".."
+    {
+        return Err(Error::InvalidDirEntryName(Box::new(bytes.into())));
+    }
+    Ok(())
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidDirEntryName(name) => {
+                write!(f, "invalid directory entry name: {}", name.display())
+            }
+            Self::UnsupportedFs(name) => write!(f, "unsupported filesystem: {}", name),
+            Self::NoSuchProcess(pid) => write!(f, "no such process: {}", pid),
+            _ => fmt::Debug::fmt(self, f),
+        }
+    }
+}
+
+pub struct Vfs<FS> {
+    inner: FS,
+}
+
+impl<FS: Filesystem> Vfs<FS> {
+    pub fn new(inner: FS) -> Self {
+        Self { inner }
+    }
+
+    /// Flush the root filesystem, filesystems mounted below it are not synced.
+    pub fn sync(&self) -> FS::SyncFut<'_> {
+        self.inner.sync()
+    }
+
+    pub async fn root(&self) -> DirEntry<FS> {
+        self.inner.root_dir_entry()
+    }
+
+    pub async fn create_parent_dentry(
+        &self,
+        parent_dir: &DirEntry<FS>,
+        filename: &FsStr,
+        mode: Mode,
+        uid: u32,
+        gid: u32,
+        create_time: Timespec,
+    ) -> Result<FS::Inode> {
+        let parent_dir = parent_dir
+            .as_dir()
+            .await?
+            .ok_or(Error::NoSuchFileOrDirectory)?;
+        self.create(&parent_dir, filename, mode, uid, gid, create_time)
+            .await
+    }
+
+    pub async fn create(
+        &self,
+        parent_dir: &FS::Inode,
+        filename: &FsStr,
+        mode: Mode,
+        uid: u32,
+        gid: u32,
+        create_time: Timespec,
+    ) -> Result<FS::Inode> {
+        check_dir_entry_name(filename)?;
+        if parent_dir.lookup(filename).await?.is_some() {
+            return Err(Error::EntryExist);
+        }
+
+        let new_inode = self.inner.create_inode(mode, uid, gid, create_time).await?;
+        parent_dir
+            .append(filename.into(), new_inode.id(), FileType::from_mode(mode))
+            .await?;
+
+        if mode.is_dir() {
+            new_inode.append_dot(parent_dir.id()).await?;
+        }
+        parent_dir.sync().await?;
+        new_inode.sync().await?;
+
+        Ok(new_inode)
+    }
+
+    /// Add a new directory entry `filename` in `parent_dir` pointing at `inode`,
+    /// which must not be a directory.
+    pub async fn link(
+        &self,
+        parent_dir: &FS::Inode,
+        filename: &FsStr,
+        inode: &FS::Inode,
+    ) -> Result<()> {
+        check_dir_entry_name(filename)?;
+        let mode = inode.metadata().await?.mode;
+        // A directory has a single parent.
+        if mode.is_dir() {
+            return Err(Error::NotPermitted);
+        }
+        if parent_dir.lookup(filename).await?.is_some() {
+            return Err(Error::EntryExist);
+        }
+        parent_dir
+            .append(filename.into(), inode.id(), FileType::from_mode(mode))
+            .await?;
+        inode.link().await?;
+        parent_dir.sync().await?;
+        inode.sync().await?;
+        Ok(())
+    }
+
+    /// Create the symlink `filename` in `parent_dir` pointing at `target`.
+    /// Symlinks are accessible by everyone. If the target cannot be written whole,
+    /// the link is removed again.
+    pub async fn symlink(
+        &self,
+        parent_dir: &FS::Inode,
+        filename: &FsStr,
+        target: &[u8],
+        uid: u32,
+        gid: u32,
+        create_time: Timespec,
+    ) -> Result<FS::Inode> {
+        let mode = Mode::TY_LNK | Mode::PERM_RWX_USR | Mode::PERM_RWX_GRP | Mode::PERM_RWX_OTH;
+        let link = self
+            .create(parent_dir, filename, mode, uid, gid, create_time)
+            .await?;
+        let written = async {
+            let mut written = 0;
+            while written < target.len() {
+                match link.write_at(written as u64, &target[written..]).await? {
+                    0 => return Err(Error::NoSpace),
+                    n => written += n,
+                }
+            }
+            link.sync().await
+        };
+        if let Err(e) = written.await {
+            // Unlinked through `link` itself, so that no other copy of the inode
+            // is left with the partial write. The error of the write is the one worth reporting.
+            let _ = async {
+                parent_dir.remove(filename).await?;
+                parent_dir.sync().await?;
+                link.unlink().await?;
+                link.sync().await
+            }
+            .await;
+            return Err(e);
+        }
+        Ok(link)
+    }
+
+    /// Remove the directory entry `filename` from `parent_dir` and drop the link it held.
+    /// The inode is freed by its filesystem once its last link is gone.
+    pub async fn unlink(&self, parent_dir: &FS::Inode, filename: &FsStr) -> Result<()> {
+        let entry = parent_dir
+            .remove(filename)
+            .await?
+            .ok_or(Error::NoSuchFileOrDirectory)?;
+        parent_dir.sync().await?;
+        if let Some(inode) = self.inner.load_inode(entry.inode_id).await? {
+            inode.unlink().await?;
+            inode.sync().await?;
+        }
+        Ok(())
+    }
+
+    /// Resolve `path` starting at `parent_dir`, following symlinks, including
+    /// one in the last component.
+    pub async fn find<'a>(
+        &'a self,
+        parent_dir: &FS::Inode,
+        path: &'a Path,
+    ) -> Result<Option<DirEntry<FS>>> {
+        self.find_inner(parent_dir, path, true).await
+    }
+
+    /// Like `find`, but a symlink in the last component is returned as is.
+    pub async fn find_nofollow<'a>(
+        &'a self,
+        parent_dir: &FS::Inode,
+        path: &'a Path,
+    ) -> Result<Option<DirEntry<FS>>> {
+        self.find_inner(parent_dir, path, false).await
+    }
+
+    /// Resolve `path` starting at `parent_dir`.
+    /// "." and ".." are collapsed while walking, so the result does not depend on
+    /// how the underlying filesystem stores dot entries. ".." at the root stays at the root.
+    /// A symlink is resolved by splicing its target in front of the remaining components.
+    async fn find_inner<'a>(
+        &'a self,
+        parent_dir: &FS::Inode,
+        path: &'a Path,
+        follow_last: bool,
+    ) -> Result<Option<DirEntry<FS>>> {
+        // Holds the rewritten path once a symlink has been followed
+        let mut resolved: Vec<u8>;
+        let mut base_inode: FS::Inode;
+        // `None` means the walk starts at `parent_dir`
+        let mut base_dentry: Option<DirEntry<FS>> = None;
+        let mut base = if path.is_absolute() {
+            let root = self.root().await;
+            base_inode = root.as_dir().await?.ok_or(Error::NoSuchFileOrDirectory)?;
+            base_dentry = Some(root);
+            &base_inode
+        } else {
+            parent_dir
+        };
+
+        // Directories entered below `base`, popped again by ".."
+        let mut dirs: Vec<(DirEntry<FS>, FS::Inode)> = Vec::new();
+        let mut walked = false;
+        let mut follows = 0;
+        let mut path = path;
+
+        while let (rest_path, Some(name)) = path.shift() {
+            path = rest_path;
+            walked = true;
+            match name.as_bytes() {
+                b"." => {}
+                b".." => {
+                    if dirs.pop().is_none() && !self.is_root(base) {
+                        // Walk above the starting directory through its ".." entry.
+                        if let Some(parent) = base.lookup(FsStr::from_bytes(b"..")).await? {
+                            base_inode =
+                                parent.as_dir().await?.ok_or(Error::NoSuchFileOrDirectory)?;
+                            base_dentry = Some(parent);
+                            base = &base_inode;
+                        }
+                    }
+                }
+                _ => {
+                    let current_dir = dirs.last().map(|(_, inode)| inode).unwrap_or(base);
+                    let entry = match current_dir.lookup(name).await? {
+                        None => return Ok(None),
+                        Some(entry) => entry,
+                    };
+                    let is_last = path.shift().1.is_none();
+                    if matches!(entry.raw.file_type, Some(FileType::Symlink))
+                        && (follow_last || !is_last)
+                    {
+                        follows += 1;
+                        if follows > MAX_SYMLINK_FOLLOWS {
+                            return Err(Error::TooManySymlinks);
+                        }
+                        let link = entry.inode().await?.ok_or(Error::NoSuchFileOrDirectory)?;
+                        let mut target = self.read_link(&link).await?;
+                        if target.is_empty() {
+                            return Ok(None);
+                        }
+                        if !is_last {
+                            target.push(b'/');
+                            target.extend_from_slice(path.inner().as_bytes());
+                        }
+                        if Path::from_bytes(&target).is_absolute() {
+                            let root = self.root().await;
+                            base_inode =
+                                root.as_dir().await?.ok_or(Error::NoSuchFileOrDirectory)?;
+                            base_dentry = Some(root);
+                            base = &base_inode;
+                            dirs.clear();
+                        }
+                        resolved = target;
+                        path = Path::from_bytes(&resolved);
+                        continue;
+                    }
+                    if is_last {
+                        return Ok(Some(entry));
+                    }
+                    match entry.as_dir().await? {
+                        Some(inode) => dirs.push((entry, inode)),
+                        None => return Ok(None),
+                    }
+                }
+            }
+        }
+
+        if !walked {
+            return Ok(None);
+        }
+
+        // The last component was "." or ".."
+        match dirs.pop() {
+            Some((dentry, _)) => Ok(Some(dentry)),
+            None => match base_dentry {
+                Some(dentry) => Ok(Some(dentry)),
+                None => base.lookup(FsStr::from_bytes(b".")).await,
+            },
+        }
+    }
+
+    /// Read the target path stored in a symlink inode.
+    pub async fn read_link(&self, link: &FS::Inode) -> Result<Vec<u8>> {
+        let metadata = link.metadata().await?;
+        if !metadata.is_symlink() {
+            return Err(Error::NotSymlink);
+        }
+        let mut target = vec![0; metadata.size as usize];
+        let mut read = 0;
+        while read < target.len() {
+            match link.read_at(read as u64, &mut target[read..]).await? {
+                0 => break,
+                n => read += n,
+            }
+        }
+        target.truncate(read);
+        Ok(target)
+    }
+
+    /// Copy the target of the symlink `link` into `buf`, truncated to the length of `buf`
+    /// like readlink(2). Returns the number of bytes copied.
+    pub async fn read_link_into(&self, link: &FS::Inode, buf: &mut [u8]) -> Result<usize> {
+        let target = self.read_link(link).await?;
+        let len = target.len().min(buf.len());
+        buf[..len].copy_from_slice(&target[..len]);
+        Ok(len)
+    }
+
+    fn is_root(&self, dir: &FS::Inode) -> bool {
+        dir.id() == self.inner.root_dir_entry_raw().inode_id
+    }
+
+    pub async fn find_parent_dentry<'a>(
+        &'a self,
+        parent_dir: &DirEntry<FS>,
+        path: &'a Path,
+    ) -> Result<Option<DirEntry<FS>>> {
+        let parent_dir = parent_dir
+            .as_dir()
+            .await?
+            .ok_or(Error::NoSuchFileOrDirectory)?;
+        self.find(&parent_dir, path).await
+    }
+
+    pub async fn mv(
+        &self,
+        src_parent_dir: &DirEntry<FS>,
+        src_name: &FsStr,
+        target_parent_dir: &DirEntry<FS>,
+        target_name: &FsStr,
+    ) -> Result<()> {
+        check_dir_entry_name(target_name)?;
+        let src_dentry = src_parent_dir
+            .as_dir()
+            .await?
+            .ok_or(Error::NoSuchFileOrDirectory)?
+            .remove(src_name)
+            .await?
+            .ok_or(Error::NoSuchFileOrDirectory)?;
+        target_parent_dir
+            .as_dir()
+            .await?
+            .ok_or(Error::NoSuchFileOrDirectory)?
+            .append(
+                target_name.to_dir_entry_name(),
+                src_dentry.inode_id,
+                src_dentry.file_type,
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct RawDirEntry {
+    pub inode_id: InodeId,
+    pub name: Box<DirEntryName>,
+    pub file_type: Option<FileType>,
+}
+
+impl RawDirEntry {
+    pub fn name(&self) -> &FsStr {
+        (*self.name).as_ref()
+    }
+}
+
+pub struct DirEntry<FS: ?Sized> {
+    pub raw: RawDirEntry,
+    pub fs: FS,
+}
+
+impl<FS: Filesystem> DirEntry<FS> {
+    pub async fn inode(&self) -> Result<Option<FS::Inode>> {
+        self.fs.load_inode(self.raw.inode_id).await
+    }
+
+    pub async fn as_dir(&self) -> Result<Option<FS::Inode>> {
+        match self.raw.file_type {
+            Some(FileType::Dir) | None => self.inode().await,
+            _ => Err(Error::NotDir),
+        }
+    }
+}
+
+impl<FS: Filesystem + Clone> Clone for DirEntry<FS> {
+    fn clone(&self) -> Self {
+        Self {
+            raw: self.raw.clone(),
+            fs: self.fs.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum FileType {
+    /// Regular File
+    RegFile = 1,
+    /// Directory File
+    Dir = 2,
+    /// Character Device
+    ChrDev = 3,
+    /// Block Device
+    BlkDev = 4,
+    /// Buffer File
+    Fifo = 5,
+    /// Socket File
+    Sock = 6,
+    /// Symbolic Link
+    Symlink = 7,
+}
+
+impl FileType {
+    #[allow(dead_code)]
+    fn from_mode(mode: Mode) -> Option<Self> {
+        Some(match mode.file_type() {
+            Mode::TY_REG => Self::RegFile,
+            Mode::TY_DIR => Self::Dir,
+            Mode::TY_CHR => Self::ChrDev,
+            Mode::TY_BLK => Self::BlkDev,
+            Mode::TY_FIFO => Self::Fifo,
+            Mode::TY_SOCK => Self::Sock,
+            Mode::TY_LNK => Self::Symlink,
+            _ => return None,
+        })
+    }
+}
+
+bitflags! {
+    #[derive(Default)]
+    pub struct Mode: u16 {
+        // File type
+        /// Socket File
+        const TY_SOCK = 0xC000;
+        /// Symbolic Link
+        const TY_LNK = 0xA000;
+        /// Regular File
+        const TY_REG = 0x8000;
+        /// Block Device
+        const TY_BLK = 0x6000;
+        /// Directory File
+        const TY_DIR = 0x4000;
+        /// Character Device
+        const TY_CHR = 0x2000;
+        /// FIFO
+        const TY_FIFO = 0x1000;
+
+        /// This bit is 1. The user id of the file needs to be used to override the user id of the process.
+        const S_UID = 0x0800;
+        ///  This bit is 1 The group id of the file to be used overrides the group id of the process
+        const S_SGID = 0x0400;
+        /// Sticky bit
+        /// https://zh.wikipedia.org/wiki/%E7%B2%98%E6%BB%9E%E4%BD%8D
+        const S_VTX = 0x0200;
+
+        // File access permissions
+        /// Readable by the file owner
+        const PERM_R_USR = 0x0100;
+        /// File owner writable
+        const PERM_W_USR = 0x0080;
+        /// File owners executable
+        const PERM_X_USR = 0x0040;
+        /// Readable and writable by the file owner
+        const PERM_RW_USR = Self::PERM_R_USR.bits | Self::PERM_W_USR.bits;
+        /// Readable and executable by the file owner
+        const PERM_RX_USR = Self::PERM_R_USR.bits | Self::PERM_X_USR.bits;
+        /// Readable, writable and executable by the file owner
+        const PERM_RWX_USR = Self::PERM_RW_USR.bits | Self::PERM_X_USR.bits;
+
+        /// Same group readable
+        const PERM_R_GRP = 0x0020;
+        /// Same group writable
+        const PERM_W_GRP = 0x0010;
+        /// Same group executable
+        const PERM_X_GRP = 0x0008;
+        /// Same group readable and writable
+        const PERM_RW_GRP = Self::PERM_R_GRP.bits | Self::PERM_W_GRP.bits;
+        /// Same group readable and executable
+        const PERM_RX_GRP = Self::PERM_R_GRP.bits | Self::PERM_X_GRP.bits;
+        /// Same group readable, writable and executable
+        const PERM_RWX_GRP = Self::PERM_RW_GRP.bits | Self::PERM_X_GRP.bits;
+
+        /// Others readable
+        const PERM_R_OTH = 0x0004;
+        /// Others writable
+        const PERM_W_OTH = 0x0002;
+        /// Others executable
+        const PERM_X_OTH = 0x0001;
+        /// Others readable and writable
+        const PERM_RW_OTH = Self::PERM_R_OTH.bits | Self::PERM_W_OTH.bits;
+        /// Others readable and executable
+        const PERM_RX_OTH = Self::PERM_R_OTH.bits | Self::PERM_X_OTH.bits;
+        /// Others readable, writable and executable
+        const PERM_RWX_OTH = Self::PERM_RW_OTH.bits | Self::PERM_X_OTH.bits;
+
+    }
+}
+
+impl Mode {
+    /// File type bits are an enumeration rather than independent flags
+    /// (e.g. `TY_LNK` shares bits with `TY_REG`), so compare them as a whole.
+    const TY_MASK: u16 = 0xF000;
+
+    pub fn file_type(&self) -> Mode {
+        Mode::from_bits_truncate(self.bits & Self::TY_MASK)
+    }
+
+    /// Replace the permission bits (including setuid, setgid and sticky), keeping the file type.
+    pub fn with_permissions(&self, perm: Mode) -> Mode {
+        Mode::from_bits_truncate(self.file_type().bits | (perm.bits & !Self::TY_MASK))
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type() == Mode::TY_DIR
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.file_type() == Mode::TY_REG
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.file_type() == Mode::TY_LNK
+    }
+}
+
+/// The permission bits a process never grants to the files it creates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Umask(Mode);
+
+impl Umask {
+    /// Only the user, group and other permission bits of `mask` are kept.
+    pub const fn new(mask: Mode) -> Self {
+        Self(Mode::from_bits_truncate(
+            mask.bits()
+                & (Mode::PERM_RWX_USR.bits()
+                    | Mode::PERM_RWX_GRP.bits()
+                    | Mode::PERM_RWX_OTH.bits()),
+        ))
+    }
+
+    /// The mode a file asked to be created with `mode` gets.
+    pub fn apply(&self, mode: Mode) -> Mode {
+        mode - self.0
+    }
+
+    /// Replaces the mask, as `umask` does, returns the previous one.
+    pub fn replace(&mut self, mask: Mode) -> Umask {
+        core::mem::replace(self, Self::new(mask))
+    }
+
+    pub fn bits(&self) -> u16 {
+        self.0.bits()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Metadata {
+    pub mode: Mode,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+    /// the number of seconds since january 1st 1970 of the last time this inode was accessed.
+    pub atime: Timespec,
+    /// the number of seconds since january 1st 1970, of when the inode was created.
+    pub ctime: Timespec,
+    /// the number of seconds since january 1st 1970, of the last time this inode was modified.
+    pub mtime: Timespec,
+    /// how many times this particular inode is linked (referred to).
+    pub links_count: u16,
+    pub blk_size: u32,
+    pub blk_count: usize,
+}
+
+#[allow(dead_code)]
+impl Metadata {
+    fn is_dir(&self) -> bool {
+        self.mode.is_dir()
+    }
+
+    fn is_file(&self) -> bool {
+        self.mode.is_file()
+    }
+
+    fn is_symlink(&self) -> bool {
+        self.mode.is_symlink()
+    }
+
+    fn owner(&self, uid: u32) -> bool {
+        self.uid == uid
+    }
+
+    fn in_group(&self, gid: u32) -> bool {
+        self.gid == gid
+    }
+
+    pub fn permission(&self, uid: u32, gid: u32, p: Permission) -> bool {
+        let mode = self.mode.bits;
+        let mut perm = (mode & 0o7) as u8;
+        if self.owner(uid) {
+            perm |= (mode >> 6 & 0o7) as u8;
+        }
+        if self.in_group(gid) {
+            perm |= (mode >> 3 & 0o7) as u8;
+        }
+        perm & p.bits == p.bits
+    }
+
+    /// Whether `uid` of group `gid` may access the inode as `p`. Root may read and write
+    /// anything, but only execute a directory or what is executable by someone.
+    pub fn check_access(&self, uid: u32, gid: u32, p: Permission) -> Result<()> {
+        let allowed = if uid == 0 {
+            !p.contains(Permission::EXEC)
+                || self.is_dir()
+                || self
+                    .mode
+                    .intersects(Mode::PERM_X_USR | Mode::PERM_X_GRP | Mode::PERM_X_OTH)
+        } else {
+            self.permission(uid, gid, p)
+        };
+        if allowed {
+            Ok(())
+        } else {
+            Err(Error::PermissionDenied)
+        }
+    }
+
+    /// Whether `caller_uid` of group `caller_gid` may give the inode to `uid` and `gid`.
+    /// Only root may change the owner, the owner may change the group to its own group.
+    pub fn check_chown(&self, caller_uid: u32, caller_gid: u32, uid: u32, gid: u32) -> Result<()> {
+        let own_group = gid == self.gid || gid == caller_gid;
+        if caller_uid == 0 || (self.owner(caller_uid) && uid == self.uid && own_group) {
+            Ok(())
+        } else {
+            Err(Error::NotPermitted)
+        }
+    }
+
+    /// Whether `uid` of group `gid` may change the times of the inode. Only the owner and
+    /// root may set `explicit` times, others may set the current time if they may write.
+    pub fn check_set_times(&self, uid: u32, gid: u32, explicit: bool) -> Result<()> {
+        if uid == 0 || self.owner(uid) {
+            Ok(())
+        } else if explicit {
+            Err(Error::NotPermitted)
+        } else if self.permission(uid, gid, Permission::WRITE) {
+            Ok(())
+        } else {
+            Err(Error::PermissionDenied)
+        }
+    }
+}
+
+bitflags! {
+    pub struct Permission: u8 {
+        const READ = 0x4;
+        const WRITE = 0x2;
+        const EXEC = 0x1;
+
+        const READ_WRITE = Self::READ.bits | Self::WRITE.bits;
+    }
+}
+
+bitflags! {
+    /// Events of a file that `Inode::poll` waits for.
+    pub struct PollEvents: u16 {
+        /// There is data to read.
+        const IN = 0x001;
+        /// There is urgent data to read.
+        const PRI = 0x002;
+        /// Writing is now possible.
+        const OUT = 0x004;
+        /// Error condition.
+        const ERR = 0x008;
+        /// Hang up.
+        const HUP = 0x010;
+        /// Invalid request: fd not open.
+        const NVAL = 0x020;
+    }
+}
+
+impl PollEvents {
+    /// Readiness of files that never block, like regular files and directories.
+    pub fn never_block(self) -> Self {
+        self & (Self::IN | Self::OUT)
+    }
+}
+
+pub trait Filesystem: Send + Sync {
+    type Inode: Inode<FS = Self>;
+
+    type CreateInodeFut<'a>: Future<Output = Result<Self::Inode>> + Send + 'a
+    where
+        Self: 'a;
+    type LoadInodeFut<'a>: Future<Output = Result<Option<Self::Inode>>> + Send + 'a
+    where
+        Self: 'a;
+    type SyncFut<'a>: Future<Output = Result<()>> + Send + 'a
+    where
+        Self: 'a;
+
+    fn root_dir_entry_raw(&self) -> RawDirEntry;
+
+    fn root_dir_entry(&self) -> DirEntry<Self>;
+
+    fn create_inode(
+        &self,
+        mode: Mode,
+        uid: u32,
+        gid: u32,
+        create_time: Timespec,
+    ) -> Self::CreateInodeFut<'_>;
+
+    fn load_inode(&self, inode_id: InodeId) -> Self::LoadInodeFut<'_>;
+
+    /// Write the filesystem's metadata back and flush the underlying device.
+    fn sync(&self) -> Self::SyncFut<'_>;
+
+    /// Get the BlkDevice's block_size.
+    fn blk_size(&self) -> u32;
+
+    /// Get the BlkDevice's block count.
+    fn blk_count(&self) -> usize;
+
+    /// Name of the filesystem type, e.g. "ramfs".
+    fn name(&self) -> &'static str;
+}
+
+pub trait Inode: Send + Sync {
+    type FS: Filesystem<Inode = Self>;
+    type MetadataFut<'a>: Future<Output = Result<Metadata>> + Send + 'a
+    where
+        Self: 'a;
+    type ChownFut<'a>: Future<Output = Result<()>> + Send + 'a
+    where
+        Self: 'a;
+    type ChmodFut<'a>: Future<Output = Result<()>> + Send + 'a
+    where
+        Self: 'a;
+    type SetTimesFut<'a>: Future<Output = Result<()>> + Send + 'a
+    where
+        Self: 'a;
+    type LinkFut<'a>: Future<Output = Result<()>> + Send + 'a
+    where
+        Self: 'a;
+    type UnlinkFut<'a>: Future<Output = Result<()>> + Send + 'a
+    where
+        Self: 'a;
+    type ReadAtFut<'a>: Future<Output = Result<usize>> + Send + 'a
+    where
+        Self: 'a;
+    type WriteAtFut<'a>: Future<Output = Result<usize>> + Send + 'a
+    where
+        Self: 'a;
+    type SyncFut<'a>: Future<Output = Result<()>> + Send + 'a
+    where
+        Self: 'a;
+    type AppendDotFut<'a>: Future<Output = Result<()>> + Send + 'a
+    where
+        Self: 'a;
+    type LookupRawFut<'a>: Future<Output = Result<Option<RawDirEntry>>> + Send + 'a
+    where
+        Self: 'a;
+    type LookupFut<'a>: Future<Output = Result<Option<DirEntry<Self::FS>>>> + Send + 'a
+    where
+        Self: 'a;
+    type AppendFut<'a>: Future<Output = Result<()>> + Send + 'a
+    where
+        Self: 'a;
+    type RemoveFut<'a>: Future<Output = Result<Option<RawDirEntry>>> + Send + 'a
+    where
+        Self: 'a;
+    type LsRawFut<'a>: Future<Output = Result<Vec<RawDirEntry>>> + Send + 'a
+    where
+        Self: 'a;
+    type LsFut<'a>: Future<Output = Result<Vec<DirEntry<Self::FS>>>> + Send + 'a
+    where
+        Self: 'a;
+    type IOCtlFut<'a>: Future<Output = Result<()>> + Send + 'a
+    where
+        Self: 'a;
+    type PollFut<'a>: Future<Output = PollEvents> + Send + 'a
+    where
+        Self: 'a;
+    type GetXattrFut<'a>: Future<Output = Result<Option<Vec<u8>>>> + Send + 'a
+    where
+        Self: 'a;
+    type SetXattrFut<'a>: Future<Output = Result<()>> + Send + 'a
+    where
+        Self: 'a;
+    type ListXattrFut<'a>: Future<Output = Result<Vec<Vec<u8>>>> + Send + 'a
+    where
+        Self: 'a;
+    type RemoveXattrFut<'a>: Future<Output = Result<Option<Vec<u8>>>> + Send + 'a
+    where
+        Self: 'a;
+
+    fn id(&self) -> InodeId;
+
+    fn metadata(&self) -> Self::MetadataFut<'_>;
+
+    fn chown(&self, uid: u32, gid: u32) -> Self::ChownFut<'_>;
+
+    fn chmod(&self, mode: Mode) -> Self::ChmodFut<'_>;
+
+    /// Set the access, modification and status change times, `None` leaves a time unchanged.
+    fn set_times(
+        &self,
+        atime: Option<Timespec>,
+        mtime: Option<Timespec>,
+        ctime: Option<Timespec>,
+    ) -> Self::SetTimesFut<'_>;
+
+    fn link(&self) -> Self::LinkFut<'_>;
+
+    fn unlink(&self) -> Self::UnlinkFut<'_>;
+
+    fn read_at<'a>(&'a self, offset: u64, buf: &'a mut [u8]) -> Self::ReadAtFut<'a>;
+
+    fn write_at<'a>(&'a self, offset: u64, src: &'a [u8]) -> Self::WriteAtFut<'a>;
+
+    /// Fill `bufs` one after another, reading from `offset`, returns the total bytes read.
+    /// Stops at the first short read. Filesystems that can do it in one pass should override this.
+    fn read_vectored<'a, 'b>(
+        &'a self,
+        offset: u64,
+        bufs: &'a mut [&'b mut [u8]],
+    ) -> BoxFuture<'a, Result<usize>> {
+        Box::pin(async move {
+            let mut read_size = 0;
+            for buf in bufs.iter_mut() {
+                let len = self.read_at(offset + read_size as u64, buf).await?;
+                read_size += len;
+                if len < buf.len() {
+                    break;
+                }
+            }
+            Ok(read_size)
+        })
+    }
+
+    /// Write `srcs` one after another, starting at `offset`, returns the total bytes written.
+    /// Stops at the first short write. Filesystems that can do it in one pass should override this.
+    fn write_vectored<'a>(
+        &'a self,
+        offset: u64,
+        srcs: &'a [&'a [u8]],
+    ) -> BoxFuture<'a, Result<usize>> {
+        Box::pin(async move {
+            let mut written_size = 0;
+            for src in srcs {
+                let len = self.write_at(offset + written_size as u64, src).await?;
+                written_size += len;
+                if len < src.len() {
+                    break;
+                }
+            }
+            Ok(written_size)
+        })
+    }
+
+    fn sync(&self) -> Self::SyncFut<'_>;
+
+    /// Append ".", ".." into this directory.
+    fn append_dot(&self, parent_inode_id: InodeId) -> Self::AppendDotFut<'_>;
+
+    fn lookup_raw<'a>(&'a self, name: &'a FsStr) -> Self::LookupRawFut<'a>;
+
+    fn lookup<'a>(&'a self, name: &'a FsStr) -> Self::LookupFut<'a>;
+
+    fn append(
+        &self,
+        dir_entry_name: DirEntryName,
+        inode_id: InodeId,
+        file_type: Option<FileType>,
+    ) -> Self::AppendFut<'_>;
+
+    fn remove<'a>(&'a self, dir_entry_name: &'a FsStr) -> Self::RemoveFut<'a>;
+
+    fn ls_raw(&self) -> Self::LsRawFut<'_>;
+
+    /// List all dir entries in the current directory
+    fn ls(&self) -> Self::LsFut<'_>;
+
+    /// Call filesystem specific ioctl methods
+    fn ioctl(&self, cmd: u32, arg: usize) -> Self::IOCtlFut<'_>;
+
+    /// Wait until any of `events` is ready, returns the ready subset of `events`.
+    fn poll(&self, events: PollEvents) -> Self::PollFut<'_>;
+
+    /// Returns the value of the extended attribute `name`, `None` if it is not set.
+    fn getxattr<'a>(&'a self, name: &'a [u8]) -> Self::GetXattrFut<'a>;
+
+    /// Create or replace the extended attribute `name`.
+    fn setxattr<'a>(&'a self, name: &'a [u8], value: &'a [u8]) -> Self::SetXattrFut<'a>;
+
+    /// Returns the names of all extended attributes.
+    fn listxattr(&self) -> Self::ListXattrFut<'_>;
+
+    /// Remove the extended attribute `name`, returns its old value.
+    fn removexattr<'a>(&'a self, name: &'a [u8]) -> Self::RemoveXattrFut<'a>;
+}
+
+#[cfg(test)]
+mod test {
+    use tokio_test::block_on;
+
+    #[cfg(feature = "naive_fs")]
+    use crate::mock::naive_vfs;
+    use crate::{
+        mock::{create, ram_vfs, TestFs, TestInode},
+        Error, Filesystem, FsStr, Inode, InodeId, Mode, Path, Permission, Umask, Vfs,
+    };
+
+    fn find(vfs: &Vfs<TestFs>, start: &TestInode, path: &str) -> Option<InodeId> {
+        block_on(vfs.find(start, Path::from_bytes(path.as_bytes())))
+            .unwrap()
+            .map(|entry| entry.raw.inode_id)
+    }
+
+    #[test]
+    fn the_umask_clears_permissions_of_new_files() {
+        let mut umask = Umask::new(Mode::from_bits_truncate(0o022));
+        let mode = umask.apply(Mode::TY_REG | Mode::from_bits_truncate(0o666));
+        assert_eq!(mode, Mode::TY_REG | Mode::from_bits_truncate(0o644));
+
+        // Only the permission bits are kept, the previous mask is returned.
+        let old = umask.replace(Mode::from_bits_truncate(0o4077));
+        assert_eq!(old.bits(), 0o022);
+        assert_eq!(umask.bits(), 0o077);
+        assert_eq!(
+            umask.apply(Mode::from_bits_truncate(0o777)),
+            Mode::from_bits_truncate(0o700)
+        );
+    }
+
+    #[test]
+    fn find_collapses_dot_and_dot_dot() {
+        let (vfs, root) = ram_vfs();
+        let a = block_on(create(&vfs, &root, "a", Mode::TY_DIR));
+        let b = block_on(create(&vfs, &a, "b", Mode::TY_DIR));
+        let f = block_on(create(&vfs, &b, "f", Mode::TY_REG));
+
+        assert_eq!(find(&vfs, &root, "/a/./b/."), Some(b.id()));
+        assert_eq!(find(&vfs, &root, "./a/././b/./f"), Some(f.id()));
+        assert_eq!(find(&vfs, &root, "a/b/../../a/b/f"), Some(f.id()));
+        assert_eq!(find(&vfs, &root, "/a/b/../.."), Some(root.id()));
+        assert_eq!(find(&vfs, &b, "../.."), Some(root.id()));
+        assert_eq!(find(&vfs, &b, "../b/f"), Some(f.id()));
+        // ".." does not skip a directory that does not exist.
+        assert_eq!(find(&vfs, &root, "/a/missing/.."), None);
+        // A regular file is not a directory to walk through.
+        assert!(matches!(
+            block_on(vfs.find(&root, Path::from_bytes(b"/a/b/f/.."))),
+            Err(Error::NotDir)
+        ));
+    }
+
+    #[test]
+    fn find_clamps_dot_dot_at_the_root() {
+        let (vfs, root) = ram_vfs();
+        let a = block_on(create(&vfs, &root, "a", Mode::TY_DIR));
+
+        assert_eq!(find(&vfs, &root, "/.."), Some(root.id()));
+        assert_eq!(find(&vfs, &root, "/../../a"), Some(a.id()));
+        assert_eq!(find(&vfs, &root, "a/../../.."), Some(root.id()));
+        assert_eq!(find(&vfs, &a, "../../../a"), Some(a.id()));
+        assert_eq!(find(&vfs, &root, ".."), Some(root.id()));
+    }
+
+    #[test]
+    fn find_nofollow_stops_at_a_symlink_in_the_last_component() {
+        let (vfs, root) = ram_vfs();
+        let dir = block_on(create(&vfs, &root, "dir", Mode::TY_DIR));
+        let f = block_on(create(&vfs, &dir, "f", Mode::TY_REG));
+        let link = block_on(create(&vfs, &root, "link", Mode::TY_LNK));
+        block_on(link.write_at(0, b"dir/f")).unwrap();
+        let dir_link = block_on(create(&vfs, &root, "dir_link", Mode::TY_LNK));
+        block_on(dir_link.write_at(0, b"/dir")).unwrap();
+
+        // stat follows the link, lstat does not.
+        assert_eq!(find(&vfs, &root, "link"), Some(f.id()));
+        let entry = block_on(vfs.find_nofollow(&root, Path::from_bytes(b"link")))
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.raw.inode_id, link.id());
+        let inode = block_on(entry.inode()).unwrap().unwrap();
+        assert!(block_on(inode.metadata()).unwrap().is_symlink());
+
+        // Links before the last component are followed either way.
+        let nofollow = |path: &str| {
+            block_on(vfs.find_nofollow(&root, Path::from_bytes(path.as_bytes())))
+                .unwrap()
+                .map(|entry| entry.raw.inode_id)
+        };
+        assert_eq!(nofollow("dir_link/f"), Some(f.id()));
+        assert_eq!(nofollow("dir_link"), Some(dir_link.id()));
+        assert_eq!(find(&vfs, &root, "dir_link"), Some(dir.id()));
+    }
+
+    #[test]
+    fn symlink_round_trips_its_target() {
+        let (vfs, root) = ram_vfs();
+        let link = block_on(vfs.symlink(
+            &root,
+            FsStr::from_bytes(b"link"),
+            b"some/target",
+            1,
+            2,
+            Default::default(),
+        ))
+        .unwrap();
+        let metadata = block_on(link.metadata()).unwrap();
+        assert!(metadata.is_symlink());
+        assert_eq!(metadata.mode.bits() & 0o777, 0o777);
+        assert_eq!((metadata.uid, metadata.gid), (1, 2));
+        assert_eq!(block_on(vfs.read_link(&link)).unwrap(), b"some/target");
+
+        let mut buf = [0; 16];
+        assert_eq!(block_on(vfs.read_link_into(&link, &mut buf)).unwrap(), 11);
+        assert_eq!(&buf[..11], b"some/target");
+        // A short buffer gets the start of the target, not NUL terminated.
+        let mut buf = [b'x'; 6];
+        assert_eq!(
+            block_on(vfs.read_link_into(&link, &mut buf[..4])).unwrap(),
+            4
+        );
+        assert_eq!(&buf, b"somexx");
+
+        assert!(matches!(
+            block_on(vfs.read_link_into(&root, &mut buf)),
+            Err(Error::NotSymlink)
+        ));
+    }
+
+    #[test]
+    fn access_checks_the_permission_bits_of_the_caller() {
+        let (vfs, root) = ram_vfs();
+        let file = block_on(create(&vfs, &root, "f", Mode::TY_REG));
+        block_on(file.chown(1000, 100)).unwrap();
+        block_on(file.chmod(Mode::TY_REG | Mode::PERM_RW_USR | Mode::PERM_R_GRP)).unwrap();
+        let metadata = block_on(file.metadata()).unwrap();
+        let access = |uid, gid, p| metadata.check_access(uid, gid, p).is_ok();
+
+        assert!(access(1000, 1, Permission::READ_WRITE));
+        assert!(!access(1000, 1, Permission::EXEC));
+        assert!(access(2000, 100, Permission::READ));
+        assert!(!access(2000, 100, Permission::WRITE));
+        assert!(!access(2000, 1, Permission::READ));
+        assert!(matches!(
+            metadata.check_access(2000, 1, Permission::READ),
+            Err(Error::PermissionDenied)
+        ));
+
+        // Root reads and writes anything, but only executes what someone may execute.
+        assert!(access(0, 0, Permission::READ_WRITE));
+        assert!(!access(0, 0, Permission::EXEC));
+        block_on(file.chmod(Mode::TY_REG | Mode::PERM_X_OTH)).unwrap();
+        let metadata = block_on(file.metadata()).unwrap();
+        assert!(metadata.check_access(0, 0, Permission::EXEC).is_ok());
+        let dir = block_on(root.metadata()).unwrap();
+        assert!(dir.check_access(0, 0, Permission::EXEC).is_ok());
+
+        // A missing file is not found, before any permission is checked.
+        assert_eq!(find(&vfs, &root, "missing"), None);
+    }
+
+    #[cfg(feature = "naive_fs")]
+    #[test]
+    fn symlink_is_removed_if_its_target_cannot_be_written() {
+        let (vfs, root) = block_on(naive_vfs(64));
+        let target = [b'a'; 64 * 512];
+        assert!(matches!(
+            block_on(vfs.symlink(
+                &root,
+                FsStr::from_bytes(b"link"),
+                &target,
+                0,
+                0,
+                Default::default(),
+            )),
+            Err(Error::NoSpace)
+        ));
+        assert!(block_on(vfs.find(&root, Path::from_bytes(b"link")))
+            .unwrap()
+            .is_none());
+    }
+
+    #[cfg(feature = "naive_fs")]
+    #[test]
+    fn a_formatted_ram_disk_is_a_usable_root() {
+        use std::sync::Arc;
+
+        use crate::{mock::TestIrq, naive_fs_vfs};
+
+        let device = Arc::new(blk::ram_blk::RamBlkDevice::<TestIrq>::new(
+            blk::BlkSize::new(512),
+            64,
+        ));
+        let disk = || blk::disk::Disk::<TestIrq>::new(device.clone(), 8);
+        let blk_size = naive_fs::BlkSize::new(512);
+        let fs = block_on(naive_fs_vfs::format::<TestIrq, _>(
+            disk(),
+            blk_size,
+            *b"ramdisk\0\0\0\0\0\0\0\0\0",
+        ))
+        .unwrap();
+        let vfs = Vfs::new(fs.clone());
+        let root = block_on(block_on(vfs.root()).inode()).unwrap().unwrap();
+        let etc = block_on(create(&vfs, &root, "etc", Mode::TY_DIR));
+        let file = block_on(create(&vfs, &etc, "motd", Mode::TY_REG));
+        assert_eq!(block_on(file.write_at(0, b"hello")).unwrap(), 5);
+        block_on(file.sync()).unwrap();
+        block_on(fs.sync()).unwrap();
+
+        // The filesystem is on the device, opening it again finds the file.
+        let fs = block_on(naive_fs_vfs::NaiveFs::<TestIrq, _>::open(disk(), false)).unwrap();
+        let vfs = Vfs::new(Arc::new(fs));
+        let root = block_on(block_on(vfs.root()).inode()).unwrap().unwrap();
+        let file = block_on(vfs.find(&root, Path::from_bytes(b"/etc/motd")))
+            .unwrap()
+            .unwrap();
+        let file = block_on(file.inode()).unwrap().unwrap();
+        let mut buf = [0; 5];
+        assert_eq!(block_on(file.read_at(0, &mut buf)).unwrap(), 5);
+        assert_eq!(&buf, b"hello");
+    }
+
+    /// Links `a` as `b`, writes through `a`, reads through `b`, then unlinks them in turn.
+    async fn hard_link_shares_its_inode<FS: Filesystem>(vfs: &Vfs<FS>, root: &FS::Inode) {
+        let inode = |name: &'static str| async move {
+            let entry = vfs.find(root, Path::from_bytes(name.as_bytes())).await?;
+            match entry {
+                Some(entry) => entry.inode().await,
+                None => Ok(None),
+            }
+        };
+        let a = create(vfs, root, "a", Mode::TY_REG).await;
+        vfs.link(root, FsStr::from_bytes(b"b"), &a).await.unwrap();
+        assert_eq!(a.write_at(0, b"shared").await.unwrap(), 6);
+        a.sync().await.unwrap();
+
+        let b = inode("b").await.unwrap().unwrap();
+        assert_eq!(b.id(), a.id());
+        assert_eq!(b.metadata().await.unwrap().links_count, 2);
+        let mut buf = [0; 6];
+        assert_eq!(b.read_at(0, &mut buf).await.unwrap(), 6);
+        assert_eq!(&buf, b"shared");
+
+        // The data stays with the last link.
+        vfs.unlink(root, FsStr::from_bytes(b"a")).await.unwrap();
+        assert!(inode("a").await.unwrap().is_none());
+        let b = inode("b").await.unwrap().unwrap();
+        assert_eq!(b.metadata().await.unwrap().links_count, 1);
+        let mut buf = [0; 6];
+        assert_eq!(b.read_at(0, &mut buf).await.unwrap(), 6);
+        assert_eq!(&buf, b"shared");
+
+        vfs.unlink(root, FsStr::from_bytes(b"b")).await.unwrap();
+        assert!(vfs.inner.load_inode(b.id()).await.unwrap().is_none());
+
+        let dir = create(vfs, root, "dir", Mode::TY_DIR).await;
+        assert!(matches!(
+            vfs.link(root, FsStr::from_bytes(b"dir2"), &dir).await,
+            Err(Error::NotPermitted)
+        ));
+    }
+
+    #[test]
+    fn hard_link_shares_its_inode_on_ram_fs() {
+        let (vfs, root) = ram_vfs();
+        block_on(hard_link_shares_its_inode(&vfs, &root));
+    }
+
+    #[cfg(feature = "naive_fs")]
+    #[test]
+    fn hard_link_shares_its_inode_on_naive_fs() {
+        let (vfs, root) = block_on(naive_vfs(64));
+        block_on(hard_link_shares_its_inode(&vfs, &root));
+    }
+}
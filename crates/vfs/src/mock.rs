@@ -0,0 +1,127 @@
+//! A RAM filesystem and a device for the tests.
+
+use core::{
+    future::ready,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::{boxed::Box, sync::Arc};
+use futures_util::future::BoxFuture;
+use spinlock::Irq;
+use time::Timespec;
+
+use crate::{dev_fs::DevInode, mount_fs::DynInode, ram_fs, FsStr, Inode, Mode, Vfs};
+#[cfg(feature = "naive_fs")]
+use crate::{
+    naive_fs_vfs::{format, NaiveFs},
+    Filesystem,
+};
+
+pub struct TestIrq;
+
+impl Irq for TestIrq {
+    fn push_off() {}
+    fn pop_off() {}
+}
+
+pub type TestFs = Arc<ram_fs::RamFs<TestIrq>>;
+pub type TestInode = Arc<ram_fs::Inode<TestIrq>>;
+
+/// A `Vfs` of an empty `RamFs`, and its root directory.
+pub fn ram_vfs() -> (Vfs<TestFs>, TestInode) {
+    let fs = Arc::new(ram_fs::RamFs::new());
+    let root = fs.create_root(Mode::TY_DIR | Mode::PERM_RWX_USR, Timespec::default());
+    (Vfs::new(fs), root)
+}
+
+/// Creates the file `name` of type `ty` in `parent`, owned by root.
+pub async fn create<I: Inode>(vfs: &Vfs<I::FS>, parent: &I, name: &str, ty: Mode) -> I {
+    let mode = ty | Mode::PERM_RWX_USR;
+    vfs.create(
+        parent,
+        FsStr::from_bytes(name.as_bytes()),
+        mode,
+        0,
+        0,
+        Timespec::default(),
+    )
+    .await
+    .unwrap()
+}
+
+#[cfg(feature = "naive_fs")]
+pub type TestNaiveFs = Arc<NaiveFs<TestIrq, blk::disk::Disk<TestIrq>>>;
+
+/// A `Vfs` of a blank naive filesystem on a RAM disk of `blk_count` blocks of 512 bytes,
+/// and its root directory.
+#[cfg(feature = "naive_fs")]
+pub async fn naive_vfs(blk_count: usize) -> (Vfs<TestNaiveFs>, <TestNaiveFs as Filesystem>::Inode) {
+    let device = Arc::new(blk::ram_blk::RamBlkDevice::<TestIrq>::new(
+        blk::BlkSize::new(512),
+        blk_count,
+    ));
+    let fs = format(
+        blk::disk::Disk::new(device, 8),
+        naive_fs::BlkSize::new(512),
+        Default::default(),
+    )
+    .await
+    .unwrap();
+    let vfs = Vfs::new(fs);
+    let root = vfs.root().await.inode().await.unwrap().unwrap();
+    (vfs, root)
+}
+
+/// A character device that counts the times it is synced and discards what is written.
+#[derive(Default)]
+pub struct TestDev {
+    pub syncs: AtomicUsize,
+}
+
+impl TestDev {
+    /// The device, and the inode to open it through.
+    pub fn inode() -> (Arc<TestDev>, Arc<dyn DynInode>) {
+        let dev = Arc::new(TestDev::default());
+        let inode = Arc::new(dev.clone() as Arc<dyn DevInode>);
+        (dev, inode)
+    }
+
+    pub fn syncs(&self) -> usize {
+        self.syncs.load(Ordering::SeqCst)
+    }
+}
+
+impl DevInode for TestDev {
+    fn id(&self) -> crate::InodeId {
+        1
+    }
+
+    fn metadata(&self) -> BoxFuture<'_, crate::Result<crate::Metadata>> {
+        Box::pin(ready(Ok(crate::Metadata {
+            mode: Mode::TY_CHR | Mode::PERM_RW_USR,
+            links_count: 1,
+            ..Default::default()
+        })))
+    }
+
+    fn read_at<'a>(
+        &'a self,
+        _offset: u64,
+        _buf: &'a mut [u8],
+    ) -> BoxFuture<'a, crate::Result<usize>> {
+        Box::pin(ready(Ok(0)))
+    }
+
+    fn write_at<'a>(&'a self, _offset: u64, src: &'a [u8]) -> BoxFuture<'a, crate::Result<usize>> {
+        Box::pin(ready(Ok(src.len())))
+    }
+
+    fn sync(&self) -> BoxFuture<'_, crate::Result<()>> {
+        self.syncs.fetch_add(1, Ordering::SeqCst);
+        Box::pin(ready(Ok(())))
+    }
+
+    fn ioctl(&self, _cmd: u32, _arg: usize) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(ready(Err(crate::Error::Unsupport)))
+    }
+}
@@ -0,0 +1,192 @@
+//! The table of filesystems mounted onto directories of the root filesystem.
+
+use alloc::{sync::Arc, vec::Vec};
+
+use spinlock::{Irq, RwLockIrq};
+
+use crate::{
+    self as vfs,
+    mount_fs::{self, DynFilesystem, DynInode, MountFs},
+    DirEntry, FsString, Path, PATH_CAP,
+};
+
+bitflags! {
+    pub struct MountOptions: u8 {
+        const READ_ONLY = 0x1;
+    }
+}
+
+#[derive(Clone)]
+pub struct MountEntry {
+    /// Normalized path of the mountpoint as given to `mount`.
+    pub path: FsString<PATH_CAP>,
+    pub fs: Arc<dyn DynFilesystem>,
+    pub options: MountOptions,
+    // The directory covered by `fs`
+    mountpoint: Arc<dyn DynInode>,
+}
+
+impl MountEntry {
+    // Whether `dentry` is the root directory of this mount.
+    fn is_root(&self, dentry: &DirEntry<Arc<dyn DynFilesystem>>) -> bool {
+        Arc::as_ptr(&self.fs) as *const () == Arc::as_ptr(&dentry.fs) as *const ()
+            && dentry.raw.inode_id == self.fs.root_dir_entry_raw().inode_id
+    }
+}
+
+/// The mounted filesystems, onto the directories of a [`MountFs`] locked with `I`.
+pub struct MountTable<I> {
+    mounts: RwLockIrq<I, Vec<MountEntry>>,
+}
+
+impl<I: Irq + 'static> MountTable<I> {
+    #[allow(clippy::new_without_default)]
+    pub const fn new() -> Self {
+        Self {
+            mounts: RwLockIrq::new(Vec::new()),
+        }
+    }
+
+    /// Returns all mounted filesystems, in mount order.
+    pub fn mounts(&self) -> Vec<MountEntry> {
+        self.mounts.read().clone()
+    }
+
+    /// Mount `fs` onto the directory `target`, which `path` resolved to.
+    /// The mounted filesystem may itself have filesystems mounted onto it.
+    pub async fn mount(
+        &self,
+        path: &Path,
+        target: &DirEntry<Arc<dyn DynFilesystem>>,
+        fs: Arc<dyn DynFilesystem>,
+        options: MountOptions,
+    ) -> vfs::Result<()> {
+        if self.mounts.read().iter().any(|entry| entry.is_root(target)) {
+            return Err(vfs::Error::Busy);
+        }
+        let path = path.normalize()?;
+        let mountpoint = target.as_dir().await?.ok_or(vfs::Error::NotDir)?;
+        let fs = Arc::new(MountFs::<_, I>::new(fs)) as Arc<dyn DynFilesystem>;
+        mount_fs::mount::<I>(mountpoint.clone(), fs.clone()).await?;
+        self.mounts.write().push(MountEntry {
+            path,
+            fs,
+            options,
+            mountpoint,
+        });
+        Ok(())
+    }
+
+    /// Detach the filesystem whose root directory is `target`.
+    /// Fails with `Busy` while files are open on the filesystem
+    /// or other filesystems are mounted onto it.
+    pub fn umount(&self, target: &DirEntry<Arc<dyn DynFilesystem>>) -> vfs::Result<()> {
+        let mut mounts = self.mounts.write();
+        let idx = mounts
+            .iter()
+            .position(|entry| entry.is_root(target))
+            .ok_or(vfs::Error::NotMountPoint)?;
+        if mounts[idx].fs.in_use() {
+            return Err(vfs::Error::Busy);
+        }
+        mount_fs::umount::<I>(&mounts[idx].mountpoint)?;
+        mounts.remove(idx);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::{sync::Arc, vec::Vec};
+
+    use tokio_test::block_on;
+
+    use super::{MountOptions, MountTable};
+    use crate::{
+        dev_fs::DevFs,
+        mock::{create, ram_vfs, TestIrq},
+        mount_fs::{DynFilesystem, MountFs},
+        DirEntry, Error, Filesystem, Mode, Path, Vfs,
+    };
+
+    type TestVfs = Vfs<Arc<dyn DynFilesystem>>;
+
+    // A `RamFs` holding the file `name`.
+    fn ram_fs_with(name: &str) -> Arc<dyn DynFilesystem> {
+        let (vfs, root) = ram_vfs();
+        block_on(create(&vfs, &root, name, Mode::TY_REG));
+        Arc::new(block_on(vfs.root()).fs)
+    }
+
+    // A `Vfs` of a `RamFs` wrapped in a `MountFs`, holding the empty directory `/mnt`.
+    fn root_vfs() -> TestVfs {
+        let fs = MountFs::<_, TestIrq>::new(ram_fs_with("file"));
+        let vfs = Vfs::new(Arc::new(fs) as Arc<dyn DynFilesystem>);
+        let root = block_on(block_on(vfs.root()).inode()).unwrap().unwrap();
+        block_on(create(&vfs, &root, "mnt", Mode::TY_DIR));
+        vfs
+    }
+
+    fn find(vfs: &TestVfs, path: &str) -> Option<DirEntry<Arc<dyn DynFilesystem>>> {
+        let root = block_on(block_on(vfs.root()).inode()).unwrap().unwrap();
+        block_on(vfs.find(&root, Path::from_bytes(path.as_bytes()))).unwrap()
+    }
+
+    #[test]
+    fn mounted_fs_is_seen_through_the_mountpoint_until_unmounted() {
+        let vfs = root_vfs();
+        let table = MountTable::<TestIrq>::new();
+        let target = find(&vfs, "/mnt").unwrap();
+        let path = Path::from_bytes(b"/./mnt/");
+        block_on(table.mount(path, &target, ram_fs_with("hello"), MountOptions::READ_ONLY))
+            .unwrap();
+        assert!(find(&vfs, "/mnt/hello").is_some());
+
+        let mounts = table.mounts();
+        assert_eq!(mounts.len(), 1);
+        assert_eq!(mounts[0].path.as_bytes(), b"/mnt");
+        assert_eq!(mounts[0].options, MountOptions::READ_ONLY);
+
+        // `/mnt` now names the root of the mounted filesystem.
+        let mounted_root = find(&vfs, "/mnt").unwrap();
+        let remount = table.mount(path, &mounted_root, ram_fs_with("a"), MountOptions::empty());
+        assert!(matches!(block_on(remount), Err(Error::Busy)));
+
+        table.umount(&mounted_root).unwrap();
+        assert!(find(&vfs, "/mnt/hello").is_none());
+        assert!(table.mounts().is_empty());
+        assert!(matches!(
+            table.umount(&mounted_root),
+            Err(Error::NotMountPoint)
+        ));
+    }
+
+    #[test]
+    fn mounts_list_the_names_of_the_filesystems() {
+        let vfs = root_vfs();
+        let root = block_on(block_on(vfs.root()).inode()).unwrap().unwrap();
+        block_on(create(&vfs, &root, "dev", Mode::TY_DIR));
+        let table = MountTable::<TestIrq>::new();
+        let dev_fs = Arc::new(DevFs::new(Vec::new())) as Arc<dyn DynFilesystem>;
+        for (path, fs) in [("/dev", dev_fs), ("/mnt", ram_fs_with("a"))] {
+            let target = find(&vfs, path).unwrap();
+            let path = Path::from_bytes(path.as_bytes());
+            block_on(table.mount(path, &target, fs, MountOptions::empty())).unwrap();
+        }
+
+        let mounts: Vec<_> = table
+            .mounts()
+            .into_iter()
+            .map(|entry| (entry.path, entry.fs.name()))
+            .collect();
+        assert_eq!(mounts, [("/dev".into(), "devfs"), ("/mnt".into(), "ramfs")]);
+        assert_eq!(DynFilesystem::name(&*block_on(vfs.root()).fs), "ramfs");
+    }
+
+    #[cfg(feature = "naive_fs")]
+    #[test]
+    fn naive_fs_is_named_naivefs() {
+        let (vfs, _) = block_on(crate::mock::naive_vfs(64));
+        assert_eq!(Filesystem::name(&block_on(vfs.root()).fs), "naivefs");
+    }
+}
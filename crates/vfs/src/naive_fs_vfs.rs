@@ -0,0 +1,477 @@
+use core::future::{ready, Ready};
+
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use future_ext::{WithArg1, WithArg1Ext};
+use futures_util::{
+    future::{Map, MapErr},
+    FutureExt, TryFutureExt,
+};
+use naive_fs::BoxFuture;
+use spinlock::{Irq, MutexIrq};
+use time::Timespec;
+
+use crate as vfs;
+use crate::{mount_fs::NotDynInode, DirEntryName, FsStr};
+
+pub type NaiveFs<I, DK> = naive_fs::NaiveFs<MutexIrq<I, ()>, DK>;
+type NaiveFsInode<I, DK> = naive_fs::inode::Inode<MutexIrq<I, ()>, DK>;
+
+/// Creates a blank naive filesystem named `volume_name` with an empty root directory
+/// on `disk`, like the one on the RAM disk the kernel boots from without a disk.
+pub async fn format<I, DK>(
+    disk: DK,
+    blk_size: naive_fs::BlkSize,
+    volume_name: [u8; 16],
+) -> vfs::Result<Arc<NaiveFs<I, DK>>>
+where
+    I: Irq + 'static,
+    DK: naive_fs::Disk + Send + Sync + 'static,
+{
+    let fs = Arc::new(NaiveFs::create_blank(
+        disk,
+        blk_size,
+        Default::default(),
+        volume_name,
+    ));
+    fs.create_root(0).await?.sync().await?;
+    Ok(fs)
+}
+
+impl<I, DK> vfs::Filesystem for Arc<NaiveFs<I, DK>>
+where
+    I: Irq + 'static,
+    DK: naive_fs::Disk + Send + Sync + 'static,
+{
+    type Inode = NaiveFsInode<I, DK>;
+
+    type CreateInodeFut<'a> = BoxFuture<'a, vfs::Result<Self::Inode>>;
+
+    type LoadInodeFut<'a> = MapErr<
+        naive_fs::inode::InodeLoadFut<'a, MutexIrq<I, ()>, DK>,
+        fn(naive_fs::Error) -> vfs::Error,
+    >;
+
+    type SyncFut<'a> =
+        MapErr<BoxFuture<'a, naive_fs::Result<()>>, fn(naive_fs::Error) -> vfs::Error>;
+
+    fn root_dir_entry_raw(&self) -> vfs::RawDirEntry {
+        vfs::RawDirEntry {
+            inode_id: naive_fs::root_inode_id() as usize,
+            name: Box::new("/".as_bytes().into()),
+            file_type: Some(vfs::FileType::Dir),
+        }
+    }
+
+    fn root_dir_entry(&self) -> vfs::DirEntry<Self> {
+        vfs::DirEntry {
+            raw: self.root_dir_entry_raw(),
+            fs: self.clone(),
+        }
+    }
+
+    fn create_inode(
+        &self,
+        mode: vfs::Mode,
+        uid: u32,
+        gid: u32,
+        create_time: Timespec,
+    ) -> Self::CreateInodeFut<'_> {
+        Box::pin(
+            naive_fs::NaiveFs::create_inode(
+                self,
+                mode.into(),
+                uid as u16,
+                gid as u16,
+                create_time.unix_timestamp(),
+            )
+            .map_err(Into::into),
+        )
+    }
+
+    fn load_inode(&self, inode_id: vfs::InodeId) -> Self::LoadInodeFut<'_> {
+        naive_fs::NaiveFs::load_inode(self, inode_id as naive_fs::InodeId).map_err(Into::into)
+    }
+
+    fn sync(&self) -> Self::SyncFut<'_> {
+        naive_fs::NaiveFs::sync(self).map_err(Into::into)
+    }
+
+    /// Get the BlkDevice's block_size.
+    fn blk_size(&self) -> u32 {
+        naive_fs::NaiveFs::blk_size(self)
+    }
+
+    /// Get the BlkDevice's block count.
+    fn blk_count(&self) -> usize {
+        naive_fs::NaiveFs::blk_count(self)
+    }
+
+    fn name(&self) -> &'static str {
+        "naivefs"
+    }
+}
+
+impl<I, DK> NotDynInode for NaiveFsInode<I, DK> {}
+
+#[allow(clippy::type_complexity)]
+impl<I, DK> vfs::Inode for NaiveFsInode<I, DK>
+where
+    I: Irq + 'static,
+    DK: naive_fs::Disk + Send + Sync + 'static,
+{
+    type FS = Arc<NaiveFs<I, DK>>;
+
+    type MetadataFut<'a> = Map<
+        WithArg1<
+            sleeplock::RwLockReadFuture<
+                'a,
+                MutexIrq<I, ()>,
+                naive_fs::MaybeDirty<naive_fs::inode::InodeRecord>,
+            >,
+            Self::FS,
+        >,
+        fn(
+            (
+                sleeplock::RwLockReadGuard<
+                    'a,
+                    MutexIrq<I, ()>,
+                    naive_fs::MaybeDirty<naive_fs::inode::InodeRecord>,
+                >,
+                Self::FS,
+            ),
+        ) -> vfs::Result<vfs::Metadata>,
+    >;
+
+    type ChownFut<'a> = BoxFuture<'a, vfs::Result<()>>;
+    type ChmodFut<'a> = BoxFuture<'a, vfs::Result<()>>;
+    type SetTimesFut<'a> = BoxFuture<'a, vfs::Result<()>>;
+    type LinkFut<'a> = BoxFuture<'a, vfs::Result<()>>;
+    type UnlinkFut<'a> = BoxFuture<'a, vfs::Result<()>>;
+    type ReadAtFut<'a> = BoxFuture<'a, vfs::Result<usize>>;
+    type WriteAtFut<'a> = BoxFuture<'a, vfs::Result<usize>>;
+    type SyncFut<'a> =
+        MapErr<BoxFuture<'a, naive_fs::Result<()>>, fn(naive_fs::Error) -> vfs::Error>;
+    type AppendDotFut<'a> = BoxFuture<'a, vfs::Result<()>>;
+    type LookupRawFut<'a> = BoxFuture<'a, vfs::Result<Option<vfs::RawDirEntry>>>;
+    type LookupFut<'a> = BoxFuture<'a, vfs::Result<Option<vfs::DirEntry<Self::FS>>>>;
+    type AppendFut<'a> = BoxFuture<'a, vfs::Result<()>>;
+    type RemoveFut<'a> = BoxFuture<'a, vfs::Result<Option<vfs::RawDirEntry>>>;
+    type LsRawFut<'a> = BoxFuture<'a, vfs::Result<Vec<vfs::RawDirEntry>>>;
+    type LsFut<'a> = BoxFuture<'a, vfs::Result<Vec<vfs::DirEntry<Self::FS>>>>;
+    type IOCtlFut<'a> = Ready<vfs::Result<()>>;
+    type PollFut<'a> = Ready<vfs::PollEvents>;
+    type GetXattrFut<'a> = BoxFuture<'a, vfs::Result<Option<Vec<u8>>>>;
+    type SetXattrFut<'a> = BoxFuture<'a, vfs::Result<()>>;
+    type ListXattrFut<'a> = BoxFuture<'a, vfs::Result<Vec<Vec<u8>>>>;
+    type RemoveXattrFut<'a> = BoxFuture<'a, vfs::Result<Option<Vec<u8>>>>;
+
+    fn id(&self) -> vfs::InodeId {
+        self.inode_id as vfs::InodeId
+    }
+
+    fn metadata(&self) -> Self::MetadataFut<'_> {
+        self.raw
+            .read()
+            .with_arg1(self.naive_fs().clone())
+            .map(|(raw, fs)| {
+                Ok(vfs::Metadata {
+                    mode: raw.mode.into(),
+                    uid: raw.uid as u32,
+                    gid: raw.gid as u32,
+                    size: raw.size as u64,
+                    atime: raw.atime.into(),
+                    ctime: raw.ctime.into(),
+                    mtime: raw.mtime.into(),
+                    links_count: raw.links_count,
+                    blk_size: fs.blk_size(),
+                    blk_count: fs.blk_count(),
+                })
+            })
+    }
+
+    fn chown(&self, uid: u32, gid: u32) -> Self::ChownFut<'_> {
+        Box::pin(async move {
+            self.naive_fs().check_writable()?;
+            let mut raw = self.raw.write().await;
+            raw.uid = uid as u16;
+            raw.gid = gid as u16;
+            Ok(())
+        })
+    }
+
+    fn chmod(&self, mode: vfs::Mode) -> Self::ChmodFut<'_> {
+        Box::pin(async move {
+            self.naive_fs().check_writable()?;
+            self.raw.write().await.mode = mode.into();
+            Ok(())
+        })
+    }
+
+    fn set_times(
+        &self,
+        atime: Option<Timespec>,
+        mtime: Option<Timespec>,
+        ctime: Option<Timespec>,
+    ) -> Self::SetTimesFut<'_> {
+        Box::pin(async move {
+            self.naive_fs().check_writable()?;
+            let mut raw = self.raw.write().await;
+            if let Some(atime) = atime {
+                raw.atime = atime.unix_timestamp();
+            }
+            if let Some(mtime) = mtime {
+                raw.mtime = mtime.unix_timestamp();
+            }
+            if let Some(ctime) = ctime {
+                raw.ctime = ctime.unix_timestamp();
+            }
+            Ok(())
+        })
+    }
+
+    fn link(&self) -> Self::LinkFut<'_> {
+        Box::pin(naive_fs::inode::Inode::link(self).map_err(Into::into))
+    }
+
+    fn unlink(&self) -> Self::UnlinkFut<'_> {
+        Box::pin(naive_fs::inode::Inode::unlink(self).map_err(Into::into))
+    }
+
+    fn read_at<'a>(&'a self, offset: u64, buf: &'a mut [u8]) -> Self::ReadAtFut<'a> {
+        Box::pin(
+            naive_fs::inode::Inode::read_at(self, offset as u32, buf).map(|res| match res {
+                Ok(len) => Ok(len as usize),
+                Err(e) => Err(e.into()),
+            }),
+        )
+    }
+
+    fn write_at<'a>(&'a self, offset: u64, src: &'a [u8]) -> Self::WriteAtFut<'a> {
+        Box::pin(
+            naive_fs::inode::Inode::write_at(self, offset as u32, src).map(|res| match res {
+                Ok(len) => Ok(len as usize),
+                Err(e) => Err(e.into()),
+            }),
+        )
+    }
+
+    fn read_vectored<'a, 'b>(
+        &'a self,
+        offset: u64,
+        bufs: &'a mut [&'b mut [u8]],
+    ) -> BoxFuture<'a, vfs::Result<usize>> {
+        Box::pin(
+            naive_fs::inode::Inode::read_vectored(self, offset as u32, bufs).map(|res| match res {
+                Ok(len) => Ok(len as usize),
+                Err(e) => Err(e.into()),
+            }),
+        )
+    }
+
+    fn write_vectored<'a>(
+        &'a self,
+        offset: u64,
+        srcs: &'a [&'a [u8]],
+    ) -> BoxFuture<'a, vfs::Result<usize>> {
+        Box::pin(
+            naive_fs::inode::Inode::write_vectored(self, offset as u32, srcs).map(
+                |res| match res {
+                    Ok(len) => Ok(len as usize),
+                    Err(e) => Err(e.into()),
+                },
+            ),
+        )
+    }
+
+    fn sync(&self) -> Self::SyncFut<'_> {
+        naive_fs::inode::Inode::sync(self).map_err(Into::into)
+    }
+
+    fn append_dot(&self, parent_inode_id: vfs::InodeId) -> Self::AppendDotFut<'_> {
+        Box::pin(
+            naive_fs::inode::Inode::append_dot(self, parent_inode_id as naive_fs::InodeId)
+                .map_err(Into::into),
+        )
+    }
+
+    fn lookup_raw<'a>(&'a self, name: &'a FsStr) -> Self::LookupRawFut<'a> {
+        Box::pin(async move {
+            match naive_fs::inode::Inode::lookup(self, name.as_bytes()).await? {
+                Some(raw_dir_entry) => Ok(Some(raw_dir_entry.into())),
+                None => Ok(None),
+            }
+        })
+    }
+
+    fn lookup<'a>(&'a self, name: &'a FsStr) -> Self::LookupFut<'a> {
+        Box::pin(async move {
+            match naive_fs::inode::Inode::lookup(self, name.as_bytes()).await? {
+                Some(raw_dir_entry) => Ok(Some(vfs::DirEntry {
+                    raw: raw_dir_entry.into(),
+                    fs: self.naive_fs().clone(),
+                })),
+                None => Ok(None),
+            }
+        })
+    }
+
+    fn append(
+        &self,
+        dir_entry_name: DirEntryName,
+        inode_id: vfs::InodeId,
+        file_type: Option<vfs::FileType>,
+    ) -> Self::AppendFut<'_> {
+        Box::pin(
+            naive_fs::inode::Inode::append(
+                self,
+                inode_id as naive_fs::InodeId,
+                dir_entry_name.into(),
+                file_type.unwrap_or(vfs::FileType::RegFile).into(),
+            )
+            .map_err(Into::into),
+        )
+    }
+
+    fn remove<'a>(&'a self, dir_entry_name: &'a FsStr) -> Self::RemoveFut<'a> {
+        Box::pin(
+            naive_fs::inode::Inode::remove(self, dir_entry_name.as_bytes())
+                .map_ok(|d| d.map(Into::into))
+                .map_err(Into::into),
+        )
+    }
+
+    fn ls_raw(&self) -> Self::LsRawFut<'_> {
+        Box::pin(
+            naive_fs::inode::Inode::ls(self)
+                .map_ok(|raws| raws.into_iter().map(Into::into).collect())
+                .map_err(Into::into),
+        )
+    }
+
+    fn ls(&self) -> Self::LsFut<'_> {
+        Box::pin(async move {
+            naive_fs::inode::Inode::ls(self)
+                .await
+                .map(|list| {
+                    list.into_iter()
+                        .map(|raw_dir_entry| vfs::DirEntry {
+                            raw: raw_dir_entry.into(),
+                            fs: self.naive_fs().clone(),
+                        })
+                        .collect()
+                })
+                .map_err(Into::into)
+        })
+    }
+
+    fn ioctl(&self, _cmd: u32, _arg: usize) -> Self::IOCtlFut<'_> {
+        ready(Err(vfs::Error::Unsupport))
+    }
+
+    fn poll(&self, events: vfs::PollEvents) -> Self::PollFut<'_> {
+        ready(events.never_block())
+    }
+
+    fn getxattr<'a>(&'a self, name: &'a [u8]) -> Self::GetXattrFut<'a> {
+        Box::pin(naive_fs::inode::Inode::getxattr(self, name).map_err(Into::into))
+    }
+
+    fn setxattr<'a>(&'a self, name: &'a [u8], value: &'a [u8]) -> Self::SetXattrFut<'a> {
+        Box::pin(naive_fs::inode::Inode::setxattr(self, name, value).map_err(Into::into))
+    }
+
+    fn listxattr(&self) -> Self::ListXattrFut<'_> {
+        Box::pin(naive_fs::inode::Inode::listxattr(self).map_err(Into::into))
+    }
+
+    fn removexattr<'a>(&'a self, name: &'a [u8]) -> Self::RemoveXattrFut<'a> {
+        Box::pin(naive_fs::inode::Inode::removexattr(self, name).map_err(Into::into))
+    }
+}
+
+impl From<naive_fs::Error> for vfs::Error {
+    fn from(naive_fs_err: naive_fs::Error) -> Self {
+        match naive_fs_err {
+            naive_fs::Error::DiskError(disk_err) => {
+                vfs::Error::BlkErr(*disk_err.downcast::<blk::Error>().unwrap())
+            }
+            naive_fs::Error::NoSpace => vfs::Error::NoSpace,
+            naive_fs::Error::InvalidDirEntryName(name) => {
+                vfs::Error::InvalidDirEntryName(Box::new((*name).into()))
+            }
+            naive_fs::Error::NameTooLong => vfs::Error::NameTooLong,
+
+            naive_fs::Error::ReadOnly => vfs::Error::ReadOnly,
+            naive_fs::Error::NotDir => vfs::Error::NotDir,
+            naive_fs::Error::Unsupported => vfs::Error::Unsupport,
+            naive_fs::Error::Corrupt | naive_fs::Error::ChecksumMismatch => vfs::Error::Io,
+        }
+    }
+}
+
+impl From<naive_fs::inode::Mode> for vfs::Mode {
+    fn from(naive_fs_mode: naive_fs::inode::Mode) -> Self {
+        Self::from_bits(naive_fs_mode.bits()).unwrap()
+    }
+}
+
+impl From<vfs::Mode> for naive_fs::inode::Mode {
+    fn from(vfs_mode: vfs::Mode) -> Self {
+        Self::from_bits(vfs_mode.bits()).unwrap()
+    }
+}
+
+impl From<vfs::FileType> for naive_fs::dir::FileType {
+    fn from(vfs_file_type: vfs::FileType) -> Self {
+        match vfs_file_type {
+            vfs::FileType::RegFile => Self::RegFile,
+            vfs::FileType::Dir => Self::Dir,
+            vfs::FileType::ChrDev => Self::ChrDev,
+            vfs::FileType::BlkDev => Self::BlkDev,
+            vfs::FileType::Fifo => Self::Fifo,
+            vfs::FileType::Sock => Self::Sock,
+            vfs::FileType::Symlink => Self::Symlink,
+        }
+    }
+}
+
+impl From<naive_fs::dir::FileType> for vfs::FileType {
+    fn from(naive_file_type: naive_fs::dir::FileType) -> Self {
+        match naive_file_type {
+            naive_fs::dir::FileType::RegFile => Self::RegFile,
+            naive_fs::dir::FileType::Dir => Self::Dir,
+            naive_fs::dir::FileType::ChrDev => Self::ChrDev,
+            naive_fs::dir::FileType::BlkDev => Self::BlkDev,
+            naive_fs::dir::FileType::Fifo => Self::Fifo,
+            naive_fs::dir::FileType::Sock => Self::Sock,
+            naive_fs::dir::FileType::Symlink => Self::Symlink,
+        }
+    }
+}
+
+impl From<DirEntryName> for naive_fs::DirEntryName {
+    fn from(name: DirEntryName) -> Self {
+        let (bytes, len) = name.into_inner();
+        Self::new(bytes, len)
+    }
+}
+
+impl From<naive_fs::DirEntryName> for DirEntryName {
+    fn from(name: naive_fs::DirEntryName) -> Self {
+        let (bytes, len) = name.into_inner();
+        Self::new(bytes, len)
+    }
+}
+
+impl From<naive_fs::RawDirEntry> for vfs::RawDirEntry {
+    fn from(naive_raw_dir_entry: naive_fs::RawDirEntry) -> Self {
+        let inode_id = naive_raw_dir_entry.inode_id as vfs::InodeId;
+        let file_type = naive_fs::dir::FileType::from_primitive(naive_raw_dir_entry.file_type);
+        let name_len = naive_raw_dir_entry.name_len;
+        vfs::RawDirEntry {
+            inode_id,
+            name: Box::new(DirEntryName::new(naive_raw_dir_entry.raw_name(), name_len)),
+            file_type: file_type.map(Into::into),
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use alloc::vec::Vec;
+
+use crate::{Error, FsStr, FsString, Result};
+
+/// Capacity of a normalized path.
+pub const PATH_CAP: usize = 255;
+
+#[repr(transparent)]
+#[derive(Debug, PartialEq, Eq)]
+pub struct Path(FsStr);
+
+#[allow(dead_code)]
+impl Path {
+    pub fn from_bytes(bytes: &[u8]) -> &Self {
+        unsafe { &*(bytes as *const [u8] as *const Self) }
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0.iter().all(|&c| c == b'/')
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn is_absolute(&self) -> bool {
+        !self.0.is_empty() && self.0.as_bytes()[0] == b'/'
+    }
+
+    pub fn shift(&self) -> (&Self, Option<&FsStr>) {
+        let mut bytes = self.0.as_bytes();
+
+        // Eat leading '/'
+        match bytes.iter().position(|&c| c != b'/') {
+            Some(start_pos) => bytes = &bytes[start_pos..],
+            None => return (self, None),
+        }
+
+        let len = bytes.iter().position(|&c| c == b'/').unwrap_or(bytes.len());
+        return (
+            Self::from_bytes(&bytes[len..]),
+            Some(FsStr::from_bytes(&bytes[..len])),
+        );
+    }
+
+    pub fn pop(&self) -> (&Self, Option<&FsStr>) {
+        let mut bytes = self.0.as_bytes();
+
+        match bytes.last() {
+            Some(b'/') => {
+                // Eat trailing '/'
+                match bytes.iter().rposition(|&c| c != b'/') {
+                    Some(end_pos) => bytes = &bytes[..end_pos],
+                    None => return (self, None),
+                }
+            }
+            None => return (self, None),
+            _ => {}
+        }
+
+        let start_pos = bytes
+            .iter()
+            .rposition(|&c| c == b'/')
+            .map(|x| x + 1)
+            .unwrap_or(0);
+
+        return (
+            Self::from_bytes(&bytes[..start_pos]),
+            Some(FsStr::from_bytes(&bytes[start_pos..])),
+        );
+    }
+
+    pub fn inner(&self) -> &FsStr {
+        &self.0
+    }
+
+    /// Returns the names between the slashes of the path, `/a//b/c` yields `a`, `b` and `c`.
+    pub fn components(&self) -> impl Iterator<Item = &FsStr> {
+        self.0
+            .as_bytes()
+            .split(|&c| c == b'/')
+            .filter(|name| !name.is_empty())
+            .map(FsStr::from_bytes)
+    }
+
+    /// Returns an object that formats the path lossily, see [`FsStr::display`].
+    pub fn display(&self) -> crate::fs_str::Display<'_> {
+        self.0.display()
+    }
+
+    /// Lexically normalizes the path without touching the filesystem.
+    /// Repeated slashes are collapsed and "." / ".." are resolved.
+    /// An absolute path keeps its leading "/" and ".." never goes above it,
+    /// while ".." escaping a relative path is kept as leading "../".
+    /// A relative path that collapses to nothing becomes ".".
+    ///
+    /// Returns `NameTooLong` if the normalized path is longer than `PATH_CAP`.
+    pub fn normalize(&self) -> Result<FsString<PATH_CAP>> {
+        let absolute = self.is_absolute();
+        let mut components: Vec<&FsStr> = Vec::new();
+        for name in self.components() {
+            match name.as_bytes() {
+                b"." => {}
+                b".." => match components.last() {
+                    Some(last) if last.as_bytes() != b".." => {
+                        components.pop();
+                    }
+                    _ if absolute => {}
+                    _ => components.push(name),
+                },
+                _ => components.push(name),
+            }
+        }
+
+        let mut bytes = [0; PATH_CAP];
+        let mut len = 0;
+        if absolute {
+            bytes[0] = b'/';
+            len = 1;
+        }
+        for (idx, component) in components.iter().enumerate() {
+            let sep = (idx > 0) as usize;
+            if len + sep + component.len() > PATH_CAP {
+                return Err(Error::NameTooLong);
+            }
+            if sep > 0 {
+                bytes[len] = b'/';
+            }
+            len += sep;
+            bytes[len..len + component.len()].copy_from_slice(component.as_bytes());
+            len += component.len();
+        }
+        if len == 0 && !self.is_empty() {
+            bytes[0] = b'.';
+            len = 1;
+        }
+        Ok(FsString::new(bytes, len as u8))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{format, vec::Vec};
+
+    use super::{Path, PATH_CAP};
+    use crate::Error;
+
+    fn normalize(path: &str) -> Vec<u8> {
+        Path::from_bytes(path.as_bytes())
+            .normalize()
+            .unwrap()
+            .as_slice()
+            .to_vec()
+    }
+
+    #[test]
+    fn normalize_collapses_slashes_and_dots() {
+        assert_eq!(normalize("//a//./b/../c"), b"/a/c");
+        assert_eq!(normalize("a/./b/"), b"a/b");
+        assert_eq!(normalize("/"), b"/");
+    }
+
+    #[test]
+    fn normalize_keeps_dot_dot_above_a_relative_path() {
+        assert_eq!(normalize("../../x"), b"../../x");
+        assert_eq!(normalize("a/../../x"), b"../x");
+        assert_eq!(normalize("a/.."), b".");
+        assert_eq!(normalize("./"), b".");
+    }
+
+    #[test]
+    fn normalize_stops_dot_dot_at_the_root() {
+        assert_eq!(normalize("/.."), b"/");
+        assert_eq!(normalize("/../../a/.."), b"/");
+        assert_eq!(normalize("/../x"), b"/x");
+    }
+
+    #[test]
+    fn normalize_leaves_the_empty_path_empty() {
+        assert_eq!(normalize(""), b"");
+    }
+
+    #[test]
+    fn normalize_rejects_paths_longer_than_the_capacity() {
+        let name = "x".repeat(PATH_CAP - 1);
+        assert_eq!(normalize(&format!("/{}", name)).len(), PATH_CAP);
+        assert!(matches!(
+            Path::from_bytes(format!("/{}y", name).as_bytes()).normalize(),
+            Err(Error::NameTooLong)
+        ));
+        // Only the normalized length counts.
+        assert_eq!(normalize(&format!("/{}/../{}", name, name)).len(), PATH_CAP);
+    }
+
+    #[test]
+    fn components_skip_empty_names() {
+        let names: Vec<&[u8]> = Path::from_bytes(b"/a//b/c/")
+            .components()
+            .map(|name| name.as_bytes())
+            .collect();
+        assert_eq!(names, [&b"a"[..], b"b", b"c"]);
+        assert_eq!(Path::from_bytes(b"//").components().count(), 0);
+    }
+}
@@ -0,0 +1,551 @@
+//! Process information filesystem, mounted at `/proc`.
+//!
+//! The root directory holds a directory per process, named by its pid, and `self`,
+//! a symlink to the directory of the calling process. Files are rendered from the
+//! live process state every time they are read, the kernel supplies it through `Procs`.
+
+use core::{
+    fmt::Write,
+    future::{ready, Ready},
+};
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+use time::Timespec;
+
+use crate as vfs;
+use crate::{mount_fs::NotDynInode, DirEntryName, FsStr};
+
+const PROC_ROOT_INODE_ID: vfs::InodeId = 1;
+const PROC_SELF_INODE_ID: vfs::InodeId = 2;
+// The inode id of a process directory is `pid << PID_SHIFT`,
+// the files in it add their `ProcFile` discriminant.
+const PID_SHIFT: usize = 2;
+
+/// The processes shown by a `ProcFs`.
+pub trait Procs: Clone + Send + Sync + 'static {
+    /// The ids of the live processes.
+    fn pids(&self) -> Vec<u32>;
+
+    fn exists(&self, pid: u32) -> bool;
+
+    /// The status of the process `pid`, `None` if there is no such process.
+    fn status(&self, pid: u32) -> Option<ProcStatus>;
+
+    /// The id of the calling process, `None` outside of a process.
+    fn current(&self) -> Option<u32>;
+}
+
+/// What the files of the directory of a process show.
+#[derive(Clone, Debug, Default)]
+pub struct ProcStatus {
+    pub name: String,
+    /// The state letter used by `ps`.
+    pub state: char,
+    pub pid: u32,
+    pub ppid: u32,
+    pub pgid: u32,
+    pub sid: u32,
+    pub threads: usize,
+    pub sig_blocked: u64,
+    pub sig_ignored: u64,
+}
+
+/// Process information filesystem
+#[derive(Clone)]
+pub struct ProcFs<P> {
+    procs: P,
+}
+
+impl<P: Procs> ProcFs<P> {
+    pub fn new(procs: P) -> Self {
+        Self { procs }
+    }
+}
+
+impl<P: Procs> vfs::Filesystem for ProcFs<P> {
+    type Inode = ProcInode<P>;
+
+    type CreateInodeFut<'a> = Ready<vfs::Result<Self::Inode>>;
+
+    type LoadInodeFut<'a> = Ready<vfs::Result<Option<Self::Inode>>>;
+
+    type SyncFut<'a> = Ready<vfs::Result<()>>;
+
+    fn root_dir_entry_raw(&self) -> vfs::RawDirEntry {
+        vfs::RawDirEntry {
+            inode_id: PROC_ROOT_INODE_ID,
+            name: Box::new("/".as_bytes().into()),
+            file_type: Some(vfs::FileType::Dir),
+        }
+    }
+
+    fn root_dir_entry(&self) -> vfs::DirEntry<Self> {
+        vfs::DirEntry {
+            raw: self.root_dir_entry_raw(),
+            fs: self.clone(),
+        }
+    }
+
+    fn create_inode(
+        &self,
+        _mode: vfs::Mode,
+        _uid: u32,
+        _gid: u32,
+        _create_time: Timespec,
+    ) -> Self::CreateInodeFut<'_> {
+        ready(Err(vfs::Error::Unsupport))
+    }
+
+    fn load_inode(&self, inode_id: vfs::InodeId) -> Self::LoadInodeFut<'_> {
+        ready(Ok(Node::from_inode_id(&self.procs, inode_id).map(|node| {
+            ProcInode {
+                fs: self.clone(),
+                node,
+            }
+        })))
+    }
+
+    fn sync(&self) -> Self::SyncFut<'_> {
+        ready(Ok(()))
+    }
+
+    /// Get the BlkDevice's block_size.
+    fn blk_size(&self) -> u32 {
+        0
+    }
+
+    /// Get the BlkDevice's block count.
+    fn blk_count(&self) -> usize {
+        0
+    }
+
+    fn name(&self) -> &'static str {
+        "procfs"
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ProcFile {
+    Status = 1,
+    Cmdline = 2,
+    Stat = 3,
+}
+
+impl ProcFile {
+    const ALL: [ProcFile; 3] = [ProcFile::Status, ProcFile::Cmdline, ProcFile::Stat];
+
+    fn name(self) -> &'static str {
+        match self {
+            ProcFile::Status => "status",
+            ProcFile::Cmdline => "cmdline",
+            ProcFile::Stat => "stat",
+        }
+    }
+
+    fn render(self, status: &ProcStatus) -> String {
+        let mut content = String::new();
+        // Writing to a `String` never fails.
+        let _ = match self {
+            ProcFile::Status => write!(
+                content,
+                "Name:\t{}\nState:\t{}\nPid:\t{}\nPPid:\t{}\nPgid:\t{}\nSid:\t{}\nThreads:\t{}\nSigBlk:\t{:016x}\nSigIgn:\t{:016x}\n",
+                status.name,
+                status.state,
+                status.pid,
+                status.ppid,
+                status.pgid,
+                status.sid,
+                status.threads,
+                status.sig_blocked,
+                status.sig_ignored,
+            ),
+            ProcFile::Cmdline => write!(content, "{}\0", status.name),
+            ProcFile::Stat => writeln!(
+                content,
+                "{} ({}) {} {} {} {}",
+                status.pid, status.name, status.state, status.ppid, status.pgid, status.sid,
+            ),
+        };
+        content
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Node {
+    Root,
+    SelfLink,
+    PidDir(u32),
+    File(u32, ProcFile),
+}
+
+impl Node {
+    fn from_inode_id<P: Procs>(procs: &P, inode_id: vfs::InodeId) -> Option<Self> {
+        match inode_id {
+            PROC_ROOT_INODE_ID => Some(Node::Root),
+            PROC_SELF_INODE_ID => Some(Node::SelfLink),
+            _ => {
+                let pid = (inode_id >> PID_SHIFT) as u32;
+                if !procs.exists(pid) {
+                    return None;
+                }
+                let file = inode_id & ((1 << PID_SHIFT) - 1);
+                if file == 0 {
+                    Some(Node::PidDir(pid))
+                } else {
+                    ProcFile::ALL
+                        .iter()
+                        .find(|f| **f as usize == file)
+                        .map(|f| Node::File(pid, *f))
+                }
+            }
+        }
+    }
+
+    fn inode_id(self) -> vfs::InodeId {
+        match self {
+            Node::Root => PROC_ROOT_INODE_ID,
+            Node::SelfLink => PROC_SELF_INODE_ID,
+            Node::PidDir(pid) => (pid as usize) << PID_SHIFT,
+            Node::File(pid, file) => ((pid as usize) << PID_SHIFT) | file as usize,
+        }
+    }
+
+    fn file_type(self) -> vfs::FileType {
+        match self {
+            Node::Root | Node::PidDir(_) => vfs::FileType::Dir,
+            Node::SelfLink => vfs::FileType::Symlink,
+            Node::File(..) => vfs::FileType::RegFile,
+        }
+    }
+
+    fn raw_dir_entry(self, name: DirEntryName) -> vfs::RawDirEntry {
+        vfs::RawDirEntry {
+            inode_id: self.inode_id(),
+            name: Box::new(name),
+            file_type: Some(self.file_type()),
+        }
+    }
+
+    // The symlink target or the file content.
+    fn content<P: Procs>(self, procs: &P) -> vfs::Result<String> {
+        match self {
+            Node::SelfLink => Ok(procs
+                .current()
+                .map(|current| {
+                    let mut pid = String::new();
+                    let _ = write!(pid, "{}", current);
+                    pid
+                })
+                .unwrap_or_default()),
+            Node::File(pid, file) => procs
+                .status(pid)
+                .map(|status| file.render(&status))
+                .ok_or(vfs::Error::NoSuchProcess(pid)),
+            Node::Root | Node::PidDir(_) => Err(vfs::Error::Unsupport),
+        }
+    }
+
+    fn lookup_raw<P: Procs>(
+        self,
+        procs: &P,
+        name: &FsStr,
+    ) -> vfs::Result<Option<vfs::RawDirEntry>> {
+        let node = match self {
+            Node::Root if name.as_bytes() == b"self" => Some(Node::SelfLink),
+            Node::Root => core::str::from_utf8(name.as_bytes())
+                .ok()
+                .and_then(|name| name.parse::<u32>().ok())
+                .filter(|pid| procs.exists(*pid))
+                .map(Node::PidDir),
+            Node::PidDir(pid) => ProcFile::ALL
+                .iter()
+                .find(|file| file.name().as_bytes() == name.as_bytes())
+                .map(|file| Node::File(pid, *file)),
+            Node::SelfLink | Node::File(..) => return Err(vfs::Error::NotDir),
+        };
+        Ok(node.map(|node| node.raw_dir_entry(DirEntryName::from(name.as_bytes()))))
+    }
+
+    fn ls_raw<P: Procs>(self, procs: &P) -> vfs::Result<Vec<vfs::RawDirEntry>> {
+        match self {
+            Node::Root => {
+                let mut pids = procs.pids();
+                pids.sort_unstable();
+                let mut dir_entries = vec![Node::SelfLink.raw_dir_entry("self".into())];
+                dir_entries.extend(pids.into_iter().map(|pid| {
+                    let mut name = String::new();
+                    let _ = write!(name, "{}", pid);
+                    Node::PidDir(pid).raw_dir_entry(name.as_str().into())
+                }));
+                Ok(dir_entries)
+            }
+            Node::PidDir(pid) => Ok(ProcFile::ALL
+                .iter()
+                .map(|file| Node::File(pid, *file).raw_dir_entry(file.name().into()))
+                .collect()),
+            Node::SelfLink | Node::File(..) => Err(vfs::Error::NotDir),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ProcInode<P> {
+    fs: ProcFs<P>,
+    node: Node,
+}
+
+impl<P: Procs> NotDynInode for ProcInode<P> {}
+
+impl<P: Procs> vfs::Inode for ProcInode<P> {
+    type FS = ProcFs<P>;
+
+    type MetadataFut<'a> = Ready<vfs::Result<vfs::Metadata>>;
+    type ChownFut<'a> = Ready<vfs::Result<()>>;
+    type ChmodFut<'a> = Ready<vfs::Result<()>>;
+    type SetTimesFut<'a> = Ready<vfs::Result<()>>;
+    type LinkFut<'a> = Ready<vfs::Result<()>>;
+    type UnlinkFut<'a> = Ready<vfs::Result<()>>;
+    type ReadAtFut<'a> = Ready<vfs::Result<usize>>;
+    type WriteAtFut<'a> = Ready<vfs::Result<usize>>;
+    type SyncFut<'a> = Ready<vfs::Result<()>>;
+    type AppendDotFut<'a> = Ready<vfs::Result<()>>;
+    type LookupRawFut<'a> = Ready<vfs::Result<Option<vfs::RawDirEntry>>>;
+    type LookupFut<'a> = Ready<vfs::Result<Option<vfs::DirEntry<Self::FS>>>>;
+    type AppendFut<'a> = Ready<vfs::Result<()>>;
+    type RemoveFut<'a> = Ready<vfs::Result<Option<vfs::RawDirEntry>>>;
+    type LsRawFut<'a> = Ready<vfs::Result<Vec<vfs::RawDirEntry>>>;
+    type LsFut<'a> = Ready<vfs::Result<Vec<vfs::DirEntry<Self::FS>>>>;
+    type IOCtlFut<'a> = Ready<vfs::Result<()>>;
+    type PollFut<'a> = Ready<vfs::PollEvents>;
+    type GetXattrFut<'a> = Ready<vfs::Result<Option<Vec<u8>>>>;
+    type SetXattrFut<'a> = Ready<vfs::Result<()>>;
+    type ListXattrFut<'a> = Ready<vfs::Result<Vec<Vec<u8>>>>;
+    type RemoveXattrFut<'a> = Ready<vfs::Result<Option<Vec<u8>>>>;
+
+    fn id(&self) -> vfs::InodeId {
+        self.node.inode_id()
+    }
+
+    fn metadata(&self) -> Self::MetadataFut<'_> {
+        let (mode, size) = match self.node {
+            Node::Root | Node::PidDir(_) => (
+                vfs::Mode::TY_DIR
+                    | vfs::Mode::PERM_RX_USR
+                    | vfs::Mode::PERM_RX_GRP
+                    | vfs::Mode::PERM_RX_OTH,
+                0,
+            ),
+            Node::SelfLink => (
+                vfs::Mode::TY_LNK
+                    | vfs::Mode::PERM_RWX_USR
+                    | vfs::Mode::PERM_RWX_GRP
+                    | vfs::Mode::PERM_RWX_OTH,
+                self.node
+                    .content(&self.fs.procs)
+                    .map_or(0, |target| target.len()),
+            ),
+            Node::File(..) => match self.node.content(&self.fs.procs) {
+                Ok(content) => (
+                    vfs::Mode::TY_REG
+                        | vfs::Mode::PERM_R_USR
+                        | vfs::Mode::PERM_R_GRP
+                        | vfs::Mode::PERM_R_OTH,
+                    content.len(),
+                ),
+                Err(e) => return ready(Err(e)),
+            },
+        };
+        ready(Ok(vfs::Metadata {
+            mode,
+            size: size as u64,
+            links_count: 1,
+            ..Default::default()
+        }))
+    }
+
+    fn chown(&self, _uid: u32, _gid: u32) -> Self::ChownFut<'_> {
+        ready(Err(vfs::Error::Unsupport))
+    }
+
+    fn chmod(&self, _mode: vfs::Mode) -> Self::ChmodFut<'_> {
+        ready(Err(vfs::Error::Unsupport))
+    }
+
+    fn set_times(
+        &self,
+        _atime: Option<Timespec>,
+        _mtime: Option<Timespec>,
+        _ctime: Option<Timespec>,
+    ) -> Self::SetTimesFut<'_> {
+        ready(Err(vfs::Error::Unsupport))
+    }
+
+    fn link(&self) -> Self::LinkFut<'_> {
+        ready(Err(vfs::Error::Unsupport))
+    }
+
+    fn unlink(&self) -> Self::UnlinkFut<'_> {
+        ready(Err(vfs::Error::Unsupport))
+    }
+
+    fn read_at<'a>(&'a self, offset: u64, buf: &'a mut [u8]) -> Self::ReadAtFut<'a> {
+        ready(self.node.content(&self.fs.procs).map(|content| {
+            let content = content.as_bytes();
+            let start = content.len().min(offset as usize);
+            let len = buf.len().min(content.len() - start);
+            buf[..len].copy_from_slice(&content[start..start + len]);
+            len
+        }))
+    }
+
+    fn write_at<'a>(&'a self, _offset: u64, _src: &'a [u8]) -> Self::WriteAtFut<'a> {
+        ready(Err(vfs::Error::Unsupport))
+    }
+
+    fn sync(&self) -> Self::SyncFut<'_> {
+        ready(Ok(()))
+    }
+
+    fn append_dot(&self, _parent_inode_id: vfs::InodeId) -> Self::AppendDotFut<'_> {
+        ready(Err(vfs::Error::Unsupport))
+    }
+
+    fn lookup_raw<'a>(&'a self, name: &'a FsStr) -> Self::LookupRawFut<'a> {
+        ready(self.node.lookup_raw(&self.fs.procs, name))
+    }
+
+    fn lookup<'a>(&'a self, name: &'a FsStr) -> Self::LookupFut<'a> {
+        ready(
+            self.node
+                .lookup_raw(&self.fs.procs, name)
+                .map(|raw_dir_entry| {
+                    raw_dir_entry.map(|raw| vfs::DirEntry {
+                        raw,
+                        fs: self.fs.clone(),
+                    })
+                }),
+        )
+    }
+
+    fn append(
+        &self,
+        _dir_entry_name: DirEntryName,
+        _inode_id: vfs::InodeId,
+        _file_type: Option<vfs::FileType>,
+    ) -> Self::AppendFut<'_> {
+        ready(Err(vfs::Error::Unsupport))
+    }
+
+    fn remove<'a>(&'a self, _dir_entry_name: &'a FsStr) -> Self::RemoveFut<'a> {
+        ready(Err(vfs::Error::Unsupport))
+    }
+
+    fn ls_raw(&self) -> Self::LsRawFut<'_> {
+        ready(self.node.ls_raw(&self.fs.procs))
+    }
+
+    fn ls(&self) -> Self::LsFut<'_> {
+        ready(self.node.ls_raw(&self.fs.procs).map(|raw_dir_entries| {
+            raw_dir_entries
+                .into_iter()
+                .map(|raw| vfs::DirEntry {
+                    raw,
+                    fs: self.fs.clone(),
+                })
+                .collect()
+        }))
+    }
+
+    fn ioctl(&self, _cmd: u32, _arg: usize) -> Self::IOCtlFut<'_> {
+        ready(Err(vfs::Error::Unsupport))
+    }
+
+    fn poll(&self, events: vfs::PollEvents) -> Self::PollFut<'_> {
+        ready(events.never_block())
+    }
+
+    fn getxattr<'a>(&'a self, _name: &'a [u8]) -> Self::GetXattrFut<'a> {
+        ready(Err(vfs::Error::Unsupport))
+    }
+
+    fn setxattr<'a>(&'a self, _name: &'a [u8], _value: &'a [u8]) -> Self::SetXattrFut<'a> {
+        ready(Err(vfs::Error::Unsupport))
+    }
+
+    fn listxattr(&self) -> Self::ListXattrFut<'_> {
+        ready(Err(vfs::Error::Unsupport))
+    }
+
+    fn removexattr<'a>(&'a self, _name: &'a [u8]) -> Self::RemoveXattrFut<'a> {
+        ready(Err(vfs::Error::Unsupport))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::{string::String, vec::Vec};
+    use tokio_test::block_on;
+
+    use super::{ProcFs, ProcStatus, Procs};
+    use crate::{util::read_all, Path, Vfs};
+
+    // The processes 1 and 7, the caller is 7.
+    #[derive(Clone)]
+    struct TestProcs;
+
+    impl Procs for TestProcs {
+        fn pids(&self) -> Vec<u32> {
+            vec![7, 1]
+        }
+
+        fn exists(&self, pid: u32) -> bool {
+            pid == 1 || pid == 7
+        }
+
+        fn status(&self, pid: u32) -> Option<ProcStatus> {
+            if !self.exists(pid) {
+                return None;
+            }
+            Some(ProcStatus {
+                name: "sh".into(),
+                state: 'S',
+                pid,
+                ppid: 1,
+                pgid: pid,
+                sid: 1,
+                threads: 1,
+                sig_blocked: 0x2,
+                ..Default::default()
+            })
+        }
+
+        fn current(&self) -> Option<u32> {
+            Some(7)
+        }
+    }
+
+    fn read(vfs: &Vfs<ProcFs<TestProcs>>, path: &str) -> Option<String> {
+        let root = block_on(block_on(vfs.root()).inode()).unwrap().unwrap();
+        let entry = block_on(vfs.find(&root, Path::from_bytes(path.as_bytes()))).unwrap()?;
+        let inode = block_on(entry.inode()).unwrap().unwrap();
+        Some(String::from_utf8(block_on(read_all(inode)).unwrap()).unwrap())
+    }
+
+    #[test]
+    fn self_status_describes_the_calling_process() {
+        let vfs = Vfs::new(ProcFs::new(TestProcs));
+        let status = read(&vfs, "/self/status").unwrap();
+        assert!(status.starts_with("Name:\tsh\nState:\tS\nPid:\t7\nPPid:\t1\nPgid:\t7\n"));
+        assert!(status.contains("SigBlk:\t0000000000000002\n"));
+        assert_eq!(read(&vfs, "/7/status"), Some(status));
+        assert_eq!(read(&vfs, "/1/stat").as_deref(), Some("1 (sh) S 1 1 1\n"));
+    }
+
+    #[test]
+    fn missing_processes_and_files_are_not_found() {
+        let vfs = Vfs::new(ProcFs::new(TestProcs));
+        assert_eq!(read(&vfs, "/9/status"), None);
+        assert_eq!(read(&vfs, "/self/environ"), None);
+        assert_eq!(read(&vfs, "/sh"), None);
+    }
+}
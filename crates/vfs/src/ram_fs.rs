@@ -0,0 +1,590 @@
+use core::{
+    cmp, future,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc, vec::Vec};
+use hashbrown::HashMap;
+use spinlock::{Irq, RwLockIrq};
+use time::Timespec;
+
+use crate as vfs;
+use crate::{mount_fs::NotDynInode, DirEntryName, FsStr};
+
+/// A filesystem based on RAM.
+pub struct RamFs<I> {
+    root_inode_id: usize,
+    id_allocator: IdAllocator,
+    inodes: RwLockIrq<I, HashMap<usize, Arc<Inode<I>>>>,
+    /// Time source for atime and mtime.
+    clock: fn() -> Timespec,
+}
+
+impl<I: Irq + 'static> RamFs<I> {
+    /// Constructs a new, empty `RamFs`.
+    /// Access and modification times are left at zero, use [`with_clock`](Self::with_clock) to track them.
+    pub fn new() -> Self {
+        Self::with_clock(Timespec::default)
+    }
+
+    /// Constructs a new, empty `RamFs` taking access and modification times from `clock`.
+    pub fn with_clock(clock: fn() -> Timespec) -> Self {
+        let root_inode_id = 1;
+        Self {
+            root_inode_id,
+            id_allocator: IdAllocator::new(root_inode_id + 1),
+            inodes: RwLockIrq::new(Default::default()),
+            clock,
+        }
+    }
+
+    /// Creates the root directory, which must be done before the filesystem is used.
+    pub fn create_root(self: &Arc<Self>, mode: vfs::Mode, create_time: Timespec) -> Arc<Inode<I>> {
+        let root = Self::insert_inode(self, self.root_inode_id, mode, 0, 0, create_time);
+        Inode::append(
+            &root,
+            "..".as_bytes().into(),
+            root.inode_id,
+            Some(vfs::FileType::Dir),
+        )
+        .and_then(|_| {
+            Inode::append(
+                &root,
+                ".".as_bytes().into(),
+                root.inode_id,
+                Some(vfs::FileType::Dir),
+            )
+        })
+        .expect("the new root directory is empty");
+        root
+    }
+
+    fn insert_inode(
+        fs: &Arc<Self>,
+        inode_id: usize,
+        mode: vfs::Mode,
+        uid: u32,
+        gid: u32,
+        create_time: Timespec,
+    ) -> Arc<Inode<I>> {
+        let inode = Arc::new(Inode {
+            inode_id,
+            inner: RwLockIrq::new(InodeInner {
+                metadata: vfs::Metadata {
+                    mode,
+                    uid,
+                    gid,
+                    size: 0,
+                    atime: create_time,
+                    ctime: create_time,
+                    mtime: create_time,
+                    links_count: 1,
+                    blk_size: vfs::Filesystem::blk_size(fs),
+                    blk_count: vfs::Filesystem::blk_count(fs),
+                },
+                content: if mode.is_dir() {
+                    Content::Dir(Default::default())
+                } else {
+                    Content::File(Default::default())
+                },
+            }),
+            fs: fs.clone(),
+        });
+        fs.inodes.write().insert(inode_id, inode.clone());
+        inode
+    }
+
+    fn load_inode(&self, inode_id: usize) -> Option<Arc<Inode<I>>> {
+        self.inodes.read().get(&inode_id).cloned()
+    }
+
+    fn remove_inode(&self, inode_id: usize) -> Option<()> {
+        let mut inodes = self.inodes.write();
+        inodes.remove(&inode_id).map(|_| ())
+    }
+}
+
+impl<I: Irq + 'static> vfs::Filesystem for Arc<RamFs<I>> {
+    type Inode = Arc<Inode<I>>;
+
+    type CreateInodeFut<'a> = future::Ready<vfs::Result<Self::Inode>>;
+
+    type LoadInodeFut<'a> = future::Ready<vfs::Result<Option<Self::Inode>>>;
+
+    type SyncFut<'a> = future::Ready<vfs::Result<()>>;
+
+    fn root_dir_entry_raw(&self) -> vfs::RawDirEntry {
+        vfs::RawDirEntry {
+            inode_id: self.root_inode_id,
+            name: Box::new("/".as_bytes().into()),
+            file_type: Some(vfs::FileType::Dir),
+        }
+    }
+
+    fn root_dir_entry(&self) -> vfs::DirEntry<Self> {
+        vfs::DirEntry {
+            raw: vfs::RawDirEntry {
+                inode_id: self.root_inode_id,
+                name: Box::new("/".as_bytes().into()),
+                file_type: Some(vfs::FileType::Dir),
+            },
+            fs: self.clone(),
+        }
+    }
+
+    fn create_inode(
+        &self,
+        mode: vfs::Mode,
+        uid: u32,
+        gid: u32,
+        create_time: Timespec,
+    ) -> Self::CreateInodeFut<'_> {
+        let inode_id = self.id_allocator.alloc();
+        future::ready(Ok(RamFs::insert_inode(
+            self,
+            inode_id,
+            mode,
+            uid,
+            gid,
+            create_time,
+        )))
+    }
+
+    fn load_inode(&self, inode_id: usize) -> Self::LoadInodeFut<'_> {
+        future::ready(Ok(RamFs::load_inode(self, inode_id)))
+    }
+
+    fn sync(&self) -> Self::SyncFut<'_> {
+        future::ready(Ok(()))
+    }
+
+    /// Get the BlkDevice's block_size.
+    fn blk_size(&self) -> u32 {
+        0
+    }
+
+    /// Get the BlkDevice's block count.
+    fn blk_count(&self) -> usize {
+        0
+    }
+
+    fn name(&self) -> &'static str {
+        "ramfs"
+    }
+}
+
+struct IdAllocator {
+    last_id: AtomicUsize,
+}
+
+impl IdAllocator {
+    fn new(start: usize) -> Self {
+        Self {
+            last_id: AtomicUsize::new(start - 1),
+        }
+    }
+
+    fn alloc(&self) -> usize {
+        self.last_id.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    #[allow(dead_code)]
+    fn dealloc(&self, _id: usize) -> bool {
+        true
+    }
+}
+
+struct InodeInner {
+    metadata: vfs::Metadata,
+    content: Content,
+}
+
+struct DirEntry {
+    pub inode_id: usize,
+    pub file_type: vfs::FileType,
+}
+
+enum Content {
+    Dir(BTreeMap<DirEntryName, DirEntry>),
+    File(Vec<u8>),
+}
+
+/// RamFs Inode
+pub struct Inode<I> {
+    inode_id: usize,
+    inner: RwLockIrq<I, InodeInner>,
+    fs: Arc<RamFs<I>>,
+}
+
+impl<I: Irq + 'static> Inode<I> {
+    /// Sets the size reported by the metadata, leaving the content as is.
+    #[cfg(test)]
+    pub(crate) fn set_metadata_size(&self, size: u64) {
+        self.inner.write().metadata.size = size;
+    }
+
+    fn lookup_raw<'a>(&'a self, name: &'a FsStr) -> vfs::Result<Option<vfs::RawDirEntry>> {
+        let inner = self.inner.read();
+        match &inner.content {
+            Content::Dir(dentries) => Ok(dentries.get(name).map(|dentry| vfs::RawDirEntry {
+                inode_id: dentry.inode_id,
+                name: Box::new(DirEntryName::from(name)),
+                file_type: Some(dentry.file_type.clone()),
+            })),
+            Content::File(_) => Err(vfs::Error::NotDir),
+        }
+    }
+
+    fn append(
+        &self,
+        dir_entry_name: DirEntryName,
+        inode_id: usize,
+        file_type: Option<vfs::FileType>,
+    ) -> vfs::Result<()> {
+        let mut inner = self.inner.write();
+        match &mut inner.content {
+            Content::Dir(dentries) => dentries
+                .try_insert(
+                    dir_entry_name,
+                    DirEntry {
+                        inode_id,
+                        file_type: file_type.unwrap(),
+                    },
+                )
+                .map_err(|_| vfs::Error::EntryExist)
+                .map(|_| ()),
+            Content::File(_) => Err(vfs::Error::NotDir),
+        }
+    }
+
+    fn unlink(&self) -> vfs::Result<()> {
+        let mut inner = self.inner.write();
+        if inner.metadata.links_count > 0 {
+            inner.metadata.links_count -= 1;
+        }
+
+        if inner.metadata.links_count == 0 {
+            self.fs.remove_inode(self.inode_id);
+        }
+        Ok(())
+    }
+}
+
+impl<I> NotDynInode for Arc<Inode<I>> {}
+
+impl<I: Irq + 'static> vfs::Inode for Arc<Inode<I>> {
+    type FS = Arc<RamFs<I>>;
+
+    type MetadataFut<'a> = future::Ready<vfs::Result<vfs::Metadata>>;
+    type ChownFut<'a> = future::Ready<vfs::Result<()>>;
+    type ChmodFut<'a> = future::Ready<vfs::Result<()>>;
+    type SetTimesFut<'a> = future::Ready<vfs::Result<()>>;
+    type LinkFut<'a> = future::Ready<vfs::Result<()>>;
+    type UnlinkFut<'a> = future::Ready<vfs::Result<()>>;
+    type ReadAtFut<'a> = future::Ready<vfs::Result<usize>>;
+    type WriteAtFut<'a> = future::Ready<vfs::Result<usize>>;
+    type SyncFut<'a> = future::Ready<vfs::Result<()>>;
+    type AppendDotFut<'a> = future::Ready<vfs::Result<()>>;
+    type LookupRawFut<'a> = future::Ready<vfs::Result<Option<vfs::RawDirEntry>>>;
+    type LookupFut<'a> = future::Ready<vfs::Result<Option<vfs::DirEntry<Self::FS>>>>;
+    type AppendFut<'a> = future::Ready<vfs::Result<()>>;
+    type RemoveFut<'a> = future::Ready<vfs::Result<Option<vfs::RawDirEntry>>>;
+    type LsRawFut<'a> = future::Ready<vfs::Result<Vec<vfs::RawDirEntry>>>;
+    type LsFut<'a> = future::Ready<vfs::Result<Vec<vfs::DirEntry<Self::FS>>>>;
+    type IOCtlFut<'a> = future::Ready<vfs::Result<()>>;
+    type PollFut<'a> = future::Ready<vfs::PollEvents>;
+    type GetXattrFut<'a> = future::Ready<vfs::Result<Option<Vec<u8>>>>;
+    type SetXattrFut<'a> = future::Ready<vfs::Result<()>>;
+    type ListXattrFut<'a> = future::Ready<vfs::Result<Vec<Vec<u8>>>>;
+    type RemoveXattrFut<'a> = future::Ready<vfs::Result<Option<Vec<u8>>>>;
+
+    fn id(&self) -> usize {
+        self.inode_id
+    }
+
+    fn metadata(&self) -> Self::MetadataFut<'_> {
+        future::ready(Ok(self.inner.read().metadata.clone()))
+    }
+
+    fn chown(&self, uid: u32, gid: u32) -> Self::ChownFut<'_> {
+        let mut inner = self.inner.write();
+        inner.metadata.uid = uid;
+        inner.metadata.gid = gid;
+        future::ready(Ok(()))
+    }
+
+    fn chmod(&self, mode: vfs::Mode) -> Self::ChmodFut<'_> {
+        let mut inner = self.inner.write();
+        inner.metadata.mode = mode;
+        future::ready(Ok(()))
+    }
+
+    fn set_times(
+        &self,
+        atime: Option<Timespec>,
+        mtime: Option<Timespec>,
+        ctime: Option<Timespec>,
+    ) -> Self::SetTimesFut<'_> {
+        let mut inner = self.inner.write();
+        let metadata = &mut inner.metadata;
+        if let Some(atime) = atime {
+            metadata.atime = atime;
+        }
+        if let Some(mtime) = mtime {
+            metadata.mtime = mtime;
+        }
+        if let Some(ctime) = ctime {
+            metadata.ctime = ctime;
+        }
+        future::ready(Ok(()))
+    }
+
+    fn link(&self) -> Self::LinkFut<'_> {
+        let mut inner = self.inner.write();
+        if inner.metadata.links_count > 0 {
+            inner.metadata.links_count += 1;
+        }
+        future::ready(Ok(()))
+    }
+
+    fn unlink(&self) -> Self::UnlinkFut<'_> {
+        future::ready(Inode::unlink(self))
+    }
+
+    fn read_at<'a>(&'a self, offset: u64, buf: &'a mut [u8]) -> Self::ReadAtFut<'a> {
+        let mut inner = self.inner.write();
+        let inner = &mut *inner;
+        future::ready(match &inner.content {
+            Content::Dir(_) => Err(vfs::Error::Unsupport),
+            Content::File(data) => {
+                let len = data.len();
+                let start = cmp::min(len, offset as usize);
+                let end = len.min(start + buf.len());
+                let src = &data[start..end];
+                buf[..src.len()].copy_from_slice(src);
+                inner.metadata.atime = (self.fs.clock)();
+                Ok(src.len())
+            }
+        })
+    }
+
+    fn write_at<'a>(&'a self, offset: u64, src: &'a [u8]) -> Self::WriteAtFut<'a> {
+        let mut inner = self.inner.write();
+        let inner = &mut *inner;
+        future::ready(match &mut inner.content {
+            Content::Dir(_) => Err(vfs::Error::Unsupport),
+            Content::File(data) => {
+                let offset = offset as usize;
+                let end = offset + src.len();
+                if end > data.len() {
+                    data.resize(end, 0);
+                }
+                data[offset..end].copy_from_slice(src);
+                inner.metadata.size = data.len() as u64;
+                inner.metadata.mtime = (self.fs.clock)();
+                Ok(src.len())
+            }
+        })
+    }
+
+    fn sync(&self) -> Self::SyncFut<'_> {
+        future::ready(Ok(()))
+    }
+
+    fn append_dot(&self, parent_inode_id: usize) -> Self::AppendDotFut<'_> {
+        future::ready(
+            Inode::append(
+                self,
+                "..".as_bytes().into(),
+                parent_inode_id,
+                Some(vfs::FileType::Dir),
+            )
+            .and_then(|_| {
+                Inode::append(
+                    self,
+                    ".".as_bytes().into(),
+                    self.inode_id,
+                    Some(vfs::FileType::Dir),
+                )
+            }),
+        )
+    }
+
+    fn lookup_raw<'a>(&'a self, name: &'a FsStr) -> Self::LookupRawFut<'a> {
+        future::ready(Inode::lookup_raw(self, name))
+    }
+
+    fn lookup<'a>(&'a self, name: &'a FsStr) -> Self::LookupFut<'a> {
+        future::ready(Inode::lookup_raw(self, name).map(|opt_raw| {
+            opt_raw.map(|raw| vfs::DirEntry {
+                raw,
+                fs: self.fs.clone(),
+            })
+        }))
+    }
+
+    fn append(
+        &self,
+        dir_entry_name: DirEntryName,
+        inode_id: usize,
+        file_type: Option<vfs::FileType>,
+    ) -> Self::AppendFut<'_> {
+        future::ready(Inode::append(self, dir_entry_name, inode_id, file_type))
+    }
+
+    fn remove<'a>(&'a self, dir_entry_name: &'a FsStr) -> Self::RemoveFut<'a> {
+        let mut inner = self.inner.write();
+        future::ready(match &mut inner.content {
+            // The link the entry held is dropped by the caller, as `mv` keeps it.
+            Content::Dir(dentries) => {
+                Ok(dentries
+                    .remove(dir_entry_name)
+                    .map(|dentry| vfs::RawDirEntry {
+                        inode_id: dentry.inode_id,
+                        name: Box::new(dir_entry_name.into()),
+                        file_type: Some(dentry.file_type),
+                    }))
+            }
+            Content::File(_) => Err(vfs::Error::NotDir),
+        })
+    }
+
+    fn ls_raw(&self) -> Self::LsRawFut<'_> {
+        let inner = self.inner.read();
+        future::ready(match &inner.content {
+            Content::Dir(dentries) => Ok(dentries.iter().map(Into::into).collect()),
+            Content::File(_) => Err(vfs::Error::NotDir),
+        })
+    }
+
+    fn ls(&self) -> Self::LsFut<'_> {
+        let inner = self.inner.read();
+
+        future::ready(match &inner.content {
+            Content::Dir(dentries) => Ok(dentries
+                .iter()
+                .map(|entry| vfs::DirEntry {
+                    raw: entry.into(),
+                    fs: self.fs.clone(),
+                })
+                .collect()),
+            Content::File(_) => Err(vfs::Error::NotDir),
+        })
+    }
+
+    fn ioctl(&self, _cmd: u32, _arg: usize) -> Self::IOCtlFut<'_> {
+        future::ready(Err(vfs::Error::Unsupport))
+    }
+
+    fn poll(&self, events: vfs::PollEvents) -> Self::PollFut<'_> {
+        future::ready(events.never_block())
+    }
+
+    fn getxattr<'a>(&'a self, _name: &'a [u8]) -> Self::GetXattrFut<'a> {
+        future::ready(Err(vfs::Error::Unsupport))
+    }
+
+    fn setxattr<'a>(&'a self, _name: &'a [u8], _value: &'a [u8]) -> Self::SetXattrFut<'a> {
+        future::ready(Err(vfs::Error::Unsupport))
+    }
+
+    fn listxattr(&self) -> Self::ListXattrFut<'_> {
+        future::ready(Err(vfs::Error::Unsupport))
+    }
+
+    fn removexattr<'a>(&'a self, _name: &'a [u8]) -> Self::RemoveXattrFut<'a> {
+        future::ready(Err(vfs::Error::Unsupport))
+    }
+}
+
+impl From<(&DirEntryName, &DirEntry)> for vfs::RawDirEntry {
+    fn from((name, dentry): (&DirEntryName, &DirEntry)) -> Self {
+        Self {
+            inode_id: dentry.inode_id,
+            name: Box::new(name.clone()),
+            file_type: Some(dentry.file_type.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::sync::atomic::{AtomicI64, Ordering};
+
+    use alloc::sync::Arc;
+    use time::Timespec;
+    use tokio_test::block_on;
+
+    use crate::{
+        mock::{create, ram_vfs, TestInode, TestIrq},
+        ram_fs::{Content, RamFs},
+        Inode, Mode, Vfs,
+    };
+
+    fn data_len(inode: &TestInode) -> usize {
+        match &inode.inner.read().content {
+            Content::File(data) => data.len(),
+            Content::Dir(_) => panic!("not a file"),
+        }
+    }
+
+    #[test]
+    fn write_past_the_end_grows_the_file_to_its_end() {
+        let (vfs, root) = ram_vfs();
+        let file = block_on(create(&vfs, &root, "f", Mode::TY_REG));
+        assert_eq!(block_on(file.write_at(100, b"abc")).unwrap(), 3);
+        assert_eq!(data_len(&file), 103);
+        assert_eq!(block_on(file.metadata()).unwrap().size, 103);
+
+        // The gap reads as zeros.
+        let mut buf = [0xff; 103];
+        assert_eq!(block_on(file.read_at(0, &mut buf)).unwrap(), 103);
+        assert!(buf[..100].iter().all(|&byte| byte == 0));
+        assert_eq!(&buf[100..], b"abc");
+
+        // A write inside the file does not grow it.
+        block_on(file.write_at(10, b"xy")).unwrap();
+        assert_eq!(data_len(&file), 103);
+    }
+
+    #[test]
+    fn appends_grow_the_file_by_their_length() {
+        let (vfs, root) = ram_vfs();
+        let file = block_on(create(&vfs, &root, "f", Mode::TY_REG));
+        for len in 0..1000 {
+            block_on(file.write_at(len, &[len as u8])).unwrap();
+        }
+        assert_eq!(data_len(&file), 1000);
+        assert_eq!(block_on(file.metadata()).unwrap().size, 1000);
+    }
+
+    static NOW: AtomicI64 = AtomicI64::new(1);
+
+    /// A clock that advances by a second on every reading.
+    fn tick() -> Timespec {
+        Timespec::new(NOW.fetch_add(1, Ordering::Relaxed), 0)
+    }
+
+    #[test]
+    fn writes_update_the_size_and_the_times() {
+        let fs = Arc::new(RamFs::<TestIrq>::with_clock(tick));
+        let root = fs.create_root(Mode::TY_DIR | Mode::PERM_RWX_USR, tick());
+        let vfs = Vfs::new(fs);
+        let file = block_on(create(&vfs, &root, "f", Mode::TY_REG));
+        let created = block_on(file.metadata()).unwrap();
+        assert_eq!(created.size, 0);
+
+        block_on(file.write_at(0, b"hello")).unwrap();
+        let written = block_on(file.metadata()).unwrap();
+        assert_eq!(written.size, 5);
+        assert!(written.mtime > created.mtime);
+        assert_eq!(written.atime, created.atime);
+
+        let mut buf = [0; 5];
+        block_on(file.read_at(0, &mut buf)).unwrap();
+        let read = block_on(file.metadata()).unwrap();
+        assert!(read.atime > written.mtime);
+        assert_eq!(read.mtime, written.mtime);
+        assert_eq!(read.size, 5);
+    }
+}
@@ -0,0 +1,322 @@
+//! Connected pairs of UNIX domain stream sockets, as created by `socketpair`.
+
+use core::{
+    future::{ready, Future},
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+
+use alloc::{boxed::Box, collections::VecDeque, sync::Arc};
+use futures_util::future::BoxFuture;
+use spinlock::{Irq, MutexIrq};
+
+use crate as vfs;
+use crate::{
+    dev_fs::DevInode,
+    ioctl,
+    wait_queue::{self, WaitQueue},
+};
+
+/// Bytes buffered in each direction before writers wait for the reader.
+const CHANNEL_CAPACITY: usize = 64 * 1024;
+
+// Socket inodes live in no filesystem, their ids only tell endpoints apart.
+static NEXT_SOCKET_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// The bytes in flight from one endpoint to the other.
+#[derive(Default)]
+struct Channel {
+    buf: VecDeque<u8>,
+    // Set once the endpoint writing to the channel is gone.
+    writer_closed: bool,
+    // Set once the endpoint reading from the channel is gone.
+    reader_closed: bool,
+    // Waiting for data.
+    readers: WaitQueue,
+    // Waiting for room.
+    writers: WaitQueue,
+}
+
+impl Channel {
+    fn room(&self) -> usize {
+        CHANNEL_CAPACITY - self.buf.len()
+    }
+}
+
+/// One end of a connected socket pair.
+/// Bytes written to an end are read from its peer,
+/// reads see end of file once the peer is closed and its data is consumed.
+pub struct SocketEnd<I: Irq> {
+    id: vfs::InodeId,
+    // `channels[side]` is read by this end, the other is written.
+    channels: Arc<[MutexIrq<I, Channel>; 2]>,
+    side: usize,
+}
+
+impl<I: Irq> SocketEnd<I> {
+    /// Creates two connected endpoints.
+    pub fn pair() -> (Self, Self) {
+        let channels = Arc::new([
+            MutexIrq::new(Channel::default()),
+            MutexIrq::new(Channel::default()),
+        ]);
+        let end = |side| Self {
+            id: NEXT_SOCKET_ID.fetch_add(1, Ordering::Relaxed),
+            channels: channels.clone(),
+            side,
+        };
+        (end(0), end(1))
+    }
+
+    /// The number of bytes that can be read without blocking.
+    pub fn nread(&self) -> usize {
+        self.rx().lock().buf.len()
+    }
+
+    /// Reads what the peer wrote, waiting for at least one byte or for the peer to close.
+    pub fn read<'a>(&'a self, buf: &'a mut [u8]) -> ReadFut<'a, I> {
+        ReadFut {
+            end: self,
+            buf,
+            key: wait_queue::key(),
+        }
+    }
+
+    /// Writes what fits in the channel to the peer, waiting for room for at least one byte.
+    pub fn write<'a>(&'a self, src: &'a [u8]) -> WriteFut<'a, I> {
+        WriteFut {
+            end: self,
+            src,
+            key: wait_queue::key(),
+        }
+    }
+
+    pub fn poll(&self, events: vfs::PollEvents) -> PollFut<'_, I> {
+        PollFut {
+            end: self,
+            events,
+            key: wait_queue::key(),
+        }
+    }
+
+    fn rx(&self) -> &MutexIrq<I, Channel> {
+        &self.channels[self.side]
+    }
+
+    fn tx(&self) -> &MutexIrq<I, Channel> {
+        &self.channels[1 - self.side]
+    }
+}
+
+impl<I: Irq> Drop for SocketEnd<I> {
+    fn drop(&mut self) {
+        let mut rx = self.rx().lock();
+        rx.reader_closed = true;
+        rx.buf.clear();
+        rx.writers.wake_all();
+        drop(rx);
+
+        let mut tx = self.tx().lock();
+        tx.writer_closed = true;
+        tx.readers.wake_all();
+    }
+}
+
+impl<I: Irq> DevInode for SocketEnd<I> {
+    fn id(&self) -> vfs::InodeId {
+        self.id
+    }
+
+    fn metadata(&self) -> BoxFuture<'_, vfs::Result<vfs::Metadata>> {
+        Box::pin(ready(Ok(vfs::Metadata {
+            mode: vfs::Mode::TY_SOCK | vfs::Mode::PERM_RW_USR,
+            links_count: 1,
+            ..Default::default()
+        })))
+    }
+
+    fn read_at<'a>(&'a self, _offset: u64, buf: &'a mut [u8]) -> BoxFuture<'a, vfs::Result<usize>> {
+        Box::pin(self.read(buf))
+    }
+
+    fn write_at<'a>(&'a self, _offset: u64, src: &'a [u8]) -> BoxFuture<'a, vfs::Result<usize>> {
+        Box::pin(self.write(src))
+    }
+
+    fn sync(&self) -> BoxFuture<'_, vfs::Result<()>> {
+        Box::pin(ready(Ok(())))
+    }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> BoxFuture<'_, vfs::Result<()>> {
+        Box::pin(ready(match cmd {
+            ioctl::CMD_FIONREAD => {
+                let argp = arg as *mut i32;
+                unsafe {
+                    *argp = self.nread() as i32;
+                }
+                Ok(())
+            }
+            _ => Err(vfs::Error::Unsupport),
+        }))
+    }
+
+    fn poll(&self, events: vfs::PollEvents) -> BoxFuture<'_, vfs::PollEvents> {
+        Box::pin(SocketEnd::poll(self, events))
+    }
+}
+
+pub struct ReadFut<'a, I: Irq> {
+    end: &'a SocketEnd<I>,
+    buf: &'a mut [u8],
+    key: usize,
+}
+
+impl<I: Irq> Future for ReadFut<'_, I> {
+    type Output = vfs::Result<usize>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut rx = this.end.rx().lock();
+        if this.buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        if rx.buf.is_empty() {
+            if rx.writer_closed {
+                return Poll::Ready(Ok(0));
+            }
+            rx.readers.register(this.key, cx.waker());
+            return Poll::Pending;
+        }
+
+        let len = this.buf.len().min(rx.buf.len());
+        for (dst, src) in this.buf.iter_mut().zip(rx.buf.drain(..len)) {
+            *dst = src;
+        }
+        rx.writers.wake_all();
+        Poll::Ready(Ok(len))
+    }
+}
+
+impl<I: Irq> Drop for ReadFut<'_, I> {
+    fn drop(&mut self) {
+        self.end.rx().lock().readers.unregister(self.key);
+    }
+}
+
+pub struct WriteFut<'a, I: Irq> {
+    end: &'a SocketEnd<I>,
+    src: &'a [u8],
+    key: usize,
+}
+
+impl<I: Irq> Future for WriteFut<'_, I> {
+    type Output = vfs::Result<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut tx = self.end.tx().lock();
+        if tx.reader_closed {
+            return Poll::Ready(Err(vfs::Error::BrokenPipe));
+        }
+        if self.src.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        // Waits only while nothing fits, a partial write is returned as is.
+        let len = self.src.len().min(tx.room());
+        if len == 0 {
+            tx.writers.register(self.key, cx.waker());
+            return Poll::Pending;
+        }
+
+        tx.buf.extend(&self.src[..len]);
+        tx.readers.wake_all();
+        Poll::Ready(Ok(len))
+    }
+}
+
+impl<I: Irq> Drop for WriteFut<'_, I> {
+    fn drop(&mut self) {
+        self.end.tx().lock().writers.unregister(self.key);
+    }
+}
+
+pub struct PollFut<'a, I: Irq> {
+    end: &'a SocketEnd<I>,
+    events: vfs::PollEvents,
+    key: usize,
+}
+
+impl<I: Irq> Future for PollFut<'_, I> {
+    type Output = vfs::PollEvents;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Hold both channels until the wakers are registered, so no wakeup is missed.
+        // They are always locked in the same order, the peer may be polled at the same time.
+        let [first, second] = &*self.end.channels;
+        let (mut first, mut second) = (first.lock(), second.lock());
+        let (rx, tx) = match self.end.side {
+            0 => (&mut *first, &mut *second),
+            _ => (&mut *second, &mut *first),
+        };
+        let mut ready = vfs::PollEvents::empty();
+        if !rx.buf.is_empty() || rx.writer_closed {
+            ready |= vfs::PollEvents::IN;
+        }
+        if rx.writer_closed {
+            ready |= vfs::PollEvents::HUP;
+        }
+        // A write to a closed peer fails right away.
+        if tx.room() > 0 || tx.reader_closed {
+            ready |= vfs::PollEvents::OUT;
+        }
+        let ready = ready & self.events;
+        if !ready.is_empty() {
+            return Poll::Ready(ready);
+        }
+        rx.readers.register(self.key, cx.waker());
+        tx.writers.register(self.key, cx.waker());
+        Poll::Pending
+    }
+}
+
+impl<I: Irq> Drop for PollFut<'_, I> {
+    fn drop(&mut self) {
+        self.end.rx().lock().readers.unregister(self.key);
+        self.end.tx().lock().writers.unregister(self.key);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio_test::block_on;
+
+    use super::SocketEnd;
+    use crate::{mock::TestIrq, Error};
+
+    #[test]
+    fn each_end_reads_what_the_other_writes() {
+        let (end0, end1) = SocketEnd::<TestIrq>::pair();
+        assert_eq!(block_on(end0.write(b"ping")).unwrap(), 4);
+        assert_eq!(block_on(end1.write(b"pong!")).unwrap(), 5);
+
+        let mut buf = [0; 8];
+        assert_eq!(block_on(end1.read(&mut buf)).unwrap(), 4);
+        assert_eq!(&buf[..4], b"ping");
+        assert_eq!(block_on(end0.read(&mut buf[..3])).unwrap(), 3);
+        assert_eq!(end0.nread(), 2);
+        assert_eq!(block_on(end0.read(&mut buf)).unwrap(), 2);
+        assert_eq!(&buf[..2], b"g!");
+    }
+
+    #[test]
+    fn closing_an_end_gives_eof_after_its_data_and_breaks_the_pipe() {
+        let (end0, end1) = SocketEnd::<TestIrq>::pair();
+        assert_eq!(block_on(end1.write(b"bye")).unwrap(), 3);
+        drop(end1);
+
+        let mut buf = [0; 8];
+        assert_eq!(block_on(end0.read(&mut buf)).unwrap(), 3);
+        assert_eq!(block_on(end0.read(&mut buf)).unwrap(), 0);
+        assert!(matches!(block_on(end0.write(b"x")), Err(Error::BrokenPipe)));
+    }
+}
@@ -0,0 +1,221 @@
+//! The `struct stat` of `fstatat` and the `struct statx` of `statx`, filled from the metadata of an inode.
+
+use time::Timespec;
+
+use crate::{InodeId, Metadata};
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct Stat {
+    /// ID of device containing file
+    dev: u64,
+    /// File serial number
+    ino: u64,
+    /// Mode of file
+    mode: u32,
+    /// Number of hard links
+    nlink: u32,
+    /// User ID of the file
+    uid: u32,
+    /// Group ID of the file
+    gid: u32,
+    /// Device ID
+    rdev: u64,
+    /// padding
+    _pad: u64,
+    /// file size, in bytes
+    size: u64,
+    /// optimal blocksize for I/O
+    blk_size: u32,
+    /// padding2
+    _pad2: u32,
+    /// blocks allocated for file
+    blk_cnt: u32,
+    /// time of last access
+    atime: Timespec,
+    /// time of last data modification
+    mtime: Timespec,
+    /// time of last status change
+    ctime: Timespec,
+}
+
+impl Stat {
+    /// The `struct stat` of the inode `ino` described by `metadata`.
+    pub fn new(ino: InodeId, metadata: &Metadata) -> Self {
+        Self {
+            dev: 0,
+            ino: ino as u64,
+            mode: metadata.mode.bits() as u32,
+            nlink: metadata.links_count as u32,
+            uid: metadata.uid,
+            gid: metadata.gid,
+            rdev: 0,
+            _pad: 0,
+            size: metadata.size,
+            blk_size: metadata.blk_size,
+            _pad2: 0,
+            blk_cnt: metadata.blk_count as u32,
+            atime: metadata.atime,
+            mtime: metadata.mtime,
+            ctime: metadata.ctime,
+        }
+    }
+}
+
+/// A timestamp of `struct statx`.
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct StatxTimestamp {
+    sec: i64,
+    nsec: u32,
+    _reserved: i32,
+}
+
+impl From<Timespec> for StatxTimestamp {
+    fn from(ts: Timespec) -> Self {
+        Self {
+            sec: ts.sec,
+            nsec: ts.nsec as u32,
+            _reserved: 0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct Statx {
+    /// Mask of the fields that were filled in
+    mask: u32,
+    /// optimal blocksize for I/O
+    blk_size: u32,
+    /// Extra file attribute indicators
+    attributes: u64,
+    /// Number of hard links
+    nlink: u32,
+    /// User ID of the file
+    uid: u32,
+    /// Group ID of the file
+    gid: u32,
+    /// Mode of file
+    mode: u16,
+    _spare0: u16,
+    /// File serial number
+    ino: u64,
+    /// file size, in bytes
+    size: u64,
+    /// blocks allocated for file
+    blocks: u64,
+    /// Mask of the supported `attributes`
+    attributes_mask: u64,
+    /// time of last access
+    atime: StatxTimestamp,
+    /// time of creation
+    btime: StatxTimestamp,
+    /// time of last status change
+    ctime: StatxTimestamp,
+    /// time of last data modification
+    mtime: StatxTimestamp,
+    /// Device ID, if the file is a device
+    rdev_major: u32,
+    rdev_minor: u32,
+    /// ID of device containing file
+    dev_major: u32,
+    dev_minor: u32,
+    _spare2: [u64; 14],
+}
+
+impl Statx {
+    /// The `struct statx` of the inode `ino` described by `metadata`.
+    /// The inodes do not track their creation time, so `BTIME` is never in the mask.
+    pub fn new(ino: InodeId, metadata: &Metadata) -> Self {
+        Self {
+            // Every basic field is always available, whatever was asked for.
+            mask: StatxMask::BASIC_STATS.bits(),
+            blk_size: metadata.blk_size,
+            nlink: metadata.links_count as u32,
+            uid: metadata.uid,
+            gid: metadata.gid,
+            mode: metadata.mode.bits(),
+            ino: ino as u64,
+            size: metadata.size,
+            blocks: metadata.blk_count as u64,
+            atime: metadata.atime.into(),
+            ctime: metadata.ctime.into(),
+            mtime: metadata.mtime.into(),
+            ..Default::default()
+        }
+    }
+}
+
+bitflags! {
+    pub struct StatxMask: u32 {
+        const TYPE = 0x0001;
+        const MODE = 0x0002;
+        const NLINK = 0x0004;
+        const UID = 0x0008;
+        const GID = 0x0010;
+        const ATIME = 0x0020;
+        const MTIME = 0x0040;
+        const CTIME = 0x0080;
+        const INO = 0x0100;
+        const SIZE = 0x0200;
+        const BLOCKS = 0x0400;
+        const BASIC_STATS = 0x07ff;
+        const BTIME = 0x0800;
+        const RESERVED = 0x8000_0000;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use time::Timespec;
+
+    use super::{Stat, Statx, StatxMask};
+    use crate::{Metadata, Mode};
+
+    fn metadata() -> Metadata {
+        Metadata {
+            mode: Mode::TY_REG | Mode::PERM_RW_USR,
+            uid: 1000,
+            gid: 100,
+            size: 5000,
+            atime: Timespec { sec: 10, nsec: 1 },
+            ctime: Timespec { sec: 20, nsec: 2 },
+            mtime: Timespec { sec: 30, nsec: 3 },
+            links_count: 2,
+            blk_size: 512,
+            blk_count: 10,
+        }
+    }
+
+    // Compares a `Timespec` of `struct stat` with a timestamp of `struct statx`.
+    fn same_time(ts: Timespec, statx: &super::StatxTimestamp) -> bool {
+        ts.sec == statx.sec && ts.nsec as u32 == statx.nsec
+    }
+
+    #[test]
+    fn statx_reports_what_fstatat_does() {
+        let (stat, statx) = (Stat::new(7, &metadata()), Statx::new(7, &metadata()));
+        assert_eq!((stat.ino, statx.ino), (7, 7));
+        assert_eq!(stat.mode, statx.mode as u32);
+        assert_eq!(statx.mode, (Mode::TY_REG | Mode::PERM_RW_USR).bits());
+        assert_eq!((stat.nlink, statx.nlink), (2, 2));
+        assert_eq!((stat.uid, stat.gid), (statx.uid, statx.gid));
+        assert_eq!((stat.size, statx.size), (5000, 5000));
+        assert_eq!((stat.blk_size, statx.blk_size), (512, 512));
+        assert_eq!(stat.blk_cnt as u64, statx.blocks);
+        assert!(same_time(stat.atime, &statx.atime));
+        assert!(same_time(stat.mtime, &statx.mtime));
+        assert!(same_time(stat.ctime, &statx.ctime));
+        assert_eq!(statx.mtime.sec, 30);
+    }
+
+    #[test]
+    fn btime_is_left_out_of_the_mask() {
+        let statx = Statx::new(7, &metadata());
+        let mask = StatxMask::from_bits_truncate(statx.mask);
+        assert!(mask.contains(StatxMask::BASIC_STATS));
+        assert!(!mask.contains(StatxMask::BTIME));
+        assert_eq!((statx.btime.sec, statx.btime.nsec), (0, 0));
+    }
+}
@@ -0,0 +1,227 @@
+//! The input of a terminal: the bytes received and not read yet, and the readers waiting for them.
+
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use alloc::collections::VecDeque;
+use spinlock::{Irq, MutexIrq};
+
+use crate as vfs;
+use crate::wait_queue::{self, WaitQueue};
+
+struct Inner {
+    buf: VecDeque<u8>,
+    wakers: WaitQueue,
+}
+
+pub struct TtyInput<I: Irq> {
+    inner: MutexIrq<I, Inner>,
+}
+
+impl<I: Irq> Default for TtyInput<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I: Irq> TtyInput<I> {
+    pub fn new() -> Self {
+        Self {
+            inner: MutexIrq::new(Inner {
+                buf: VecDeque::new(),
+                wakers: WaitQueue::new(),
+            }),
+        }
+    }
+
+    /// Queues the received byte `c` and wakes the readers.
+    pub fn push(&self, c: u8) {
+        let mut inner = self.inner.lock();
+        inner.buf.push_back(c);
+        inner.wakers.wake_all();
+    }
+
+    /// Queues the bytes `getchar` returns until it returns `None`,
+    /// as the receive interrupt does with the FIFO of the UART.
+    pub fn receive(&self, mut getchar: impl FnMut() -> Option<u8>) {
+        while let Some(c) = getchar() {
+            self.push(c);
+        }
+    }
+
+    /// The number of bytes that can be read without blocking.
+    pub fn len(&self) -> usize {
+        self.inner.lock().buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Reads one byte into `buf`, waiting for one to be received.
+    pub fn read<'a>(&'a self, buf: &'a mut [u8]) -> ReadFut<'a, I> {
+        ReadFut {
+            input: self,
+            buf,
+            key: wait_queue::key(),
+        }
+    }
+
+    /// Waits for one of `events` to be ready, output is always ready.
+    pub fn poll(&self, events: vfs::PollEvents) -> PollFut<'_, I> {
+        PollFut {
+            input: self,
+            events,
+            key: wait_queue::key(),
+        }
+    }
+}
+
+pub struct ReadFut<'a, I: Irq> {
+    input: &'a TtyInput<I>,
+    buf: &'a mut [u8],
+    key: usize,
+}
+
+impl<I: Irq> Future for ReadFut<'_, I> {
+    type Output = vfs::Result<usize>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        // Register under the same lock, so a `push` in between is not missed.
+        let mut inner = this.input.inner.lock();
+        if this.buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        match inner.buf.pop_front() {
+            Some(c) => {
+                this.buf[0] = c;
+                Poll::Ready(Ok(1))
+            }
+            None => {
+                inner.wakers.register(this.key, cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<I: Irq> Drop for ReadFut<'_, I> {
+    fn drop(&mut self) {
+        self.input.inner.lock().wakers.unregister(self.key);
+    }
+}
+
+pub struct PollFut<'a, I: Irq> {
+    input: &'a TtyInput<I>,
+    events: vfs::PollEvents,
+    key: usize,
+}
+
+impl<I: Irq> Future for PollFut<'_, I> {
+    type Output = vfs::PollEvents;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Output goes straight to the console, so the tty is always writable.
+        let mut ready = vfs::PollEvents::OUT;
+        let mut inner = self.input.inner.lock();
+        if !inner.buf.is_empty() {
+            ready |= vfs::PollEvents::IN;
+        }
+        let ready = ready & self.events;
+        if !ready.is_empty() {
+            return Poll::Ready(ready);
+        }
+        inner.wakers.register(self.key, cx.waker());
+        Poll::Pending
+    }
+}
+
+impl<I: Irq> Drop for PollFut<'_, I> {
+    fn drop(&mut self) {
+        self.input.inner.lock().wakers.unregister(self.key);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::{
+        future::Future,
+        pin::Pin,
+        sync::atomic::{AtomicUsize, Ordering},
+        task::{Context, Poll, Waker},
+    };
+    use std::{sync::Arc, task::Wake};
+
+    use super::TtyInput;
+    use crate::{mock::TestIrq, PollEvents};
+
+    #[derive(Default)]
+    struct CountWaker(AtomicUsize);
+
+    impl Wake for CountWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn a_blocked_read_completes_when_the_interrupt_delivers_a_byte() {
+        let input = TtyInput::<TestIrq>::new();
+        let mut buf = [0; 4];
+        let mut read = input.read(&mut buf);
+        let count = Arc::new(CountWaker::default());
+        let waker = Waker::from(count.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        assert!(Pin::new(&mut read).poll(&mut cx).is_pending());
+        assert_eq!(count.0.load(Ordering::SeqCst), 0);
+
+        // The FIFO of the UART holds two bytes.
+        let mut fifo = b"ab".iter().copied();
+        input.receive(|| fifo.next());
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+        assert!(matches!(
+            Pin::new(&mut read).poll(&mut cx),
+            Poll::Ready(Ok(1))
+        ));
+        drop(read);
+        assert_eq!(buf[0], b'a');
+        assert_eq!(input.len(), 1);
+    }
+
+    #[test]
+    fn poll_is_always_writable_and_readable_once_a_byte_is_received() {
+        let input = TtyInput::<TestIrq>::new();
+        let count = Arc::new(CountWaker::default());
+        let waker = Waker::from(count.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let mut poll = input.poll(PollEvents::IN | PollEvents::OUT);
+        assert_eq!(
+            Pin::new(&mut poll).poll(&mut cx),
+            Poll::Ready(PollEvents::OUT)
+        );
+
+        let mut poll = input.poll(PollEvents::IN);
+        assert!(Pin::new(&mut poll).poll(&mut cx).is_pending());
+        input.push(b'a');
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            Pin::new(&mut poll).poll(&mut cx),
+            Poll::Ready(PollEvents::IN)
+        );
+
+        // A dropped poll is not woken any more.
+        let mut buf = [0; 1];
+        assert!(tokio_test::block_on(input.read(&mut buf)).is_ok());
+        let mut poll = input.poll(PollEvents::IN);
+        assert!(Pin::new(&mut poll).poll(&mut cx).is_pending());
+        drop(poll);
+        input.push(b'b');
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,408 @@
+use core::task::{Context, Poll};
+
+use alloc::vec::Vec;
+use futures_util::future::BoxFuture;
+use time::{Timespec, NSEC_PER_SEC};
+
+use crate::{Error, Inode, PollEvents, Result};
+
+/// Chunk size used when the filesystem does not report a block size.
+const DEFAULT_CHUNK_SIZE: usize = 4096;
+
+/// `Timespec::nsec` value given to `utimens` that sets the time to the current time.
+pub const UTIME_NOW: i32 = (1 << 30) - 1;
+/// `Timespec::nsec` value given to `utimens` that leaves the time unchanged.
+pub const UTIME_OMIT: i32 = (1 << 30) - 2;
+
+/// Read the whole file.
+/// The buffer is pre-sized from the size in the metadata, but reading goes on
+/// until `read_at` returns 0, so stale size metadata does not truncate the result.
+pub async fn read_all<I: Inode>(file: I) -> Result<Vec<u8>> {
+    let metadata = file.metadata().await?;
+    let chunk_size = match metadata.blk_size as usize {
+        0 => DEFAULT_CHUNK_SIZE,
+        blk_size => blk_size,
+    };
+    let mut buf = vec![0; metadata.size as usize];
+    let mut read = 0;
+    loop {
+        if read == buf.len() {
+            buf.resize(read + chunk_size, 0);
+        }
+        let end = buf.len().min(read + chunk_size);
+        match file.read_at(read as u64, &mut buf[read..end]).await? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    buf.truncate(read);
+    Ok(buf)
+}
+
+/// Read from `offset` until `buf` is full or the end of the file is reached.
+/// Returns the number of bytes read.
+pub async fn read_full_at<I: Inode>(file: &I, offset: u64, buf: &mut [u8]) -> Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match file.read_at(offset + read as u64, &mut buf[read..]).await? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(read)
+}
+
+/// Copy the content of `src` to `dst` from the start of both files,
+/// stopping at EOF of `src` or after `len` bytes. Returns the number of bytes copied.
+pub async fn copy<I: Inode>(src: &I, dst: &I, len: Option<u64>) -> Result<u64> {
+    copy_at(src, 0, dst, 0, len).await
+}
+
+/// Like [`copy`], but reading from `src_offset` of `src` and writing at `dst_offset` of `dst`.
+pub async fn copy_at<I: Inode>(
+    src: &I,
+    src_offset: u64,
+    dst: &I,
+    dst_offset: u64,
+    len: Option<u64>,
+) -> Result<u64> {
+    let chunk_size = match src.metadata().await?.blk_size as usize {
+        0 => DEFAULT_CHUNK_SIZE,
+        blk_size => blk_size,
+    };
+    let mut buf = vec![0; chunk_size];
+    let mut copied = 0;
+    loop {
+        let want = match len {
+            Some(len) if len - copied < chunk_size as u64 => (len - copied) as usize,
+            _ => chunk_size,
+        };
+        if want == 0 {
+            break;
+        }
+        let read = src.read_at(src_offset + copied, &mut buf[..want]).await?;
+        if read == 0 {
+            break;
+        }
+        let mut written = 0;
+        while written < read {
+            match dst
+                .write_at(dst_offset + copied + written as u64, &buf[written..read])
+                .await?
+            {
+                0 => return Err(Error::NoSpace),
+                n => written += n,
+            }
+        }
+        copied += read as u64;
+    }
+    Ok(copied)
+}
+
+/// Give `inode` to `uid` and `gid` for `caller_uid` of group `caller_gid`, as `fchownat`.
+/// An id of `u32::MAX` is left unchanged.
+pub async fn chown<I: Inode>(
+    inode: &I,
+    uid: u32,
+    gid: u32,
+    caller_uid: u32,
+    caller_gid: u32,
+) -> Result<()> {
+    let metadata = inode.metadata().await?;
+    let uid = if uid == u32::MAX { metadata.uid } else { uid };
+    let gid = if gid == u32::MAX { metadata.gid } else { gid };
+    metadata.check_chown(caller_uid, caller_gid, uid, gid)?;
+    inode.chown(uid, gid).await?;
+    inode.sync().await
+}
+
+/// Set the access and modification times of `inode` for `uid` of group `gid`, as `utimensat`.
+/// `None` sets both to `now`, otherwise a time may be `UTIME_NOW` or `UTIME_OMIT`.
+pub async fn utimens<I: Inode>(
+    inode: &I,
+    times: Option<[Timespec; 2]>,
+    now: Timespec,
+    uid: u32,
+    gid: u32,
+) -> Result<()> {
+    let now_times = [Timespec {
+        sec: 0,
+        nsec: UTIME_NOW,
+    }; 2];
+    let times = times.unwrap_or(now_times);
+    let explicit = times
+        .iter()
+        .any(|time| time.nsec != UTIME_NOW && time.nsec != UTIME_OMIT);
+    let (atime, mtime) = (utime(times[0], now)?, utime(times[1], now)?);
+    if atime.is_none() && mtime.is_none() {
+        return Ok(());
+    }
+    inode
+        .metadata()
+        .await?
+        .check_set_times(uid, gid, explicit)?;
+    inode.set_times(atime, mtime, Some(now)).await
+}
+
+// Resolve a time given to `utimens`, `None` leaves the time unchanged.
+fn utime(time: Timespec, now: Timespec) -> Result<Option<Timespec>> {
+    match time.nsec {
+        UTIME_NOW => Ok(Some(now)),
+        UTIME_OMIT => Ok(None),
+        nsec if (0..NSEC_PER_SEC as i32).contains(&nsec) => Ok(Some(time)),
+        _ => Err(Error::InvalidTime),
+    }
+}
+
+/// Polls the `Inode::poll` futures of a poll set once, each with the index of its entry,
+/// and reports the events of the ready entries to `revents`.
+/// Returns the number of ready entries, pending while there is none.
+pub fn poll_set(
+    polls: &mut [(usize, BoxFuture<'_, PollEvents>)],
+    cx: &mut Context<'_>,
+    mut revents: impl FnMut(usize, PollEvents),
+) -> Poll<usize> {
+    let mut nready = 0;
+    for (idx, fut) in polls.iter_mut() {
+        if let Poll::Ready(events) = fut.as_mut().poll(cx) {
+            revents(*idx, events);
+            nready += 1;
+        }
+    }
+    if nready > 0 {
+        Poll::Ready(nready)
+    } else {
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::{boxed::Box, vec::Vec};
+    use core::{
+        sync::atomic::{AtomicUsize, Ordering},
+        task::{Context, Poll, Waker},
+    };
+    use futures_util::future::BoxFuture;
+    use std::{sync::Arc, task::Wake};
+    use time::Timespec;
+    use tokio_test::block_on;
+
+    use super::{chown, poll_set, read_all, utimens, UTIME_NOW, UTIME_OMIT};
+    use crate::{
+        mock::{create, ram_vfs, TestIrq},
+        socket::SocketEnd,
+        Error, Inode, Mode, PollEvents,
+    };
+
+    fn pattern(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7) as u8).collect()
+    }
+
+    #[cfg(feature = "naive_fs")]
+    #[test]
+    fn read_all_reads_every_block() {
+        let (vfs, root) = block_on(crate::mock::naive_vfs(64));
+        let file = block_on(create(&vfs, &root, "f", Mode::TY_REG));
+        let data = pattern(3000);
+        assert_eq!(
+            block_on(Inode::write_at(&file, 0, &data)).unwrap(),
+            data.len()
+        );
+        block_on(Inode::sync(&file)).unwrap();
+        assert_eq!(block_on(Inode::metadata(&file)).unwrap().blk_size, 512);
+
+        assert_eq!(block_on(read_all(file)).unwrap(), data);
+    }
+
+    #[test]
+    fn read_all_does_not_trust_the_size() {
+        let (vfs, root) = ram_vfs();
+        let file = block_on(create(&vfs, &root, "f", Mode::TY_REG));
+        let data = pattern(10000);
+        block_on(file.write_at(0, &data)).unwrap();
+
+        file.set_metadata_size(100);
+        assert_eq!(block_on(read_all(file.clone())).unwrap(), data);
+        file.set_metadata_size(20000);
+        assert_eq!(block_on(read_all(file)).unwrap(), data);
+    }
+
+    #[cfg(feature = "naive_fs")]
+    #[test]
+    fn copy_copies_a_multi_block_file() {
+        use super::copy;
+
+        let (vfs, root) = block_on(crate::mock::naive_vfs(128));
+        let src = block_on(create(&vfs, &root, "src", Mode::TY_REG));
+        let data = pattern(5000);
+        block_on(Inode::write_at(&src, 0, &data)).unwrap();
+        block_on(Inode::sync(&src)).unwrap();
+
+        let dst = block_on(create(&vfs, &root, "dst", Mode::TY_REG));
+        assert_eq!(block_on(copy(&src, &dst, None)).unwrap(), 5000);
+        block_on(Inode::sync(&dst)).unwrap();
+        assert_eq!(block_on(read_all(dst)).unwrap(), data);
+
+        // A length ending inside a block.
+        let part = block_on(create(&vfs, &root, "part", Mode::TY_REG));
+        assert_eq!(block_on(copy(&src, &part, Some(1300))).unwrap(), 1300);
+        block_on(Inode::sync(&part)).unwrap();
+        assert_eq!(block_on(read_all(part)).unwrap(), data[..1300]);
+    }
+
+    #[test]
+    fn utimens_sets_explicit_times_and_leaves_omitted_ones() {
+        let (vfs, root) = ram_vfs();
+        let file = block_on(create(&vfs, &root, "f", Mode::TY_REG));
+        let before = block_on(file.metadata()).unwrap();
+        let (t1, t2, now) = (
+            Timespec::new(10, 1),
+            Timespec::new(20, 2),
+            Timespec::new(30, 3),
+        );
+        let omit = Timespec {
+            sec: 0,
+            nsec: UTIME_OMIT,
+        };
+
+        block_on(utimens(&file, Some([t1, omit]), now, 0, 0)).unwrap();
+        let metadata = block_on(file.metadata()).unwrap();
+        assert_eq!(metadata.atime, t1);
+        assert_eq!(metadata.mtime, before.mtime);
+        assert_eq!(metadata.ctime, now);
+
+        let now_time = Timespec {
+            sec: 0,
+            nsec: UTIME_NOW,
+        };
+        block_on(utimens(&file, Some([omit, now_time]), t2, 0, 0)).unwrap();
+        let metadata = block_on(file.metadata()).unwrap();
+        assert_eq!((metadata.atime, metadata.mtime), (t1, t2));
+
+        // Omitting both leaves even the status change time alone.
+        block_on(utimens(&file, Some([omit, omit]), now, 0, 0)).unwrap();
+        assert_eq!(block_on(file.metadata()).unwrap().ctime, t2);
+
+        let invalid = Timespec { sec: 0, nsec: -1 };
+        assert!(matches!(
+            block_on(utimens(&file, Some([invalid, omit]), now, 0, 0)),
+            Err(Error::InvalidTime)
+        ));
+    }
+
+    #[test]
+    fn only_the_owner_sets_explicit_times() {
+        let (vfs, root) = ram_vfs();
+        let file = block_on(create(&vfs, &root, "f", Mode::TY_REG));
+        block_on(file.chown(1000, 1000)).unwrap();
+        let (t, now) = (Timespec::new(10, 0), Timespec::new(20, 0));
+
+        // Someone else without write permission may not touch the file at all.
+        let utimens_as = |uid, times| block_on(utimens(&file, times, now, uid, 2000));
+        assert!(matches!(
+            utimens_as(2000, Some([t, t])),
+            Err(Error::NotPermitted)
+        ));
+        assert!(matches!(
+            utimens_as(2000, None),
+            Err(Error::PermissionDenied)
+        ));
+
+        // Write permission allows setting the current time, but still not explicit times.
+        block_on(file.chmod(Mode::TY_REG | Mode::PERM_RW_USR | Mode::PERM_W_OTH)).unwrap();
+        utimens_as(2000, None).unwrap();
+        assert_eq!(block_on(file.metadata()).unwrap().mtime, now);
+        assert!(matches!(
+            utimens_as(2000, Some([t, t])),
+            Err(Error::NotPermitted)
+        ));
+
+        utimens_as(1000, Some([t, t])).unwrap();
+        utimens_as(0, Some([now, t])).unwrap();
+        let metadata = block_on(file.metadata()).unwrap();
+        assert_eq!((metadata.atime, metadata.mtime), (now, t));
+    }
+
+    #[test]
+    fn only_root_gives_a_file_away() {
+        let (vfs, root) = ram_vfs();
+        let file = block_on(create(&vfs, &root, "f", Mode::TY_REG));
+        let owner = |file: &crate::mock::TestInode| {
+            let metadata = block_on(file.metadata()).unwrap();
+            (metadata.uid, metadata.gid)
+        };
+        block_on(chown(&file, 1000, 1000, 0, 0)).unwrap();
+        assert_eq!(owner(&file), (1000, 1000));
+
+        // The owner may not give the file away, nor pick a group it is not in.
+        let chown_as = |uid, gid| block_on(chown(&file, uid, gid, 1000, 100));
+        assert!(matches!(chown_as(2000, u32::MAX), Err(Error::NotPermitted)));
+        assert!(matches!(chown_as(u32::MAX, 300), Err(Error::NotPermitted)));
+        assert!(matches!(
+            block_on(chown(&file, u32::MAX, 100, 2000, 100)),
+            Err(Error::NotPermitted)
+        ));
+        assert_eq!(owner(&file), (1000, 1000));
+
+        chown_as(u32::MAX, 100).unwrap();
+        assert_eq!(owner(&file), (1000, 100));
+        // Nor go back to a group it has left.
+        assert!(matches!(chown_as(1000, 1000), Err(Error::NotPermitted)));
+        chown_as(1000, u32::MAX).unwrap();
+        assert_eq!(owner(&file), (1000, 100));
+    }
+
+    #[cfg(feature = "naive_fs")]
+    #[test]
+    fn chown_persists_across_a_reload() {
+        use crate::Filesystem;
+
+        let (vfs, root) = block_on(crate::mock::naive_vfs(64));
+        let file = block_on(create(&vfs, &root, "f", Mode::TY_REG));
+        block_on(chown(&file, 1000, 2000, 0, 0)).unwrap();
+        let id = Inode::id(&file);
+        drop(file);
+
+        let fs = block_on(vfs.root()).fs;
+        let file = block_on(Filesystem::load_inode(&fs, id)).unwrap().unwrap();
+        let metadata = block_on(Inode::metadata(&file)).unwrap();
+        assert_eq!((metadata.uid, metadata.gid), (1000, 2000));
+    }
+
+    #[derive(Default)]
+    struct CountWaker(AtomicUsize);
+
+    impl Wake for CountWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn a_poll_set_waits_for_pollin_until_the_peer_writes() {
+        let (end0, end1) = SocketEnd::<TestIrq>::pair();
+        let (other0, _other1) = SocketEnd::<TestIrq>::pair();
+        // The second entry is never ready.
+        let mut polls: Vec<(usize, BoxFuture<'_, PollEvents>)> = vec![
+            (0, Box::pin(end0.poll(PollEvents::IN))),
+            (1, Box::pin(other0.poll(PollEvents::IN))),
+        ];
+        let count = Arc::new(CountWaker::default());
+        let waker = Waker::from(count.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut revents = [PollEvents::empty(); 2];
+
+        assert_eq!(
+            poll_set(&mut polls, &mut cx, |idx, events| revents[idx] = events),
+            Poll::Pending
+        );
+        assert_eq!(block_on(end1.write(b"x")).unwrap(), 1);
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            poll_set(&mut polls, &mut cx, |idx, events| revents[idx] = events),
+            Poll::Ready(1)
+        );
+        assert_eq!(revents, [PollEvents::IN, PollEvents::empty()]);
+    }
+}
@@ -0,0 +1,53 @@
+//! Wakers of futures waiting for an inode to become ready.
+
+use core::{
+    mem,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::Waker,
+};
+
+use alloc::vec::Vec;
+
+static NEXT_KEY: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns a key that tells a waiting future apart from the others in a `WaitQueue`.
+pub fn key() -> usize {
+    NEXT_KEY.fetch_add(1, Ordering::Relaxed)
+}
+
+/// The futures waiting for an event, kept under the lock of the state they wait on.
+/// A future is registered at most once, with the waker of its latest poll,
+/// and should unregister itself when it is dropped before being woken.
+#[derive(Default)]
+pub struct WaitQueue {
+    wakers: Vec<(usize, Waker)>,
+}
+
+impl WaitQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the future `key` to be woken by `waker`, replacing the waker of an earlier poll.
+    pub fn register(&mut self, key: usize, waker: &Waker) {
+        match self.wakers.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, w)) => {
+                if !w.will_wake(waker) {
+                    *w = waker.clone();
+                }
+            }
+            None => self.wakers.push((key, waker.clone())),
+        }
+    }
+
+    pub fn unregister(&mut self, key: usize) {
+        self.wakers.retain(|(k, _)| *k != key);
+    }
+
+    /// Wakes all registered futures, they register again if they keep waiting.
+    pub fn wake_all(&mut self) {
+        for (_, w) in mem::take(&mut self.wakers) {
+            w.wake();
+        }
+    }
+}
@@ -0,0 +1,648 @@
+//! The driver of a virtio block device.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    future::Future,
+    marker::PhantomData,
+    mem,
+    pin::Pin,
+    ptr,
+    task::{Context, Poll, Waker},
+};
+
+use blk::{BlkDevice, BlkSize};
+use futures_util::future::BoxFuture;
+use mm::VirtualAddress;
+use mmio::Reg;
+use spinlock::{Irq, MutexIrq};
+
+use crate::{
+    queue::{Buf, VirtQueue},
+    Device, Hal, Result,
+};
+
+/// Requests address the device in sectors of 512 bytes, whatever its block size.
+const SECTOR_SIZE: u32 = 512;
+
+/// The entries of the request queue.
+const QUEUE_SIZE: u16 = 16;
+
+/// `capacity` in the configuration space, in sectors. It is 64 bits wide and read as two halves.
+const CONFIG_CAPACITY_LOW: Reg<u32> = Reg::new(0);
+const CONFIG_CAPACITY_HIGH: Reg<u32> = Reg::new(4);
+/// `size_max` in the configuration space: the most bytes in a data segment.
+const CONFIG_SIZE_MAX: Reg<u32> = Reg::new(8);
+/// `seg_max` in the configuration space: the most data segments the device takes in
+/// one request.
+const CONFIG_SEG_MAX: Reg<u32> = Reg::new(12);
+
+/// `size_max` is valid.
+const F_SIZE_MAX: u64 = 1 << 1;
+/// `seg_max` is valid.
+const F_SEG_MAX: u64 = 1 << 2;
+
+const REQ_IN: u32 = 0;
+const REQ_OUT: u32 = 1;
+
+const STATUS_OK: u8 = 0;
+const STATUS_UNSUPP: u8 = 2;
+
+#[repr(C)]
+struct ReqHeader {
+    ty: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+/// The part of a request the device reads and writes besides the data.
+#[repr(C)]
+struct ReqDma {
+    header: ReqHeader,
+    status: u8,
+}
+
+enum ReqState {
+    Free,
+    /// Waiting for the device, the waker of the task waiting for the request.
+    InFlight(Option<Waker>),
+    /// The device is done with the request.
+    Done,
+    /// The request was dropped while the device still had it.
+    Abandoned,
+}
+
+struct Requests<H: Hal> {
+    queue: VirtQueue<H>,
+    /// Indexed by the descriptor at the head of the chain of the request.
+    dma: Box<[ReqDma]>,
+    states: Vec<ReqState>,
+    /// The tasks waiting for enough free descriptors for their requests.
+    waiting: Vec<Waker>,
+}
+
+impl<H: Hal> Requests<H> {
+    /// Hands a request to the device, returns the head of its chain.
+    /// `None` if the queue does not have enough free descriptors.
+    fn submit(&mut self, ty: u32, sector: u64, data: &[Buf]) -> Option<u16> {
+        if data.len() + 2 > self.queue.num_free() as usize {
+            return None;
+        }
+        let head = self.queue.next_head()?;
+        let dma = &mut self.dma[head as usize];
+        dma.header = ReqHeader {
+            ty,
+            reserved: 0,
+            sector,
+        };
+        dma.status = u8::MAX;
+        let header = Buf {
+            addr: H::virt_to_phys(VirtualAddress(&dma.header as *const _ as usize)),
+            len: mem::size_of::<ReqHeader>() as u32,
+            device_writes: false,
+        };
+        let status = Buf {
+            addr: H::virt_to_phys(VirtualAddress(&dma.status as *const _ as usize)),
+            len: 1,
+            device_writes: true,
+        };
+        let mut bufs = Vec::with_capacity(data.len() + 2);
+        bufs.push(header);
+        bufs.extend_from_slice(data);
+        bufs.push(status);
+        let added = self.queue.add(&bufs);
+        debug_assert_eq!(added, Some(head));
+        self.states[head as usize] = ReqState::InFlight(None);
+        added
+    }
+
+    /// Frees the request at `head` the device is done with, returns its result
+    /// and the tasks to wake now that its descriptors are free.
+    fn complete(&mut self, head: u16) -> (blk::Result<()>, Vec<Waker>) {
+        let status = unsafe { ptr::read_volatile(&self.dma[head as usize].status) };
+        self.states[head as usize] = ReqState::Free;
+        self.queue.recycle(head);
+        let res = match status {
+            STATUS_OK => Ok(()),
+            STATUS_UNSUPP => Err(blk::Error::InvalidParam),
+            _ => Err(blk::Error::IoErr),
+        };
+        (res, mem::take(&mut self.waiting))
+    }
+}
+
+/// A virtio block device.
+/// Requests are handed to the device without waiting on the used ring: the returned
+/// futures stay pending until the device raises its interrupt and
+/// [`handle_interrupt`](Self::handle_interrupt) wakes the task waiting for that request.
+/// A request must not be dropped while the device has it, the device would still
+/// access its buffer.
+pub struct VirtioBlk<H: Hal, I> {
+    device: Device,
+    reqs: MutexIrq<I, Requests<H>>,
+    /// The most bytes of a data segment, a whole number of sectors.
+    seg_len: usize,
+    /// The most blocks a single request carries, in at most `seg_max` segments.
+    max_req_blks: usize,
+}
+
+impl<H: Hal, I: Irq> VirtioBlk<H, I> {
+    /// Initializes the block device `device`.
+    pub fn new(device: Device) -> Result<Self> {
+        let features = device.begin_init(F_SIZE_MAX | F_SEG_MAX)?;
+        let queue = match VirtQueue::new(&device, 0, QUEUE_SIZE) {
+            Ok(queue) => queue,
+            Err(e) => {
+                device.fail();
+                return Err(e);
+            }
+        };
+        let size = queue.size() as usize;
+        device.finish_init();
+
+        // A request chains a descriptor for its header and one for its status around
+        // its data segments. Without `seg_max` the device may only take one.
+        let data_segs = size - 2;
+        let seg_max = if features & F_SEG_MAX != 0 {
+            (device.read_config(CONFIG_SEG_MAX) as usize).clamp(1, data_segs)
+        } else {
+            1
+        };
+        // Without `size_max` a segment is only bounded by the length of a descriptor.
+        let size_max = if features & F_SIZE_MAX != 0 {
+            device.read_config(CONFIG_SIZE_MAX)
+        } else {
+            u32::MAX
+        };
+        let seg_len = seg_len(size_max);
+        let dma = (0..size)
+            .map(|_| ReqDma {
+                header: ReqHeader {
+                    ty: 0,
+                    reserved: 0,
+                    sector: 0,
+                },
+                status: 0,
+            })
+            .collect();
+        Ok(Self {
+            device,
+            reqs: MutexIrq::new(Requests {
+                queue,
+                dma,
+                states: (0..size).map(|_| ReqState::Free).collect(),
+                waiting: Vec::new(),
+            }),
+            seg_len,
+            max_req_blks: seg_max * seg_len / SECTOR_SIZE as usize,
+        })
+    }
+
+    /// Acks the device interrupt and completes the requests the device is done with.
+    /// Must be called from the device's IRQ handler.
+    pub fn handle_interrupt(&self) {
+        self.device.ack_interrupt();
+        let mut wakers = Vec::new();
+        {
+            let mut reqs = self.reqs.lock();
+            while let Some((head, _)) = reqs.queue.pop_used() {
+                let state = match reqs.states.get_mut(head as usize) {
+                    Some(state) => mem::replace(state, ReqState::Done),
+                    None => continue,
+                };
+                match state {
+                    ReqState::InFlight(waker) => wakers.extend(waker),
+                    ReqState::Abandoned => wakers.append(&mut reqs.complete(head).1),
+                    ReqState::Free | ReqState::Done => {}
+                }
+            }
+        }
+        // Woken without the lock held, the tasks may run on another hart right away.
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    /// A request of type `ty` transferring `data` from the block `blk_id` on.
+    fn request<'a>(&'a self, ty: u32, blk_id: usize, data: Vec<Buf>) -> Request<'a, H, I> {
+        Request {
+            blk: self,
+            ty,
+            sector: blk_id as u64,
+            data,
+            head: None,
+            _buf: PhantomData,
+        }
+    }
+
+    /// Splits the `len` bytes at `buf` into data segments the device takes.
+    fn data_bufs(&self, buf: *const u8, len: usize, device_writes: bool) -> Vec<Buf> {
+        (0..len)
+            .step_by(self.seg_len)
+            .map(|offset| Buf {
+                addr: H::virt_to_phys(VirtualAddress(buf as usize + offset)),
+                len: (len - offset).min(self.seg_len) as u32,
+                device_writes,
+            })
+            .collect()
+    }
+}
+
+impl<H: Hal, I> Drop for VirtioBlk<H, I> {
+    fn drop(&mut self) {
+        // The device must not access the queue once it is freed.
+        self.device.reset();
+    }
+}
+
+/// Future of a request, see [`VirtioBlk::request`].
+struct Request<'a, H: Hal, I: Irq> {
+    blk: &'a VirtioBlk<H, I>,
+    ty: u32,
+    sector: u64,
+    data: Vec<Buf>,
+    /// The head of the chain of the request, once handed to the device.
+    head: Option<u16>,
+    _buf: PhantomData<&'a mut [u8]>,
+}
+
+impl<H: Hal, I: Irq> Future for Request<'_, H, I> {
+    type Output = blk::Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut reqs = this.blk.reqs.lock();
+        let head = match this.head {
+            Some(head) => head,
+            None => match reqs.submit(this.ty, this.sector, &this.data) {
+                Some(head) => {
+                    this.head = Some(head);
+                    this.blk.device.notify(reqs.queue.index());
+                    head
+                }
+                None => {
+                    reqs.waiting.push(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            },
+        };
+        match &mut reqs.states[head as usize] {
+            ReqState::InFlight(waker) => {
+                *waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            ReqState::Done => {}
+            ReqState::Free | ReqState::Abandoned => unreachable!(),
+        }
+        this.head = None;
+        let (res, wakers) = reqs.complete(head);
+        drop(reqs);
+        for waker in wakers {
+            waker.wake();
+        }
+        Poll::Ready(res)
+    }
+}
+
+impl<H: Hal, I: Irq> Drop for Request<'_, H, I> {
+    fn drop(&mut self) {
+        let head = match self.head {
+            Some(head) => head,
+            None => return,
+        };
+        let mut reqs = self.blk.reqs.lock();
+        let wakers = match &reqs.states[head as usize] {
+            ReqState::InFlight(_) => {
+                // Freed by `handle_interrupt` once the device is done with it.
+                reqs.states[head as usize] = ReqState::Abandoned;
+                return;
+            }
+            _ => reqs.complete(head).1,
+        };
+        drop(reqs);
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+/// Submits requests to a block device, each carrying all the blocks of its buffer.
+trait BlkRequests {
+    fn read<'a>(&'a self, blk_id: usize, buf: &'a mut [u8]) -> BoxFuture<'a, blk::Result<()>>;
+
+    fn write<'a>(&'a self, blk_id: usize, src: &'a [u8]) -> BoxFuture<'a, blk::Result<()>>;
+}
+
+impl<H: Hal, I: Irq + Send + Sync> BlkRequests for VirtioBlk<H, I> {
+    fn read<'a>(&'a self, blk_id: usize, buf: &'a mut [u8]) -> BoxFuture<'a, blk::Result<()>> {
+        let data = self.data_bufs(buf.as_ptr(), buf.len(), true);
+        Box::pin(self.request(REQ_IN, blk_id, data))
+    }
+
+    fn write<'a>(&'a self, blk_id: usize, src: &'a [u8]) -> BoxFuture<'a, blk::Result<()>> {
+        let data = self.data_bufs(src.as_ptr(), src.len(), false);
+        Box::pin(self.request(REQ_OUT, blk_id, data))
+    }
+}
+
+impl<H: Hal, I: Irq + Send + Sync> BlkDevice for VirtioBlk<H, I> {
+    fn read_blk<'a>(&'a self, blk_id: usize, buf: &'a mut [u8]) -> BoxFuture<'a, blk::Result<()>> {
+        self.read(blk_id, buf)
+    }
+
+    fn write_blk<'a>(&'a self, blk_id: usize, src: &'a [u8]) -> BoxFuture<'a, blk::Result<()>> {
+        self.write(blk_id, src)
+    }
+
+    fn read_blks<'a>(
+        &'a self,
+        start_blk_id: usize,
+        buf: &'a mut [u8],
+    ) -> BoxFuture<'a, blk::Result<()>> {
+        Box::pin(read_runs(
+            self,
+            self.logical_blk_size(),
+            self.max_req_blks,
+            start_blk_id,
+            buf,
+        ))
+    }
+
+    fn write_blks<'a>(
+        &'a self,
+        start_blk_id: usize,
+        src: &'a [u8],
+    ) -> BoxFuture<'a, blk::Result<()>> {
+        Box::pin(write_runs(
+            self,
+            self.logical_blk_size(),
+            self.max_req_blks,
+            start_blk_id,
+            src,
+        ))
+    }
+
+    // VIRTIO_BLK_F_FLUSH is not negotiated, so the device runs in write-through mode
+    // and the default no-op `flush` is enough.
+
+    fn logical_blk_size(&self) -> BlkSize {
+        BlkSize::new(SECTOR_SIZE)
+    }
+
+    /// Read from the device every time, the capacity goes stale once the device is resized.
+    fn blk_count(&self) -> usize {
+        read_capacity(&self.device) as usize
+    }
+}
+
+/// The most bytes of a data segment whose length is at most `size_max`,
+/// whole sectors so that no sector is split between two requests.
+fn seg_len(size_max: u32) -> usize {
+    let sectors = (size_max / SECTOR_SIZE).max(1);
+    (sectors * SECTOR_SIZE) as usize
+}
+
+/// Reads the capacity from the configuration space of `device`. The halves are read
+/// again if the device changed its configuration in between.
+fn read_capacity(device: &Device) -> u64 {
+    loop {
+        let generation = device.config_generation();
+        let low = device.read_config(CONFIG_CAPACITY_LOW);
+        let high = device.read_config(CONFIG_CAPACITY_HIGH);
+        if device.config_generation() == generation {
+            return (high as u64) << 32 | low as u64;
+        }
+    }
+}
+
+/// Reads the blocks of `buf` from `start_blk_id` on, with one request per run of at
+/// most `max_req_blks` blocks.
+async fn read_runs(
+    reqs: &impl BlkRequests,
+    blk_size: BlkSize,
+    max_req_blks: usize,
+    start_blk_id: usize,
+    buf: &mut [u8],
+) -> blk::Result<()> {
+    if buf.len() % blk_size.size() as usize != 0 {
+        return Err(blk::Error::InvalidParam);
+    }
+    let run_len = blk_size.mul(max_req_blks);
+    for (i, run) in buf.chunks_mut(run_len).enumerate() {
+        reqs.read(start_blk_id + i * max_req_blks, run).await?;
+    }
+    Ok(())
+}
+
+/// Writes the blocks of `src` from `start_blk_id` on, with one request per run of at
+/// most `max_req_blks` blocks.
+async fn write_runs(
+    reqs: &impl BlkRequests,
+    blk_size: BlkSize,
+    max_req_blks: usize,
+    start_blk_id: usize,
+    src: &[u8],
+) -> blk::Result<()> {
+    if src.len() % blk_size.size() as usize != 0 {
+        return Err(blk::Error::InvalidParam);
+    }
+    let run_len = blk_size.mul(max_req_blks);
+    for (i, run) in src.chunks(run_len).enumerate() {
+        reqs.write(start_blk_id + i * max_req_blks, run).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::{boxed::Box, vec, vec::Vec};
+    use core::{future::Future, task::Poll};
+    use futures_util::future::BoxFuture;
+    use tokio_test::{block_on, task};
+
+    use blk::{BlkDevice, BlkSize, Result};
+    use spinlock::MutexIrq;
+
+    use super::{read_capacity, read_runs, write_runs, BlkRequests, VirtioBlk};
+    use crate::{
+        mock::{MockBlk, TestHal, TestIrq},
+        Device,
+    };
+
+    const BLK_SIZE: usize = 512;
+
+    /// A disk recording the `(blk_id, blk_cnt)` of each request.
+    struct MockRequests {
+        data: MutexIrq<TestIrq, Vec<u8>>,
+        reqs: MutexIrq<TestIrq, Vec<(usize, usize)>>,
+    }
+
+    impl MockRequests {
+        fn new(blk_cnt: usize) -> Self {
+            Self {
+                data: MutexIrq::new((0..blk_cnt * BLK_SIZE).map(|i| i as u8).collect()),
+                reqs: MutexIrq::new(Vec::new()),
+            }
+        }
+    }
+
+    impl BlkRequests for MockRequests {
+        fn read<'a>(&'a self, blk_id: usize, buf: &'a mut [u8]) -> BoxFuture<'a, Result<()>> {
+            self.reqs.lock().push((blk_id, buf.len() / BLK_SIZE));
+            let start = blk_id * BLK_SIZE;
+            buf.copy_from_slice(&self.data.lock()[start..start + buf.len()]);
+            Box::pin(core::future::ready(Ok(())))
+        }
+
+        fn write<'a>(&'a self, blk_id: usize, src: &'a [u8]) -> BoxFuture<'a, Result<()>> {
+            self.reqs.lock().push((blk_id, src.len() / BLK_SIZE));
+            let start = blk_id * BLK_SIZE;
+            self.data.lock()[start..start + src.len()].copy_from_slice(src);
+            Box::pin(core::future::ready(Ok(())))
+        }
+    }
+
+    fn blk_size() -> BlkSize {
+        BlkSize::new(BLK_SIZE as u32)
+    }
+
+    #[test]
+    fn contiguous_blocks_are_read_in_one_request() {
+        let reqs = MockRequests::new(16);
+        let mut buf = vec![0; 4 * BLK_SIZE];
+        block_on(read_runs(&reqs, blk_size(), 14, 2, &mut buf)).unwrap();
+        assert_eq!(*reqs.reqs.lock(), [(2, 4)]);
+        assert_eq!(buf[..], reqs.data.lock()[2 * BLK_SIZE..6 * BLK_SIZE]);
+    }
+
+    #[test]
+    fn requests_are_split_by_the_block_limit() {
+        let reqs = MockRequests::new(16);
+        let mut buf = vec![0; 10 * BLK_SIZE];
+        block_on(read_runs(&reqs, blk_size(), 4, 1, &mut buf)).unwrap();
+        assert_eq!(*reqs.reqs.lock(), [(1, 4), (5, 4), (9, 2)]);
+        assert_eq!(buf[..], reqs.data.lock()[BLK_SIZE..11 * BLK_SIZE]);
+
+        let src = vec![0xa5; 5 * BLK_SIZE];
+        reqs.reqs.lock().clear();
+        block_on(write_runs(&reqs, blk_size(), 4, 3, &src)).unwrap();
+        assert_eq!(*reqs.reqs.lock(), [(3, 4), (7, 1)]);
+        assert_eq!(reqs.data.lock()[3 * BLK_SIZE..8 * BLK_SIZE], src[..]);
+    }
+
+    #[test]
+    fn capacity_is_read_from_the_device() {
+        let mut mock = MockBlk::new(0x10);
+        mock.regs[0x104 / 4] = 0x2;
+        let device = Device::probe(mock.mmio()).unwrap();
+        assert_eq!(read_capacity(&device), 0x2_0000_0010);
+        // The device grew.
+        mock.regs[0x100 / 4] = 0x20;
+        assert_eq!(read_capacity(&device), 0x2_0000_0020);
+    }
+
+    // Runs `fut`, letting the device serve the requests it is waiting for.
+    fn run<F: Future>(mock: &mut MockBlk, blk: &VirtioBlk<TestHal, TestIrq>, fut: F) -> F::Output {
+        let mut fut = task::spawn(fut);
+        loop {
+            if let Poll::Ready(res) = fut.poll() {
+                return res;
+            }
+            mock.process();
+            blk.handle_interrupt();
+        }
+    }
+
+    #[test]
+    fn blocks_are_written_and_read_through_the_queue() {
+        let mut mock = MockBlk::new(8);
+        let blk = VirtioBlk::<TestHal, TestIrq>::new(Device::probe(mock.mmio()).unwrap()).unwrap();
+        assert_eq!(blk.blk_count(), 8);
+
+        let src = vec![0xa5; 2 * BLK_SIZE];
+        run(&mut mock, &blk, blk.write_blks(3, &src)).unwrap();
+        assert_eq!(mock.disk[3 * BLK_SIZE..5 * BLK_SIZE], src[..]);
+
+        let mut buf = vec![0; 3 * BLK_SIZE];
+        run(&mut mock, &blk, blk.read_blks(2, &mut buf)).unwrap();
+        assert_eq!(buf[..], mock.disk[2 * BLK_SIZE..5 * BLK_SIZE]);
+        // A request each, carrying all of its blocks.
+        assert_eq!(
+            mock.requests,
+            [[2 * BLK_SIZE as u32], [3 * BLK_SIZE as u32]]
+        );
+
+        // Past the end of the device.
+        let mut buf = vec![0; BLK_SIZE];
+        assert!(run(&mut mock, &blk, blk.read_blk(8, &mut buf)).is_err());
+    }
+
+    #[test]
+    fn requests_complete_once_the_interrupt_is_handled() {
+        let mut mock = MockBlk::new(8);
+        let blk = VirtioBlk::<TestHal, TestIrq>::new(Device::probe(mock.mmio()).unwrap()).unwrap();
+        let mut buf = vec![0; BLK_SIZE];
+        let mut read = task::spawn(blk.read_blk(5, &mut buf));
+        assert!(read.poll().is_pending());
+
+        // The device is done, but its interrupt is not handled yet.
+        mock.process();
+        assert_eq!(mock.requests.len(), 1);
+        assert!(!read.is_woken());
+        assert!(read.poll().is_pending());
+
+        blk.handle_interrupt();
+        // InterruptACK
+        assert_eq!(mock.regs[0x064 / 4], 1);
+        assert!(read.is_woken());
+        assert!(matches!(read.poll(), Poll::Ready(Ok(()))));
+        drop(read);
+        assert_eq!(buf[..], mock.disk[5 * BLK_SIZE..6 * BLK_SIZE]);
+    }
+
+    #[test]
+    fn requests_chain_segments_within_the_negotiated_limits() {
+        let mut mock = MockBlk::new(16);
+        // SIZE_MAX and SEG_MAX, with VERSION_1 read from the same cell.
+        mock.regs[0x010 / 4] = 0b111;
+        mock.regs[0x108 / 4] = 2 * BLK_SIZE as u32;
+        mock.regs[0x10c / 4] = 3;
+        let blk = VirtioBlk::<TestHal, TestIrq>::new(Device::probe(mock.mmio()).unwrap()).unwrap();
+        let mut buf = vec![0; 10 * BLK_SIZE];
+        run(&mut mock, &blk, blk.read_blks(1, &mut buf)).unwrap();
+        assert_eq!(buf[..], mock.disk[BLK_SIZE..11 * BLK_SIZE]);
+        let seg = 2 * BLK_SIZE as u32;
+        assert_eq!(mock.requests, [vec![seg, seg, seg], vec![seg, seg]]);
+
+        // Segments are whole sectors.
+        let mut mock = MockBlk::new(16);
+        mock.regs[0x010 / 4] = 0b111;
+        mock.regs[0x108 / 4] = 700;
+        mock.regs[0x10c / 4] = 2;
+        let blk = VirtioBlk::<TestHal, TestIrq>::new(Device::probe(mock.mmio()).unwrap()).unwrap();
+        let src = vec![0xa5; 3 * BLK_SIZE];
+        run(&mut mock, &blk, blk.write_blks(0, &src)).unwrap();
+        assert_eq!(mock.disk[..3 * BLK_SIZE], src[..]);
+        let seg = BLK_SIZE as u32;
+        assert_eq!(mock.requests, [vec![seg, seg], vec![seg]]);
+    }
+
+    #[test]
+    fn limits_the_device_does_not_offer_are_ignored() {
+        let mut mock = MockBlk::new(16);
+        mock.regs[0x108 / 4] = BLK_SIZE as u32;
+        mock.regs[0x10c / 4] = 4;
+        let blk = VirtioBlk::<TestHal, TestIrq>::new(Device::probe(mock.mmio()).unwrap()).unwrap();
+        let mut buf = vec![0; 6 * BLK_SIZE];
+        run(&mut mock, &blk, blk.read_blks(0, &mut buf)).unwrap();
+        // A single segment, of any length.
+        assert_eq!(mock.requests, [[6 * BLK_SIZE as u32]]);
+    }
+
+    #[test]
+    fn partial_blocks_are_rejected() {
+        let reqs = MockRequests::new(4);
+        let mut buf = vec![0; BLK_SIZE + 1];
+        assert!(block_on(read_runs(&reqs, blk_size(), 4, 0, &mut buf)).is_err());
+        assert!(reqs.reqs.lock().is_empty());
+    }
+}
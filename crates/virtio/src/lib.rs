@@ -0,0 +1,60 @@
+//! Drivers of virtio devices attached through the virtio-mmio interface,
+//! both the legacy interface (version 1) and the version 2 one of virtio 1.x.
+
+#![no_std]
+
+#[cfg(test)]
+extern crate std;
+
+extern crate alloc;
+
+pub mod blk;
+#[cfg(test)]
+mod mock;
+mod queue;
+mod transport;
+
+use mm::{PhysicalAddress, VirtualAddress};
+
+pub use queue::QueueLayout;
+pub use transport::{Device, Version, DEVICE_ID_BLK};
+
+/// The page size the legacy interface is told, the queues are aligned to it.
+pub const PAGE_SIZE: usize = 4096;
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The registers are not those of a virtio-mmio device.
+    NotVirtio,
+    /// The virtio-mmio slot has no device attached.
+    NoDevice,
+    /// The device implements an unknown version of the virtio-mmio interface.
+    UnknownVersion(u32),
+    /// The device did not accept the features the driver can use.
+    FeaturesRefused,
+    /// The device does not have the queue.
+    QueueUnavailable,
+    /// The queue is already in use.
+    QueueAlreadyUsed,
+    /// The device supports at most `max` entries in the queue.
+    QueueTooLarge { max: u32 },
+    /// Failed to allocate the memory shared with the device.
+    DmaErr,
+}
+
+/// What the drivers need from the kernel to share memory with a device.
+pub trait Hal {
+    /// Allocates `pages` zeroed, physically contiguous pages.
+    fn dma_alloc(pages: usize) -> Option<PhysicalAddress>;
+
+    /// Frees the `pages` pages at `paddr` `dma_alloc` returned.
+    fn dma_dealloc(paddr: PhysicalAddress, pages: usize);
+
+    fn phys_to_virt(paddr: PhysicalAddress) -> VirtualAddress;
+
+    /// The physical address of the kernel memory at `vaddr`, such as the buffer of a request.
+    /// The memory of a buffer must be physically contiguous.
+    fn virt_to_phys(vaddr: VirtualAddress) -> PhysicalAddress;
+}
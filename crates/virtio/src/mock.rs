@@ -0,0 +1,181 @@
+//! A version 2 virtio-mmio block device in host memory, for the tests.
+//! Physical addresses are the host addresses.
+
+use core::ptr;
+use std::{
+    alloc::{self, Layout},
+    boxed::Box,
+    vec::Vec,
+};
+
+use mm::{PhysicalAddress, VirtualAddress};
+use mmio::Mmio;
+use spinlock::Irq;
+
+use crate::{Hal, PAGE_SIZE};
+
+pub struct TestIrq;
+
+impl Irq for TestIrq {
+    fn push_off() {}
+    fn pop_off() {}
+}
+
+pub struct TestHal;
+
+impl Hal for TestHal {
+    fn dma_alloc(pages: usize) -> Option<PhysicalAddress> {
+        let ptr = unsafe { alloc::alloc_zeroed(dma_layout(pages)) };
+        if ptr.is_null() {
+            None
+        } else {
+            Some(PhysicalAddress(ptr as usize))
+        }
+    }
+
+    fn dma_dealloc(paddr: PhysicalAddress, pages: usize) {
+        unsafe { alloc::dealloc(paddr.0 as *mut u8, dma_layout(pages)) }
+    }
+
+    fn phys_to_virt(paddr: PhysicalAddress) -> VirtualAddress {
+        VirtualAddress(paddr.0)
+    }
+
+    fn virt_to_phys(vaddr: VirtualAddress) -> PhysicalAddress {
+        PhysicalAddress(vaddr.0)
+    }
+}
+
+fn dma_layout(pages: usize) -> Layout {
+    Layout::from_size_align(pages * PAGE_SIZE, PAGE_SIZE).unwrap()
+}
+
+pub fn regs_window(regs: &mut [u32]) -> Mmio {
+    unsafe { Mmio::new(VirtualAddress(regs.as_mut_ptr() as usize), regs.len() * 4) }
+}
+
+pub const SECTOR_SIZE: usize = 512;
+
+/// A block device of 512 bytes sectors, served by `process`.
+pub struct MockBlk {
+    pub regs: Box<[u32; 0x120 / 4]>,
+    pub disk: Vec<u8>,
+    /// The lengths of the data buffers of each request served.
+    pub requests: Vec<Vec<u32>>,
+    last_avail_idx: u16,
+}
+
+impl MockBlk {
+    /// A device of `sectors` sectors, whose bytes are their offset on the device.
+    /// The registers are plain memory, so the device reads as offering the same
+    /// features in both halves: `VIRTIO_F_VERSION_1`, bit 32, is bit 0.
+    pub fn new(sectors: usize) -> Self {
+        let mut regs = Box::new([0; 0x120 / 4]);
+        // MagicValue, Version and DeviceID.
+        regs[0] = 0x7472_6976;
+        regs[1] = 2;
+        regs[2] = 2;
+        regs[0x010 / 4] = 1;
+        regs[0x034 / 4] = 16;
+        regs[0x100 / 4] = sectors as u32;
+        Self {
+            regs,
+            disk: (0..sectors * SECTOR_SIZE).map(|i| i as u8).collect(),
+            requests: Vec::new(),
+            last_avail_idx: 0,
+        }
+    }
+
+    pub fn mmio(&mut self) -> Mmio {
+        regs_window(&mut self.regs[..])
+    }
+
+    fn reg_addr(&self, low: usize) -> usize {
+        (self.regs[low / 4] as u64 | (self.regs[low / 4 + 1] as u64) << 32) as usize
+    }
+
+    /// Serves the requests made available since the last call, and raises the interrupt
+    /// if there were any.
+    pub fn process(&mut self) {
+        let size = self.regs[0x038 / 4] as u16;
+        let desc = self.reg_addr(0x080) as *const u8;
+        let avail = self.reg_addr(0x090) as *const u16;
+        let used = self.reg_addr(0x0a0) as *mut u16;
+        let avail_idx = unsafe { ptr::read_volatile(avail.add(1)) };
+        if avail_idx == self.last_avail_idx {
+            return;
+        }
+        while self.last_avail_idx != avail_idx {
+            let slot = (self.last_avail_idx % size) as usize;
+            let head = unsafe { ptr::read_volatile(avail.add(2 + slot)) };
+            let chain = unsafe { read_chain(desc, head) };
+            let written = self.serve(&chain);
+            unsafe {
+                let used_idx = ptr::read_volatile(used.add(1));
+                let elem = used.add(2 + 4 * (used_idx % size) as usize) as *mut u32;
+                ptr::write_volatile(elem, head as u32);
+                ptr::write_volatile(elem.add(1), written);
+                ptr::write_volatile(used.add(1), used_idx.wrapping_add(1));
+            }
+            self.last_avail_idx = self.last_avail_idx.wrapping_add(1);
+        }
+        self.regs[0x060 / 4] |= 1;
+    }
+
+    /// Serves the request of `chain`, the header, the data buffers and the status.
+    /// Returns the number of bytes written to the chain.
+    fn serve(&mut self, chain: &[(usize, u32)]) -> u32 {
+        let (header, _) = chain[0];
+        let (status, _) = chain[chain.len() - 1];
+        let data = &chain[1..chain.len() - 1];
+        let (ty, sector) = unsafe {
+            (
+                ptr::read(header as *const u32),
+                ptr::read((header + 8) as *const u64),
+            )
+        };
+        self.requests
+            .push(data.iter().map(|&(_, len)| len).collect());
+
+        let mut pos = sector as usize * SECTOR_SIZE;
+        let mut written = 1;
+        let total: usize = data.iter().map(|&(_, len)| len as usize).sum();
+        let ok = ty == 4 || pos + total <= self.disk.len();
+        if ok {
+            for &(addr, len) in data {
+                let len = len as usize;
+                let buf = addr as *mut u8;
+                match ty {
+                    0 => unsafe {
+                        ptr::copy_nonoverlapping(self.disk[pos..].as_ptr(), buf, len);
+                        written += len as u32;
+                    },
+                    1 => unsafe {
+                        ptr::copy_nonoverlapping(buf, self.disk[pos..].as_mut_ptr(), len);
+                    },
+                    _ => {}
+                }
+                pos += len;
+            }
+        }
+        unsafe { ptr::write_volatile(status as *mut u8, if ok { 0 } else { 1 }) };
+        written
+    }
+}
+
+/// The `(address, length)` of the descriptors of the chain at `head`.
+unsafe fn read_chain(desc: *const u8, head: u16) -> Vec<(usize, u32)> {
+    let mut chain = Vec::new();
+    let mut id = head as usize;
+    loop {
+        let entry = desc.add(16 * id);
+        let addr = ptr::read(entry as *const u64) as usize;
+        let len = ptr::read(entry.add(8) as *const u32);
+        let flags = ptr::read(entry.add(12) as *const u16);
+        chain.push((addr, len));
+        if flags & 1 == 0 {
+            return chain;
+        }
+        id = ptr::read(entry.add(14) as *const u16) as usize;
+    }
+}
@@ -0,0 +1,320 @@
+//! A split virtqueue: the descriptor table, the available ring the driver hands the
+//! descriptor chains of its requests to the device in, and the used ring the device
+//! returns them in.
+
+use core::{
+    marker::PhantomData,
+    ptr,
+    sync::atomic::{fence, Ordering},
+};
+
+use mm::{Addr, PhysicalAddress};
+
+use crate::{transport::Device, Error, Hal, Result, PAGE_SIZE};
+
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+
+#[repr(C)]
+struct Desc {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+/// Where the parts of a queue of `size` entries are, as physical addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueLayout {
+    pub size: u16,
+    /// The descriptor table.
+    pub desc: usize,
+    /// The available ring.
+    pub driver: usize,
+    /// The used ring.
+    pub device: usize,
+}
+
+impl QueueLayout {
+    /// The layout of the legacy interface, which the queues of both versions are allocated
+    /// with: the descriptor table starts at `base` and is followed by the available ring,
+    /// the used ring starts at the next page boundary.
+    pub fn legacy(base: usize, size: u16) -> Self {
+        let n = size as usize;
+        let driver = base + 16 * n;
+        let avail_end = driver + 6 + 2 * n;
+        Self {
+            size,
+            desc: base,
+            driver,
+            device: (avail_end + PAGE_SIZE - 1) & !(PAGE_SIZE - 1),
+        }
+    }
+
+    /// The bytes from the start of the descriptor table to the end of the used ring.
+    fn len(&self) -> usize {
+        self.device + 6 + 8 * self.size as usize - self.desc
+    }
+}
+
+/// A buffer of a request.
+#[derive(Debug, Clone, Copy)]
+pub struct Buf {
+    pub addr: PhysicalAddress,
+    pub len: u32,
+    /// The device writes the buffer, instead of reading it.
+    pub device_writes: bool,
+}
+
+pub struct VirtQueue<H: Hal> {
+    index: u32,
+    layout: QueueLayout,
+    pages: usize,
+    desc: *mut Desc,
+    /// `flags`, `idx`, then the ring.
+    avail: *mut u16,
+    /// `flags`, `idx`, then the ring of `UsedElem`s.
+    used: *mut u16,
+    /// The free descriptors are linked through their `next`.
+    free_head: u16,
+    num_free: u16,
+    avail_idx: u16,
+    last_used_idx: u16,
+    _hal: PhantomData<H>,
+}
+
+// The queue memory is only accessed through `&mut self`.
+unsafe impl<H: Hal> Send for VirtQueue<H> {}
+
+impl<H: Hal> VirtQueue<H> {
+    /// Allocates the queue `index` of `device`, of at most `size` entries,
+    /// and programs the device with it.
+    pub fn new(device: &Device, index: u32, size: u16) -> Result<Self> {
+        let size = match device.queue_num_max(index) {
+            0 => return Err(Error::QueueUnavailable),
+            max => size.min(max.min(u16::MAX as u32) as u16),
+        };
+        let pages = (QueueLayout::legacy(0, size).len() + PAGE_SIZE - 1) / PAGE_SIZE;
+        let base = H::dma_alloc(pages).ok_or(Error::DmaErr)?;
+        let layout = QueueLayout::legacy(base.inner(), size);
+        let vbase = H::phys_to_virt(base);
+        let queue = Self {
+            index,
+            layout,
+            pages,
+            desc: vbase.as_mut_ptr(),
+            avail: vbase.add(layout.driver - layout.desc).as_mut_ptr(),
+            used: vbase.add(layout.device - layout.desc).as_mut_ptr(),
+            free_head: 0,
+            num_free: size,
+            avail_idx: 0,
+            last_used_idx: 0,
+            _hal: PhantomData,
+        };
+        for id in 0..size - 1 {
+            unsafe { (*queue.desc.add(id as usize)).next = id + 1 };
+        }
+        device.setup_queue(index, &layout)?;
+        Ok(queue)
+    }
+
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn size(&self) -> u16 {
+        self.layout.size
+    }
+
+    pub fn num_free(&self) -> u16 {
+        self.num_free
+    }
+
+    /// The id `add` returns next, `None` if there is no free descriptor.
+    pub fn next_head(&self) -> Option<u16> {
+        if self.num_free == 0 {
+            None
+        } else {
+            Some(self.free_head)
+        }
+    }
+
+    /// Chains a descriptor for each of `bufs` and makes the chain available to the device,
+    /// returns the id of its head. `None` if there are not enough free descriptors.
+    /// The device is notified by the caller.
+    pub fn add(&mut self, bufs: &[Buf]) -> Option<u16> {
+        if bufs.is_empty() || bufs.len() > self.num_free as usize {
+            return None;
+        }
+        let head = self.free_head;
+        for (i, buf) in bufs.iter().enumerate() {
+            let desc = unsafe { &mut *self.desc.add(self.free_head as usize) };
+            desc.addr = buf.addr.inner() as u64;
+            desc.len = buf.len;
+            desc.flags = if buf.device_writes { DESC_F_WRITE } else { 0 };
+            // The next free descriptor is the next one of the chain.
+            if i + 1 < bufs.len() {
+                desc.flags |= DESC_F_NEXT;
+            }
+            self.free_head = desc.next;
+        }
+        self.num_free -= bufs.len() as u16;
+
+        let slot = (self.avail_idx % self.layout.size) as usize;
+        unsafe { ptr::write_volatile(self.avail.add(2 + slot), head) };
+        // The device must see the chain and the ring entry before the index,
+        // and the index before it is notified.
+        fence(Ordering::SeqCst);
+        self.avail_idx = self.avail_idx.wrapping_add(1);
+        unsafe { ptr::write_volatile(self.avail.add(1), self.avail_idx) };
+        fence(Ordering::SeqCst);
+        Some(head)
+    }
+
+    /// Returns the head of the next chain the device is done with,
+    /// and the number of bytes it wrote to the chain.
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        let used_idx = unsafe { ptr::read_volatile(self.used.add(1)) };
+        if used_idx == self.last_used_idx {
+            return None;
+        }
+        // The entry is read after the index it is published with.
+        fence(Ordering::SeqCst);
+        let slot = (self.last_used_idx % self.layout.size) as usize;
+        let elem = unsafe { ptr::read_volatile((self.used.add(2) as *const UsedElem).add(slot)) };
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+        Some((elem.id as u16, elem.len))
+    }
+
+    /// Frees the descriptors of the chain at `head`, once the device is done with it.
+    pub fn recycle(&mut self, head: u16) {
+        let mut id = head;
+        loop {
+            let desc = unsafe { &mut *self.desc.add(id as usize) };
+            self.num_free += 1;
+            if desc.flags & DESC_F_NEXT == 0 {
+                desc.next = self.free_head;
+                break;
+            }
+            id = desc.next;
+        }
+        self.free_head = head;
+    }
+}
+
+/// The device must be reset first, so it no longer accesses the queue.
+impl<H: Hal> Drop for VirtQueue<H> {
+    fn drop(&mut self) {
+        H::dma_dealloc(PhysicalAddress(self.layout.desc), self.pages);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::ptr;
+    use std::vec::Vec;
+
+    use mm::PhysicalAddress;
+
+    use super::{Buf, QueueLayout, UsedElem, VirtQueue, DESC_F_NEXT, DESC_F_WRITE};
+    use crate::{
+        mock::{MockBlk, TestHal},
+        Device, Error,
+    };
+
+    #[test]
+    fn legacy_layout_aligns_the_used_ring() {
+        let layout = QueueLayout::legacy(0x8000_0000, 16);
+        assert_eq!(layout.desc, 0x8000_0000);
+        assert_eq!(layout.driver, 0x8000_0100);
+        assert_eq!(layout.device, 0x8000_1000);
+        // 256 entries fill the first page with the descriptor table alone.
+        assert_eq!(QueueLayout::legacy(0, 256).device, 0x2000);
+    }
+
+    fn buf(addr: usize, len: u32, device_writes: bool) -> Buf {
+        Buf {
+            addr: PhysicalAddress(addr),
+            len,
+            device_writes,
+        }
+    }
+
+    // Returns the chain at `id` as the device does.
+    fn push_used(queue: &VirtQueue<TestHal>, id: u32, len: u32) {
+        unsafe {
+            let idx = ptr::read(queue.used.add(1));
+            let slot = (idx % queue.size()) as usize;
+            ptr::write(
+                (queue.used.add(2) as *mut UsedElem).add(slot),
+                UsedElem { id, len },
+            );
+            ptr::write(queue.used.add(1), idx.wrapping_add(1));
+        }
+    }
+
+    #[test]
+    fn chains_are_made_available_and_recycled() {
+        let mut mock = MockBlk::new(8);
+        let device = Device::probe(mock.mmio()).unwrap();
+        let mut queue = VirtQueue::<TestHal>::new(&device, 0, 4).unwrap();
+        assert_eq!(queue.size(), 4);
+
+        let bufs = [
+            buf(0x1000, 16, false),
+            buf(0x2000, 512, true),
+            buf(0x3000, 1, true),
+        ];
+        assert_eq!(queue.add(&bufs), Some(0));
+        assert_eq!(queue.num_free(), 1);
+        unsafe {
+            let descs: Vec<_> = (0..3)
+                .map(|id| {
+                    let desc = &*queue.desc.add(id);
+                    (desc.addr, desc.len, desc.flags, desc.next)
+                })
+                .collect();
+            assert_eq!(
+                descs,
+                [
+                    (0x1000, 16, DESC_F_NEXT, 1),
+                    (0x2000, 512, DESC_F_NEXT | DESC_F_WRITE, 2),
+                    (0x3000, 1, DESC_F_WRITE, 3),
+                ]
+            );
+            // The chain is in the first entry of the ring, and the index counts it.
+            assert_eq!(ptr::read(queue.avail.add(2)), 0);
+            assert_eq!(ptr::read(queue.avail.add(1)), 1);
+        }
+        // Not enough descriptors are left for another chain.
+        assert_eq!(queue.add(&bufs), None);
+
+        assert_eq!(queue.pop_used(), None);
+        push_used(&queue, 0, 513);
+        assert_eq!(queue.pop_used(), Some((0, 513)));
+        assert_eq!(queue.pop_used(), None);
+        queue.recycle(0);
+        assert_eq!(queue.num_free(), 4);
+        // The descriptors are taken again, last freed first.
+        assert_eq!(queue.next_head(), Some(0));
+        assert_eq!(queue.add(&bufs[..1]), Some(0));
+        assert_eq!(queue.add(&bufs[..1]), Some(1));
+    }
+
+    #[test]
+    fn queues_the_device_does_not_have_are_refused() {
+        let mut mock = MockBlk::new(8);
+        mock.regs[0x034 / 4] = 0;
+        let device = Device::probe(mock.mmio()).unwrap();
+        assert!(matches!(
+            VirtQueue::<TestHal>::new(&device, 0, 4),
+            Err(Error::QueueUnavailable)
+        ));
+    }
+}
@@ -0,0 +1,375 @@
+//! The registers of a virtio-mmio device, and the initialization sequence of its driver.
+//!
+//! A version 1 device takes 32 bits of features and the page number of each queue in
+//! `QueuePFN`. A version 2 device takes 64 bits of features, must be offered
+//! `VIRTIO_F_VERSION_1`, confirms them with `FEATURES_OK`, and takes the address of
+//! each of the three parts of a queue.
+
+use mmio::{Mmio, Reg};
+
+use crate::{queue::QueueLayout, Error, Result, PAGE_SIZE};
+
+const MAGIC_VALUE: Reg<u32> = Reg::new(0x000);
+const VERSION: Reg<u32> = Reg::new(0x004);
+const DEVICE_ID: Reg<u32> = Reg::new(0x008);
+const DEVICE_FEATURES: Reg<u32> = Reg::new(0x010);
+const DEVICE_FEATURES_SEL: Reg<u32> = Reg::new(0x014);
+const DRIVER_FEATURES: Reg<u32> = Reg::new(0x020);
+const DRIVER_FEATURES_SEL: Reg<u32> = Reg::new(0x024);
+const QUEUE_SEL: Reg<u32> = Reg::new(0x030);
+const QUEUE_NUM_MAX: Reg<u32> = Reg::new(0x034);
+const QUEUE_NUM: Reg<u32> = Reg::new(0x038);
+const QUEUE_NOTIFY: Reg<u32> = Reg::new(0x050);
+const INTERRUPT_STATUS: Reg<u32> = Reg::new(0x060);
+const INTERRUPT_ACK: Reg<u32> = Reg::new(0x064);
+const STATUS: Reg<u32> = Reg::new(0x070);
+
+// Registers of the version 1 interface only.
+const GUEST_PAGE_SIZE: Reg<u32> = Reg::new(0x028);
+const QUEUE_ALIGN: Reg<u32> = Reg::new(0x03c);
+const QUEUE_PFN: Reg<u32> = Reg::new(0x040);
+
+// Registers of the version 2 interface only.
+const QUEUE_READY: Reg<u32> = Reg::new(0x044);
+const QUEUE_DESC_LOW: Reg<u32> = Reg::new(0x080);
+const QUEUE_DESC_HIGH: Reg<u32> = Reg::new(0x084);
+const QUEUE_DRIVER_LOW: Reg<u32> = Reg::new(0x090);
+const QUEUE_DRIVER_HIGH: Reg<u32> = Reg::new(0x094);
+const QUEUE_DEVICE_LOW: Reg<u32> = Reg::new(0x0a0);
+const QUEUE_DEVICE_HIGH: Reg<u32> = Reg::new(0x0a4);
+const CONFIG_GENERATION: Reg<u32> = Reg::new(0x0fc);
+
+/// The device specific configuration space starts here.
+const CONFIG: usize = 0x100;
+
+// "virt" in little endian.
+const MAGIC: u32 = 0x7472_6976;
+
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+const STATUS_FEATURES_OK: u32 = 8;
+const STATUS_FAILED: u32 = 128;
+
+/// The device complies with virtio 1.x, a version 2 device must offer it.
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+pub const DEVICE_ID_BLK: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    Legacy,
+    Modern,
+}
+
+/// A virtio-mmio device.
+#[derive(Clone, Copy)]
+pub struct Device {
+    regs: Mmio,
+    version: Version,
+}
+
+impl Device {
+    /// Returns the device whose registers are `regs`.
+    pub fn probe(regs: Mmio) -> Result<Self> {
+        if regs.read(MAGIC_VALUE) != MAGIC {
+            return Err(Error::NotVirtio);
+        }
+        let version = match regs.read(VERSION) {
+            1 => Version::Legacy,
+            2 => Version::Modern,
+            version => return Err(Error::UnknownVersion(version)),
+        };
+        // Slots without a device attached report a device id of 0.
+        if regs.read(DEVICE_ID) == 0 {
+            return Err(Error::NoDevice);
+        }
+        Ok(Self { regs, version })
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn device_id(&self) -> u32 {
+        self.regs.read(DEVICE_ID)
+    }
+
+    /// Resets the device and negotiates the features of `supported` it offers,
+    /// returns the negotiated features. The queues are set up next, then `finish_init`
+    /// lets the device run.
+    pub fn begin_init(&self, supported: u64) -> Result<u64> {
+        self.reset();
+        self.regs.write(STATUS, STATUS_ACKNOWLEDGE);
+        self.regs.write(STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+
+        let offered = self.device_features();
+        let features = match self.version {
+            Version::Legacy => offered & supported & u32::MAX as u64,
+            Version::Modern if offered & VIRTIO_F_VERSION_1 == 0 => {
+                self.fail();
+                return Err(Error::FeaturesRefused);
+            }
+            Version::Modern => offered & (supported | VIRTIO_F_VERSION_1),
+        };
+        self.set_driver_features(features);
+
+        match self.version {
+            Version::Legacy => self.regs.write(GUEST_PAGE_SIZE, PAGE_SIZE as u32),
+            Version::Modern => {
+                self.regs.set_bits(STATUS, STATUS_FEATURES_OK);
+                // The device clears it if it cannot work with these features.
+                if self.regs.read(STATUS) & STATUS_FEATURES_OK == 0 {
+                    self.fail();
+                    return Err(Error::FeaturesRefused);
+                }
+            }
+        }
+        Ok(features)
+    }
+
+    /// Stops the device, it no longer accesses its queues.
+    pub fn reset(&self) {
+        self.regs.write(STATUS, 0);
+    }
+
+    /// Tells the device the driver is ready, once its queues are set up.
+    pub fn finish_init(&self) {
+        self.regs.set_bits(STATUS, STATUS_DRIVER_OK);
+    }
+
+    /// Tells the device the driver gave up on it.
+    pub fn fail(&self) {
+        self.regs.set_bits(STATUS, STATUS_FAILED);
+    }
+
+    fn device_features(&self) -> u64 {
+        self.regs.write(DEVICE_FEATURES_SEL, 0);
+        let low = self.regs.read(DEVICE_FEATURES) as u64;
+        if self.version == Version::Legacy {
+            return low;
+        }
+        self.regs.write(DEVICE_FEATURES_SEL, 1);
+        low | (self.regs.read(DEVICE_FEATURES) as u64) << 32
+    }
+
+    fn set_driver_features(&self, features: u64) {
+        self.regs.write(DRIVER_FEATURES_SEL, 0);
+        self.regs.write(DRIVER_FEATURES, features as u32);
+        if self.version == Version::Modern {
+            self.regs.write(DRIVER_FEATURES_SEL, 1);
+            self.regs.write(DRIVER_FEATURES, (features >> 32) as u32);
+        }
+    }
+
+    /// The most entries the queue `queue` takes, 0 if the device does not have it.
+    pub fn queue_num_max(&self, queue: u32) -> u32 {
+        self.regs.write(QUEUE_SEL, queue);
+        self.regs.read(QUEUE_NUM_MAX)
+    }
+
+    /// Programs the queue `queue` with `layout`, and marks it ready.
+    pub fn setup_queue(&self, queue: u32, layout: &QueueLayout) -> Result<()> {
+        self.regs.write(QUEUE_SEL, queue);
+        let used = match self.version {
+            Version::Legacy => self.regs.read(QUEUE_PFN) != 0,
+            Version::Modern => self.regs.read(QUEUE_READY) != 0,
+        };
+        if used {
+            return Err(Error::QueueAlreadyUsed);
+        }
+        let max = self.regs.read(QUEUE_NUM_MAX);
+        if max == 0 {
+            return Err(Error::QueueUnavailable);
+        }
+        if layout.size as u32 > max {
+            return Err(Error::QueueTooLarge { max });
+        }
+        self.regs.write(QUEUE_NUM, layout.size as u32);
+        match self.version {
+            Version::Legacy => {
+                // The legacy interface finds the rings from the size and the alignment.
+                assert_eq!(*layout, QueueLayout::legacy(layout.desc, layout.size));
+                self.regs.write(QUEUE_ALIGN, PAGE_SIZE as u32);
+                self.regs.write(QUEUE_PFN, (layout.desc / PAGE_SIZE) as u32);
+            }
+            Version::Modern => {
+                self.write_addr(QUEUE_DESC_LOW, QUEUE_DESC_HIGH, layout.desc);
+                self.write_addr(QUEUE_DRIVER_LOW, QUEUE_DRIVER_HIGH, layout.driver);
+                self.write_addr(QUEUE_DEVICE_LOW, QUEUE_DEVICE_HIGH, layout.device);
+                // The device may use the queue as soon as it is ready, so this is written last.
+                self.regs.write(QUEUE_READY, 1);
+            }
+        }
+        Ok(())
+    }
+
+    fn write_addr(&self, low: Reg<u32>, high: Reg<u32>, addr: usize) {
+        self.regs.write(low, addr as u32);
+        self.regs.write(high, (addr as u64 >> 32) as u32);
+    }
+
+    /// Tells the device there are new buffers in the queue `queue`.
+    pub fn notify(&self, queue: u32) {
+        self.regs.write(QUEUE_NOTIFY, queue);
+    }
+
+    /// Acknowledges the interrupt of the device, returns the reasons it was raised for.
+    pub fn ack_interrupt(&self) -> u32 {
+        let status = self.regs.read(INTERRUPT_STATUS);
+        self.regs.write(INTERRUPT_ACK, status);
+        status
+    }
+
+    /// Reads the register `reg` of the device specific configuration space.
+    pub fn read_config<T: Copy>(&self, reg: Reg<T>) -> T {
+        self.regs.at(CONFIG).read(reg)
+    }
+
+    /// Changes whenever the device updates its configuration space, e.g. on a resize.
+    /// Always 0 for a version 1 device, which does not have the register.
+    pub fn config_generation(&self) -> u32 {
+        match self.version {
+            Version::Legacy => 0,
+            Version::Modern => self.regs.read(CONFIG_GENERATION),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mmio::Reg;
+
+    use super::{Device, VIRTIO_F_VERSION_1};
+    use crate::{mock::regs_window, queue::QueueLayout, Error, Version};
+
+    fn device(version: u32, num_max: u32) -> [u32; 0x110 / 4] {
+        let mut regs = [0u32; 0x110 / 4];
+        // MagicValue, Version and DeviceID.
+        regs[0] = 0x7472_6976;
+        regs[1] = version;
+        regs[2] = 2;
+        regs[0x034 / 4] = num_max;
+        regs
+    }
+
+    fn probe(regs: &mut [u32]) -> Device {
+        Device::probe(regs_window(regs)).unwrap()
+    }
+
+    #[test]
+    fn probes_the_version_of_the_device() {
+        let mut regs = device(2, 16);
+        assert_eq!(probe(&mut regs).version(), Version::Modern);
+        assert_eq!(probe(&mut regs).device_id(), 2);
+        regs[1] = 1;
+        assert_eq!(probe(&mut regs).version(), Version::Legacy);
+
+        let probe = |regs: &mut [u32]| Device::probe(regs_window(regs)).err();
+        regs[1] = 3;
+        assert_eq!(probe(&mut regs), Some(Error::UnknownVersion(3)));
+        regs[1] = 2;
+        regs[2] = 0;
+        assert_eq!(probe(&mut regs), Some(Error::NoDevice));
+        regs[0] = 0;
+        assert_eq!(probe(&mut regs), Some(Error::NotVirtio));
+    }
+
+    // The registers are plain memory, so the device reads as offering the same
+    // features in both halves.
+    #[test]
+    fn version_2_confirms_the_features() {
+        let mut regs = device(2, 16);
+        regs[0x010 / 4] = 0b111;
+        let features = probe(&mut regs).begin_init(0b110).unwrap();
+        assert_eq!(features, 0b110 | VIRTIO_F_VERSION_1);
+        // ACKNOWLEDGE | DRIVER | FEATURES_OK
+        assert_eq!(regs[0x070 / 4], 0b1011);
+        assert_eq!(regs[0x024 / 4], 1);
+
+        // Without VERSION_1 the device is not a virtio 1.x device.
+        regs[0x010 / 4] = 0b110;
+        assert_eq!(
+            probe(&mut regs).begin_init(0b110),
+            Err(Error::FeaturesRefused)
+        );
+        assert_eq!(regs[0x070 / 4] & 128, 128);
+    }
+
+    #[test]
+    fn version_1_takes_32_bits_of_features_and_the_page_size() {
+        let mut regs = device(1, 16);
+        regs[0x010 / 4] = 0b111;
+        let features = probe(&mut regs).begin_init(0b110 | VIRTIO_F_VERSION_1);
+        assert_eq!(features, Ok(0b110));
+        assert_eq!(regs[0x020 / 4], 0b110);
+        assert_eq!(regs[0x024 / 4], 0);
+        assert_eq!(regs[0x028 / 4], 4096);
+        // No FEATURES_OK.
+        assert_eq!(regs[0x070 / 4], 0b11);
+    }
+
+    #[test]
+    fn programs_the_parts_of_a_version_2_queue() {
+        let mut regs = device(2, 32);
+        let layout = QueueLayout {
+            size: 16,
+            desc: 0x1_2345_6000,
+            driver: 0x1_2345_6100,
+            device: 0x1_2345_7000,
+        };
+        let device = probe(&mut regs);
+        assert_eq!(device.setup_queue(1, &layout), Ok(()));
+        device.finish_init();
+        assert_eq!(regs[0x030 / 4], 1);
+        assert_eq!(regs[0x038 / 4], 16);
+        assert_eq!(regs[0x080 / 4..0x088 / 4], [0x2345_6000, 1]);
+        assert_eq!(regs[0x090 / 4..0x098 / 4], [0x2345_6100, 1]);
+        assert_eq!(regs[0x0a0 / 4..0x0a8 / 4], [0x2345_7000, 1]);
+        assert_eq!(regs[0x044 / 4], 1);
+        assert_eq!(regs[0x040 / 4], 0);
+        assert_eq!(regs[0x070 / 4], 4);
+    }
+
+    #[test]
+    fn programs_the_page_of_a_version_1_queue() {
+        let mut regs = device(1, 32);
+        let layout = QueueLayout::legacy(0x8765_4000, 16);
+        assert_eq!(probe(&mut regs).setup_queue(0, &layout), Ok(()));
+        assert_eq!(regs[0x038 / 4], 16);
+        assert_eq!(regs[0x03c / 4], 4096);
+        assert_eq!(regs[0x040 / 4], 0x8_7654);
+        assert_eq!(regs[0x044 / 4], 0);
+        assert_eq!(regs[0x080 / 4], 0);
+    }
+
+    #[test]
+    fn rejects_queues_the_device_cannot_take() {
+        let layout = QueueLayout::legacy(0x1000, 16);
+        let setup = |regs: &mut [u32]| {
+            Device::probe(regs_window(regs))
+                .unwrap()
+                .setup_queue(0, &layout)
+        };
+        let mut regs = device(2, 8);
+        assert_eq!(setup(&mut regs), Err(Error::QueueTooLarge { max: 8 }));
+        let mut regs = device(2, 0);
+        assert_eq!(setup(&mut regs), Err(Error::QueueUnavailable));
+        let mut regs = device(2, 32);
+        regs[0x044 / 4] = 1;
+        assert_eq!(setup(&mut regs), Err(Error::QueueAlreadyUsed));
+        // Nothing is programmed into a rejected queue.
+        assert_eq!(regs[0x038 / 4], 0);
+        assert_eq!(regs[0x080 / 4], 0);
+    }
+
+    #[test]
+    fn reads_the_configuration_space() {
+        let mut regs = device(2, 16);
+        regs[0x104 / 4] = 0xabcd;
+        regs[0x0fc / 4] = 3;
+        let device = probe(&mut regs);
+        assert_eq!(device.read_config(Reg::<u32>::new(4)), 0xabcd);
+        assert_eq!(device.config_generation(), 3);
+    }
+}
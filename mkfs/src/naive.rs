@@ -7,6 +7,7 @@ extern crate log;
 
 use std::{
     any::Any,
+    convert::TryFrom,
     fs::Metadata,
     future::Future,
     path::{Path, PathBuf},
@@ -214,12 +215,10 @@ fn copy_file<'a>(
     Box::pin(async move {
         for file in files {
             let attr = tokio::fs::metadata(&file).await?;
-            let filename = file
-                .file_name()
-                .unwrap()
-                .to_string_lossy()
-                .as_bytes()
-                .into();
+            let filename = naive_fs::DirEntryName::try_from(
+                file.file_name().unwrap().to_string_lossy().as_bytes(),
+            )
+            .map_err(naive_fs_err_to_stdio_err)?;
             if attr.is_dir() {
                 let mut read_dir = tokio::fs::read_dir(&file).await?;
                 let mut children = Vec::new();
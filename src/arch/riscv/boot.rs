@@ -1,8 +1,13 @@
 use crate::kmain;
-use core::arch::{asm, global_asm};
+use core::{
+    arch::{asm, global_asm},
+    ops::Range,
+};
 
+// Stack size of each hart, see `entry.asm`
+const HART_STACK_SIZE: usize = 1 << 17;
 // TODO `8` use config
-const BOOT_STACK_SIZE: usize = (1 << 17) * 8;
+const BOOT_STACK_SIZE: usize = HART_STACK_SIZE * 8;
 
 #[repr(C)]
 struct BootStack([u8; BOOT_STACK_SIZE]);
@@ -15,6 +20,13 @@ extern "C" {
     static mut _boot_page_table: usize;
 }
 
+/// Returns the address range of the stack of the hart `hartid`,
+/// all kernel code running on the hart uses it.
+pub fn boot_stack(hartid: usize) -> Range<usize> {
+    let start = unsafe { BOOT_STACK.0.as_ptr() as usize } + hartid * HART_STACK_SIZE;
+    start..start + HART_STACK_SIZE
+}
+
 #[export_name = "_boot"]
 extern "C" fn boot(hartid: usize, dtb_pa: usize) -> ! {
     // Write hartid to tp register for cpu_id()
@@ -4,8 +4,12 @@ use mm::PhysicalAddress;
 pub const USER_STACK_OFFSET: usize = 0x3f_ffff_f000;
 // User Stack Size (1MB)
 pub const USER_STACK_SIZE: usize = 1024 * 1024;
-// Memory end address
+// Size the user stack may grow to on page faults (8MB)
+pub const USER_STACK_MAX_SIZE: usize = 8 * 1024 * 1024;
+// Memory end address, used when the device tree does not describe the memory
 pub const MEMORY_END_ADDRESS: PhysicalAddress = PhysicalAddress(0x88000000);
+// The boot page table maps 1GiB of memory from 0x8000_0000, memory above is not reachable
+pub const MEMORY_MAX_END_ADDRESS: PhysicalAddress = PhysicalAddress(0xC000_0000);
 
 /// MMIO device segment memory area start address
 pub const DEVICE_START_ADDRESS: PhysicalAddress = PhysicalAddress(0x0C00_0000);
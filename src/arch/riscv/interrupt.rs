@@ -57,6 +57,11 @@ impl Context {
         self.epc = pc.0;
     }
 
+    /// Sets the thread pointer, which points to the thread local storage.
+    pub fn set_tls(&mut self, tls: usize) {
+        self.tp = tls;
+    }
+
     pub fn run_user(&mut self) -> *mut Trap {
         let mut sstatus: usize;
         unsafe { asm!("csrr {}, sstatus", out(reg) sstatus) };
@@ -169,9 +174,11 @@ extern "C" fn user_trap_handler(_tf: &mut Context) -> *mut Trap {
             Trap::Interrupt
         }
         scause::Trap::Exception(scause::Exception::UserEnvCall) => Trap::Syscall,
-        scause::Trap::Exception(scause::Exception::StorePageFault) => {
-            Trap::PageFault(stval::read().into())
-        }
+        scause::Trap::Exception(
+            scause::Exception::StorePageFault
+            | scause::Exception::LoadPageFault
+            | scause::Exception::InstructionPageFault,
+        ) => Trap::PageFault(stval::read().into()),
         _ => {
             crate::println!("ucause: {:?}", scause.cause());
             crate::println!("ustval: 0x{:x}", stval::read());
@@ -240,12 +247,20 @@ pub fn timer_now() -> Duration {
     Duration::from_nanos(time * 100)
 }
 
-fn set_next_timer_interrupt() {
+/// Programs the next periodic timer interrupt.
+pub fn set_next_timer_interrupt() {
     // 10Hz @ QEMU
     let timebase = 1000000;
     sbi::set_timer(get_cycle() + timebase);
 }
 
+/// Programs a single timer interrupt at `deadline`, on the clock of `timer_now`,
+/// `None` turns the timer interrupt off.
+pub fn set_timer_deadline(deadline: Option<Duration>) {
+    let cycle = deadline.map_or(u64::MAX, |deadline| (deadline.as_nanos() / 100) as u64);
+    sbi::set_timer(cycle);
+}
+
 /// Enable external interrupt
 unsafe fn init_ext_irq() {
     sie::set_sext();
@@ -254,7 +269,7 @@ unsafe fn init_ext_irq() {
 pub unsafe fn register_external_irq(
     _interrupt_controller_num: u32,
     irq_num: u32,
-    irq_ack_fn: Box<dyn Fn()>,
+    irq_ack_fn: Box<dyn Fn() + Send + Sync>,
 ) {
     plic().register_external_irq(irq_num);
     set_driver_irq_ack_fn(irq_num, irq_ack_fn);
@@ -1,3 +1,8 @@
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
 use alloc::vec::Vec;
 use mm::{
     arch::page::PageParam as PageParamA,
@@ -19,12 +24,36 @@ extern "C" {
     fn kernel_end();
 }
 
-pub fn memory_range() -> (PhysicalAddress, PhysicalAddress) {
+// End of the memory region holding the kernel, as found by `memory_range`.
+static MEMORY_END: AtomicUsize = AtomicUsize::new(consts::MEMORY_END_ADDRESS.0);
+
+/// Returns the range of physical memory after the kernel image,
+/// up to the end of the region of `ram` holding the kernel.
+/// Regions in the range may still be reserved, see `kernel_regions`.
+pub fn memory_range(ram: &[Range<PhysicalAddress>]) -> (PhysicalAddress, PhysicalAddress) {
     let start = PageParamA::linear_kvirt_to_phys(VirtualAddress(kernel_end as usize));
-    let end = consts::MEMORY_END_ADDRESS;
+    let end = ram
+        .iter()
+        .find(|region| region.start <= start && start < region.end)
+        .map_or(consts::MEMORY_END_ADDRESS, |region| region.end)
+        .min(consts::MEMORY_MAX_END_ADDRESS);
+    MEMORY_END.store(end.0, Ordering::Relaxed);
     (start, end)
 }
 
+/// Returns the physical memory taken by the kernel image,
+/// its code, data and the boot stacks in `.bss`.
+pub fn kernel_regions() -> Vec<Range<PhysicalAddress>> {
+    vec![
+        PageParamA::linear_kvirt_to_phys(VirtualAddress(kernel_start as usize))
+            ..PageParamA::linear_kvirt_to_phys(VirtualAddress(kernel_end as usize)),
+    ]
+}
+
+fn memory_end() -> PhysicalAddress {
+    PhysicalAddress(MEMORY_END.load(Ordering::Relaxed))
+}
+
 pub const fn user_stack_offset() -> usize {
     consts::USER_STACK_OFFSET
 }
@@ -37,6 +66,16 @@ pub const fn user_stack_size() -> usize {
     consts::USER_STACK_SIZE
 }
 
+pub const fn user_stack_max_size() -> usize {
+    consts::USER_STACK_MAX_SIZE
+}
+
+/// The user address space anonymous mappings are placed in,
+/// the page at 0 is left unmapped to catch null pointers.
+pub const fn user_mmap_range() -> Range<VirtualAddress> {
+    VirtualAddress(PageParamA::PAGE_SIZE)..VirtualAddress(user_stack_offset())
+}
+
 pub fn kernel_segments() -> Vec<Segment> {
     vec![
         // mmio device segment, rw-
@@ -81,7 +120,7 @@ pub fn kernel_segments() -> Vec<Segment> {
         // remaining memory space，rw-
         Segment {
             addr_range: VirtualAddress(kernel_end as usize)
-                ..PageParamA::linear_phys_to_kvirt(consts::MEMORY_END_ADDRESS),
+                ..PageParamA::linear_phys_to_kvirt(memory_end()),
             flags: PageParamA::flag_set_kernel(
                 PageParamA::FLAG_PTE_READABLE | PageParamA::FLAG_PTE_WRITEABLE,
             ),
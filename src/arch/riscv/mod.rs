@@ -1,5 +1,7 @@
 use core::arch::asm;
 
+use mm::{arch::page::PageParam as PageParamA, page::PageParam as _, VirtualAddress};
+
 mod boot;
 pub mod consts;
 pub mod interrupt;
@@ -17,6 +19,39 @@ pub fn getchar() -> u8 {
     sbi::console_getchar() as u8
 }
 
+/// Read a character from the console, returns `None` if no input is pending.
+pub fn try_getchar() -> Option<u8> {
+    match sbi::console_getchar() as isize {
+        -1 => None,
+        c => Some(c as u8),
+    }
+}
+
+extern "C" {
+    fn _start();
+}
+
+/// Starts the hart `hartid` at the kernel entry, where it calls `kmain(hartid, dtb_pa)`.
+/// Returns false if the hart does not exist or is already running.
+pub fn start_hart(hartid: usize, dtb_pa: usize) -> bool {
+    let start_pa = PageParamA::linear_kvirt_to_phys(VirtualAddress(_start as usize));
+    sbi::hart_start(hartid, start_pa.0, dtb_pa).is_ok()
+}
+
+pub use boot::boot_stack;
+
+/// Returns the frame pointer of the caller, the frame record below it holds the return address
+/// at `fp - 8` and the frame pointer of the previous frame at `fp - 16`.
+/// Meaningful only if the kernel is built with `-C force-frame-pointers=yes`.
+#[inline(always)]
+pub fn frame_pointer() -> usize {
+    let fp: usize;
+    unsafe {
+        asm!("mv {}, s0", out(reg) fp);
+    }
+    fp
+}
+
 pub fn cpu_id() -> usize {
     let id: usize;
     unsafe {
@@ -1,22 +1,29 @@
-use core::{mem::MaybeUninit, ptr};
+use core::mem::MaybeUninit;
 
-use mm::{Addr, VirtualAddress};
+use mm::VirtualAddress;
+use mmio::{Mmio, Reg};
 
 static mut PLIC: MaybeUninit<Plic> = MaybeUninit::uninit();
 
+// Size of the register space of a PLIC.
+const PLIC_LEN: usize = 0x400_0000;
+// Offset of the S-mode context registers of hart 0, and the stride between harts.
+const SCONTEXT_OFFSET: usize = 0x201000;
+const SCONTEXT_STRIDE: usize = 0x2000;
+// Offset of the S-mode interrupt enable bits of hart 0, and the stride between harts.
+const SENABLE_OFFSET: usize = 0x2080;
+const SENABLE_STRIDE: usize = 0x100;
+
+// Registers of an S-mode context.
+const THRESHOLD: Reg<u32> = Reg::new(0);
+const CLAIM: Reg<u32> = Reg::new(4);
+
 pub fn init(base_addr: VirtualAddress, hart: usize) {
     unsafe {
         PLIC = MaybeUninit::new(Plic::new(base_addr, hart));
-
-        // set this hart's S-mode priority threshold to 0.
-        ptr::write_volatile(
-            base_addr
-                .add(0x201000)
-                .add(hart.wrapping_mul(0x2000))
-                .as_mut_ptr(),
-            0,
-        );
     }
+    // set this hart's S-mode priority threshold to 0.
+    plic().scontext().write(THRESHOLD, 0);
 }
 
 pub fn plic() -> &'static mut Plic {
@@ -24,40 +31,39 @@ pub fn plic() -> &'static mut Plic {
 }
 
 pub struct Plic {
-    base_addr: VirtualAddress,
+    regs: Mmio,
     hart: usize,
 }
 
 impl Plic {
     pub fn new(base_addr: VirtualAddress, hart: usize) -> Self {
-        Self { base_addr, hart }
+        Self {
+            regs: unsafe { Mmio::new(base_addr, PLIC_LEN) },
+            hart,
+        }
     }
 
     pub unsafe fn register_external_irq(&mut self, irq_num: u32) {
-        let senable_p: *mut u32 = self
-            .base_addr
-            .add(0x2080)
-            .add(self.hart.wrapping_mul(0x100))
-            .as_mut_ptr();
-        ptr::write_volatile(senable_p, *senable_p | 1 << irq_num);
+        let senable = Reg::new(
+            SENABLE_OFFSET + self.hart.wrapping_mul(SENABLE_STRIDE) + irq_num as usize / 32 * 4,
+        );
+        self.regs.set_bits(senable, 1 << (irq_num % 32));
         // set priority to 7
-        ptr::write_volatile(self.base_addr.add(irq_num as usize * 4).as_mut_ptr(), 7);
+        self.regs.write(Reg::<u32>::new(irq_num as usize * 4), 7);
     }
 
-    fn plic_sclaim(&self) -> *mut u32 {
-        self.base_addr
-            .add(0x201004)
-            .add(self.hart.wrapping_mul(0x2000))
-            .as_mut_ptr()
+    fn scontext(&self) -> Mmio {
+        self.regs
+            .at(SCONTEXT_OFFSET + self.hart.wrapping_mul(SCONTEXT_STRIDE))
     }
 
     /// ask the PLIC what interrupt we should serve.
     pub unsafe fn plic_claim(&self) -> u32 {
-        ptr::read_volatile(self.plic_sclaim())
+        self.scontext().read(CLAIM)
     }
 
     /// tell the PLIC we've served this IRQ.
     pub unsafe fn plic_complete(&self, irq: u32) {
-        ptr::write_volatile(self.plic_sclaim(), irq)
+        self.scontext().write(CLAIM, irq)
     }
 }
@@ -14,6 +14,25 @@ fn sbi_call_legacy(which: usize, arg0: usize, arg1: usize, arg2: usize) -> usize
     ret
 }
 
+/// Calls a function of an SBI extension, returns the value or the SBI error code.
+#[inline(always)]
+fn sbi_call(eid: usize, fid: usize, arg0: usize, arg1: usize, arg2: usize) -> Result<usize, isize> {
+    let (error, value): (isize, usize);
+    unsafe {
+        asm!(
+            "ecall",
+            in("a0") arg0, in("a1") arg1, in("a2") arg2,
+            in("a6") fid, in("a7") eid,
+            lateout("a0") error, lateout("a1") value,
+        )
+    };
+    if error == 0 {
+        Ok(value)
+    } else {
+        Err(error)
+    }
+}
+
 const SBI_SET_TIMER: usize = 0;
 const SBI_CONSOLE_PUTCHAR: usize = 1;
 const SBI_CONSOLE_GETCHAR: usize = 2;
@@ -24,6 +43,10 @@ const SBI_REMOTE_SFENCE_VMA: usize = 6;
 const SBI_REMOTE_SFENCE_VMA_ASID: usize = 7;
 const SBI_SHUTDOWN: usize = 8;
 
+/// Hart State Management extension
+const SBI_EXT_HSM: usize = 0x48534D;
+const SBI_HSM_HART_START: usize = 0;
+
 pub fn console_putchar(c: usize) {
     sbi_call_legacy(SBI_CONSOLE_PUTCHAR, c, 0, 0);
 }
@@ -43,3 +66,9 @@ pub fn set_timer(time: u64) {
     #[cfg(target_pointer_width = "64")]
     sbi_call_legacy(SBI_SET_TIMER, time as usize, 0, 0);
 }
+
+/// Starts the stopped hart `hartid` in supervisor mode at the physical address `start_addr`,
+/// with `hartid` in a0 and `opaque` in a1.
+pub fn hart_start(hartid: usize, start_addr: usize, opaque: usize) -> Result<(), isize> {
+    sbi_call(SBI_EXT_HSM, SBI_HSM_HART_START, hartid, start_addr, opaque).map(|_| ())
+}
@@ -5,6 +5,7 @@ use core::mem;
 use super::interrupt;
 
 pub struct Context {
+    pub epc: usize,
     pub ra: usize,
     pub sp: usize,
     pub gp: usize,
@@ -41,6 +42,7 @@ pub struct Context {
 impl Context {
     pub fn from_interr_ctx(interr_ctx: &interrupt::Context) -> Self {
         Self {
+            epc: interr_ctx.epc,
             ra: interr_ctx.ra,
             sp: interr_ctx.sp,
             gp: interr_ctx.gp,
@@ -76,6 +78,7 @@ impl Context {
     }
 
     pub fn fill_interr_ctx(&self, interr_ctx: &mut interrupt::Context) {
+        interr_ctx.epc = self.epc;
         interr_ctx.ra = self.ra;
         interr_ctx.sp = self.sp;
         interr_ctx.gp = self.gp;
@@ -110,6 +113,16 @@ impl Context {
     }
 }
 
+impl signal::SyscallContext for Context {
+    fn syscall_num(&self) -> usize {
+        self.a7
+    }
+
+    fn interrupt(&mut self) {
+        self.a0 = (-(crate::syscall::Error::EINTR as isize)) as usize;
+    }
+}
+
 pub fn set_signal_handler(
     interr_ctx: &mut interrupt::Context,
     sp: usize,
@@ -121,8 +134,8 @@ pub fn set_signal_handler(
     interr_ctx.sp = sp;
     interr_ctx.epc = signal_handler_wapper as usize;
     interr_ctx.a0 = handler;
-    interr_ctx.a1 = flags.bits();
-    interr_ctx.a2 = signo;
+    interr_ctx.a1 = signo;
+    interr_ctx.a2 = flags.bits();
     interr_ctx.a3 = siginfo as usize;
 }
 
@@ -46,6 +46,8 @@ pub mod consts {
     pub const USER_STACK_OFFSET: usize = 0x3f_ffff_f000;
     // User Stack Size (1MB)
     pub const USER_STACK_SIZE: usize = 1024 * 1024;
+    // Size the user stack may grow to on page faults (8MB)
+    pub const USER_STACK_MAX_SIZE: usize = 8 * 1024 * 1024;
     // Memory end address
     pub const MEMORY_END_ADDRESS: PhysicalAddress = PhysicalAddress(0x88000000);
 }
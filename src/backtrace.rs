@@ -0,0 +1,15 @@
+//! Stack backtraces from the frame pointer chain,
+//! the kernel must be built with `-C force-frame-pointers=yes`.
+
+use backtrace::Frames;
+
+use crate::{arch, cpu, println};
+
+/// Prints the return addresses of the frames on the stack of the current hart.
+pub fn print() {
+    println!("backtrace:");
+    let frames = Frames::new(arch::frame_pointer(), arch::boot_stack(cpu::cpu_id()));
+    for (idx, ra) in frames.enumerate() {
+        println!("{:>4}: {:#x}", idx, ra);
+    }
+}
@@ -2,9 +2,23 @@
 pub const KERNEL_STACK_SIZE: usize = 1913;
 /// CPU maximum number of cores
 pub const NCPU: usize = 8;
-/// Max thread id
+/// Max thread id, thread ids are below it
 pub const MAX_THREAD_ID: u32 = 32767;
 /// Thread reserved id, after thread grows to maximum, returns to THREAD_RESERVED_ID and grows upwards
 pub const THREAD_RESERVED_ID: u32 = 255;
+/// Default maximum number of signals queued for a thread, or shared by a process
+pub const SIGPENDING_QUEUE_CAP: usize = 128;
 /// Maximum number of files that can be opened by the process
 pub const PROC_MAX_OPEN_FILES: usize = 65_536;
+/// Number of blocks a `Disk` reads ahead of sequential reads, 0 disables readahead
+pub const DISK_READ_AHEAD_BLKS: usize = 8;
+/// Index of the block device used as swap, see `driver::blk_driver`. `None` disables swap
+pub const SWAP_BLK_DEVICE: Option<usize> = None;
+/// Load the segments of executables page by page on first access instead of when they are started
+pub const ELF_DEMAND_PAGING: bool = true;
+/// Use a blank RAM disk as root filesystem when no block device is found, instead of panicking
+pub const RAM_DISK_FALLBACK: bool = true;
+/// Block size of the RAM disk used as root filesystem when no block device is found (4KB)
+pub const RAM_DISK_BLK_SIZE: u32 = 4096;
+/// Block count of the RAM disk used as root filesystem when no block device is found (16MB)
+pub const RAM_DISK_BLK_COUNT: usize = 4096;
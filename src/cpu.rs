@@ -1,5 +1,6 @@
 use alloc::vec::Vec;
 use core::{cell::UnsafeCell, mem::MaybeUninit};
+use spinlock::NestedOff;
 
 use crate::{
     arch::{self, interrupt},
@@ -21,6 +22,34 @@ pub fn pop_off() {
     unsafe { (*current()).pop_off() }
 }
 
+/// The interrupts of the current CPU, for the locks of the `spinlock` crate.
+pub struct CpuIrq;
+
+impl spinlock::Irq for CpuIrq {
+    fn push_off() {
+        push_off()
+    }
+
+    fn pop_off() {
+        pop_off()
+    }
+}
+
+/// Keeps interrupts disabled on the current CPU for as long as it lives,
+/// see `spinlock::InterruptGuard`.
+pub type InterruptGuard = spinlock::InterruptGuard<CpuIrq>;
+
+/// Disables interrupts, and with them preemption by the timer,
+/// until the returned guard is dropped.
+pub fn no_preempt() -> InterruptGuard {
+    InterruptGuard::new()
+}
+
+/// Returns how many `push_off`s on the current CPU are not matched by a `pop_off` yet.
+pub fn interrupt_depth() -> usize {
+    unsafe { (*current()).off.depth() }
+}
+
 fn current() -> *mut Cpu {
     cpus().0[cpu_id()].get()
 }
@@ -52,31 +81,23 @@ pub fn cpu_id() -> usize {
 }
 
 struct Cpu {
-    // depth of nesting of push_off()
-    noff: isize,
-    // Whether to turn on interrupts before calling push_off()
-    intena: bool,
+    off: NestedOff,
 }
 
 impl Cpu {
     fn new() -> Self {
         Self {
-            noff: 0,
-            intena: false,
+            off: NestedOff::new(),
         }
     }
 
     unsafe fn push_off(&mut self) {
         let old = interrupt::disable();
-        if self.noff == 0 {
-            self.intena = old;
-        }
-        self.noff += 1;
+        self.off.push(old);
     }
 
     unsafe fn pop_off(&mut self) {
-        self.noff -= 1;
-        if self.noff == 0 && self.intena {
+        if self.off.pop() {
             interrupt::enable();
         }
     }
@@ -1,4 +1,4 @@
-use core::slice;
+use core::{ops::Range, slice};
 
 use alloc::{
     boxed::Box,
@@ -8,45 +8,53 @@ use alloc::{
     vec::Vec,
 };
 
+use mm::PhysicalAddress;
+
 use crate::{fs::blk, spinlock::RwLockIrq};
 
 mod plic;
 mod uart;
-mod virtio_blk;
 mod virtio_mmio;
 
-const DEVICE_TREE_MAGIC: u32 = 0xd00dfeed;
+pub type IrqAckFn = Arc<dyn Fn() + Send + Sync>;
 
-static mut DRIVER_IRQ_ACK_FNS: BTreeMap<u32, Box<dyn Fn()>> = BTreeMap::new();
+static DRIVER_IRQ_ACK_FNS: RwLockIrq<BTreeMap<u32, IrqAckFn>> = RwLockIrq::new(BTreeMap::new());
 
-static mut BLK_DRIVERS: Vec<Arc<dyn blk::BlkDevice>> = Vec::new();
+static BLK_DRIVERS: RwLockIrq<Vec<Arc<dyn blk::BlkDevice>>> = RwLockIrq::new(Vec::new());
 
 /// Compatible lookup
 #[allow(clippy::type_complexity)]
 static DEVICE_TREE_REGISTRY: RwLockIrq<BTreeMap<&'static str, (isize, fn(&device_tree::Node))>> =
     RwLockIrq::new(BTreeMap::new());
 
-pub fn driver_irq_ack_fn(irq_num: &u32) -> Option<&dyn Fn()> {
-    unsafe { DRIVER_IRQ_ACK_FNS.get(irq_num).map(AsRef::as_ref) }
+/// Returns the closure acking the interrupt `irq_num`.
+/// The closure is cloned out of the registry, so it can run without holding the registry lock.
+pub fn driver_irq_ack_fn(irq_num: &u32) -> Option<IrqAckFn> {
+    DRIVER_IRQ_ACK_FNS.read().get(irq_num).cloned()
 }
 
-pub fn set_driver_irq_ack_fn(irq_num: u32, ack_fn: Box<dyn Fn()>) {
-    unsafe {
-        DRIVER_IRQ_ACK_FNS.insert(irq_num, ack_fn);
-    }
+/// Registers the closure acking the interrupt `irq_num`.
+/// The closure runs in the trap handler with interrupts off. Code on the same hart
+/// cannot be interrupted while holding a `MutexIrq` or `RwLockIrq`, so the closure
+/// may take those locks, but it must not wait on sleep locks or futures.
+pub fn set_driver_irq_ack_fn(irq_num: u32, ack_fn: Box<dyn Fn() + Send + Sync>) {
+    DRIVER_IRQ_ACK_FNS
+        .write()
+        .insert(irq_num, IrqAckFn::from(ack_fn));
 }
 
-pub fn blk_drivers() -> &'static Vec<Arc<dyn blk::BlkDevice>> {
-    unsafe { &BLK_DRIVERS }
+/// Returns the `idx`th registered block device.
+pub fn blk_driver(idx: usize) -> Option<Arc<dyn blk::BlkDevice>> {
+    BLK_DRIVERS.read().get(idx).cloned()
 }
 
 pub fn add_blk_drivers(blk_driver: Arc<dyn blk::BlkDevice>) {
-    unsafe { BLK_DRIVERS.push(blk_driver) };
+    BLK_DRIVERS.write().push(blk_driver);
 }
 
 #[allow(clippy::type_complexity)]
-pub fn device_tree_registry()
--> &'static RwLockIrq<BTreeMap<&'static str, (isize, fn(&device_tree::Node))>> {
+pub fn device_tree_registry(
+) -> &'static RwLockIrq<BTreeMap<&'static str, (isize, fn(&device_tree::Node))>> {
     &DEVICE_TREE_REGISTRY
 }
 
@@ -105,9 +113,26 @@ fn walk_dt_node<'a>(
     }
 }
 
-struct DtbHeader {
-    magic: u32,
-    size: u32,
+/// Returns the size of the device tree blob at `dtb`, `None` if there is none.
+pub fn dtb_size(dtb: usize) -> Option<usize> {
+    let header = unsafe { slice::from_raw_parts(dtb as *const u8, dtb::HEADER_LEN) };
+    dtb::size(header)
+}
+
+fn load_device_tree(dtb: usize) -> Option<device_tree::DeviceTree> {
+    let size = dtb_size(dtb)?;
+    dtb::load(unsafe { slice::from_raw_parts(dtb as *const u8, size) })
+}
+
+/// Returns the physical memory regions listed by the `reg` properties
+/// of the `/memory` nodes of the device tree at `dtb`.
+pub fn memory_regions(dtb: usize) -> Vec<Range<PhysicalAddress>> {
+    load_device_tree(dtb).map_or_else(Vec::new, |dt| dtb::memory_regions(&dt))
+}
+
+/// Returns the memory regions the firmware keeps for itself, see `dtb::reserved_memory_regions`.
+pub fn reserved_memory_regions(dtb: usize) -> Vec<Range<PhysicalAddress>> {
+    load_device_tree(dtb).map_or_else(Vec::new, |dt| dtb::reserved_memory_regions(&dt))
 }
 
 pub fn init(dtb: usize) {
@@ -115,18 +140,11 @@ pub fn init(dtb: usize) {
     uart::init();
     virtio_mmio::init();
 
-    let header = unsafe { &*(dtb as *const DtbHeader) };
-    let magic = u32::from_be(header.magic);
-
-    if magic == DEVICE_TREE_MAGIC {
-        let size = u32::from_be(header.size);
-        let dtb_data = unsafe { slice::from_raw_parts(dtb as *const u8, size as usize) };
-        if let Ok(dt) = device_tree::DeviceTree::load(dtb_data) {
-            let mut driver_registers = BinaryHeap::new();
-            walk_dt_node(&dt.root, &mut driver_registers);
-            for driver_register in driver_registers {
-                (driver_register.f)(driver_register.node);
-            }
+    if let Some(dt) = load_device_tree(dtb) {
+        let mut driver_registers = BinaryHeap::new();
+        walk_dt_node(&dt.root, &mut driver_registers);
+        for driver_register in driver_registers {
+            (driver_register.f)(driver_register.node);
         }
     }
 }
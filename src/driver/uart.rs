@@ -1,14 +1,16 @@
-use crate::arch::{getchar, interrupt::register_external_irq};
+use crate::arch::{interrupt::register_external_irq, try_getchar};
 use crate::mm::PageParamA;
 use alloc::boxed::Box;
-use core::ptr;
+use mm::page::PageParam;
 use mm::PhysicalAddress;
-use mm::{page::PageParam, Addr};
+use mmio::{Mmio, Reg};
 
 use super::setup_registry_fn;
 
-const UART_INT_EN_OFFSET: usize = 1;
-const UART_MODEM_CONTROL_OFFSET: usize = 4;
+// A 16550 has 8 byte-wide registers.
+const UART_LEN: usize = 8;
+const UART_INT_EN: Reg<u8> = Reg::new(1);
+const UART_MODEM_CONTROL: Reg<u8> = Reg::new(4);
 
 pub fn init() {
     setup_registry_fn("ns16550a", -999, init_uart)
@@ -24,13 +26,15 @@ pub fn init_uart(node: &device_tree::Node) {
             register_external_irq(
                 intc,
                 irq,
-                Box::new(|| {
-                    crate::fs::tty().push(getchar());
-                }),
+                // The receive FIFO may hold several characters per interrupt.
+                Box::new(|| crate::fs::tty().input().receive(try_getchar)),
             );
-            let uart_base = PageParamA::linear_phys_to_kvirt(PhysicalAddress(addr));
-            ptr::write_volatile(uart_base.add(UART_INT_EN_OFFSET).as_mut_ptr(), 0x01);
-            ptr::write_volatile(uart_base.add(UART_MODEM_CONTROL_OFFSET).as_mut_ptr(), 0x0b);
+            let uart = Mmio::new(
+                PageParamA::linear_phys_to_kvirt(PhysicalAddress(addr)),
+                UART_LEN,
+            );
+            uart.write(UART_INT_EN, 0x01);
+            uart.write(UART_MODEM_CONTROL, 0x0b);
         }
     }
 }
@@ -1,12 +1,50 @@
 use super::setup_registry_fn;
 use crate::{
     arch,
-    driver::{add_blk_drivers, virtio_blk},
+    cpu::CpuIrq,
+    driver::add_blk_drivers,
     mm::{frame_allocator, PageParamA},
 };
 use alloc::{boxed::Box, sync::Arc};
+use core::ptr;
 use device_tree::util::SliceRead;
-use mm::{page::PageParam, Addr, PhysicalAddress, VirtualAddress};
+use mm::{page::PageParam, Addr, Frame, PhysicalAddress, VirtualAddress};
+use mmio::Mmio;
+use virtio::{Device, Error, DEVICE_ID_BLK};
+
+pub type VirtioBlk = virtio::blk::VirtioBlk<KernelHal, CpuIrq>;
+
+/// Shares the memory of the kernel with virtio devices.
+pub struct KernelHal;
+
+impl virtio::Hal for KernelHal {
+    fn dma_alloc(pages: usize) -> Option<PhysicalAddress> {
+        let paddr = frame_allocator().alloc_consecutive(pages).first()?.start();
+        unsafe {
+            ptr::write_bytes(
+                Self::phys_to_virt(paddr).as_mut_ptr::<u8>(),
+                0,
+                pages * PageParamA::PAGE_SIZE,
+            )
+        };
+        Some(paddr)
+    }
+
+    fn dma_dealloc(paddr: PhysicalAddress, pages: usize) {
+        for page in 0..pages {
+            let frame = Frame::of_addr(paddr.add(page * PageParamA::PAGE_SIZE));
+            frame_allocator().dealloc(&frame);
+        }
+    }
+
+    fn phys_to_virt(paddr: PhysicalAddress) -> VirtualAddress {
+        PageParamA::linear_phys_to_kvirt(paddr)
+    }
+
+    fn virt_to_phys(vaddr: VirtualAddress) -> PhysicalAddress {
+        PageParamA::linear_kvirt_to_phys(vaddr)
+    }
+}
 
 pub fn init() {
     setup_registry_fn("virtio,mmio", -999, virtio_probe);
@@ -19,18 +57,24 @@ pub fn virtio_probe(node: &device_tree::Node) {
         _ => return,
     };
     let pa = PhysicalAddress(reg.as_slice().read_be_u64(0).unwrap() as usize);
+    let len = reg.as_slice().read_be_u64(8).unwrap() as usize;
     let va = PageParamA::linear_phys_to_kvirt(pa);
-    let header = unsafe { &mut *(va.0 as *mut virtio_drivers::VirtIOHeader) };
-    if !header.verify() {
-        return;
-    }
+    let device = match Device::probe(unsafe { Mmio::new(va, len) }) {
+        Ok(device) => device,
+        Err(Error::UnknownVersion(version)) => {
+            println!("virtio-mmio device at {}: unknown version {}", pa, version);
+            return;
+        }
+        // Not a virtio device, or a slot without a device attached.
+        Err(_) => return,
+    };
 
     if let (Ok(irq), Ok(intc)) = (
         node.prop_u32("interrupts"),
         node.prop_u32("interrupt-parent"),
     ) {
-        match header.device_type() {
-            virtio_drivers::DeviceType::Block => match virtio_blk::VirtioBlk::new(header) {
+        match device.device_id() {
+            DEVICE_ID_BLK => match VirtioBlk::new(device) {
                 Ok(virt_blk) => {
                     let virt_blk = Arc::new(virt_blk);
                     add_blk_drivers(virt_blk.clone());
@@ -38,43 +82,13 @@ pub fn virtio_probe(node: &device_tree::Node) {
                         arch::interrupt::register_external_irq(
                             intc,
                             irq,
-                            Box::new(move || {
-                                let _ = virt_blk.handle_interrupt();
-                            }),
+                            Box::new(move || virt_blk.handle_interrupt()),
                         )
                     }
                 }
                 Err(e) => panic!("Failed to create VirtioBlk. err: {:?}", e),
             },
-            device => println!("unrecognized virtio device: {:?}", device),
+            device_id => println!("unrecognized virtio device: {}", device_id),
         };
     }
 }
-
-#[no_mangle]
-extern "C" fn virtio_dma_alloc(pages: usize) -> usize {
-    let frames = frame_allocator().alloc_consecutive(pages);
-    frames
-        .first()
-        .map(|f| f.start().inner())
-        .unwrap_or_default()
-}
-
-#[no_mangle]
-extern "C" fn virtio_dma_dealloc(paddr: usize, pages: usize) -> i32 {
-    for page in 0..pages {
-        frame_allocator()
-            .dealloc(&PhysicalAddress::new((paddr + page) << PageParamA::PAGE_SIZE_SHIFT).into());
-    }
-    0
-}
-
-#[no_mangle]
-extern "C" fn virtio_phys_to_virt(paddr: usize) -> usize {
-    PageParamA::linear_phys_to_kvirt(PhysicalAddress::new(paddr)).inner()
-}
-
-#[no_mangle]
-extern "C" fn virtio_virt_to_phys(vaddr: usize) -> usize {
-    PageParamA::linear_kvirt_to_phys(VirtualAddress::new(vaddr)).inner()
-}
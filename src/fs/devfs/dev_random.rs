@@ -0,0 +1,67 @@
+use core::future::ready;
+
+use alloc::boxed::Box;
+use futures_util::future::BoxFuture;
+use random::Pool;
+
+use crate::{arch::interrupt, fs::vfs, spinlock::MutexIrq};
+
+use super::DevInode;
+
+pub const RANDOM_INODE_ID: vfs::InodeId = 4;
+pub const URANDOM_INODE_ID: vfs::InodeId = 5;
+
+/// The pool behind `/dev/random`, `/dev/urandom` and `getrandom`.
+static POOL: MutexIrq<Pool> = MutexIrq::new(Pool::unseeded());
+
+/// Fills `buf` with bytes from the random pool.
+/// The pool is seeded from the timer on first use and never blocks.
+pub fn fill(buf: &mut [u8]) {
+    random::fill_from(&POOL, || interrupt::timer_now().as_nanos() as u64, buf)
+}
+
+/// `/dev/random` and `/dev/urandom`, both read from the same pool.
+pub struct RandomInode {
+    id: vfs::InodeId,
+}
+
+impl RandomInode {
+    pub fn new(id: vfs::InodeId) -> Self {
+        Self { id }
+    }
+}
+
+impl DevInode for RandomInode {
+    fn id(&self) -> vfs::InodeId {
+        self.id
+    }
+
+    fn metadata(&self) -> BoxFuture<'_, vfs::Result<vfs::Metadata>> {
+        Box::pin(ready(Ok(vfs::Metadata {
+            mode: vfs::Mode::TY_CHR
+                | vfs::Mode::PERM_RW_USR
+                | vfs::Mode::PERM_RW_GRP
+                | vfs::Mode::PERM_RW_OTH,
+            links_count: 1,
+            ..Default::default()
+        })))
+    }
+
+    fn read_at<'a>(&'a self, _offset: u64, buf: &'a mut [u8]) -> BoxFuture<'a, vfs::Result<usize>> {
+        fill(buf);
+        Box::pin(ready(Ok(buf.len())))
+    }
+
+    /// Writes are accepted and dropped, they do not add to the pool.
+    fn write_at<'a>(&'a self, _offset: u64, src: &'a [u8]) -> BoxFuture<'a, vfs::Result<usize>> {
+        Box::pin(ready(Ok(src.len())))
+    }
+
+    fn sync(&self) -> BoxFuture<'_, vfs::Result<()>> {
+        Box::pin(ready(Ok(())))
+    }
+
+    fn ioctl(&self, _cmd: u32, _arg: usize) -> BoxFuture<'_, vfs::Result<()>> {
+        Box::pin(ready(Err(vfs::Error::Unsupport)))
+    }
+}
@@ -1,26 +1,34 @@
-use core::{
-    future::{ready, Future},
-    pin::Pin,
-    task::{Context, Poll, Waker},
-};
+use core::future::ready;
 
-use alloc::{boxed::Box, collections::VecDeque};
+use alloc::{boxed::Box, sync::Arc};
 
 use crate::{
-    fs::{ioctl, vfs},
-    proc::{executor, pid::Pid},
-    spinlock::{MutexIrq, RwLockIrq},
+    cpu::CpuIrq,
+    fs::{self, ioctl, vfs, vfs::tty::TtyInput},
+    proc::{
+        pid::{self, Pid},
+        signal::{self, Info, SendTo, Signo},
+        thread, Proc,
+    },
+    spinlock::RwLockIrq,
 };
 use futures_util::future::BoxFuture;
+use process::group::{self, TtyAccess};
 
-use super::termios::{Termios, Winsize};
+use super::{
+    termios::{Termios, Winsize},
+    DevInode,
+};
 
-const TTY_INODE_ID: vfs::InodeId = 2;
+const CTTY_INODE_ID: vfs::InodeId = 2;
+const TTY_INODE_ID: vfs::InodeId = 3;
 
+/// A terminal, the controlling terminal of at most one session.
 pub struct TtyInode {
+    /// The session controlled by the terminal
+    session: RwLockIrq<Option<u32>>,
     foreground_pgid: RwLockIrq<Option<Pid>>,
-    buf: MutexIrq<VecDeque<u8>>,
-    wakers: MutexIrq<VecDeque<Waker>>,
+    input: TtyInput<CpuIrq>,
     termios: RwLockIrq<Termios>,
     winsize: RwLockIrq<Winsize>,
 }
@@ -28,28 +36,66 @@ pub struct TtyInode {
 impl TtyInode {
     pub fn new() -> Self {
         Self {
+            session: RwLockIrq::new(None),
             foreground_pgid: RwLockIrq::new(None),
-            buf: MutexIrq::new(VecDeque::new()),
-            wakers: MutexIrq::new(VecDeque::new()),
+            input: TtyInput::new(),
             termios: RwLockIrq::new(Default::default()),
             winsize: RwLockIrq::new(Default::default()),
         }
     }
 
-    pub fn push(&self, c: u8) {
-        self.buf.lock().push_back(c);
-        let mut wakers = self.wakers.lock();
-        while let Some(w) = wakers.pop_front() {
-            w.wake()
-        }
+    /// The bytes received from the UART.
+    pub fn input(&self) -> &TtyInput<CpuIrq> {
+        &self.input
     }
 
-    pub fn pop(&self) -> Option<u8> {
-        self.buf.lock().pop_front()
+    /// Makes the terminal the controlling terminal of the session of `proc`,
+    /// with the process group of `proc` in the foreground.
+    pub fn set_controlling(&self, proc: &Arc<Proc>) {
+        *self.session.write() = Some(proc.sid());
+        *self.foreground_pgid.write() = Some(Pid::new(group::leader(proc)));
+    }
+
+    /// Whether the terminal is the controlling terminal of the session `sid`.
+    pub fn controls(&self, sid: u32) -> bool {
+        *self.session.read() == Some(sid)
+    }
+
+    // A background process group of the session may not use the terminal,
+    // `sig` (SIGTTIN or SIGTTOU) is sent to the group of the caller to stop it,
+    // see `group::tty_access`.
+    fn job_control(&self, sig: Signo) -> vfs::Result<()> {
+        let thread = match thread::current() {
+            Some(thread) => thread,
+            None => return Ok(()),
+        };
+        let proc = thread.proc();
+        let ignored = {
+            let proc_signal = proc.signal().lock();
+            proc_signal.action(&sig).handler().is_ignored(&sig)
+                || proc_signal.blocked.blocked.contains(&sig)
+        };
+        let foreground = self.foreground_pgid.read().as_ref().map(|pgid| *pgid.id());
+        match group::tty_access(
+            proc.group.ids(),
+            *self.session.read(),
+            foreground,
+            sig == Signo::SIGTTIN,
+            ignored,
+        ) {
+            TtyAccess::Allowed => return Ok(()),
+            TtyAccess::Denied => return Err(vfs::Error::Io),
+            TtyAccess::Stop => {}
+        }
+        for member in pid::group_members(proc.pgid()) {
+            let _ =
+                signal::signal().send_signal(sig, Info::kernel(sig), SendTo::ProcGroup(&member));
+        }
+        Err(vfs::Error::Interrupted)
     }
 }
 
-impl super::DevInode for TtyInode {
+impl DevInode for TtyInode {
     fn id(&self) -> vfs::InodeId {
         TTY_INODE_ID
     }
@@ -67,13 +113,18 @@ impl super::DevInode for TtyInode {
     }
 
     fn read_at<'a>(&'a self, _offset: u64, buf: &'a mut [u8]) -> BoxFuture<'a, vfs::Result<usize>> {
-        Box::pin(ReadAtFut {
-            tty_inode: self,
-            buf,
-        })
+        if let Err(e) = self.job_control(Signo::SIGTTIN) {
+            return Box::pin(ready(Err(e)));
+        }
+        Box::pin(self.input.read(buf))
     }
 
     fn write_at<'a>(&'a self, _offset: u64, src: &'a [u8]) -> BoxFuture<'a, vfs::Result<usize>> {
+        if self.termios.read().tostop() {
+            if let Err(e) = self.job_control(Signo::SIGTTOU) {
+                return Box::pin(ready(Err(e)));
+            }
+        }
         let s = unsafe { core::str::from_utf8_unchecked(src) };
         crate::print!("{}", s);
         Box::pin(ready(Ok(src.len())))
@@ -100,6 +151,13 @@ impl super::DevInode for TtyInode {
                 }
                 Ok(())
             }
+            ioctl::CMD_FIONREAD => {
+                let argp = arg as *mut i32;
+                unsafe {
+                    *argp = self.input.len() as i32;
+                }
+                Ok(())
+            }
             ioctl::CMD_TIOCGWINSZ => {
                 let winsize = arg as *mut Winsize;
                 unsafe {
@@ -122,40 +180,94 @@ impl super::DevInode for TtyInode {
                 Ok(())
             }
 
+            // The new foreground group must belong to the session controlled by the terminal.
             ioctl::CMD_TIOCSPGRP => {
                 let fpgid = unsafe { *(arg as *const i32) } as u32;
-                match executor::thread(&fpgid) {
-                    Some(thread) => {
-                        *self.foreground_pgid.write() = Some(Pid::new(thread.proc().clone()));
-                        Ok(())
+                match thread::current() {
+                    Some(thread) if self.controls(thread.proc().sid()) => {
+                        let sid = thread.proc().sid();
+                        match pid::group_members(fpgid).into_iter().next() {
+                            Some(member) if member.sid() == sid => {
+                                *self.foreground_pgid.write() =
+                                    Some(Pid::new(group::leader(&member)));
+                                Ok(())
+                            }
+                            Some(_) => Err(vfs::Error::NotPermitted),
+                            None => Err(vfs::Error::NoSuchProcess(fpgid)),
+                        }
                     }
-                    None => Err(vfs::Error::NoSuchProcess(fpgid)),
+                    _ => Err(vfs::Error::NotPermitted),
                 }
             }
 
             _ => Err(vfs::Error::Unsupport),
         }))
     }
+
+    fn poll(&self, events: vfs::PollEvents) -> BoxFuture<'_, vfs::PollEvents> {
+        Box::pin(self.input.poll(events))
+    }
 }
 
-pub struct ReadAtFut<'a> {
-    tty_inode: &'a TtyInode,
-    buf: &'a mut [u8],
+/// `/dev/tty`, the controlling terminal of the session of the calling process.
+pub struct CttyInode;
+
+impl CttyInode {
+    fn tty(&self) -> vfs::Result<&'static Arc<TtyInode>> {
+        let sid = thread::current()
+            .map(|thread| thread.proc().sid())
+            .ok_or(vfs::Error::NoDevice)?;
+        let tty = fs::tty();
+        if tty.controls(sid) {
+            Ok(tty)
+        } else {
+            Err(vfs::Error::NoDevice)
+        }
+    }
 }
 
-impl Future for ReadAtFut<'_> {
-    type Output = vfs::Result<usize>;
-
-    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        if let Some(c) = self.tty_inode.pop() {
-            return if !self.buf.is_empty() {
-                self.buf[0] = c;
-                Poll::Ready(Ok(1))
-            } else {
-                Poll::Ready(Ok(0))
-            };
+impl DevInode for CttyInode {
+    fn id(&self) -> vfs::InodeId {
+        CTTY_INODE_ID
+    }
+
+    fn metadata(&self) -> BoxFuture<'_, vfs::Result<vfs::Metadata>> {
+        match self.tty() {
+            Ok(tty) => tty.metadata(),
+            Err(e) => Box::pin(ready(Err(e))),
+        }
+    }
+
+    fn read_at<'a>(&'a self, offset: u64, buf: &'a mut [u8]) -> BoxFuture<'a, vfs::Result<usize>> {
+        match self.tty() {
+            Ok(tty) => tty.read_at(offset, buf),
+            Err(e) => Box::pin(ready(Err(e))),
+        }
+    }
+
+    fn write_at<'a>(&'a self, offset: u64, src: &'a [u8]) -> BoxFuture<'a, vfs::Result<usize>> {
+        match self.tty() {
+            Ok(tty) => tty.write_at(offset, src),
+            Err(e) => Box::pin(ready(Err(e))),
+        }
+    }
+
+    fn sync(&self) -> BoxFuture<'_, vfs::Result<()>> {
+        Box::pin(ready(Ok(())))
+    }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> BoxFuture<'_, vfs::Result<()>> {
+        match self.tty() {
+            Ok(tty) => tty.ioctl(cmd, arg),
+            Err(e) => Box::pin(ready(Err(e))),
+        }
+    }
+
+    fn poll(&self, events: vfs::PollEvents) -> BoxFuture<'_, vfs::PollEvents> {
+        match self.tty() {
+            Ok(tty) => tty.poll(events),
+            // Nothing to wait for, the operation fails right away.
+            Err(_) => Box::pin(ready(events)),
         }
-        self.tty_inode.wakers.lock().push_back(cx.waker().clone());
-        Poll::Pending
     }
 }
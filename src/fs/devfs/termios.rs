@@ -22,6 +22,13 @@ pub struct Termios {
     ospeed: u32,
 }
 
+impl Termios {
+    /// Whether background processes are stopped when they write to the terminal.
+    pub fn tostop(&self) -> bool {
+        self.lflag.contains(LFlag::TOSTOP)
+    }
+}
+
 impl Default for Termios {
     fn default() -> Self {
         let mut cc: [u8; NCCS] = Default::default();
@@ -1,32 +1,41 @@
 pub mod blk;
-mod cache_fs;
 pub mod devfs;
 mod disk;
 pub mod fs_str;
-mod ioctl;
+pub mod ioctl;
 #[allow(clippy::type_complexity)]
 #[cfg(feature = "naive_fs")]
 pub mod naive_fs_vfs;
 mod path;
+mod procfs;
 mod ram_blk;
 mod ram_fs;
 pub mod rootfs;
+pub mod socket;
 pub mod util;
 pub mod vfs;
 
 use core::mem::MaybeUninit;
 
-use alloc::sync::Arc;
+use alloc::{string::ToString, sync::Arc};
 pub use disk::Disk;
-pub use fs_str::{DirEntryName, FsStr, FsString};
+pub use fs_str::{FsStr, FsString};
 pub use path::*;
 
-use crate::{driver, fs::devfs::dev_tty::TtyInode, proc};
+use crate::{
+    config, driver,
+    fs::devfs::{
+        dev_random::{RandomInode, RANDOM_INODE_ID, URANDOM_INODE_ID},
+        dev_tty::{CttyInode, TtyInode},
+    },
+    proc, time,
+};
 
-use self::{mount_fs::DynInode, rootfs::root_fs};
+use self::{mount_fs::DynFilesystem, mount_table::MountOptions, rootfs::root_fs};
 
 #[allow(clippy::type_complexity)]
 pub mod mount_fs;
+pub mod mount_table;
 
 pub type Inode = Arc<dyn mount_fs::DynInode>;
 pub type DirEntry = vfs::DirEntry<Arc<dyn mount_fs::DynFilesystem>>;
@@ -43,32 +52,114 @@ pub fn init() {
         // mount device filesystem
         unsafe { TTY = MaybeUninit::new(Arc::new(TtyInode::new())) };
 
-        let dev_fs = Arc::new(devfs::DevFs::new(vec![(
-            "tty".into(),
-            Some(vfs::FileType::ChrDev),
-            tty().clone() as Arc<dyn devfs::DevInode>,
-        )]));
+        // `/dev/tty0` is the console, `/dev/tty` the controlling terminal of the caller.
+        let dev_fs = Arc::new(devfs::DevFs::new(vec![
+            (
+                "tty".into(),
+                Some(vfs::FileType::ChrDev),
+                Arc::new(CttyInode) as Arc<dyn devfs::DevInode>,
+            ),
+            (
+                "tty0".into(),
+                Some(vfs::FileType::ChrDev),
+                tty().clone() as Arc<dyn devfs::DevInode>,
+            ),
+            (
+                "random".into(),
+                Some(vfs::FileType::ChrDev),
+                Arc::new(RandomInode::new(RANDOM_INODE_ID)) as Arc<dyn devfs::DevInode>,
+            ),
+            (
+                "urandom".into(),
+                Some(vfs::FileType::ChrDev),
+                Arc::new(RandomInode::new(URANDOM_INODE_ID)) as Arc<dyn devfs::DevInode>,
+            ),
+        ]));
 
-        let dev_dir = find_or_create_dev_dir()
+        let dev_dir = find_or_create_dir("dev")
             .await
             .expect("field to find or create `/dev` directory");
 
-        mount_fs::mount(dev_dir, dev_fs)
+        mount_table::mount(
+            Path::from_bytes(b"/dev"),
+            &dev_dir,
+            dev_fs,
+            MountOptions::empty(),
+        )
+        .await
+        .expect("field to mount dev fs");
+
+        let proc_dir = find_or_create_dir("proc")
             .await
-            .expect("field to mount dev fs");
+            .expect("field to find or create `/proc` directory");
+        mount_table::mount(
+            Path::from_bytes(b"/proc"),
+            &proc_dir,
+            Arc::new(procfs::ProcFs::new(procfs::KernelProcs)),
+            MountOptions::empty(),
+        )
+        .await
+        .expect("field to mount proc fs");
     });
 }
 
+/// Lists the mounted filesystems as `(mountpoint, filesystem name, options)`, in mount order.
+pub fn mounts() -> impl Iterator<Item = (FsString<PATH_CAP>, &'static str, MountOptions)> {
+    mount_table::mounts()
+        .into_iter()
+        .map(|entry| (entry.path, DynFilesystem::name(&*entry.fs), entry.options))
+}
+
+/// Create a filesystem of type `fstype` to be mounted.
+/// `source` names the block device (`blk0`, `blk1`, ...) for disk based filesystems
+/// and is ignored by `ramfs`.
+pub async fn create_fs(
+    fstype: &str,
+    source: &str,
+    read_only: bool,
+) -> vfs::Result<Arc<dyn mount_fs::DynFilesystem>> {
+    match fstype {
+        "ramfs" => {
+            let ramfs = Arc::new(ram_fs::RamFs::with_clock(time::now));
+            ramfs.create_root(
+                vfs::Mode::TY_DIR
+                    | vfs::Mode::PERM_RWX_USR
+                    | vfs::Mode::PERM_RX_GRP
+                    | vfs::Mode::PERM_RX_OTH,
+                time::now(),
+            );
+            Ok(Arc::new(ramfs))
+        }
+        #[cfg(feature = "naive_fs")]
+        "naivefs" => {
+            let blk_device = source
+                .strip_prefix("blk")
+                .and_then(|idx| idx.parse::<usize>().ok())
+                .and_then(driver::blk_driver)
+                .ok_or(vfs::Error::NoSuchFileOrDirectory)?;
+            let disk = Disk::new(blk_device, config::DISK_READ_AHEAD_BLKS);
+            let naivefs = Arc::new(naive_fs_vfs::NaiveFs::open(disk, read_only).await?);
+            Ok(Arc::new(naivefs))
+        }
+        _ => Err(vfs::Error::UnsupportedFs(fstype.to_string())),
+    }
+}
+
 async fn create_fs_inner() -> Arc<dyn mount_fs::DynFilesystem> {
-    let blk_device = driver::blk_drivers()
-        .first()
-        .expect("No block device could be found.")
-        .clone();
+    let blk_device = match driver::blk_driver(0) {
+        Some(blk_device) => blk_device,
+        None if config::RAM_DISK_FALLBACK => {
+            println!("No block device could be found, using a RAM disk as root filesystem.");
+            return create_ram_fs_inner().await;
+        }
+        None => panic!("No block device could be found."),
+    };
 
     #[cfg(feature = "naive_fs")]
     {
+        let disk = Disk::new(blk_device, config::DISK_READ_AHEAD_BLKS);
         let naivefs = Arc::new(
-            naive_fs_vfs::NaiveFs::open(Disk::new(blk_device), false)
+            naive_fs_vfs::NaiveFs::open(disk, false)
                 .await
                 .expect("Failed to open naive filesystem."),
         );
@@ -76,30 +167,52 @@ async fn create_fs_inner() -> Arc<dyn mount_fs::DynFilesystem> {
     }
 }
 
-async fn find_or_create_dev_dir() -> vfs::Result<Arc<dyn DynInode>> {
+/// Create a fresh filesystem on a RAM block device.
+/// The RAM block device is registered as a block driver too.
+async fn create_ram_fs_inner() -> Arc<dyn mount_fs::DynFilesystem> {
+    let ram_blk_device = Arc::new(ram_blk::RamBlkDevice::new(
+        blk::BlkSize::new(config::RAM_DISK_BLK_SIZE),
+        config::RAM_DISK_BLK_COUNT,
+    ));
+    driver::add_blk_drivers(ram_blk_device.clone());
+
+    #[cfg(feature = "naive_fs")]
+    {
+        let mut volume_name = [0; 16];
+        (&mut volume_name[..7]).copy_from_slice(b"ramdisk");
+        let naivefs: Arc<naive_fs_vfs::NaiveFs<_>> = naive_fs_vfs::format(
+            Disk::new(ram_blk_device, config::DISK_READ_AHEAD_BLKS),
+            naive_fs::BlkSize::new(config::RAM_DISK_BLK_SIZE),
+            volume_name,
+        )
+        .await
+        .expect("Failed to create the RAM disk filesystem.");
+        Arc::new(naivefs)
+    }
+}
+
+/// Find the directory `name` in the root directory, creating it if it does not exist.
+async fn find_or_create_dir(name: &str) -> vfs::Result<DirEntry> {
     let root_dir_entry = root_fs().root().await;
-    Ok(
-        match root_fs()
-            .find_parent_dentry(&root_dir_entry, Path::from_bytes("dev".as_bytes()))
-            .await?
-        {
-            Some(dev) => dev.as_dir().await?.ok_or(vfs::Error::WrongFS)?,
-            None => {
-                let new_inode = root_fs()
-                    .create_parent_dentry(
-                        &root_dir_entry,
-                        FsStr::from_bytes("dev".as_bytes()),
-                        vfs::Mode::TY_DIR
-                            | vfs::Mode::PERM_RWX_USR
-                            | vfs::Mode::PERM_RX_GRP
-                            | vfs::Mode::PERM_RX_OTH,
-                        0,
-                        0,
-                        Default::default(),
-                    )
-                    .await?;
-                new_inode
-            }
-        },
-    )
+    let path = Path::from_bytes(name.as_bytes());
+    if let Some(dir) = root_fs().find_parent_dentry(&root_dir_entry, path).await? {
+        return Ok(dir);
+    }
+    root_fs()
+        .create_parent_dentry(
+            &root_dir_entry,
+            FsStr::from_bytes(name.as_bytes()),
+            vfs::Mode::TY_DIR
+                | vfs::Mode::PERM_RWX_USR
+                | vfs::Mode::PERM_RX_GRP
+                | vfs::Mode::PERM_RX_OTH,
+            0,
+            0,
+            Default::default(),
+        )
+        .await?;
+    root_fs()
+        .find_parent_dentry(&root_dir_entry, path)
+        .await?
+        .ok_or(vfs::Error::NoSuchFileOrDirectory)
 }
@@ -0,0 +1,29 @@
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::cpu::CpuIrq;
+
+use super::{mount_fs::DynFilesystem, vfs, DirEntry, Path};
+
+pub use vfs::mount_table::{MountEntry, MountOptions};
+
+static MOUNTS: vfs::mount_table::MountTable<CpuIrq> = vfs::mount_table::MountTable::new();
+
+/// Returns all mounted filesystems, in mount order.
+pub fn mounts() -> Vec<MountEntry> {
+    MOUNTS.mounts()
+}
+
+/// Mount `fs` onto the directory `target`, which `path` resolved to.
+pub async fn mount(
+    path: &Path,
+    target: &DirEntry,
+    fs: Arc<dyn DynFilesystem>,
+    options: MountOptions,
+) -> vfs::Result<()> {
+    MOUNTS.mount(path, target, fs, options).await
+}
+
+/// Detach the filesystem whose root directory is `target`.
+pub fn umount(target: &DirEntry) -> vfs::Result<()> {
+    MOUNTS.umount(target)
+}
@@ -0,0 +1,73 @@
+//! Process information filesystem, mounted at `/proc`, see `vfs::proc_fs`.
+
+use alloc::{string::ToString, vec::Vec};
+
+use crate::proc::{
+    pid,
+    signal::SignalSet,
+    thread::{self, State},
+    Proc,
+};
+
+use super::vfs::proc_fs::{ProcStatus, Procs};
+
+pub type ProcFs = super::vfs::proc_fs::ProcFs<KernelProcs>;
+
+/// The processes of the kernel.
+#[derive(Clone)]
+pub struct KernelProcs;
+
+impl Procs for KernelProcs {
+    fn pids(&self) -> Vec<u32> {
+        pid::procs().map(|proc| *proc.id()).collect()
+    }
+
+    fn exists(&self, pid: u32) -> bool {
+        pid::find(&pid).is_some()
+    }
+
+    fn status(&self, pid: u32) -> Option<ProcStatus> {
+        let proc = pid::find(&pid)?;
+        let (blocked, ignored) = {
+            let signal = proc.signal().lock();
+            let ignored = SignalSet::fill()
+                .iter()
+                .filter(|sig| signal.action(sig).handler().is_ignored(sig))
+                .collect::<SignalSet>();
+            (signal.blocked.blocked, ignored)
+        };
+        Some(ProcStatus {
+            name: proc.cmd().to_string(),
+            state: state_char(&proc),
+            pid,
+            ppid: proc.family.ppid(),
+            pgid: proc.pgid(),
+            sid: proc.sid(),
+            threads: proc.threads.read().len(),
+            sig_blocked: blocked.bits(),
+            sig_ignored: ignored.bits(),
+        })
+    }
+
+    fn current(&self) -> Option<u32> {
+        thread::current().map(|thread| *thread.proc().id())
+    }
+}
+
+// The state letter used by `ps`.
+fn state_char(proc: &Proc) -> char {
+    let thread = &proc.main_thread;
+    if thread.flags.stopping() {
+        return 'T';
+    }
+    let state = thread.inner.read().state();
+    if state.contains(State::EXIT) {
+        'Z'
+    } else if state.contains(State::RUNNING) {
+        'R'
+    } else if state.contains(State::UNINTERRUPTIBLE) {
+        'D'
+    } else {
+        'S'
+    }
+}
@@ -0,0 +1,6 @@
+//! Connected pairs of UNIX domain stream sockets, as created by `socketpair`.
+
+use crate::cpu::CpuIrq;
+
+/// One end of a connected socket pair, see `vfs::socket::SocketEnd`.
+pub type SocketEnd = vfs::socket::SocketEnd<CpuIrq>;
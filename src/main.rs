@@ -16,6 +16,8 @@
 #![allow(dead_code)]
 #![feature(const_btree_new)]
 
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
 use arch::interrupt as interruptA;
 
 #[macro_use]
@@ -24,6 +26,8 @@ extern crate alloc;
 extern crate bitflags;
 
 mod arch;
+#[cfg(feature = "backtrace")]
+mod backtrace;
 mod config;
 mod console;
 mod cpu;
@@ -47,25 +51,46 @@ extern "C" {
     fn _bootstack();
 }
 
-// Kernel entry.
+// The hart that initializes the kernel, `usize::MAX` until one has entered `kmain`.
+static BOOT_HART: AtomicUsize = AtomicUsize::new(usize::MAX);
+// Set once the boot hart has initialized the kernel.
+static KERNEL_READY: AtomicBool = AtomicBool::new(false);
+
+// Kernel entry of every hart.
 // #[cfg(not(test))]
-fn kmain(_hartid: usize, dtb_pa: usize) {
-    console::init();
-    heap::init();
-    timer::init();
-    interruptA::init();
-    cpu::init();
-    mm::init();
-    driver::init(dtb_pa);
-    fs::init();
-    proc::init();
+fn kmain(hartid: usize, dtb_pa: usize) {
+    if BOOT_HART
+        .compare_exchange(usize::MAX, hartid, Ordering::AcqRel, Ordering::Acquire)
+        .is_ok()
+    {
+        console::init();
+        heap::init();
+        timer::init();
+        interruptA::init();
+        cpu::init();
+        mm::init(dtb_pa);
+        driver::init(dtb_pa);
+        mm::init_swap();
+        fs::init();
+        proc::init();
+        KERNEL_READY.store(true, Ordering::Release);
+
+        // Harts that are not booted by the firmware wait in the stopped state.
+        for other in (0..config::NCPU).filter(|other| *other != hartid) {
+            arch::start_hart(other, dtb_pa);
+        }
+    } else {
+        while !KERNEL_READY.load(Ordering::Acquire) {
+            core::hint::spin_loop();
+        }
+        // Everything but the trap setup is shared with the boot hart.
+        interruptA::init();
+    }
 
     loop {
         proc::executor::run_ready_tasks();
-        unsafe {
-            // When there is no task in the operating system,
-            // it is necessary to turn on interrupts to allow external interrupts so that wake can be called
-            interruptA::enable_and_wfi();
-        };
+        // When there is no task in the operating system,
+        // it is necessary to turn on interrupts to allow external interrupts so that wake can be called
+        proc::executor::idle();
     }
 }
@@ -1,12 +1,25 @@
+use core::{
+    mem, slice,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::{boxed::Box, sync::Arc, vec, vec::Vec};
 use mm::{
     frame::{allocator::BumpAllocator, LockedAllocator},
-    memory::Memory,
+    memory::{BoxFuture, Fault, LazyLoad, Memory, PageSource, SwapOut},
     page::mapper::PageMapper,
     page::PageParam as _,
-    Result,
+    swap::{LockedSwap, SwapSpace},
+    Frame, PhysicalAddress, Result, VirtualAddress,
 };
 
-use crate::{arch::memory::memory_range, spinlock::MutexIrq};
+use crate::{
+    arch::memory::{kernel_regions, memory_range},
+    config, driver,
+    fs::{blk::BlkDevice, Inode},
+    proc::executor,
+    spinlock::{MutexIrq, RwLockIrq},
+};
 
 pub use mm::arch::page::PageParam as PageParamA;
 
@@ -16,15 +29,212 @@ pub type Mem = Memory<'static, MutexIrq<()>, Allocator, PageParamA>;
 static FRAME_ALLOCATOR: LockedAllocator<MutexIrq<()>, Allocator> =
     LockedAllocator::new(Allocator::uninit());
 
-pub fn init() {
-    let (start, end) = memory_range();
-    FRAME_ALLOCATOR.init(start, end)
+/// Hands the memory found in the device tree at `dtb_pa` to the frame allocator,
+/// except the kernel image, the device tree blob and the memory reserved by the firmware.
+pub fn init(dtb_pa: usize) {
+    let (start, end) = memory_range(&driver::memory_regions(dtb_pa));
+    let mut reserved = kernel_regions();
+    if let Some(dtb_size) = driver::dtb_size(dtb_pa) {
+        reserved.push(PhysicalAddress(dtb_pa)..PhysicalAddress(dtb_pa + dtb_size));
+    }
+    reserved.extend(driver::reserved_memory_regions(dtb_pa));
+    FRAME_ALLOCATOR.init(start, end, &reserved)
 }
 
 pub fn frame_allocator() -> &'static LockedAllocator<MutexIrq<()>, Allocator> {
     &FRAME_ALLOCATOR
 }
 
+/// A block device used as swap, each slot takes a page worth of consecutive blocks.
+/// The address spaces only keep track of the slots, pages are written and read here
+/// without holding the lock of an address space.
+struct Swap {
+    slots: LockedSwap<MutexIrq<()>>,
+    device: Arc<dyn BlkDevice>,
+    blks_per_slot: usize,
+}
+
+static mut SWAP: Option<Swap> = None;
+
+fn swap() -> Option<&'static Swap> {
+    unsafe { SWAP.as_ref() }
+}
+
+/// Enable swapping to the block device configured by `config::SWAP_BLK_DEVICE`.
+/// Must be called after the block drivers are registered and before the first process is created.
+pub fn init_swap() {
+    if let Some(device) = config::SWAP_BLK_DEVICE.and_then(driver::blk_driver) {
+        let blks_per_slot = PageParamA::PAGE_SIZE / device.logical_blk_size().size() as usize;
+        let slots = LockedSwap::new(device.blk_count() / blks_per_slot);
+        unsafe {
+            SWAP = Some(Swap {
+                slots,
+                device,
+                blks_per_slot,
+            })
+        };
+    }
+}
+
+pub fn has_swap() -> bool {
+    swap().is_some()
+}
+
 pub fn new_memory() -> Result<Memory<'static, MutexIrq<()>, Allocator, PageParamA>> {
-    Ok(Memory::new(PageMapper::create(frame_allocator())?))
+    let mut page_mapper = PageMapper::create(frame_allocator())?;
+    if let Some(swap) = swap() {
+        page_mapper.set_swap(&swap.slots);
+    }
+    Ok(Memory::new(page_mapper))
+}
+
+/// Evict a user page of one of the processes to swap, the processes take turns.
+/// Returns whether a page was written to swap and its frame freed.
+pub async fn reclaim() -> bool {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+    let swap = match swap() {
+        Some(swap) => swap,
+        None => return false,
+    };
+    // Threads of a process, and processes created with `CLONE_VM`, share an address space.
+    let mut memories: Vec<Arc<RwLockIrq<Mem>>> = Vec::new();
+    for thread in executor::threads() {
+        let memory = &thread.proc().memory;
+        if !memories.iter().any(|m| Arc::ptr_eq(m, memory)) {
+            memories.push(memory.clone());
+        }
+    }
+    let start = NEXT.fetch_add(1, Ordering::Relaxed);
+    for i in 0..memories.len() {
+        let out = memories[(start + i) % memories.len()].write().reclaim();
+        if let Ok(Some(SwapOut { flush, slot, frame })) = out {
+            // Flush the entry before the write, the page must not change while it is written.
+            drop(flush);
+            let src = unsafe { page_bytes(&frame) };
+            let written = swap
+                .device
+                .write_blks(slot * swap.blks_per_slot, src)
+                .await
+                .is_ok();
+            if let Some(frame) = swap.slots.finish_write(slot, written) {
+                frame_allocator().dealloc(&frame);
+            }
+            return written;
+        }
+    }
+    false
+}
+
+/// Read the page at `vaddr` of `memory` back from swap `slot`, after a fault on the page
+/// returned `Fault::SwapIn`, evicting other pages if frames run out.
+pub async fn swap_in(
+    memory: Arc<RwLockIrq<Mem>>,
+    vaddr: VirtualAddress,
+    slot: usize,
+) -> Result<()> {
+    let swap = swap().expect("a page is in swap but there is no swap");
+    // Also dropped with the future, when the thread exits during the read.
+    let read = SwapInRead {
+        memory: &memory,
+        slot,
+    };
+    let mut buf = vec![0; PageParamA::PAGE_SIZE];
+    swap.device
+        .read_blks(slot * swap.blks_per_slot, &mut buf)
+        .await
+        .map_err(|_| mm::Error::SwapIo)?;
+    loop {
+        let res = memory.write().finish_swap_in(vaddr, slot, &buf);
+        match res {
+            Err(mm::Error::NoSpace) => {
+                if !reclaim().await {
+                    return Err(mm::Error::NoSpace);
+                }
+            }
+            res => {
+                mem::forget(read);
+                return res.map(drop);
+            }
+        }
+    }
+}
+
+// A read of a page from swap, whose reference to the slot is dropped
+// unless the page is handed to `Memory::finish_swap_in`.
+struct SwapInRead<'a> {
+    memory: &'a RwLockIrq<Mem>,
+    slot: usize,
+}
+
+impl Drop for SwapInRead<'_> {
+    fn drop(&mut self) {
+        self.memory.read().cancel_swap_in(self.slot)
+    }
+}
+
+// The bytes of `frame`, through the linear mapping.
+unsafe fn page_bytes(frame: &Frame) -> &[u8] {
+    slice::from_raw_parts(
+        PageParamA::linear_phys_to_kvirt(frame.start()).as_mut_ptr(),
+        PageParamA::PAGE_SIZE,
+    )
+}
+
+/// Read the page at `vaddr` of `memory` from the backing of its lazy segment, after a fault
+/// on the page returned `Fault::Load`, evicting other pages if frames run out.
+pub async fn load_lazy_page(
+    memory: Arc<RwLockIrq<Mem>>,
+    vaddr: VirtualAddress,
+    load: LazyLoad,
+) -> Result<()> {
+    let mut buf = vec![0; PageParamA::PAGE_SIZE];
+    load.read(&mut buf).await?;
+    loop {
+        let res = memory.write().finish_lazy_load(vaddr, &load, &buf);
+        match res {
+            Err(mm::Error::NoSpace) => {
+                if !reclaim().await {
+                    return Err(mm::Error::NoSpace);
+                }
+            }
+            res => return res.map(drop),
+        }
+    }
+}
+
+/// Map the page at `vaddr` of `memory` as a fault on it from user mode would, so the kernel
+/// may access it, e.g. a futex word in a page that was never touched or is in swap.
+/// The page may be evicted again once the lock of `memory` is released, the caller
+/// checks it is mapped under the lock and retries.
+pub async fn fault_in(memory: &Arc<RwLockIrq<Mem>>, vaddr: VirtualAddress) -> Result<()> {
+    let fault = memory.write().handle_page_fault(vaddr);
+    match fault {
+        Ok(Fault::Handled(_)) => Ok(()),
+        Ok(Fault::SwapIn(slot)) => swap_in(memory.clone(), vaddr, slot).await,
+        Ok(Fault::Load(load)) => load_lazy_page(memory.clone(), vaddr, load).await,
+        Err(mm::Error::NoSpace) if has_swap() => {
+            if reclaim().await {
+                Ok(())
+            } else {
+                Err(mm::Error::NoSpace)
+            }
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// A file backing a lazy segment, such as a segment of an executable.
+/// Pages are read without holding the lock of the address space, see `load_lazy_page`.
+pub struct InodeSource(pub Inode);
+
+impl PageSource for InodeSource {
+    fn read_at<'a>(&'a self, offset: usize, buf: &'a mut [u8]) -> BoxFuture<'a, Result<usize>> {
+        Box::pin(async move {
+            self.0
+                .read_at(offset as u64, buf)
+                .await
+                .map_err(|_| mm::Error::SourceIo)
+        })
+    }
 }
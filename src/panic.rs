@@ -14,6 +14,8 @@ pub extern "C" fn rust_eh_personality() {}
 #[no_mangle]
 pub extern "C" fn rust_begin_unwind(info: &PanicInfo) -> ! {
     println!("KERNEL PANIC: {}", info);
+    #[cfg(feature = "backtrace")]
+    crate::backtrace::print();
 
     println!("WFI");
     loop {
@@ -4,24 +4,45 @@ use core::{
     sync::atomic::AtomicUsize,
     sync::atomic::Ordering,
     task::{Context, Poll, Waker},
+    time::Duration,
 };
 
 use alloc::{sync::Arc, task::Wake};
 use executor::fifo::FIFOExecutor;
 use futures_util::pin_mut;
 
-use crate::arch::interrupt;
+use crate::{arch::interrupt, spinlock::MutexIrq, timer};
 
 use super::thread::{Thread, ThreadFuture};
 
-static mut GLOBAL_EXECUTOR: MaybeUninit<FIFOExecutor<ThreadFuture>> = MaybeUninit::uninit();
+/// The executor shared by all harts.
+static mut GLOBAL_EXECUTOR: MaybeUninit<FIFOExecutor<ThreadFuture, MutexIrq<()>>> =
+    MaybeUninit::uninit();
 
+/// About 5 seconds of the 10Hz tick.
+#[cfg(feature = "watchdog")]
+const WATCHDOG_STALL_TICKS: usize = 50;
+
+/// The period of the tick `set_next_timer_interrupt` programs.
+#[cfg(feature = "watchdog")]
+const WATCHDOG_TICK: Duration = Duration::from_millis(100);
+
+// The timer ticks before the executor is initialized.
+#[cfg(feature = "watchdog")]
+static EXECUTOR_READY: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
+
+/// Must be called by the boot hart before the other harts are started.
 pub fn init() {
     unsafe { GLOBAL_EXECUTOR = MaybeUninit::new(FIFOExecutor::new(100)) }
+    #[cfg(feature = "watchdog")]
+    {
+        executor().set_watchdog_ticks(WATCHDOG_STALL_TICKS);
+        EXECUTOR_READY.store(true, Ordering::Release);
+    }
 }
 
-fn executor() -> &'static mut FIFOExecutor<ThreadFuture> {
-    unsafe { GLOBAL_EXECUTOR.assume_init_mut() }
+fn executor() -> &'static FIFOExecutor<ThreadFuture, MutexIrq<()>> {
+    unsafe { GLOBAL_EXECUTOR.assume_init_ref() }
 }
 
 pub fn spawn(thread: ThreadFuture) -> Option<()> {
@@ -34,12 +55,50 @@ impl executor::WaitForInterrupt for Wfi {
     fn wfi() {
         unsafe { interrupt::wfi() };
     }
+
+    fn next_deadline() -> Option<Duration> {
+        let deadline = timer::next_deadline();
+        #[cfg(feature = "watchdog")]
+        let deadline =
+            executor().watchdog_deadline(deadline, interrupt::timer_now() + WATCHDOG_TICK);
+        deadline
+    }
+
+    fn set_timer(deadline: Option<Duration>) {
+        interrupt::set_timer_deadline(deadline);
+    }
 }
 
 pub fn run_ready_tasks() {
     executor().run_ready_tasks()
 }
 
+pub fn has_ready_tasks() -> bool {
+    executor().has_ready_tasks()
+}
+
+/// Called on every timer interrupt. An idle hart keeps ticking while the
+/// watchdog is armed, see `idle`.
+#[cfg(feature = "watchdog")]
+pub fn watchdog_tick() {
+    if EXECUTOR_READY.load(Ordering::Acquire) {
+        executor().watchdog_tick();
+    }
+}
+
+/// Waits for an interrupt, unless a task was woken since the last `run_ready_tasks`,
+/// see `FIFOExecutor::idle`. The tick is resumed once the hart wakes up.
+pub fn idle() {
+    unsafe {
+        interrupt::disable();
+        // A pending interrupt ends `wfi` even though interrupts are disabled,
+        // it is taken once they are enabled below.
+        executor().idle::<Wfi>();
+        interrupt::enable();
+    }
+    interrupt::set_next_timer_interrupt();
+}
+
 pub fn waker(tid: &<ThreadFuture as executor::ThreadFuture>::ID) -> Waker {
     executor().waker(tid)
 }
@@ -49,6 +108,11 @@ pub fn thread(tid: &<ThreadFuture as executor::ThreadFuture>::ID) -> Option<Arc<
     executor().thread(tid)
 }
 
+/// Returns an iterator over all spawned threads.
+pub fn threads() -> impl Iterator<Item = Arc<Thread>> {
+    executor().threads()
+}
+
 struct BlockOnWaker {
     wake_times: Arc<AtomicUsize>,
 }
@@ -0,0 +1,68 @@
+//! Fast user-space locking.
+//!
+//! Waiters are keyed by the address space and the virtual address of the futex word.
+//! There are no shared mappings, so only the threads of an address space share a futex,
+//! and the key does not change when a write copies a copy-on-write page.
+
+use alloc::sync::Arc;
+use mm::VirtualAddress;
+
+use crate::{
+    cpu::CpuIrq,
+    mm::{fault_in, Mem},
+    spinlock::RwLockIrq,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    /// The futex word is not mapped.
+    InvalidAddress(VirtualAddress),
+    /// The futex word does not hold the expected value.
+    ValueMismatch,
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+pub type FutexWait = futex::FutexWait<'static, CpuIrq, (usize, usize)>;
+
+static FUTEXES: futex::Futexes<CpuIrq, (usize, usize)> = futex::Futexes::new();
+
+fn key(memory: &Arc<RwLockIrq<Mem>>, uaddr: VirtualAddress) -> (usize, usize) {
+    (Arc::as_ptr(memory) as usize, uaddr.0)
+}
+
+/// Queues the caller on the futex at `uaddr` if it still holds `val`, faulting in the page
+/// of the word first. The returned future completes when the waiter is woken by `wake`.
+pub async fn wait(
+    memory: &Arc<RwLockIrq<Mem>>,
+    uaddr: VirtualAddress,
+    val: u32,
+) -> Result<FutexWait> {
+    loop {
+        {
+            // The page is not evicted while the address space is locked.
+            let mem = memory.read();
+            if mem.translate(uaddr).is_some() {
+                let load = || unsafe { (uaddr.0 as *const u32).read_volatile() };
+                return FUTEXES
+                    .wait(key(memory, uaddr), val, load)
+                    .ok_or(Error::ValueMismatch);
+            }
+        }
+        fault_in(memory, uaddr)
+            .await
+            .map_err(|_| Error::InvalidAddress(uaddr))?;
+    }
+}
+
+/// Writes 0 to the thread id at `uaddr` and wakes a waiter of it, the thread that set it
+/// with `set_tid_address` is gone.
+pub fn clear_child_tid(memory: &Arc<RwLockIrq<Mem>>, uaddr: VirtualAddress) -> usize {
+    let clear = || unsafe { (uaddr.0 as *mut u32).write_volatile(0) };
+    FUTEXES.clear_child_tid(key(memory, uaddr), clear)
+}
+
+/// Wakes up to `n` waiters of the futex at `uaddr`, returns the number of woken waiters.
+pub fn wake(memory: &Arc<RwLockIrq<Mem>>, uaddr: VirtualAddress, n: usize) -> usize {
+    FUTEXES.wake(key(memory, uaddr), n)
+}
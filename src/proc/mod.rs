@@ -1,12 +1,13 @@
 pub mod executor;
 pub mod file;
+pub mod futex;
 pub mod pid;
 pub mod process;
 pub mod signal;
 pub mod thread;
 mod tid;
 
-pub use process::*;
+pub use self::process::*;
 
 use self::thread::thread_future;
 
@@ -1,8 +1,6 @@
-use alloc::{collections::BTreeMap, sync::Arc};
+use alloc::{sync::Arc, vec::Vec};
 
-use crate::spinlock::RwLockIrq;
-
-use super::{tid, Proc};
+use super::{executor, tid, Proc};
 
 pub struct Pid {
     proc: Arc<Proc>,
@@ -16,8 +14,23 @@ impl Pid {
     pub fn id(&self) -> &tid::RawThreadId {
         self.proc.id()
     }
+}
 
-    pub fn group(&self) -> &RwLockIrq<BTreeMap<tid::RawThreadId, Arc<Proc>>> {
-        &self.proc.children
-    }
+/// Returns the process whose id is `pid`.
+pub fn find(pid: &tid::RawThreadId) -> Option<Arc<Proc>> {
+    executor::thread(pid)
+        .filter(|thread| thread.is_main_thread())
+        .map(|thread| thread.proc().clone())
+}
+
+/// Returns all processes.
+pub fn procs() -> impl Iterator<Item = Arc<Proc>> {
+    executor::threads()
+        .filter(|thread| thread.is_main_thread())
+        .map(|thread| thread.proc().clone())
+}
+
+/// Returns the members of the process group `pgid`.
+pub fn group_members(pgid: tid::RawThreadId) -> Vec<Arc<Proc>> {
+    process::group::members(procs(), pgid)
 }
@@ -1,28 +1,34 @@
 use super::{
     executor, file,
-    signal::{self, SigAction, SignalFlags, SignalSet, Signo},
+    signal::{self, ProcSignal, SignalFlags, Signo},
     thread::Thread,
     tid::{self, RawThreadId},
 };
 use crate::{
-    arch::memory::kernel_segments,
+    arch::memory::{kernel_segments, user_stack_max_size, user_stack_offset},
     config,
+    cpu::CpuIrq,
     fs::{
         rootfs::{self, root_fs},
-        util::read_all,
-        DirEntry, Inode, Path,
+        util::{read_all, read_full_at},
+        vfs, DirEntry, Inode, Path,
     },
-    mm::Mem,
+    mm::{InodeSource, Mem},
     spinlock::{MutexIrq, RwLockIrq},
 };
 use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
 use core::{mem, ptr::null};
 use mm::{
     arch::page::PageParam as PageParamA,
-    memory::{MapType, Segment},
-    page::{flush::FlushAllGuard, PageParam as _},
+    brk::ProgramBreak,
+    elf::ph_table_end,
+    memory::{MapType, PageSource, Segment},
+    page::{flush::FlushBatch, PageParam as _},
     Addr, Result as MemoryResult, VirtualAddress,
 };
+pub use process::Credentials;
+pub use process::{resource, RLimit, RLimits, ShareFlags};
+use process::{CpuUsage, Family, FdTable, Group};
 use xmas_elf::{header, program, ElfFile};
 
 #[derive(Debug)]
@@ -37,16 +43,62 @@ pub type Result<T> = core::result::Result<T, Error>;
 pub struct Proc {
     id: tid::RawThreadId,
     pub main_thread: Arc<Thread>,
-    pub group_leader: RwLockIrq<Option<Arc<Proc>>>,
-    pub parent: RwLockIrq<Option<Arc<Proc>>>,
-    pub children: RwLockIrq<BTreeMap<tid::RawThreadId, Arc<Proc>>>,
+    pub group: Group<CpuIrq, Proc>,
+    pub family: Family<CpuIrq, Proc>,
     pub threads: RwLockIrq<BTreeMap<tid::RawThreadId, Arc<Thread>>>,
     cmd: String,
     // Current working directory
     pub cwd: crate::sleeplock::RwLock<DirEntry>,
-    pub open_files: OpenFiles,
-    pub memory: RwLockIrq<Mem>,
-    signal: MutexIrq<Signal>,
+    /// Shared with the processes forked with `ShareFlags::FILES`
+    pub open_files: Arc<OpenFiles>,
+    /// Shared with the processes forked with `ShareFlags::MEMORY`
+    pub memory: Arc<RwLockIrq<Mem>>,
+    pub brk: MutexIrq<ProgramBreak>,
+    signal: MutexIrq<ProcSignal>,
+    credentials: RwLockIrq<Credentials>,
+    // Permission bits cleared from the mode of files created by the process
+    umask: MutexIrq<vfs::Umask>,
+    /// The status `wait` reports for the process once it has exited.
+    pub wait_status: MutexIrq<Option<i32>>,
+    rlimits: MutexIrq<RLimits>,
+    /// CPU time used by the threads of the process and by its reaped children.
+    pub cpu_usage: MutexIrq<CpuUsage>,
+}
+
+/// The umask of the init process, new files are not writable by group and others.
+const DEFAULT_UMASK: vfs::Umask =
+    vfs::Umask::new(vfs::Mode::PERM_W_GRP.union(vfs::Mode::PERM_W_OTH));
+
+/// The resource limits a process starts with.
+fn default_rlimits() -> RLimits {
+    let mut limits = RLimits::unlimited();
+    limits.replace(
+        resource::STACK,
+        RLimit::new(user_stack_max_size() as u64, RLimit::INFINITY),
+    );
+    limits.replace(
+        resource::NOFILE,
+        RLimit::new(
+            config::PROC_MAX_OPEN_FILES as u64,
+            config::PROC_MAX_OPEN_FILES as u64,
+        ),
+    );
+    limits.replace(
+        resource::SIGPENDING,
+        RLimit::new(
+            config::SIGPENDING_QUEUE_CAP as u64,
+            config::SIGPENDING_QUEUE_CAP as u64,
+        ),
+    );
+    limits
+}
+
+/// The lowest address the user stack may grow down to under the stack limit `limit`.
+/// The stack may not grow out of the window reserved for it.
+fn stack_limit_for(limit: RLimit) -> VirtualAddress {
+    let size = limit.cur_usize().min(user_stack_max_size()) / PageParamA::PAGE_SIZE
+        * PageParamA::PAGE_SIZE;
+    VirtualAddress(user_stack_offset() - size)
 }
 
 impl Proc {
@@ -56,7 +108,7 @@ impl Proc {
         init: bool,
         main_thread: Arc<Thread>,
     ) -> Result<Arc<Self>> {
-        let mut signal = Signal::new();
+        let mut signal = ProcSignal::new(config::SIGPENDING_QUEUE_CAP);
         if init {
             signal.flags |= SignalFlags::UNKILLABLE;
         }
@@ -69,16 +121,24 @@ impl Proc {
 
         Ok(Arc::new(Self {
             id: *main_thread.id(),
+            group: Group::new(*main_thread.id()),
             main_thread,
-            group_leader: RwLockIrq::new(None),
-            parent: RwLockIrq::new(None),
-            children: RwLockIrq::new(BTreeMap::new()),
+            family: Family::new(),
             threads: RwLockIrq::new(threads),
             cmd: cmd.into(),
             cwd: crate::sleeplock::RwLock::new(cwd),
-            open_files: OpenFiles::new(),
-            memory: RwLockIrq::new(memory),
+            open_files: Arc::new(OpenFiles::new()),
+            memory: Arc::new(RwLockIrq::new(memory)),
+            brk: MutexIrq::new(ProgramBreak {
+                start: VirtualAddress(0),
+                current: VirtualAddress(0),
+            }),
             signal: MutexIrq::new(signal),
+            credentials: RwLockIrq::new(Credentials::root()),
+            umask: MutexIrq::new(DEFAULT_UMASK),
+            wait_status: MutexIrq::new(None),
+            rlimits: MutexIrq::new(default_rlimits()),
+            cpu_usage: MutexIrq::new(CpuUsage::default()),
         }))
     }
 
@@ -117,11 +177,12 @@ impl Proc {
         prog: Inode,
         args: Vec<String>,
         envs: Vec<String>,
-    ) -> Result<FlushAllGuard<PageParamA>> {
-        let bytes = read_all(prog).await.map_err(|_fs_err| {
-            // TODO: trace log _fs_err
-            Error::ElfErr("Failed to read elf file.")
-        })?;
+    ) -> Result<FlushBatch<PageParamA>> {
+        let bytes = if config::ELF_DEMAND_PAGING {
+            read_elf_headers(&prog).await?
+        } else {
+            read_all(prog.clone()).await.map_err(elf_read_err)?
+        };
 
         let elf = ElfFile::new(&bytes).map_err(Error::ElfErr)?;
 
@@ -144,18 +205,16 @@ impl Proc {
         }
 
         let mut mem = self.memory.write();
+        // Flush the pages of all loaded segments together once loading is done.
+        let mut flush = FlushBatch::new(Some(self.asid()));
+        let mut elf_end = VirtualAddress(0);
+        let source: Arc<dyn PageSource> = Arc::new(InodeSource(prog));
         for ph in elf.program_iter() {
             if ph.get_type() != Ok(program::Type::Load) {
                 continue;
             }
             let start = VirtualAddress(ph.virtual_addr() as usize);
             let size = ph.mem_size() as usize;
-            let data: &[u8] =
-                if let program::SegmentData::Undefined(data) = ph.get_data(&elf).unwrap() {
-                    data
-                } else {
-                    return Err(Error::ElfErr("unsupported elf format"));
-                };
             let mut flags = 0;
             if ph.flags().is_read() {
                 flags |= PageParamA::FLAG_PTE_READABLE;
@@ -166,39 +225,85 @@ impl Proc {
             if ph.flags().is_execute() {
                 flags |= PageParamA::FLAG_PTE_EXECUTABLE;
             }
-            mem.add_user_segment(
-                Segment {
-                    addr_range: start..(start.add(size)),
-                    flags: PageParamA::flag_set_user(flags),
-                    map_type: MapType::Framed,
-                },
-                data,
-            )
-            .map_err(Error::MemoryErr)?
-            .ignore();
+            let segment = Segment {
+                addr_range: start..(start.add(size)),
+                flags: PageParamA::flag_set_user(flags),
+                map_type: MapType::Framed,
+            };
+            if config::ELF_DEMAND_PAGING {
+                mem.add_lazy_user_segment(
+                    segment,
+                    source.clone(),
+                    ph.offset() as usize,
+                    ph.file_size() as usize,
+                )
+                .map_err(Error::MemoryErr)?;
+            } else {
+                let data: &[u8] =
+                    if let program::SegmentData::Undefined(data) = ph.get_data(&elf).unwrap() {
+                        data
+                    } else {
+                        return Err(Error::ElfErr("unsupported elf format"));
+                    };
+                flush.append(
+                    mem.add_user_segment(segment, data)
+                        .map_err(Error::MemoryErr)?,
+                );
+            }
+            elf_end = elf_end.max(start.add(size));
         }
+        let heap_start = elf_end
+            .add(PageParamA::PAGE_SIZE - 1)
+            .align_down_to(PageParamA::PAGE_SIZE);
+        *self.brk.lock() = ProgramBreak {
+            start: heap_start,
+            current: heap_start,
+        };
+
         let proc_init_info = ProcInitInfo {
             args,
             envs,
             auxval: Auxval::from_elf(&elf),
         };
         self.main_thread.reset_context(&proc_init_info);
-        Ok(FlushAllGuard::new(Some(self.asid())))
+        Ok(flush)
     }
 
-    pub async fn fork(&self, asid: usize, main_thread: Arc<Thread>) -> MemoryResult<Self> {
+    pub async fn fork(
+        &self,
+        asid: usize,
+        main_thread: Arc<Thread>,
+        share: ShareFlags,
+    ) -> MemoryResult<Self> {
+        let open_files = if share.contains(ShareFlags::FILES) {
+            self.open_files.clone()
+        } else {
+            Arc::new((*self.open_files).clone())
+        };
+        let memory = if share.contains(ShareFlags::MEMORY) {
+            self.memory.clone()
+        } else {
+            Arc::new(RwLockIrq::new(self.memory.write().borrow_memory(asid)?))
+        };
+        let mut threads = BTreeMap::new();
+        threads.insert(*main_thread.id(), main_thread.clone());
         Ok(Self {
             id: *main_thread.id(),
             main_thread,
-            group_leader: RwLockIrq::new(self.group_leader.read().clone()),
-            parent: RwLockIrq::new(None),
-            children: RwLockIrq::new(BTreeMap::new()),
-            threads: RwLockIrq::new(BTreeMap::new()),
+            group: self.group.fork(),
+            family: Family::new(),
+            threads: RwLockIrq::new(threads),
             cmd: self.cmd.clone(),
             cwd: crate::sleeplock::RwLock::new(self.cwd.read().await.clone()),
-            open_files: self.open_files.clone(),
-            memory: RwLockIrq::new(self.memory.read().borrow_memory(asid)?),
+            open_files,
+            memory,
+            brk: MutexIrq::new(*self.brk.lock()),
             signal: MutexIrq::new(self.signal.lock().fork()),
+            credentials: RwLockIrq::new(self.credentials()),
+            umask: MutexIrq::new(self.umask()),
+            wait_status: MutexIrq::new(None),
+            rlimits: MutexIrq::new(*self.rlimits.lock()),
+            cpu_usage: MutexIrq::new(CpuUsage::default()),
         })
     }
 
@@ -206,7 +311,7 @@ impl Proc {
         self.id == 1
     }
 
-    pub fn signal(&self) -> &MutexIrq<Signal> {
+    pub fn signal(&self) -> &MutexIrq<ProcSignal> {
         &self.signal
     }
 
@@ -214,209 +319,253 @@ impl Proc {
         &self.id
     }
 
-    pub fn exit(&self, _status: isize) {
-        self.threads
-            .read()
-            .iter()
-            .filter(|(_, t)| !t.is_main_thread())
-            .for_each(|(_, t)| {
-                t.exit(0);
-                t.waker().wake();
-            });
-        // TODO: Handling sub-processes
+    pub fn credentials(&self) -> Credentials {
+        *self.credentials.read()
     }
 
-    fn asid(&self) -> usize {
-        *self.id() as usize
+    pub fn set_credentials(&self, credentials: Credentials) {
+        *self.credentials.write() = credentials;
     }
-}
 
-pub struct Signal {
-    actions: [SigAction; signal::NSIG as usize],
-    /// `shared_pending` holds the signals sent to the process group
-    pub shared_pending: signal::Pending,
-    /// Blocked signals set
-    pub blocked: SigBlocked,
-    /// Current thread group signal load-balancing target
-    /// A signal sent to a process group requires a thread in the process to handle it.
-    /// For load balancing purposes,
-    /// `current_target` stores the current thread that is handling the signal,
-    /// ensure that the threads processing the signal are as different as possible each time.
-    pub current_target: Option<RawThreadId>,
-    pub flags: SignalFlags,
-}
-
-pub struct SigBlocked {
-    pub blocked: SignalSet,
-    pub real_blocked: SignalSet,
-}
-
-impl Signal {
-    pub fn new() -> Self {
-        Self {
-            actions: array_init::array_init(|_| Default::default()),
-            shared_pending: signal::Pending::new(),
-            blocked: SigBlocked {
-                blocked: SignalSet::empty(),
-                real_blocked: SignalSet::empty(),
-            },
-            current_target: None,
-            flags: SignalFlags::empty(),
-        }
+    pub fn umask(&self) -> vfs::Umask {
+        *self.umask.lock()
     }
 
-    pub fn fork(&self) -> Self {
-        Self {
-            actions: self.actions.clone(),
-            shared_pending: signal::Pending::new(),
-            blocked: SigBlocked {
-                blocked: SignalSet::empty(),
-                real_blocked: SignalSet::empty(),
-            },
-            current_target: None,
-            flags: SignalFlags::empty(),
-        }
+    /// Replaces the umask, returns the previous umask.
+    pub fn replace_umask(&self, mask: vfs::Mode) -> vfs::Umask {
+        self.umask.lock().replace(mask)
     }
 
-    pub fn action(&self, sig: &Signo) -> &SigAction {
-        unsafe { self.actions.get_unchecked(sig.to_primitive() as usize - 1) }
+    /// Returns the limit of `resource`, None if `resource` is not a `RLIMIT_*` value.
+    pub fn rlimit(&self, resource: usize) -> Option<RLimit> {
+        self.rlimits.lock().get(resource)
     }
 
-    pub fn action_mut(&mut self, sig: &Signo) -> &mut SigAction {
-        unsafe {
-            self.actions
-                .get_unchecked_mut(sig.to_primitive() as usize - 1)
-        }
+    /// The lowest address the user stack may grow down to under `RLIMIT_STACK`.
+    pub fn stack_limit(&self) -> VirtualAddress {
+        stack_limit_for(self.rlimit(resource::STACK).unwrap())
     }
 
-    pub fn replace_action(&mut self, sig: &Signo, sa: SigAction) -> SigAction {
-        unsafe {
-            mem::replace(
-                self.actions.get_unchecked_mut(sig.to_primitive() as usize),
-                sa,
-            )
+    /// Replaces the limit of `resource` and applies it, returns the previous limit.
+    /// The caller checks that `limit` is valid and that it may be set.
+    pub fn set_rlimit(&self, resource: usize, limit: RLimit) -> Option<RLimit> {
+        let mut rlimits = self.rlimits.lock();
+        let old = rlimits.replace(resource, limit)?;
+        match resource {
+            resource::NOFILE => self.open_files.set_limit(limit.cur_usize()),
+            resource::SIGPENDING => self.signal.lock().pending_limit = limit.cur_usize(),
+            resource::STACK => {
+                let stack_top = VirtualAddress(user_stack_offset() - 1);
+                self.memory
+                    .write()
+                    .set_grow_down_limit(stack_top, stack_limit_for(limit));
+            }
+            _ => {}
         }
+        Some(old)
     }
-}
 
-pub struct OpenFiles(RwLockIrq<OpenFileInner>);
+    pub fn cmd(&self) -> &str {
+        &self.cmd
+    }
 
-impl Clone for OpenFiles {
-    fn clone(&self) -> Self {
-        Self(RwLockIrq::new(self.0.read().clone()))
+    pub fn pgid(&self) -> RawThreadId {
+        self.group.pgid()
     }
-}
 
-#[derive(Clone)]
-struct OpenFileInner {
-    max_fd: usize,
-    next_fd: usize,
-    files: Vec<Option<file::Descriptor>>,
-}
+    pub fn sid(&self) -> RawThreadId {
+        self.group.sid()
+    }
 
-impl OpenFileInner {
-    fn add_file(&mut self, file: file::Descriptor) -> Option<usize> {
-        self.insert_file(self.next_fd, file)
+    pub fn exit(&self, _status: isize) {
+        self.threads
+            .read()
+            .iter()
+            .filter(|(_, t)| !t.is_main_thread())
+            .for_each(|(_, t)| {
+                t.exit(0);
+                t.waker().wake();
+            });
+        // TODO: Handling sub-processes
     }
 
-    fn add_file_min(&mut self, file: file::Descriptor, min: usize) -> Option<usize> {
-        let fd_num = if min <= self.next_fd {
-            self.next_fd
-        } else {
-            self.files
-                .iter()
-                .skip(min)
-                .position(Option::is_none)
-                .unwrap_or(self.files.len())
+    /// Tells the parent that the process changed state by sending it `SIGCHLD`,
+    /// `code` is one of the `signal::CLD_*` values and `status` the exit code or the signal.
+    /// A process that has terminated is reaped right away
+    /// if the parent sets `SA_NOCLDWAIT` or ignores `SIGCHLD`.
+    pub fn notify_parent(&self, code: isize, status: i32) {
+        let parent = match self.family.parent() {
+            Some(parent) => parent,
+            None => return,
         };
+        let exited = !matches!(code, signal::CLD_STOPPED | signal::CLD_CONTINUED);
+        if exited {
+            *self.wait_status.lock() = Some(match code {
+                signal::CLD_EXITED => (status & 0xff) << 8,
+                _ => status & 0x7f,
+            });
+        }
 
-        self.insert_file(fd_num, file)
+        let (send, reap) = parent.signal().lock().child_notification(code);
+        if reap {
+            parent.reap_child(&self.id);
+        }
+        if send {
+            let info = signal::Info::child(self.id, self.credentials().uid, code, status);
+            // Lost only if the pending queue of the parent is full.
+            let _ = signal::signal().send_signal(
+                Signo::SIGCHLD,
+                info,
+                signal::SendTo::ProcGroup(&parent),
+            );
+        }
     }
 
-    fn insert_file(&mut self, fd_num: usize, file: file::Descriptor) -> Option<usize> {
-        if fd_num < config::PROC_MAX_OPEN_FILES {
-            if fd_num >= self.files.len() {
-                self.files.resize(fd_num + 1, None);
-            }
+    /// Removes the terminated child `id`, its CPU time and the one of the children
+    /// it reaped count to the children of the process from then on.
+    pub fn reap_child(&self, id: &RawThreadId) -> Option<Arc<Proc>> {
+        let child = self.family.remove_child(id)?;
+        let child_usage = *child.cpu_usage.lock();
+        self.cpu_usage.lock().reap(&child_usage);
+        Some(child)
+    }
 
-            let slot = unsafe { self.files.get_unchecked_mut(fd_num) };
+    fn asid(&self) -> usize {
+        *self.id() as usize
+    }
+}
 
-            if slot.is_none() {
-                slot.replace(file);
+impl process::Process for Proc {
+    type Irq = CpuIrq;
 
-                if fd_num == self.next_fd {
-                    self.next_fd = self
-                        .files
-                        .iter()
-                        .skip(self.next_fd + 1)
-                        .position(Option::is_none)
-                        .unwrap_or(self.files.len())
-                }
+    fn pid(&self) -> RawThreadId {
+        self.id
+    }
 
-                if fd_num > self.max_fd {
-                    self.max_fd = fd_num;
-                }
+    fn family(&self) -> &Family<CpuIrq, Proc> {
+        &self.family
+    }
 
-                return Some(fd_num);
-            }
-        }
-        None
+    fn group(&self) -> &Group<CpuIrq, Proc> {
+        &self.group
     }
+}
 
-    fn remove_file(&mut self, fd_num: usize) -> Option<file::Descriptor> {
-        let removed_file = self.files.get_mut(fd_num).and_then(|f| f.take());
-        if removed_file.is_some() {
-            if fd_num == self.max_fd {
-                let max_fd = self.files.iter().rposition(Option::is_some).unwrap_or(0);
-                self.files.truncate(max_fd + 1);
-                self.files.shrink_to_fit();
-                self.max_fd = max_fd;
-            }
+fn elf_read_err(_fs_err: vfs::Error) -> Error {
+    // TODO: trace log _fs_err
+    Error::ElfErr("Failed to read elf file.")
+}
 
-            if fd_num < self.next_fd {
-                self.next_fd = fd_num
-            }
-        }
+/// Reads the ELF header and the program headers of `prog`, the segments are left out.
+async fn read_elf_headers(prog: &Inode) -> Result<Vec<u8>> {
+    // The size of the ELF header of 64-bit files, the header of 32-bit files is shorter.
+    let mut bytes = vec![0; 64];
+    let read = read_full_at(prog, 0, &mut bytes)
+        .await
+        .map_err(elf_read_err)?;
+    bytes.truncate(read);
+    let header = match header::parse_header(&bytes) {
+        Ok(header) => header,
+        // Left to `ElfFile::new` to report.
+        Err(_) => return Ok(bytes),
+    };
+    let file_size = prog.metadata().await.map_err(elf_read_err)?.size;
+    let ph_end = ph_table_end(
+        header.pt2.ph_offset(),
+        header.pt2.ph_entry_size(),
+        header.pt2.ph_count(),
+        file_size,
+    )
+    .ok_or(Error::ElfErr("program headers out of the file"))?;
+    if ph_end > read {
+        bytes.resize(ph_end, 0);
+        let tail = read_full_at(prog, read as u64, &mut bytes[read..])
+            .await
+            .map_err(elf_read_err)?;
+        bytes.truncate(read + tail);
+    }
+    Ok(bytes)
+}
 
-        removed_file
+pub struct OpenFiles(RwLockIrq<FdTable<file::Descriptor>>);
+
+impl Clone for OpenFiles {
+    fn clone(&self) -> Self {
+        Self(RwLockIrq::new(self.0.read().clone()))
     }
 }
 
 impl OpenFiles {
     fn new() -> Self {
-        Self(RwLockIrq::new(OpenFileInner {
-            max_fd: 0,
-            next_fd: 0,
-            files: Vec::new(),
-        }))
+        Self(RwLockIrq::new(FdTable::new(config::PROC_MAX_OPEN_FILES)))
+    }
+
+    /// Limit the file descriptor numbers of new files to below `limit`.
+    /// Files that are already open are kept.
+    pub fn set_limit(&self, limit: usize) {
+        self.0
+            .write()
+            .set_limit(limit.min(config::PROC_MAX_OPEN_FILES));
     }
 
     /// Get a file
     pub fn get_file(&self, fd_num: usize) -> Option<file::Descriptor> {
-        self.0.read().files.get(fd_num).and_then(|fd| fd.clone())
+        self.0.read().get(fd_num).cloned()
     }
 
     /// Add a file to the lowest available slot.
     /// Return the file descriptor number or None if no slot was found
     pub fn add_file(&self, file: file::Descriptor) -> Option<usize> {
-        self.0.write().add_file(file)
+        self.0.write().add(file)
     }
 
     /// Add a file to the lowest available slot greater than or equal to min.
     /// Return the file descriptor number or None if no slot was found
     pub fn add_file_min(&self, file: file::Descriptor, min: usize) -> Option<usize> {
-        self.0.write().add_file_min(file, min)
+        self.0.write().add_min(file, min)
     }
 
     /// Insert a file with a specific fd number. This is used by dup2
     /// Return the file descriptor number or None if the slot was not empty, or fd_num was invalid
     pub fn insert_file(&self, fd_num: usize, file: file::Descriptor) -> Option<usize> {
-        self.0.write().insert_file(fd_num, file)
+        self.0.write().insert(fd_num, file)
+    }
+
+    /// Place a file at a specific fd number, removing the file that was there. This is used by dup3
+    /// Return the removed file or None if fd_num was invalid
+    pub fn replace_file(
+        &self,
+        fd_num: usize,
+        file: file::Descriptor,
+    ) -> Option<Option<file::Descriptor>> {
+        self.0.write().replace(fd_num, file)
+    }
+
+    /// Set the close-on-exec flag of a file.
+    /// Return None if there is no file with fd_num
+    pub fn set_cloexec(&self, fd_num: usize, cloexec: bool) -> Option<()> {
+        self.0.write().get_mut(fd_num)?.set_cloexec(cloexec);
+        Some(())
+    }
+
+    /// Returns all open files.
+    pub fn files(&self) -> Vec<file::Descriptor> {
+        self.0.read().iter().map(|(_, file)| file.clone()).collect()
+    }
+
+    /// Remove all files marked close-on-exec. This is used by execve
+    pub fn remove_cloexec_files(&self) -> Vec<file::Descriptor> {
+        self.0.write().remove_if(file::Descriptor::cloexec)
     }
 
     /// Remove a file
     pub fn remove_file(&self, fd_num: usize) -> Option<file::Descriptor> {
-        self.0.write().remove_file(fd_num)
+        self.0.write().remove(fd_num)
+    }
+
+    /// Remove all files, for the caller to close them. This is used by exit
+    pub fn remove_all(&self) -> Vec<file::Descriptor> {
+        self.0.write().remove_all()
     }
 }
 
@@ -427,7 +576,7 @@ pub fn create_init_proc() -> Arc<Proc> {
         .expect("init proc not exist. path: '/init'");
 
     // TODO trace error
-    executor::block_on(async {
+    let init_proc = executor::block_on(async {
         Proc::from_elf(
             "/init",
             root_fs().root().await,
@@ -438,7 +587,10 @@ pub fn create_init_proc() -> Arc<Proc> {
         )
         .await
     })
-    .expect("Field to create init proc")
+    .expect("Field to create init proc");
+    // The console is the controlling terminal of the first session.
+    crate::fs::tty().set_controlling(&init_proc);
+    init_proc
 }
 
 pub struct ProcInitInfo {
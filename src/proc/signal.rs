@@ -4,34 +4,27 @@ use crate::{
 };
 use core::{
     future::Future,
-    iter,
-    mem::{self, ManuallyDrop},
+    iter, mem,
     pin::Pin,
     ptr,
-    sync::atomic::Ordering,
     task::{ready, Poll, Waker},
 };
 
-use alloc::{
-    boxed::Box,
-    collections::{
-        linked_list::{self, LinkedList},
-        BTreeMap,
-    },
-    sync::Arc,
-};
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc};
 
 use futures_util::future::Either;
-use mm::VirtualAddress;
+
+pub use signal::{
+    has_pendding_sigs, sig_fatal, suspend_mask, wait_for_signal, AltStack, Info, Pending,
+    ProcSignal, SigAction, SigActionFlags, SigBlocked, SigHandler, SigStack, SignalFlags,
+    SignalSet, Signo, StackError, SyscallContext, ThreadFlags, CLD_CONTINUED, CLD_EXITED,
+    CLD_KILLED, CLD_STOPPED,
+};
 
 use super::{
-    process,
-    thread::{
-        self, State as ThreadState, Thread, ThreadInner, FLAGS_HAS_PENDDING_SIGS,
-        FLAGS_SIG_STOPPING,
-    },
-    tid::{self, RawThreadId},
-    Proc, SigBlocked,
+    thread::{State as ThreadState, Thread, ThreadInner},
+    tid::RawThreadId,
+    Proc,
 };
 
 pub type Result<T> = core::result::Result<T, Error>;
@@ -40,136 +33,6 @@ pub enum Error {
     InvalidArgs,
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct SignalSet(u64);
-
-impl SignalSet {
-    pub fn empty() -> Self {
-        Self(0)
-    }
-
-    #[inline(always)]
-    pub const fn difference(&self, other: &Self) -> Self {
-        Self(self.0 & !other.0)
-    }
-
-    #[inline(always)]
-    pub const fn sigmask(sig: &Signo) -> Self {
-        Self(1 << (sig.to_primitive() as u64 - 1))
-    }
-
-    #[inline(always)]
-    pub const fn contains(&self, sig: &Signo) -> bool {
-        !Self::sigmask(sig).intersection(self).is_emptry()
-    }
-
-    #[inline(always)]
-    pub const fn union(&self, other: &Self) -> Self {
-        Self(self.0 | other.0)
-    }
-
-    #[inline(always)]
-    pub const fn inv(&self) -> Self {
-        Self(!self.0)
-    }
-
-    #[inline(always)]
-    pub const fn intersection(&self, other: &Self) -> Self {
-        Self(self.0 & other.0)
-    }
-
-    pub const fn is_emptry(&self) -> bool {
-        self.0 == 0
-    }
-
-    pub fn delset(&mut self, sig: &Signo) {
-        self.0 &= !Self::sigmask(sig).0
-    }
-
-    pub fn min_sig(&self) -> Option<Signo> {
-        Signo::from_primitive(self.0.leading_zeros() as u8 + 1)
-    }
-}
-
-const SIG_HANDLER_DFL: usize = 0;
-const SIG_HANDLER_IGN: usize = 1;
-
-#[repr(C)]
-#[derive(Clone, Copy)]
-pub union SigHandler {
-    pub handler: extern "C" fn(usize),
-    pub info_handler: extern "C" fn(usize, *const Info),
-}
-
-impl SigHandler {
-    pub fn default_handler() -> Self {
-        Self {
-            handler: unsafe { mem::transmute::<usize, _>(SIG_HANDLER_DFL) },
-        }
-    }
-
-    pub fn is_ignored(&self, sig: &Signo) -> bool {
-        let handler = self.as_usize();
-        handler == SIG_HANDLER_IGN || (handler == SIG_HANDLER_DFL && sig.kernel_ignore())
-    }
-
-    pub fn is_default(&self) -> bool {
-        self.as_usize() == SIG_HANDLER_DFL
-    }
-
-    fn as_usize(&self) -> usize {
-        unsafe { mem::transmute::<_, usize>(self) }
-    }
-}
-
-#[repr(C)]
-#[derive(Clone)]
-pub struct SigAction {
-    handler: Option<SigHandler>,
-    pub flags: SigActionFlags,
-    mask: SignalSet,
-}
-
-impl Default for SigAction {
-    fn default() -> Self {
-        Self {
-            handler: None,
-            flags: SigActionFlags::empty(),
-            mask: SignalSet::empty(),
-        }
-    }
-}
-
-impl SigAction {
-    pub fn handler(&self) -> SigHandler {
-        self.handler
-            .unwrap_or(unsafe { mem::transmute::<usize, SigHandler>(SIG_HANDLER_DFL) })
-    }
-
-    pub fn set_handler(&mut self, h: SigHandler) {
-        self.handler = Some(h)
-    }
-}
-
-bitflags! {
-    pub struct SigActionFlags: usize {
-        /// NOCLDSTOP flag to turn off SIGCHLD when children stop.
-        const NOCLDSTOP = 0x00000001;
-        /// NOCLDWAIT flag on SIGCHLD to inhibit zombies.
-        const NOCLDWAIT = 0x00000002;
-        /// SIGINFO delivers the signal with `Info` structs.
-        const SIGINFO = 0x00000004;
-        /// ONSTACK indicates that a registered `AltStack` will be used.
-        const ONSTACK = 0x08000000;
-        /// RESTART flag to get restarting signals (which were the default long ago)
-        const RESTART = 0x10000000;
-        /// NODEFER prevents the current signal from being masked in the handler.
-        const NODEFER = 0x40000000;
-        /// RESETHAND clears the handler when the signal is delivered.
-        const RESETHAND = 0x80000000;
-    }
-}
-
 pub fn do_sigaction(
     thread: Pin<&mut Thread>,
     sig: &Signo,
@@ -255,30 +118,23 @@ impl Signal {
     fn get_signal(&self, thread: &Arc<Thread>) -> Poll<Option<(SigAction, Info)>> {
         let mut proc_signal = thread.proc().signal().lock();
         let pending = unsafe { thread.sig_pending.assume_locked() };
-        let blocked = proc_signal.blocked.blocked;
+        let blocked = blocked_for(thread, &proc_signal.blocked.blocked);
 
         let (act, info) = loop {
-            let (mut info_opt, mut only_one) = dequeue_signal(pending, &blocked);
-            if info_opt.is_none() {
-                let (shared_info, shared_only_one) =
-                    dequeue_signal(&mut proc_signal.shared_pending, &blocked);
-                info_opt = shared_info;
-                only_one = shared_only_one;
-            }
-
+            let (info_opt, only_one) = proc_signal.dequeue(pending, &blocked);
             match info_opt {
                 None => {
                     return Poll::Ready(None);
                 }
                 Some(info) => {
-                    if only_one && !has_pendding_sigs(&pending.signal, &proc_signal) {
-                        // remove FLAGS_SIG_STOPPING thread flag
-                        let flags = thread.flags.load(Ordering::Acquire);
-                        if flags & FLAGS_HAS_PENDDING_SIGS != 0 {
-                            thread
-                                .flags
-                                .store(flags & !FLAGS_HAS_PENDDING_SIGS, Ordering::Release);
-                        }
+                    if only_one
+                        && !has_pendding_sigs(
+                            pending.signal(),
+                            proc_signal.shared_pending.signal(),
+                            &blocked,
+                        )
+                    {
+                        thread.flags.clear_pending_sigs();
                     }
 
                     let act = proc_signal.action_mut(&info.sig);
@@ -299,17 +155,25 @@ impl Signal {
                     }
 
                     if info.sig.kernel_stop() {
-                        let mut wakers = self.wakers.lock();
+                        {
+                            let mut wakers = self.wakers.lock();
+                            thread
+                                .proc()
+                                .threads
+                                .read()
+                                .iter()
+                                .for_each(|(_, t)| do_sig_stop(t, &mut wakers));
+                        }
+                        // Sending SIGCHLD takes the wakers lock.
                         thread
                             .proc()
-                            .threads
-                            .read()
-                            .iter()
-                            .for_each(|(_, t)| do_sig_stop(t, &mut wakers));
+                            .notify_parent(CLD_STOPPED, info.sig.to_primitive() as i32);
 
                         return Poll::Pending;
                     }
 
+                    // The mask installed by `rt_sigsuspend` only lasts until a handler is set up.
+                    unsafe { *thread.sig_suspend_mask.assume_locked() = None };
                     break (ret_act, info);
                 }
             }
@@ -327,17 +191,23 @@ impl Signal {
         let interr_ctx = &mut thread_inner.context;
         if let Some((act, info)) = ready!(self.get_signal(thread)) {
             let signo = info.sig;
-            let (sig_sp, info_user_ptr) = if act.flags.contains(SigActionFlags::SIGINFO) {
-                let sig_sp = thread_inner.sig_alt_stack.sp;
-                (sig_sp, copy_info_to_user(sig_sp, info) as *const _)
+            // Switch to the alternate stack, unless the thread is already running on it.
+            let mut sig_sp = thread_inner
+                .sig_alt_stack
+                .handler_sp(act.flags.contains(SigActionFlags::ONSTACK), interr_ctx.sp());
+            let info_user_ptr = if act.flags.contains(SigActionFlags::SIGINFO) {
+                let info_ptr = copy_info_to_user(sig_sp, info);
+                // Keep the stack of the handler 16-byte aligned below the info.
+                sig_sp = info_ptr as usize & !0xf;
+                info_ptr as *const _
             } else {
-                (interr_ctx.sp(), ptr::null())
+                ptr::null()
             };
 
-            let sig_ctx = SignalContext {
-                arch_ctx: ArchSigCtx::from_interr_ctx(interr_ctx),
-                syscall: None,
-            };
+            let sig_ctx = SignalContext::new(
+                ArchSigCtx::from_interr_ctx(interr_ctx),
+                act.flags.contains(SigActionFlags::RESTART),
+            );
             thread_inner.sig_ctx = Some(sig_ctx);
             set_signal_handler(
                 interr_ctx,
@@ -372,28 +242,20 @@ impl Signal {
             return Ok(());
         }
 
-        let pending = match send_to {
-            SendTo::ProcGroup(_) => &mut proc_signal.shared_pending,
-            SendTo::Thread(thread) => unsafe { thread.sig_pending.assume_locked() },
+        let thread_pending = match send_to {
+            SendTo::ProcGroup(_) => None,
+            SendTo::Thread(thread) => Some(unsafe { thread.sig_pending.assume_locked() }),
         };
-
-        if sig.legacy() && pending.contains(&sig) {
+        if !proc_signal.queue(thread_pending, info)? {
             return Ok(());
         }
-
-        pending.push(info)?;
         self.signal_wakeup(&sig, &send_to, &mut proc_signal);
         Ok(())
     }
 
     /// Returns true if the signal should be actually delivered, otherwise
     /// it should be dropped.
-    fn prepare_signal(
-        &self,
-        sig: Signo,
-        proc: &Arc<Proc>,
-        proc_signal: &mut process::Signal,
-    ) -> bool {
+    fn prepare_signal(&self, sig: Signo, proc: &Arc<Proc>, proc_signal: &mut ProcSignal) -> bool {
         if sig.kernel_stop() {
             // This is a stop signal.  Remove SIGCONT from all queues.
             let flush = SignalSet::sigmask(&Signo::SIGCONT);
@@ -408,26 +270,28 @@ impl Signal {
             proc_signal
                 .shared_pending
                 .flush_by_mask(&Signo::MASK_SIG_KERNEL_STOP);
-            let wakers = self.wakers.lock();
-            proc.threads.read().iter().for_each(|(_, t)| {
-                unsafe { t.sig_pending.assume_locked() }
-                    .flush_by_mask(&Signo::MASK_SIG_KERNEL_STOP);
-                let thread_flags = t.flags.load(Ordering::Acquire);
-                // remove FLAGS_SIG_STOPPING thread flag
-                if thread_flags & FLAGS_SIG_STOPPING != 0 {
-                    t.flags
-                        .store(thread_flags & !FLAGS_SIG_STOPPING, Ordering::Release);
-                }
-                if let Some(w) = wakers.get(t.id()) {
-                    w.wake_by_ref()
-                }
-            })
+            let mut stopped = false;
+            {
+                let wakers = self.wakers.lock();
+                proc.threads.read().iter().for_each(|(_, t)| {
+                    unsafe { t.sig_pending.assume_locked() }
+                        .flush_by_mask(&Signo::MASK_SIG_KERNEL_STOP);
+                    stopped |= t.flags.cont();
+                    if let Some(w) = wakers.get(t.id()) {
+                        w.wake_by_ref()
+                    }
+                });
+            }
+            // Sending SIGCHLD takes the wakers lock.
+            if stopped {
+                proc.notify_parent(CLD_CONTINUED, sig.to_primitive() as i32);
+            }
         }
 
-        !sig_ignored(&sig, proc_signal, proc.is_init())
+        !proc_signal.ignored(&sig, proc.is_init())
     }
 
-    fn signal_wakeup(&self, sig: &Signo, send_to: &SendTo, proc_signal: &mut process::Signal) {
+    fn signal_wakeup(&self, sig: &Signo, send_to: &SendTo, proc_signal: &mut ProcSignal) {
         let wants_signal_fn = wants_signal_fn(self.thread_is_stop_fn());
 
         let (target, proc) = match send_to {
@@ -462,10 +326,8 @@ impl Signal {
             // This signal will be fatal to the whole thread group.
             proc.threads.read().iter().for_each(|(_, t)| {
                 t.try_wake_up_state(&ThreadState::KILLABLE);
-                let flags = t.flags.load(Ordering::Acquire);
-                if flags & FLAGS_SIG_STOPPING == 0 {
-                    t.flags.store(flags & FLAGS_SIG_STOPPING, Ordering::Release);
-                }
+                // The stop flag must stay untouched.
+                t.flags.set_pending_sigs();
             });
             return;
         }
@@ -475,12 +337,7 @@ impl Signal {
         } else {
             &ThreadState::INTERRUPTIBLE
         });
-        let flags = target_thread.flags.load(Ordering::Acquire);
-        if flags & FLAGS_SIG_STOPPING == 0 {
-            target_thread
-                .flags
-                .store(flags & FLAGS_SIG_STOPPING, Ordering::Release);
-        }
+        target_thread.flags.set_pending_sigs();
     }
 
     fn thread_is_stop_fn(&self) -> impl Fn(&RawThreadId) -> bool + '_ {
@@ -489,6 +346,18 @@ impl Signal {
     }
 }
 
+/// Send a synchronous signal, like SIGSEGV, raised by `thread` itself, see `ProcSignal::force`.
+/// Returns true if the default action must be taken, which terminates the process of `thread`.
+pub fn force_signal(thread: &Arc<Thread>, info: Info) -> bool {
+    let sig = info.sig;
+    if thread.proc().signal().lock().force(&sig) {
+        return true;
+    }
+    // Fault signals are legacy signals, they are not dropped even if the queue is full.
+    let _ = signal().send_signal(sig, info, SendTo::Thread(thread));
+    false
+}
+
 pub fn copy_info_to_user(sig_sp: usize, info: Info) -> *mut Info {
     let info_user_addr = sig_sp - mem::size_of::<Info>();
 
@@ -499,114 +368,42 @@ pub fn copy_info_to_user(sig_sp: usize, info: Info) -> *mut Info {
     info_ptr
 }
 
-fn dequeue_signal(pending: &mut Pending, mask: &SignalSet) -> (Option<Info>, bool) {
-    let mut s = pending.signal.difference(mask);
-    let target_sig = if !s.is_emptry() {
-        // Synchronous signals should be dequeued first.
-        let sync = s.intersection(&Signo::MASK_SIG_SYNCHRONOUS);
-        if !sync.is_emptry() {
-            s = sync;
-        }
-        s.min_sig().unwrap()
-    } else {
-        return (None, false);
-    };
-
-    let mut target_kernel_info_opt: *mut linked_list::CursorMut<'_, Info> = ptr::null_mut();
-    let mut target_info_opt: *mut linked_list::CursorMut<'_, Info> = ptr::null_mut();
-    let mut only_one_target = true;
-
-    let mut x = pending.queue.cursor_front_mut();
-
-    while let Some(info) = x.current() {
-        if info.sig == target_sig {
-            if info.code > SI_USER {
-                match unsafe { target_kernel_info_opt.as_mut() } {
-                    None => target_kernel_info_opt = &mut x as *mut _,
-                    Some(_) => {
-                        only_one_target = false;
-                        break;
-                    }
-                }
-            } else {
-                match unsafe { target_info_opt.as_mut() } {
-                    None => target_info_opt = &mut x as *mut _,
-                    Some(_) => only_one_target = false,
-                }
-            }
-        }
-
-        x.move_next();
-    }
-
-    let target_info = match unsafe { (target_kernel_info_opt.as_mut(), target_info_opt.as_mut()) } {
-        (Some(target), Some(_)) => {
-            only_one_target = false;
-            target
-        }
-        (None, Some(target)) => target,
-        (Some(target), None) => target,
-        (None, None) => return (None, false),
-    };
-
-    if only_one_target {
-        pending.signal.delset(&target_info.current().unwrap().sig);
-    }
-
-    target_info.remove_current();
-    (target_info.current().cloned(), only_one_target)
+/// Returns true if a signal that is not blocked is pending for `thread`.
+pub fn signal_pending(thread: &Arc<Thread>) -> bool {
+    let proc_signal = thread.proc().signal().lock();
+    let pending = unsafe { thread.sig_pending.assume_locked() };
+    has_pendding_sigs(
+        pending.signal(),
+        proc_signal.shared_pending.signal(),
+        &blocked_for(thread, &proc_signal.blocked.blocked),
+    )
 }
 
-fn has_pendding_sigs(thread_pending_signal: &SignalSet, proc_signal: &process::Signal) -> bool {
-    let blocked = &proc_signal.blocked.blocked;
+/// Returns the signals blocked for `thread`, `proc_blocked` unless it waits in `rt_sigsuspend`.
+/// The caller must hold the proc.signal lock.
+fn blocked_for(thread: &Thread, proc_blocked: &SignalSet) -> SignalSet {
+    unsafe { *thread.sig_suspend_mask.assume_locked() }.unwrap_or(*proc_blocked)
+}
 
-    thread_pending_signal.difference(blocked).is_emptry()
-        && proc_signal
-            .shared_pending
-            .signal
-            .difference(blocked)
-            .is_emptry()
+/// Installs `mask` as the signal mask of `thread` until a signal handler is set up.
+/// SIGKILL and SIGSTOP can not be blocked.
+pub fn set_suspend_mask(thread: &Arc<Thread>, mask: SignalSet) {
+    let _proc_signal = thread.proc().signal().lock();
+    unsafe { *thread.sig_suspend_mask.assume_locked() = Some(suspend_mask(mask)) };
 }
 
 fn do_sig_stop(thread: &Arc<Thread>, signal_wakers: &mut SignalWakers) {
-    let mut flags = thread.flags.load(Ordering::Acquire);
-    if flags & thread::FLAGS_SIG_STOPPING != 0 {
+    if !thread.flags.stop() {
         return;
     }
     signal_wakers.insert(*thread.id(), thread.waker());
-    flags &= thread::FLAGS_SIG_STOPPING;
-    thread.flags.store(flags, Ordering::Release);
-}
-
-fn sig_ignored(sig: &Signo, proc_signal: &process::Signal, is_init_proc: bool) -> bool {
-    // Blocked signals are never ignored,
-    // since the signal handler may change by the time it is unblocked.
-    if proc_signal.blocked.blocked.contains(sig) || proc_signal.blocked.real_blocked.contains(sig) {
-        return false;
-    }
-
-    let handler = proc_signal.action(sig).handler();
-
-    // SIGKILL and SIGSTOP may not be sent to the global init
-    if is_init_proc && sig.kernel_only() {
-        return true;
-    }
-
-    if proc_signal.flags.contains(SignalFlags::UNKILLABLE)
-        && handler.is_default()
-        && !sig.kernel_only()
-    {
-        return true;
-    }
-
-    handler.is_ignored(sig)
 }
 
 fn wants_signal_fn(
     thread_is_stop_fn: impl Fn(&RawThreadId) -> bool,
 ) -> impl Fn(&Signo, &Arc<Thread>, &SigBlocked) -> bool {
     move |sig, thread, sig_blocked| -> bool {
-        if sig_blocked.blocked.contains(sig) {
+        if blocked_for(thread, &sig_blocked.blocked).contains(sig) {
             return false;
         }
         if sig == &Signo::SIGKILL {
@@ -617,8 +414,7 @@ fn wants_signal_fn(
             return false;
         }
 
-        let flags = thread.flags.load(Ordering::Acquire);
-        flags & FLAGS_SIG_STOPPING == 0
+        !thread.flags.stopping()
     }
 }
 
@@ -635,289 +431,19 @@ fn thread_iter(
         None => Either::Left(threads.iter()),
     };
 
-    iter::from_fn(move || {
-        loop {
-            match &mut it {
-                Either::Left(it_left) => match it_left.next() {
-                    Some((_, thread)) => return Some(thread),
-                    None => it = Either::Left(threads.iter()),
-                },
-                Either::Right(it_right) => {
-                    return it_right.next().map(|(_, thread)| thread);
-                }
+    iter::from_fn(move || loop {
+        match &mut it {
+            Either::Left(it_left) => match it_left.next() {
+                Some((_, thread)) => return Some(thread),
+                None => it = Either::Left(threads.iter()),
+            },
+            Either::Right(it_right) => {
+                return it_right.next().map(|(_, thread)| thread);
             }
         }
     })
 }
 
-num_enum::num_enum! (
-    pub Signo:u8 {
-        SIGHUP = 1,
-        SIGINT = 2,
-        SIGQUIT = 3,
-        SIGILL = 4,
-        SIGTRAP = 5,
-        SIGABRT = 6,
-        SIGBUS = 7,
-        SIGFPE = 8,
-        SIGKILL = 9,
-        SIGUSR1 = 10,
-        SIGSEGV = 11,
-        SIGUSR2 = 12,
-        SIGPIPE = 13,
-        SIGALRM = 14,
-        SIGTERM = 15,
-        SIGSTKFLT = 16,
-        SIGCHLD = 17,
-        SIGCONT = 18,
-        SIGSTOP = 19,
-        SIGTSTP = 20,
-        SIGTTIN = 21,
-        SIGTTOU = 22,
-        SIGURG = 23,
-        SIGXCPU = 24,
-        SIGXFSZ = 25,
-        SIGVTALRM = 26,
-        SIGPROF = 27,
-        SIGWINCH = 28,
-        SIGIO = 29,
-        SIGPWR = 30,
-        SIGSYS = 31,
-        SIGRTMIN = 32,
-        SIGRT33 = 33,
-        SIGRT34 = 34,
-        SIGRT35 = 35,
-        SIGRT36 = 36,
-        SIGRT37 = 37,
-        SIGRT38 = 38,
-        SIGRT39 = 39,
-        SIGRT40 = 40,
-        SIGRT41 = 41,
-        SIGRT42 = 42,
-        SIGRT43 = 43,
-        SIGRT44 = 44,
-        SIGRT45 = 45,
-        SIGRT46 = 46,
-        SIGRT47 = 47,
-        SIGRT48 = 48,
-        SIGRT49 = 49,
-        SIGRT50 = 50,
-        SIGRT51 = 51,
-        SIGRT52 = 52,
-        SIGRT53 = 53,
-        SIGRT54 = 54,
-        SIGRT55 = 55,
-        SIGRT56 = 56,
-        SIGRT57 = 57,
-        SIGRT58 = 58,
-        SIGRT59 = 59,
-        SIGRT60 = 60,
-        SIGRT61 = 61,
-        SIGRT62 = 62,
-        SIGRT63 = 63,
-        SIGRTMAX = 64,
-    }
-);
-
-impl Signo {
-    pub const MASK_SIG_KERNEL_ONLY: SignalSet =
-        SignalSet::sigmask(&Signo::SIGKILL).union(&SignalSet::sigmask(&Signo::SIGSTOP));
-
-    #[inline(always)]
-    pub const fn kernel_only(&self) -> bool {
-        Self::MASK_SIG_KERNEL_ONLY.contains(self)
-    }
-
-    pub const MASK_SIG_KERNEL_IGNORE: SignalSet = SignalSet::sigmask(&Signo::SIGCONT)
-        .union(&SignalSet::sigmask(&Signo::SIGCHLD))
-        .union(&SignalSet::sigmask(&Signo::SIGWINCH))
-        .union(&SignalSet::sigmask(&Signo::SIGURG));
-
-    #[inline(always)]
-    pub const fn kernel_ignore(&self) -> bool {
-        Self::MASK_SIG_KERNEL_IGNORE.contains(self)
-    }
-
-    pub const MASK_SIG_KERNEL_STOP: SignalSet = SignalSet::sigmask(&Signo::SIGSTOP)
-        .union(&SignalSet::sigmask(&Signo::SIGTSTP))
-        .union(&SignalSet::sigmask(&Signo::SIGTTIN))
-        .union(&SignalSet::sigmask(&Signo::SIGTTOU));
-
-    #[inline(always)]
-    pub const fn kernel_stop(&self) -> bool {
-        Self::MASK_SIG_KERNEL_STOP.contains(self)
-    }
-
-    pub const MASK_SIG_SYNCHRONOUS: SignalSet = SignalSet::sigmask(&Signo::SIGSEGV)
-        .union(&SignalSet::sigmask(&Signo::SIGBUS))
-        .union(&SignalSet::sigmask(&Signo::SIGILL))
-        .union(&SignalSet::sigmask(&Signo::SIGTRAP))
-        .union(&SignalSet::sigmask(&Signo::SIGFPE))
-        .union(&SignalSet::sigmask(&Signo::SIGSYS));
-
-    #[inline(always)]
-    pub const fn synchronous(&self) -> bool {
-        Self::MASK_SIG_SYNCHRONOUS.contains(self)
-    }
-
-    pub fn legacy(&self) -> bool {
-        self <= &Self::SIGRTMIN
-    }
-}
-
-fn sig_fatal(sig: &Signo, action: &SigAction) -> bool {
-    !Signo::MASK_SIG_KERNEL_IGNORE
-        .union(&Signo::MASK_SIG_KERNEL_STOP)
-        .contains(sig)
-        && action.handler().is_default()
-}
-
-/// Signal count
-pub const NSIG: u8 = Signo::SIGRTMAX as u8;
-
-bitflags! {
-    pub struct SignalFlags: usize {
-        const UNKILLABLE = 0x00000040;
-    }
-}
-
-#[repr(C)]
-#[derive(Clone)]
-pub struct Info {
-    pub sig: Signo,
-    pub errno: usize,
-    pub code: isize,
-    fields: InfoFields,
-}
-
-/// si_code values
-/// Digital reserves positive values for kernel-generated signals.
-
-/// sent by kill, sigsend, raise
-pub const SI_USER: isize = 0;
-/// sent by the kernel
-pub const SI_KERNEL: isize = 0x80;
-/// sent by sigqueue
-pub const SI_QUEUE: isize = -1;
-/// sent by timer expiration
-pub const SI_TIMER: isize = -2;
-/// sent by real time mesq state change
-pub const SI_MESGQ: isize = -3;
-/// sent by AIO completion
-pub const SI_ASYNCIO: isize = -4;
-/// sent by queued SIGIO
-pub const SI_SIGIO: isize = -5;
-/// sent by tkill system call
-pub const SI_TKILL: isize = -6;
-/// sent by execve() killing subsidiary threads
-pub const SI_DETHREAD: isize = -7;
-/// sent by glibc async name lookup completion
-pub const SI_ASYNCNL: isize = -60;
-
-#[repr(C)]
-#[derive(Clone, Copy)]
-pub union InfoFields {
-    /// Kill
-    kill: ManuallyDrop<InfoFieldsKill>,
-    /// POSIX.1b signals
-    rt: ManuallyDrop<InfoFieldsRt>,
-}
-
-#[repr(C)]
-#[derive(Debug, Clone, Copy)]
-pub struct InfoFieldsKill {
-    /// Sender's pid
-    pid: tid::RawThreadId,
-    /// Sender's uid
-    uid: u32,
-}
-
-/// POSIX.1b signals
-#[repr(C)]
-#[derive(Clone, Copy)]
-pub struct InfoFieldsRt {
-    /// Sender's pid
-    pid: tid::RawThreadId,
-    /// Sender's uid
-    uid: u32,
-    val: InfoValue,
-}
-
-#[repr(C)]
-#[derive(Clone, Copy)]
-pub union InfoValue {
-    int: isize,
-    ptr: VirtualAddress,
-}
-
-const SIGPENDING_QUEUE_CAP: usize = 11;
-
-pub struct Pending {
-    signal: SignalSet,
-    queue: LinkedList<Info>,
-}
-
-impl Pending {
-    pub fn new() -> Self {
-        Self {
-            signal: SignalSet::empty(),
-            queue: LinkedList::new(),
-        }
-    }
-
-    /// Remove signals in mask from the pending set and queue.
-    fn flush_by_mask(&mut self, mask: &SignalSet) {
-        let m = self.signal.intersection(mask);
-        if m.is_emptry() {
-            return;
-        }
-
-        self.signal = self.signal.difference(mask);
-
-        let mut queue_cursor = self.queue.cursor_front_mut();
-
-        while let Some(sig_info) = queue_cursor.current() {
-            if mask.contains(&sig_info.sig) {
-                queue_cursor.remove_current();
-            }
-            queue_cursor.move_next();
-        }
-    }
-
-    fn contains(&self, sig: &Signo) -> bool {
-        self.signal.contains(sig)
-    }
-
-    fn push(&mut self, info: Info) -> core::result::Result<(), Info> {
-        if self.queue.len() >= SIGPENDING_QUEUE_CAP {
-            return Err(info);
-        }
-
-        if self.signal.contains(&info.sig) {
-            self.signal = self.signal.union(&SignalSet::sigmask(&info.sig));
-        }
-        self.queue.push_back(info);
-
-        Ok(())
-    }
-}
-
-pub struct SignalContext {
-    pub arch_ctx: ArchSigCtx,
-    pub syscall: Option<Pin<Box<dyn Future<Output = ()> + Send + Sync + 'static>>>,
-}
-
-#[repr(C)]
-#[derive(Debug, Default)]
-pub struct AltStack {
-    /// Base address of stack
-    pub sp: usize,
-    /// Number of bytes in stack
-    pub size: usize,
-}
-
-impl AltStack {
-    pub fn on_stack(&self, sp: usize) -> bool {
-        sp <= self.sp && sp > self.sp - self.size
-    }
-}
+/// The context a signal handler returns to, with the syscall it interrupted.
+pub type SignalContext =
+    signal::SignalContext<ArchSigCtx, Pin<Box<dyn Future<Output = ()> + Send + Sync + 'static>>>;
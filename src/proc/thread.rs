@@ -4,33 +4,36 @@ use core::{
     mem::{self, MaybeUninit},
     ops::Deref,
     pin::Pin,
-    sync::atomic::{AtomicU8, Ordering},
     task::{ready, Context, Poll, Waker},
+    time::Duration,
 };
 
 use alloc::{boxed::Box, fmt, string::String, sync::Arc};
 use mm::{
     arch::page::PageParam as PageParamA,
-    memory::{MapType, Segment},
+    memory::{Fault, MapType, Segment},
     page::PageParam as _,
-    Result as MemoryResult, VirtualAddress,
+    Error as MemoryError, Result as MemoryResult, VirtualAddress,
 };
 
 use super::{
     executor::waker,
-    signal::{self, SignalContext},
+    futex,
+    signal::{self, Info, SignalContext, SignalSet, Signo},
     tid::{self, RawThreadId, ThreadId},
-    Error, Proc, ProcInitInfo, Result,
+    Error, Proc, ProcInitInfo, Result, ShareFlags,
 };
 use crate::{
     arch::{
-        interrupt::{Context as InterruptCtx, Trap},
+        interrupt::{self, Context as InterruptCtx, Trap},
         memory::{user_init_stack, user_stack_offset, user_stack_size},
     },
+    config, cpu,
     spinlock::RwLockIrq,
     syscall::syscall,
 };
 use pin_project::pin_project;
+use process::{group, Family};
 
 bitflags! {
     pub struct State: u8 {
@@ -44,8 +47,59 @@ bitflags! {
     }
 }
 
-pub const FLAGS_SIG_STOPPING: u8 = 0b1;
-pub const FLAGS_HAS_PENDDING_SIGS: u8 = 0b10;
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_THREAD: RwLockIrq<Option<Arc<Thread>>> = RwLockIrq::new(None);
+
+// The thread whose future each hart is polling, indexed by `cpu_id`.
+static CURRENT: [RwLockIrq<Option<Arc<Thread>>>; config::NCPU] = [NO_THREAD; config::NCPU];
+
+/// Returns the thread running on this hart, `None` outside of a thread future.
+pub fn current() -> Option<Arc<Thread>> {
+    CURRENT[cpu::cpu_id()].read().clone()
+}
+
+// Marks a thread as current on this hart while it is being polled.
+struct CurrentGuard;
+
+impl CurrentGuard {
+    fn enter(thread: &Arc<Thread>) -> Self {
+        *CURRENT[cpu::cpu_id()].write() = Some(thread.clone());
+        Self
+    }
+}
+
+impl Drop for CurrentGuard {
+    fn drop(&mut self) {
+        *CURRENT[cpu::cpu_id()].write() = None;
+    }
+}
+
+// Charges the time a thread future is polled for to the process of the thread,
+// the time spent in user mode as user time and the rest as system time.
+struct CpuTimer<'a> {
+    thread: &'a Arc<Thread>,
+    timer: process::CpuTimer<fn() -> Duration>,
+}
+
+impl<'a> CpuTimer<'a> {
+    fn start(thread: &'a Arc<Thread>) -> Self {
+        Self {
+            thread,
+            timer: process::CpuTimer::start(interrupt::timer_now),
+        }
+    }
+
+    fn run_user(&mut self, ctx: &mut InterruptCtx) -> *mut Trap {
+        self.timer.run_user(|| ctx.run_user())
+    }
+}
+
+impl Drop for CpuTimer<'_> {
+    fn drop(&mut self) {
+        let elapsed = self.timer.elapsed();
+        self.thread.proc().cpu_usage.lock().charge(&elapsed);
+    }
+}
 
 pub struct ThreadInner {
     // Interrupt context, which holds the values of all CPU general registers
@@ -55,6 +109,9 @@ pub struct ThreadInner {
     state: State,
     pub sig_alt_stack: signal::AltStack,
     pub sig_ctx: Option<SignalContext>,
+    /// User address of the thread id that is cleared when the thread exits,
+    /// set by `set_tid_address`. 0 if not set.
+    pub clear_child_tid: VirtualAddress,
 }
 
 impl ThreadInner {
@@ -67,6 +124,10 @@ impl ThreadInner {
         true
     }
 
+    pub fn state(&self) -> State {
+        self.state
+    }
+
     pub fn fork(&self) -> Self {
         let mut new_context = self.context.clone();
         new_context.set_syscall_ret(0);
@@ -75,6 +136,7 @@ impl ThreadInner {
             state: self.state,
             sig_alt_stack: signal::AltStack::default(),
             sig_ctx: None,
+            clear_child_tid: VirtualAddress(0),
         }
     }
 }
@@ -85,11 +147,14 @@ pub struct Thread {
     cmd: String,
     // The process to which the current thread belongs
     proc: MaybeUninit<Arc<Proc>>,
-    /// FLAGS_xxx
-    pub flags: AtomicU8,
+    pub flags: signal::ThreadFlags,
     /// `sig_pending` holds the signal sent to this thread.
     /// the caller must hold proc.signal lock
     pub sig_pending: MaybeUnlock<signal::Pending>,
+    /// The mask installed by `rt_sigsuspend`, it replaces the process mask for this thread
+    /// until a signal handler is set up.
+    /// the caller must hold proc.signal lock
+    pub sig_suspend_mask: MaybeUnlock<Option<SignalSet>>,
     pub inner: RwLockIrq<ThreadInner>,
 }
 
@@ -99,28 +164,42 @@ impl Thread {
     }
 
     pub fn new(tid: ThreadId, cmd: impl Into<String>) -> Self {
-        Self {
+        Self::with_inner(
             tid,
-            cmd: cmd.into(),
-            proc: MaybeUninit::uninit(),
-            flags: AtomicU8::new(0),
-            sig_pending: MaybeUnlock(signal::Pending::new()),
-
-            inner: RwLockIrq::new(ThreadInner {
+            cmd,
+            ThreadInner {
                 context: InterruptCtx::default(),
                 state: State::INTERRUPTIBLE,
                 sig_alt_stack: signal::AltStack::default(),
                 sig_ctx: None,
-            }),
+                clear_child_tid: VirtualAddress(0),
+            },
+        )
+    }
+
+    // The process of the thread must be set with `set_proc` before it is used.
+    fn with_inner(tid: ThreadId, cmd: impl Into<String>, inner: ThreadInner) -> Self {
+        Self {
+            tid,
+            cmd: cmd.into(),
+            proc: MaybeUninit::uninit(),
+            flags: signal::ThreadFlags::new(),
+            sig_pending: MaybeUnlock(signal::Pending::new()),
+            sig_suspend_mask: MaybeUnlock(None),
+            inner: RwLockIrq::new(inner),
         }
     }
 
     pub unsafe fn init(&self, proc: Arc<Proc>) -> MemoryResult<()> {
-        Self::alloc_user_stack(&mut proc.memory.write())?;
+        Self::alloc_user_stack(&mut proc.memory.write(), proc.stack_limit())?;
+        self.set_proc(proc);
+        Ok(())
+    }
 
+    // Must be called once, before the thread is shared.
+    unsafe fn set_proc(&self, proc: Arc<Proc>) {
         #[allow(clippy::cast_ref_to_mut)]
         (*(self as *const Self as *mut Self)).proc = MaybeUninit::new(proc);
-        Ok(())
     }
 
     pub fn reset_context(&self, proc_init_info: &ProcInitInfo) {
@@ -130,29 +209,52 @@ impl Thread {
         ctx.set_init_stack(sp);
     }
 
-    pub async fn fork(self: &Arc<Thread>, new_inner: ThreadInner) -> Result<Self> {
+    /// Creates a process running a copy of this thread with `new_inner`,
+    /// it shares `share` with the process of this thread.
+    /// Returns the main thread of the new process.
+    pub async fn fork(
+        self: &Arc<Thread>,
+        new_inner: ThreadInner,
+        share: ShareFlags,
+    ) -> Result<Arc<Self>> {
         let tid = tid::alloc().ok_or(Error::ThreadIdNotEnough)?;
-        let proc = MaybeUninit::new(Arc::new(
+        let asid = *tid.id() as usize;
+        let thread = Arc::new(Self::with_inner(tid, self.cmd.clone(), new_inner));
+        let child = Arc::new(
             self.proc()
-                .fork(*tid.id() as usize, self.clone())
+                .fork(asid, thread.clone(), share)
                 .await
                 .map_err(Error::MemoryErr)?,
-        ));
-        Ok(Self {
-            proc,
-            cmd: self.cmd.clone(),
-            tid,
-            flags: AtomicU8::new(0),
-            sig_pending: MaybeUnlock(signal::Pending::new()),
-            inner: RwLockIrq::new(new_inner),
-        })
+        );
+        // The child joins the process group of its parent.
+        group::join(&*child, Some(group::leader(self.proc())));
+        Family::adopt(self.proc(), child.clone());
+        unsafe { thread.set_proc(child) };
+        Ok(thread)
+    }
+
+    /// Creates another thread of the process of this thread, running with `new_inner`.
+    /// It shares everything with the other threads but the signals sent to it.
+    pub fn clone_thread(self: &Arc<Thread>, new_inner: ThreadInner) -> Result<Arc<Self>> {
+        let tid = tid::alloc().ok_or(Error::ThreadIdNotEnough)?;
+        let thread = Arc::new(Self::with_inner(tid, self.cmd.clone(), new_inner));
+        unsafe { thread.set_proc(self.proc().clone()) };
+        self.proc()
+            .threads
+            .write()
+            .insert(*thread.id(), thread.clone());
+        Ok(thread)
     }
 
     // Allocate user stack, return stack pointer on success
-    fn alloc_user_stack(memory: &mut crate::mm::Mem) -> MemoryResult<()> {
+    // The stack grows on page faults below it, down to `stack_limit`, the limit of `RLIMIT_STACK`.
+    fn alloc_user_stack(
+        memory: &mut crate::mm::Mem,
+        stack_limit: VirtualAddress,
+    ) -> MemoryResult<()> {
         let stack_start = VirtualAddress(user_stack_offset() - user_stack_size());
         let stack_end = VirtualAddress(user_stack_offset());
-        memory.add_user_segment(
+        memory.add_grow_down_segment(
             Segment {
                 addr_range: stack_start..stack_end,
                 flags: PageParamA::flag_set_user(
@@ -160,7 +262,7 @@ impl Thread {
                 ),
                 map_type: MapType::Framed,
             },
-            &[],
+            stack_limit,
         )?;
         Ok(())
     }
@@ -188,11 +290,34 @@ impl Thread {
     }
 
     pub fn exit(&self, status: isize) {
+        self.exit_with(signal::CLD_EXITED, status);
+    }
+
+    /// Exits like `exit`, the process was killed by `sig`.
+    pub fn exit_by_signal(&self, sig: Signo) {
+        self.exit_with(signal::CLD_KILLED, sig.to_primitive() as isize);
+    }
+
+    fn exit_with(&self, code: isize, status: isize) {
         if self.is_main_thread() {
             // When the main thread exits, it should exit the corresponding process directly.
             self.proc().exit(status);
+            self.proc().notify_parent(code, status as i32);
+        }
+        let mut inner = self.inner.write();
+        inner.state = State::EXIT;
+        let clear_child_tid = mem::replace(&mut inner.clear_child_tid, VirtualAddress(0));
+        drop(inner);
+        if clear_child_tid.0 != 0 {
+            // Let threads joining this one know that it is gone.
+            futex::clear_child_tid(&self.proc().memory, clear_child_tid);
         }
-        self.inner.write().state = State::EXIT;
+    }
+
+    /// Sets the address whose thread id is cleared on exit, returns the thread id.
+    pub fn set_tid_address(&self, tidptr: VirtualAddress) -> RawThreadId {
+        self.inner.write().clear_child_tid = tidptr;
+        *self.id()
     }
 }
 
@@ -221,6 +346,14 @@ impl<DT, T: Deref<Target = DT>> Deref for MaybeUnlock<T> {
 enum ThreadFutureState {
     RunUser,
     Syscall(Pin<Box<dyn Future<Output = ()> + Send + Sync + 'static>>),
+    // A page fault at the address waits for swap or for the page to be read from its file,
+    // the access is retried once it is done.
+    Fault(
+        VirtualAddress,
+        Pin<Box<dyn Future<Output = MemoryResult<()>> + Send + Sync + 'static>>,
+    ),
+    // The main thread has exited and closes the open files of its process.
+    Closing(Pin<Box<dyn Future<Output = ()> + Send + Sync + 'static>>),
     Exit,
 }
 
@@ -232,6 +365,8 @@ impl fmt::Display for ThreadFutureState {
             match self {
                 ThreadFutureState::RunUser => "RunUser",
                 ThreadFutureState::Syscall(_) => "Syscall(_)",
+                ThreadFutureState::Fault(..) => "Fault(..)",
+                ThreadFutureState::Closing(_) => "Closing(_)",
                 ThreadFutureState::Exit => "Exit",
             }
         )
@@ -259,21 +394,81 @@ impl ThreadFuture {
     }
 }
 
+// The state of a thread that has exited. The main thread first closes the files of
+// its process, unless they are shared with another process which closes them instead.
+fn exit_state(thread: &Arc<Thread>) -> ThreadFutureState {
+    let proc = thread.proc();
+    if !thread.is_main_thread() || Arc::strong_count(&proc.open_files) > 1 {
+        return ThreadFutureState::Exit;
+    }
+    let files = proc.open_files.remove_all();
+    ThreadFutureState::Closing(unsafe {
+        remove_future_lifetime(Box::new(async move {
+            for descriptor in files {
+                let _ = descriptor.close().await;
+            }
+        }))
+    })
+}
+
+// Report a page fault at `vaddr` that could not be handled with a signal.
+// Returns the next state, `None` if the signal is to be handled before returning to user mode.
+fn fault_signal(
+    thread: &Arc<Thread>,
+    vaddr: VirtualAddress,
+    err: MemoryError,
+) -> Option<ThreadFutureState> {
+    // A page that can not be allocated or read from its file or swap is reported as a bus error.
+    let sig = match err {
+        MemoryError::NoSpace | MemoryError::SourceIo | MemoryError::SwapIo => Signo::SIGBUS,
+        _ => Signo::SIGSEGV,
+    };
+    if signal::force_signal(thread, Info::fault(sig, vaddr)) {
+        // Both signals terminate the process by default.
+        let main_thread = &thread.proc().main_thread;
+        main_thread.exit_by_signal(sig);
+        main_thread.waker().wake();
+        Some(exit_state(thread))
+    } else {
+        None
+    }
+}
+
+// Finishes the exit of `thread`, which is then dropped from its process.
+// The main thread stays, it stands for the process until the parent reaps it.
+fn poll_exit(
+    thread: &Arc<Thread>,
+    state: &mut ThreadFutureState,
+    cx: &mut Context<'_>,
+) -> Poll<()> {
+    if let ThreadFutureState::Closing(close) = state {
+        ready!(close.as_mut().poll(cx));
+        *state = ThreadFutureState::Exit;
+    }
+    if !thread.is_main_thread() {
+        thread.proc().threads.write().remove(thread.id());
+    }
+    Poll::Ready(())
+}
+
 impl Future for ThreadFuture {
     type Output = ();
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.project();
-        if let ThreadFutureState::Exit = this.state {
-            return Poll::Ready(());
+        if let ThreadFutureState::Closing(_) | ThreadFutureState::Exit = this.state {
+            return poll_exit(this.thread, this.state, cx);
         }
+        let _current = CurrentGuard::enter(this.thread);
+        let mut cpu_timer = CpuTimer::start(this.thread);
 
         let mut thread_inner = this.thread.inner.write();
         if thread_inner.state == State::EXIT {
-            return Poll::Ready(());
+            drop(thread_inner);
+            *this.state = exit_state(this.thread);
+            return poll_exit(this.thread, this.state, cx);
         }
 
-        let flags = this.thread.flags.load(Ordering::Acquire);
-        if flags & FLAGS_SIG_STOPPING != 0 {
+        if this.thread.flags.stopping() {
             return Poll::Pending;
         }
 
@@ -282,7 +477,7 @@ impl Future for ThreadFuture {
                 mem::replace(this.state, ThreadFutureState::RunUser)
             {
                 if let Some(sig_ctx) = thread_inner.sig_ctx.as_mut() {
-                    sig_ctx.syscall = Some(syscall);
+                    sig_ctx.interrupt_syscall(syscall, crate::syscall::restartable);
                 }
             }
         }
@@ -296,7 +491,7 @@ impl Future for ThreadFuture {
                     let mut thread_ctx = this.thread.inner.write().context.clone();
                     // crate::println!("thread poll run_user1: {:?}", this.thread.id());
 
-                    let trap = unsafe { Box::from_raw(thread_ctx.run_user()) };
+                    let trap = unsafe { Box::from_raw(cpu_timer.run_user(&mut thread_ctx)) };
                     // crate::println!("thread poll run_user2: {:?}", this.thread.id());
 
                     {
@@ -306,9 +501,47 @@ impl Future for ThreadFuture {
                     }
                     match *trap {
                         Trap::PageFault(vaddr) => {
-                            // TODO handle result
-                            this.thread.proc().memory.write().handle_page_fault(vaddr);
-                            ThreadFutureState::RunUser
+                            let memory = &this.thread.proc().memory;
+                            let fault = memory.write().handle_page_fault(vaddr);
+                            match fault {
+                                Ok(Fault::Handled(_)) => ThreadFutureState::RunUser,
+                                Ok(Fault::SwapIn(slot)) => {
+                                    ThreadFutureState::Fault(vaddr, unsafe {
+                                        remove_future_lifetime(Box::new(crate::mm::swap_in(
+                                            memory.clone(),
+                                            vaddr,
+                                            slot,
+                                        )))
+                                    })
+                                }
+                                Ok(Fault::Load(load)) => ThreadFutureState::Fault(vaddr, unsafe {
+                                    remove_future_lifetime(Box::new(crate::mm::load_lazy_page(
+                                        memory.clone(),
+                                        vaddr,
+                                        load,
+                                    )))
+                                }),
+                                // Evict a page of any process, then retry.
+                                Err(MemoryError::NoSpace) if crate::mm::has_swap() => {
+                                    ThreadFutureState::Fault(vaddr, unsafe {
+                                        remove_future_lifetime(Box::new(async {
+                                            if crate::mm::reclaim().await {
+                                                Ok(())
+                                            } else {
+                                                Err(MemoryError::NoSpace)
+                                            }
+                                        }))
+                                    })
+                                }
+                                Err(err) => match fault_signal(this.thread, vaddr, err) {
+                                    Some(state) => state,
+                                    None => {
+                                        // Handle the signal before returning to user mode.
+                                        cx.waker().wake_by_ref();
+                                        return Poll::Pending;
+                                    }
+                                },
+                            }
                         }
                         Trap::Syscall => ThreadFutureState::Syscall(unsafe {
                             remove_future_lifetime(Box::new(syscall(this.thread)))
@@ -325,7 +558,8 @@ impl Future for ThreadFuture {
                     ready!(syscall_fut.as_mut().poll(cx));
                     let mut thread_inner = this.thread.inner.write();
                     if thread_inner.state == State::EXIT {
-                        ThreadFutureState::Exit
+                        drop(thread_inner);
+                        exit_state(this.thread)
                     } else {
                         if State::SLEEPPING.contains(thread_inner.state) {
                             thread_inner.state = State::RUNNING;
@@ -333,9 +567,25 @@ impl Future for ThreadFuture {
                         ThreadFutureState::RunUser
                     }
                 }
-                ThreadFutureState::Exit => return Poll::Ready(()),
+                ThreadFutureState::Fault(vaddr, fault) => {
+                    let vaddr = *vaddr;
+                    match ready!(fault.as_mut().poll(cx)) {
+                        // The access faults again if the page was evicted meanwhile.
+                        Ok(()) => ThreadFutureState::RunUser,
+                        Err(err) => match fault_signal(this.thread, vaddr, err) {
+                            Some(state) => state,
+                            None => {
+                                *this.state = ThreadFutureState::RunUser;
+                                cx.waker().wake_by_ref();
+                                return Poll::Pending;
+                            }
+                        },
+                    }
+                }
+                ThreadFutureState::Closing(_) | ThreadFutureState::Exit => break,
             };
         }
+        poll_exit(this.thread, this.state, cx)
     }
 }
 
@@ -1,53 +1,53 @@
-use core::slice;
+use core::{
+    convert::TryFrom,
+    future::Future,
+    ptr, slice,
+    task::{Context, Poll},
+    time::Duration,
+};
 
-use alloc::sync::Arc;
+use alloc::{sync::Arc, vec::Vec};
+use futures_util::{
+    future::{poll_fn, select, BoxFuture, Either},
+    task::noop_waker_ref,
+};
 
 use super::{Error, Result};
 use crate::{
-    fs::{self, rootfs::root_fs, vfs},
+    fs::{self, ioctl, mount_table, rootfs::root_fs, vfs},
     proc::{
         file::{self, SeekFrom},
+        pid, resource,
         thread::Thread,
     },
-    time::Timespec,
+    time::{self, Timespec},
+    timer,
 };
 
+pub use crate::fs::vfs::stat::{Stat, Statx, StatxMask};
+
 // If pathname is relative and fd is the special value AT_FDCWD, then pathname is interpreted relative to the current working directory of the calling process.
 const AT_FDCWD: isize = -100;
 
+bitflags! {
+    pub struct StatxFlags: u32 {
+        const AT_SYMLINK_NOFOLLOW = 0x100;
+        const AT_NO_AUTOMOUNT = 0x800;
+        const AT_EMPTY_PATH = 0x1000;
+        const AT_STATX_FORCE_SYNC = 0x2000;
+        const AT_STATX_DONT_SYNC = 0x4000;
+    }
+}
+
 #[repr(C)]
 #[derive(Debug)]
-pub struct Stat {
-    /// ID of device containing file
-    dev: u64,
-    /// File serial number
-    ino: u64,
-    /// Mode of file
-    mode: u32,
-    /// Number of hard links
-    nlink: u32,
-    /// User ID of the file
-    uid: u32,
-    /// Group ID of the file
-    gid: u32,
-    /// Device ID
-    rdev: u64,
-    /// padding
-    _pad: u64,
-    /// file size, in bytes
-    size: u64,
-    /// optimal blocksize for I/O
-    blk_size: u32,
-    /// padding2
-    _pad2: u32,
-    /// blocks allocated for file
-    blk_cnt: u32,
-    /// time of last access
-    atime: Timespec,
-    /// time of last data modification
-    mtime: Timespec,
-    /// time of last status change
-    ctime: Timespec,
+pub struct PollFd {
+    /// file descriptor
+    fd: i32,
+    /// requested events
+    events: i16,
+    /// returned events
+    revents: i16,
 }
 
 bitflags! {
@@ -57,6 +57,64 @@ bitflags! {
     }
 }
 
+bitflags! {
+    pub struct UTimensAtFlags: u32 {
+        const AT_SYMLINK_NOFOLLOW = 0x100;
+    }
+}
+
+bitflags! {
+    pub struct FChownAtFlags: u32 {
+        const AT_SYMLINK_NOFOLLOW = 0x100;
+    }
+}
+
+bitflags! {
+    pub struct FAccessAtFlags: u32 {
+        const AT_SYMLINK_NOFOLLOW = 0x100;
+        /// Permission checks always use the effective ids, so this is accepted and implied.
+        const AT_EACCESS = 0x200;
+        const AT_EMPTY_PATH = 0x1000;
+    }
+}
+
+bitflags! {
+    pub struct SetXattrFlags: usize {
+        /// Fail if the attribute already exists
+        const CREATE = 1;
+        /// Fail if the attribute does not exist
+        const REPLACE = 2;
+    }
+}
+
+/// Maximum length of an extended attribute name.
+const XATTR_NAME_MAX: usize = 255;
+
+/// Maximum number of buffers a single `readv`/`writev` takes.
+const IOV_MAX: usize = 1024;
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct IoVec {
+    /// Starting address
+    base: *mut u8,
+    /// Number of bytes to transfer
+    len: usize,
+}
+
+bitflags! {
+    pub struct MountFlags: usize {
+        /// Mount read-only
+        const RDONLY = 1;
+    }
+}
+
+bitflags! {
+    pub struct LinkAtFlags: u32 {
+        const AT_SYMLINK_FOLLOW = 0x400;
+    }
+}
+
 bitflags! {
     pub struct OpenFlags: usize {
         /// read only
@@ -73,8 +131,14 @@ bitflags! {
         const TRUNCATE = 1 << 9;
         /// append on each write
         const APPEND = 1 << 10;
+        /// fail with EAGAIN instead of blocking
+        const NONBLOCK = 1 << 11;
+        /// fail if the file is not a directory
+        const DIRECTORY = 1 << 16;
         /// close on exec
         const CLOEXEC = 1 << 19;
+        /// obtain a descriptor that only names the file, for `*at` lookups and `fstat`
+        const PATH = 1 << 21;
     }
 }
 
@@ -100,6 +164,26 @@ num_enum::num_enum! (
     }
 );
 
+num_enum::num_enum! (
+    pub FcntlCmd:u16 {
+        // Duplicate the file descriptor to the lowest free number >= arg.
+        DupFd = 0,
+        // Get the file descriptor flags.
+        GetFd = 1,
+        // Set the file descriptor flags to arg.
+        SetFd = 2,
+        // Get the file status flags.
+        GetFl = 3,
+        // Set the file status flags to arg.
+        SetFl = 4,
+        // Like DupFd, but set close-on-exec on the duplicate.
+        DupFdCloexec = 1030,
+    }
+);
+
+// The only file descriptor flag, close the descriptor on `execve`.
+const FD_CLOEXEC: usize = 1;
+
 pub async fn sys_openat(
     thread: &Arc<Thread>,
     dirfd: isize,
@@ -107,12 +191,10 @@ pub async fn sys_openat(
     flags: OpenFlags,
     mode: fs::vfs::Mode,
 ) -> Result {
-    let inode = if flags.contains(OpenFlags::CREATE) {
-        let (dirpath, basename) = match path.pop() {
-            (path, Some(basename)) => (path, basename),
-            (path, None) => (fs::Path::from_bytes(".".as_bytes()), path.inner()),
-        };
-        let dir_inode = lookup_inode_at(thread, dirfd, dirpath).await?;
+    // A path descriptor ignores `O_CREAT`, see `Descriptor::open`.
+    let inode = if flags.contains(OpenFlags::CREATE) && !flags.contains(OpenFlags::PATH) {
+        let (dirpath, basename) = split_basename(path);
+        let dir_inode = lookup_inode_at(thread, dirfd, dirpath, true).await?;
         match dir_inode.lookup(basename).await? {
             Some(file) => {
                 if flags.contains(OpenFlags::EXCLUSIVE) {
@@ -123,15 +205,22 @@ pub async fn sys_openat(
             }
             None => {
                 root_fs()
-                    .create(&dir_inode, basename, mode, 0, 0, Default::default())
+                    .create(
+                        &dir_inode,
+                        basename,
+                        thread.proc().umask().apply(mode),
+                        caller_uid(thread),
+                        caller_gid(thread),
+                        Default::default(),
+                    )
                     .await?
             }
         }
     } else {
-        lookup_inode_at(thread, dirfd, path).await?
+        lookup_inode_at(thread, dirfd, path, true).await?
     };
-
-    let descriptor = file::Descriptor::new(inode, flags.into(), flags.contains(OpenFlags::CLOEXEC));
+    let descriptor =
+        file::Descriptor::open(inode, flags.into(), flags.contains(OpenFlags::CLOEXEC)).await?;
     let fd = thread
         .proc()
         .open_files
@@ -140,25 +229,254 @@ pub async fn sys_openat(
     Ok(fd)
 }
 
-pub fn sys_close(thread: &Arc<Thread>, fd: isize) -> Result {
+/// Create the directory `path`, with the permissions of `mode` less the bits set in the umask.
+pub async fn sys_mkdirat(
+    thread: &Arc<Thread>,
+    dirfd: isize,
+    path: &fs::Path,
+    mode: vfs::Mode,
+) -> Result {
+    let (dirpath, basename) = split_basename(path);
+    let dir_inode = lookup_inode_at(thread, dirfd, dirpath, true).await?;
+    root_fs()
+        .create(
+            &dir_inode,
+            basename,
+            vfs::Mode::TY_DIR.with_permissions(thread.proc().umask().apply(mode)),
+            caller_uid(thread),
+            caller_gid(thread),
+            Default::default(),
+        )
+        .await?;
+    Ok(0)
+}
+
+/// Create the file `path` without opening it, with the permissions of `mode`
+/// less the bits set in the umask. Only regular files are supported,
+/// device nodes, FIFOs and sockets fail with EPERM.
+pub async fn sys_mknodat(
+    thread: &Arc<Thread>,
+    dirfd: isize,
+    path: &fs::Path,
+    mode: vfs::Mode,
+) -> Result {
+    let file_type = match mode.file_type() {
+        ty if ty.is_empty() => vfs::Mode::TY_REG,
+        vfs::Mode::TY_REG => vfs::Mode::TY_REG,
+        vfs::Mode::TY_CHR | vfs::Mode::TY_BLK | vfs::Mode::TY_FIFO | vfs::Mode::TY_SOCK => {
+            return Err(Error::EPERM)
+        }
+        _ => return Err(Error::EINVAL),
+    };
+    let (dirpath, basename) = split_basename(path);
+    let dir_inode = lookup_inode_at(thread, dirfd, dirpath, true).await?;
+    root_fs()
+        .create(
+            &dir_inode,
+            basename,
+            file_type.with_permissions(thread.proc().umask().apply(mode)),
+            caller_uid(thread),
+            caller_gid(thread),
+            Default::default(),
+        )
+        .await?;
+    Ok(0)
+}
+
+/// Create the symlink `linkpath` pointing at `target`.
+/// Symlinks are always accessible by everyone, the umask does not apply.
+/// A link whose target cannot be written whole is removed again.
+pub async fn sys_symlinkat(
+    thread: &Arc<Thread>,
+    target: &fs::Path,
+    newdirfd: isize,
+    linkpath: &fs::Path,
+) -> Result {
+    if target.is_empty() || linkpath.is_empty() {
+        return Err(Error::ENOENT);
+    }
+    let (dirpath, basename) = split_basename(linkpath);
+    let dir_inode = lookup_inode_at(thread, newdirfd, dirpath, true).await?;
+    root_fs()
+        .symlink(
+            &dir_inode,
+            basename,
+            target.inner().as_bytes(),
+            caller_uid(thread),
+            caller_gid(thread),
+            Default::default(),
+        )
+        .await?;
+    Ok(0)
+}
+
+pub async fn sys_readlinkat(
+    thread: &Arc<Thread>,
+    dirfd: isize,
+    path: &fs::Path,
+    buf: *mut u8,
+    bufsiz: usize,
+) -> Result {
+    if bufsiz == 0 {
+        return Err(Error::EINVAL);
+    }
+    let link = lookup_inode_at(thread, dirfd, path, false).await?;
+    let buf = unsafe { slice::from_raw_parts_mut(buf, bufsiz) };
+    Ok(root_fs().read_link_into(&link, buf).await?)
+}
+
+/// Create the hard link `newpath` to `oldpath`. Directories can not be linked, `EPERM`.
+pub async fn sys_linkat(
+    thread: &Arc<Thread>,
+    olddirfd: isize,
+    oldpath: &fs::Path,
+    newdirfd: isize,
+    newpath: &fs::Path,
+    flags: LinkAtFlags,
+) -> Result {
+    if oldpath.is_empty() || newpath.is_empty() {
+        return Err(Error::ENOENT);
+    }
+    let follow_symlink = flags.contains(LinkAtFlags::AT_SYMLINK_FOLLOW);
+    let inode = lookup_inode_at(thread, olddirfd, oldpath, follow_symlink).await?;
+    // TODO: EXDEV when `newpath` is on another mounted filesystem
+    let (dirpath, basename) = split_basename(newpath);
+    let dir_inode = lookup_inode_at(thread, newdirfd, dirpath, true).await?;
+    root_fs().link(&dir_inode, basename, &inode).await?;
+    Ok(0)
+}
+
+pub async fn sys_close(thread: &Arc<Thread>, fd: isize) -> Result {
     let proc = thread.proc();
-    proc.open_files
+    let descriptor = proc
+        .open_files
         .remove_file(fd as usize)
         .ok_or(Error::EBADF)?;
+    descriptor.close().await?;
     Ok(0)
 }
 
+/// Write the open files of every process and all mounted filesystems back to their devices.
+/// As on Linux, failures are not reported.
+pub async fn sys_sync() -> Result {
+    for proc in pid::procs() {
+        for file in proc.open_files.files() {
+            let _ = file.flush().await;
+        }
+    }
+    let _ = root_fs().sync().await;
+    for entry in mount_table::mounts() {
+        let _ = vfs::Filesystem::sync(&entry.fs).await;
+    }
+    Ok(0)
+}
+
+/// Write back the filesystem holding the open file `fd`.
+pub async fn sys_syncfs(thread: &Arc<Thread>, fd: isize) -> Result {
+    let file = thread
+        .proc()
+        .open_files
+        .get_file(fd as usize)
+        .ok_or(Error::EBADF)?;
+    file.inode().sync_fs().await?;
+    Ok(0)
+}
+
+/// Duplicate `oldfd` to the lowest free descriptor.
+/// Both refer to the same open file and share its offset.
+pub fn sys_dup(thread: &Arc<Thread>, oldfd: isize) -> Result {
+    let open_files = &thread.proc().open_files;
+    let descriptor = open_files.get_file(oldfd as usize).ok_or(Error::EBADF)?;
+    open_files
+        .add_file(descriptor.dup(false))
+        .ok_or(Error::EMFILE)
+}
+
+/// Duplicate `oldfd` to `newfd`, closing the file `newfd` referred to first.
+/// `O_CLOEXEC` is the only flag accepted.
+pub async fn sys_dup3(
+    thread: &Arc<Thread>,
+    oldfd: isize,
+    newfd: isize,
+    flags: OpenFlags,
+) -> Result {
+    if oldfd == newfd || !(flags - OpenFlags::CLOEXEC).is_empty() {
+        return Err(Error::EINVAL);
+    }
+    let open_files = &thread.proc().open_files;
+    let descriptor = open_files.get_file(oldfd as usize).ok_or(Error::EBADF)?;
+    let replaced = open_files
+        .replace_file(
+            newfd as usize,
+            descriptor.dup(flags.contains(OpenFlags::CLOEXEC)),
+        )
+        .ok_or(Error::EBADF)?;
+    if let Some(replaced) = replaced {
+        replaced.close().await?;
+    }
+    Ok(newfd as usize)
+}
+
+/// Manipulate the file descriptor `fd`.
+pub fn sys_fcntl(thread: &Arc<Thread>, fd: isize, cmd: FcntlCmd, arg: usize) -> Result {
+    let open_files = &thread.proc().open_files;
+    let descriptor = open_files.get_file(fd as usize).ok_or(Error::EBADF)?;
+    match cmd {
+        FcntlCmd::DupFd | FcntlCmd::DupFdCloexec => {
+            let nofile = thread.proc().rlimit(resource::NOFILE).unwrap();
+            if arg as u64 >= nofile.cur {
+                return Err(Error::EINVAL);
+            }
+            let cloexec = cmd == FcntlCmd::DupFdCloexec;
+            open_files
+                .add_file_min(descriptor.dup(cloexec), arg)
+                .ok_or(Error::EMFILE)
+        }
+        FcntlCmd::GetFd => Ok(if descriptor.cloexec() { FD_CLOEXEC } else { 0 }),
+        FcntlCmd::SetFd => {
+            open_files
+                .set_cloexec(fd as usize, arg & FD_CLOEXEC != 0)
+                .ok_or(Error::EBADF)?;
+            Ok(0)
+        }
+        FcntlCmd::GetFl => Ok(OpenFlags::from(descriptor.options()).bits()),
+        FcntlCmd::SetFl => {
+            descriptor.set_status_flags(OpenFlags::from_bits_truncate(arg).into());
+            Ok(0)
+        }
+    }
+}
+
+/// Generic commands are handled here, any other is passed to the inode.
+pub async fn sys_ioctl(thread: &Arc<Thread>, fd: isize, cmd: u32, arg: usize) -> Result {
+    let open_files = &thread.proc().open_files;
+    let descriptor = io_file(thread, fd)?;
+    match cmd {
+        ioctl::CMD_FIONBIO | ioctl::CMD_FIONREAD if arg == 0 => return Err(Error::EFAULT),
+        ioctl::CMD_FIOCLEX | ioctl::CMD_FIONCLEX => {
+            open_files
+                .set_cloexec(fd as usize, cmd == ioctl::CMD_FIOCLEX)
+                .ok_or(Error::EBADF)?;
+            return Ok(0);
+        }
+        _ => {}
+    }
+
+    match descriptor.ioctl(cmd, arg).await {
+        Ok(()) => Ok(0),
+        // Not a device, or a device without the command.
+        Err(vfs::Error::Unsupport) => Err(Error::ENOTTY),
+        Err(e) => Err(e.into()),
+    }
+}
+
 pub async fn sys_lseek(
     thread: &Arc<Thread>,
     fd: isize,
     offset: i64,
     whence: LSeekWhence,
 ) -> Result {
-    let mut descriptor = thread
-        .proc()
-        .open_files
-        .get_file(fd as usize)
-        .ok_or(Error::EBADF)?;
+    let descriptor = io_file(thread, fd)?;
     let seek_from = match whence {
         LSeekWhence::Set => SeekFrom::Start(offset as u64),
         LSeekWhence::Cur => SeekFrom::Current(offset),
@@ -168,27 +486,76 @@ pub async fn sys_lseek(
 }
 
 pub async fn sys_read(thread: &Arc<Thread>, fd: isize, buf: *mut u8, count: usize) -> Result {
-    let mut descriptor = thread
-        .proc()
-        .open_files
-        .get_file(fd as usize)
-        .ok_or(Error::EBADF)?;
+    let descriptor = io_file(thread, fd)?;
     let buf = unsafe { slice::from_raw_parts_mut(buf, count) };
     let len = descriptor.read(buf).await?;
     Ok(len)
 }
 
 pub async fn sys_write(thread: &Arc<Thread>, fd: isize, buf: *const u8, count: usize) -> Result {
-    let mut descriptor = thread
-        .proc()
-        .open_files
-        .get_file(fd as usize)
-        .ok_or(Error::EBADF)?;
+    let descriptor = io_file(thread, fd)?;
     let buf = unsafe { slice::from_raw_parts(buf, count) };
     let len = descriptor.write(buf).await?;
     Ok(len)
 }
 
+pub async fn sys_readv(
+    thread: &Arc<Thread>,
+    fd: isize,
+    iov: *const IoVec,
+    iovcnt: usize,
+) -> Result {
+    let descriptor = io_file(thread, fd)?;
+    let mut bufs: Vec<&mut [u8]> = user_iovecs(iov, iovcnt)?
+        .iter()
+        .map(|iov| unsafe { slice::from_raw_parts_mut(iov.base, iov.len) })
+        .collect();
+    Ok(descriptor.read_vectored(&mut bufs).await?)
+}
+
+pub async fn sys_writev(
+    thread: &Arc<Thread>,
+    fd: isize,
+    iov: *const IoVec,
+    iovcnt: usize,
+) -> Result {
+    let descriptor = io_file(thread, fd)?;
+    let srcs: Vec<&[u8]> = user_iovecs(iov, iovcnt)?
+        .iter()
+        .map(|iov| unsafe { slice::from_raw_parts(iov.base, iov.len) })
+        .collect();
+    Ok(descriptor.write_vectored(&srcs).await?)
+}
+
+/// Returns the non-empty buffers of the user `iovec` array,
+/// EINVAL if there are too many or their total length overflows, EFAULT on a null buffer.
+fn user_iovecs<'a>(
+    iov: *const IoVec,
+    iovcnt: usize,
+) -> core::result::Result<Vec<&'a IoVec>, Error> {
+    if iovcnt > IOV_MAX {
+        return Err(Error::EINVAL);
+    }
+    if iovcnt == 0 {
+        return Ok(Vec::new());
+    }
+    if iov.is_null() {
+        return Err(Error::EFAULT);
+    }
+    let iovecs = unsafe { slice::from_raw_parts(iov, iovcnt) };
+    let mut total_len: usize = 0;
+    for iov in iovecs {
+        total_len = total_len
+            .checked_add(iov.len)
+            .filter(|total_len| *total_len <= isize::MAX as usize)
+            .ok_or(Error::EINVAL)?;
+        if iov.len > 0 && iov.base.is_null() {
+            return Err(Error::EFAULT);
+        }
+    }
+    Ok(iovecs.iter().filter(|iov| iov.len > 0).collect())
+}
+
 pub async fn sys_fstat(thread: &Arc<Thread>, fd: isize, stat: &mut Stat) -> Result {
     sys_fstatat(
         thread,
@@ -205,33 +572,417 @@ pub async fn sys_fstatat(
     dirfd: isize,
     path: &fs::Path,
     stat: &mut Stat,
-    _flag: FStatAtFlags,
+    flag: FStatAtFlags,
 ) -> Result {
-    // TODO: flag AT_SYMLINK_NOFOLLOW
-    let inode = lookup_inode_at(thread, dirfd, path).await?;
+    let follow_symlink = !flag.contains(FStatAtFlags::AT_SYMLINK_NOFOLLOW);
+    let inode = lookup_inode_at(thread, dirfd, path, follow_symlink).await?;
+    *stat = Stat::new(inode.id(), &inode.metadata().await?);
+    Ok(0)
+}
+
+/// Like `fstatat`, but fills a `struct statx` and reports in its mask which fields are valid,
+/// see `Statx::new`.
+pub async fn sys_statx(
+    thread: &Arc<Thread>,
+    dirfd: isize,
+    path: &fs::Path,
+    flags: u32,
+    mask: u32,
+    statx: &mut Statx,
+) -> Result {
+    let flags = StatxFlags::from_bits(flags).ok_or(Error::EINVAL)?;
+    if flags.contains(StatxFlags::AT_STATX_FORCE_SYNC | StatxFlags::AT_STATX_DONT_SYNC) {
+        return Err(Error::EINVAL);
+    }
+    if StatxMask::from_bits_truncate(mask).contains(StatxMask::RESERVED) {
+        return Err(Error::EINVAL);
+    }
+    if path.is_empty() && !flags.contains(StatxFlags::AT_EMPTY_PATH) {
+        return Err(Error::ENOENT);
+    }
+
+    let follow_symlink = !flags.contains(StatxFlags::AT_SYMLINK_NOFOLLOW);
+    let inode = lookup_inode_at(thread, dirfd, path, follow_symlink).await?;
+    *statx = Statx::new(inode.id(), &inode.metadata().await?);
+    Ok(0)
+}
+
+/// Change the permissions of a file, only its owner or root may do so.
+pub async fn sys_fchmodat(
+    thread: &Arc<Thread>,
+    dirfd: isize,
+    path: &fs::Path,
+    mode: vfs::Mode,
+) -> Result {
+    let inode = lookup_inode_at(thread, dirfd, path, true).await?;
     let metadata = inode.metadata().await?;
-    stat.dev = 0;
-    stat.ino = inode.id() as u64;
-    stat.mode = metadata.mode.bits() as u32;
-    stat.nlink = metadata.links_count as u32;
-    stat.uid = metadata.uid;
-    stat.gid = metadata.gid;
-    stat.rdev = 0;
-    stat.size = metadata.size;
-    stat.blk_size = metadata.blk_size;
-    stat.blk_cnt = metadata.blk_count as u32;
-    stat.atime = metadata.atime;
-    stat.mtime = metadata.mtime;
-    stat.ctime = metadata.ctime;
+    let uid = caller_uid(thread);
+    if uid != 0 && uid != metadata.uid {
+        return Err(Error::EPERM);
+    }
+    inode.chmod(metadata.mode.with_permissions(mode)).await?;
+    inode.sync().await?;
+    Ok(0)
+}
+
+/// Set the umask of the calling process, returns the previous umask.
+pub fn sys_umask(thread: &Arc<Thread>, mask: vfs::Mode) -> Result {
+    Ok(thread.proc().replace_umask(mask).bits() as usize)
+}
+
+/// Change the owner and group of a file, `u32::MAX` leaves the id unchanged.
+/// Only root may give a file to another owner, the owner may change its group to its own.
+pub async fn sys_fchownat(
+    thread: &Arc<Thread>,
+    dirfd: isize,
+    path: &fs::Path,
+    uid: u32,
+    gid: u32,
+    flags: FChownAtFlags,
+) -> Result {
+    let follow_symlink = !flags.contains(FChownAtFlags::AT_SYMLINK_NOFOLLOW);
+    let inode = lookup_inode_at(thread, dirfd, path, follow_symlink).await?;
+    let (caller_uid, caller_gid) = (caller_uid(thread), caller_gid(thread));
+    vfs::util::chown(&inode, uid, gid, caller_uid, caller_gid).await?;
+    Ok(0)
+}
+
+/// Check whether the caller may access a file, `mode` is a mask of
+/// `R_OK` (4), `W_OK` (2) and `X_OK` (1). `F_OK` (0) only checks existence.
+pub async fn sys_faccessat(
+    thread: &Arc<Thread>,
+    dirfd: isize,
+    path: &fs::Path,
+    mode: u32,
+    flags: FAccessAtFlags,
+) -> Result {
+    let perm = u8::try_from(mode)
+        .ok()
+        .and_then(vfs::Permission::from_bits)
+        .ok_or(Error::EINVAL)?;
+    if path.is_empty() && !flags.contains(FAccessAtFlags::AT_EMPTY_PATH) {
+        return Err(Error::ENOENT);
+    }
+    let follow_symlink = !flags.contains(FAccessAtFlags::AT_SYMLINK_NOFOLLOW);
+    let inode = lookup_inode_at(thread, dirfd, path, follow_symlink).await?;
+    if perm.is_empty() {
+        return Ok(0);
+    }
+
+    // `access` checks the real ids, unless asked for the effective ones.
+    let credentials = thread.proc().credentials();
+    let (uid, gid) = if flags.contains(FAccessAtFlags::AT_EACCESS) {
+        (credentials.euid, credentials.egid)
+    } else {
+        (credentials.uid, credentials.gid)
+    };
+    inode.metadata().await?.check_access(uid, gid, perm)?;
+    Ok(0)
+}
+
+// Permission checks use the effective ids of the calling process.
+fn caller_uid(thread: &Arc<Thread>) -> u32 {
+    thread.proc().credentials().fs_ids().0
+}
+
+fn caller_gid(thread: &Arc<Thread>) -> u32 {
+    thread.proc().credentials().fs_ids().1
+}
+
+/// Change the access and modification times of a file.
+/// A null `times` sets both to the current time.
+pub async fn sys_utimensat(
+    thread: &Arc<Thread>,
+    dirfd: isize,
+    path: &fs::Path,
+    times: *const [Timespec; 2],
+    flags: UTimensAtFlags,
+) -> Result {
+    let times = if times.is_null() {
+        None
+    } else {
+        Some(unsafe { ptr::read(times) })
+    };
+    let follow_symlink = !flags.contains(UTimensAtFlags::AT_SYMLINK_NOFOLLOW);
+    let inode = lookup_inode_at(thread, dirfd, path, follow_symlink).await?;
+    let (uid, gid) = (caller_uid(thread), caller_gid(thread));
+    vfs::util::utimens(&inode, times, time::now(), uid, gid).await?;
+    Ok(0)
+}
+
+/// Set the extended attribute `name` of a file, the caller needs write permission.
+pub async fn sys_setxattr(
+    thread: &Arc<Thread>,
+    path: &fs::Path,
+    name: &fs::Path,
+    value: *const u8,
+    size: usize,
+    flags: SetXattrFlags,
+) -> Result {
+    let name = xattr_name(name)?;
+    let inode = lookup_inode_at(thread, AT_FDCWD, path, true).await?;
+    check_xattr_access(thread, &inode, vfs::Permission::WRITE).await?;
+
+    if !flags.is_empty() {
+        let exists = inode.getxattr(name).await?.is_some();
+        if flags.contains(SetXattrFlags::CREATE) && exists {
+            return Err(Error::EEXIST);
+        }
+        if flags.contains(SetXattrFlags::REPLACE) && !exists {
+            return Err(Error::ENODATA);
+        }
+    }
+    let value = if size == 0 {
+        &[]
+    } else {
+        unsafe { slice::from_raw_parts(value, size) }
+    };
+    inode.setxattr(name, value).await?;
+    inode.sync().await?;
+    Ok(0)
+}
+
+/// Copy the value of the extended attribute `name` into `value`,
+/// a `size` of 0 only returns the length of the value.
+pub async fn sys_getxattr(
+    thread: &Arc<Thread>,
+    path: &fs::Path,
+    name: &fs::Path,
+    value: *mut u8,
+    size: usize,
+) -> Result {
+    let name = xattr_name(name)?;
+    let inode = lookup_inode_at(thread, AT_FDCWD, path, true).await?;
+    check_xattr_access(thread, &inode, vfs::Permission::READ).await?;
+    let xattr = inode.getxattr(name).await?.ok_or(Error::ENODATA)?;
+    copy_xattr_out(&xattr, value, size)
+}
+
+/// Copy the names of all extended attributes of a file into `list`, each followed by a NUL.
+/// A `size` of 0 only returns the length of the list.
+pub async fn sys_listxattr(
+    thread: &Arc<Thread>,
+    path: &fs::Path,
+    list: *mut u8,
+    size: usize,
+) -> Result {
+    let inode = lookup_inode_at(thread, AT_FDCWD, path, true).await?;
+    let names = inode
+        .listxattr()
+        .await?
+        .into_iter()
+        .fold(Vec::new(), |mut names, name| {
+            names.extend_from_slice(&name);
+            names.push(0);
+            names
+        });
+    copy_xattr_out(&names, list, size)
+}
+
+fn xattr_name(name: &fs::Path) -> core::result::Result<&[u8], Error> {
+    let name = name.inner().as_bytes();
+    if name.is_empty() || name.len() > XATTR_NAME_MAX {
+        return Err(Error::ERANGE);
+    }
+    Ok(name)
+}
+
+async fn check_xattr_access(
+    thread: &Arc<Thread>,
+    inode: &fs::Inode,
+    perm: vfs::Permission,
+) -> core::result::Result<(), Error> {
+    let uid = caller_uid(thread);
+    if uid == 0
+        || inode
+            .metadata()
+            .await?
+            .permission(uid, caller_gid(thread), perm)
+    {
+        Ok(())
+    } else {
+        Err(Error::EACCES)
+    }
+}
+
+fn copy_xattr_out(src: &[u8], buf: *mut u8, size: usize) -> Result {
+    if size == 0 {
+        return Ok(src.len());
+    }
+    if size < src.len() {
+        return Err(Error::ERANGE);
+    }
+    unsafe { slice::from_raw_parts_mut(buf, src.len()) }.copy_from_slice(src);
+    Ok(src.len())
+}
+
+/// Mount a filesystem of type `fstype` on the directory `target`.
+/// `source` names the backing block device, see `fs::create_fs`.
+pub async fn sys_mount(
+    thread: &Arc<Thread>,
+    source: &fs::Path,
+    target: &fs::Path,
+    fstype: &fs::Path,
+    flags: MountFlags,
+) -> Result {
+    if caller_uid(thread) != 0 {
+        return Err(Error::EPERM);
+    }
+    let fstype = core::str::from_utf8(fstype.inner().as_bytes()).map_err(|_| Error::ENODEV)?;
+    let source = core::str::from_utf8(source.inner().as_bytes()).map_err(|_| Error::EINVAL)?;
+    let target_dentry = lookup_dentry(thread, target).await?;
+    let options = mount_table::MountOptions::from(flags);
+    let fs = fs::create_fs(
+        fstype,
+        source,
+        options.contains(mount_table::MountOptions::READ_ONLY),
+    )
+    .await?;
+    mount_table::mount(target, &target_dentry, fs, options).await?;
     Ok(0)
 }
 
+/// Detach the filesystem mounted on `target`, fails with EBUSY while it is in use.
+pub async fn sys_umount(thread: &Arc<Thread>, target: &fs::Path) -> Result {
+    if caller_uid(thread) != 0 {
+        return Err(Error::EPERM);
+    }
+    let target_dentry = lookup_dentry(thread, target).await?;
+    mount_table::umount(&target_dentry)?;
+    Ok(0)
+}
+
+async fn lookup_dentry(
+    thread: &Arc<Thread>,
+    path: &fs::Path,
+) -> core::result::Result<fs::DirEntry, Error> {
+    let cwd = thread
+        .proc()
+        .cwd
+        .read()
+        .await
+        .inode()
+        .await?
+        .ok_or(Error::ENOENT)?;
+    root_fs().find(&cwd, path).await?.ok_or(Error::ENOENT)
+}
+
+// Split `path` into its parent directory and last component.
+fn split_basename(path: &fs::Path) -> (&fs::Path, &fs::FsStr) {
+    match path.pop() {
+        (path, Some(basename)) => (path, basename),
+        (path, None) => (fs::Path::from_bytes(".".as_bytes()), path.inner()),
+    }
+}
+
+/// Copy up to `count` bytes from `in_fd` to `out_fd` within the kernel.
+/// If `offset` is not null, reading starts at `*offset` which is then advanced
+/// instead of the offset of `in_fd`.
+pub async fn sys_sendfile(
+    thread: &Arc<Thread>,
+    out_fd: isize,
+    in_fd: isize,
+    offset: *mut i64,
+    count: usize,
+) -> Result {
+    let in_file = io_file(thread, in_fd)?;
+    let out_file = io_file(thread, out_fd)?;
+    if !in_file.readable() || !out_file.writable() {
+        return Err(Error::EBADF);
+    }
+    // Only regular files can be read at arbitrary offsets.
+    if !in_file.inode().metadata().await?.mode.is_file() {
+        return Err(Error::EINVAL);
+    }
+
+    let copied = if offset.is_null() {
+        file::sendfile(&out_file, &in_file, None, count as u64).await?
+    } else {
+        let mut src_offset = match unsafe { *offset } {
+            offset if offset < 0 => return Err(Error::EINVAL),
+            offset => offset as u64,
+        };
+        let copied =
+            file::sendfile(&out_file, &in_file, Some(&mut src_offset), count as u64).await?;
+        unsafe { *offset = src_offset as i64 };
+        copied
+    };
+    Ok(copied as usize)
+}
+
+/// Wait until one of the files in `fds` becomes ready, or `timeout` expires.
+/// A `None` timeout waits forever. Returns the number of entries with non-zero `revents`.
+pub async fn sys_ppoll(
+    thread: &Arc<Thread>,
+    fds: *mut PollFd,
+    nfds: usize,
+    timeout: Option<Duration>,
+) -> Result {
+    let fds = unsafe { slice::from_raw_parts_mut(fds, nfds) };
+    let proc = thread.proc();
+
+    let mut nval = 0;
+    let inodes: Vec<Option<fs::Inode>> = fds
+        .iter_mut()
+        .map(|pollfd| {
+            pollfd.revents = 0;
+            // Negative file descriptors are ignored
+            if pollfd.fd < 0 {
+                return None;
+            }
+            let descriptor = proc.open_files.get_file(pollfd.fd as usize);
+            if descriptor.is_none() {
+                pollfd.revents = vfs::PollEvents::NVAL.bits() as i16;
+                nval += 1;
+            }
+            descriptor.map(|descriptor| descriptor.inode().clone())
+        })
+        .collect();
+
+    let mut polls: Vec<(usize, BoxFuture<'_, vfs::PollEvents>)> = inodes
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, inode)| {
+            // ERR and HUP are always reported
+            let events = vfs::PollEvents::from_bits_truncate(fds[idx].events as u16)
+                | vfs::PollEvents::ERR
+                | vfs::PollEvents::HUP;
+            inode.as_ref().map(|inode| (idx, inode.poll(events)))
+        })
+        .collect();
+
+    let mut poll_once = |cx: &mut Context<'_>| {
+        vfs::util::poll_set(&mut polls, cx, |idx, revents| {
+            fds[idx].revents = revents.bits() as i16
+        })
+    };
+
+    if nval > 0 || timeout == Some(Duration::ZERO) {
+        let nready = match poll_once(&mut Context::from_waker(noop_waker_ref())) {
+            Poll::Ready(nready) => nready,
+            Poll::Pending => 0,
+        };
+        return Ok(nval + nready);
+    }
+
+    let wait = poll_fn(poll_once);
+    Ok(match timeout {
+        None => wait.await,
+        Some(timeout) => match select(wait, timer::sleep(timeout)).await {
+            Either::Left((nready, _)) => nready,
+            Either::Right(_) => 0,
+        },
+    })
+}
+
 //  If the `dirfd` is the special value `AT_FDCWD`, then the directory is
 //   current working directory of the process.
+//  A symlink in the last component of `path` is only resolved if `follow_symlink` is set.
 pub async fn lookup_inode_at(
     thread: &Arc<Thread>,
     dirfd: isize,
     path: &fs::Path,
+    follow_symlink: bool,
 ) -> core::result::Result<fs::Inode, Error> {
     let proc = thread.proc();
     let mut inode = if dirfd == AT_FDCWD {
@@ -241,13 +992,17 @@ pub async fn lookup_inode_at(
         proc.open_files
             .get_file(dirfd as usize)
             .ok_or(Error::EBADF)?
-            .inode
+            .inode()
+            .clone()
     };
 
     if !path.is_empty() {
-        inode = root_fs()
-            .find(&inode, path)
-            .await?
+        let dentry = if follow_symlink {
+            root_fs().find(&inode, path).await?
+        } else {
+            root_fs().find_nofollow(&inode, path).await?
+        };
+        inode = dentry
             .ok_or(Error::ENOENT)?
             .inode()
             .await?
@@ -256,6 +1011,40 @@ pub async fn lookup_inode_at(
     Ok(inode)
 }
 
+/// Returns the descriptor `fd` to do I/O through, a descriptor opened with `O_PATH` is EBADF.
+fn io_file(thread: &Arc<Thread>, fd: isize) -> core::result::Result<file::Descriptor, Error> {
+    match thread.proc().open_files.get_file(fd as usize) {
+        Some(descriptor) if !descriptor.is_path() => Ok(descriptor),
+        _ => Err(Error::EBADF),
+    }
+}
+
+impl From<file::OpenOptions> for OpenFlags {
+    fn from(opts: file::OpenOptions) -> Self {
+        let mut flags = match (
+            opts.contains(file::OpenOptions::READ),
+            opts.contains(file::OpenOptions::WRITE),
+        ) {
+            (true, true) => Self::RDWR,
+            (false, true) => Self::WRONLY,
+            _ => Self::RDONLY,
+        };
+        if opts.contains(file::OpenOptions::APPEND) {
+            flags |= Self::APPEND;
+        }
+        if opts.contains(file::OpenOptions::NONBLOCK) {
+            flags |= Self::NONBLOCK;
+        }
+        if opts.contains(file::OpenOptions::PATH) {
+            flags |= Self::PATH;
+        }
+        if opts.contains(file::OpenOptions::DIRECTORY) {
+            flags |= Self::DIRECTORY;
+        }
+        flags
+    }
+}
+
 impl From<OpenFlags> for file::OpenOptions {
     fn from(flags: OpenFlags) -> Self {
         let mut open_options = Self::empty();
@@ -274,10 +1063,29 @@ impl From<OpenFlags> for file::OpenOptions {
         if flags.contains(OpenFlags::CREATE) {
             open_options |= Self::CREATE;
         }
+        if flags.contains(OpenFlags::NONBLOCK) {
+            open_options |= Self::NONBLOCK;
+        }
+        if flags.contains(OpenFlags::PATH) {
+            open_options |= Self::PATH;
+        }
+        if flags.contains(OpenFlags::DIRECTORY) {
+            open_options |= Self::DIRECTORY;
+        }
         open_options
     }
 }
 
+impl From<MountFlags> for mount_table::MountOptions {
+    fn from(flags: MountFlags) -> Self {
+        let mut options = Self::empty();
+        if flags.contains(MountFlags::RDONLY) {
+            options |= Self::READ_ONLY;
+        }
+        options
+    }
+}
+
 impl From<vfs::Error> for Error {
     fn from(vfs_error: vfs::Error) -> Self {
         match vfs_error {
@@ -291,10 +1099,23 @@ impl From<vfs::Error> for Error {
             vfs::Error::InvalidDirEntryName(_) => Error::EINVAL,
             vfs::Error::WrongFS => Error::EINVAL,
             vfs::Error::ReadOnly => Error::EROFS,
-            vfs::Error::UnsupportedFs(_) => Error::ENOSYS,
+            vfs::Error::UnsupportedFs(_) => Error::ENODEV,
             vfs::Error::InvalidSeekOffset => Error::EINVAL,
             vfs::Error::Unsupport => Error::ENOSYS,
             vfs::Error::NoSuchProcess(_) => Error::ESRCH,
+            vfs::Error::NotSymlink => Error::EINVAL,
+            vfs::Error::TooManySymlinks => Error::ELOOP,
+            vfs::Error::Busy => Error::EBUSY,
+            vfs::Error::NotMountPoint => Error::EINVAL,
+            vfs::Error::WouldBlock => Error::EAGAIN,
+            vfs::Error::Interrupted => Error::EINTR,
+            vfs::Error::NoDevice => Error::ENXIO,
+            vfs::Error::Io => Error::EIO,
+            vfs::Error::NotPermitted => Error::EPERM,
+            vfs::Error::BrokenPipe => Error::EPIPE,
+            vfs::Error::NameTooLong => Error::ENAMETOOLONG,
+            vfs::Error::PermissionDenied => Error::EACCES,
+            vfs::Error::InvalidTime => Error::EINVAL,
         }
     }
 }
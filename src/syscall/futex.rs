@@ -0,0 +1,59 @@
+use alloc::sync::Arc;
+
+use futures_util::future::{select, Either};
+use mm::VirtualAddress;
+
+use super::{Error, Result};
+use crate::{
+    proc::{futex, thread::Thread},
+    time::Timespec,
+    timer,
+};
+
+pub const FUTEX_WAIT: usize = 0;
+pub const FUTEX_WAKE: usize = 1;
+/// The futex is private to the process, futexes are always keyed by address space here.
+pub const FUTEX_PRIVATE_FLAG: usize = 128;
+pub const FUTEX_CLOCK_REALTIME: usize = 256;
+
+pub async fn sys_futex(
+    thread: &Arc<Thread>,
+    uaddr: usize,
+    op: usize,
+    val: u32,
+    timeout: *const Timespec,
+) -> Result {
+    if uaddr % core::mem::align_of::<u32>() != 0 {
+        return Err(Error::EINVAL);
+    }
+    let uaddr = VirtualAddress(uaddr);
+    let memory = &thread.proc().memory;
+
+    match op & !(FUTEX_PRIVATE_FLAG | FUTEX_CLOCK_REALTIME) {
+        FUTEX_WAIT => {
+            // The other operations do not take a timeout, and leave the argument unset.
+            let timeout = unsafe { super::timeout(timeout) }?;
+            let wait = futex::wait(memory, uaddr, val).await?;
+            match timeout {
+                None => wait.await,
+                Some(timeout) => {
+                    if let Either::Right(_) = select(wait, timer::sleep(timeout)).await {
+                        return Err(Error::ETIMEDOUT);
+                    }
+                }
+            }
+            Ok(0)
+        }
+        FUTEX_WAKE => Ok(futex::wake(memory, uaddr, val as usize)),
+        _ => Err(Error::ENOSYS),
+    }
+}
+
+impl From<futex::Error> for Error {
+    fn from(futex_err: futex::Error) -> Self {
+        match futex_err {
+            futex::Error::InvalidAddress(_) => Error::EFAULT,
+            futex::Error::ValueMismatch => Error::EAGAIN,
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use alloc::sync::Arc;
+use mm::{
+    arch::page::PageParam as PageParamA,
+    memory::{MapType, Segment},
+    page::{Flag, PageParam as _},
+    VirtualAddress,
+};
+
+use super::{Error, Result};
+use crate::{arch::memory::user_mmap_range, proc::thread::Thread};
+
+bitflags! {
+    pub struct MmapProt: usize {
+        /// Pages may be read
+        const READ = 0x1;
+        /// Pages may be written
+        const WRITE = 0x2;
+        /// Pages may be executed
+        const EXEC = 0x4;
+    }
+}
+
+bitflags! {
+    pub struct MmapFlags: usize {
+        /// Share this mapping
+        const SHARED = 0x01;
+        /// Create a private copy-on-write mapping
+        const PRIVATE = 0x02;
+        /// Place the mapping at exactly that address
+        const FIXED = 0x10;
+        /// The mapping is not backed by any file
+        const ANONYMOUS = 0x20;
+    }
+}
+
+num_enum::num_enum! (
+    pub MadviseAdvice:u8 {
+        // No special treatment.
+        Normal = 0,
+        // Expect page references in random order.
+        Random = 1,
+        // Expect page references in sequential order.
+        Sequential = 2,
+        // Expect access in the near future.
+        WillNeed = 3,
+        // Do not expect access in the near future, the pages may be freed.
+        DontNeed = 4,
+    }
+);
+
+impl MmapProt {
+    fn page_flags(&self) -> Flag {
+        let mut flags = 0;
+        if self.contains(MmapProt::READ) {
+            flags |= PageParamA::FLAG_PTE_READABLE;
+        }
+        if self.contains(MmapProt::WRITE) {
+            // There are no write-only pages, writable implies readable.
+            flags |= PageParamA::FLAG_PTE_READABLE | PageParamA::FLAG_PTE_WRITEABLE;
+        }
+        if self.contains(MmapProt::EXEC) {
+            flags |= PageParamA::FLAG_PTE_EXECUTABLE;
+        }
+        PageParamA::flag_set_user(flags)
+    }
+}
+
+pub fn sys_mmap(
+    thread: &Arc<Thread>,
+    addr: usize,
+    len: usize,
+    prot: MmapProt,
+    flags: MmapFlags,
+    _fd: isize,
+    offset: usize,
+) -> Result {
+    if len == 0 || !is_page_aligned(addr) || !is_page_aligned(offset) {
+        return Err(Error::EINVAL);
+    }
+    // TODO: file-backed and shared mappings
+    if !flags.contains(MmapFlags::ANONYMOUS | MmapFlags::PRIVATE) {
+        return Err(Error::ENOSYS);
+    }
+    let len = page_round_up(len).ok_or(Error::ENOMEM)?;
+
+    let segment = |addr_range| Segment {
+        addr_range,
+        flags: prot.page_flags(),
+        map_type: MapType::Framed,
+    };
+    let mut memory = thread.proc().memory.write();
+    if flags.contains(MmapFlags::FIXED) {
+        if addr == 0 {
+            return Err(Error::EINVAL);
+        }
+        let addr_range =
+            VirtualAddress(addr)..VirtualAddress(addr.checked_add(len).ok_or(Error::ENOMEM)?);
+        let user_range = user_mmap_range();
+        if addr_range.start < user_range.start || addr_range.end > user_range.end {
+            return Err(Error::ENOMEM);
+        }
+        // The new mapping replaces whatever was mapped there before,
+        // the stale entries of the old pages are flushed when the batch is dropped.
+        memory
+            .replace_user_segment(segment(addr_range))
+            .map_err(|_| Error::EINVAL)?;
+        return Ok(addr);
+    }
+
+    let addr_range = memory
+        .find_free_region(len, PageParamA::PAGE_SIZE, user_mmap_range())
+        .ok_or(Error::ENOMEM)?;
+    let start = addr_range.start;
+    memory
+        .add_user_segment(segment(addr_range), &[])
+        .map_err(|e| match e {
+            mm::Error::NoSpace => Error::ENOMEM,
+            _ => Error::EINVAL,
+        })?;
+    Ok(start.0)
+}
+
+pub fn sys_munmap(thread: &Arc<Thread>, addr: usize, len: usize) -> Result {
+    if len == 0 || !is_page_aligned(addr) {
+        return Err(Error::EINVAL);
+    }
+    let len = page_round_up(len).ok_or(Error::EINVAL)?;
+    let end = VirtualAddress(addr.checked_add(len).ok_or(Error::EINVAL)?);
+
+    thread
+        .proc()
+        .memory
+        .write()
+        .unmap_range(&(VirtualAddress(addr)..end))
+        .map_err(|_| Error::EINVAL)?;
+    Ok(0)
+}
+
+pub fn sys_mprotect(thread: &Arc<Thread>, addr: usize, len: usize, prot: MmapProt) -> Result {
+    if !is_page_aligned(addr) {
+        return Err(Error::EINVAL);
+    }
+    if len == 0 {
+        return Ok(0);
+    }
+    let len = page_round_up(len).ok_or(Error::ENOMEM)?;
+    let end = VirtualAddress(addr.checked_add(len).ok_or(Error::ENOMEM)?);
+
+    thread
+        .proc()
+        .memory
+        .write()
+        .protect(&(VirtualAddress(addr)..end), prot.page_flags())
+        .map_err(|e| match e {
+            mm::Error::InvalidVirtualAddress(_) => Error::ENOMEM,
+            _ => Error::EINVAL,
+        })?;
+    Ok(0)
+}
+
+/// Advises the kernel about the use of the pages in `addr..addr + len`.
+/// `DontNeed` frees the pages of the range, they read back as zero when accessed again,
+/// or as the file contents for a mapping loaded from a file.
+/// The other advices are accepted and ignored.
+pub fn sys_madvise(thread: &Arc<Thread>, addr: usize, len: usize, advice: MadviseAdvice) -> Result {
+    if !is_page_aligned(addr) {
+        return Err(Error::EINVAL);
+    }
+    if len == 0 {
+        return Ok(0);
+    }
+    let len = page_round_up(len).ok_or(Error::EINVAL)?;
+    let end = VirtualAddress(addr.checked_add(len).ok_or(Error::EINVAL)?);
+
+    match advice {
+        MadviseAdvice::DontNeed => {
+            thread
+                .proc()
+                .memory
+                .write()
+                .discard(&(VirtualAddress(addr)..end))
+                .map_err(|e| match e {
+                    mm::Error::InvalidVirtualAddress(_) => Error::ENOMEM,
+                    _ => Error::EINVAL,
+                })?;
+        }
+        MadviseAdvice::Normal
+        | MadviseAdvice::Random
+        | MadviseAdvice::Sequential
+        | MadviseAdvice::WillNeed => {}
+    }
+    Ok(0)
+}
+
+pub fn sys_brk(thread: &Arc<Thread>, addr: usize) -> Result {
+    let proc = thread.proc();
+    let mut brk = proc.brk.lock();
+    let flags = (MmapProt::READ | MmapProt::WRITE).page_flags();
+    let current = brk.set(&mut proc.memory.write(), VirtualAddress(addr), flags);
+    Ok(current.0)
+}
+
+fn is_page_aligned(addr: usize) -> bool {
+    addr % PageParamA::PAGE_SIZE == 0
+}
+
+fn page_round_up(len: usize) -> Option<usize> {
+    let page_size = PageParamA::PAGE_SIZE;
+    Some(len.checked_add(page_size - 1)? / page_size * page_size)
+}
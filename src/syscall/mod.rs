@@ -1,17 +1,45 @@
-use crate::{proc::thread::Thread, time::Timespec};
+use crate::{
+    proc::{signal::SigStack, thread::Thread, RLimit},
+    time::Timespec,
+};
 use alloc::sync::Arc;
-use core::{mem, ptr, slice};
+use core::{convert::TryFrom, mem, ptr, slice, time::Duration};
 
 mod fs;
+mod futex;
+mod mm;
 mod proc;
+mod random;
+mod signal;
+mod socket;
 mod syscall_table;
 
+use self::futex::sys_futex;
+use self::mm::{
+    sys_brk, sys_madvise, sys_mmap, sys_mprotect, sys_munmap, MadviseAdvice, MmapFlags, MmapProt,
+};
 use crate::fs::{vfs, Path};
 use fs::{
-    sys_close, sys_fstat, sys_fstatat, sys_lseek, sys_openat, sys_read, sys_write, FStatAtFlags,
-    LSeekWhence, OpenFlags, Stat,
+    sys_close, sys_dup, sys_dup3, sys_faccessat, sys_fchmodat, sys_fchownat, sys_fcntl, sys_fstat,
+    sys_fstatat, sys_getxattr, sys_ioctl, sys_linkat, sys_listxattr, sys_lseek, sys_mkdirat,
+    sys_mknodat, sys_mount, sys_openat, sys_ppoll, sys_read, sys_readlinkat, sys_readv,
+    sys_sendfile, sys_setxattr, sys_statx, sys_symlinkat, sys_sync, sys_syncfs, sys_umask,
+    sys_umount, sys_utimensat, sys_write, sys_writev, FAccessAtFlags, FChownAtFlags, FStatAtFlags,
+    FcntlCmd, IoVec, LSeekWhence, LinkAtFlags, MountFlags, OpenFlags, PollFd, SetXattrFlags, Stat,
+    Statx, UTimensAtFlags,
+};
+use proc::{
+    sys_clone, sys_exit, sys_getegid, sys_geteuid, sys_getgid, sys_getpgid, sys_getpid,
+    sys_getppid, sys_getrlimit, sys_getrusage, sys_gettid, sys_getuid, sys_prlimit64,
+    sys_set_tid_address, sys_setgid, sys_setpgid, sys_setrlimit, sys_setsid, sys_setuid,
+    CloneFlags, RUsage,
+};
+use random::sys_getrandom;
+use signal::{
+    sys_kill, sys_pause, sys_rt_sigreturn, sys_rt_sigsuspend, sys_sigaltstack, sys_tgkill,
+    sys_tkill,
 };
-use proc::{sys_exit, sys_fork};
+use socket::sys_socketpair;
 use syscall_table::*;
 
 use self::proc::sys_nanosleep;
@@ -23,12 +51,18 @@ pub type Result = core::result::Result<usize, Error>;
 #[allow(clippy::upper_case_acronyms)]
 pub enum Error {
     UNKNOWM = 0,
+    /// Operation not permitted
+    EPERM = 1,
     /// No such file or directory
     ENOENT = 2,
     /// No such process
     ESRCH = 3,
+    /// Interrupted system call
+    EINTR = 4,
     /// I/O error
     EIO = 5,
+    /// No such device or address
+    ENXIO = 6,
     /// Exec format error
     ENOEXEC = 8,
     /// fd is not a valid file descriptor.
@@ -37,20 +71,44 @@ pub enum Error {
     EAGAIN = 11,
     /// Out of memory
     ENOMEM = 12,
+    /// Permission denied
+    EACCES = 13,
+    /// Bad address
+    EFAULT = 14,
+    /// Device or resource busy
+    EBUSY = 16,
     /// File exists
     EEXIST = 17,
+    /// No such device
+    ENODEV = 19,
     /// Not a directory.
     ENOTDIR = 20,
     /// Invalid flag specified in flags.
     EINVAL = 22,
     /// Too many open files
     EMFILE = 24,
+    /// Not a typewriter
+    ENOTTY = 25,
     /// No space left on device
     ENOSPC = 28,
     /// Read-only file system
     EROFS = 30,
+    /// Broken pipe
+    EPIPE = 32,
+    /// Math result not representable
+    ERANGE = 34,
+    /// File name too long
+    ENAMETOOLONG = 36,
     /// Function not implemented
     ENOSYS = 38,
+    /// Too many symbolic links encountered
+    ELOOP = 40,
+    /// No data available
+    ENODATA = 61,
+    /// Address family not supported by protocol
+    EAFNOSUPPORT = 97,
+    /// Connection timed out
+    ETIMEDOUT = 110,
 }
 
 pub async fn syscall(thread: &Arc<Thread>) {
@@ -63,6 +121,95 @@ pub async fn syscall(thread: &Arc<Thread>) {
     };
 
     let res = match syscall_num {
+        SYS_SETXATTR => unsafe {
+            sys_setxattr(
+                thread,
+                path(syscall_args[0] as *const u8),
+                path(syscall_args[1] as *const u8),
+                syscall_args[2] as *const u8,
+                syscall_args[3],
+                SetXattrFlags::from_bits_truncate(syscall_args[4]),
+            )
+            .await
+        },
+        SYS_GETXATTR => unsafe {
+            sys_getxattr(
+                thread,
+                path(syscall_args[0] as *const u8),
+                path(syscall_args[1] as *const u8),
+                syscall_args[2] as *mut u8,
+                syscall_args[3],
+            )
+            .await
+        },
+        SYS_LISTXATTR => unsafe {
+            sys_listxattr(
+                thread,
+                path(syscall_args[0] as *const u8),
+                syscall_args[1] as *mut u8,
+                syscall_args[2],
+            )
+            .await
+        },
+        SYS_FCNTL => match u16::try_from(syscall_args[1])
+            .ok()
+            .and_then(FcntlCmd::from_primitive)
+        {
+            Some(cmd) => sys_fcntl(thread, syscall_args[0] as isize, cmd, syscall_args[2]),
+            None => Err(Error::EINVAL),
+        },
+        SYS_IOCTL => {
+            sys_ioctl(
+                thread,
+                syscall_args[0] as isize,
+                syscall_args[1] as u32,
+                syscall_args[2],
+            )
+            .await
+        }
+        SYS_FACCESSAT => unsafe {
+            sys_faccessat(
+                thread,
+                syscall_args[0] as isize,
+                path(syscall_args[1] as *const u8),
+                syscall_args[2] as u32,
+                FAccessAtFlags::empty(),
+            )
+            .await
+        },
+        SYS_FACCESSAT2 => match FAccessAtFlags::from_bits(syscall_args[3] as u32) {
+            Some(flags) => unsafe {
+                sys_faccessat(
+                    thread,
+                    syscall_args[0] as isize,
+                    path(syscall_args[1] as *const u8),
+                    syscall_args[2] as u32,
+                    flags,
+                )
+                .await
+            },
+            None => Err(Error::EINVAL),
+        },
+        SYS_FCHMODAT => unsafe {
+            sys_fchmodat(
+                thread,
+                syscall_args[0] as isize,
+                path(syscall_args[1] as *const u8),
+                vfs::Mode::from_bits_truncate(syscall_args[2] as u16),
+            )
+            .await
+        },
+        SYS_FCHOWNAT => unsafe {
+            sys_fchownat(
+                thread,
+                syscall_args[0] as isize,
+                path(syscall_args[1] as *const u8),
+                syscall_args[2] as u32,
+                syscall_args[3] as u32,
+                FChownAtFlags::from_bits_truncate(syscall_args[4] as u32),
+            )
+            .await
+        },
         SYS_OPENAT => unsafe {
             let path_ptr = syscall_args[1] as *const u8;
             sys_openat(
@@ -74,7 +221,22 @@ pub async fn syscall(thread: &Arc<Thread>) {
             )
             .await
         },
-        SYS_CLOSE => sys_close(thread, syscall_args[0] as isize),
+        SYS_CLOSE => sys_close(thread, syscall_args[0] as isize).await,
+        SYS_SYNC => sys_sync().await,
+        SYS_SYNCFS => sys_syncfs(thread, syscall_args[0] as isize).await,
+        SYS_DUP => sys_dup(thread, syscall_args[0] as isize),
+        SYS_DUP3 => match OpenFlags::from_bits(syscall_args[2]) {
+            Some(flags) => {
+                sys_dup3(
+                    thread,
+                    syscall_args[0] as isize,
+                    syscall_args[1] as isize,
+                    flags,
+                )
+                .await
+            }
+            None => Err(Error::EINVAL),
+        },
         SYS_LSEEK => match LSeekWhence::from_primitive(syscall_args[2] as u8) {
             Some(whence) => {
                 sys_lseek(
@@ -105,6 +267,83 @@ pub async fn syscall(thread: &Arc<Thread>) {
             )
             .await
         }
+        SYS_READV => {
+            sys_readv(
+                thread,
+                syscall_args[0] as isize,
+                syscall_args[1] as *const IoVec,
+                syscall_args[2],
+            )
+            .await
+        }
+        SYS_WRITEV => {
+            sys_writev(
+                thread,
+                syscall_args[0] as isize,
+                syscall_args[1] as *const IoVec,
+                syscall_args[2],
+            )
+            .await
+        }
+        SYS_MKNODAT => unsafe {
+            sys_mknodat(
+                thread,
+                syscall_args[0] as isize,
+                path(syscall_args[1] as *const u8),
+                vfs::Mode::from_bits_truncate(syscall_args[2] as u16),
+            )
+            .await
+        },
+        SYS_MKDIRAT => unsafe {
+            sys_mkdirat(
+                thread,
+                syscall_args[0] as isize,
+                path(syscall_args[1] as *const u8),
+                vfs::Mode::from_bits_truncate(syscall_args[2] as u16),
+            )
+            .await
+        },
+        SYS_SYMLINKAT => unsafe {
+            sys_symlinkat(
+                thread,
+                path(syscall_args[0] as *const u8),
+                syscall_args[1] as isize,
+                path(syscall_args[2] as *const u8),
+            )
+            .await
+        },
+        SYS_LINKAT => unsafe {
+            sys_linkat(
+                thread,
+                syscall_args[0] as isize,
+                path(syscall_args[1] as *const u8),
+                syscall_args[2] as isize,
+                path(syscall_args[3] as *const u8),
+                LinkAtFlags::from_bits_truncate(syscall_args[4] as u32),
+            )
+            .await
+        },
+        SYS_UMOUNT2 => unsafe { sys_umount(thread, path(syscall_args[0] as *const u8)).await },
+        SYS_MOUNT => unsafe {
+            sys_mount(
+                thread,
+                path(syscall_args[0] as *const u8),
+                path(syscall_args[1] as *const u8),
+                path(syscall_args[2] as *const u8),
+                MountFlags::from_bits_truncate(syscall_args[3]),
+            )
+            .await
+        },
+        SYS_READLINKAT => unsafe {
+            sys_readlinkat(
+                thread,
+                syscall_args[0] as isize,
+                path(syscall_args[1] as *const u8),
+                syscall_args[2] as *mut u8,
+                syscall_args[3],
+            )
+            .await
+        },
         SYS_NEWFSTATAT => unsafe {
             let path_ptr = syscall_args[1] as *const u8;
             sys_fstatat(
@@ -116,6 +355,17 @@ pub async fn syscall(thread: &Arc<Thread>) {
             )
             .await
         },
+        SYS_STATX => unsafe {
+            sys_statx(
+                thread,
+                syscall_args[0] as isize,
+                path(syscall_args[1] as *const u8),
+                syscall_args[2] as u32,
+                syscall_args[3] as u32,
+                mem::transmute::<_, &mut Statx>(syscall_args[4]),
+            )
+            .await
+        },
         SYS_FSTAT => unsafe {
             sys_fstat(
                 thread,
@@ -124,8 +374,150 @@ pub async fn syscall(thread: &Arc<Thread>) {
             )
             .await
         },
+        SYS_UTIMENSAT => unsafe {
+            sys_utimensat(
+                thread,
+                syscall_args[0] as isize,
+                path(syscall_args[1] as *const u8),
+                syscall_args[2] as *const [Timespec; 2],
+                UTimensAtFlags::from_bits_truncate(syscall_args[3] as u32),
+            )
+            .await
+        },
+        SYS_SENDFILE => {
+            sys_sendfile(
+                thread,
+                syscall_args[0] as isize,
+                syscall_args[1] as isize,
+                syscall_args[2] as *mut i64,
+                syscall_args[3],
+            )
+            .await
+        }
+        SYS_PPOLL => {
+            // TODO: sigmask
+            match unsafe { timeout(syscall_args[2] as *const Timespec) } {
+                // `pause`
+                Ok(None) if syscall_args[1] == 0 => sys_pause(thread).await,
+                Ok(timeout) => {
+                    sys_ppoll(
+                        thread,
+                        syscall_args[0] as *mut PollFd,
+                        syscall_args[1],
+                        timeout,
+                    )
+                    .await
+                }
+                Err(e) => Err(e),
+            }
+        }
         SYS_EXIT => sys_exit(thread, syscall_args[0] as isize),
-        SYS_CLONE => sys_fork(thread).await,
+        SYS_FUTEX => {
+            sys_futex(
+                thread,
+                syscall_args[0],
+                syscall_args[1],
+                syscall_args[2] as u32,
+                syscall_args[3] as *const Timespec,
+            )
+            .await
+        }
+        SYS_SET_TID_ADDRESS => sys_set_tid_address(thread, syscall_args[0]),
+        SYS_KILL => sys_kill(thread, syscall_args[0] as isize, syscall_args[1]),
+        SYS_TKILL => sys_tkill(thread, syscall_args[0] as isize, syscall_args[1]),
+        SYS_SIGALTSTACK => sys_sigaltstack(
+            thread,
+            syscall_args[0] as *const SigStack,
+            syscall_args[1] as *mut SigStack,
+        ),
+        SYS_RT_SIGSUSPEND => {
+            sys_rt_sigsuspend(thread, syscall_args[0] as *const u64, syscall_args[1]).await
+        }
+        SYS_RT_SIGRETURN => sys_rt_sigreturn(thread).await,
+        SYS_TGKILL => sys_tgkill(
+            thread,
+            syscall_args[0] as isize,
+            syscall_args[1] as isize,
+            syscall_args[2],
+        ),
+        SYS_SETGID => sys_setgid(thread, syscall_args[0] as u32),
+        SYS_SETUID => sys_setuid(thread, syscall_args[0] as u32),
+        SYS_GETRLIMIT => sys_getrlimit(thread, syscall_args[0], syscall_args[1] as *mut RLimit),
+        SYS_SETRLIMIT => sys_setrlimit(thread, syscall_args[0], syscall_args[1] as *const RLimit),
+        SYS_GETRUSAGE => sys_getrusage(
+            thread,
+            syscall_args[0] as isize,
+            syscall_args[1] as *mut RUsage,
+        ),
+        SYS_PRLIMIT64 => sys_prlimit64(
+            thread,
+            syscall_args[0] as isize,
+            syscall_args[1],
+            syscall_args[2] as *const RLimit,
+            syscall_args[3] as *mut RLimit,
+        ),
+        SYS_SETPGID => sys_setpgid(thread, syscall_args[0] as isize, syscall_args[1] as isize),
+        SYS_GETPGID => sys_getpgid(thread, syscall_args[0] as isize),
+        SYS_SETSID => sys_setsid(thread),
+        SYS_UMASK => sys_umask(
+            thread,
+            vfs::Mode::from_bits_truncate(syscall_args[0] as u16),
+        ),
+        SYS_GETPID => sys_getpid(thread),
+        SYS_GETPPID => sys_getppid(thread),
+        SYS_GETUID => sys_getuid(thread),
+        SYS_GETEUID => sys_geteuid(thread),
+        SYS_GETGID => sys_getgid(thread),
+        SYS_GETEGID => sys_getegid(thread),
+        SYS_GETTID => sys_gettid(thread),
+        SYS_SOCKETPAIR => sys_socketpair(
+            thread,
+            syscall_args[0],
+            syscall_args[1],
+            syscall_args[2],
+            syscall_args[3] as *mut [i32; 2],
+        ),
+        SYS_CLONE => {
+            sys_clone(
+                thread,
+                CloneFlags::from_bits_truncate(syscall_args[0]),
+                syscall_args[1],
+                syscall_args[2],
+                syscall_args[3],
+                syscall_args[4],
+            )
+            .await
+        }
+        SYS_MMAP => sys_mmap(
+            thread,
+            syscall_args[0],
+            syscall_args[1],
+            MmapProt::from_bits_truncate(syscall_args[2]),
+            MmapFlags::from_bits_truncate(syscall_args[3]),
+            syscall_args[4] as isize,
+            syscall_args[5],
+        ),
+        SYS_GETRANDOM => sys_getrandom(
+            thread,
+            syscall_args[0] as *mut u8,
+            syscall_args[1],
+            syscall_args[2],
+        ),
+        SYS_BRK => sys_brk(thread, syscall_args[0]),
+        SYS_MUNMAP => sys_munmap(thread, syscall_args[0], syscall_args[1]),
+        SYS_MPROTECT => sys_mprotect(
+            thread,
+            syscall_args[0],
+            syscall_args[1],
+            MmapProt::from_bits_truncate(syscall_args[2]),
+        ),
+        SYS_MADVISE => match u8::try_from(syscall_args[2])
+            .ok()
+            .and_then(MadviseAdvice::from_primitive)
+        {
+            Some(advice) => sys_madvise(thread, syscall_args[0], syscall_args[1], advice),
+            None => Err(Error::EINVAL),
+        },
         SYS_NANOSLEEP => {
             let time_ptr = syscall_args[0] as *const Timespec;
             sys_nanosleep(unsafe { ptr::read(time_ptr) }).await
@@ -143,6 +535,28 @@ pub async fn syscall(thread: &Arc<Thread>) {
     }
 }
 
+/// Returns true if the syscall `syscall_num` is resumed once the handler of the signal
+/// that interrupted it returns, when that handler was registered with `SA_RESTART`.
+/// Only syscalls that block on I/O are, a restarted sleep would not account for the
+/// time already slept.
+pub fn restartable(syscall_num: usize) -> bool {
+    matches!(
+        syscall_num,
+        SYS_READ | SYS_WRITE | SYS_READV | SYS_WRITEV | SYS_SENDFILE
+    )
+}
+
+// Reads an optional timeout, null means none. Fails with EINVAL if it is out of range.
+unsafe fn timeout(timeout_ptr: *const Timespec) -> core::result::Result<Option<Duration>, Error> {
+    if timeout_ptr.is_null() {
+        return Ok(None);
+    }
+    ptr::read(timeout_ptr)
+        .to_duration()
+        .map(Some)
+        .ok_or(Error::EINVAL)
+}
+
 unsafe fn path(path_ptr: *const u8) -> &'static Path {
     Path::from_bytes(slice::from_raw_parts(path_ptr, c_str_len(path_ptr)))
 }
@@ -1,32 +1,68 @@
 use alloc::{string::String, sync::Arc, vec::Vec};
+use core::ptr;
+use mm::VirtualAddress;
+pub use process::CloneFlags;
+use process::{group, CloneKind};
 
 use crate::{
+    config,
     fs::{self, rootfs},
     proc::{
         self,
         executor::spawn,
+        pid, resource,
         thread::{thread_future, Thread},
+        RLimit,
     },
-    time::Timespec,
+    time::{Timespec, Timeval},
     timer,
 };
 
 use super::{Error, Result};
 
-pub async fn sys_fork(thread: &Arc<Thread>) -> Result {
-    match thread.fork(thread.inner.read().fork()).await {
-        Ok(new_thread) => {
-            let new_thread_id = *new_thread.id() as usize;
-            // TODO handle spwan result
-            spawn(thread_future(Arc::new(new_thread))).ok_or(Error::EAGAIN)?;
-            Ok(new_thread_id)
-        }
+/// Creates a thread or a process running a copy of the calling thread,
+/// on the stack `newsp` if it is not 0. Returns the id of the new thread.
+/// What the new thread shares with the caller is told by `CloneFlags::kind`.
+pub async fn sys_clone(
+    thread: &Arc<Thread>,
+    flags: CloneFlags,
+    newsp: usize,
+    ptid: usize,
+    tls: usize,
+    ctid: usize,
+) -> Result {
+    let kind = flags.kind()?;
+    let mut new_inner = thread.inner.read().fork();
+    if newsp != 0 {
+        new_inner.context.set_init_stack(VirtualAddress(newsp));
+    }
+    if flags.contains(CloneFlags::SETTLS) {
+        new_inner.context.set_tls(tls);
+    }
+    if flags.contains(CloneFlags::CHILD_CLEARTID) {
+        new_inner.clear_child_tid = VirtualAddress(ctid);
+    }
+
+    let new_thread = match kind {
+        CloneKind::Thread => thread.clone_thread(new_inner),
+        CloneKind::Process(share) => thread.fork(new_inner, share).await,
+    };
+    let new_thread = match new_thread {
+        Ok(new_thread) => new_thread,
         Err(e) => {
             println!("error: {:?}", e);
-            log::error!("sys_fork: {:?}", e);
-            Err(e.into())
+            log::error!("sys_clone: {:?}", e);
+            return Err(e.into());
         }
+    };
+
+    let new_thread_id = *new_thread.id();
+    if flags.contains(CloneFlags::PARENT_SETTID) && ptid != 0 {
+        unsafe { (ptid as *mut u32).write_volatile(new_thread_id) };
     }
+    // TODO handle spwan result
+    spawn(thread_future(new_thread)).ok_or(Error::EAGAIN)?;
+    Ok(new_thread_id as usize)
 }
 
 pub fn sys_exit(thread: &Arc<Thread>, status: isize) -> Result {
@@ -34,6 +70,170 @@ pub fn sys_exit(thread: &Arc<Thread>, status: isize) -> Result {
     Ok(0)
 }
 
+/// Returns the id of the calling process, which is the id of its main thread.
+pub fn sys_getpid(thread: &Arc<Thread>) -> Result {
+    Ok(*thread.proc().id() as usize)
+}
+
+/// Returns the id of the parent process, 0 if the caller has no parent.
+pub fn sys_getppid(thread: &Arc<Thread>) -> Result {
+    Ok(thread.proc().family.ppid() as usize)
+}
+
+pub fn sys_gettid(thread: &Arc<Thread>) -> Result {
+    Ok(*thread.id() as usize)
+}
+
+pub fn sys_set_tid_address(thread: &Arc<Thread>, tidptr: usize) -> Result {
+    Ok(thread.set_tid_address(VirtualAddress(tidptr)) as usize)
+}
+
+/// Moves the process `pid` (the caller if 0) into the process group `pgid`
+/// (a new group led by `pid` if 0).
+/// The process must be the caller or one of its children, and the group
+/// must belong to the same session.
+pub fn sys_setpgid(thread: &Arc<Thread>, pid: isize, pgid: isize) -> Result {
+    if pid < 0 || pgid < 0 {
+        return Err(Error::EINVAL);
+    }
+    group::set_pgid(thread.proc(), pid as u32, pgid as u32, pid::group_members)?;
+    Ok(0)
+}
+
+pub fn sys_getpgid(thread: &Arc<Thread>, pid: isize) -> Result {
+    let pgid = match pid {
+        0 => thread.proc().pgid(),
+        pid if pid > 0 => pid::find(&(pid as u32)).ok_or(Error::ESRCH)?.pgid(),
+        _ => return Err(Error::ESRCH),
+    };
+    Ok(pgid as usize)
+}
+
+/// Starts a new session led by the caller, fails if the caller's id is
+/// already used as a process group id.
+pub fn sys_setsid(thread: &Arc<Thread>) -> Result {
+    Ok(group::set_sid(&**thread.proc(), pid::group_members)? as usize)
+}
+
+pub fn sys_getuid(thread: &Arc<Thread>) -> Result {
+    Ok(thread.proc().credentials().uid as usize)
+}
+
+pub fn sys_geteuid(thread: &Arc<Thread>) -> Result {
+    Ok(thread.proc().credentials().euid as usize)
+}
+
+pub fn sys_getgid(thread: &Arc<Thread>) -> Result {
+    Ok(thread.proc().credentials().gid as usize)
+}
+
+pub fn sys_getegid(thread: &Arc<Thread>) -> Result {
+    Ok(thread.proc().credentials().egid as usize)
+}
+
+/// Sets the user ids of the calling process, see `Credentials::set_uid`.
+pub fn sys_setuid(thread: &Arc<Thread>, uid: u32) -> Result {
+    let proc = thread.proc();
+    let mut credentials = proc.credentials();
+    credentials.set_uid(uid)?;
+    proc.set_credentials(credentials);
+    Ok(0)
+}
+
+/// Sets the group ids of the calling process, see `Credentials::set_gid`.
+pub fn sys_setgid(thread: &Arc<Thread>, gid: u32) -> Result {
+    let proc = thread.proc();
+    let mut credentials = proc.credentials();
+    credentials.set_gid(gid)?;
+    proc.set_credentials(credentials);
+    Ok(0)
+}
+
+/// `struct rusage`, only the CPU times are tracked.
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct RUsage {
+    /// Time spent in user mode
+    pub utime: Timeval,
+    /// Time spent in the kernel
+    pub stime: Timeval,
+    // maxrss, ixrss, idrss, isrss, minflt, majflt, nswap, inblock, oublock,
+    // msgsnd, msgrcv, nsignals, nvcsw and nivcsw, always 0.
+    pub untracked: [i64; 14],
+}
+
+/// Stores the resources used by `who` in `usage`.
+/// CPU time is accounted per process, `RUSAGE_THREAD` is not supported.
+pub fn sys_getrusage(thread: &Arc<Thread>, who: isize, usage: *mut RUsage) -> Result {
+    let cpu_times = thread
+        .proc()
+        .cpu_usage
+        .lock()
+        .get(who)
+        .ok_or(Error::EINVAL)?;
+    unsafe {
+        usage.write(RUsage {
+            utime: cpu_times.user.into(),
+            stime: cpu_times.system.into(),
+            ..Default::default()
+        })
+    };
+    Ok(0)
+}
+
+/// Stores the limit of `resource` of the calling process in `rlim`.
+pub fn sys_getrlimit(thread: &Arc<Thread>, resource: usize, rlim: *mut RLimit) -> Result {
+    sys_prlimit64(thread, 0, resource, ptr::null(), rlim)
+}
+
+/// Sets the limit of `resource` of the calling process to `rlim`.
+pub fn sys_setrlimit(thread: &Arc<Thread>, resource: usize, rlim: *const RLimit) -> Result {
+    sys_prlimit64(thread, 0, resource, rlim, ptr::null_mut())
+}
+
+/// Sets the limit of `resource` of the process `pid` to `new_limit` and stores
+/// the previous one in `old_limit`, either may be null. A `pid` of 0 is the caller.
+/// Only a privileged process may raise a hard limit or change the limits of
+/// a process running as another user.
+pub fn sys_prlimit64(
+    thread: &Arc<Thread>,
+    pid: isize,
+    resource: usize,
+    new_limit: *const RLimit,
+    old_limit: *mut RLimit,
+) -> Result {
+    let caller = thread.proc();
+    let target = match pid {
+        0 => caller.clone(),
+        pid if pid > 0 => pid::find(&(pid as u32)).ok_or(Error::ESRCH)?,
+        _ => return Err(Error::EINVAL),
+    };
+    let old = target.rlimit(resource).ok_or(Error::EINVAL)?;
+
+    if !new_limit.is_null() {
+        let credentials = caller.credentials();
+        let target_credentials = target.credentials();
+        let privileged = credentials.privileged();
+        if !privileged
+            && (credentials.uid != target_credentials.uid
+                || credentials.gid != target_credentials.gid)
+        {
+            return Err(Error::EPERM);
+        }
+        let new = unsafe { new_limit.read() };
+        let ceiling = match resource {
+            resource::NOFILE => config::PROC_MAX_OPEN_FILES as u64,
+            _ => RLimit::INFINITY,
+        };
+        old.check_replace(&new, privileged, ceiling)?;
+        target.set_rlimit(resource, new);
+    }
+    if !old_limit.is_null() {
+        unsafe { old_limit.write(old) };
+    }
+    Ok(0)
+}
+
 pub async fn sys_execve(
     thread: &Arc<Thread>,
     path: &fs::Path,
@@ -53,16 +253,31 @@ pub async fn sys_execve(
         .await
         .map_err::<Error, _>(Into::into)?;
 
+    for descriptor in thread.proc().open_files.remove_cloexec_files() {
+        // There is no one left to report an error finalizing the file to.
+        let _ = descriptor.close().await;
+    }
     Ok(0)
 }
 
 pub async fn sys_nanosleep(time: Timespec) -> Result {
+    let duration = time.to_duration().ok_or(Error::EINVAL)?;
     if !time.is_zero() {
-        timer::sleep(time.to_duration()).await;
+        timer::sleep(duration).await;
     }
     Ok(0)
 }
 
+impl From<process::Error> for Error {
+    fn from(process_err: process::Error) -> Self {
+        match process_err {
+            process::Error::NoSuchProcess => Error::ESRCH,
+            process::Error::NotPermitted => Error::EPERM,
+            process::Error::InvalidArgument => Error::EINVAL,
+        }
+    }
+}
+
 impl From<proc::Error> for Error {
     fn from(proc_err: proc::Error) -> Self {
         match proc_err {
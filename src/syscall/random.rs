@@ -0,0 +1,34 @@
+use alloc::sync::Arc;
+use core::slice;
+
+use super::{Error, Result};
+use crate::{fs::devfs::dev_random, proc::thread::Thread};
+
+bitflags! {
+    pub struct GetRandomFlags: usize {
+        /// Do not block if the pool is not initialized.
+        const NONBLOCK = 0x1;
+        /// Read from the blocking `/dev/random` pool.
+        const RANDOM = 0x2;
+        /// Return bytes even if the pool is not initialized.
+        const INSECURE = 0x4;
+    }
+}
+
+// The largest number of bytes returned by one call, as on Linux.
+const GETRANDOM_MAX: usize = (1 << 25) - 1;
+
+/// Fills `buf` with up to `buflen` random bytes, returns the number of bytes written.
+/// The pool is a software generator that is always ready, so no flag makes the call block.
+pub fn sys_getrandom(_thread: &Arc<Thread>, buf: *mut u8, buflen: usize, flags: usize) -> Result {
+    let flags = GetRandomFlags::from_bits(flags).ok_or(Error::EINVAL)?;
+    if flags.contains(GetRandomFlags::RANDOM | GetRandomFlags::INSECURE) {
+        return Err(Error::EINVAL);
+    }
+    if buflen == 0 {
+        return Ok(0);
+    }
+    let len = buflen.min(GETRANDOM_MAX);
+    dev_random::fill(unsafe { slice::from_raw_parts_mut(buf, len) });
+    Ok(len)
+}
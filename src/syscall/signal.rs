@@ -0,0 +1,177 @@
+use alloc::{sync::Arc, vec::Vec};
+use core::{convert::TryFrom, mem};
+
+use super::{Error, Result};
+use crate::proc::{
+    executor, pid,
+    signal::{self, Info, SendTo, SigStack, SignalSet, Signo, StackError},
+    thread::Thread,
+    Proc,
+};
+
+/// Sends the signal `sig` to processes:
+/// * `pid > 0`: the process `pid`.
+/// * `pid == 0`: every process in the process group of the caller.
+/// * `pid == -1`: every process except init and the caller.
+/// * `pid < -1`: every process in the process group `-pid`.
+///
+/// A `sig` of 0 only checks that the target processes exist and may be signaled.
+/// Signaling several processes succeeds if at least one of them got the signal,
+/// otherwise the error of the last one is returned.
+pub fn sys_kill(thread: &Arc<Thread>, pid: isize, sig: usize) -> Result {
+    let sig = signo(sig)?;
+    let caller = thread.proc();
+
+    if pid > 0 {
+        let proc = pid::find(&(pid as u32)).ok_or(Error::ESRCH)?;
+        return send(caller, sig, Info::kill, SendTo::Thread(&proc.main_thread));
+    }
+
+    let targets: Vec<Arc<Proc>> = match pid {
+        0 => pid::group_members(caller.pgid()),
+        -1 => pid::procs()
+            .filter(|proc| !proc.is_init() && proc.id() != caller.id())
+            .collect(),
+        _ => pid::group_members(pid.checked_neg().ok_or(Error::ESRCH)? as u32),
+    };
+    let mut res = Err(Error::ESRCH);
+    for proc in &targets {
+        match send(caller, sig, Info::kill, SendTo::ProcGroup(proc)) {
+            Ok(_) => res = Ok(0),
+            Err(err) if res.is_err() => res = Err(err),
+            Err(_) => {}
+        }
+    }
+    res
+}
+
+/// Sends the signal `sig` to the thread `tid` of the process `tgid`.
+pub fn sys_tgkill(thread: &Arc<Thread>, tgid: isize, tid: isize, sig: usize) -> Result {
+    if tgid <= 0 {
+        return Err(Error::EINVAL);
+    }
+    tkill(thread, Some(tgid as u32), tid, sig)
+}
+
+/// Sends the signal `sig` to the thread `tid`, whatever process it belongs to.
+pub fn sys_tkill(thread: &Arc<Thread>, tid: isize, sig: usize) -> Result {
+    tkill(thread, None, tid, sig)
+}
+
+// The signal goes to the pending queue of the target thread, not to the one shared by its process.
+fn tkill(thread: &Arc<Thread>, tgid: Option<u32>, tid: isize, sig: usize) -> Result {
+    if tid <= 0 {
+        return Err(Error::EINVAL);
+    }
+    let sig = signo(sig)?;
+    let target = executor::thread(&(tid as u32))
+        .filter(|target| tgid.map_or(true, |tgid| *target.proc().id() == tgid))
+        .ok_or(Error::ESRCH)?;
+    send(thread.proc(), sig, Info::tkill, SendTo::Thread(&target))
+}
+
+// Send `sig` from `caller` to `send_to`, `None` only checks the permission.
+// The caller must run as the owner of the target process or be privileged,
+// SIGCONT may also be sent to any process of the caller's session.
+// Fails with EAGAIN if the realtime signal queue of the target is full.
+fn send(
+    caller: &Arc<Proc>,
+    sig: Option<Signo>,
+    info: fn(Signo, u32) -> Info,
+    send_to: SendTo,
+) -> Result {
+    let target = match send_to {
+        SendTo::ProcGroup(proc) => proc,
+        SendTo::Thread(thread) => thread.proc(),
+    };
+    let same_session_cont = sig == Some(Signo::SIGCONT) && caller.sid() == target.sid();
+    if !same_session_cont && !caller.credentials().may_signal(&target.credentials()) {
+        return Err(Error::EPERM);
+    }
+    match sig {
+        Some(sig) => signal::signal()
+            .send_signal(sig, info(sig, *caller.id()), send_to)
+            .map(|_| 0)
+            .map_err(|_| Error::EAGAIN),
+        None => Ok(0),
+    }
+}
+
+// Parse a signal number, 0 only checks that the targets exist.
+fn signo(sig: usize) -> core::result::Result<Option<Signo>, Error> {
+    match sig {
+        0 => Ok(None),
+        _ => u8::try_from(sig)
+            .ok()
+            .and_then(Signo::from_primitive)
+            .map(Some)
+            .ok_or(Error::EINVAL),
+    }
+}
+
+/// Registers `ss` as the alternate signal stack of the caller,
+/// and stores the previous one in `old_ss`. Either may be null.
+/// The stack can not be changed while the caller is executing on it.
+pub fn sys_sigaltstack(thread: &Arc<Thread>, ss: *const SigStack, old_ss: *mut SigStack) -> Result {
+    let mut thread_inner = thread.inner.write();
+    let sp = thread_inner.context.sp();
+    let alt_stack = &mut thread_inner.sig_alt_stack;
+    let old = alt_stack.query(sp);
+    if !ss.is_null() {
+        alt_stack
+            .set(&unsafe { ss.read() }, sp)
+            .map_err(|err| match err {
+                StackError::OnStack => Error::EPERM,
+                StackError::TooSmall => Error::ENOMEM,
+                StackError::Invalid => Error::EINVAL,
+            })?;
+    }
+    if !old_ss.is_null() {
+        unsafe { old_ss.write(old) };
+    }
+    Ok(0)
+}
+
+/// Suspends the caller until a signal that is not blocked is delivered to it.
+/// RISC-V has no `pause` system call, libc calls `ppoll` without descriptors
+/// nor timeout instead, which is dispatched here.
+pub async fn sys_pause(thread: &Arc<Thread>) -> Result {
+    wait_for_signal(thread).await
+}
+
+/// Replaces the signal mask of the calling thread with `*mask` and suspends it until
+/// a signal that is not blocked is delivered. The mask of the process applies again
+/// once the handler of that signal is set up. SIGKILL and SIGSTOP can not be blocked.
+pub async fn sys_rt_sigsuspend(
+    thread: &Arc<Thread>,
+    mask: *const u64,
+    sigsetsize: usize,
+) -> Result {
+    if sigsetsize != mem::size_of::<SignalSet>() {
+        return Err(Error::EINVAL);
+    }
+    signal::set_suspend_mask(thread, SignalSet::from_bits(unsafe { mask.read() }));
+    wait_for_signal(thread).await
+}
+
+/// Returns from a signal handler to the context it interrupted.
+/// A syscall the handler interrupted is resumed here if it is restarted,
+/// otherwise the restored context already holds EINTR as its result.
+pub async fn sys_rt_sigreturn(thread: &Arc<Thread>) -> Result {
+    let sig_ctx = thread.inner.write().sig_ctx.take().ok_or(Error::EINVAL)?;
+    sig_ctx
+        .arch_ctx
+        .fill_interr_ctx(&mut thread.inner.write().context);
+    if let Some(syscall) = sig_ctx.syscall {
+        // It stores its result in the restored context.
+        syscall.await;
+    }
+    // Returning `a0` keeps the restored context as it is.
+    Ok(thread.inner.read().context.get_syscall_args()[0])
+}
+
+// Sleeps until a signal is pending. The thread future then sets up the handler
+// and drops this syscall, which is never restarted, so it fails with EINTR.
+async fn wait_for_signal(thread: &Arc<Thread>) -> Result {
+    signal::wait_for_signal(|| signal::signal_pending(thread)).await
+}
@@ -0,0 +1,56 @@
+use alloc::sync::Arc;
+
+use super::{Error, Result};
+use crate::{
+    fs::{self, devfs::DevInode, socket::SocketEnd},
+    proc::{file, thread::Thread},
+};
+
+pub const AF_UNIX: usize = 1;
+pub const SOCK_STREAM: usize = 1;
+/// Flags that may be or-ed into the socket type.
+pub const SOCK_NONBLOCK: usize = 0o4000;
+pub const SOCK_CLOEXEC: usize = 0o2000000;
+
+/// Creates a pair of connected sockets and stores their descriptors in `sv`.
+/// Only UNIX domain stream sockets are supported.
+pub fn sys_socketpair(
+    thread: &Arc<Thread>,
+    domain: usize,
+    ty: usize,
+    protocol: usize,
+    sv: *mut [i32; 2],
+) -> Result {
+    if domain != AF_UNIX {
+        return Err(Error::EAFNOSUPPORT);
+    }
+    if ty & !(SOCK_NONBLOCK | SOCK_CLOEXEC) != SOCK_STREAM || protocol != 0 {
+        return Err(Error::EINVAL);
+    }
+    if sv.is_null() {
+        return Err(Error::EFAULT);
+    }
+
+    let mut opts = file::OpenOptions::READ | file::OpenOptions::WRITE;
+    if ty & SOCK_NONBLOCK != 0 {
+        opts |= file::OpenOptions::NONBLOCK;
+    }
+    let cloexec = ty & SOCK_CLOEXEC != 0;
+
+    let (end0, end1) = SocketEnd::pair();
+    let descriptor = |end: SocketEnd| {
+        let inode: fs::Inode = Arc::new(Arc::new(end) as Arc<dyn DevInode>);
+        file::Descriptor::new(inode, opts, cloexec)
+    };
+    let open_files = &thread.proc().open_files;
+    let fd0 = open_files.add_file(descriptor(end0)).ok_or(Error::EMFILE)?;
+    let fd1 = match open_files.add_file(descriptor(end1)) {
+        Some(fd1) => fd1,
+        None => {
+            open_files.remove_file(fd0);
+            return Err(Error::EMFILE);
+        }
+    };
+    unsafe { *sv = [fd0 as i32, fd1 as i32] };
+    Ok(0)
+}
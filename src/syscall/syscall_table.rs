@@ -1,11 +1,69 @@
 // generic syscall table.
+pub const SYS_SETXATTR: usize = 5;
+pub const SYS_GETXATTR: usize = 8;
+pub const SYS_LISTXATTR: usize = 11;
+pub const SYS_DUP: usize = 23;
+pub const SYS_DUP3: usize = 24;
+pub const SYS_FCNTL: usize = 25;
+pub const SYS_IOCTL: usize = 29;
+pub const SYS_MKNODAT: usize = 33;
+pub const SYS_MKDIRAT: usize = 34;
+pub const SYS_SYMLINKAT: usize = 36;
+pub const SYS_LINKAT: usize = 37;
+pub const SYS_UMOUNT2: usize = 39;
+pub const SYS_MOUNT: usize = 40;
+pub const SYS_FACCESSAT: usize = 48;
+pub const SYS_FCHMODAT: usize = 53;
+pub const SYS_FCHOWNAT: usize = 54;
 pub const SYS_OPENAT: usize = 56;
 pub const SYS_CLOSE: usize = 57;
 pub const SYS_LSEEK: usize = 62;
 pub const SYS_READ: usize = 63;
 pub const SYS_WRITE: usize = 64;
+pub const SYS_READV: usize = 65;
+pub const SYS_WRITEV: usize = 66;
+pub const SYS_SENDFILE: usize = 71;
+pub const SYS_PPOLL: usize = 73;
+pub const SYS_READLINKAT: usize = 78;
 pub const SYS_NEWFSTATAT: usize = 79;
 pub const SYS_FSTAT: usize = 80;
+pub const SYS_SYNC: usize = 81;
+pub const SYS_UTIMENSAT: usize = 88;
 pub const SYS_EXIT: usize = 93;
+pub const SYS_SET_TID_ADDRESS: usize = 96;
+pub const SYS_FUTEX: usize = 98;
 pub const SYS_NANOSLEEP: usize = 101;
+pub const SYS_KILL: usize = 129;
+pub const SYS_TKILL: usize = 130;
+pub const SYS_TGKILL: usize = 131;
+pub const SYS_SIGALTSTACK: usize = 132;
+pub const SYS_RT_SIGSUSPEND: usize = 133;
+pub const SYS_RT_SIGRETURN: usize = 139;
+pub const SYS_SETGID: usize = 144;
+pub const SYS_SETUID: usize = 146;
+pub const SYS_SETPGID: usize = 154;
+pub const SYS_GETPGID: usize = 155;
+pub const SYS_SETSID: usize = 157;
+pub const SYS_GETRLIMIT: usize = 163;
+pub const SYS_SETRLIMIT: usize = 164;
+pub const SYS_GETRUSAGE: usize = 165;
+pub const SYS_UMASK: usize = 166;
+pub const SYS_GETPID: usize = 172;
+pub const SYS_GETPPID: usize = 173;
+pub const SYS_GETUID: usize = 174;
+pub const SYS_GETEUID: usize = 175;
+pub const SYS_GETGID: usize = 176;
+pub const SYS_GETEGID: usize = 177;
+pub const SYS_GETTID: usize = 178;
+pub const SYS_SOCKETPAIR: usize = 199;
+pub const SYS_BRK: usize = 214;
+pub const SYS_MUNMAP: usize = 215;
 pub const SYS_CLONE: usize = 220;
+pub const SYS_MMAP: usize = 222;
+pub const SYS_MPROTECT: usize = 226;
+pub const SYS_MADVISE: usize = 233;
+pub const SYS_PRLIMIT64: usize = 261;
+pub const SYS_SYNCFS: usize = 267;
+pub const SYS_GETRANDOM: usize = 278;
+pub const SYS_STATX: usize = 291;
+pub const SYS_FACCESSAT2: usize = 439;
@@ -17,6 +17,13 @@ pub fn init() {
 pub fn on_timer(_kernel: bool) {
     let now = interrupt::timer_now();
     unsafe { NAIVE_TIMER.assume_init_ref().lock().expire(now) }
+    #[cfg(feature = "watchdog")]
+    crate::proc::executor::watchdog_tick();
+}
+
+/// Returns the deadline of the soonest pending timer.
+pub fn next_deadline() -> Option<Duration> {
+    unsafe { NAIVE_TIMER.assume_init_ref().lock().next() }
 }
 
 pub fn sleep(duration: Duration) -> SleepFuture {